@@ -1,19 +1,2018 @@
-use dreamfield_system::{include_world_model, build_log};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::time::Instant;
+
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use dreamfield_system::build_log;
 use dreamfield_system::world::world_builder::*;
 
-/// Directory to output chunks to
+/// The manifest listing the world models to build
+pub const WORLD_MANIFEST_PATH: &'static str = "resources/world_manifest.toml";
+
+/// Directory to output each level's world chunks to, in a directory named after the level
 pub const CHUNK_OUTPUT_DIR: &'static str = "target/world_chunks";
 
-/// World models
-const WORLD_MODELS: &'static [WorldModel] = &[
-    include_world_model!("resources/models/village.glb"),
-    include_world_model!("resources/models/dungeon.glb"),
+/// Directory to keep the hashes of each build step's inputs in, so unchanged steps can be skipped
+pub const STAMP_DIR: &'static str = "target/build_stamps";
+
+/// Directory to output the world models generated for terrains to
+pub const TERRAIN_OUTPUT_DIR: &'static str = "target/terrain";
+
+/// The path to output the water surface plane to
+pub const WATER_PLANE_OUTPUT_PATH: &'static str = "target/water_plane.glb";
+
+/// Directory to output the billboard sprites' frame models to
+pub const SPRITE_OUTPUT_DIR: &'static str = "target/sprites";
+
+/// Directory to output the decal models to
+pub const DECAL_OUTPUT_DIR: &'static str = "target/decals";
+
+/// Directory to output vegetation scatters to
+pub const VEGETATION_OUTPUT_DIR: &'static str = "target/vegetation";
+
+/// Directory to output each level's chunk LODs to, in a directory named after the level
+pub const LOD_OUTPUT_DIR: &'static str = "target/world_lods";
+
+/// Directory to output potentially visible sets to
+pub const PVS_OUTPUT_DIR: &'static str = "target/pvs";
+
+/// Directory to output minimaps to
+pub const MINIMAP_OUTPUT_DIR: &'static str = "target/minimaps";
+
+/// Directory to output physics collision meshes to
+pub const COLLISION_OUTPUT_DIR: &'static str = "target/collision";
+
+/// Directory to output the per chunk collision BVHs to
+pub const COLLISION_BVH_OUTPUT_DIR: &'static str = "target/collision_bvh";
+
+/// Directory to output navigation grids to
+pub const NAV_OUTPUT_DIR: &'static str = "target/nav";
+
+/// Directory to output root motion tracks to
+pub const ROOT_MOTION_OUTPUT_DIR: &'static str = "target/root_motion";
+
+/// Directory to output the point entities placed in world models to
+pub const POINTS_OUTPUT_DIR: &'static str = "target/world_points";
+
+/// Directory to output sampled skeleton poses to
+pub const SKELETON_OUTPUT_DIR: &'static str = "target/skeletons";
+
+/// Directory to output the fonts baked from BMFont files to
+pub const FONT_OUTPUT_DIR: &'static str = "target/fonts";
+
+/// The paths to output the asset manifest, listing the models, textures and sounds the game loads
+/// from resources, and the pack with their contents to
+pub const ASSET_MANIFEST_OUTPUT_PATH: &'static str = "target/asset_manifest.txt";
+pub const ASSET_PACK_OUTPUT_PATH: &'static str = "target/assets.pack";
+
+/// The width of the glyph atlases BMFont files are baked into
+const FONT_ATLAS_WIDTH: u32 = 256;
+
+/// How many times a second to sample skeleton poses at
+const SKELETON_SAMPLE_RATE: f32 = 15.0;
+
+/// The size of the square chunks world models are split into for collision queries, in world units
+const COLLISION_CHUNK_SIZE: f32 = 32.0;
+
+/// The most triangles in a leaf of a collision BVH
+const BVH_LEAF_SIZE: usize = 4;
+
+/// How many quads along each side of the square chunks terrains are split into
+const TERRAIN_CHUNK_QUADS: usize = 16;
+
+/// How many quads along each side of the water surface plane, which needs enough vertices for the
+/// water shader to ripple
+const WATER_PLANE_QUADS: usize = 32;
+
+/// The color of the water surface plane, which the water shader lights and bands
+const WATER_PLANE_COLOR: [f32; 4] = [0.15, 0.35, 0.45, 0.7];
+
+/// How far above scattered vegetation is checked for anything covering it, like roofs
+const VEGETATION_CLEARANCE: f32 = 12.0;
+
+/// The size of the square tiles world models are split into for LODs, in world units
+const LOD_TILE_SIZE: f32 = 32.0;
+
+/// The size of the cells potentially visible sets are baked for, in world units
+const PVS_CELL_SIZE: f32 = 8.0;
+
+/// The number of points sampled across each PVS cell in x and z, which are checked against each
+/// other to see whether cells can see each other
+const PVS_SAMPLES: usize = 3;
+
+/// How high above the ground each PVS sample is, around where the player's eyes are
+const PVS_EYE_HEIGHT: f32 = 1.5;
+
+/// How far apart cells can be and still see each other, a bit past the far clip
+const PVS_MAX_DISTANCE: f32 = 160.0;
+
+/// The size of each minimap cell in world units
+const MINIMAP_CELL_SIZE: f32 = 2.0;
+
+/// The name prefixes of the empties that are exported as point entities
+const POINT_PREFIXES: &'static [&'static str] = &["spawn", "anchor", "light", "trigger"];
+
+/// The minimum normal y for a surface to be drawn as floor instead of wall
+const MINIMAP_FLOOR_NORMAL: f32 = 0.7;
+
+/// The size of each navigation grid cell in world units, which is also roughly the agents' radius
+const NAV_CELL_SIZE: f32 = 0.5;
+
+/// The minimum normal y for a surface to be walkable
+const NAV_WALKABLE_NORMAL: f32 = 0.7;
+
+/// The headroom agents need above walkable ground
+const NAV_AGENT_HEIGHT: f32 = 1.8;
+
+/// The tallest step agents can walk up, which surfaces lower than don't block the ground
+const NAV_STEP_HEIGHT: f32 = 0.4;
+
+/// How a world model's physics collision mesh is baked
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum CollisionMode {
+    /// All of the model's triangles
+    Mesh,
+    /// No collision mesh, so props fall through it and only the world chunks collide
+    None,
+}
+
+impl Default for CollisionMode {
+    fn default() -> Self {
+        CollisionMode::Mesh
+    }
+}
+
+/// A world model from the manifest
+#[derive(Deserialize, Debug)]
+struct ManifestModel {
+    /// The name the model's baked data is written under
+    name: String,
+    path: String,
+    /// The level whose chunks the model is built into
+    level: String,
+    #[serde(default)]
+    collision: CollisionMode,
+    #[serde(default = "default_true")]
+    minimap: bool,
+    #[serde(default = "default_true")]
+    nav: bool,
+    /// The cell size vertices are merged in for each LOD, from the most detailed to the least
+    #[serde(default)]
+    lod_cell_sizes: Vec<f32>,
+    #[serde(default)]
+    pvs: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A terrain from the manifest, which is generated into a world model from its heightmap, and then
+/// built and baked like any other world model
+#[derive(Deserialize, Debug)]
+struct ManifestTerrain {
+    /// The name the terrain's world model and baked data are written under
+    name: String,
+    /// The level whose chunks the terrain is built into
+    level: String,
+    /// A greyscale png with the height of each vertex
+    heightmap: String,
+    /// An rgba png with how much of each texture is on the ground in each channel
+    splat: Option<String>,
+    /// The png textures for each channel of the splat map
+    textures: Vec<String>,
+    /// The world position of the heightmap's first pixel, at height zero
+    origin: [f32; 3],
+    /// The size of the terrain along x and z
+    size: [f32; 2],
+    /// The height of a white pixel above the origin
+    height: f32,
+    /// How many world units each repeat of the textures covers
+    #[serde(default = "default_texture_scale")]
+    texture_scale: f32,
+    #[serde(default = "default_true")]
+    minimap: bool,
+    #[serde(default = "default_true")]
+    nav: bool,
+    #[serde(default)]
+    lod_cell_sizes: Vec<f32>,
+}
+
+fn default_texture_scale() -> f32 {
+    4.0
+}
+
+impl ManifestTerrain {
+    /// Get the path of the terrain's generated world model
+    fn model_path(&self) -> String {
+        format!("{}/{}.glb", TERRAIN_OUTPUT_DIR, self.name)
+    }
+
+    /// Get the world model the terrain's generated into, which always has a collision mesh so
+    /// there's something to stand on
+    fn model(&self) -> ManifestModel {
+        ManifestModel {
+            name: self.name.clone(),
+            path: self.model_path(),
+            level: self.level.clone(),
+            collision: CollisionMode::Mesh,
+            minimap: self.minimap,
+            nav: self.nav,
+            lod_cell_sizes: self.lod_cell_sizes.clone(),
+            pvs: false,
+        }
+    }
+}
+
+/// A vegetation layer from the manifest, scattered across one of the world models or terrains
+#[derive(Deserialize, Debug)]
+struct ManifestVegetation {
+    /// The name the scatter's written under
+    name: String,
+    /// The name of the model or terrain to scatter across
+    model: String,
+    /// The model each instance is drawn with
+    visual: String,
+    /// How many instances there are for each square world unit of ground
+    density: f32,
+    /// The closest two instances can be
+    min_spacing: f32,
+    /// The least the ground's normal can point upwards for anything to grow on it
+    #[serde(default = "default_min_normal")]
+    min_normal: f32,
+    /// The range instances are scaled in
+    #[serde(default = "default_scale_range")]
+    scale: [f32; 2],
+}
+
+fn default_min_normal() -> f32 {
+    0.85
+}
+
+fn default_scale_range() -> [f32; 2] {
+    [1.0, 1.0]
+}
+
+/// The world manifest, with the world models to build. Each level gets its own set of chunks,
+/// which are swapped in when it's loaded, and each model gets its own minimap, collision mesh,
+/// navigation grid and point entities. Terrains are added to the models once they're loaded, so
+/// everything after generating them treats them as any other world model.
+#[derive(Deserialize, Debug)]
+struct WorldManifest {
+    #[serde(rename = "model")]
+    models: Vec<ManifestModel>,
+    #[serde(rename = "terrain", default)]
+    terrains: Vec<ManifestTerrain>,
+    #[serde(default)]
+    vegetation: Vec<ManifestVegetation>,
+}
+
+impl WorldManifest {
+    fn load() -> Self {
+        println!("cargo:rerun-if-changed={}", WORLD_MANIFEST_PATH);
+        let source = std::fs::read_to_string(WORLD_MANIFEST_PATH).unwrap();
+        let mut manifest: WorldManifest = toml::from_str(&source)
+            .unwrap_or_else(|err| panic!("Failed to parse {}: {}", WORLD_MANIFEST_PATH, err));
+        let terrain_models: Vec<ManifestModel> = manifest.terrains.iter().map(ManifestTerrain::model).collect();
+        manifest.models.extend(terrain_models);
+        manifest
+    }
+
+    /// Get the models in each level
+    fn levels(&self) -> BTreeMap<&str, Vec<&ManifestModel>> {
+        let mut levels: BTreeMap<&str, Vec<&ManifestModel>> = BTreeMap::new();
+        for model in self.models.iter() {
+            levels.entry(model.level.as_str()).or_default().push(model);
+        }
+        levels
+    }
+}
+
+/// Load a world model for the world builder. The models used to be included in the build script
+/// with include_world_model!, which needs a literal path, so the data's read and leaked instead to
+/// give it the same lifetime.
+fn load_world_model(path: &str) -> WorldModel {
+    let data: &'static [u8] = Box::leak(std::fs::read(path).unwrap().into_boxed_slice());
+    let name: &'static str = Box::leak(path.to_string().into_boxed_str());
+    WorldModel { name, data }
+}
+
+/// Animated models to extract root motion from, as (name, path)
+const ANIMATED_MODELS: &'static [(&'static str, &'static str)] = &[
+    ("elf", "resources/models/elf.glb"),
+];
+
+/// Sprite sheets to generate billboard models from, as (name, path, frames), with the frames laid
+/// out left to right in a single row
+const SPRITE_SHEETS: &'static [(&'static str, &'static str, usize)] = &[
+    ("coin", "resources/sprites/coin.png", 4),
+    ("flowers", "resources/sprites/flowers.png", 1),
+];
+
+/// Textures to generate decal models from, as (name, path)
+const DECAL_TEXTURES: &'static [(&'static str, &'static str)] = &[
+    ("scorch", "resources/decals/scorch.png"),
+    ("footprint", "resources/decals/footprint.png"),
+    ("slash", "resources/decals/slash.png"),
+    ("blob_shadow", "resources/decals/blob_shadow.png"),
+];
+
+/// BMFont text format .fnt files to bake into fonts for the font manager, as (name, path). Their
+/// pages are found next to them.
+const BITMAP_FONTS: &'static [(&'static str, &'static str)] = &[
+    ("medieval_bm", "resources/fonts/medieval_bm/medieval_bm.fnt"),
+];
+
+/// Directories to scan for the asset manifest, as (kind, directory, extension, prefix), with each
+/// asset named after its file with the prefix in front
+const ASSET_DIRS: &'static [(&'static str, &'static str, &'static str, &'static str)] = &[
+    ("model", "resources/models", "glb", ""),
+    ("texture", "resources/textures", "png", ""),
+    ("texture", "resources/sprites", "png", "sprite_"),
+    ("sound", "resources/sounds", "wav", ""),
+];
+
+/// A build step's stamp, the hash of its inputs and parameters when its outputs were last built
+struct Stamp {
+    path: String,
+    hash: u64,
+}
+
+impl Stamp {
+    /// Hash a build step's input files and parameters, which are its settings and the constants it
+    /// bakes with, telling cargo to rerun the build script when the files change
+    fn new(step: &str, inputs: &[&str], parameters: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(parameters.as_bytes());
+        for input in inputs {
+            println!("cargo:rerun-if-changed={}", input);
+            hasher.write(input.as_bytes());
+            hasher.write(&std::fs::read(input).unwrap());
+        }
+
+        Stamp {
+            path: format!("{}/{}.hash", STAMP_DIR, step.replace('/', "_")),
+            hash: hasher.finish(),
+        }
+    }
+
+    /// Check whether the step's outputs exist and were built from the same inputs
+    fn is_current(&self, outputs: &[&str]) -> bool {
+        let stamped = std::fs::read_to_string(&self.path).ok().and_then(|hash| hash.trim().parse::<u64>().ok());
+        stamped == Some(self.hash) && outputs.iter().all(|output| std::path::Path::new(output).exists())
+    }
+
+    /// Record that the step's outputs have been built
+    fn save(&self) {
+        std::fs::write(&self.path, self.hash.to_string()).unwrap();
+    }
+}
+
+/// The stages of the build that run once the terrains have been generated, since they're built from
+/// them too, by name
+const BUILD_STAGES: &'static [(&'static str, fn(&WorldManifest))] = &[
+    ("Building world chunks", build_world_chunks),
+    ("Baking world models", bake_world_models),
+    ("Scattering vegetation", scatter_vegetation),
+    ("Extracting animations", |_| extract_animations()),
+    ("Generating the water plane", |_| generate_water_plane()),
+    ("Generating sprites", |_| generate_sprites()),
+    ("Generating decals", |_| generate_decals()),
+    ("Baking fonts", |_| bake_fonts()),
+    ("Generating the asset manifest", generate_asset_manifest),
 ];
 
-/// TODO: output files to update on when changed
+/// Bake everything the game includes at compile time. Each step is skipped if its inputs and
+/// parameters haven't changed since it was last built, though changes to the engine's world builder
+/// or to how a step bakes aren't noticed, so its outputs have to be rebuilt by deleting its stamp
+/// in target/build_stamps after changing it. Once the terrains have been generated, the stages are
+/// all built at once on rayon's thread pool, as is each model within them. The world builder builds
+/// each level's chunks one after another, so a level with one big world model still takes as long
+/// as that model does.
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    std::fs::create_dir_all(STAMP_DIR).unwrap();
+    std::fs::create_dir_all(MINIMAP_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(COLLISION_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(NAV_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(POINTS_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(TERRAIN_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(LOD_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(VEGETATION_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(SPRITE_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(DECAL_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(PVS_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(COLLISION_BVH_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(ROOT_MOTION_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(SKELETON_OUTPUT_DIR).unwrap();
+    std::fs::create_dir_all(FONT_OUTPUT_DIR).unwrap();
+
+    let manifest = WorldManifest::load();
+    timed("Building everything", || {
+        timed("Generating terrains", || generate_terrains(&manifest));
+        BUILD_STAGES.par_iter().for_each(|(stage, build)| timed(stage, || build(&manifest)));
+    });
+}
+
+/// Run a build step, logging how long it took
+fn timed<T>(step: &str, run: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = run();
+    build_log!("{} took {:.2}s", step, start.elapsed().as_secs_f32());
+    result
+}
+
+/// Generate the world model for each terrain from its heightmap
+fn generate_terrains(manifest: &WorldManifest) {
+    build_log!("Generating terrains");
+    manifest.terrains.par_iter().for_each(|terrain| {
+        let output = terrain.model_path();
+        let mut inputs = vec![terrain.heightmap.as_str()];
+        inputs.extend(terrain.splat.as_deref());
+        inputs.extend(terrain.textures.iter().map(String::as_str));
+        let parameters = format!("{:?} {}", terrain, TERRAIN_CHUNK_QUADS);
+        let stamp = Stamp::new(&format!("terrain_{}", terrain.name), &inputs, &parameters);
+        if stamp.is_current(&[output.as_str()]) {
+            return;
+        }
+
+        timed(&format!("Generating {}", output), || {
+            std::fs::write(&output, generate_terrain(terrain)).unwrap();
+        });
+        stamp.save();
+    });
+}
+
+/// Build the world chunks for each level
+fn build_world_chunks(manifest: &WorldManifest) {
     build_log!("Building world models");
-    // A hack because otherwise it tries to delete it later and fails
-    std::fs::create_dir_all(CHUNK_OUTPUT_DIR).unwrap();
-    WorldBuilder::new(CHUNK_OUTPUT_DIR, WORLD_MODELS).build_world_models();
+    manifest.levels().into_par_iter().for_each(|(level, models)| {
+        let output_dir = format!("{}/{}", CHUNK_OUTPUT_DIR, level);
+        let paths: Vec<&str> = models.iter().map(|model| model.path.as_str()).collect();
+        let stamp = Stamp::new(&output_dir, &paths, "");
+        if stamp.is_current(&[output_dir.as_str()]) {
+            return;
+        }
+
+        timed(&format!("Building {}", output_dir), || {
+            let world_models: Vec<WorldModel> = paths.iter().map(|path| load_world_model(path)).collect();
+            // A hack because otherwise it tries to delete it later and fails
+            std::fs::create_dir_all(&output_dir).unwrap();
+            WorldBuilder::new(&output_dir, &world_models).build_world_models();
+        });
+        stamp.save();
+    });
+}
+
+/// Bake the minimaps, collision meshes, navigation grids, point entities, LODs and potentially
+/// visible sets for each world model
+fn bake_world_models(manifest: &WorldManifest) {
+    build_log!("Baking minimaps, collision meshes, navigation grids, point entities, LODs and PVS");
+    manifest.models.par_iter().for_each(|model| {
+        let (name, path) = (&model.name, model.path.as_str());
+        let outputs = [
+            format!("{}/{}.txt", MINIMAP_OUTPUT_DIR, name),
+            format!("{}/{}.bin", COLLISION_OUTPUT_DIR, name),
+            format!("{}/{}.txt", NAV_OUTPUT_DIR, name),
+            format!("{}/{}.txt", POINTS_OUTPUT_DIR, name),
+            format!("{}/{}/{}.tiles", LOD_OUTPUT_DIR, model.level, name),
+            format!("{}/{}.txt", PVS_OUTPUT_DIR, name),
+            format!("{}/{}.bin", COLLISION_BVH_OUTPUT_DIR, name),
+        ];
+        let parameters = format!("{:?} {:?}", model, [MINIMAP_CELL_SIZE, MINIMAP_FLOOR_NORMAL, COLLISION_CHUNK_SIZE,
+            BVH_LEAF_SIZE as f32, NAV_CELL_SIZE, NAV_WALKABLE_NORMAL, NAV_AGENT_HEIGHT, NAV_STEP_HEIGHT, LOD_TILE_SIZE,
+            PVS_CELL_SIZE, PVS_SAMPLES as f32, PVS_EYE_HEIGHT, PVS_MAX_DISTANCE]) + &POINT_PREFIXES.join(" ");
+        let stamp = Stamp::new(&format!("baked_{}", name), &[path], &parameters);
+        if stamp.is_current(&outputs.iter().map(String::as_str).collect::<Vec<_>>()) {
+            return;
+        }
+
+        // Anything that's turned off is written out empty, so the game can still include it
+        timed(&format!("Baking {}", name), || {
+            let triangles = load_triangles(path);
+            let ((minimap, collision), (nav, points)) = rayon::join(
+                || rayon::join(
+                    || match model.minimap {
+                        true => bake_minimap(&triangles),
+                        false => String::new(),
+                    },
+                    || match model.collision {
+                        CollisionMode::Mesh => (bake_collision_mesh(&triangles), bake_collision_bvh(&triangles)),
+                        CollisionMode::None => (Vec::new(), Vec::new()),
+                    }),
+                || rayon::join(
+                    || match model.nav {
+                        true => bake_nav_grid(&triangles),
+                        false => String::new(),
+                    },
+                    || bake_points(path)));
+            let lod_dir = format!("{}/{}", LOD_OUTPUT_DIR, model.level);
+            std::fs::create_dir_all(&lod_dir).unwrap();
+            let (lods, pvs) = rayon::join(
+                || bake_lods(name, path, &lod_dir, &model.lod_cell_sizes),
+                || match model.pvs {
+                    true => bake_pvs(&triangles),
+                    false => String::new(),
+                });
+
+            std::fs::write(&outputs[0], minimap).unwrap();
+            std::fs::write(&outputs[1], collision.0).unwrap();
+            std::fs::write(&outputs[2], nav).unwrap();
+            std::fs::write(&outputs[3], points).unwrap();
+            std::fs::write(&outputs[4], lods).unwrap();
+            std::fs::write(&outputs[5], pvs).unwrap();
+            std::fs::write(&outputs[6], collision.1).unwrap();
+        });
+        stamp.save();
+    });
+}
+
+/// Scatter each vegetation layer across its world model
+fn scatter_vegetation(manifest: &WorldManifest) {
+    build_log!("Scattering vegetation");
+    manifest.vegetation.par_iter().for_each(|layer| {
+        let model = match manifest.models.iter().find(|model| model.name == layer.model) {
+            Some(model) => model,
+            None => panic!("Vegetation {} is scattered across unknown model {}", layer.name, layer.model),
+        };
+
+        let output = format!("{}/{}.txt", VEGETATION_OUTPUT_DIR, layer.name);
+        let parameters = format!("{:?} {}", layer, VEGETATION_CLEARANCE);
+        let stamp = Stamp::new(&format!("vegetation_{}", layer.name), &[model.path.as_str()], &parameters);
+        if stamp.is_current(&[output.as_str()]) {
+            return;
+        }
+
+        timed(&format!("Scattering {}", layer.name), || {
+            std::fs::write(&output, bake_vegetation(layer, &load_triangles(&model.path))).unwrap();
+        });
+        stamp.save();
+    });
+}
+
+/// Scatter vegetation across a world model's triangles. Each triangle the ground's flat enough on
+/// gets instances in proportion to its area, at random points that are far enough from the rest
+/// and don't have anything over them. The scatter's random but the same every time it's baked. The
+/// output is a header line with the visual, followed by a line for each instance with its x, y, z,
+/// yaw in radians and scale.
+fn bake_vegetation(layer: &ManifestVegetation, triangles: &[[[f32; 3]; 3]]) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(layer.name.as_bytes());
+    let mut state = (hasher.finish() as u32).max(1);
+    let mut random = move || {
+        // xorshift32
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as f32 / u32::MAX as f32
+    };
+
+    let cell_of = |p: &[f32; 3]| ((p[0] / layer.min_spacing).floor() as i32, (p[2] / layer.min_spacing).floor() as i32);
+    let mut placed: HashMap<(i32, i32), Vec<[f32; 3]>> = HashMap::new();
+    let mut output = format!("{}\n", layer.visual);
+
+    for triangle in triangles {
+        let [a, b, c] = triangle;
+        if triangle_normal(a, b, c)[1] < layer.min_normal {
+            continue;
+        }
+
+        let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+        let cross = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+        let area = 0.5 * (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+
+        // Round the count randomly so that small triangles still get their share
+        let count = (area * layer.density + random()).floor() as usize;
+        for _ in 0..count {
+            let (mut s, mut t) = (random(), random());
+            if s + t > 1.0 {
+                (s, t) = (1.0 - s, 1.0 - t);
+            }
+            let point = [0, 1, 2].map(|i| a[i] + u[i] * s + v[i] * t);
+
+            let (cell_x, cell_z) = cell_of(&point);
+            let too_close = (-1..=1).flat_map(|x| (-1..=1).map(move |z| (cell_x + x, cell_z + z)))
+                .filter_map(|cell| placed.get(&cell))
+                .flatten()
+                .any(|other| (other[0] - point[0]).powi(2) + (other[2] - point[2]).powi(2) < layer.min_spacing.powi(2));
+            if too_close {
+                continue;
+            }
+
+            let from = [point[0], point[1] + 0.1, point[2]];
+            let to = [point[0], point[1] + VEGETATION_CLEARANCE, point[2]];
+            if triangles.iter().any(|other| segment_hits_triangle(&from, &to, other)) {
+                continue;
+            }
+
+            let yaw = random() * 2.0 * std::f32::consts::PI;
+            let scale = layer.scale[0] + (layer.scale[1] - layer.scale[0]) * random();
+            output += &format!("{} {} {} {} {}\n", point[0], point[1], point[2], yaw, scale);
+            placed.entry((cell_x, cell_z)).or_default().push(point);
+        }
+    }
+
+    output
+}
+
+/// Generate the plane water surfaces are drawn with, a unit square on the xz plane centred on the
+/// origin, which is scaled to the size of each water volume
+fn generate_water_plane() {
+    let stamp = Stamp::new("water_plane", &[], &format!("{} {:?}", WATER_PLANE_QUADS, WATER_PLANE_COLOR));
+    if stamp.is_current(&[WATER_PLANE_OUTPUT_PATH]) {
+        return;
+    }
+
+    let row = WATER_PLANE_QUADS + 1;
+    let positions: Vec<[f32; 3]> = (0..row * row)
+        .map(|i| [(i % row) as f32 / WATER_PLANE_QUADS as f32 - 0.5, 0.0, (i / row) as f32 / WATER_PLANE_QUADS as f32 - 0.5])
+        .collect();
+    let normals = vec![[0.0, 1.0, 0.0]; positions.len()];
+    let colors = vec![WATER_PLANE_COLOR; positions.len()];
+    let indices: Vec<u32> = (0..WATER_PLANE_QUADS * WATER_PLANE_QUADS)
+        .flat_map(|quad| {
+            let corner = (quad / WATER_PLANE_QUADS * row + quad % WATER_PLANE_QUADS) as u32;
+            let row = row as u32;
+            [corner, corner + row, corner + 1, corner + 1, corner + row, corner + row + 1]
+        })
+        .collect();
+
+    std::fs::write(WATER_PLANE_OUTPUT_PATH, write_glb(&positions, &normals, &colors, &indices)).unwrap();
+    stamp.save();
+}
+
+/// Generate a model for each frame of each sprite sheet, named `sprite_<name>_<frame>`. Each one's
+/// a unit quad facing along z, standing on the origin, with the sheet embedded in it and its uvs
+/// on the frame, which billboards turn to face the camera and swap between to animate.
+fn generate_sprites() {
+    build_log!("Generating sprites");
+    SPRITE_SHEETS.par_iter().for_each(|(name, path, frames)| {
+        let outputs: Vec<String> = (0..*frames).map(|frame| format!("{}/sprite_{}_{}.glb", SPRITE_OUTPUT_DIR, name, frame)).collect();
+        let stamp = Stamp::new(&format!("sprite_{}", name), &[path], &frames.to_string());
+        if stamp.is_current(&outputs.iter().map(String::as_str).collect::<Vec<_>>()) {
+            return;
+        }
+
+        let sheet = std::fs::read(path).unwrap();
+        for (frame, output) in outputs.iter().enumerate() {
+            let (left, right) = (frame as f32 / *frames as f32, (frame + 1) as f32 / *frames as f32);
+            let quad = TexturedPrimitive {
+                texture: 0,
+                vertices: HashMap::new(),
+                positions: vec![[-0.5, 0.0, 0.0], [0.5, 0.0, 0.0], [0.5, 1.0, 0.0], [-0.5, 1.0, 0.0]],
+                normals: vec![[0.0, 0.0, 1.0]; 4],
+                uvs: vec![[left, 1.0], [right, 1.0], [right, 0.0], [left, 0.0]],
+                indices: vec![0, 1, 2, 0, 2, 3],
+            };
+            let glb = write_textured_glb(&[(format!("sprite_{}_{}", name, frame), vec![quad])], &[sheet.clone()], true);
+            std::fs::write(output, glb).unwrap();
+        }
+        stamp.save();
+    });
+}
+
+/// Generate a model for each decal texture, named `decal_<name>`. Each one's a unit quad on the xz
+/// plane facing up along y, centred on the origin, with the top of the texture towards -z.
+fn generate_decals() {
+    build_log!("Generating decals");
+    DECAL_TEXTURES.par_iter().for_each(|(name, path)| {
+        let output = format!("{}/decal_{}.glb", DECAL_OUTPUT_DIR, name);
+        let stamp = Stamp::new(&format!("decal_{}", name), &[path], "");
+        if stamp.is_current(&[output.as_str()]) {
+            return;
+        }
+
+        let quad = TexturedPrimitive {
+            texture: 0,
+            vertices: HashMap::new(),
+            positions: vec![[-0.5, 0.0, 0.5], [0.5, 0.0, 0.5], [0.5, 0.0, -0.5], [-0.5, 0.0, -0.5]],
+            normals: vec![[0.0, 1.0, 0.0]; 4],
+            uvs: vec![[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        };
+        let glb = write_textured_glb(&[(format!("decal_{}", name), vec![quad])], &[std::fs::read(path).unwrap()], true);
+        std::fs::write(&output, glb).unwrap();
+        stamp.save();
+    });
+}
+
+/// Generate the asset manifest, with a line for each model, texture and sound in the asset
+/// directories of its kind, name, offset and length in the pack, and path, and the pack with all of
+/// their contents one after another. The world models are left out, since they're built into
+/// chunks instead. Cargo's told to rerun the build script when anything in the directories changes,
+/// so added and removed files are picked up as well as changed ones.
+fn generate_asset_manifest(manifest: &WorldManifest) {
+    build_log!("Generating asset manifest");
+    let world_models: HashSet<&str> = manifest.models.iter().map(|model| model.path.as_str()).collect();
+
+    let mut assets: Vec<(&str, String, String)> = Vec::new();
+    for (kind, dir, extension, prefix) in ASSET_DIRS {
+        println!("cargo:rerun-if-changed={}", dir);
+        let mut paths: Vec<String> = std::fs::read_dir(dir).unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == *extension))
+            .map(|path| format!("{}/{}", dir, path.file_name().unwrap().to_str().unwrap()))
+            .filter(|path| !world_models.contains(path.as_str()))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let stem = std::path::Path::new(&path).file_stem().unwrap().to_str().unwrap();
+            assets.push((kind, format!("{}{}", prefix, stem), path));
+        }
+    }
+
+    let inputs: Vec<&str> = assets.iter().map(|(_, _, path)| path.as_str()).collect();
+    let stamp = Stamp::new("asset_manifest", &inputs, &format!("{:?}", assets));
+    if stamp.is_current(&[ASSET_MANIFEST_OUTPUT_PATH, ASSET_PACK_OUTPUT_PATH]) {
+        return;
+    }
+
+    let mut lines = String::new();
+    let mut pack = Vec::new();
+    for (kind, name, path) in assets.iter() {
+        let contents = std::fs::read(path).unwrap();
+        lines += &format!("{} {} {} {} {}\n", kind, name, pack.len(), contents.len(), path);
+        pack.extend(contents);
+    }
+    std::fs::write(ASSET_MANIFEST_OUTPUT_PATH, lines).unwrap();
+    std::fs::write(ASSET_PACK_OUTPUT_PATH, pack).unwrap();
+    stamp.save();
+}
+
+/// A glyph from a BMFont file
+struct BitmapGlyph {
+    id: u32,
+    page: usize,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    x_offset: i32,
+    y_offset: i32,
+    x_advance: u32,
+}
+
+/// A BMFont file, parsed from the text format
+struct BitmapFont {
+    face: String,
+    line_height: u32,
+    pages: Vec<String>,
+    glyphs: Vec<BitmapGlyph>,
+    /// The kerning pairs, as the first and second codepoint and how far the second moves along
+    kerning: Vec<(u32, u32, i32)>,
+}
+
+impl BitmapFont {
+    /// Parse a BMFont text format file. Each line is a tag followed by key=value pairs, and only
+    /// the tags and keys needed to bake the font are read.
+    fn parse(path: &str) -> Self {
+        let source = std::fs::read_to_string(path).unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err));
+        let mut font = BitmapFont { face: String::new(), line_height: 0, pages: Vec::new(), glyphs: Vec::new(), kerning: Vec::new() };
+
+        for (line_number, line) in source.lines().enumerate() {
+            let (tag, values) = parse_bmfont_line(line);
+            let get = |key: &str| values.get(key)
+                .unwrap_or_else(|| panic!("{} line {}: {} is missing {}", path, line_number + 1, tag, key));
+            let number = |key: &str| get(key).parse::<i32>()
+                .unwrap_or_else(|_| panic!("{} line {}: {} isn't a number", path, line_number + 1, key));
+
+            match tag.as_str() {
+                "info" => font.face = get("face").clone(),
+                "common" => font.line_height = number("lineHeight") as u32,
+                "page" => {
+                    let id = number("id") as usize;
+                    font.pages.resize(usize::max(font.pages.len(), id + 1), String::new());
+                    font.pages[id] = get("file").clone();
+                },
+                "char" => font.glyphs.push(BitmapGlyph {
+                    id: number("id") as u32,
+                    page: number("page") as usize,
+                    x: number("x") as u32,
+                    y: number("y") as u32,
+                    width: number("width") as u32,
+                    height: number("height") as u32,
+                    x_offset: number("xoffset"),
+                    y_offset: number("yoffset"),
+                    x_advance: number("xadvance") as u32,
+                }),
+                "kerning" => font.kerning.push((number("first") as u32, number("second") as u32, number("amount"))),
+                _ => (),
+            }
+        }
+
+        assert!(!font.face.is_empty() && font.line_height > 0, "{} needs an info face and a common lineHeight", path);
+        font
+    }
+}
+
+/// Split a line of a BMFont text format file into its tag and key=value pairs, where values can be
+/// quoted to put spaces in them
+fn parse_bmfont_line(line: &str) -> (String, HashMap<String, String>) {
+    let line = line.trim();
+    let (tag, mut rest) = line.split_once(' ').unwrap_or((line, ""));
+
+    let mut values = HashMap::new();
+    while let Some((key, after)) = rest.trim_start().split_once('=') {
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(' ').unwrap_or((after, "")),
+        };
+        values.insert(key.to_string(), value.to_string());
+        rest = after;
+    }
+
+    (tag.to_string(), values)
+}
+
+/// Bake each BMFont file into a font for the font manager, which needs all of a font's glyphs on
+/// one texture, and draws each one the width of its cell and moves along by the same. Each glyph is
+/// copied from its page into a cell as wide as it moves the text along and as tall as the line, at
+/// its offset in it, so variable width glyphs from any number of pages end up on one atlas. The
+/// outputs are `<name>.png` with the atlas, `<name>.csv` with the glyph map, whose font variant is
+/// the font's face, and `<name>.kern` with a line for each kerning pair, of the variant, the first
+/// and second codepoint and the amount, for rich text to apply since the font manager can't.
+fn bake_fonts() {
+    build_log!("Baking fonts");
+    BITMAP_FONTS.par_iter().for_each(|(name, path)| {
+        let font = BitmapFont::parse(path);
+        let dir = std::path::Path::new(path).parent().unwrap();
+        let pages: Vec<String> = font.pages.iter().map(|page| dir.join(page).to_str().unwrap().to_string()).collect();
+
+        let outputs = ["png", "csv", "kern"].map(|ext| format!("{}/{}.{}", FONT_OUTPUT_DIR, name, ext));
+        let inputs: Vec<&str> = std::iter::once(*path).chain(pages.iter().map(String::as_str)).collect();
+        let stamp = Stamp::new(&format!("font_{}", name), &inputs, &FONT_ATLAS_WIDTH.to_string());
+        if stamp.is_current(&outputs.iter().map(String::as_str).collect::<Vec<_>>()) {
+            return;
+        }
+
+        let pages: Vec<image::RgbaImage> = pages.iter()
+            .map(|page| image::open(page).unwrap_or_else(|err| panic!("Failed to open {}: {}", page, err)).to_rgba8())
+            .collect();
+
+        // Lay the cells out in rows across the atlas
+        let mut cells = Vec::new();
+        let (mut x, mut y) = (0, 0);
+        for glyph in font.glyphs.iter().filter(|glyph| glyph.x_advance > 0) {
+            assert!(glyph.x_advance <= FONT_ATLAS_WIDTH, "Glyph {} in {} is wider than the atlas", glyph.id, path);
+            if x + glyph.x_advance > FONT_ATLAS_WIDTH {
+                x = 0;
+                y += font.line_height;
+            }
+            cells.push((glyph, x, y));
+            x += glyph.x_advance;
+        }
+
+        let mut atlas = image::RgbaImage::new(FONT_ATLAS_WIDTH, (y + font.line_height).next_power_of_two());
+        let mut map = String::from("font variant,unicode,source x,source y,width,height\n");
+        for &(glyph, cell_x, cell_y) in cells.iter() {
+            let page = pages.get(glyph.page)
+                .unwrap_or_else(|| panic!("Glyph {} in {} is on page {}, which doesn't exist", glyph.id, path, glyph.page));
+
+            // Anything that hangs outside the cell is cut off
+            for source_y in 0..glyph.height {
+                for source_x in 0..glyph.width {
+                    let (target_x, target_y) = (glyph.x_offset + source_x as i32, glyph.y_offset + source_y as i32);
+                    let in_cell = (0..glyph.x_advance as i32).contains(&target_x)
+                        && (0..font.line_height as i32).contains(&target_y);
+                    let (page_x, page_y) = (glyph.x + source_x, glyph.y + source_y);
+                    if in_cell && page_x < page.width() && page_y < page.height() {
+                        atlas.put_pixel(cell_x + target_x as u32, cell_y + target_y as u32, *page.get_pixel(page_x, page_y));
+                    }
+                }
+            }
+
+            map += &format!("{},{},{},{},{},{}\n", font.face, glyph.id, cell_x, cell_y, glyph.x_advance, font.line_height);
+        }
+
+        let kerning: String = font.kerning.iter()
+            .map(|(first, second, amount)| format!("{},{},{},{}\n", font.face, first, second, amount))
+            .collect();
+
+        atlas.save(&outputs[0]).unwrap_or_else(|err| panic!("Failed to write {}: {}", outputs[0], err));
+        std::fs::write(&outputs[1], map).unwrap();
+        std::fs::write(&outputs[2], kerning).unwrap();
+        stamp.save();
+    });
+}
+
+/// Extract the root motion and skeleton poses from each animated model
+fn extract_animations() {
+    build_log!("Extracting root motion and skeleton poses");
+    ANIMATED_MODELS.par_iter().for_each(|(name, path)| {
+        let outputs = [
+            format!("{}/{}.txt", ROOT_MOTION_OUTPUT_DIR, name),
+            format!("{}/{}.txt", SKELETON_OUTPUT_DIR, name),
+        ];
+        let stamp = Stamp::new(&format!("animated_{}", name), &[*path], &SKELETON_SAMPLE_RATE.to_string());
+        if stamp.is_current(&outputs.iter().map(String::as_str).collect::<Vec<_>>()) {
+            return;
+        }
+
+        timed(&format!("Extracting {}", name), || {
+            let (root_motion, skeleton) = rayon::join(|| bake_root_motion(path), || bake_skeleton(path));
+            std::fs::write(&outputs[0], root_motion).unwrap();
+            std::fs::write(&outputs[1], skeleton).unwrap();
+        });
+        stamp.save();
+    });
+}
+
+/// Extract the translation of the root bone in each of a model's animations. The root bone is the
+/// first joint of the model's first skin. The output has a line with the clip name, length and key
+/// count for each clip, followed by a line per key with its time and x, y and z.
+fn bake_root_motion(path: &str) -> String {
+    let (document, buffers, _) = gltf::import(path).unwrap();
+
+    let root = match document.skins().next().and_then(|skin| skin.joints().next()) {
+        Some(root) => root.index(),
+        None => return String::new(),
+    };
+
+    let mut output = String::new();
+    for animation in document.animations() {
+        let channel = animation.channels().find(|channel| {
+            channel.target().node().index() == root
+                && channel.target().property() == gltf::animation::Property::Translation
+        });
+
+        let keys: Vec<(f32, [f32; 3])> = match channel {
+            Some(channel) => {
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                match (reader.read_inputs(), reader.read_outputs()) {
+                    (Some(times), Some(gltf::animation::util::ReadOutputs::Translations(translations))) => {
+                        times.zip(translations).collect()
+                    },
+                    _ => Vec::new(),
+                }
+            },
+            None => Vec::new(),
+        };
+
+        // Clip names can't have spaces in them, since they're separated by them
+        let name = animation.name().unwrap_or("").replace(' ', "_");
+        let length = keys.last().map(|(time, _)| *time).unwrap_or(0.0);
+        output += &format!("{} {} {}\n", name, length, keys.len());
+        for (time, [x, y, z]) in keys {
+            output += &format!("{} {} {} {}\n", time, x, y, z);
+        }
+    }
+
+    output
+}
+
+/// A node's translation, rotation as an xyzw quaternion, and scale
+type Trs = ([f32; 3], [f32; 4], [f32; 3]);
+
+/// Sample the bones of a model's first skin at a fixed rate through each of its animations, in
+/// model space. The output starts with a line listing the bone names, then the rest pose, then for
+/// each clip a line with its name, length and frame count, followed by the frames. Each pose is a
+/// line per bone with its transform's 3x4 matrix in column major order.
+fn bake_skeleton(path: &str) -> String {
+    let (document, buffers, _) = gltf::import(path).unwrap();
+
+    let joints: Vec<gltf::Node> = match document.skins().next() {
+        Some(skin) => skin.joints().collect(),
+        None => return String::new(),
+    };
+
+    let mut parents = vec![None; document.nodes().len()];
+    for node in document.nodes() {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+    let rest: Vec<Trs> = document.nodes().map(|node| node.transform().decomposed()).collect();
+
+    let names: Vec<String> = joints.iter().map(|joint| joint.name().unwrap_or("").replace(' ', "_")).collect();
+    let mut output = format!("bones {}\n", names.join(" "));
+    output += &format_pose(&joints, &parents, &rest);
+
+    for animation in document.animations() {
+        let channels: Vec<_> = animation.channels().map(|channel| {
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let times: Vec<f32> = reader.read_inputs().map(|times| times.collect()).unwrap_or_default();
+            let values: Vec<[f32; 4]> = match reader.read_outputs() {
+                Some(gltf::animation::util::ReadOutputs::Translations(values)) => {
+                    values.map(|[x, y, z]| [x, y, z, 0.0]).collect()
+                },
+                Some(gltf::animation::util::ReadOutputs::Rotations(values)) => values.into_f32().collect(),
+                Some(gltf::animation::util::ReadOutputs::Scales(values)) => {
+                    values.map(|[x, y, z]| [x, y, z, 0.0]).collect()
+                },
+                _ => Vec::new(),
+            };
+            // Cubic spline keys have an in and out tangent either side of each value, which we skip
+            let values = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::CubicSpline => values.into_iter().skip(1).step_by(3).collect(),
+                _ => values,
+            };
+            (channel.target().node().index(), channel.target().property(), times, values)
+        }).collect();
+
+        let length = channels.iter().filter_map(|(_, _, times, _)| times.last().copied()).fold(0.0, f32::max);
+        let frames = (length * SKELETON_SAMPLE_RATE).ceil() as usize + 1;
+
+        let name = animation.name().unwrap_or("").replace(' ', "_");
+        output += &format!("{} {} {}\n", name, length, frames);
+        for frame in 0..frames {
+            let time = f32::min(frame as f32 / SKELETON_SAMPLE_RATE, length);
+            let mut pose = rest.clone();
+            for (node, property, times, values) in channels.iter() {
+                let value = sample_keys(times, values, time, *property == gltf::animation::Property::Rotation);
+                let [x, y, z, w] = match value {
+                    Some(value) => value,
+                    None => continue,
+                };
+                match property {
+                    gltf::animation::Property::Translation => pose[*node].0 = [x, y, z],
+                    gltf::animation::Property::Rotation => pose[*node].1 = [x, y, z, w],
+                    gltf::animation::Property::Scale => pose[*node].2 = [x, y, z],
+                    gltf::animation::Property::MorphTargetWeights => (),
+                }
+            }
+            output += &format_pose(&joints, &parents, &pose);
+        }
+    }
+
+    output
+}
+
+/// Sample a channel's keys at a time, interpolating linearly between them, and normalizing
+/// rotations afterwards
+fn sample_keys(times: &[f32], values: &[[f32; 4]], time: f32, rotation: bool) -> Option<[f32; 4]> {
+    if times.is_empty() || values.len() < times.len() {
+        return None;
+    }
+
+    let next = times.iter().position(|key_time| *key_time > time).unwrap_or(times.len());
+    let (start, end, t) = match next {
+        0 => (0, 0, 0.0),
+        next if next == times.len() => (next - 1, next - 1, 0.0),
+        next => (next - 1, next, (time - times[next - 1]) / (times[next] - times[next - 1])),
+    };
+
+    // Take the shortest way around between rotations
+    let (a, mut b) = (values[start], values[end]);
+    if rotation && (0..4).map(|i| a[i] * b[i]).sum::<f32>() < 0.0 {
+        b = b.map(|value| -value);
+    }
+
+    let mut value = [0.0; 4];
+    for i in 0..4 {
+        value[i] = a[i] + (b[i] - a[i]) * t;
+    }
+    if rotation {
+        let length = value.iter().map(|value| value * value).sum::<f32>().sqrt();
+        value = value.map(|value| value / length);
+    }
+
+    Some(value)
+}
+
+/// Get the matrix of a translation, rotation and scale
+fn trs_matrix((t, [x, y, z, w], s): &Trs) -> Mat4 {
+    [
+        [(1.0 - 2.0 * (y * y + z * z)) * s[0], 2.0 * (x * y + z * w) * s[0], 2.0 * (x * z - y * w) * s[0], 0.0],
+        [2.0 * (x * y - z * w) * s[1], (1.0 - 2.0 * (x * x + z * z)) * s[1], 2.0 * (y * z + x * w) * s[1], 0.0],
+        [2.0 * (x * z + y * w) * s[2], 2.0 * (y * z - x * w) * s[2], (1.0 - 2.0 * (x * x + y * y)) * s[2], 0.0],
+        [t[0], t[1], t[2], 1.0],
+    ]
+}
+
+/// Write out the model space transform of each joint in a pose
+fn format_pose(joints: &[gltf::Node], parents: &[Option<usize>], pose: &[Trs]) -> String {
+    let mut output = String::new();
+    for joint in joints {
+        let mut transform = trs_matrix(&pose[joint.index()]);
+        let mut parent = parents[joint.index()];
+        while let Some(index) = parent {
+            transform = mat_mul(&trs_matrix(&pose[index]), &transform);
+            parent = parents[index];
+        }
+
+        let values: Vec<String> = transform.iter().flat_map(|column| column[0..3].iter()).map(|v| v.to_string()).collect();
+        output += &values.join(" ");
+        output += "\n";
+    }
+    output
+}
+
+/// Load all the triangles in a world model, in world space
+fn load_triangles(path: &str) -> Vec<[[f32; 3]; 3]> {
+    let (document, buffers, _) = gltf::import(path).unwrap();
+
+    let mut triangles = Vec::new();
+    for scene in document.scenes() {
+        for node in scene.nodes() {
+            collect_triangles(&node, &buffers, IDENTITY, &mut triangles);
+        }
+    }
+
+    triangles
+}
+
+/// Export the empties in a world model whose names start with one of the point prefixes, like
+/// spawn_village_gate or trigger_well, so they can be spawned along with the level. The output has
+/// a line per point with its kind, name, world space position, forward direction, and the half
+/// extents of a unit cube scaled by its transform.
+fn bake_points(path: &str) -> String {
+    let (document, _, _) = gltf::import(path).unwrap();
+
+    let mut output = String::new();
+    for scene in document.scenes() {
+        for node in scene.nodes() {
+            collect_points(&node, IDENTITY, &mut output);
+        }
+    }
+
+    output
+}
+
+/// Write out a node's point entity if it's got one, and its children's
+fn collect_points(node: &gltf::Node, parent: Mat4, out: &mut String) {
+    let transform = mat_mul(&parent, &node.transform().matrix());
+
+    let is_empty = node.mesh().is_none() && node.camera().is_none() && node.skin().is_none();
+    let point = node.name()
+        .and_then(|name| name.split_once('_'))
+        .filter(|(prefix, name)| is_empty && POINT_PREFIXES.contains(prefix) && !name.is_empty());
+
+    if let Some((kind, name)) = point {
+        let length = |v: &[f32; 4]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        let [x, y, z, _] = transform[3];
+        let [fx, fy, fz, _] = transform[2].map(|v| -v / length(&transform[2]).max(f32::EPSILON));
+        let [ex, ey, ez] = [length(&transform[0]), length(&transform[1]), length(&transform[2])];
+        // Names can't have spaces in them, since the fields are separated by them
+        *out += &format!("{} {} {} {} {} {} {} {} {} {} {}\n", kind, name.replace(' ', "_"), x, y, z, fx, fy, fz, ex, ey, ez);
+    }
+
+    for child in node.children() {
+        collect_points(&child, transform, out);
+    }
+}
+
+/// Bake a collision mesh from a world model's triangles, as a flat list of little endian f32
+/// vertex positions with three vertices per triangle
+fn bake_collision_mesh(triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+    triangles.iter().flatten().flatten().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+/// Bake the collision BVHs for a world model's triangles, splitting them into square chunks by
+/// their centres and building a bounding volume hierarchy for each. The output is little endian,
+/// with a u32 chunk count, then for each chunk a u32 triangle count followed by its triangles as
+/// nine f32s each, and a u32 node count followed by its nodes. Each node is its bounds' min and max
+/// as six f32s, then two u32s: for a leaf, its first triangle and triangle count, and for a branch,
+/// the index of its second child and 0, with its first child straight after it.
+fn bake_collision_bvh(triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+    let mut chunks: BTreeMap<(i32, i32), Vec<[[f32; 3]; 3]>> = BTreeMap::new();
+    for triangle in triangles {
+        let centre = triangle_centre(triangle);
+        let chunk = ((centre[0] / COLLISION_CHUNK_SIZE).floor() as i32, (centre[2] / COLLISION_CHUNK_SIZE).floor() as i32);
+        chunks.entry(chunk).or_default().push(*triangle);
+    }
+
+    let mut output = Vec::new();
+    let write_u32 = |output: &mut Vec<u8>, value: u32| output.extend(value.to_le_bytes());
+    write_u32(&mut output, chunks.len() as u32);
+    for (_, mut triangles) in chunks {
+        let mut nodes = Vec::new();
+        build_bvh(&mut triangles, 0, &mut nodes);
+
+        write_u32(&mut output, triangles.len() as u32);
+        output.extend(triangles.iter().flatten().flatten().flat_map(|value| value.to_le_bytes()));
+        write_u32(&mut output, nodes.len() as u32);
+        for (min, max, a, b) in nodes {
+            output.extend(min.iter().chain(max.iter()).flat_map(|value| value.to_le_bytes()));
+            write_u32(&mut output, a);
+            write_u32(&mut output, b);
+        }
+    }
+
+    output
+}
+
+/// A BVH node, with its bounds and either its first triangle and triangle count or its second
+/// child and 0
+type BvhNode = ([f32; 3], [f32; 3], u32, u32);
+
+/// Build a BVH over some triangles, which start at an offset into the chunk's triangles, splitting
+/// them at the median along the longest axis of their centres. The triangles are sorted in place so
+/// each leaf's are next to each other.
+fn build_bvh(triangles: &mut [[[f32; 3]; 3]], offset: usize, nodes: &mut Vec<BvhNode>) {
+    let (min, max) = triangle_bounds(triangles);
+    let index = nodes.len();
+    nodes.push((min, max, offset as u32, triangles.len() as u32));
+    if triangles.len() <= BVH_LEAF_SIZE {
+        return;
+    }
+
+    let (centre_min, centre_max) = triangle_bounds(&triangles.iter().map(|triangle| [triangle_centre(triangle); 3]).collect::<Vec<_>>());
+    let axis = (0..3).max_by(|a, b| (centre_max[*a] - centre_min[*a]).total_cmp(&(centre_max[*b] - centre_min[*b]))).unwrap();
+    triangles.sort_by(|a, b| triangle_centre(a)[axis].total_cmp(&triangle_centre(b)[axis]));
+
+    let half = triangles.len() / 2;
+    let (first, second) = triangles.split_at_mut(half);
+    build_bvh(first, offset, nodes);
+    let second_index = nodes.len();
+    build_bvh(second, offset + half, nodes);
+    nodes[index].2 = second_index as u32;
+    nodes[index].3 = 0;
+}
+
+fn triangle_centre([a, b, c]: &[[f32; 3]; 3]) -> [f32; 3] {
+    [(a[0] + b[0] + c[0]) / 3.0, (a[1] + b[1] + c[1]) / 3.0, (a[2] + b[2] + c[2]) / 3.0]
+}
+
+/// Get the bounds of a world model's triangles
+fn triangle_bounds(triangles: &[[[f32; 3]; 3]]) -> ([f32; 3], [f32; 3]) {
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    for point in triangles.iter().flatten() {
+        for i in 0..3 {
+            min[i] = f32::min(min[i], point[i]);
+            max[i] = f32::max(max[i], point[i]);
+        }
+    }
+    (min, max)
+}
+
+/// Get the range of cells a triangle's bounds cover along an axis
+fn triangle_cell_range(triangle: &[[f32; 3]; 3], axis: usize, min: f32, cell_size: f32, count: usize)
+    -> std::ops::Range<usize>
+{
+    let [a, b, c] = triangle;
+    let lo = ((a[axis].min(b[axis]).min(c[axis]) - min) / cell_size).floor().max(0.0) as usize;
+    let hi = ((a[axis].max(b[axis]).max(c[axis]) - min) / cell_size).ceil() as usize;
+    lo..usize::min(hi + 1, count)
+}
+
+/// Bake a navigation grid from a world model's triangles. Each cell has a list of the heights of
+/// the walkable ground in it, so that it can have several floors, and ground is only walkable if an
+/// agent fits on it without anything in the way. The output is a header line with the min x, min
+/// z, cell size, width and height, followed by a line for each row of the grid with the cells
+/// separated by spaces, each being a comma separated list of heights or '-' if it has none.
+fn bake_nav_grid(triangles: &[[[f32; 3]; 3]]) -> String {
+    let (min, max) = triangle_bounds(triangles);
+    let width = ((max[0] - min[0]) / NAV_CELL_SIZE).ceil().max(1.0) as usize;
+    let height = ((max[2] - min[2]) / NAV_CELL_SIZE).ceil().max(1.0) as usize;
+    let cell_index = |x: f32, z: f32| {
+        let col = ((x - min[0]) / NAV_CELL_SIZE).floor();
+        let row = ((z - min[2]) / NAV_CELL_SIZE).floor();
+        match col >= 0.0 && row >= 0.0 && (col as usize) < width && (row as usize) < height {
+            true => Some(row as usize * width + col as usize),
+            false => None,
+        }
+    };
+
+    // Find the walkable ground at each cell's centre, and the heights of everything else in each cell.
+    // Walls have no height at a point, so they're sampled across their surface instead.
+    let mut ground: Vec<Vec<f32>> = vec![Vec::new(); width * height];
+    let mut obstacles: Vec<Vec<f32>> = vec![Vec::new(); width * height];
+    for triangle in triangles.iter() {
+        let [a, b, c] = triangle;
+        let walkable = triangle_normal(a, b, c)[1] >= NAV_WALKABLE_NORMAL;
+
+        if walkable {
+            for z in triangle_cell_range(triangle, 2, min[2], NAV_CELL_SIZE, height) {
+                for x in triangle_cell_range(triangle, 0, min[0], NAV_CELL_SIZE, width) {
+                    let px = min[0] + (x as f32 + 0.5) * NAV_CELL_SIZE;
+                    let pz = min[2] + (z as f32 + 0.5) * NAV_CELL_SIZE;
+                    if let Some(y) = height_in_triangle(a, b, c, px, pz) {
+                        ground[z * width + x].push(y);
+                    }
+                }
+            }
+        }
+        else {
+            let edge = |p: &[f32; 3], q: &[f32; 3]| ((p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2)).sqrt();
+            let longest = f32::max(edge(a, b), f32::max(edge(b, c), edge(c, a)));
+            let steps = (longest / (0.5 * NAV_CELL_SIZE)).ceil().max(1.0) as usize;
+            for i in 0..=steps {
+                for j in 0..=(steps - i) {
+                    let (u, v) = (i as f32 / steps as f32, j as f32 / steps as f32);
+                    let point: Vec<f32> = (0..3).map(|axis| a[axis] + (b[axis] - a[axis]) * u + (c[axis] - a[axis]) * v).collect();
+                    if let Some(cell) = cell_index(point[0], point[2]) {
+                        obstacles[cell].push(point[1]);
+                    }
+                }
+            }
+        }
+    }
+
+    // Ground is blocked if anything in its cell or the cells next to it is in the way of an agent
+    // standing on it, which also keeps agents a cell away from walls
+    let blocks = |cell: usize, y: f32| {
+        let in_the_way = |height: &f32| *height > y + NAV_STEP_HEIGHT && *height < y + NAV_AGENT_HEIGHT;
+        ground[cell].iter().any(in_the_way) || obstacles[cell].iter().any(in_the_way)
+    };
+
+    let mut output = format!("{} {} {} {} {}\n", min[0], min[2], NAV_CELL_SIZE, width, height);
+    for z in 0..height {
+        let row: Vec<String> = (0..width).map(|x| {
+            let cell = z * width + x;
+            let mut heights: Vec<f32> = ground[cell].iter().copied()
+                .filter(|y| {
+                    let neighbours = [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)].iter().filter_map(|(dx, dz)| {
+                        let (nx, nz) = (x as i64 + dx, z as i64 + dz);
+                        match nx >= 0 && nz >= 0 && (nx as usize) < width && (nz as usize) < height {
+                            true => Some(nz as usize * width + nx as usize),
+                            false => None,
+                        }
+                    });
+                    !neighbours.into_iter().any(|neighbour| blocks(neighbour, *y))
+                })
+                .collect();
+            heights.sort_by(|a, b| a.total_cmp(b));
+            heights.dedup_by(|a, b| (*a - *b).abs() < 0.1);
+
+            match heights.is_empty() {
+                true => String::from("-"),
+                false => heights.iter().map(|y| format!("{:.2}", y)).collect::<Vec<_>>().join(","),
+            }
+        }).collect();
+
+        output += &row.join(" ");
+        output += "\n";
+    }
+
+    output
+}
+
+/// Bake a top-down minimap from a world model's triangles. The output is a header line with the min x, min z,
+/// cell size, width and height, followed by a row of cell characters and a row of cell heights for
+/// each row of the map.
+fn bake_minimap(triangles: &[[[f32; 3]; 3]]) -> String {
+    let (min, max) = triangle_bounds(triangles);
+
+    let width = ((max[0] - min[0]) / MINIMAP_CELL_SIZE).ceil().max(1.0) as usize;
+    let height = ((max[2] - min[2]) / MINIMAP_CELL_SIZE).ceil().max(1.0) as usize;
+
+    // Rasterize the triangles top down, keeping the highest surface in each cell
+    let mut cells: Vec<Option<(f32, bool)>> = vec![None; width * height];
+    for [a, b, c] in triangles.iter() {
+        let normal = triangle_normal(a, b, c);
+        let is_floor = normal[1].abs() >= MINIMAP_FLOOR_NORMAL;
+
+        let cell_range = |axis: usize, count: usize| {
+            let lo = ((a[axis].min(b[axis]).min(c[axis]) - min[axis]) / MINIMAP_CELL_SIZE).floor().max(0.0) as usize;
+            let hi = ((a[axis].max(b[axis]).max(c[axis]) - min[axis]) / MINIMAP_CELL_SIZE).ceil() as usize;
+            lo..usize::min(hi + 1, count)
+        };
+
+        for z in cell_range(2, height) {
+            for x in cell_range(0, width) {
+                let px = min[0] + (x as f32 + 0.5) * MINIMAP_CELL_SIZE;
+                let pz = min[2] + (z as f32 + 0.5) * MINIMAP_CELL_SIZE;
+                if let Some(y) = height_in_triangle(a, b, c, px, pz) {
+                    let cell = &mut cells[z * width + x];
+                    if cell.map(|(top, _)| y > top).unwrap_or(true) {
+                        *cell = Some((y, is_floor));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut output = format!("{} {} {} {} {}\n", min[0], min[2], MINIMAP_CELL_SIZE, width, height);
+    for row in cells.chunks(width) {
+        output += &row.iter().map(|cell| match cell {
+            Some((_, true)) => '.',
+            Some((_, false)) => '#',
+            None => ' ',
+        }).collect::<String>();
+        output += "\n";
+
+        let heights: Vec<String> = row.iter().map(|cell| format!("{:.1}", cell.map(|(y, _)| y).unwrap_or(f32::NAN))).collect();
+        output += &heights.join(" ");
+        output += "\n";
+    }
+
+    output
+}
+
+type Mat4 = [[f32; 4]; 4];
+
+const IDENTITY: Mat4 = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+/// Multiply two column major matrices
+fn mat_mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [[0.0; 4]; 4];
+    for col in 0..4 {
+        for row in 0..4 {
+            out[col][row] = (0..4).map(|i| a[i][row] * b[col][i]).sum();
+        }
+    }
+    out
+}
+
+/// Collect a node's triangles and its children's in world space
+fn collect_triangles(node: &gltf::Node, buffers: &[gltf::buffer::Data], parent: Mat4, out: &mut Vec<[[f32; 3]; 3]>) {
+    let transform = mat_mul(&parent, &node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                Some(positions) => positions.map(|p| transform_point(&transform, p)).collect(),
+                None => continue,
+            };
+
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            for triangle in indices.chunks_exact(3) {
+                out.push([positions[triangle[0] as usize], positions[triangle[1] as usize], positions[triangle[2] as usize]]);
+            }
+        }
+    }
+
+    for child in node.children() {
+        collect_triangles(&child, buffers, transform, out);
+    }
+}
+
+fn transform_point(m: &Mat4, p: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for row in 0..3 {
+        out[row] = m[0][row] * p[0] + m[1][row] * p[1] + m[2][row] * p[2] + m[3][row];
+    }
+    out
+}
+
+fn triangle_normal(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> [f32; 3] {
+    let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt().max(f32::EPSILON);
+    [n[0] / len, n[1] / len, n[2] / len]
+}
+
+/// Get the height of a triangle at a point on the xz plane, if the point's inside it
+fn height_in_triangle(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3], x: f32, z: f32) -> Option<f32> {
+    let det = (b[2] - c[2]) * (a[0] - c[0]) + (c[0] - b[0]) * (a[2] - c[2]);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let wa = ((b[2] - c[2]) * (x - c[0]) + (c[0] - b[0]) * (z - c[2])) / det;
+    let wb = ((c[2] - a[2]) * (x - c[0]) + (a[0] - c[0]) * (z - c[2])) / det;
+    let wc = 1.0 - wa - wb;
+
+    match wa >= 0.0 && wb >= 0.0 && wc >= 0.0 {
+        true => Some(wa * a[1] + wb * b[1] + wc * c[1]),
+        false => None,
+    }
+}
+
+/// A triangle's world space vertex positions and colors, for baking LODs from
+type ColoredTriangle = [([f32; 3], [f32; 4]); 3];
+
+/// Load all the triangles in a world model with their colors, in world space. Each vertex's color
+/// is its vertex color times its material's base color, and the average color of the material's
+/// texture if it has one, since LODs are drawn untextured.
+fn load_colored_triangles(path: &str) -> Vec<ColoredTriangle> {
+    let (document, buffers, images) = gltf::import(path).unwrap();
+
+    let average_colors: Vec<[f32; 4]> = images.iter().map(average_image_color).collect();
+    let mut triangles = Vec::new();
+    for scene in document.scenes() {
+        for node in scene.nodes() {
+            collect_colored_triangles(&node, &buffers, &average_colors, IDENTITY, &mut triangles);
+        }
+    }
+
+    triangles
+}
+
+/// Get the average color of an image
+fn average_image_color(image: &gltf::image::Data) -> [f32; 4] {
+    let channels = match image.format {
+        gltf::image::Format::R8G8B8 => 3,
+        gltf::image::Format::R8G8B8A8 => 4,
+        _ => return [1.0; 4],
+    };
+
+    let mut sum = [0.0; 4];
+    let pixels = image.pixels.chunks_exact(channels);
+    let count = pixels.len().max(1) as f32;
+    for pixel in pixels {
+        for i in 0..channels {
+            sum[i] += pixel[i] as f32 / 255.0;
+        }
+    }
+
+    match channels {
+        3 => [sum[0] / count, sum[1] / count, sum[2] / count, 1.0],
+        _ => sum.map(|value| value / count),
+    }
+}
+
+/// Collect a node's colored triangles and its children's in world space
+fn collect_colored_triangles(node: &gltf::Node, buffers: &[gltf::buffer::Data], average_colors: &[[f32; 4]],
+    parent: Mat4, out: &mut Vec<ColoredTriangle>)
+{
+    let transform = mat_mul(&parent, &node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            let pbr = primitive.material().pbr_metallic_roughness();
+            let texture_color = pbr.base_color_texture()
+                .and_then(|info| average_colors.get(info.texture().source().index()).copied())
+                .unwrap_or([1.0; 4]);
+            let base_color: Vec<f32> = (0..4).map(|i| pbr.base_color_factor()[i] * texture_color[i]).collect();
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                Some(positions) => positions.map(|p| transform_point(&transform, p)).collect(),
+                None => continue,
+            };
+            let colors: Vec<[f32; 4]> = match reader.read_colors(0) {
+                Some(colors) => colors.into_rgba_f32().map(|c| [0, 1, 2, 3].map(|i| c[i] * base_color[i])).collect(),
+                None => vec![[base_color[0], base_color[1], base_color[2], base_color[3]]; positions.len()],
+            };
+
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            for triangle in indices.chunks_exact(3) {
+                out.push(triangle.iter()
+                    .map(|index| (positions[*index as usize], colors[*index as usize]))
+                    .collect::<Vec<_>>()
+                    .try_into()
+                    .unwrap());
+            }
+        }
+    }
+
+    for child in node.children() {
+        collect_colored_triangles(&child, buffers, average_colors, transform, out);
+    }
+}
+
+/// Bake the LODs for a world model, splitting it into square tiles and simplifying each tile once
+/// per cell size by vertex clustering, where every vertex in the same cell of a grid is merged and
+/// the triangles that collapse are dropped. Each LOD of each tile is written to the level's LOD
+/// directory as `<name>_lod<n>_<x>_<z>.glb`, and the returned index has a header line with the
+/// tile size, followed by a line with the x and z of each tile and how many LODs it has. A tile
+/// stops having LODs once it's simplified away to nothing.
+fn bake_lods(name: &str, path: &str, output_dir: &str, cell_sizes: &[f32]) -> String {
+    let triangles = load_colored_triangles(path);
+
+    let mut tiles: BTreeMap<(i32, i32), Vec<ColoredTriangle>> = BTreeMap::new();
+    for triangle in triangles {
+        let centre_x = (triangle[0].0[0] + triangle[1].0[0] + triangle[2].0[0]) / 3.0;
+        let centre_z = (triangle[0].0[2] + triangle[1].0[2] + triangle[2].0[2]) / 3.0;
+        let tile = ((centre_x / LOD_TILE_SIZE).floor() as i32, (centre_z / LOD_TILE_SIZE).floor() as i32);
+        tiles.entry(tile).or_default().push(triangle);
+    }
+
+    let mut index = format!("{}\n", LOD_TILE_SIZE);
+    for ((x, z), triangles) in tiles.iter() {
+        let lods: Vec<Option<Vec<u8>>> = cell_sizes.par_iter().map(|cell_size| simplify(triangles, *cell_size)).collect();
+        let lods: Vec<Vec<u8>> = lods.into_iter().map_while(|glb| glb).collect();
+        if lods.is_empty() {
+            continue;
+        }
+
+        index += &format!("{} {} {}\n", x, z, lods.len());
+        for (lod, glb) in lods.into_iter().enumerate() {
+            std::fs::write(format!("{}/{}_lod{}_{}_{}.glb", output_dir, name, lod, x, z), glb).unwrap();
+        }
+    }
+
+    index
+}
+
+/// Simplify some triangles by vertex clustering, returning them as a glb, or None if there's
+/// nothing left of them
+fn simplify(triangles: &[ColoredTriangle], cell_size: f32) -> Option<Vec<u8>> {
+    // Merge the vertices in each cell, averaging their positions and colors
+    let cell_of = |p: &[f32; 3]| p.map(|value| (value / cell_size).floor() as i32);
+    let mut clusters: BTreeMap<[i32; 3], (u32, [f32; 3], [f32; 4], u32)> = BTreeMap::new();
+    for (pos, color) in triangles.iter().flatten() {
+        let next_index = clusters.len() as u32;
+        let cluster = clusters.entry(cell_of(pos)).or_insert((next_index, [0.0; 3], [0.0; 4], 0));
+        for i in 0..3 {
+            cluster.1[i] += pos[i];
+        }
+        for i in 0..4 {
+            cluster.2[i] += color[i];
+        }
+        cluster.3 += 1;
+    }
+
+    // Keep the triangles whose corners are still in different cells, once each
+    let mut kept = std::collections::BTreeSet::new();
+    let mut indices = Vec::new();
+    for triangle in triangles {
+        let [a, b, c] = triangle.map(|(pos, _)| clusters[&cell_of(&pos)].0);
+        let mut key = [a, b, c];
+        key.sort();
+        if a != b && b != c && c != a && kept.insert(key) {
+            indices.extend([a, b, c]);
+        }
+    }
+    if indices.is_empty() {
+        return None;
+    }
+
+    let mut positions = vec![[0.0; 3]; clusters.len()];
+    let mut colors = vec![[0.0; 4]; clusters.len()];
+    for (index, pos, color, count) in clusters.values() {
+        positions[*index as usize] = pos.map(|value| value / *count as f32);
+        colors[*index as usize] = color.map(|value| value / *count as f32);
+    }
+
+    // Smooth the normals, since the clusters are shared between all their triangles
+    let mut normals = vec![[0.0; 3]; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+        let normal = triangle_normal(&a, &b, &c);
+        for index in triangle {
+            for i in 0..3 {
+                normals[*index as usize][i] += normal[i];
+            }
+        }
+    }
+    for normal in normals.iter_mut() {
+        let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt().max(f32::EPSILON);
+        *normal = normal.map(|value| value / length);
+    }
+
+    Some(write_glb(&positions, &normals, &colors, &indices))
+}
+
+/// Write a mesh to a glb with a single node and primitive, with positions, normals, vertex colors
+/// and indices
+fn write_glb(positions: &[[f32; 3]], normals: &[[f32; 3]], colors: &[[f32; 4]], indices: &[u32]) -> Vec<u8> {
+    let mut bin: Vec<u8> = Vec::new();
+    let mut views = Vec::new();
+    let mut write_view = |bytes: Vec<u8>, bin: &mut Vec<u8>| {
+        views.push((bin.len(), bytes.len()));
+        bin.extend(bytes);
+    };
+    write_view(positions.iter().flatten().flat_map(|value| value.to_le_bytes()).collect(), &mut bin);
+    write_view(normals.iter().flatten().flat_map(|value| value.to_le_bytes()).collect(), &mut bin);
+    write_view(colors.iter().flatten().flat_map(|value| value.to_le_bytes()).collect(), &mut bin);
+    write_view(indices.iter().flat_map(|value| value.to_le_bytes()).collect(), &mut bin);
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    for pos in positions {
+        for i in 0..3 {
+            min[i] = f32::min(min[i], pos[i]);
+            max[i] = f32::max(max[i], pos[i]);
+        }
+    }
+
+    let buffer_views: Vec<String> = views.iter().enumerate().map(|(i, (offset, length))| {
+        // The index buffer's an element array buffer, and the rest are vertex attributes
+        let target = if i == 3 { 34963 } else { 34962 };
+        format!(r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#, offset, length, target)
+    }).collect();
+
+    let json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+            r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"COLOR_0":2}},"indices":3}}]}}],"#,
+            r#""accessors":["#,
+            r#"{{"bufferView":0,"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}},"#,
+            r#"{{"bufferView":1,"componentType":5126,"count":{},"type":"VEC3"}},"#,
+            r#"{{"bufferView":2,"componentType":5126,"count":{},"type":"VEC4"}},"#,
+            r#"{{"bufferView":3,"componentType":5125,"count":{},"type":"SCALAR"}}],"#,
+            r#""bufferViews":[{}],"buffers":[{{"byteLength":{}}}]}}"#),
+        positions.len(), min[0], min[1], min[2], max[0], max[1], max[2],
+        normals.len(), colors.len(), indices.len(), buffer_views.join(","), bin.len());
+
+    pack_glb(json, bin)
+}
+
+/// Pack a gltf's json and binary buffer into a glb, where the binary buffer's already padded to 4
+/// bytes
+fn pack_glb(json: String, bin: Vec<u8>) -> Vec<u8> {
+    let mut json = json.into_bytes();
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+
+    // The glb header, then the json and binary chunks
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    let mut glb = Vec::with_capacity(length);
+    glb.extend(b"glTF");
+    glb.extend(2u32.to_le_bytes());
+    glb.extend((length as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(json);
+    glb.extend((bin.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(bin);
+    glb
+}
+
+/// The triangles of a mesh that are drawn with one of its textures
+struct TexturedPrimitive {
+    texture: usize,
+    /// The index of each heightmap pixel's vertex in the primitive, for terrain chunks
+    vertices: HashMap<(usize, usize), u32>,
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+/// Generate a terrain's world model, with a vertex for each pixel of its heightmap. It's split into
+/// square chunks, which are each a node with a primitive for each texture drawn on it, and each
+/// triangle is drawn with whichever texture the splat map has the most of at its corners, so the
+/// textures meet at hard edges the same as they do on the rest of the world.
+fn generate_terrain(terrain: &ManifestTerrain) -> Vec<u8> {
+    let open = |path: &str| image::open(path).unwrap_or_else(|err| panic!("Failed to open {}: {}", path, err));
+    let heightmap = open(&terrain.heightmap).to_luma16();
+    let splat = terrain.splat.as_deref().map(|path| open(path).to_rgba8());
+    let textures: Vec<Vec<u8>> = terrain.textures.iter().map(|path| std::fs::read(path).unwrap()).collect();
+    assert!((1..=4).contains(&textures.len()), "Terrain {} needs between 1 and 4 textures", terrain.name);
+
+    let (width, depth) = (heightmap.width() as usize, heightmap.height() as usize);
+    assert!(width > 1 && depth > 1, "Terrain {}'s heightmap is too small", terrain.name);
+    let spacing = [terrain.size[0] / (width - 1) as f32, terrain.size[1] / (depth - 1) as f32];
+
+    let height_at = |x: usize, z: usize| {
+        heightmap.get_pixel(x as u32, z as u32)[0] as f32 / u16::MAX as f32 * terrain.height
+    };
+    let position = |x: usize, z: usize| [
+        terrain.origin[0] + x as f32 * spacing[0],
+        terrain.origin[1] + height_at(x, z),
+        terrain.origin[2] + z as f32 * spacing[1],
+    ];
+    let normal = |x: usize, z: usize| {
+        let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+        let (back, front) = (z.saturating_sub(1), (z + 1).min(depth - 1));
+        let dx = (height_at(right, z) - height_at(left, z)) / ((right - left) as f32 * spacing[0]);
+        let dz = (height_at(x, front) - height_at(x, back)) / ((front - back) as f32 * spacing[1]);
+        let length = (dx * dx + 1.0 + dz * dz).sqrt();
+        [-dx / length, 1.0 / length, -dz / length]
+    };
+    // The splat map doesn't have to be the same size as the heightmap, so each vertex uses the
+    // nearest pixel of it
+    let weights = |x: usize, z: usize| match &splat {
+        Some(splat) => {
+            let pixel_x = (x as f32 / (width - 1) as f32 * (splat.width() - 1) as f32).round() as u32;
+            let pixel_z = (z as f32 / (depth - 1) as f32 * (splat.height() - 1) as f32).round() as u32;
+            splat.get_pixel(pixel_x, pixel_z).0.map(|weight| weight as f32)
+        },
+        None => [1.0, 0.0, 0.0, 0.0],
+    };
+
+    let mut chunks = Vec::new();
+    for chunk_z in (0..depth - 1).step_by(TERRAIN_CHUNK_QUADS) {
+        for chunk_x in (0..width - 1).step_by(TERRAIN_CHUNK_QUADS) {
+            let mut primitives: Vec<TexturedPrimitive> = (0..textures.len())
+                .map(|texture| TexturedPrimitive {
+                    texture,
+                    vertices: HashMap::new(),
+                    positions: Vec::new(),
+                    normals: Vec::new(),
+                    uvs: Vec::new(),
+                    indices: Vec::new(),
+                })
+                .collect();
+
+            for z in chunk_z..(chunk_z + TERRAIN_CHUNK_QUADS).min(depth - 1) {
+                for x in chunk_x..(chunk_x + TERRAIN_CHUNK_QUADS).min(width - 1) {
+                    // Two triangles for each quad, wound counter clockwise from above
+                    for triangle in [[(x, z), (x, z + 1), (x + 1, z)], [(x + 1, z), (x, z + 1), (x + 1, z + 1)]] {
+                        let mut totals = [0.0; 4];
+                        for (x, z) in triangle {
+                            let weights = weights(x, z);
+                            for i in 0..4 {
+                                totals[i] += weights[i];
+                            }
+                        }
+                        let texture = (0..textures.len()).max_by(|a, b| totals[*a].total_cmp(&totals[*b])).unwrap();
+
+                        let primitive = &mut primitives[texture];
+                        for (x, z) in triangle {
+                            let index = match primitive.vertices.get(&(x, z)) {
+                                Some(index) => *index,
+                                None => {
+                                    let index = primitive.positions.len() as u32;
+                                    let pos = position(x, z);
+                                    primitive.positions.push(pos);
+                                    primitive.normals.push(normal(x, z));
+                                    primitive.uvs.push([pos[0] / terrain.texture_scale, pos[2] / terrain.texture_scale]);
+                                    primitive.vertices.insert((x, z), index);
+                                    index
+                                }
+                            };
+                            primitive.indices.push(index);
+                        }
+                    }
+                }
+            }
+
+            primitives.retain(|primitive| !primitive.indices.is_empty());
+            chunks.push((format!("terrain_{}_{}", chunk_x / TERRAIN_CHUNK_QUADS, chunk_z / TERRAIN_CHUNK_QUADS), primitives));
+        }
+    }
+
+    write_textured_glb(&chunks, &textures, false)
+}
+
+/// Write textured meshes to a glb, with a named node for each mesh, and a material for each of the
+/// textures, which are embedded in it as pngs. The textures are sampled with nearest filtering and
+/// repeat, like the rest of the world's. Cutout materials are alpha masked, so that the transparent
+/// pixels of sprites aren't drawn.
+fn write_textured_glb(nodes: &[(String, Vec<TexturedPrimitive>)], textures: &[Vec<u8>], cutout: bool) -> Vec<u8> {
+    let mut bin: Vec<u8> = Vec::new();
+    let mut buffer_views = Vec::new();
+    // Vertex attributes are array buffers and indices are element array buffers, and images
+    // don't have a target
+    let mut write_view = |bytes: Vec<u8>, target: Option<u32>, bin: &mut Vec<u8>| {
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+        let target = target.map(|target| format!(r#","target":{}"#, target)).unwrap_or_default();
+        buffer_views.push(format!(r#"{{"buffer":0,"byteOffset":{},"byteLength":{}{}}}"#, bin.len(), bytes.len(), target));
+        bin.extend(bytes);
+        buffer_views.len() - 1
+    };
+
+    let (mut accessors, mut meshes, mut gltf_nodes) = (Vec::new(), Vec::new(), Vec::new());
+    for (name, primitives) in nodes {
+        let mut mesh_primitives = Vec::new();
+        for primitive in primitives {
+            let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+            for pos in primitive.positions.iter() {
+                for i in 0..3 {
+                    min[i] = f32::min(min[i], pos[i]);
+                    max[i] = f32::max(max[i], pos[i]);
+                }
+            }
+
+            let views = [
+                write_view(primitive.positions.iter().flatten().flat_map(|value| value.to_le_bytes()).collect(), Some(34962), &mut bin),
+                write_view(primitive.normals.iter().flatten().flat_map(|value| value.to_le_bytes()).collect(), Some(34962), &mut bin),
+                write_view(primitive.uvs.iter().flatten().flat_map(|value| value.to_le_bytes()).collect(), Some(34962), &mut bin),
+                write_view(primitive.indices.iter().flat_map(|value| value.to_le_bytes()).collect(), Some(34963), &mut bin),
+            ];
+
+            let first = accessors.len();
+            let count = primitive.positions.len();
+            accessors.push(format!(r#"{{"bufferView":{},"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+                views[0], count, min[0], min[1], min[2], max[0], max[1], max[2]));
+            accessors.push(format!(r#"{{"bufferView":{},"componentType":5126,"count":{},"type":"VEC3"}}"#, views[1], count));
+            accessors.push(format!(r#"{{"bufferView":{},"componentType":5126,"count":{},"type":"VEC2"}}"#, views[2], count));
+            accessors.push(format!(r#"{{"bufferView":{},"componentType":5125,"count":{},"type":"SCALAR"}}"#, views[3], primitive.indices.len()));
+
+            mesh_primitives.push(format!(r#"{{"attributes":{{"POSITION":{},"NORMAL":{},"TEXCOORD_0":{}}},"indices":{},"material":{}}}"#,
+                first, first + 1, first + 2, first + 3, primitive.texture));
+        }
+
+        gltf_nodes.push(format!(r#"{{"name":"{}","mesh":{}}}"#, name, meshes.len()));
+        meshes.push(format!(r#"{{"primitives":[{}]}}"#, mesh_primitives.join(",")));
+    }
+
+    let images: Vec<String> = textures.iter()
+        .map(|png| format!(r#"{{"bufferView":{},"mimeType":"image/png"}}"#, write_view(png.clone(), None, &mut bin)))
+        .collect();
+    let gltf_textures: Vec<String> = (0..textures.len()).map(|i| format!(r#"{{"sampler":0,"source":{}}}"#, i)).collect();
+    let alpha_mode = match cutout {
+        true => r#""alphaMode":"MASK","#,
+        false => "",
+    };
+    let materials: Vec<String> = (0..textures.len())
+        .map(|i| format!(r#"{{{}"pbrMetallicRoughness":{{"baseColorTexture":{{"index":{}}},"metallicFactor":0.0}}}}"#, alpha_mode, i))
+        .collect();
+    let scene_nodes: Vec<String> = (0..gltf_nodes.len()).map(|i| i.to_string()).collect();
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+
+    let json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0"}},"scene":0,"scenes":[{{"nodes":[{}]}}],"nodes":[{}],"meshes":[{}],"#,
+            r#""materials":[{}],"textures":[{}],"images":[{}],"#,
+            r#""samplers":[{{"magFilter":9728,"minFilter":9728,"wrapS":10497,"wrapT":10497}}],"#,
+            r#""accessors":[{}],"bufferViews":[{}],"buffers":[{{"byteLength":{}}}]}}"#),
+        scene_nodes.join(","), gltf_nodes.join(","), meshes.join(","), materials.join(","), gltf_textures.join(","),
+        images.join(","), accessors.join(","), buffer_views.join(","), bin.len());
+
+    pack_glb(json, bin)
+}
+
+/// Bake a potentially visible set from a world model's triangles. The model is split into square
+/// cells, each with points sampled at eye height above the ground in it, and two cells can see
+/// each other if a line between any of their points gets past every triangle. Cells without any
+/// ground can't be stood in, and are treated as visible from everywhere, since the walls and
+/// ceilings in them are seen from the cells around them. The output is a header line with the min
+/// x, min z, cell size, width and height, followed by a line for each cell with the indices of the
+/// cells it can see, or '*' if it has no ground.
+fn bake_pvs(triangles: &[[[f32; 3]; 3]]) -> String {
+    let (min, max) = triangle_bounds(triangles);
+    let width = ((max[0] - min[0]) / PVS_CELL_SIZE).ceil().max(1.0) as usize;
+    let height = ((max[2] - min[2]) / PVS_CELL_SIZE).ceil().max(1.0) as usize;
+
+    // Put the triangles in the cells they overlap, for casting rays through
+    let mut cell_triangles: Vec<Vec<usize>> = vec![Vec::new(); width * height];
+    for (i, triangle) in triangles.iter().enumerate() {
+        for z in triangle_cell_range(triangle, 2, min[2], PVS_CELL_SIZE, height) {
+            for x in triangle_cell_range(triangle, 0, min[0], PVS_CELL_SIZE, width) {
+                cell_triangles[z * width + x].push(i);
+            }
+        }
+    }
+
+    // Sample every floor at each point across each cell
+    let samples: Vec<Vec<[f32; 3]>> = (0..width * height).into_par_iter().map(|cell| {
+        let (x, z) = (cell % width, cell / width);
+        let mut points = Vec::new();
+        for i in 0..PVS_SAMPLES {
+            for j in 0..PVS_SAMPLES {
+                let px = min[0] + (x as f32 + (i as f32 + 0.5) / PVS_SAMPLES as f32) * PVS_CELL_SIZE;
+                let pz = min[2] + (z as f32 + (j as f32 + 0.5) / PVS_SAMPLES as f32) * PVS_CELL_SIZE;
+                for &triangle in cell_triangles[cell].iter() {
+                    let [a, b, c] = &triangles[triangle];
+                    if triangle_normal(a, b, c)[1] < NAV_WALKABLE_NORMAL {
+                        continue;
+                    }
+                    if let Some(y) = height_in_triangle(a, b, c, px, pz) {
+                        points.push([px, y + PVS_EYE_HEIGHT, pz]);
+                    }
+                }
+            }
+        }
+        points
+    }).collect();
+
+    let blocked = |from: &[f32; 3], to: &[f32; 3]| {
+        // Walk along the line in steps smaller than a cell, testing the triangles in each cell once
+        let length = ((to[0] - from[0]).powi(2) + (to[2] - from[2]).powi(2)).sqrt();
+        let steps = (length / (0.25 * PVS_CELL_SIZE)).ceil().max(1.0) as usize;
+        let mut tested = HashSet::new();
+        (0..=steps).any(|step| {
+            let t = step as f32 / steps as f32;
+            let x = ((from[0] + (to[0] - from[0]) * t - min[0]) / PVS_CELL_SIZE).floor();
+            let z = ((from[2] + (to[2] - from[2]) * t - min[2]) / PVS_CELL_SIZE).floor();
+            let cell = (x as usize).min(width - 1) + (z as usize).min(height - 1) * width;
+            tested.insert(cell) && cell_triangles[cell].iter().any(|&triangle| segment_hits_triangle(from, to, &triangles[triangle]))
+        })
+    };
+
+    let visible: Vec<Option<Vec<usize>>> = (0..width * height).into_par_iter().map(|cell| {
+        if samples[cell].is_empty() {
+            return None;
+        }
+
+        let (x, z) = ((cell % width) as f32, (cell / width) as f32);
+        let visible = (0..width * height).filter(|&other| {
+            let (ox, oz) = ((other % width) as f32, (other / width) as f32);
+            let distance = ((ox - x).powi(2) + (oz - z).powi(2)).sqrt() * PVS_CELL_SIZE;
+            other == cell || samples[other].is_empty() || (distance <= PVS_MAX_DISTANCE && samples[cell].iter()
+                .any(|from| samples[other].iter().any(|to| !blocked(from, to))))
+        });
+        Some(visible.collect())
+    }).collect();
+
+    let mut output = format!("{} {} {} {} {}\n", min[0], min[2], PVS_CELL_SIZE, width, height);
+    for cell in visible {
+        output += &match cell {
+            Some(cells) => cells.iter().map(usize::to_string).collect::<Vec<_>>().join(" "),
+            None => "*".to_string(),
+        };
+        output += "\n";
+    }
+
+    output
+}
+
+/// Check whether a line segment goes through a triangle, not counting its ends
+fn segment_hits_triangle(from: &[f32; 3], to: &[f32; 3], [a, b, c]: &[[f32; 3]; 3]) -> bool {
+    const EPSILON: f32 = 1e-6;
+    let sub = |p: &[f32; 3], q: &[f32; 3]| [p[0] - q[0], p[1] - q[1], p[2] - q[2]];
+    let dot = |p: &[f32; 3], q: &[f32; 3]| p[0] * q[0] + p[1] * q[1] + p[2] * q[2];
+    let cross = |p: &[f32; 3], q: &[f32; 3]| [p[1] * q[2] - p[2] * q[1], p[2] * q[0] - p[0] * q[2], p[0] * q[1] - p[1] * q[0]];
+
+    let (dir, edge1, edge2) = (sub(to, from), sub(b, a), sub(c, a));
+    let p = cross(&dir, &edge2);
+    let det = dot(&edge1, &p);
+    if det.abs() < EPSILON {
+        return false;
+    }
+
+    let offset = sub(from, a);
+    let u = dot(&offset, &p) / det;
+    let q = cross(&offset, &edge1);
+    let v = dot(&dir, &q) / det;
+    let t = dot(&edge2, &q) / det;
+    u >= 0.0 && v >= 0.0 && u + v <= 1.0 && t > EPSILON && t < 1.0 - EPSILON
 }
@@ -0,0 +1,23 @@
+-- Village gameplay scripts. These are loaded from resources/scripts at startup, so they can be
+-- changed without recompiling the game.
+
+local mine_visits = 0
+
+game.on("trigger_enter", function(event)
+    if event.name == "Mine" then
+        mine_visits = mine_visits + 1
+        game.log("Entered the mine (visit " .. mine_visits .. ")")
+    end
+end)
+
+game.on("dialogue_complete", function(event)
+    if event.tree == "elf" and event.node == "dungeon" then
+        game.log("The elf warned the player about the mine")
+    end
+end)
+
+game.on("area_transition", function(event)
+    if event.to_level == "dungeon" then
+        game.log("The player went down into the mine")
+    end
+end)
@@ -1,7 +1,9 @@
 #version 330 core
+#extension GL_ARB_shading_language_420pack : require
 
 #include resources/shaders/include/uniforms.glsl
 #include resources/shaders/include/utils.glsl
+#include resources/shaders/include/crt_params.glsl
 
 #ifdef BUILDING_VERTEX_SHADER
 
@@ -25,6 +27,11 @@ in vec2 var_uv;
 
 out vec4 out_frag_color;
 
+// Cheap hash-based noise from -1 to 1
+float noise(vec2 pos) {
+    return fract(sin(dot(pos, vec2(12.9898, 78.233))) * 43758.5453) * 2.0 - 1.0;
+}
+
 void main() {
     // Sample the different components from different mipmap levels to downscale them
     const float MIP_LEVEL_Y = 0.0;
@@ -34,15 +41,22 @@ void main() {
     // https://uk.mathworks.com/help/releases/R2020a/images/ref/ntsc2rgb.html#mw_0a7b75f5-1fde-400a-ad3c-68208bdaf07e
     const mat3 yiq_to_rgb = mat3(1.0, 1.0, 1.0, 0.956, -0.272, -1.106, 0.621, -0.647, 1.703);
 
-    vec3 yiq = vec3(
+    vec3 composite_yiq = vec3(
         textureLod(tex, var_uv, MIP_LEVEL_Y).r,
         textureLod(tex, var_uv, MIP_LEVEL_I).g,
         textureLod(tex, var_uv, MIP_LEVEL_Q).b
     );
 
+    // Blend between the full resolution signal and the composite one
+    vec3 yiq = mix(textureLod(tex, var_uv, 0.0).rgb, composite_yiq, crt_params.z);
+
     // Convert to rgb
     vec3 rgb = srgb_to_linear(yiq_to_rgb * yiq);
 
+    // Darken alternate lines, and add animated static
+    rgb *= 1.0 - mod(floor(gl_FragCoord.y), 2.0) * crt_params.x;
+    rgb = max(rgb + vec3(noise(gl_FragCoord.xy + vec2(sim_time * 60.0)) * crt_params.y), vec3(0.0));
+
     out_frag_color = vec4(rgb, 1.0);
 }
 
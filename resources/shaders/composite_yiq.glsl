@@ -1,7 +1,9 @@
 #version 330 core
+#extension GL_ARB_shading_language_420pack : require
 
 #include resources/shaders/include/uniforms.glsl
 #include resources/shaders/include/utils.glsl
+#include resources/shaders/include/quantize_params.glsl
 
 #ifdef BUILDING_VERTEX_SHADER
 
@@ -25,14 +27,42 @@ in vec2 var_uv;
 
 out vec4 out_frag_color;
 
+// The bayer matrices
+const float bayer2[4] = float[4](
+    0.0, 2.0,
+    3.0, 1.0
+);
+
+const float bayer4[16] = float[16](
+     0.0,  8.0,  2.0, 10.0,
+    12.0,  4.0, 14.0,  6.0,
+     3.0, 11.0,  1.0,  9.0,
+    15.0,  7.0, 13.0,  5.0
+);
+
+// Get the ordered dithering offset for a pixel, from -0.5 to 0.5 of a quantization step
+float bayer_offset(ivec2 pixel_pos, int size) {
+    ivec2 index = pixel_pos % max(size, 1);
+    switch (size) {
+        case 2: return (bayer2[index.y * 2 + index.x] + 0.5) / 4.0 - 0.5;
+        case 4: return (bayer4[index.y * 4 + index.x] + 0.5) / 16.0 - 0.5;
+        default: return 0.0;
+    }
+}
+
 void main() {
     const mat3 rgb_to_yiq = mat3(0.299, 0.596, 0.211, 0.587, -0.274, -0.523, 0.114, -0.322, 0.312);
 
     // Sample texture
     vec3 rgb = linear_to_srgb(texture(tex, var_uv).rgb);
 
-    // Downsample to 5-bit (32 colors)
-    rgb = floor(rgb * 32.0) / 32.0;
+    // Quantize to the color depth, offsetting each pixel first so that neighbouring pixels round in
+    // different directions
+    if (quantize_params.x > 0.0) {
+        vec3 levels = exp2(quantize_params.xyz) - 1.0;
+        float offset = bayer_offset(ivec2(gl_FragCoord.xy), int(quantize_params.w));
+        rgb = clamp(floor(rgb * levels + 0.5 + offset) / levels, 0.0, 1.0);
+    }
 
     // Convert to yiq
     vec3 yiq = rgb_to_yiq * rgb;
@@ -0,0 +1,39 @@
+#version 330 core
+#extension GL_ARB_shading_language_420pack : require
+
+#include resources/shaders/include/uniforms.glsl
+#include resources/shaders/include/damage_params.glsl
+
+#ifdef BUILDING_VERTEX_SHADER
+
+layout (location = 0) in vec3 vs_pos;
+
+out vec2 fs_ndc;
+
+void main() {
+    fs_ndc = vs_pos.xy;
+    gl_Position = vec4(vs_pos.x, vs_pos.y, vs_pos.z, 1.0);
+}
+
+#endif
+
+#ifdef BUILDING_FRAGMENT_SHADER
+
+in vec2 fs_ndc;
+
+out vec4 out_frag_color;
+
+void main() {
+    // The vignette darkens towards the corners, in flat bands like the rest of the shading
+    vec2 from_centre = fs_ndc * vec2(target_aspect, 1.0) / max(target_aspect, 1.0);
+    float edge = smoothstep(1.0 - vignette_size, 1.4, length(from_centre));
+    edge = floor(edge * 4.0 + 0.5) / 4.0;
+    float vignette = vignette_color.a * edge;
+
+    // The flash covers the whole screen, over the vignette
+    float alpha = vignette + flash_color.a * (1.0 - vignette);
+    vec3 color = mix(vignette_color.rgb, flash_color.rgb, alpha > 0.0 ? flash_color.a / alpha : 0.0);
+    out_frag_color = vec4(color, alpha);
+}
+
+#endif
@@ -0,0 +1,33 @@
+#version 330 core
+
+// The debug draw shader, for the lines the template's DebugDraw resource draws over the scene.
+// It's compiled by the template rather than the shader manager, so it takes the camera as its own
+// uniform instead of the global params.
+
+#ifdef BUILDING_VERTEX_SHADER
+
+layout (location = 0) in vec3 vs_pos;
+layout (location = 1) in vec4 vs_color;
+
+uniform mat4 debug_view_proj;
+
+out vec4 fs_color;
+
+void main() {
+    fs_color = vs_color;
+    gl_Position = debug_view_proj * vec4(vs_pos, 1.0);
+}
+
+#endif
+
+#ifdef BUILDING_FRAGMENT_SHADER
+
+in vec4 fs_color;
+
+out vec4 out_frag_color;
+
+void main() {
+    out_frag_color = fs_color;
+}
+
+#endif
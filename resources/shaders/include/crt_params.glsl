@@ -0,0 +1,13 @@
+#ifndef CRT_PARAMS_GLSL
+#define CRT_PARAMS_GLSL
+
+// The CRT effect, uploaded by the template from the PostFxSettings resource. x is how much every
+// other line is darkened, y is the strength of the animated static, and z is how much of the
+// composite signal's blurred chroma is blended in, with 0 resolving the scene at full resolution.
+// The buffer is all zeros until the first upload, so the effect is off until then.
+layout (std140, binding = 15) uniform CrtParams
+{
+    vec4 crt_params;
+};
+
+#endif
@@ -0,0 +1,15 @@
+#ifndef DAMAGE_PARAMS_GLSL
+#define DAMAGE_PARAMS_GLSL
+
+// The damage feedback, uploaded by the template from the DamageFeedback resource. The flash and
+// vignette colors' alphas are how strong they are right now, and the buffer is all zeros until the
+// first upload, so nothing is drawn until then.
+layout (std140, binding = 12) uniform DamageParams
+{
+    vec4 flash_color;
+    vec4 vignette_color;
+    // How far in from the edges of the screen the vignette reaches, from 0 to 1
+    float vignette_size;
+};
+
+#endif
@@ -0,0 +1,14 @@
+#ifndef FADE_PARAMS_GLSL
+#define FADE_PARAMS_GLSL
+
+// The screen fade and letterbox, uploaded by the template from the ScreenFade resource. The alpha
+// is how far faded out the screen is, and letterbox.x is how much of the screen's height each
+// letterbox bar covers. The buffer is all zeros until the first upload, so nothing is drawn until
+// then.
+layout (std140, binding = 9) uniform FadeParams
+{
+    vec4 fade_color;
+    vec4 letterbox;
+};
+
+#endif
@@ -0,0 +1,13 @@
+#ifndef QUANTIZE_PARAMS_GLSL
+#define QUANTIZE_PARAMS_GLSL
+
+// The color depth the composite_yiq pass quantizes to, uploaded by the template from the
+// PostFxSettings resource. xyz are the bits for the red, green and blue channels, and w is the size
+// of the bayer matrix the scene's dithered with before it's quantized, with 0 for no dithering.
+// The buffer is all zeros until the first upload, so the scene isn't quantized until then.
+layout (std140, binding = 16) uniform QuantizeParams
+{
+    vec4 quantize_params;
+};
+
+#endif
@@ -0,0 +1,26 @@
+#ifndef RETRO_PARAMS_GLSL
+#define RETRO_PARAMS_GLSL
+
+// The retro rendering params, uploaded by the template from the RetroRenderSettings resource. The
+// buffer is all zeros until the first upload, so retro_params_valid is 0.0 until then.
+layout (std140, binding = 8) uniform RetroParams
+{
+    float retro_params_valid;
+    float vertex_snap_enabled;
+    float vertex_snap_scale;
+    float affine_amount;
+};
+
+// Get the pixel grid to snap vertices to, or vec2(0.0) for no snapping
+vec2 vertex_snap_grid() {
+    if (retro_params_valid == 0.0)
+        return render_res;
+    return vertex_snap_enabled != 0.0 ? render_res * vertex_snap_scale : vec2(0.0);
+}
+
+// Get how affine texture mapping is, from 0 for perspective correct to 1 for fully affine
+float affine_texture_amount() {
+    return retro_params_valid == 0.0 ? 1.0 : affine_amount;
+}
+
+#endif
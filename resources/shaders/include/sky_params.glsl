@@ -0,0 +1,17 @@
+#ifndef SKY_PARAMS_GLSL
+#define SKY_PARAMS_GLSL
+
+// The sky params, uploaded by the template from the SkyParams resource. The buffer is all zeros
+// until the first upload, so sky_params_valid is 0.0 until then.
+layout (std140, binding = 11) uniform SkyParams
+{
+    vec4 sky_sun_direction;
+    vec4 sky_sun_color;
+    vec4 sky_horizon_color;
+    vec4 sky_zenith_color;
+    float sky_params_valid;
+    float sky_sunset_amount;
+    float sky_star_visibility;
+};
+
+#endif
@@ -0,0 +1,28 @@
+#ifndef TEXT_STYLE_PARAMS_GLSL
+#define TEXT_STYLE_PARAMS_GLSL
+
+// The colored runs of rich text, uploaded by the template from the TextStyles resource. The
+// renderer doesn't know about styles, so each fragment's color is found by which run it's in. The
+// buffer is all zeros until the first upload, so there are no colored runs until then.
+#define MAX_TEXT_COLORS 64
+
+layout (std140, binding = 14) uniform TextStyleParams
+{
+    vec4 text_color_count;
+    // The bounds of each run in normalized device coordinates, as (min x, min y, max x, max y)
+    vec4 text_color_rects[MAX_TEXT_COLORS];
+    vec4 text_colors[MAX_TEXT_COLORS];
+};
+
+// Get the color of the text at a point in normalized device coordinates
+vec4 text_color(vec2 ndc) {
+    for (int i = 0; i < int(text_color_count.x) && i < MAX_TEXT_COLORS; i++) {
+        vec4 rect = text_color_rects[i];
+        if (all(greaterThanEqual(ndc, rect.xy)) && all(lessThanEqual(ndc, rect.zw)))
+            return text_colors[i];
+    }
+
+    return vec4(1.0);
+}
+
+#endif
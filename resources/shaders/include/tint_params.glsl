@@ -0,0 +1,33 @@
+#ifndef TINT_PARAMS_GLSL
+#define TINT_PARAMS_GLSL
+
+// The tints of the entities closest to the camera, uploaded by the template from their Tint
+// components. The renderer doesn't know about tints, so each model's is found by the position of
+// its model matrix. The buffer is all zeros until the first upload, so there are no tints until
+// then.
+#define MAX_TINTS 32
+
+layout (std140, binding = 13) uniform TintParams
+{
+    vec4 tint_count;
+    // The position of each tinted entity, with how far it's flashed towards white in w
+    vec4 tint_positions[MAX_TINTS];
+    vec4 tint_colors[MAX_TINTS];
+};
+
+// Get the tint and flash of the model being drawn
+vec4 model_tint(out float flash) {
+    vec3 model_pos = mat_model[3].xyz;
+    for (int i = 0; i < int(tint_count.x) && i < MAX_TINTS; i++) {
+        vec3 offset = tint_positions[i].xyz - model_pos;
+        if (dot(offset, offset) < 1e-6) {
+            flash = tint_positions[i].w;
+            return tint_colors[i];
+        }
+    }
+
+    flash = 0.0;
+    return vec4(1.0);
+}
+
+#endif
@@ -0,0 +1,18 @@
+#ifndef WATER_PARAMS_GLSL
+#define WATER_PARAMS_GLSL
+
+// The water surface params, uploaded by the template from the WaterSettings resource. The buffer
+// is all zeros until the first upload, so water_params_valid is 0.0 until then.
+layout (std140, binding = 10) uniform WaterParams
+{
+    float water_params_valid;
+    float water_bands;
+    float water_alpha;
+    float camera_underwater;
+    vec2 water_scroll;
+    float ripple_height;
+    float ripple_length;
+    float ripple_speed;
+};
+
+#endif
@@ -1,8 +1,11 @@
 #version 400 core
+#extension GL_ARB_shading_language_420pack : require
 
 #define SNAP_VERTEX_POS
 
 #include resources/shaders/include/uniforms.glsl
+#include resources/shaders/include/retro_params.glsl
+#include resources/shaders/include/tint_params.glsl
 #include resources/shaders/include/utils.glsl
 
 #ifdef BUILDING_VERTEX_SHADER
@@ -26,6 +29,7 @@ noperspective out float frag_dist;
 noperspective out vec3 frag_world_pos;
 noperspective out vec3 frag_nrm;
 noperspective out vec2 frag_uv;
+out vec2 frag_uv_persp;
 noperspective out vec3 frag_light;
 #endif
 
@@ -44,7 +48,9 @@ void main() {
     vec4 clip_pos = mat_proj * eye_pos;
 
 #ifdef SNAP_VERTEX_POS
-    clip_pos = snap_pos(clip_pos, render_res);
+    vec2 snap_grid = vertex_snap_grid();
+    if (snap_grid.x > 0.0 && snap_grid.y > 0.0)
+        clip_pos = snap_pos(clip_pos, snap_grid);
 #endif
 
 #ifdef TESSELLATION_ENABLED
@@ -59,6 +65,7 @@ void main() {
     frag_world_pos = world_pos.xyz;
     frag_nrm = normalize(mat_normal * vs_normal);
     frag_uv = vs_uv;
+    frag_uv_persp = vs_uv;
     frag_dist = length(eye_pos);
     gl_Position = clip_pos;
     frag_light = vs_col.rgb;
@@ -124,6 +131,7 @@ noperspective out float frag_dist;
 noperspective out vec3 frag_world_pos;
 noperspective out vec3 frag_nrm;
 noperspective out vec2 frag_uv;
+out vec2 frag_uv_persp;
 
 noperspective out vec3 frag_light;
 
@@ -148,6 +156,7 @@ void main() {
     frag_world_pos = world_pos;
     frag_nrm = normalize(mat_normal * normal);
     frag_uv = uv;
+    frag_uv_persp = uv;
     frag_dist = length(eye_pos);
     gl_Position = clip_pos;
     frag_light = col;
@@ -163,13 +172,17 @@ noperspective in float frag_dist;
 noperspective in vec3 frag_world_pos;
 noperspective in vec3 frag_nrm;
 noperspective in vec2 frag_uv;
+in vec2 frag_uv_persp;
 noperspective in vec3 frag_light;
 
 out vec4 out_frag_color;
 
 void main() {
+    // Blend between the affine and perspective correct uvs for the texture warping
+    vec2 uv = mix(frag_uv_persp, frag_uv, affine_texture_amount());
+
     // Sample base color texture and calculate base color and alpha
-    vec4 base_color_tex = has_base_color_texture ? texture(tex_base_color, frag_uv) : vec4(1.0);
+    vec4 base_color_tex = has_base_color_texture ? texture(tex_base_color, uv) : vec4(1.0);
     vec3 albedo = base_color.rgb * base_color_tex.rgb;
     float alpha = base_color.a * base_color_tex.a;
 
@@ -177,6 +190,14 @@ void main() {
     if (alpha < 0.1)
         discard;
 
+    // Apply the model's tint, with screen door transparency for its opacity
+    float flash;
+    vec4 tint = model_tint(flash);
+    albedo *= tint.rgb;
+    ivec2 dither_pos = ivec2(gl_FragCoord.xy) % 4;
+    if (tint.a < (dither_values[dither_pos.y * 4 + dither_pos.x] + 4.5) / 8.0)
+        discard;
+
     // Calculate vertex lighting for fragment
     const vec3 AMBIENT_LIGHT = vec3(0.01);
     vec3 light = 1.5 * frag_light * lighting_strength + AMBIENT_LIGHT;
@@ -201,6 +222,9 @@ void main() {
     vec3 post_fog_color = post_dither_color * (1.0 - fog_factor)
         + fog_factor * fog_color;
 
+    // Hit flashes push the color to white through the fog
+    post_fog_color = mix(post_fog_color, vec3(1.0), flash);
+
     out_frag_color = vec4(post_fog_color, alpha);
 }
 
@@ -0,0 +1,38 @@
+#version 330 core
+#extension GL_ARB_shading_language_420pack : require
+
+#include resources/shaders/include/text_style_params.glsl
+
+#ifdef BUILDING_VERTEX_SHADER
+
+layout (location = 0) in vec3 in_pos;
+layout (location = 1) in vec2 in_uv;
+
+out vec2 var_uv;
+out vec2 var_ndc;
+
+void main() {
+    var_uv = in_uv;
+    var_ndc = in_pos.xy;
+    gl_Position = vec4(in_pos.x, in_pos.y, in_pos.z, 1.0);
+}
+
+#endif
+
+#ifdef BUILDING_FRAGMENT_SHADER
+
+uniform sampler2D blit_tex;
+
+in vec2 var_uv;
+in vec2 var_ndc;
+
+out vec4 out_frag_color;
+
+void main() {
+    vec4 tex_sample = texture(blit_tex, var_uv);
+    if (tex_sample.a < 0.1)
+        discard;
+    out_frag_color = tex_sample * text_color(var_ndc);
+}
+
+#endif
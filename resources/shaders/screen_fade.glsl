@@ -0,0 +1,37 @@
+#version 330 core
+#extension GL_ARB_shading_language_420pack : require
+
+#include resources/shaders/include/uniforms.glsl
+#include resources/shaders/include/fade_params.glsl
+
+#ifdef BUILDING_VERTEX_SHADER
+
+layout (location = 0) in vec3 vs_pos;
+
+out vec2 fs_ndc;
+
+void main() {
+    fs_ndc = vs_pos.xy;
+    gl_Position = vec4(vs_pos.x, vs_pos.y, vs_pos.z, 1.0);
+}
+
+#endif
+
+#ifdef BUILDING_FRAGMENT_SHADER
+
+in vec2 fs_ndc;
+
+out vec4 out_frag_color;
+
+void main() {
+    // The letterbox bars cover the top and bottom of the screen, over the fade
+    float bar_edge = 1.0 - 2.0 * letterbox.x;
+    if (abs(fs_ndc.y) > bar_edge) {
+        out_frag_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    out_frag_color = fade_color;
+}
+
+#endif
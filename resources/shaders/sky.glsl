@@ -1,7 +1,9 @@
 #version 330 core
+#extension GL_ARB_shading_language_420pack : require
 
 #include resources/shaders/include/constants.glsl
 #include resources/shaders/include/uniforms.glsl
+#include resources/shaders/include/sky_params.glsl
 #include resources/shaders/include/utils.glsl
 
 #ifdef BUILDING_VERTEX_SHADER
@@ -37,6 +39,37 @@ vec3 calc_ray_dir(vec2 pixel_pos) {
     return normalize(ray_dir);
 }
 
+// Cheap hash-based noise from 0 to 1
+float hash(vec2 pos) {
+    return fract(sin(dot(pos, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+// Draw the sky from the sky params, with a gradient from the horizon to the zenith lit by the
+// texture, the sun and its sunrise and sunset glow, and stars at night
+vec3 sky_color(vec3 ray_dir, vec2 uv, vec3 tex_color) {
+    vec3 gradient = mix(sky_horizon_color.rgb, sky_zenith_color.rgb, smoothstep(0.0, 0.6, ray_dir.y));
+    vec3 color = gradient * (0.5 + tex_color);
+
+    // The glow spreads out along the horizon around the sun
+    vec3 sun_dir = sky_sun_direction.xyz;
+    float sun_dot = max(dot(ray_dir, sun_dir), 0.0);
+    float glow = pow(sun_dot, 6.0) * (1.0 - smoothstep(0.0, 0.5, ray_dir.y));
+    color += sky_sun_color.rgb * glow * sky_sunset_amount;
+
+    // A hard edged sun disc, like a sprite
+    const float SUN_SIZE = 0.9985;
+    if (sun_dot > SUN_SIZE)
+        color = sky_sun_color.rgb;
+
+    // Stars are single texels of the equirectangular projection, so they stay put as the camera turns
+    const vec2 STAR_GRID = vec2(1024.0, 512.0);
+    const float STAR_DENSITY = 0.003;
+    float star = step(1.0 - STAR_DENSITY, hash(floor(uv * STAR_GRID)));
+    color += vec3(star * sky_star_visibility);
+
+    return color;
+}
+
 // Project a ray direction to equirectangular texture coordinates
 vec2 project_equirectangular(vec3 ray_dir) {
     return vec2(atan(ray_dir.z, ray_dir.x) + M_PI, acos(ray_dir.y)) / vec2(2.0 * M_PI, M_PI);
@@ -60,11 +93,21 @@ void main() {
     vec2 uv = project_equirectangular(ray_dir); 
 
     // Sample skybox texture. To save storing the bottom half of the equirectangular skybox image, which is black, we
-    // black out the lower part of the view here, and scale the uv accordingly
+    // fill the lower part of the view with the fog color here, and scale the uv accordingly
     vec3 out_color = uv.y > 0.5
-        ? vec3(0.0)
+        ? fog_color
         : texture(tex_skybox, vec2(uv.x, uv.y * 2.0)).rgb;
 
+    // The sky's lit by the sky params, which are driven by the day/night cycle, and is just the
+    // texture until they've been uploaded
+    if (sky_params_valid != 0.0 && uv.y <= 0.5)
+        out_color = sky_color(ray_dir, uv, out_color);
+
+    // Fade the sky into the fog at the horizon
+    const float HORIZON_FADE_START = 0.4;
+    float horizon_factor = smoothstep(HORIZON_FADE_START, 0.5, uv.y);
+    out_color = mix(out_color, fog_color, horizon_factor);
+
     // Add dithering
     const float DITHER_EXPONENT = 0.65;
     float dither_strength = pow(luma(out_color), DITHER_EXPONENT);
@@ -0,0 +1,98 @@
+#version 400 core
+#extension GL_ARB_shading_language_420pack : require
+
+#include resources/shaders/include/uniforms.glsl
+#include resources/shaders/include/retro_params.glsl
+#include resources/shaders/include/water_params.glsl
+#include resources/shaders/include/utils.glsl
+
+#ifdef BUILDING_VERTEX_SHADER
+
+layout(location = 0) in vec3 vs_pos;
+layout(location = 1) in vec3 vs_normal;
+layout(location = 5) in vec4 vs_col;
+
+noperspective out float frag_dist;
+noperspective out vec2 frag_uv;
+noperspective out vec3 frag_nrm;
+out vec4 frag_col;
+
+// The height of the ripples at a world position, two waves crossing at an angle so they don't
+// line up with the plane's grid
+float ripple(vec2 pos) {
+    float wave_number = 6.2831853 / max(ripple_length, 0.01);
+    float phase = sim_time * ripple_speed;
+    return ripple_height * 0.5 * (sin(dot(pos, vec2(0.8, 0.6)) * wave_number + phase)
+        + sin(dot(pos, vec2(-0.6, 0.8)) * wave_number * 1.3 + phase * 0.7));
+}
+
+void main() {
+    vec4 world_pos = mat_model * vec4(vs_pos, 1.0);
+
+    // Ripple the surface, working out the normal from the slope of the ripples
+    const float SLOPE_STEP = 0.1;
+    float height = ripple(world_pos.xz);
+    float slope_x = (ripple(world_pos.xz + vec2(SLOPE_STEP, 0.0)) - height) / SLOPE_STEP;
+    float slope_z = (ripple(world_pos.xz + vec2(0.0, SLOPE_STEP)) - height) / SLOPE_STEP;
+    world_pos.y += height;
+
+    vec4 eye_pos = mat_view * world_pos;
+    vec4 clip_pos = mat_proj * eye_pos;
+
+    vec2 snap_grid = vertex_snap_grid();
+    if (snap_grid.x > 0.0 && snap_grid.y > 0.0)
+        clip_pos = snap_pos(clip_pos, snap_grid);
+
+    // The uvs are in world space, so the scrolling lines up between neighbouring surfaces
+    frag_uv = world_pos.xz + water_scroll * sim_time;
+    frag_nrm = normalize(vec3(-slope_x, 1.0, -slope_z));
+    frag_col = vs_col;
+    frag_dist = length(eye_pos);
+    gl_Position = clip_pos;
+}
+
+#endif
+
+#ifdef BUILDING_FRAGMENT_SHADER
+
+noperspective in float frag_dist;
+noperspective in vec2 frag_uv;
+noperspective in vec3 frag_nrm;
+in vec4 frag_col;
+
+out vec4 out_frag_color;
+
+void main() {
+    // Fall back to a few bands until the params have been uploaded
+    float bands = water_params_valid == 0.0 ? 4.0 : max(water_bands, 1.0);
+    float alpha = water_params_valid == 0.0 ? 0.6 : water_alpha;
+
+    // Cheap caustic-ish pattern from the scrolling uvs, lit by how much the ripples face upwards
+    float pattern = 0.5 + 0.25 * (sin(frag_uv.x * 1.7 + sin(frag_uv.y * 1.3)) + sin(frag_uv.y * 2.1 + sin(frag_uv.x * 0.9)));
+    float light = mix(0.6, 1.2, pattern) * mix(0.7, 1.0, frag_nrm.y);
+
+    // Quantize the light into a few flat bands, like the PS1's limited shading
+    light = floor(light * bands + 0.5) / bands;
+
+    vec3 color = frag_col.rgb * base_color.rgb * light;
+
+    // Seen from below, the surface is mostly the underwater fog
+    if (camera_underwater != 0.0) {
+        color *= 0.5;
+        alpha = max(alpha, 0.85);
+    }
+
+    color = dither(color, ivec2(gl_FragCoord.xy), pow(luma(color), 0.5));
+
+    // Fog the color, and fade the surface out into the fog with it so the far edge of the water
+    // doesn't cut across the horizon
+    float fog_factor = fog_dist.y > 0.0 && fog_dist.y > fog_dist.x ?
+        clamp((frag_dist - fog_dist.x)/(fog_dist.y - fog_dist.x), 0.0, 1.0)
+        : 1.0;
+    color = mix(color, fog_color, fog_factor);
+    alpha = mix(alpha * frag_col.a * base_color.a, 1.0, fog_factor);
+
+    out_frag_color = vec4(color, alpha);
+}
+
+#endif
@@ -3,7 +3,7 @@ mod sim;
 use bevy_ecs::prelude::*;
 use bevy_ecs::world::World;
 
-use cgmath::{vec4, vec3, vec2, Vector2, Vector3, perspective, Deg, Matrix4, SquareMatrix, Matrix3};
+use cgmath::{vec4, vec3, vec2, Vector2, Vector3, SquareMatrix, Matrix3, Matrix4, Point3, EuclideanSpace, Zero};
 use include_dir::{include_dir, Dir};
 
 use dreamfield_system::GameHost;
@@ -17,17 +17,51 @@ use dreamfield_macros::*;
 
 use sim::*;
 
-/// The fixed update frequency
-const FIXED_UPDATE: i32 = 15;
+/// The level shown behind the title screen, and the camera's position and the point it looks at
+const TITLE_LEVEL: &'static str = "village";
+const TITLE_CAMERA: (Vector3<f32>, Vector3<f32>) = (vec3(-110.0, 20.0, 140.0), vec3(-125.1, 5.8, 123.8));
 
-/// The fixed update target time
-const FIXED_UPDATE_TIME: f64 = 1.0 / (FIXED_UPDATE as f64);
+/// The player's maximum health
+const PLAYER_MAX_HEALTH: f32 = 100.0;
 
-/// The player position entering the village
-const VILLAGE_ENTRANCE: (Vector3<f32>, Vector2<f32>) = (vec3(-125.1, 5.8, 123.8), vec2(0.063, -0.5));
+/// The number of slots in the player's inventory
+const INVENTORY_CAPACITY: usize = 12;
 
-/// The world chunks
-const WORLD_CHUNKS: Dir<'_> = include_dir!("target/world_chunks");
+/// The spawn point the game starts at
+const START_SPAWN: &'static str = "start";
+
+/// The world chunks of each level
+const VILLAGE_CHUNKS: Dir<'_> = include_dir!("target/world_chunks/village");
+const DUNGEON_CHUNKS: Dir<'_> = include_dir!("target/world_chunks/dungeon");
+
+/// The LOD tiles of every level
+const WORLD_LODS: Dir<'_> = include_dir!("target/world_lods");
+
+/// The frame models of every billboard sprite
+const SPRITES: Dir<'_> = include_dir!("target/sprites");
+
+/// The decal models
+const DECALS: Dir<'_> = include_dir!("target/decals");
+
+/// The asset manifest build.rs generates, listing the models, textures and sounds in resources, and
+/// the pack with their contents, which is left out when they're read from resources at runtime
+const ASSET_MANIFEST: &'static str = include_str!("../target/asset_manifest.txt");
+#[cfg(not(feature = "loose_assets"))]
+const ASSET_PACK: &'static [u8] = include_bytes!("../target/assets.pack");
+#[cfg(feature = "loose_assets")]
+const ASSET_PACK: &'static [u8] = &[];
+
+/// The fonts' textures and glyph maps
+const MEDIEVAL_FONT_TEX: &'static [u8] = include_bytes!("../resources/fonts/0xDB_medievalish_chonker_8x8_1bpp_bmp_font_packed.png");
+const MEDIEVAL_FONT_MAP: &'static [u8] = include_bytes!("../resources/fonts/0xDB_medievalish_chonker_8x8_1bpp_bmp_font_packed.csv");
+const UI_FONT_TEX: &'static [u8] = include_bytes!("../resources/fonts/ui_glyphs.png");
+const UI_FONT_MAP: &'static [u8] = include_bytes!("../resources/fonts/ui_glyphs.csv");
+
+/// The fonts build.rs bakes from BMFont files, with a texture, glyph map and kerning pairs for each
+const BAKED_FONTS: Dir<'_> = include_dir!("target/fonts");
+
+/// The language packs, and the fonts with the extra glyph pages they need
+const LOCALES: Dir<'_> = include_dir!("resources/locales");
 
 /// Create the shader manager
 pub fn create_shader_manager() -> ShaderManager {
@@ -37,95 +71,568 @@ pub fn create_shader_manager() -> ShaderManager {
         ("ps1_tess", preprocess_shader_vtf!(include_bytes!("../resources/shaders/ps1.glsl"))),
         ("composite_yiq", preprocess_shader_vf!(include_bytes!("../resources/shaders/composite_yiq.glsl"))),
         ("composite_resolve", preprocess_shader_vf!(include_bytes!("../resources/shaders/composite_resolve.glsl"))),
+        ("screen_fade", preprocess_shader_vf!(include_bytes!("../resources/shaders/screen_fade.glsl"))),
+        ("damage_feedback", preprocess_shader_vf!(include_bytes!("../resources/shaders/damage_feedback.glsl"))),
         ("blit", preprocess_shader_vf!(include_bytes!("../resources/shaders/blit.glsl"))),
         ("text", preprocess_shader_vf!(include_bytes!("../resources/shaders/text.glsl"))),
+        ("rich_text", preprocess_shader_vf!(include_bytes!("../resources/shaders/rich_text.glsl"))),
+        ("water", preprocess_shader_vf!(include_bytes!("../resources/shaders/water.glsl"))),
     ])
 }
 
+/// Load the asset manifest
+fn asset_manifest() -> AssetManifest {
+    AssetManifest::parse(ASSET_MANIFEST, ASSET_PACK)
+}
+
 /// Create the texture manager
 pub fn create_texture_manager() -> TextureManager {
-    TextureManager::new_with_textures(vec![
-        ("sky", (include_bytes!("../resources/textures/sky.png"), TextureParams::repeat_nearest(), true, None))
-    ])
+    create_texture_manager_with(&AssetOverrides::default())
+}
+
+/// Create the texture manager, with any textures that have been reloaded from disk in place of the
+/// built in ones
+pub fn create_texture_manager_with(overrides: &AssetOverrides) -> TextureManager {
+    TextureManager::new_with_textures(asset_manifest().assets(AssetKind::Texture)
+        .map(|(name, bytes)| (name, (overrides.bytes(name, bytes), TextureParams::repeat_nearest(), true, None)))
+        .collect())
 }
 
 /// Create the model manager
 pub fn create_model_manager() -> ModelManager {
-    ModelManager::new_with_models(vec![
-        ("fire_orb", include_bytes!("../resources/models/fire_orb.glb")),
-        ("tree", include_bytes!("../resources/models/tree.glb")),
-        ("elf", include_bytes!("../resources/models/elf.glb")),
-        ("minecart", include_bytes!("../resources/models/minecart.glb")),
-    ])
+    create_model_manager_with(&AssetOverrides::default())
+}
+
+/// Create the model manager, with any models that have been reloaded from disk in place of the
+/// built in ones
+pub fn create_model_manager_with(overrides: &AssetOverrides) -> ModelManager {
+    let world_lods: &'static Dir = &WORLD_LODS;
+    // The LOD tiles are named after their path in the LOD directory
+    let lod_models = world_lods.dirs()
+        .flat_map(|dir| dir.files())
+        .filter_map(|file| Some((file.path().to_str()?.strip_suffix(".glb")?, file.contents())));
+    // The sprites' frame models and the decals are named after their files
+    let sprites: &'static Dir = &SPRITES;
+    let decals: &'static Dir = &DECALS;
+    let sprite_models = sprites.files().chain(decals.files())
+        .filter_map(|file| Some((file.path().to_str()?.strip_suffix(".glb")?, file.contents())));
+
+    let mut models: Vec<(&str, &[u8])> = asset_manifest().assets(AssetKind::Model).collect();
+    models.push(("water_plane", include_bytes!("../target/water_plane.glb")));
+    models.extend(lod_models);
+    models.extend(sprite_models);
+
+    let models = models.into_iter().map(|(name, bytes)| (name, overrides.bytes(name, bytes))).collect();
+    ModelManager::new_with_models(models)
 }
 
 /// Create the font manager
 fn create_font_manager() -> FontManager {
-    const MEDIEVAL_FONT_TEX: &'static [u8] = include_bytes!("../resources/fonts/0xDB_medievalish_chonker_8x8_1bpp_bmp_font_packed.png");
-    const MEDIEVAL_FONT_MAP: &'static [u8] = include_bytes!("../resources/fonts/0xDB_medievalish_chonker_8x8_1bpp_bmp_font_packed.csv");
-    FontManager::new(vec![
-        ("medieval", MEDIEVAL_FONT_TEX, MEDIEVAL_FONT_MAP)
+    let mut fonts = vec![
+        ("medieval", MEDIEVAL_FONT_TEX, MEDIEVAL_FONT_MAP),
+        ("ui", UI_FONT_TEX, UI_FONT_MAP)
+    ];
+    fonts.extend(baked_fonts().map(|(name, tex, map, _)| (name, tex, map)));
+    fonts.extend(locale_fonts());
+    FontManager::new(fonts)
+}
+
+/// Create the font metrics rich text is laid out with
+fn create_font_metrics() -> FontMetrics {
+    let mut fonts = vec![
+        ("medieval", MEDIEVAL_FONT_MAP),
+        ("ui", UI_FONT_MAP)
+    ];
+    fonts.extend(baked_fonts().map(|(name, _, map, _)| (name, map)));
+    fonts.extend(locale_fonts().map(|(name, _, map)| (name, map)));
+
+    let mut metrics = FontMetrics::new(fonts);
+    for (name, _, _, kerning) in baked_fonts() {
+        metrics.add_kerning(name, kerning);
+    }
+    metrics
+}
+
+/// Get the fonts baked from BMFont files, as their name, texture, glyph map and kerning pairs
+fn baked_fonts() -> impl Iterator<Item = (&'static str, &'static [u8], &'static [u8], &'static [u8])> {
+    let fonts: &'static Dir = &BAKED_FONTS;
+    fonts.files()
+        .filter(|file| file.path().extension().map_or(false, |ext| ext == "png"))
+        .filter_map(|texture| {
+            let name = texture.path().file_stem()?.to_str()?;
+            let map = fonts.get_file(texture.path().with_extension("csv"))?;
+            let kerning = fonts.get_file(texture.path().with_extension("kern"))?;
+            Some((name, texture.contents(), map.contents(), kerning.contents()))
+        })
+}
+
+/// Get the fonts language packs use in place of the default ones, which are a texture and glyph
+/// map with the same name in the fonts directory of the locales
+fn locale_fonts() -> impl Iterator<Item = (&'static str, &'static [u8], &'static [u8])> {
+    let locales: &'static Dir = &LOCALES;
+    locales.get_dir("fonts").into_iter()
+        .flat_map(|dir| dir.files())
+        .filter(|file| file.path().extension().map_or(false, |ext| ext == "png"))
+        .filter_map(|texture| {
+            let name = texture.path().file_stem()?.to_str()?;
+            let map = locales.get_file(texture.path().with_extension("csv"))?;
+            Some((name, texture.contents(), map.contents()))
+        })
+}
+
+/// Create the locale from the language packs, which are named after their language codes
+fn create_locale() -> Locale {
+    let locales: &'static Dir = &LOCALES;
+    Locale::new(locales.files()
+        .filter(|file| file.path().extension().map_or(false, |ext| ext == "csv"))
+        .filter_map(|file| Some((file.path().file_stem()?.to_str()?, file.contents_utf8()?)))
+        .collect())
+}
+
+/// Create the audio manager
+fn create_audio_manager() -> AudioManager {
+    AudioManager::new_with_sounds(asset_manifest().assets(AssetKind::Sound).collect())
+}
+
+/// Create the music manager
+fn create_music_manager() -> MusicManager {
+    MusicManager::new_with_tracks(vec![
+        ("village", include_bytes!("../resources/music/village.wav"), 0.5),
+        ("dungeon", include_bytes!("../resources/music/dungeon.wav"), 0.7),
+    ], Some("village"))
+}
+
+/// Create the dialogue manager
+fn create_dialogue_manager() -> DialogueManager {
+    DialogueManager::new(vec![
+        ("elf", include_str!("../resources/dialogue/elf.ron")),
+    ])
+}
+
+/// Create the cutscene manager
+fn create_cutscene_manager() -> CutsceneManager {
+    CutsceneManager::new(vec![
+        ("village_intro", include_str!("../resources/cutscenes/village_intro.ron")),
+    ])
+}
+
+/// Create the minimap from the maps baked by build.rs
+fn create_minimap() -> Minimap {
+    Minimap::new(vec![
+        ("village", include_str!("../target/minimaps/village.txt")),
+        ("village_hills", include_str!("../target/minimaps/village_hills.txt")),
+        ("dungeon", include_str!("../target/minimaps/dungeon.txt")),
+    ])
+}
+
+/// Create the nav mesh from the navigation grids baked by build.rs
+fn create_nav_mesh() -> NavMesh {
+    NavMesh::new(vec![
+        ("village", include_str!("../target/nav/village.txt")),
+        ("village_hills", include_str!("../target/nav/village_hills.txt")),
+        ("dungeon", include_str!("../target/nav/dungeon.txt")),
+    ])
+}
+
+/// Create the potentially visible sets baked by build.rs, for the levels that have them
+fn create_visibility_sets() -> VisibilitySets {
+    VisibilitySets::new(vec![
+        ("village", include_str!("../target/pvs/village.txt")),
+        ("dungeon", include_str!("../target/pvs/dungeon.txt")),
+    ])
+}
+
+/// Create the vegetation build.rs scattered across each level
+fn create_vegetation() -> Vegetation {
+    Vegetation::new(vec![
+        ("village", include_str!("../target/vegetation/village_hills_trees.txt")),
+    ])
+}
+
+/// Create the behavior trees for npcs and enemies
+fn create_behavior_trees() -> BehaviorTrees {
+    BehaviorTrees::new(vec![
+        ("patrol", include_str!("../resources/behaviors/patrol.ron")),
+        ("enemy", include_str!("../resources/behaviors/enemy.ron")),
+    ])
+}
+
+/// Create the quest manager
+fn create_quest_manager() -> QuestManager {
+    QuestManager::new(vec![
+        ("the_mine", include_str!("../resources/quests/the_mine.ron")),
+    ])
+}
+
+/// Create the physics world, with static colliders from the collision meshes baked by build.rs
+#[cfg(feature = "physics")]
+fn create_physics_world(tick_length: f32) -> PhysicsWorld {
+    PhysicsWorld::new(tick_length, vec![
+        ("village", include_bytes!("../target/collision/village.bin")),
+        ("village_hills", include_bytes!("../target/collision/village_hills.bin")),
+        ("dungeon", include_bytes!("../target/collision/dungeon.bin")),
+    ])
+}
+
+/// Create the static collision from the collision BVHs baked by build.rs
+fn create_static_collision() -> StaticCollision {
+    StaticCollision::new(vec![
+        ("village", include_bytes!("../target/collision_bvh/village.bin")),
+        ("village", include_bytes!("../target/collision_bvh/village_hills.bin")),
+        ("dungeon", include_bytes!("../target/collision_bvh/dungeon.bin")),
+    ])
+}
+
+/// Create the item registry
+fn create_item_registry() -> ItemRegistry {
+    ItemRegistry::new(include_str!("../resources/items.ron"))
+}
+
+/// Create the spawn tables
+fn create_spawn_tables() -> SpawnTables {
+    SpawnTables::new(include_str!("../resources/spawn_tables.ron"))
+}
+
+/// Create the root motion clips from the tracks the build script extracted
+fn create_root_motion_clips() -> RootMotionClips {
+    RootMotionClips::new(vec![
+        ("elf", include_str!("../target/root_motion/elf.txt")),
+    ])
+}
+
+/// Create the skeletons from the poses the build script sampled
+fn create_skeletons() -> Skeletons {
+    Skeletons::new(vec![
+        ("elf", include_str!("../target/skeletons/elf.txt")),
+    ])
+}
+
+/// Create the animation event markers
+fn create_animation_markers() -> AnimationMarkers {
+    AnimationMarkers::new(include_str!("../resources/anim_events.ron"))
+}
+
+/// Create the title screen, with the demo it plays in attract mode
+fn create_title_screen() -> TitleScreen {
+    TitleScreen::new(include_str!("../resources/demos/attract.ron"))
+}
+
+/// Create the levels, with the chunks and scene of each one
+fn create_levels() -> Levels {
+    Levels::new(include_str!("../resources/levels.ron"), vec![
+        ("village", &VILLAGE_CHUNKS, include_str!("../target/world_points/village.txt"), spawn_village as fn(&mut World)),
+        ("dungeon", &DUNGEON_CHUNKS, include_str!("../target/world_points/dungeon.txt"), spawn_dungeon),
     ])
 }
 
-/// Create world entities
-fn create_entities(world: &mut World) {
-    // Diagnostics
-    let stats_bounds = vec4(10.0, 10.0, 310.0, 230.0);
+/// Create the spawn points, from the spawn points file and the levels' world models
+fn create_spawn_points(levels: &Levels) -> SpawnPoints {
+    let mut spawn_points = SpawnPoints::new(include_str!("../resources/spawn_points.ron"));
+    for (level, point) in levels.spawn_points() {
+        let (pos, pitch_yaw) = (point.pos, point.pitch_yaw());
+        spawn_points.insert(&point.name, SpawnPoint {
+            level: level.to_string(),
+            pos: (pos.x, pos.y, pos.z),
+            pitch_yaw: (pitch_yaw.x, pitch_yaw.y),
+        });
+    }
+    spawn_points
+}
+
+/// Create the prefab registry
+fn create_prefab_registry() -> PrefabRegistry {
+    PrefabRegistry::new(include_str!("../resources/prefabs.ron"))
+}
+
+/// Create the entities that stay around whatever the game state is
+fn create_persistent_entities(world: &mut World) {
+    // Text boxes are made in the font the language uses
+    let font = world.resource::<Locale>().font("medieval").to_string();
+
+    // Diagnostics, with the engine's text box and the one the overlay's other pages are shown in,
+    // and the time graphs on the timings page
+    let diagnostics_size = vec2(300.0, 220.0);
     world.spawn()
+        .insert(DiagnosticsBox)
         .insert(DiagnosticsTextBox)
-        .insert(TextBox::new("text", "medieval", "Vx8", "", None, Some(stats_bounds)));
+        .insert(TextBox::new("text", &font, "Vx8", "", None, None))
+        .insert(UiLayout::anchored(UiAnchor::TopLeft, vec2(10.0, 10.0), diagnostics_size));
+    world.spawn()
+        .insert(DiagnosticsOverlayText)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, None))
+        .insert(RichText::new("medieval", "Vx8"))
+        .insert(UiLayout::anchored(UiAnchor::TopLeft, vec2(10.0, 10.0), diagnostics_size));
+    for (graph, line) in [(DiagnosticsGraph::Frame, 1.0), (DiagnosticsGraph::Tick, 3.0)] {
+        let widget = UiWidget::Graph { values: vec![0.0; GRAPH_SAMPLES] };
+        let layout = UiLayout::anchored(UiAnchor::TopLeft, vec2(10.0, 10.0 + UI_CELL_SIZE * line), widget.size());
+        let entity = spawn_widget(world, widget, layout, 0);
+        world.entity_mut(entity).insert(graph).insert(UiVisible(false));
+    }
+
+    // Pause menu
+    let menu_bounds = vec4(70.0, 70.0, 250.0, 170.0);
+    world.spawn()
+        .insert(PauseMenu)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, Some(menu_bounds)))
+        .insert(UiLayout::new(menu_bounds));
+
+    // Title screen
+    let title_bounds = vec4(100.0, 90.0, 220.0, 60.0);
+    world.spawn()
+        .insert(TitleTextBox)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, Some(title_bounds)))
+        .insert(UiLayout::new(title_bounds));
+
+    // Loading progress
+    world.spawn()
+        .insert(LoadingTextBox)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, None))
+        .insert(UiLayout::anchored(UiAnchor::Center, vec2(0.0, 0.0), vec2(200.0, 30.0)));
+
+    // Debug console
+    world.spawn()
+        .insert(ConsoleTextBox)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, None))
+        .insert(RichText::new("medieval_bm", "Vx8"))
+        .insert(UiLayout::anchored(UiAnchor::Top, vec2(0.0, 5.0), vec2(310.0, 135.0)));
+
+    // Entity inspector
+    world.spawn()
+        .insert(InspectorText)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, None))
+        .insert(RichText::new("medieval_bm", "Vx8"))
+        .insert(UiLayout::anchored(UiAnchor::TopRight, vec2(-10.0, 10.0), vec2(200.0, 220.0)));
 
     // Create sky
     world.spawn()
         .insert(ScreenEffect::new(RunTime::PreScene, "sky", Some("sky")));
 
-    // Create player
-    let (initial_pos, initial_rot) = VILLAGE_ENTRANCE;
+    // Create the damage flash and low health vignette, which are transparent until the player's hurt
+    world.spawn()
+        .insert(ScreenEffect::new(RunTime::PostScene, "damage_feedback", None));
+
+    // Create the screen fade and letterbox, which are transparent until something uses them
+    world.spawn()
+        .insert(ScreenEffect::new(RunTime::PostScene, "screen_fade", None));
+}
+
+/// Create the title screen scene, the village with a camera looking over it
+fn spawn_title_scene(world: &mut World) {
+    load_level(world, TITLE_LEVEL, None);
+
+    let (eye, target) = TITLE_CAMERA;
+    let mut camera = world.resource::<GraphicsSettings>().create_camera();
+    camera.view = Matrix4::look_at_rh(Point3::from_vec(eye), Point3::from_vec(target), vec3(0.0, 1.0, 0.0));
+    world.spawn()
+        .insert(EntityName::new("TitleCamera"))
+        .insert(camera);
+}
+
+/// Create the game, when it's started from the title screen. The player, their HUD and their
+/// companion stay around between levels.
+fn spawn_game_world(world: &mut World) {
+    // Anything left over from the last game refers to entities that are gone now
+    world.insert_resource(EntityPool::default());
+    world.insert_resource(QuestLog::default());
+    world.insert_resource(CutsceneState::default());
+
+    // Text boxes are made in the font the language uses
+    let font = world.resource::<Locale>().font("medieval").to_string();
+
+    // Orb counter
+    world.spawn()
+        .insert(OrbCounter)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, None))
+        .insert(UiLayout::anchored(UiAnchor::TopRight, vec2(-10.0, 10.0), vec2(80.0, 10.0)));
+
+    // Compass
+    world.spawn()
+        .insert(Compass)
+        .insert(TextBox::new("text", &font, "8x8", "", None, None))
+        .insert(UiLayout::anchored(UiAnchor::Top, vec2(0.0, 10.0), vec2(COMPASS_WIDTH as f32 * 8.0, 18.0)));
+
+    // Minimap
+    world.spawn()
+        .insert(MinimapTextBox)
+        .insert(TextBox::new("text", &font, "8x8", "", None, None))
+        .insert(UiLayout::anchored(UiAnchor::BottomRight, vec2(-10.0, -10.0),
+            vec2(MINIMAP_COLS as f32 * 8.0, MINIMAP_ROWS as f32 * 8.0)));
+
+    // Health and stamina bars
+    let mut hud = UiStack::vertical(UiAnchor::TopRight, vec2(-10.0, 22.0), 2.0);
+    let health_layout = hud.next(vec2(UI_CELL_SIZE * 11.0, UI_CELL_SIZE));
+    let stamina_layout = hud.next(vec2(UI_CELL_SIZE * 11.0, UI_CELL_SIZE));
+
+    for entity in spawn_icon_bar(world, UiIcon::Heart, &health_layout, 10, 1) {
+        world.entity_mut(entity).insert(HealthBar);
+    }
+
+    // The stamina bar's hidden until the player starts sprinting
+    for entity in spawn_icon_bar(world, UiIcon::Bolt, &stamina_layout, 10, 1) {
+        world.entity_mut(entity).insert(StaminaBar).insert(UiVisible(false));
+    }
+
+    // Inventory screen
+    create_inventory_screen(world, INVENTORY_CAPACITY);
+
+    // Quest objectives
     world.spawn()
+        .insert(QuestTextBox)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, None))
+        .insert(UiLayout::anchored(UiAnchor::Left, vec2(10.0, 0.0), vec2(150.0, 60.0)));
+
+    // Dialogue
+    world.spawn()
+        .insert(DialogueTextBox)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, None))
+        .insert(RichText::new("medieval_bm", "Vx8").with_typewriter(DIALOGUE_TEXT_SPEED))
+        .insert(UiLayout::anchored(UiAnchor::Bottom, vec2(0.0, -10.0), vec2(300.0, 60.0)));
+
+    // Create player, who's put at the start of the first level when it's loaded
+    let (initial_pos, initial_rot) = start_spawn_point(world);
+    let player_camera = world.resource::<GraphicsSettings>().create_camera();
+    #[cfg_attr(not(feature = "physics"), allow(unused_variables))]
+    let player = world.spawn()
         .insert(EntityName::new("Player"))
-        // Entrance to village
         .insert(Transform::new(initial_pos, Matrix3::identity()))
         .insert(PlayerMovement::new_pos_look(PlayerMovementMode::Normal, initial_rot))
         .insert(PlayerMovement::collider())
-        .insert(create_player_camera())
-        .insert(EntitySpawnRadius::new(10.0));
+        .insert(player_camera)
+        .insert(EntitySpawnRadius::new(10.0))
+        .insert(PlayerInventory::new(INVENTORY_CAPACITY))
+        .insert(Health::new(PLAYER_MAX_HEALTH))
+        .insert(MeleeAttack::default())
+        .insert(Stamina::default())
+        .insert(CastsBlobShadow::new(0.35))
+        .id();
+
+    // The player pushes physics props around with a kinematic capsule
+    #[cfg(feature = "physics")]
+    world.entity_mut(player)
+        .insert(RigidBody::kinematic())
+        .insert(PhysicsCollider { offset: vec3(0.0, 0.9, 0.0), ..PhysicsCollider::capsule(0.5, 0.4) });
+
+    // Create a companion to follow the player around, carrying a torch
+    let companion = spawn_prefab(world, "companion", Transform::new(initial_pos + vec3(1.5, 0.0, 1.5), Matrix3::identity()));
+    let torch = spawn_prefab(world, "held_torch", Transform::new(initial_pos, Matrix3::identity()));
+    if let (Some(companion), Some(torch)) = (companion, torch) {
+        world.entity_mut(torch).insert(AttachedTo::new(companion, "elf", "RHand", vec3(0.0, 0.1, 0.0)));
+    }
 
-    // Create fire orb
+    // Create the player's held item
     world.spawn()
-        .insert(FireOrb::default())
-        .insert(Transform::new(vec3(-9.0, 0.0, 9.0), Matrix3::identity()))
-        .insert(Visual::new_with_anim("fire_orb", false, Animation::Loop("Orb".to_string())));
+        .insert(HeldItem)
+        .insert(Transform::new(initial_pos, Matrix3::identity()))
+        .insert(Visual::new("sword", false));
+
+    // Load the first level, and play the intro flyover
+    teleport_player(world, START_SPAWN);
+    world.resource_mut::<CutsceneState>().play("village_intro");
 }
 
-/// Create the PlayerCamera with all our renderer params
-fn create_player_camera() -> PlayerCamera {
-    const RENDER_WIDTH: i32 = 320;
-    const RENDER_HEIGHT: i32 = 240;
+/// Get where the game starts, for placing things around the player before the first level's loaded
+fn start_spawn_point(world: &World) -> (Vector3<f32>, Vector2<f32>) {
+    world.resource::<SpawnPoints>().get(START_SPAWN)
+        .map(|point| (point.pos(), point.pitch_yaw()))
+        .unwrap_or((Vector3::zero(), Vector2::zero()))
+}
 
-    const RENDER_ASPECT: f32 = 4.0 / 3.0;
+/// Create the village level's entities
+fn spawn_village(world: &mut World) {
+    let initial_pos = start_spawn_point(world).0;
 
-    const FOV: f32 = 60.0;
-    const NEAR_CLIP: f32 = 0.1;
-    const FAR_CLIP: f32 = 35.0;
+    // The way into the mine
+    world.spawn()
+        .insert(Portal::new_to_level("dungeon", "mine_entrance"))
+        .insert(Transform::new(vec3(-63.0, 1.0, 10.0), Matrix3::identity()));
 
-    const FOG_START: f32 = FAR_CLIP - 10.0;
-    const FOG_END: f32 = FAR_CLIP - 5.0;
+    // The mine's music drifts out of its entrance, crossfading from the village's as the player
+    // gets close
+    world.spawn()
+        .insert(MusicZone::new("dungeon"))
+        .insert(Transform::new(vec3(-63.0, 1.0, 10.0), Matrix3::identity()))
+        .insert(TriggerVolume::new_sphere(12.0));
 
-    const FOG_COLOR: Vector3<f32> = vec3(0.0, 0.0, 0.0);
+    // Spawn areas
+    world.spawn()
+        .insert(SpawnArea::new("village_outskirts"))
+        .insert(Transform::new(initial_pos, Matrix3::identity()))
+        .insert(TriggerVolume::new_sphere(25.0));
+
+    // Create some crates to knock around
+    #[cfg(feature = "physics")]
+    for offset in [vec3(2.0, 0.5, -3.0), vec3(3.2, 0.5, -3.0), vec3(2.6, 1.5, -3.0)] {
+        world.spawn()
+            .insert(EntityName::new("Crate"))
+            .insert(Transform::new(initial_pos + offset, Matrix3::identity()))
+            .insert(Visual::new("crate", false))
+            .insert(RigidBody::dynamic())
+            .insert(PhysicsCollider::cuboid(vec3(0.5, 0.5, 0.5)));
+    }
 
-    let proj = perspective(Deg(FOV), RENDER_ASPECT, NEAR_CLIP, FAR_CLIP);
-    let view = Matrix4::identity();
+    // Create an elevator to ride
+    spawn_prefab(world, "elevator", Transform::new(initial_pos + vec3(-4.0, -0.125, -3.0), Matrix3::identity()));
+
+    // Create some blocks to push around
+    spawn_prefab(world, "push_block", Transform::new(initial_pos + vec3(-2.0, 0.5, -6.0), Matrix3::identity()));
+    spawn_prefab(world, "push_crate", Transform::new(initial_pos + vec3(0.0, 0.5, -6.0), Matrix3::identity()));
+
+    // Create some villagers going about their day
+    let villagers = [
+        ("villager", vec3(-6.0, 0.0, -10.0)),
+        ("villager", vec3(4.0, 0.0, -9.0)),
+        ("villager_early_riser", vec3(-2.0, 0.0, -16.0)),
+    ];
+    for (prefab, offset) in villagers {
+        spawn_prefab(world, prefab, Transform::new(initial_pos + offset, Matrix3::identity()));
+    }
 
-    PlayerCamera {
-        proj,
-        view,
-        render_res: vec2(RENDER_WIDTH as f32, RENDER_HEIGHT as f32),
-        render_aspect: RENDER_ASPECT,
-        render_fov_rad: FOV * std::f32::consts::PI / 180.0,
-        fog_color: FOG_COLOR,
-        fog_range: vec2(FOG_START, FOG_END)
+    // Scatter some flowers and coins around
+    for offset in [vec3(-3.0, 0.0, 2.0), vec3(-3.6, 0.0, 2.5), vec3(5.0, 0.0, 1.0), vec3(6.5, 0.0, -1.5)] {
+        spawn_prefab(world, "flowers", Transform::new(initial_pos + offset, Matrix3::identity()));
+    }
+    for offset in [vec3(1.0, 0.3, 4.0), vec3(2.0, 0.3, 4.0), vec3(3.0, 0.3, 4.0)] {
+        world.spawn()
+            .insert(EntityName::new("Coin"))
+            .insert(Transform::new(initial_pos + offset, Matrix3::identity()))
+            .insert(Billboard::new_animated("coin", 4, 8.0, 0.5))
+            .insert(PickupItem::new("coin", 1));
     }
+
+    // Create an enemy guarding the road
+    spawn_prefab(world, "enemy", Transform::new(initial_pos + vec3(8.0, 0.0, -14.0), Matrix3::identity()));
+
+    // Create fire orb
+    let fire_orb_transform = Transform::new(vec3(-9.0, 0.0, 9.0), Matrix3::identity());
+    world.spawn()
+        .insert(FireOrb::default())
+        .insert(PreviousTransform::new(&fire_orb_transform))
+        .insert(fire_orb_transform)
+        .insert(Visual::new_with_anim("fire_orb", false, Animation::Loop("Orb".to_string())))
+        .insert(AudioSource::new_looping("fire_crackle", 0.8, 8.0))
+        .insert(ParticleEmitter::new_fire())
+        .insert(ObjectiveMarker::default());
+}
+
+/// Create the dungeon level's entities
+fn spawn_dungeon(world: &mut World) {
+    // The mine, which the quest wants the player to explore
+    world.spawn()
+        .insert(QuestTrigger::new("mine"))
+        .insert(ScriptName::new("Mine"))
+        .insert(Transform::new(vec3(0.0, 0.0, 0.0), Matrix3::identity()))
+        .insert(TriggerVolume::new_aabb(vec3(-62.0, -1.0, -3.0), vec3(13.0, 11.0, 23.0)));
+
+    // The way back out to the village
+    world.spawn()
+        .insert(Portal::new_to_level("village", "mine_exit"))
+        .insert(Transform::new(vec3(-61.0, 0.5, 10.0), Matrix3::identity()));
+
+    // And the village's music comes back in near the way out
+    world.spawn()
+        .insert(MusicZone::new("village"))
+        .insert(Transform::new(vec3(-61.0, 0.5, 10.0), Matrix3::identity()))
+        .insert(TriggerVolume::new_sphere(8.0));
 }
 
 /// Entry point
@@ -134,38 +641,123 @@ fn main() {
     env_logger::init();
     log::info!("Welcome to Dreamfield!");
 
+    // Load the sim settings, which set the fixed update rate, and start recording or playing back
+    // input, which might change them
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut sim_settings = SimSettings::load(SIM_SETTINGS_PATH).with_args(args.iter().cloned());
+    let replay = Replay::from_args(&args, &mut sim_settings);
+    let tick_length = sim_settings.tick_length();
+
     // Create game host
-    let mut host = GameHost::new(None, FIXED_UPDATE_TIME);
+    let mut host = GameHost::new(None, tick_length);
 
     // Create bevy world
     let mut world = World::default();
 
     // Initialise system and renderer
     dreamfield_system::init(&mut world);
+    // The models are loaded on a background thread, so the renderer starts without them
     dreamfield_renderer::init(&mut world,
-        create_model_manager(),
+        ModelManager::new_with_models(vec![]),
         create_shader_manager(),
         create_texture_manager(),
         create_font_manager(),
-        WorldChunkManager::new(&WORLD_CHUNKS));
-
-    // Create update schedule
+        WorldChunkManager::new(&VILLAGE_CHUNKS));
+    sim::init(&mut world, create_dialogue_manager(), create_audio_manager(), create_music_manager(),
+        Settings::load(SETTINGS_PATH), create_locale());
+    world.insert_resource(create_item_registry());
+    world.insert_resource(create_cutscene_manager());
+    world.insert_resource(create_minimap());
+    world.insert_resource(create_quest_manager());
+    world.insert_resource(create_prefab_registry());
+    world.insert_resource(create_spawn_tables());
+    world.insert_resource(create_nav_mesh());
+    world.insert_resource(create_behavior_trees());
+    #[cfg(feature = "physics")]
+    world.insert_resource(create_physics_world(tick_length as f32));
+    #[cfg(feature = "hot_reload")]
+    world.insert_resource(AssetWatcher::new(create_model_manager_with, create_texture_manager_with));
+    world.insert_resource(create_font_metrics());
+    world.insert_resource(create_animation_markers());
+    world.insert_resource(create_root_motion_clips());
+    world.insert_resource(create_skeletons());
+    world.insert_resource(InterpolationClock::new(tick_length as f32));
+    world.insert_resource(sim_settings.create_rng());
+    world.insert_resource(AssetLoader::default().load_resource("models", create_model_manager).start());
+    let levels = create_levels();
+    let spawn_points = create_spawn_points(&levels);
+    let start_pos = spawn_points.get(START_SPAWN).map(SpawnPoint::pos).unwrap_or_else(Vector3::zero);
+    world.insert_resource(LoadingScreen::new(start_pos));
+    world.insert_resource(spawn_points);
+    world.insert_resource(create_title_screen());
+    world.insert_resource(levels);
+    world.insert_resource(WorldLods::new(&WORLD_LODS));
+    world.insert_resource(create_visibility_sets());
+    world.insert_resource(create_vegetation());
+    world.insert_resource(create_static_collision());
+    world.insert_resource(GameScenes::new(spawn_title_scene, spawn_game_world));
+    // The title screen is skipped when recording or playing back, so the replay starts with the game
+    if replay.is_playing() || replay.is_recording() {
+        world.resource_mut::<NextGameState>().set(GameState::Loading);
+    }
+    world.insert_resource(replay);
+    let deterministic = sim_settings.deterministic;
+    let profile_systems = sim_settings.profile_systems;
+    world.insert_resource(sim_settings);
+
+    // Stages run their systems one at a time with the TimedExecutor when profiling them, and every
+    // stage is timed for the diagnostics overlay
+    let stage = |single_threaded: bool| match (profile_systems, single_threaded) {
+        (true, _) => SystemStage::new(Box::new(TimedExecutor::default())),
+        (false, true) => SystemStage::single_threaded(),
+        (false, false) => SystemStage::parallel(),
+    };
+
+    // Create update schedule. In deterministic mode the sim systems run one at a time, so they
+    // always run in the same order.
     let mut update_schedule = Schedule::default();
 
-    update_schedule.add_stage("sim", SystemStage::parallel()
+    update_schedule.add_stage("pre_sim", TimedStage::new(TimedSchedule::Update, "pre_sim", stage(deterministic)
+        .with_system_set(sim::pre_sim_systems())
+    ));
+
+    let sim_stage = stage(deterministic)
         .with_system_set(dreamfield_system::systems())
+        .with_system_set(sim::input_systems())
         .with_system_set(sim::systems())
-    );
+        .with_system_set(sim::ui_systems());
+    #[cfg(feature = "physics")]
+    let sim_stage = sim_stage.with_system_set(sim::physics_systems());
+    #[cfg(feature = "hot_reload")]
+    let sim_stage = sim_stage.with_system_set(sim::hot_reload_systems());
+    update_schedule.add_stage("sim", TimedStage::new(TimedSchedule::Update, "sim", sim_stage));
+
+    update_schedule.add_stage("post_sim", TimedStage::new(TimedSchedule::Update, "post_sim", stage(deterministic)
+        .with_system_set(sim::post_sim_systems())
+    ));
 
     // Create render schedule
     let mut render_schedule = Schedule::default();
 
-    render_schedule.add_stage("render", SystemStage::single_threaded()
+    render_schedule.add_stage("interpolate", TimedStage::new(TimedSchedule::Render, "interpolate", stage(true)
+        .with_system_set(sim::pre_render_systems())
+    ));
+
+    render_schedule.add_stage_after("interpolate", "render", TimedStage::new(TimedSchedule::Render, "render", stage(true)
         .with_system_set(dreamfield_renderer::systems())
-    );
+    ));
+
+    render_schedule.add_stage_after("render", "sim_render", TimedStage::new(TimedSchedule::Render, "sim_render", stage(true)
+        .with_system_set(sim::render_systems())
+    ));
+
+    render_schedule.add_stage_after("sim_render", "restore", TimedStage::new(TimedSchedule::Render, "restore", stage(true)
+        .with_system_set(sim::post_render_systems())
+    ));
 
     // Initialise entities
-    create_entities(&mut world);
+    create_persistent_entities(&mut world);
+    set_game_state(&mut world, GameState::Title);
 
     // Run game
     host.run(world, update_schedule, render_schedule);
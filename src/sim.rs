@@ -1,19 +1,484 @@
 mod player_movement;
+mod character_controller;
+mod platform;
+mod pushable;
+mod zip_line;
+mod enemy;
+mod nav;
+mod steering;
+mod behavior;
+mod villager;
+mod follower;
+mod anim_state;
+mod anim_events;
+mod root_motion;
+mod look_at;
+mod attachment;
+mod hierarchy;
+mod tween;
+mod interpolation;
+mod sim_settings;
+mod determinism;
+mod replay;
+mod title;
+mod game_state;
+mod loading;
+mod level;
+mod chunk_lod;
+mod chunk_streaming;
+mod pvs;
+mod static_collision;
+mod portal;
+mod spawn_points;
+mod scene_points;
 mod fire_orb;
 mod entity_spawner;
 mod minecart;
+mod daynight;
+mod menu;
+mod npc;
+mod dialogue;
+mod input_bindings;
+mod gamepad;
+mod head_bob;
+mod audio;
+mod music;
+mod trigger;
+mod inventory;
+mod door;
+mod checkpoint;
+mod health;
+mod combat;
+mod projectile;
+mod particles;
+mod light;
+mod water;
+mod weather;
+mod ladder;
+mod stamina;
+mod third_person;
+mod cutscene;
+mod photo_mode;
+mod camera_shake;
+mod graphics_settings;
+mod settings;
+mod post_fx;
+mod uniform_buffer;
+mod retro_render;
+mod sky;
+mod vegetation;
+mod billboard;
+mod decal;
+mod screen_fade;
+mod damage_feedback;
+mod blob_shadow;
+mod tint;
+mod display_mode;
+mod ui_layout;
+mod rich_text;
+mod locale;
+mod ui;
+mod compass;
+mod minimap;
+mod quest;
+mod scripting;
+mod plugins;
+mod prefab;
+mod pool;
+mod console;
+mod diagnostics_overlay;
+mod debug_draw;
+mod inspector;
+mod free_camera;
+mod asset_manifest;
+#[cfg(feature = "physics")]
+mod physics;
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
 
 pub use player_movement::*;
+pub use character_controller::*;
+pub use platform::*;
+pub use pushable::*;
+pub use zip_line::*;
+pub use enemy::*;
+pub use nav::*;
+pub use steering::*;
+pub use behavior::*;
+pub use villager::*;
+pub use follower::*;
+pub use anim_state::*;
+pub use anim_events::*;
+pub use root_motion::*;
+pub use look_at::*;
+pub use attachment::*;
+pub use hierarchy::*;
+pub use tween::*;
+pub use interpolation::*;
+pub use sim_settings::*;
+pub use determinism::*;
+pub use replay::*;
+pub use title::*;
+pub use game_state::*;
+pub use loading::*;
+pub use level::*;
+pub use chunk_lod::*;
+pub use chunk_streaming::*;
+pub use pvs::*;
+pub use static_collision::*;
+pub use portal::*;
+pub use spawn_points::*;
+pub use scene_points::*;
 pub use fire_orb::*;
+pub use entity_spawner::*;
+pub use daynight::*;
+pub use menu::*;
+pub use npc::*;
+pub use dialogue::*;
+pub use input_bindings::*;
+pub use gamepad::*;
+pub use head_bob::*;
+pub use audio::*;
+pub use music::*;
+pub use trigger::*;
+pub use inventory::*;
+pub use door::*;
+pub use checkpoint::*;
+pub use health::*;
+pub use combat::*;
+pub use projectile::*;
+pub use particles::*;
+pub use light::*;
+pub use water::*;
+pub use weather::*;
+pub use ladder::*;
+pub use stamina::*;
+pub use third_person::*;
+pub use cutscene::*;
+pub use photo_mode::*;
+pub use camera_shake::*;
+pub use graphics_settings::*;
+pub use settings::*;
+pub use post_fx::*;
+pub use uniform_buffer::*;
+pub use retro_render::*;
+pub use sky::*;
+pub use vegetation::*;
+pub use billboard::*;
+pub use decal::*;
+pub use screen_fade::*;
+pub use damage_feedback::*;
+pub use blob_shadow::*;
+pub use tint::*;
+pub use display_mode::*;
+pub use ui_layout::*;
+pub use rich_text::*;
+pub use locale::*;
+pub use ui::*;
+pub use compass::*;
+pub use minimap::*;
+pub use quest::*;
+pub use scripting::*;
+pub use plugins::*;
+pub use prefab::*;
+pub use pool::*;
+pub use console::*;
+pub use diagnostics_overlay::*;
+pub use debug_draw::*;
+pub use inspector::*;
+pub use free_camera::*;
+pub use asset_manifest::*;
+#[cfg(feature = "physics")]
+pub use physics::*;
+#[cfg(feature = "hot_reload")]
+pub use hot_reload::*;
 
-use bevy_ecs::schedule::SystemSet;
+use bevy_ecs::event::Events;
+use bevy_ecs::schedule::{SystemSet, ParallelSystemDescriptorCoercion, ExclusiveSystemDescriptorCoercion};
+use bevy_ecs::system::IntoExclusiveSystem;
+use bevy_ecs::world::World;
+
+/// Initialise sim resources
+pub fn init(world: &mut World, dialogue_manager: DialogueManager, audio_manager: AudioManager,
+    music_manager: MusicManager, settings: Settings, locale: Locale)
+{
+    world.insert_resource(InputBindings::load(INPUT_BINDINGS_PATH));
+    world.insert_resource(GraphicsSettings::load(GRAPHICS_SETTINGS_PATH));
+    world.insert_resource(DisplayMode::default());
+    // The language is set before anything's spawned, so text boxes are made in its fonts
+    let mut locale = locale;
+    locale.set_language(&settings.language);
+    world.insert_resource(locale);
+    world.insert_resource(settings);
+    world.insert_resource(PostFxSettings::default());
+    world.insert_resource(RetroRenderSettings::default());
+    world.insert_resource(WaterSettings::default());
+    world.insert_resource(SkyParams::default());
+    world.insert_resource(Weather::default());
+    world.insert_resource(Decals::default());
+    world.insert_non_send_resource(GamepadContext::new());
+    world.insert_resource(GamepadSettings::default());
+    world.insert_resource(GamepadState::default());
+    world.insert_resource(KeyboardPoll::default());
+    world.insert_resource(TimeOfDay::default());
+    world.insert_resource(GameState::Title);
+    world.insert_resource(NextGameState::default());
+    world.insert_resource(MenuState::default());
+    world.insert_resource(PortalTransition::default());
+    world.insert_resource(ScreenFade::default());
+    world.insert_resource(DamageFeedback::default());
+    world.insert_resource(WorldLodSettings::default());
+    world.insert_resource(ChunkStreamSettings::default());
+    world.insert_resource(ChunkStreamer::default());
+    world.insert_resource(dialogue_manager);
+    world.insert_non_send_resource(audio_manager);
+    world.insert_non_send_resource(music_manager);
+    world.insert_resource(DialogueState::default());
+    world.insert_resource(SpawnBudget::default());
+    world.insert_resource(EntityPool::default());
+    world.insert_resource(InventoryScreen::default());
+    world.insert_resource(ThirdPersonCamera::default());
+    world.insert_resource(CutsceneState::default());
+    world.insert_resource(PhotoMode::default());
+    world.insert_resource(CameraShake::default());
+    world.insert_resource(TextStyles::default());
+    world.insert_resource(DebugConsole::default());
+    world.insert_resource(console_commands());
+    world.insert_resource(DiagnosticsOverlay::default());
+    world.insert_resource(SystemTimings::default());
+    world.insert_resource(DebugDraw::default());
+    world.insert_resource(Inspector::default());
+    world.insert_resource(inspector_registry());
+    world.insert_resource(FreeCamera::default());
+    world.insert_resource(QuestLog::default());
+    world.insert_non_send_resource(ScriptEngine::load(SCRIPTS_PATH));
+    world.insert_non_send_resource(PluginManager::load(PLUGINS_PATH));
+    world.insert_resource(Events::<DialogueBranchCompleteEvent>::default());
+    world.insert_resource(Events::<FootstepEvent>::default());
+    world.insert_resource(Events::<TriggerEnterEvent>::default());
+    world.insert_resource(Events::<TriggerExitEvent>::default());
+    world.insert_resource(Events::<DamageEvent>::default());
+    world.insert_resource(Events::<DeathEvent>::default());
+    world.insert_resource(Events::<AnimationEvent>::default());
+    world.insert_resource(Events::<TweenCompleteEvent>::default());
+    world.insert_resource(Events::<GameStateChangedEvent>::default());
+    world.insert_resource(Events::<LoadLevelEvent>::default());
+    world.insert_resource(Events::<AreaTransitionEvent>::default());
+    world.insert_resource(StateChecksum::default());
+    world.insert_resource(Events::<ProjectileImpactEvent>::default());
+    world.insert_resource(Events::<DecalEvent>::default());
+    world.insert_resource(Events::<CutsceneMarkerEvent>::default());
+    world.insert_resource(Events::<CutsceneFinishedEvent>::default());
+    world.insert_resource(Events::<CameraShakeEvent>::default());
+    world.insert_resource(Events::<ResolutionChangedEvent>::default());
+    world.insert_resource(Events::<QuestStageChangedEvent>::default());
+}
+
+/// Create the console command registry, with the commands each sim module provides
+fn console_commands() -> ConsoleCommands {
+    let mut commands = ConsoleCommands::default();
+    console::register_console_commands(&mut commands);
+    spawn_points::register_teleport_command(&mut commands);
+    inventory::register_give_command(&mut commands);
+    prefab::register_spawn_command(&mut commands);
+    sim_settings::register_timescale_command(&mut commands);
+    player_movement::register_noclip_command(&mut commands);
+    debug_draw::register_debug_draw_command(&mut commands);
+    inspector::register_inspect_command(&mut commands);
+    free_camera::register_freecam_command(&mut commands);
+    commands
+}
+
+/// Create the inspector registry, with the components the inspector can show
+fn inspector_registry() -> InspectorRegistry {
+    let mut registry = InspectorRegistry::default();
+    inspector::register_component_inspectors(&mut registry);
+    registry
+}
+
+// Systems that run at the start of each sim tick, before anything has moved
+pub fn pre_sim_systems() -> SystemSet {
+    SystemSet::new()
+        .with_system(game_state::apply_game_state_transitions.exclusive_system().at_start())
+        .with_system(loading::poll_asset_loader.exclusive_system().at_start())
+        .with_system(interpolation::record_previous_transforms)
+        .with_system(static_collision::update_collision_colliders)
+        .with_system(debug_draw::clear_debug_draw)
+}
+
+// Systems that run at the end of each sim tick, once everything has moved
+pub fn post_sim_systems() -> SystemSet {
+    SystemSet::new()
+        .with_system(level::handle_level_loads.exclusive_system().at_start())
+        .with_system(determinism::update_state_checksum)
+        .with_system(debug_draw::update_debug_text)
+}
+
+// Input systems, which run before the other sim systems
+pub fn input_systems() -> SystemSet {
+    SystemSet::new()
+        .label("sim_input")
+        .with_system(gamepad::update_gamepad.label("gamepad"))
+        .with_system(input_bindings::update_bound_keys.label("bound_keys"))
+        .with_system(title::update_title_screen.after("gamepad").after("bound_keys").before("replay"))
+        .with_system(replay::update_replay.label("replay").after("gamepad").after("bound_keys"))
+        .with_system(sim_settings::update_sim_settings.after("replay"))
+}
 
 // Sim systems
 pub fn systems() -> SystemSet {
     SystemSet::new()
         .label("sim")
+        .after("sim_input")
+        .with_run_criteria(menu::sim_running)
         .with_system(entity_spawner::entity_spawner)
-        .with_system(player_movement::player_update)
+        .with_system(entity_spawner::update_spawn_areas)
+        .with_system(player_movement::player_update.label("player_update").after("platforms"))
         .with_system(fire_orb::fire_orb_movement)
-        .with_system(minecart::update_minecart)
+        .with_system(fire_orb::update_orb_counter)
+        .with_system(inventory::pickup_items)
+        .with_system(door::update_doors)
+        .with_system(minecart::update_minecart.label("platform_movers"))
+        .with_system(platform::update_moving_platforms.label("platform_movers"))
+        .with_system(platform::update_platform_velocities.label("platforms").after("platform_movers"))
+        .with_system(pushable::update_pushables.after("player_update"))
+        .with_system(chunk_streaming::stream_world_chunks.after("player_update"))
+        .with_system(zip_line::update_zip_lines.before("player_update"))
+        .with_system(daynight::update_time_of_day.label("time_of_day"))
+        .with_system(villager::update_villagers.after("time_of_day"))
+        .with_system(sky::update_sky_params.after("time_of_day"))
+        .with_system(npc::update_npcs.label("agents"))
+        .with_system(enemy::update_enemies.label("agents"))
+        .with_system(follower::update_followers.label("agents").after("player_update"))
+        .with_system(look_at::update_look_at.label("look_at").after("agents").after("player_update"))
+        .with_system(hierarchy::update_children.label("children"))
+        .with_system(hierarchy::propagate_transforms.label("transform_propagate").after("children")
+            .after("platforms").after("agents").after("look_at").after("root_motion"))
+        .with_system(attachment::update_attachments.after("transform_propagate"))
+        .with_system(anim_state::update_anim_state_machines.label("anim_state"))
+        .with_system(root_motion::apply_root_motion.label("root_motion").after("anim_state"))
+        .with_system(anim_events::update_animation_events.label("animation_events"))
+        .with_system(audio::play_animation_sounds.after("animation_events"))
+        .with_system(audio::despawn_finished_sounds)
+        .with_system(enemy::enemy_impacts.after("animation_events"))
+        .with_system(Events::<AnimationEvent>::update_system)
+        .with_system(tween::update_tweens.label("tweens").before("transform_propagate"))
+        .with_system(Events::<TweenCompleteEvent>::update_system)
+        .with_system(dialogue::update_dialogue)
+        .with_system(Events::<DialogueBranchCompleteEvent>::update_system)
+        .with_system(Events::<FootstepEvent>::update_system)
+        .with_system(trigger::update_triggers)
+        .with_system(Events::<TriggerEnterEvent>::update_system)
+        .with_system(Events::<TriggerExitEvent>::update_system)
+        .with_system(level::update_level_exits)
+        .with_system(Events::<AreaTransitionEvent>::update_system)
+        .with_system(checkpoint::update_checkpoints)
+        .with_system(checkpoint::respawn_player)
+        .with_system(health::apply_damage.label("damage"))
+        .with_system(damage_feedback::update_damage_feedback.after("damage"))
+        .with_system(tint::flash_on_hit.after("damage"))
+        .with_system(tint::update_fade_outs)
+        .with_system(health::handle_deaths)
+        .with_system(combat::player_attack)
+        .with_system(combat::apply_knockback)
+        // Pooled entities are acquired after they're released, so that the commands that clear
+        // them are applied before the ones that reuse them
+        .with_system(projectile::cast_fire_orb.after("projectiles"))
+        .with_system(projectile::update_projectiles.label("projectiles"))
+        .with_system(Events::<ProjectileImpactEvent>::update_system)
+        .with_system(decal::update_decals.label("decals"))
+        .with_system(decal::spawn_decals.after("decals"))
+        .with_system(Events::<DecalEvent>::update_system)
+        .with_system(particles::update_particle_emitters.after("particles"))
+        .with_system(particles::update_particles.label("particles"))
+        .with_system(billboard::update_billboards.after("player_update"))
+        .with_system(blob_shadow::update_blob_shadows.after("player_update"))
+        .with_system(light::update_lights)
+        .with_system(light::ignite_lights)
+        .with_system(weather::update_weather.label("weather").after("time_of_day"))
+        .with_system(water::update_swimming.after("time_of_day").after("weather"))
+        .with_system(water::spawn_water_surfaces)
+        .with_system(vegetation::update_vegetation.after("weather"))
+        .with_system(ladder::update_climbing)
+        .with_system(stamina::update_stamina_bar)
+        .with_system(health::update_health_bar)
+        .with_system(compass::update_compass.after("player_update"))
+        .with_system(minimap::update_minimap.after("player_update"))
+        .with_system(quest::update_quests.label("quests"))
+        .with_system(quest::update_quest_hud.after("quests"))
+        .with_system(Events::<QuestStageChangedEvent>::update_system)
+        .with_system(scripting::run_scripts)
+        .with_system(plugins::run_plugins)
+        .with_system(cutscene::trigger_cutscenes)
+        .with_system(cutscene::update_cutscene.label("cutscene").after("player_update"))
+        .with_system(camera_shake::shake_on_impacts.before("camera_shake"))
+        .with_system(camera_shake::update_camera_shake.label("camera_shake").after("player_update").after("cutscene"))
+        .with_system(free_camera::update_free_camera.after("player_update").before("camera_shake"))
+        .with_system(Events::<CameraShakeEvent>::update_system)
+        .with_system(Events::<CutsceneMarkerEvent>::update_system)
+        .with_system(Events::<CutsceneFinishedEvent>::update_system)
+        .with_system(Events::<DamageEvent>::update_system)
+        .with_system(Events::<DeathEvent>::update_system)
+}
+
+// Render systems, which run after the scene has been rendered
+pub fn render_systems() -> SystemSet {
+    SystemSet::new()
+        .with_system(photo_mode::capture_screenshot)
+        .with_system(console::poll_console_keys)
+        .with_system(input_bindings::poll_bound_keys)
+        .with_system(diagnostics_overlay::poll_diagnostics_keys)
+        .with_system(debug_draw::render_debug_draw)
+        .with_system(inspector::poll_inspector_keys)
+        .with_system(retro_render::upload_retro_params)
+        .with_system(screen_fade::upload_fade_params)
+        .with_system(damage_feedback::upload_damage_params)
+        .with_system(rich_text::upload_text_style_params)
+        .with_system(water::upload_water_params)
+        .with_system(display_mode::update_window_size)
+}
+
+// Systems that run before the renderer draws each frame
+pub fn pre_render_systems() -> SystemSet {
+    SystemSet::new()
+        .with_system(interpolation::interpolate_transforms.label("interpolate_transforms"))
+        .with_system(tint::upload_tint_params.after("interpolate_transforms"))
+        .with_system(sky::upload_sky_params)
+        .with_system(post_fx::upload_crt_params)
+        .with_system(post_fx::upload_quantize_params)
+}
+
+// Systems that run after everything has been drawn
+pub fn post_render_systems() -> SystemSet {
+    SystemSet::new()
+        .with_system(interpolation::restore_transforms)
+}
+
+// UI systems, which keep running while the sim is paused
+pub fn ui_systems() -> SystemSet {
+    SystemSet::new()
+        .label("sim_ui")
+        .after("sim_input")
+        .with_system(console::update_console.exclusive_system().at_start())
+        .with_system(inspector::update_inspector.exclusive_system().at_start())
+        .with_system(menu::update_menu)
+        .with_system(loading::update_loading_screen)
+        .with_system(portal::update_portals.label("portals"))
+        .with_system(screen_fade::update_screen_fade.after("portals"))
+        .with_system(chunk_lod::update_world_lods)
+        .with_system(diagnostics_overlay::update_diagnostics_overlay)
+        .with_system(debug_draw::draw_debug_layers)
+        .with_system(pvs::cull_entities.exclusive_system().at_end())
+        .with_system(Events::<GameStateChangedEvent>::update_system)
+        .with_system(inventory::update_inventory_screen)
+        .with_system(audio::update_audio)
+        .with_system(music::update_music)
+        .with_system(photo_mode::update_photo_mode)
+        .with_system(settings::apply_settings.label("settings").before("graphics_settings"))
+        .with_system(graphics_settings::apply_graphics_settings.label("graphics_settings"))
+        .with_system(ui_layout::update_ui_layout.label("ui_layout").after("graphics_settings"))
+        .with_system(rich_text::update_rich_text.after("ui_layout"))
+        .with_system(ui::update_ui_widgets)
+        .with_system(ui::update_ui_order.exclusive_system().at_end())
+        .with_system(Events::<ResolutionChangedEvent>::update_system)
 }
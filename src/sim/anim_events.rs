@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventWriter};
+use bevy_ecs::system::{Res, Query};
+use cgmath::Vector3;
+use serde::Deserialize;
+use dreamfield_renderer::components::{Visual, Animation};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::head_bob::FootstepEvent;
+
+/// The kinds of animation event
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AnimEventKind {
+    Footstep,
+    /// A weapon starting to swing
+    Swing,
+    /// A weapon hitting
+    Impact,
+}
+
+/// A marker at a time in a clip
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnimMarker {
+    /// The time in seconds from the start of the clip
+    pub time: f32,
+    pub event: AnimEventKind,
+    /// A sound to play at the entity when the marker is reached
+    #[serde(default)]
+    pub sound: Option<String>,
+}
+
+/// The markers in a clip. The renderer doesn't expose clip lengths, so they're listed here too.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ClipMarkers {
+    pub length: f32,
+    pub markers: Vec<AnimMarker>,
+}
+
+/// The AnimationMarkers resource, with the event markers for each model's clips
+pub struct AnimationMarkers {
+    models: HashMap<String, HashMap<String, ClipMarkers>>,
+}
+
+impl AnimationMarkers {
+    /// Load the markers from a RON map of model name to a map of clip name to markers
+    pub fn new(source: &str) -> Self {
+        let models = match ron::from_str(source) {
+            Ok(models) => models,
+            Err(err) => {
+                log::error!("Failed to load animation markers: {}", err);
+                HashMap::new()
+            }
+        };
+
+        AnimationMarkers { models }
+    }
+
+    pub fn get(&self, model: &str, clip: &str) -> Option<&ClipMarkers> {
+        self.models.get(model).and_then(|clips| clips.get(clip))
+    }
+}
+
+/// An event emitted when an entity's animation reaches a marker
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub event: AnimEventKind,
+    pub sound: Option<String>,
+    pub position: Vector3<f32>,
+}
+
+/// The AnimationEvents component, for entities whose Visual's animations emit events at their
+/// markers. It keeps track of how far through the playing clip the entity is, which starts again
+/// whenever the clip changes.
+#[derive(Component)]
+pub struct AnimationEvents {
+    /// The model the markers are looked up for
+    pub model: String,
+    clip: Option<String>,
+    time: f32,
+    left_foot: bool,
+}
+
+impl AnimationEvents {
+    pub fn new(model: &str) -> Self {
+        AnimationEvents {
+            model: model.to_string(),
+            clip: None,
+            time: 0.0,
+            left_foot: false,
+        }
+    }
+}
+
+/// The animation event system, which emits an AnimationEvent for every marker the playing clips
+/// pass this frame, and a FootstepEvent for footsteps
+pub fn update_animation_events(sim_time: Res<SimTime>, markers: Res<AnimationMarkers>,
+    mut query: Query<(Entity, &mut AnimationEvents, &Visual, &Transform)>,
+    mut events: EventWriter<AnimationEvent>, mut footsteps: EventWriter<FootstepEvent>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (entity, mut tracker, visual, transform) in query.iter_mut() {
+        let clip = match &visual.animation {
+            Some(Animation::Loop(clip)) => clip,
+            _ => {
+                tracker.clip = None;
+                continue;
+            }
+        };
+
+        if tracker.clip.as_ref() != Some(clip) {
+            tracker.clip = Some(clip.clone());
+            tracker.time = 0.0;
+        }
+
+        let clip_markers = match markers.get(&tracker.model, clip) {
+            Some(clip_markers) if clip_markers.length > 0.0 => clip_markers,
+            _ => continue,
+        };
+
+        // Find the markers between the last frame and this one, including the ones at the start of
+        // the clip again if it loops around
+        let start = tracker.time;
+        let end = start + time_delta;
+        for marker in clip_markers.markers.iter() {
+            let passed = (marker.time >= start && marker.time < end)
+                || (marker.time + clip_markers.length >= start && marker.time + clip_markers.length < end);
+            if !passed {
+                continue;
+            }
+
+            if marker.event == AnimEventKind::Footstep {
+                tracker.left_foot = !tracker.left_foot;
+                footsteps.send(FootstepEvent { entity, position: transform.pos, left_foot: tracker.left_foot });
+            }
+
+            events.send(AnimationEvent {
+                entity,
+                event: marker.event,
+                sound: marker.sound.clone(),
+                position: transform.pos,
+            });
+        }
+
+        tracker.time = end % clip_markers.length;
+    }
+}
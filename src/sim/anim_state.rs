@@ -0,0 +1,214 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, InnerSpace, Zero, vec3};
+use serde::Deserialize;
+use dreamfield_renderer::components::{Visual, Animation};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+/// A condition on the sim values for taking a transition
+#[derive(Deserialize, Clone, Debug)]
+pub enum AnimCondition {
+    /// Whether the entity's horizontal speed is above or below a value
+    SpeedAbove(f32),
+    SpeedBelow(f32),
+    Grounded,
+    Airborne,
+    Attacking,
+    NotAttacking,
+    /// Whether the machine has been in the current state for a number of seconds
+    TimeInState(f32),
+}
+
+/// A transition to another state, taken when all its conditions are true
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnimTransition {
+    pub to: String,
+    pub conditions: Vec<AnimCondition>,
+    /// How many seconds to crossfade from the old state's clip to the new one over
+    #[serde(default)]
+    pub crossfade: f32,
+}
+
+/// A state in an animation state machine, with the clip it plays
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnimState {
+    pub name: String,
+    pub clip: String,
+    #[serde(default)]
+    pub transitions: Vec<AnimTransition>,
+    /// Whether the clip's root bone translation moves the entity while in this state
+    #[serde(default)]
+    pub root_motion: bool,
+}
+
+/// The sim values transitions can depend on. The speed is measured from how the entity moves, and
+/// the rest are set by whatever system controls the entity.
+#[derive(Clone, Copy, Debug)]
+pub struct AnimParams {
+    pub speed: f32,
+    pub grounded: bool,
+    pub attacking: bool,
+}
+
+impl Default for AnimParams {
+    fn default() -> Self {
+        AnimParams {
+            speed: 0.0,
+            grounded: true,
+            attacking: false,
+        }
+    }
+}
+
+/// A crossfade in progress from one state to another
+#[derive(Clone, Copy, Debug)]
+struct AnimFade {
+    from: usize,
+    duration: f32,
+    time: f32,
+}
+
+/// The AnimStateMachine component, which picks the clip an entity's Visual plays from a set of
+/// named states and the transitions between them. The renderer only plays one clip at a time, so
+/// the new clip starts straight away, and the crossfade is tracked for anything that wants to
+/// blend between them with blend().
+#[derive(Component)]
+pub struct AnimStateMachine {
+    pub states: Vec<AnimState>,
+    pub params: AnimParams,
+    /// The model the clips belong to, for looking up their root motion
+    pub model: Option<String>,
+    current: usize,
+    time_in_state: f32,
+    prev_time_in_state: f32,
+    fade: Option<AnimFade>,
+    prev_pos: Option<Vector3<f32>>,
+    applied_clip: Option<String>,
+}
+
+impl AnimStateMachine {
+    /// Create a state machine, starting in the first state
+    pub fn new(states: Vec<AnimState>) -> Self {
+        AnimStateMachine {
+            states,
+            params: AnimParams::default(),
+            model: None,
+            current: 0,
+            time_in_state: 0.0,
+            prev_time_in_state: 0.0,
+            fade: None,
+            prev_pos: None,
+            applied_clip: None,
+        }
+    }
+
+    /// Get the name of the current state
+    pub fn state(&self) -> Option<&str> {
+        self.states.get(self.current).map(|state| state.name.as_str())
+    }
+
+    /// Get the clip being faded out of during a crossfade, and its weight from 1 to 0
+    pub fn blend(&self) -> Option<(&str, f32)> {
+        self.fade.map(|fade| (self.states[fade.from].clip.as_str(), 1.0 - fade.time / fade.duration))
+    }
+
+    /// Get the clip playing in the current state and how far through the state it was last tick
+    /// and is now, if the state uses root motion
+    pub fn root_motion_window(&self) -> Option<(&str, f32, f32)> {
+        self.states.get(self.current)
+            .filter(|state| state.root_motion)
+            .map(|state| (state.clip.as_str(), self.prev_time_in_state, self.time_in_state))
+    }
+
+    /// Go straight to a state by name, without a crossfade
+    pub fn set_state(&mut self, name: &str) {
+        match self.states.iter().position(|state| state.name == name) {
+            Some(index) => {
+                self.current = index;
+                self.time_in_state = 0.0;
+                self.prev_time_in_state = 0.0;
+                self.fade = None;
+            },
+            None => log::warn!("Unknown animation state: {}", name),
+        }
+    }
+
+    /// Check whether a condition is true
+    fn check(&self, condition: &AnimCondition) -> bool {
+        match condition {
+            AnimCondition::SpeedAbove(speed) => self.params.speed > *speed,
+            AnimCondition::SpeedBelow(speed) => self.params.speed < *speed,
+            AnimCondition::Grounded => self.params.grounded,
+            AnimCondition::Airborne => !self.params.grounded,
+            AnimCondition::Attacking => self.params.attacking,
+            AnimCondition::NotAttacking => !self.params.attacking,
+            AnimCondition::TimeInState(time) => self.time_in_state >= *time,
+        }
+    }
+
+    /// Advance the state machine, taking the first transition out of the current state whose
+    /// conditions are true
+    fn update(&mut self, time_delta: f32) {
+        self.prev_time_in_state = self.time_in_state;
+        self.time_in_state += time_delta;
+        if let Some(fade) = self.fade.as_mut() {
+            fade.time += time_delta;
+            if fade.time >= fade.duration {
+                self.fade = None;
+            }
+        }
+
+        let state = match self.states.get(self.current) {
+            Some(state) => state,
+            None => return,
+        };
+
+        let transition = state.transitions.iter()
+            .find(|transition| transition.conditions.iter().all(|condition| self.check(condition)));
+
+        if let Some(transition) = transition {
+            match self.states.iter().position(|state| state.name == transition.to) {
+                Some(next) => {
+                    self.fade = (transition.crossfade > 0.0).then_some(AnimFade {
+                        from: self.current,
+                        duration: transition.crossfade,
+                        time: 0.0,
+                    });
+                    self.current = next;
+                    self.time_in_state = 0.0;
+                    self.prev_time_in_state = 0.0;
+                },
+                None => log::warn!("Unknown animation state in transition: {}", transition.to),
+            }
+        }
+    }
+}
+
+/// The animation state machine system, which measures each entity's speed, advances its state
+/// machine, and sets its Visual's animation when the clip changes
+pub fn update_anim_state_machines(sim_time: Res<SimTime>,
+    mut query: Query<(&mut AnimStateMachine, &Transform, &mut Visual)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    if time_delta <= 0.0 {
+        return;
+    }
+
+    for (mut machine, transform, mut visual) in query.iter_mut() {
+        let moved = machine.prev_pos.map(|prev_pos| transform.pos - prev_pos).unwrap_or(Vector3::zero());
+        machine.params.speed = vec3(moved.x, 0.0, moved.z).magnitude() / time_delta;
+        machine.prev_pos = Some(transform.pos);
+
+        machine.update(time_delta);
+
+        let clip = match machine.states.get(machine.current) {
+            Some(state) => state.clip.clone(),
+            None => continue,
+        };
+        if machine.applied_clip.as_ref() != Some(&clip) {
+            visual.animation = Some(Animation::Loop(clip.clone()));
+            machine.applied_clip = Some(clip);
+        }
+    }
+}
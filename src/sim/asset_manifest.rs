@@ -0,0 +1,95 @@
+/// The kinds of asset in the asset manifest, which each go to their own manager
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AssetKind {
+    Model,
+    Texture,
+    Sound,
+}
+
+impl AssetKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "model" => Some(AssetKind::Model),
+            "texture" => Some(AssetKind::Texture),
+            "sound" => Some(AssetKind::Sound),
+            _ => None,
+        }
+    }
+}
+
+/// An asset in the manifest, with where its contents are in the pack
+struct ManifestAsset {
+    kind: AssetKind,
+    name: &'static str,
+    path: &'static str,
+    offset: usize,
+    len: usize,
+}
+
+/// The AssetManifest, listing the models, textures and sounds build.rs finds in resources, so
+/// adding one only needs its file dropping in. Normally their contents are packed into one file
+/// that's built into the game, but with the loose_assets feature the pack's left out and each asset
+/// is read from resources whenever its manager's built, so they can be edited without rebuilding.
+/// Adding or removing one still needs a rebuild to update the manifest.
+pub struct AssetManifest {
+    assets: Vec<ManifestAsset>,
+    pack: &'static [u8],
+}
+
+impl AssetManifest {
+    /// Parse the manifest, which has a line for each asset of its kind, name, offset and length in
+    /// the pack, and path, separated by spaces
+    pub fn parse(source: &'static str, pack: &'static [u8]) -> Self {
+        let assets = source.lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| Self::parse_asset(line).unwrap_or_else(|| panic!("Invalid asset manifest line: {}", line)))
+            .collect();
+        AssetManifest { assets, pack }
+    }
+
+    fn parse_asset(line: &'static str) -> Option<ManifestAsset> {
+        let mut fields = line.splitn(5, ' ');
+        Some(ManifestAsset {
+            kind: AssetKind::parse(fields.next()?)?,
+            name: fields.next()?,
+            offset: fields.next()?.parse().ok()?,
+            len: fields.next()?.parse().ok()?,
+            path: fields.next()?,
+        })
+    }
+
+    /// Get the assets of a kind, by name
+    pub fn assets(&self, kind: AssetKind) -> impl Iterator<Item = (&'static str, &'static [u8])> + '_ {
+        self.assets.iter()
+            .filter(move |asset| asset.kind == kind)
+            .filter_map(|asset| Some((asset.name, self.contents(asset)?)))
+    }
+
+    /// Get an asset's contents from the pack
+    fn packed(&self, asset: &ManifestAsset) -> Option<&'static [u8]> {
+        let contents = self.pack.get(asset.offset..asset.offset + asset.len);
+        if contents.is_none() {
+            log::error!("{} isn't in the asset pack", asset.path);
+        }
+        contents
+    }
+
+    #[cfg(not(feature = "loose_assets"))]
+    fn contents(&self, asset: &ManifestAsset) -> Option<&'static [u8]> {
+        self.packed(asset)
+    }
+
+    /// Read an asset's contents from resources, leaking them since the managers expect static data
+    /// like the packed assets. Each manager that's built leaks a copy, which is fine for development.
+    /// If the file can't be read, the pack's tried, which is empty unless the game passed one in.
+    #[cfg(feature = "loose_assets")]
+    fn contents(&self, asset: &ManifestAsset) -> Option<&'static [u8]> {
+        match std::fs::read(asset.path) {
+            Ok(bytes) => Some(&*Box::leak(bytes.into_boxed_slice())),
+            Err(err) => {
+                log::error!("Failed to read {}: {}", asset.path, err);
+                self.packed(asset)
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, Without};
+use bevy_ecs::system::{Res, Query, Commands};
+use cgmath::{Vector3, Matrix3, Matrix4, InnerSpace, SquareMatrix, Zero};
+use dreamfield_renderer::components::{Visual, Animation};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+/// A clip's poses, sampled at a fixed rate
+pub struct SkeletonClip {
+    pub length: f32,
+    frames: Vec<Vec<Matrix4<f32>>>,
+}
+
+/// A model's bones, and their model space transforms in the rest pose and through each clip
+pub struct Skeleton {
+    bones: Vec<String>,
+    rest: Vec<Matrix4<f32>>,
+    clips: HashMap<String, SkeletonClip>,
+}
+
+impl Skeleton {
+    /// Find a bone by name
+    pub fn bone_index(&self, name: &str) -> Option<usize> {
+        self.bones.iter().position(|bone| bone == name)
+    }
+
+    /// Get a bone's model space transform at a time in a clip, or in the rest pose if there's no
+    /// clip or it's unknown. The time wraps around the clip's length.
+    pub fn bone_transform(&self, bone: usize, clip: Option<&str>, time: f32) -> Matrix4<f32> {
+        let clip = match clip.and_then(|clip| self.clips.get(clip)) {
+            Some(clip) if !clip.frames.is_empty() => clip,
+            _ => return self.rest.get(bone).copied().unwrap_or(Matrix4::identity()),
+        };
+
+        let last = clip.frames.len() - 1;
+        let frame = match clip.length > 0.0 {
+            true => (time % clip.length) / clip.length * last as f32,
+            false => 0.0,
+        };
+        let (start, end) = (frame.floor() as usize, usize::min(frame.floor() as usize + 1, last));
+        let t = frame.fract();
+
+        match (clip.frames[start].get(bone), clip.frames[end].get(bone)) {
+            (Some(start), Some(end)) => *start + (*end - *start) * t,
+            _ => Matrix4::identity(),
+        }
+    }
+}
+
+/// The Skeletons resource, with the skeleton of each animated model as sampled by the build script
+pub struct Skeletons {
+    models: HashMap<String, Skeleton>,
+}
+
+impl Skeletons {
+    /// Load the skeletons for a list of (model name, baked skeleton) pairs
+    pub fn new(models: Vec<(&str, &str)>) -> Self {
+        let models = models.into_iter()
+            .filter_map(|(name, source)| match Self::parse(source) {
+                Some(skeleton) => Some((name.to_string(), skeleton)),
+                None => {
+                    log::error!("Failed to load skeleton for model {}", name);
+                    None
+                }
+            })
+            .collect();
+
+        Skeletons { models }
+    }
+
+    pub fn get(&self, model: &str) -> Option<&Skeleton> {
+        self.models.get(model)
+    }
+
+    /// Parse a baked skeleton, which starts with the bone names and the rest pose, followed by
+    /// each clip's header with its name, length and frame count, and then its frames
+    fn parse(source: &str) -> Option<Skeleton> {
+        let mut lines = source.lines();
+
+        let bones: Vec<String> = lines.next()?.strip_prefix("bones")?
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+
+        let read_pose = |lines: &mut std::str::Lines| -> Option<Vec<Matrix4<f32>>> {
+            (0..bones.len()).map(|_| Self::parse_matrix(lines.next()?)).collect()
+        };
+
+        let rest = read_pose(&mut lines)?;
+
+        let mut clips = HashMap::new();
+        while let Some(header) = lines.next() {
+            let header: Vec<&str> = header.split_whitespace().collect();
+            let (name, length, count) = match header.as_slice() {
+                [name, length, count] => (name.to_string(), length.parse().ok()?, count.parse().ok()?),
+                _ => return None,
+            };
+
+            let frames = (0..count).map(|_| read_pose(&mut lines)).collect::<Option<Vec<_>>>()?;
+            clips.insert(name, SkeletonClip { length, frames });
+        }
+
+        Some(Skeleton { bones, rest, clips })
+    }
+
+    /// Parse a line with a column major 3x4 matrix
+    fn parse_matrix(line: &str) -> Option<Matrix4<f32>> {
+        let values: Vec<f32> = line.split_whitespace().map(|value| value.parse().ok()).collect::<Option<_>>()?;
+        match values.as_slice() {
+            [a, b, c, d, e, f, g, h, i, j, k, l] => Some(Matrix4::new(
+                *a, *b, *c, 0.0,
+                *d, *e, *f, 0.0,
+                *g, *h, *i, 0.0,
+                *j, *k, *l, 1.0)),
+            _ => None,
+        }
+    }
+}
+
+/// The AttachedTo component, which keeps an entity on a bone of another entity's skinned Visual,
+/// like a torch in an npc's hand. The renderer doesn't expose its animation playback, so like
+/// AnimationEvents it keeps track of how far through the parent's clip it is itself, starting again
+/// whenever the clip changes. The entity is despawned along with its parent.
+#[derive(Component)]
+pub struct AttachedTo {
+    pub parent: Entity,
+    /// The parent's model, for looking up its skeleton
+    pub model: String,
+    pub bone: String,
+    /// The entity's position and rotation relative to the bone
+    pub offset: Vector3<f32>,
+    pub rotation: Matrix3<f32>,
+    clip: Option<String>,
+    time: f32,
+}
+
+impl AttachedTo {
+    pub fn new(parent: Entity, model: &str, bone: &str, offset: Vector3<f32>) -> Self {
+        AttachedTo {
+            parent,
+            model: model.to_string(),
+            bone: bone.to_string(),
+            offset,
+            rotation: Matrix3::identity(),
+            clip: None,
+            time: 0.0,
+        }
+    }
+}
+
+/// The attachment system, which moves attached entities to their bones once everything else has
+/// moved and animated their parents
+pub fn update_attachments(mut commands: Commands, sim_time: Res<SimTime>, skeletons: Res<Skeletons>,
+    parent_query: Query<(&Transform, Option<&Visual>), Without<AttachedTo>>,
+    mut query: Query<(Entity, &mut AttachedTo, &mut Transform)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (entity, mut attachment, mut transform) in query.iter_mut() {
+        let (parent_transform, visual) = match parent_query.get(attachment.parent) {
+            Ok(parent) => parent,
+            Err(_) => {
+                commands.entity(entity).despawn();
+                continue;
+            }
+        };
+
+        // Keep track of the parent's clip
+        let clip = match visual.and_then(|visual| visual.animation.as_ref()) {
+            Some(Animation::Loop(clip)) => Some(clip.clone()),
+            _ => None,
+        };
+        if attachment.clip != clip {
+            attachment.clip = clip;
+            attachment.time = 0.0;
+        }
+        else {
+            attachment.time += time_delta;
+        }
+
+        // Find the bone's transform, or just stick to the parent's origin if we can't
+        let bone = skeletons.get(&attachment.model)
+            .and_then(|skeleton| skeleton.bone_index(&attachment.bone)
+                .map(|bone| skeleton.bone_transform(bone, attachment.clip.as_deref(), attachment.time)));
+        let (bone_pos, bone_rot) = match bone {
+            Some(bone) => {
+                // Take out any scale in the bone's transform, so it doesn't scale the offset
+                let axis = |column: Vector3<f32>| match column.magnitude2() > 0.0 {
+                    true => column.normalize(),
+                    false => column,
+                };
+                let rot = Matrix3::from_cols(axis(bone.x.truncate()), axis(bone.y.truncate()), axis(bone.z.truncate()));
+                (bone.w.truncate(), rot)
+            },
+            None => (Vector3::zero(), Matrix3::identity()),
+        };
+
+        let rot = parent_transform.rot * bone_rot;
+        transform.pos = parent_transform.pos + parent_transform.rot * bone_pos + rot * attachment.offset;
+        transform.rot = rot * attachment.rotation;
+    }
+}
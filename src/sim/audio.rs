@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventReader, With};
+use bevy_ecs::system::{NonSendMut, Query, Commands};
+use cgmath::{Vector3, Matrix3, SquareMatrix, InnerSpace};
+use rodio::{OutputStream, OutputStreamHandle, SpatialSink, Decoder, Source};
+use dreamfield_renderer::components::PlayerCamera;
+use dreamfield_system::components::Transform;
+
+use super::anim_events::AnimationEvent;
+
+/// The distance between the listener's ears
+const EAR_DISTANCE: f32 = 0.2;
+
+/// The volume and attenuation radius of sounds played by animation events
+const ANIMATION_SOUND_VOLUME: f32 = 0.6;
+const ANIMATION_SOUND_RADIUS: f32 = 15.0;
+
+/// The AudioSource component
+#[derive(Component)]
+pub struct AudioSource {
+    pub sound: String,
+    pub looping: bool,
+    pub volume: f32,
+    /// The distance at which the sound becomes inaudible
+    pub attenuation_radius: f32,
+    pub playing: bool,
+}
+
+impl AudioSource {
+    pub fn new_looping(sound: &str, volume: f32, attenuation_radius: f32) -> Self {
+        AudioSource {
+            sound: sound.to_string(),
+            looping: true,
+            volume,
+            attenuation_radius,
+            playing: true,
+        }
+    }
+
+    pub fn new_one_shot(sound: &str, volume: f32, attenuation_radius: f32) -> Self {
+        AudioSource {
+            looping: false,
+            ..Self::new_looping(sound, volume, attenuation_radius)
+        }
+    }
+}
+
+/// The TransientSound component, for entities that only exist to play a one-shot sound, which are
+/// despawned when it finishes
+#[derive(Component)]
+pub struct TransientSound;
+
+/// The audio manager, which owns the output device and the sounds. The output stream isn't Send, so
+/// this is stored as a non-send resource.
+pub struct AudioManager {
+    sounds: HashMap<String, &'static [u8]>,
+    sinks: HashMap<Entity, SpatialSink>,
+    /// The master volume, from 0 to 1
+    pub master_volume: f32,
+    // The stream needs to be kept alive for the handle to keep working
+    _stream: Option<OutputStream>,
+    handle: Option<OutputStreamHandle>,
+}
+
+impl AudioManager {
+    pub fn new_with_sounds(sounds: Vec<(&str, &'static [u8])>) -> Self {
+        let (stream, handle) = match OutputStream::try_default() {
+            Ok((stream, handle)) => (Some(stream), Some(handle)),
+            Err(err) => {
+                log::warn!("Failed to open audio output, audio will be disabled: {}", err);
+                (None, None)
+            }
+        };
+
+        AudioManager {
+            sounds: sounds.into_iter().map(|(name, data)| (name.to_string(), data)).collect(),
+            sinks: HashMap::new(),
+            master_volume: 1.0,
+            _stream: stream,
+            handle,
+        }
+    }
+
+    /// Get the output stream handle, if audio output is available
+    pub fn output_handle(&self) -> Option<&OutputStreamHandle> {
+        self.handle.as_ref()
+    }
+
+    /// Start playing a sound on a new sink
+    fn play(&self, source: &AudioSource, pos: Vector3<f32>, listener: &Listener) -> Option<SpatialSink> {
+        let handle = self.handle.as_ref()?;
+
+        let data = match self.sounds.get(&source.sound) {
+            Some(data) => *data,
+            None => {
+                log::warn!("Asked to play unknown sound: {}", source.sound);
+                return None;
+            }
+        };
+
+        let decoder = match Decoder::new(Cursor::new(data)) {
+            Ok(decoder) => decoder,
+            Err(err) => {
+                log::error!("Failed to decode sound {}: {}", source.sound, err);
+                return None;
+            }
+        };
+
+        let sink = SpatialSink::try_new(handle, pos.into(), listener.left_ear.into(), listener.right_ear.into()).ok()?;
+        match source.looping {
+            true => sink.append(decoder.repeat_infinite()),
+            false => sink.append(decoder),
+        }
+
+        Some(sink)
+    }
+}
+
+/// The audio listener, derived from the player camera
+struct Listener {
+    pos: Vector3<f32>,
+    left_ear: Vector3<f32>,
+    right_ear: Vector3<f32>,
+}
+
+impl Listener {
+    fn from_camera(cam: &PlayerCamera) -> Option<Self> {
+        let cam_transform = cam.view.invert()?;
+        let pos = cam_transform.w.truncate();
+        let right = cam_transform.x.truncate().normalize();
+
+        Some(Listener {
+            pos,
+            left_ear: pos - right * (0.5 * EAR_DISTANCE),
+            right_ear: pos + right * (0.5 * EAR_DISTANCE),
+        })
+    }
+
+    /// Calculate the volume of a source based on its distance from the listener
+    fn attenuation(&self, pos: Vector3<f32>, radius: f32) -> f32 {
+        match radius > 0.0 {
+            true => f32::clamp(1.0 - (pos - self.pos).magnitude() / radius, 0.0, 1.0),
+            false => 1.0,
+        }
+    }
+}
+
+/// The audio update system
+pub fn update_audio(mut audio: NonSendMut<AudioManager>, cam_query: Query<&PlayerCamera>,
+    mut sources: Query<(Entity, &mut AudioSource, &Transform)>)
+{
+    let listener = match cam_query.get_single().ok().and_then(Listener::from_camera) {
+        Some(listener) => listener,
+        None => return,
+    };
+
+    let audio = audio.as_mut();
+
+    // Stop sinks whose sources have been removed
+    audio.sinks.retain(|entity, _| sources.get(*entity).is_ok());
+
+    for (entity, mut source, transform) in sources.iter_mut() {
+        // Stop the sound if it's been stopped or if a one-shot sound has finished
+        let finished = audio.sinks.get(&entity).map(|sink| sink.empty()).unwrap_or(false);
+        if finished {
+            source.playing = false;
+        }
+        if !source.playing {
+            audio.sinks.remove(&entity);
+            continue;
+        }
+
+        // Start the sound if it isn't playing
+        if !audio.sinks.contains_key(&entity) {
+            match audio.play(&source, transform.pos, &listener) {
+                Some(sink) => { audio.sinks.insert(entity, sink); },
+                None => {
+                    source.playing = false;
+                    continue;
+                }
+            }
+        }
+
+        // Update position and volume
+        let sink = audio.sinks.get(&entity).unwrap();
+        let volume = source.volume * audio.master_volume * listener.attenuation(transform.pos, source.attenuation_radius);
+        sink.set_emitter_position(transform.pos.into());
+        sink.set_left_ear_position(listener.left_ear.into());
+        sink.set_right_ear_position(listener.right_ear.into());
+        sink.set_volume(volume);
+    }
+}
+
+/// The animation sound system, which plays the sounds of animation events where they happened
+pub fn play_animation_sounds(mut commands: Commands, mut events: EventReader<AnimationEvent>) {
+    for event in events.iter() {
+        if let Some(sound) = &event.sound {
+            commands.spawn()
+                .insert(AudioSource::new_one_shot(sound, ANIMATION_SOUND_VOLUME, ANIMATION_SOUND_RADIUS))
+                .insert(Transform::new(event.position, Matrix3::identity()))
+                .insert(TransientSound);
+        }
+    }
+}
+
+/// The transient sound system, which despawns transient sounds once they've finished playing
+pub fn despawn_finished_sounds(mut commands: Commands, query: Query<(Entity, &AudioSource), With<TransientSound>>) {
+    for (entity, source) in query.iter() {
+        if !source.playing {
+            commands.entity(entity).despawn();
+        }
+    }
+}
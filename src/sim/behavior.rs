@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::Entity;
+use cgmath::{Vector3, InnerSpace, vec3};
+use serde::Deserialize;
+
+/// The distance at which an agent is considered to have reached where it's walking to
+const ARRIVE_DISTANCE: f32 = 0.25;
+
+/// The blackboard keys the built in nodes keep their memory in
+const PATROL_POINT_KEY: &'static str = "patrol_point";
+const LAST_SEEN_KEY: &'static str = "last_seen";
+const UNSEEN_TIME_KEY: &'static str = "unseen_time";
+
+/// A behavior tree node, as loaded from a RON file. Composites and decorators control how their
+/// children run, conditions succeed or fail straight away, and actions keep running until they're
+/// done.
+#[derive(Deserialize, Clone, Debug)]
+pub enum BehaviorNode {
+    /// Run the children in order until one doesn't succeed, starting from the first one every tick
+    /// so earlier conditions can interrupt later actions
+    Sequence(Vec<BehaviorNode>),
+    /// Run the children in order until one doesn't succeed, carrying on from the running child
+    MemorySequence(Vec<BehaviorNode>),
+    /// Run the children in order until one doesn't fail, starting from the first one every tick so
+    /// higher priority children can take over from lower priority ones
+    Selector(Vec<BehaviorNode>),
+    /// Swap a child's success and failure
+    Invert(Box<BehaviorNode>),
+    /// Succeed when a child finishes, even if it fails
+    Succeed(Box<BehaviorNode>),
+    /// Fail a child that's been running for longer than a number of seconds
+    Timeout(f32, Box<BehaviorNode>),
+
+    /// Whether a value is on the blackboard
+    IsSet(String),
+    /// Whether the agent can see or hear the player
+    PlayerNoticed,
+    /// Whether the agent has noticed the player and they're within attack range
+    PlayerInAttackRange,
+    /// Whether the agent knows where the player was recently
+    RemembersPlayer,
+    /// Whether the agent is within its leash range of home
+    WithinLeash,
+
+    /// Set a flag on the blackboard
+    Set(String),
+    /// Remove a value from the blackboard
+    Clear(String),
+    /// Stand still for a number of seconds
+    Wait(f32),
+    /// Stand still for the agent's idle time
+    Idle,
+    /// Walk to the next patrol point, failing if there aren't any
+    Patrol,
+    /// Run to where the player was last seen for as long as the agent remembers them
+    Chase,
+    /// Attack the player, succeeding when the attack is over
+    Attack,
+    /// Forget where the player was last seen
+    ForgetPlayer,
+    /// Walk back home
+    ReturnHome,
+}
+
+/// The result of running a node
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BehaviorStatus {
+    Success,
+    Failure,
+    Running,
+}
+
+/// A value on a blackboard
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BlackboardValue {
+    Flag,
+    Number(f32),
+    Position(Vector3<f32>),
+}
+
+/// An agent's memory, which its behavior tree's nodes read and write
+#[derive(Default, Debug)]
+pub struct Blackboard {
+    values: HashMap<String, BlackboardValue>,
+}
+
+impl Blackboard {
+    pub fn get(&self, key: &str) -> Option<BlackboardValue> {
+        self.values.get(key).copied()
+    }
+
+    pub fn set(&mut self, key: &str, value: BlackboardValue) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    pub fn clear(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+
+    pub fn is_set(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
+    pub fn number(&self, key: &str) -> Option<f32> {
+        match self.get(key) {
+            Some(BlackboardValue::Number(number)) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn position(&self, key: &str) -> Option<Vector3<f32>> {
+        match self.get(key) {
+            Some(BlackboardValue::Position(pos)) => Some(pos),
+            _ => None,
+        }
+    }
+}
+
+/// What an agent knows about the player this tick
+pub struct PlayerPerception {
+    pub player: Entity,
+    pub pos: Vector3<f32>,
+    pub distance: f32,
+    /// Whether the agent can see or hear the player
+    pub noticed: bool,
+}
+
+/// What a behavior tree's nodes know about the agent running it
+pub struct BehaviorContext<'a> {
+    pub pos: Vector3<f32>,
+    pub home: Vector3<f32>,
+    pub patrol_points: &'a [Vector3<f32>],
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    pub idle_time: f32,
+    pub leash_range: f32,
+    /// How many seconds the agent remembers where the player was for after losing them
+    pub memory_time: f32,
+    pub attack_range: f32,
+    /// Seconds from the start of an attack to when it hits
+    pub attack_windup: f32,
+    pub attack_duration: f32,
+    pub perception: Option<&'a PlayerPerception>,
+    /// Whether the agent walks along the ground, so only needs to reach things horizontally
+    pub grounded: bool,
+    pub time_delta: f32,
+}
+
+impl<'a> BehaviorContext<'a> {
+    /// Create a context for an agent that ignores the player
+    pub fn new(pos: Vector3<f32>, patrol_points: &'a [Vector3<f32>], walk_speed: f32, idle_time: f32,
+        grounded: bool, time_delta: f32) -> Self
+    {
+        BehaviorContext {
+            pos,
+            home: pos,
+            patrol_points,
+            walk_speed,
+            run_speed: walk_speed,
+            idle_time,
+            leash_range: f32::INFINITY,
+            memory_time: 0.0,
+            attack_range: 0.0,
+            attack_windup: 0.0,
+            attack_duration: 0.0,
+            perception: None,
+            grounded,
+            time_delta,
+        }
+    }
+
+    /// Check whether the agent has reached a position
+    fn arrived(&self, target: Vector3<f32>) -> bool {
+        let offset = target - self.pos;
+        let offset = match self.grounded {
+            true => vec3(offset.x, 0.0, offset.z),
+            false => offset,
+        };
+        offset.magnitude() <= ARRIVE_DISTANCE
+    }
+}
+
+/// The animations a behavior tree can ask its agent to play, which each agent maps to its own
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AgentAnimation {
+    Idle,
+    Walk,
+    Attack,
+}
+
+/// What a behavior tree wants its agent to do this tick
+pub struct BehaviorOutput {
+    /// Where the agent wants to move to, and how fast
+    pub move_to: Option<(Vector3<f32>, f32)>,
+    pub animation: AgentAnimation,
+    /// Whether an attack lands this tick
+    pub attack_hit: bool,
+    /// Whether the agent wants to face the player
+    pub face_player: bool,
+}
+
+impl Default for BehaviorOutput {
+    fn default() -> Self {
+        BehaviorOutput {
+            move_to: None,
+            animation: AgentAnimation::Idle,
+            attack_hit: false,
+            face_player: false,
+        }
+    }
+}
+
+/// A loaded behavior tree
+pub struct BehaviorTreeDef {
+    root: BehaviorNode,
+    node_count: usize,
+}
+
+/// The behavior tree registry
+pub struct BehaviorTrees {
+    trees: HashMap<String, BehaviorTreeDef>,
+}
+
+impl BehaviorTrees {
+    pub fn new(trees: Vec<(&str, &str)>) -> Self {
+        let trees = trees.into_iter()
+            .filter_map(|(name, source)| {
+                match ron::from_str::<BehaviorNode>(source) {
+                    Ok(root) => Some((name.to_string(), BehaviorTreeDef { node_count: node_count(&root), root })),
+                    Err(err) => {
+                        log::error!("Failed to load behavior tree {}: {}", name, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        BehaviorTrees { trees }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BehaviorTreeDef> {
+        self.trees.get(name)
+    }
+}
+
+/// The running state of a node, which is reset whenever it finishes
+#[derive(Clone, Copy, Default, Debug)]
+struct NodeState {
+    /// The child a composite was last running
+    child: Option<usize>,
+    /// How many seconds the node has been running for
+    time: f32,
+}
+
+/// The BehaviorTree component, for agents whose behavior is defined by a tree in the registry
+#[derive(Component)]
+pub struct BehaviorTree {
+    pub tree: String,
+    pub blackboard: Blackboard,
+    /// The state of each node, indexed by its position in the tree in depth first order
+    state: Vec<NodeState>,
+}
+
+impl BehaviorTree {
+    pub fn new(tree: &str) -> Self {
+        BehaviorTree {
+            tree: tree.to_string(),
+            blackboard: Blackboard::default(),
+            state: Vec::new(),
+        }
+    }
+
+    /// Run the tree for a tick, returning what the agent wants to do
+    pub fn tick(&mut self, trees: &BehaviorTrees, context: &BehaviorContext) -> BehaviorOutput {
+        let mut output = BehaviorOutput::default();
+        let def = match trees.get(&self.tree) {
+            Some(def) => def,
+            None => return output,
+        };
+
+        if self.state.len() != def.node_count {
+            self.state = vec![NodeState::default(); def.node_count];
+        }
+
+        // Remember where the player was last noticed
+        match context.perception.filter(|perception| perception.noticed) {
+            Some(perception) => {
+                self.blackboard.set(LAST_SEEN_KEY, BlackboardValue::Position(perception.pos));
+                self.blackboard.set(UNSEEN_TIME_KEY, BlackboardValue::Number(0.0));
+            },
+            None => {
+                let unseen_time = self.blackboard.number(UNSEEN_TIME_KEY).unwrap_or(0.0);
+                self.blackboard.set(UNSEEN_TIME_KEY, BlackboardValue::Number(unseen_time + context.time_delta));
+            }
+        }
+
+        let mut runner = TreeRunner {
+            state: &mut self.state,
+            blackboard: &mut self.blackboard,
+            context,
+            output: &mut output,
+        };
+        runner.tick(&def.root, 0);
+
+        output
+    }
+}
+
+/// Count the nodes in a subtree
+fn node_count(node: &BehaviorNode) -> usize {
+    1 + match node {
+        BehaviorNode::Sequence(children) | BehaviorNode::MemorySequence(children) | BehaviorNode::Selector(children) => {
+            children.iter().map(node_count).sum()
+        },
+        BehaviorNode::Invert(child) | BehaviorNode::Succeed(child) | BehaviorNode::Timeout(_, child) => node_count(child),
+        _ => 0,
+    }
+}
+
+/// Get a composite's children along with their node indices
+fn child_indices(children: &[BehaviorNode], index: usize) -> impl Iterator<Item = (&BehaviorNode, usize)> {
+    children.iter().scan(index + 1, |next, child| {
+        let child_index = *next;
+        *next += node_count(child);
+        Some((child, child_index))
+    })
+}
+
+/// The state needed while running a tree for a tick
+struct TreeRunner<'a, 'b> {
+    state: &'a mut [NodeState],
+    blackboard: &'a mut Blackboard,
+    context: &'a BehaviorContext<'b>,
+    output: &'a mut BehaviorOutput,
+}
+
+impl TreeRunner<'_, '_> {
+    /// Run a node, resetting its state if it finishes
+    fn tick(&mut self, node: &BehaviorNode, index: usize) -> BehaviorStatus {
+        let status = self.run(node, index);
+        if status != BehaviorStatus::Running {
+            self.reset(node, index);
+        }
+        status
+    }
+
+    /// Reset the state of a subtree
+    fn reset(&mut self, node: &BehaviorNode, index: usize) {
+        self.state[index..index + node_count(node)].fill(NodeState::default());
+    }
+
+    /// Note which child a composite is running, resetting the one it was running before if it changed,
+    /// since that one has been interrupted
+    fn switch_child(&mut self, children: &[BehaviorNode], index: usize, child: Option<usize>) {
+        let previous = self.state[index].child;
+        if previous != child {
+            if let Some((node, node_index)) = previous.and_then(|previous| child_indices(children, index).nth(previous)) {
+                self.reset(node, node_index);
+            }
+        }
+        self.state[index].child = child;
+    }
+
+    /// Walk towards a position, returning whether the agent is already there
+    fn walk_to(&mut self, target: Vector3<f32>, speed: f32) -> bool {
+        if self.context.arrived(target) {
+            return true;
+        }
+
+        self.output.move_to = Some((target, speed));
+        self.output.animation = AgentAnimation::Walk;
+        false
+    }
+
+    /// Wait for a number of seconds
+    fn wait(&mut self, index: usize, seconds: f32) -> BehaviorStatus {
+        self.state[index].time += self.context.time_delta;
+        self.output.animation = AgentAnimation::Idle;
+        match self.state[index].time >= seconds {
+            true => BehaviorStatus::Success,
+            false => BehaviorStatus::Running,
+        }
+    }
+
+    fn run(&mut self, node: &BehaviorNode, index: usize) -> BehaviorStatus {
+        let condition = |value: bool| match value {
+            true => BehaviorStatus::Success,
+            false => BehaviorStatus::Failure,
+        };
+        let context = self.context;
+
+        match node {
+            BehaviorNode::Sequence(children) | BehaviorNode::Selector(children) => {
+                let stop_on = match node {
+                    BehaviorNode::Sequence(_) => BehaviorStatus::Success,
+                    _ => BehaviorStatus::Failure,
+                };
+
+                for (i, (child, child_index)) in child_indices(children, index).enumerate() {
+                    let status = self.tick(child, child_index);
+                    if status != stop_on {
+                        let running = (status == BehaviorStatus::Running).then_some(i);
+                        self.switch_child(children, index, running);
+                        return status;
+                    }
+                }
+                stop_on
+            },
+            BehaviorNode::MemorySequence(children) => {
+                let start = self.state[index].child.unwrap_or(0);
+                for (i, (child, child_index)) in child_indices(children, index).enumerate().skip(start) {
+                    let status = self.tick(child, child_index);
+                    if status != BehaviorStatus::Success {
+                        self.state[index].child = Some(i);
+                        return status;
+                    }
+                }
+                BehaviorStatus::Success
+            },
+            BehaviorNode::Invert(child) => match self.tick(child, index + 1) {
+                BehaviorStatus::Success => BehaviorStatus::Failure,
+                BehaviorStatus::Failure => BehaviorStatus::Success,
+                BehaviorStatus::Running => BehaviorStatus::Running,
+            },
+            BehaviorNode::Succeed(child) => match self.tick(child, index + 1) {
+                BehaviorStatus::Running => BehaviorStatus::Running,
+                _ => BehaviorStatus::Success,
+            },
+            BehaviorNode::Timeout(seconds, child) => {
+                self.state[index].time += context.time_delta;
+                match self.state[index].time > *seconds {
+                    true => BehaviorStatus::Failure,
+                    false => self.tick(child, index + 1),
+                }
+            },
+
+            BehaviorNode::IsSet(key) => condition(self.blackboard.is_set(key)),
+            BehaviorNode::PlayerNoticed => condition(context.perception.map(|perception| perception.noticed).unwrap_or(false)),
+            BehaviorNode::PlayerInAttackRange => condition(context.perception
+                .map(|perception| perception.noticed && perception.distance <= context.attack_range)
+                .unwrap_or(false)),
+            BehaviorNode::RemembersPlayer => condition(self.blackboard.is_set(LAST_SEEN_KEY)
+                && self.blackboard.number(UNSEEN_TIME_KEY).unwrap_or(0.0) <= context.memory_time),
+            BehaviorNode::WithinLeash => condition((context.pos - context.home).magnitude() <= context.leash_range),
+
+            BehaviorNode::Set(key) => {
+                self.blackboard.set(key, BlackboardValue::Flag);
+                BehaviorStatus::Success
+            },
+            BehaviorNode::Clear(key) => {
+                self.blackboard.clear(key);
+                BehaviorStatus::Success
+            },
+            BehaviorNode::Wait(seconds) => self.wait(index, *seconds),
+            BehaviorNode::Idle => self.wait(index, context.idle_time),
+            BehaviorNode::Patrol => {
+                if context.patrol_points.is_empty() {
+                    return BehaviorStatus::Failure;
+                }
+
+                let next = self.blackboard.number(PATROL_POINT_KEY).unwrap_or(0.0) as usize % context.patrol_points.len();
+                match self.walk_to(context.patrol_points[next], context.walk_speed) {
+                    true => {
+                        self.blackboard.set(PATROL_POINT_KEY, BlackboardValue::Number((next + 1) as f32));
+                        BehaviorStatus::Success
+                    },
+                    false => BehaviorStatus::Running,
+                }
+            },
+            BehaviorNode::Chase => match self.blackboard.position(LAST_SEEN_KEY) {
+                Some(last_seen) => {
+                    self.walk_to(last_seen, context.run_speed);
+                    BehaviorStatus::Running
+                },
+                None => BehaviorStatus::Failure,
+            },
+            BehaviorNode::Attack => {
+                let time = self.state[index].time;
+                let new_time = time + context.time_delta;
+                self.state[index].time = new_time;
+
+                self.output.animation = AgentAnimation::Attack;
+                self.output.face_player = true;
+                self.output.attack_hit |= time < context.attack_windup && new_time >= context.attack_windup;
+
+                match new_time >= context.attack_duration {
+                    true => BehaviorStatus::Success,
+                    false => BehaviorStatus::Running,
+                }
+            },
+            BehaviorNode::ForgetPlayer => {
+                self.blackboard.clear(LAST_SEEN_KEY);
+                BehaviorStatus::Success
+            },
+            BehaviorNode::ReturnHome => match self.walk_to(context.home, context.walk_speed) {
+                true => BehaviorStatus::Success,
+                false => BehaviorStatus::Running,
+            },
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Commands, Entity};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Matrix3, Rad, SquareMatrix};
+use dreamfield_renderer::components::{PlayerCamera, Visual};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+/// The Billboard component, for 2D sprites that turn to face the camera, drawn with the frame
+/// models build.rs generates from their sprite sheets. It sets the entity's Visual and rotation
+/// itself, so it shouldn't have either of its own. Upright billboards only turn around y, so that
+/// foliage and pickups stay standing on the ground, and the rest face the camera fully.
+#[derive(Component)]
+pub struct Billboard {
+    pub sprite: String,
+    /// How many frames the sprite sheet has
+    pub frames: usize,
+    /// How many frames are shown per second
+    pub fps: f32,
+    /// The height and width of the sprite in world units
+    pub size: f32,
+    pub upright: bool,
+    time: f32,
+    frame: Option<usize>,
+}
+
+impl Billboard {
+    /// Create an upright billboard showing a single frame sprite
+    pub fn new(sprite: &str, size: f32) -> Self {
+        Self::new_animated(sprite, 1, 0.0, size)
+    }
+
+    /// Create an upright billboard that loops through a sprite sheet's frames
+    pub fn new_animated(sprite: &str, frames: usize, fps: f32, size: f32) -> Self {
+        Billboard {
+            sprite: sprite.to_string(),
+            frames: frames.max(1),
+            fps,
+            size,
+            upright: true,
+            time: 0.0,
+            frame: None,
+        }
+    }
+
+    /// Get the name of the model for a frame of the sprite
+    fn frame_model(&self, frame: usize) -> String {
+        format!("sprite_{}_{}", self.sprite, frame)
+    }
+}
+
+/// The billboard system, which animates billboards and turns them to face the camera
+pub fn update_billboards(mut commands: Commands, sim_time: Res<SimTime>, cameras: Query<&PlayerCamera>,
+    mut billboards: Query<(Entity, &mut Billboard, &mut Transform)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    let cam_transform = match cameras.iter().next().and_then(|cam| cam.view.invert()) {
+        Some(cam_transform) => cam_transform,
+        None => return,
+    };
+    let camera_rot = Matrix3::from_cols(cam_transform.x.truncate(), cam_transform.y.truncate(), cam_transform.z.truncate());
+    let cam_pos = cam_transform.w.truncate();
+
+    for (entity, mut billboard, mut transform) in billboards.iter_mut() {
+        billboard.time += time_delta;
+        let frame = (billboard.time * billboard.fps) as usize % billboard.frames;
+        if billboard.frame != Some(frame) {
+            commands.entity(entity).insert(Visual::new(&billboard.frame_model(frame), false));
+            billboard.frame = Some(frame);
+        }
+
+        // The sprite models face along z, so upright billboards turn that towards the camera
+        let rot = match billboard.upright {
+            true => {
+                let to_cam = cam_pos - transform.pos;
+                Matrix3::from_angle_y(Rad(f32::atan2(to_cam.x, to_cam.z)))
+            },
+            false => camera_rot,
+        };
+        transform.rot = rot * billboard.size;
+    }
+}
@@ -0,0 +1,103 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Commands, Entity, Without};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Matrix3, SquareMatrix, vec3};
+use dreamfield_renderer::components::Visual;
+use dreamfield_system::components::Transform;
+
+use super::decal::surface_rotation;
+use super::static_collision::StaticCollision;
+
+/// The model blob shadows are drawn with, a decal build.rs generates
+const BLOB_SHADOW_MODEL: &'static str = "decal_blob_shadow";
+
+/// How far above the caster's position the ray to the ground starts, so that it still finds the
+/// ground when the caster's position is slightly under it
+const RAY_START_HEIGHT: f32 = 0.5;
+
+/// How far shadows are lifted off the ground, so they don't z-fight with it. It's a little higher
+/// than decals so that shadows are drawn over them.
+const SURFACE_OFFSET: f32 = 0.03;
+
+/// The CastsBlobShadow component, for characters and props that have a round shadow on the ground
+/// under them. The shadow shrinks as they get higher above the ground, and goes once they're out of
+/// range of it.
+#[derive(Component)]
+pub struct CastsBlobShadow {
+    /// The shadow's radius when the caster's on the ground
+    pub radius: f32,
+    /// How high above the ground the caster can be and still have a shadow
+    pub max_height: f32,
+    shadow: Option<Entity>,
+}
+
+impl CastsBlobShadow {
+    pub fn new(radius: f32) -> Self {
+        CastsBlobShadow {
+            radius,
+            max_height: 6.0,
+            shadow: None,
+        }
+    }
+}
+
+/// The BlobShadow component, for the entity that draws a caster's shadow
+#[derive(Component)]
+pub struct BlobShadow {
+    caster: Entity,
+    visible: bool,
+}
+
+/// The blob shadow system, which finds the ground under each caster and puts its shadow there,
+/// spawning the shadow the first time and despawning it once the caster's gone
+pub fn update_blob_shadows(mut commands: Commands, static_collision: Res<StaticCollision>,
+    mut casters: Query<(Entity, &mut CastsBlobShadow, &Transform), Without<BlobShadow>>,
+    mut shadows: Query<(Entity, &mut BlobShadow, &mut Transform)>)
+{
+    for (entity, mut caster, transform) in casters.iter_mut() {
+        let origin = transform.pos + vec3(0.0, RAY_START_HEIGHT, 0.0);
+        let hit = static_collision.raycast(origin, vec3(0.0, -1.0, 0.0), caster.max_height + RAY_START_HEIGHT)
+            .filter(|hit| caster.max_height > 0.0 && hit.distance - RAY_START_HEIGHT < caster.max_height);
+
+        // Shrink to half size at the max height
+        let placement = hit.map(|hit| {
+            let height = f32::max(hit.distance - RAY_START_HEIGHT, 0.0);
+            let scale = caster.radius * 2.0 * (1.0 - 0.5 * height / caster.max_height);
+            let rot = surface_rotation(hit.normal, vec3(0.0, 0.0, -1.0)) * scale;
+            (hit.point + hit.normal * SURFACE_OFFSET, rot)
+        });
+
+        let shadow = caster.shadow.and_then(|shadow| shadows.get_mut(shadow).ok());
+        match (shadow, placement) {
+            (Some((shadow_entity, mut shadow, mut shadow_transform)), placement) => {
+                if let Some((pos, rot)) = placement {
+                    shadow_transform.pos = pos;
+                    shadow_transform.rot = rot;
+                }
+                if shadow.visible != placement.is_some() {
+                    shadow.visible = placement.is_some();
+                    match shadow.visible {
+                        true => commands.entity(shadow_entity).insert(Visual::new(BLOB_SHADOW_MODEL, false)),
+                        false => commands.entity(shadow_entity).remove::<Visual>(),
+                    };
+                }
+            },
+            (None, placement) => {
+                let (pos, rot) = placement.unwrap_or((transform.pos, Matrix3::identity()));
+                let mut shadow = commands.spawn();
+                shadow.insert(BlobShadow { caster: entity, visible: placement.is_some() })
+                    .insert(Transform::new(pos, rot));
+                if placement.is_some() {
+                    shadow.insert(Visual::new(BLOB_SHADOW_MODEL, false));
+                }
+                caster.shadow = Some(shadow.id());
+            },
+        }
+    }
+
+    for (entity, shadow, _) in shadows.iter() {
+        if casters.get(shadow.caster).is_err() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use bevy_ecs::prelude::{EventReader, With};
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Matrix4, Rad, SquareMatrix, InnerSpace, vec3};
+use dreamfield_renderer::components::PlayerCamera;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::player_movement::PlayerMovement;
+use super::projectile::ProjectileImpactEvent;
+
+/// The maximum rotation offset of each axis at full trauma, in radians
+const MAX_ANGLE: f32 = 0.08;
+
+/// The maximum translation offset of each axis at full trauma
+const MAX_OFFSET: f32 = 0.1;
+
+/// The noise frequency of the shake
+const SHAKE_FREQUENCY: f32 = 15.0;
+
+/// The trauma added by a projectile impact right next to the player
+const IMPACT_TRAUMA: f32 = 0.6;
+
+/// The distance from the player at which projectile impacts stop shaking the camera
+const IMPACT_SHAKE_RADIUS: f32 = 8.0;
+
+/// The CameraShake resource. Trauma from 0 to 1 is added by events like explosions and hard
+/// landings, and then decays over time, with the shake strength being the trauma squared.
+pub struct CameraShake {
+    pub trauma: f32,
+    /// The amount of trauma removed per second
+    pub decay: f32,
+    time: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        CameraShake {
+            trauma: 0.0,
+            decay: 1.5,
+            time: 0.0,
+        }
+    }
+}
+
+impl CameraShake {
+    /// Add trauma to the camera
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = f32::clamp(self.trauma + amount, 0.0, 1.0);
+    }
+}
+
+/// An event to shake the camera by adding trauma
+pub struct CameraShakeEvent {
+    pub trauma: f32,
+}
+
+/// Get smooth pseudorandom noise from -1 to 1, with a different function for each seed
+fn perlin_noise_1d(x: f32, seed: u32) -> f32 {
+    let gradient = |i: i32| {
+        let mut hash = (i as u32).wrapping_mul(0x27d4eb2d) ^ seed.wrapping_mul(0x9e3779b9);
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(0x85ebca6b);
+        hash ^= hash >> 13;
+        (hash as f32 / u32::MAX as f32) * 2.0 - 1.0
+    };
+
+    let (cell, t) = (x.floor() as i32, x - x.floor());
+    let (g0, g1) = (gradient(cell), gradient(cell + 1));
+
+    // Blend the contributions of the two neighbouring gradients with a quintic fade
+    let fade = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let noise = g0 * t + (g1 * (t - 1.0) - g0 * t) * fade;
+
+    // 1d gradient noise peaks at 0.5, so scale it up to roughly -1 to 1
+    noise * 2.0
+}
+
+/// The camera shake system. It runs after everything else that sets the camera view so that it can
+/// add the shake on top.
+pub fn update_camera_shake(sim_time: Res<SimTime>, mut shake: ResMut<CameraShake>,
+    mut events: EventReader<CameraShakeEvent>, mut query: Query<&mut PlayerCamera>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for event in events.iter() {
+        shake.add_trauma(event.trauma);
+    }
+
+    shake.time += time_delta;
+    let decay = shake.decay * time_delta;
+    shake.trauma = f32::max(shake.trauma - decay, 0.0);
+
+    let strength = shake.trauma * shake.trauma;
+    if strength <= 0.0 {
+        return;
+    }
+
+    let t = shake.time * SHAKE_FREQUENCY;
+    let noise = |seed| perlin_noise_1d(t, seed) * strength;
+
+    let shake_transform = Matrix4::from_translation(vec3(noise(0), noise(1), noise(2)) * MAX_OFFSET)
+        * Matrix4::from_angle_y(Rad(noise(3) * MAX_ANGLE))
+        * Matrix4::from_angle_x(Rad(noise(4) * MAX_ANGLE))
+        * Matrix4::from_angle_z(Rad(noise(5) * MAX_ANGLE));
+
+    if let Some(shake_inv) = shake_transform.invert() {
+        for mut cam in query.iter_mut() {
+            cam.view = shake_inv * cam.view;
+        }
+    }
+}
+
+/// The impact shake system, which shakes the camera when projectiles explode near the player
+pub fn shake_on_impacts(mut shake: ResMut<CameraShake>, mut events: EventReader<ProjectileImpactEvent>,
+    player_query: Query<&Transform, With<PlayerMovement>>)
+{
+    let player_pos = match player_query.get_single() {
+        Ok(transform) => transform.pos,
+        Err(_) => return,
+    };
+
+    for event in events.iter() {
+        let falloff = 1.0 - (event.position - player_pos).magnitude() / IMPACT_SHAKE_RADIUS;
+        if falloff > 0.0 {
+            shake.add_trauma(IMPACT_TRAUMA * falloff);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::Entity;
+use cgmath::{Vector3, InnerSpace, ElementWise, vec2, vec3};
+use dreamfield_system::intersection::{Plane, Collider, Shape};
+
+use super::static_collision::{StaticCollision, StaticHit};
+
+/// A tiny value which stops us from coming too close to walls
+const MIN_DISTANCE_FROM_WALLS: f32 = 0.01;
+
+/// How far below a character to look for the ground it's standing on
+pub const GROUND_CHECK_DISTANCE: f32 = 0.05;
+
+/// How much further a step has to get the character than walking into it for it to be taken
+const MIN_STEP_PROGRESS: f32 = 0.01;
+
+/// A kinematic character controller, which moves a character's collider through the world by
+/// sliding along what it hits, stepping up stairs and snapping down to the ground when walking
+/// down slopes. Characters are bounding spheroids, which the static collision sweeps in their
+/// ellipsoid space, so they're treated as rounded capsules. It can be added as a component to npcs to make them collide with
+/// the world.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CharacterController {
+    /// The steepest slope that can be walked on, in degrees
+    pub max_slope_angle: f32,
+    /// The tallest step that can be walked up without jumping
+    pub step_height: f32,
+    /// How far below the character the ground can be while still sticking to it
+    pub ground_snap_distance: f32,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        CharacterController {
+            max_slope_angle: 25.8,
+            step_height: 0.3,
+            ground_snap_distance: 0.3,
+        }
+    }
+}
+
+/// A hit from sweeping a character's collider through the world, in world space
+pub struct CharacterHit {
+    /// The distance the collider moved before hitting something
+    pub distance: f32,
+    pub point: Vector3<f32>,
+    pub normal: Vector3<f32>,
+}
+
+/// A character's collider, for moving it in the static collision's ellipsoid space
+#[derive(Clone, Copy)]
+struct Spheroid {
+    offset: Vector3<f32>,
+    cbm: Vector3<f32>,
+}
+
+impl Spheroid {
+    fn new(collider: &Collider) -> Option<Self> {
+        match collider.shape {
+            Shape::BoundingSpheroid(offset, radius) => Some(Spheroid {
+                offset,
+                cbm: vec3(1.0 / radius.x, 1.0 / radius.y, 1.0 / radius.z),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Sweep the collider from a position
+    fn sweep(&self, collision: &StaticCollision, pos: Vector3<f32>,
+        movement: Vector3<f32>, ignore_entity: Entity) -> Option<CharacterHit>
+    {
+        let position_es = (pos + self.offset).mul_element_wise(self.cbm);
+        let movement_es = movement.mul_element_wise(self.cbm);
+        sweep_unit(collision, &self.cbm, position_es, movement_es, ignore_entity).map(|hit| CharacterHit {
+            distance: hit.distance * movement.magnitude(),
+            point: hit.point.div_element_wise(self.cbm),
+            normal: hit.normal.div_element_wise(self.cbm).normalize(),
+        })
+    }
+
+    /// Move the collider from a position, sliding along anything it hits, and return the new position
+    fn slide(&self, collision: &StaticCollision, pos: Vector3<f32>,
+        movement: Vector3<f32>, ignore_entity: Entity) -> Vector3<f32>
+    {
+        let position_es = (pos + self.offset).mul_element_wise(self.cbm);
+        let movement_es = movement.mul_element_wise(self.cbm);
+        let position_es = recursive_slide(collision, &self.cbm, position_es, movement_es, ignore_entity, 0);
+        position_es.div_element_wise(self.cbm) - self.offset
+    }
+}
+
+/// Get the horizontal distance between two positions
+fn horizontal_distance(a: Vector3<f32>, b: Vector3<f32>) -> f32 {
+    vec2(a.x - b.x, a.z - b.z).magnitude()
+}
+
+impl CharacterController {
+    /// Get the minimum normal y value of walkable ground
+    pub fn min_walk_normal(&self) -> f32 {
+        self.max_slope_angle.to_radians().cos()
+    }
+
+    /// Get whether ground with a given normal can be walked on
+    pub fn is_walkable(&self, normal: Vector3<f32>) -> bool {
+        normal.y >= self.min_walk_normal()
+    }
+
+    /// Sweep a character's collider through the world
+    pub fn sweep(&self, collision: &StaticCollision, collider: &Collider,
+        pos: Vector3<f32>, movement: Vector3<f32>, ignore_entity: Entity) -> Option<CharacterHit>
+    {
+        sweep_collider(collision, collider, pos, movement, ignore_entity)
+    }
+
+    /// Move a character through the world with a given velocity, first laterally and then
+    /// vertically, and return the new position. Grounded characters step up over anything shorter
+    /// than the step height, and stick to the ground when walking down slopes and stairs.
+    pub fn move_and_slide(&self, collision: &StaticCollision, collider: &Collider,
+        pos: Vector3<f32>, velocity: Vector3<f32>, grounded: bool, ignore_entity: Entity, time_delta: f32)
+        -> Vector3<f32>
+    {
+        let spheroid = match Spheroid::new(collider) {
+            Some(spheroid) => spheroid,
+            None => {
+                log::warn!("Character controllers need a BoundingSpheroid collider");
+                return pos;
+            }
+        };
+
+        // Lateral movement, stepping up over anything that stops us if we're on the ground
+        let lateral = vec3(velocity.x, 0.0, velocity.z) * time_delta;
+        let mut new_pos = spheroid.slide(collision, pos, lateral, ignore_entity);
+
+        let blocked = horizontal_distance(new_pos, pos) + MIN_STEP_PROGRESS < lateral.magnitude();
+        if grounded && blocked && self.step_height > 0.0 {
+            if let Some(stepped) = self.try_step(&spheroid, collision, pos, lateral, ignore_entity) {
+                if horizontal_distance(stepped, pos) > horizontal_distance(new_pos, pos) + MIN_STEP_PROGRESS {
+                    new_pos = stepped;
+                }
+            }
+        }
+
+        // Vertical movement
+        if velocity.y != 0.0 {
+            new_pos = spheroid.slide(collision, new_pos, vec3(0.0, velocity.y * time_delta, 0.0), ignore_entity);
+        }
+
+        // Snap down to the ground if we were on it and aren't moving upwards
+        if grounded && velocity.y <= 0.0 && self.ground_snap_distance > 0.0 {
+            let snap = vec3(0.0, -self.ground_snap_distance, 0.0);
+            if let Some(hit) = spheroid.sweep(collision, new_pos, snap, ignore_entity) {
+                if self.is_walkable(hit.normal) {
+                    new_pos.y -= f32::max(hit.distance - MIN_DISTANCE_FROM_WALLS, 0.0);
+                }
+            }
+        }
+
+        new_pos
+    }
+
+    /// Try moving laterally after lifting the character by the step height, and then put it back
+    /// down, returning the new position if it landed on walkable ground
+    fn try_step(&self, spheroid: &Spheroid, collision: &StaticCollision,
+        pos: Vector3<f32>, lateral: Vector3<f32>, ignore_entity: Entity) -> Option<Vector3<f32>>
+    {
+        let raised = spheroid.slide(collision, pos, vec3(0.0, self.step_height, 0.0), ignore_entity);
+        let rise = raised.y - pos.y;
+        if rise <= MIN_DISTANCE_FROM_WALLS {
+            return None;
+        }
+
+        let across = spheroid.slide(collision, raised, lateral, ignore_entity);
+        let hit = spheroid.sweep(collision, across, vec3(0.0, -rise, 0.0), ignore_entity)?;
+        match self.is_walkable(hit.normal) {
+            true => Some(across - vec3(0.0, f32::max(hit.distance - MIN_DISTANCE_FROM_WALLS, 0.0), 0.0)),
+            false => None,
+        }
+    }
+}
+
+/// Sweep a bounding spheroid collider at a position through the world
+pub fn sweep_collider(collision: &StaticCollision, collider: &Collider,
+    pos: Vector3<f32>, movement: Vector3<f32>, ignore_entity: Entity) -> Option<CharacterHit>
+{
+    Spheroid::new(collider)?.sweep(collision, pos, movement, ignore_entity)
+}
+
+/// Sweep a unit sphere through the world from the start with a given velocity. Start and velocity
+/// must be converted to e-space first by multiplying by the collider's cbm, and then the results
+/// must eventually be converted back to world space by doing the opposite.
+pub fn sweep_unit(collision: &StaticCollision, cbm: &Vector3<f32>,
+    position: Vector3<f32>, velocity: Vector3<f32>, ignore_entity: Entity) -> Option<StaticHit>
+{
+    collision.sweep_unit_sphere(position, velocity, *cbm, Some(ignore_entity))
+}
+
+/// Move through the world sliding on surfaces we collide with
+fn recursive_slide(collision: &StaticCollision, cbm: &Vector3<f32>,
+    position: Vector3<f32>, velocity: Vector3<f32>, ignore_entity: Entity, depth: i32) -> Vector3<f32>
+{
+    const MAX_RECURSION_DEPTH: i32 = 5;
+
+    // If we hit the maximum recursion, just return the current position and don't advance anymore
+    if depth >= MAX_RECURSION_DEPTH {
+        return position;
+    }
+
+    // If the velocity is 0, stop advancing too
+    let velocity_length = velocity.magnitude();
+    if velocity_length == 0.0 {
+        return position;
+    }
+
+    // Sphere sweep and find next intersection point
+    let hit = sweep_unit(collision, cbm, position, velocity, ignore_entity);
+    if hit.is_none() {
+        return position + velocity;
+    }
+    let hit = hit.unwrap();
+
+    // Only update position if we aren't already very close
+    let hit_distance = hit.distance * velocity_length;
+    let (new_position, hit_point) = match hit_distance > MIN_DISTANCE_FROM_WALLS {
+        true => {
+            let velocity_dir = velocity / velocity_length;
+
+            // Update position to just before the hit point so we don't move into it
+            let new_position = position + velocity_dir * (hit_distance - MIN_DISTANCE_FROM_WALLS);
+
+            // Update the hit point too so that it doesn't throw off the plane calculation
+            let hit_point = hit.point - MIN_DISTANCE_FROM_WALLS * velocity_dir;
+
+            (new_position, hit_point)
+        },
+        false => (position, hit.point)
+    };
+
+    // Calculate sliding normal using clever math from triangle soup paper
+    let slide_plane_origin = hit_point;
+    let slide_plane_normal = (new_position - hit_point).normalize();
+    let slide_plane = Plane::new_from_point_and_normal(slide_plane_origin, slide_plane_normal);
+
+    // Project original destination onto plane, and subtract it the intersection point from it to
+    // get a new velocity
+    let original_destination = position + velocity;
+    let new_destination_point = slide_plane.project(original_destination);
+    let new_velocity_vector = new_destination_point - hit_point;
+
+    // If the new velocity is too low, just return the new position and stop moving
+    if new_velocity_vector.magnitude2() < (MIN_DISTANCE_FROM_WALLS * MIN_DISTANCE_FROM_WALLS) {
+        return new_position;
+    }
+
+    recursive_slide(collision, cbm, new_position, new_velocity_vector, ignore_entity, depth + 1)
+}
@@ -0,0 +1,106 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{EventReader, With};
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Vector3, Vector2, vec2, vec3, Zero};
+use dreamfield_system::components::Transform;
+
+use super::PlayerMovement;
+use super::health::DEATH_FADE_TIME;
+use super::screen_fade::ScreenFade;
+use super::spawn_points::SpawnPoints;
+use super::trigger::{TriggerVolume, TriggerEnterEvent};
+
+/// The default height below which the player is respawned
+const DEFAULT_KILL_Z: f32 = -50.0;
+
+/// The Checkpoint component. Checkpoints need a TriggerVolume, and when the player enters it the
+/// checkpoint's Transform becomes the respawn point, or the spawn point it names if it has one.
+#[derive(Component, Default)]
+pub struct Checkpoint {
+    pub activated: bool,
+    pub spawn_point: Option<String>,
+}
+
+impl Checkpoint {
+    /// Create a checkpoint that respawns the player at a spawn point
+    pub fn at_spawn_point(name: &str) -> Self {
+        Checkpoint {
+            activated: false,
+            spawn_point: Some(name.to_string()),
+        }
+    }
+}
+
+/// The RespawnPoint resource
+pub struct RespawnPoint {
+    pub pos: Vector3<f32>,
+    pub pitch_yaw: Vector2<f32>,
+    /// The height below which the player is respawned
+    pub kill_z: f32,
+    /// Set to respawn the player on the next update
+    pub respawn_requested: bool,
+}
+
+impl RespawnPoint {
+    pub fn new(pos: Vector3<f32>, pitch_yaw: Vector2<f32>) -> Self {
+        RespawnPoint {
+            pos,
+            pitch_yaw,
+            kill_z: DEFAULT_KILL_Z,
+            respawn_requested: false,
+        }
+    }
+}
+
+/// The checkpoint system
+pub fn update_checkpoints(mut respawn_point: ResMut<RespawnPoint>, spawn_points: Res<SpawnPoints>,
+    mut events: EventReader<TriggerEnterEvent>, mut checkpoints: Query<(&mut Checkpoint, &Transform), With<TriggerVolume>>)
+{
+    for event in events.iter() {
+        if let Ok((mut checkpoint, transform)) = checkpoints.get_mut(event.trigger) {
+            let spawn_point = checkpoint.spawn_point.as_ref().and_then(|name| spawn_points.get(name));
+            match spawn_point {
+                Some(spawn_point) => {
+                    respawn_point.pos = spawn_point.pos();
+                    respawn_point.pitch_yaw = spawn_point.pitch_yaw();
+                },
+                None => {
+                    // Face the same way as the checkpoint when respawning
+                    let forward = transform.rot * vec3(0.0, 0.0, -1.0);
+                    let yaw = f32::atan2(-forward.x, -forward.z);
+
+                    respawn_point.pos = transform.pos;
+                    respawn_point.pitch_yaw = vec2(0.0, yaw);
+                }
+            }
+
+            if !checkpoint.activated {
+                log::info!("Checkpoint reached");
+                checkpoint.activated = true;
+            }
+        }
+    }
+}
+
+/// The player respawn system, which fades the screen back in after the player has died
+pub fn respawn_player(mut respawn_point: ResMut<RespawnPoint>, mut screen_fade: ResMut<ScreenFade>,
+    mut query: Query<(&mut Transform, &mut PlayerMovement)>)
+{
+    for (mut transform, mut player_movement) in query.iter_mut() {
+        if transform.pos.y < respawn_point.kill_z {
+            respawn_point.respawn_requested = true;
+        }
+
+        if respawn_point.respawn_requested {
+            transform.pos = respawn_point.pos;
+            player_movement.pitch_yaw = respawn_point.pitch_yaw;
+            player_movement.velocity = Vector3::zero();
+            player_movement.ground_plane = None;
+        }
+    }
+
+    if respawn_point.respawn_requested && screen_fade.is_faded_out() {
+        screen_fade.fade_in(DEATH_FADE_TIME);
+    }
+    respawn_point.respawn_requested = false;
+}
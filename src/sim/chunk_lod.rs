@@ -0,0 +1,159 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Commands, Entity};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector2, Vector3, vec2, vec3, Matrix3, SquareMatrix, InnerSpace, Zero};
+use dreamfield_renderer::components::{PlayerCamera, Visual};
+use dreamfield_system::components::Transform;
+use include_dir::Dir;
+
+use super::level::Levels;
+use super::pvs::VisibilitySets;
+
+/// One tile of a level's world models, with the names of its LOD models from the most detailed to
+/// the least
+struct LodTile {
+    level: String,
+    centre: Vector2<f32>,
+    models: Vec<String>,
+}
+
+/// The WorldLods resource, with the LOD tiles build.rs baked for each level. The full detail world
+/// is drawn by the WorldChunkManager near the player, and the LOD tiles are drawn past it, so the
+/// far clip can be pushed out without drawing the whole world at full detail. The tiles only have
+/// vertex colors, which is hard to tell apart from the textured world through the fog.
+pub struct WorldLods {
+    tiles: Vec<LodTile>,
+}
+
+impl WorldLods {
+    /// Load the tile indexes from the LOD directory, which has a directory for each level with an
+    /// index for each world model in it, in the format written by bake_lods
+    pub fn new(dir: &'static Dir<'static>) -> Self {
+        let mut tiles = Vec::new();
+        for level_dir in dir.dirs() {
+            let level = level_dir.path().to_string_lossy().to_string();
+            let indexes = level_dir.files().filter(|file| file.path().extension().map_or(false, |ext| ext == "tiles"));
+            for index in indexes {
+                let name = index.path().file_stem().unwrap_or_default().to_string_lossy().to_string();
+                match index.contents_utf8().and_then(|source| Self::parse_index(&level, &name, source, &mut tiles)) {
+                    Some(()) => (),
+                    None => log::error!("Failed to parse LOD index {}", index.path().display()),
+                }
+            }
+        }
+
+        WorldLods { tiles }
+    }
+
+    /// Parse the index of a world model's tiles, adding them to the list
+    fn parse_index(level: &str, name: &str, source: &str, tiles: &mut Vec<LodTile>) -> Option<()> {
+        let mut lines = source.lines();
+        let tile_size: f32 = lines.next()?.trim().parse().ok()?;
+
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let values: Vec<i32> = line.split_whitespace().map(str::parse).collect::<Result<_, _>>().ok()?;
+            let [x, z, lod_count] = <[i32; 3]>::try_from(values).ok()?;
+            tiles.push(LodTile {
+                level: level.to_string(),
+                centre: vec2(x as f32 + 0.5, z as f32 + 0.5) * tile_size,
+                models: (0..lod_count).map(|lod| format!("{}/{}_lod{}_{}_{}", level, name, lod, x, z)).collect(),
+            });
+        }
+
+        Some(())
+    }
+}
+
+/// The WorldLodSettings resource, the distances the LODs are picked by
+pub struct WorldLodSettings {
+    /// The distance the full detail world is drawn to, which tiles closer than aren't drawn. It
+    /// should match how far the WorldChunkManager draws chunks around the camera.
+    pub chunk_distance: f32,
+    /// How far each LOD is drawn to, from the most detailed to the least, with tiles any further
+    /// away not being drawn at all
+    pub lod_distances: Vec<f32>,
+}
+
+impl Default for WorldLodSettings {
+    fn default() -> Self {
+        WorldLodSettings {
+            chunk_distance: 32.0,
+            lod_distances: vec![64.0, 128.0],
+        }
+    }
+}
+
+impl WorldLodSettings {
+    /// Pick the LOD to draw at a distance, or None if nothing should be drawn
+    fn pick(&self, distance: f32, lod_count: usize) -> Option<usize> {
+        match distance < self.chunk_distance {
+            true => None,
+            false => self.lod_distances.iter()
+                .position(|max_distance| distance < *max_distance)
+                .filter(|lod| *lod < lod_count),
+        }
+    }
+}
+
+/// The ChunkLod component, for the entity that draws a LOD tile
+#[derive(Component)]
+pub struct ChunkLod {
+    level: String,
+    centre: Vector2<f32>,
+    models: Vec<String>,
+    lod: Option<usize>,
+}
+
+/// The world LOD system, which spawns the current level's LOD tiles and picks which LOD each one
+/// draws by its distance from the camera, hiding the ones that can't be seen from it
+pub fn update_world_lods(mut commands: Commands, lods: Res<WorldLods>, settings: Res<WorldLodSettings>,
+    levels: Res<Levels>, visibility: Res<VisibilitySets>, cam_query: Query<&PlayerCamera>, mut tile_query: Query<(Entity, &mut ChunkLod)>)
+{
+    let level = levels.current();
+
+    // Despawn the tiles for any other level, and spawn this level's if they aren't already
+    let mut spawned = false;
+    for (entity, tile) in tile_query.iter() {
+        match Some(tile.level.as_str()) == level {
+            true => spawned = true,
+            false => commands.entity(entity).despawn(),
+        }
+    }
+
+    if !spawned {
+        for tile in lods.tiles.iter().filter(|tile| Some(tile.level.as_str()) == level) {
+            commands.spawn()
+                .insert(ChunkLod {
+                    level: tile.level.clone(),
+                    centre: tile.centre,
+                    models: tile.models.clone(),
+                    lod: None,
+                })
+                .insert(Transform::new(Vector3::zero(), Matrix3::identity()));
+        }
+        return;
+    }
+
+    let cam_pos = match cam_query.iter().next().and_then(|cam| cam.view.invert()) {
+        Some(cam_transform) => cam_transform.w.truncate(),
+        None => return,
+    };
+
+    for (entity, mut tile) in tile_query.iter_mut() {
+        let tile_pos = vec3(tile.centre.x, cam_pos.y, tile.centre.y);
+        let lod = match visibility.is_visible(level, cam_pos, tile_pos) {
+            true => settings.pick((tile.centre - vec2(cam_pos.x, cam_pos.z)).magnitude(), tile.models.len()),
+            false => None,
+        };
+        if lod == tile.lod {
+            continue;
+        }
+
+        let mut entity = commands.entity(entity);
+        match lod {
+            Some(lod) => entity.insert(Visual::new(&tile.models[lod], false)),
+            None => entity.remove::<Visual>(),
+        };
+        tile.lod = lod;
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Vector3, vec3};
+use include_dir::Dir;
+use dreamfield_system::components::Transform;
+use dreamfield_system::world::WorldChunkManager;
+use dreamfield_system::world::world_collision::WorldCollision;
+
+use super::PlayerMovement;
+use super::level::Levels;
+
+/// How far above and below a cell's centre its probe starts and ends
+const PROBE_HEIGHT: f32 = 64.0;
+
+/// The radius of the sphere dropped through each cell to load it
+const PROBE_RADIUS: f32 = 0.25;
+
+/// The size of the pages touched when paging in a level's chunk data
+const PAGE_SIZE: usize = 4096;
+
+/// A cell of the streaming grid, by its x and z index
+type Cell = (i32, i32);
+
+/// The ChunkStreamSettings resource, how far ahead chunks are prefetched and how many are loaded each
+/// frame
+pub struct ChunkStreamSettings {
+    /// The size of the grid cells chunks are prefetched in, in world units
+    pub cell_size: f32,
+    /// How far around the player chunks are prefetched, which should be further than the player's
+    /// entity spawn radius so that entities never spawn onto a world that isn't there yet
+    pub prefetch_radius: f32,
+    /// How many seconds of the player's velocity the prefetch area is moved ahead by
+    pub lookahead_time: f32,
+    /// How many cells are loaded each frame. Each cell's chunks are decoded and uploaded on the main
+    /// thread when its probe touches them, so this is the per-frame budget that keeps that from hitching
+    pub cells_per_frame: usize,
+    /// The most cells that are prefetched in a level, after which chunks are only loaded once they're
+    /// reached. This isn't a memory cap, the WorldChunkManager can't unload chunks, so everything that's
+    /// loaded stays loaded until the next level replaces it
+    pub max_prefetched_cells: usize,
+}
+
+impl Default for ChunkStreamSettings {
+    fn default() -> Self {
+        ChunkStreamSettings {
+            cell_size: 16.0,
+            prefetch_radius: 48.0,
+            lookahead_time: 1.5,
+            cells_per_frame: 2,
+            max_prefetched_cells: 256,
+        }
+    }
+}
+
+/// A request for the streaming thread
+enum StreamRequest {
+    /// Page in a level's chunk data
+    Level(&'static Dir<'static>),
+    /// Work out which cells to prefetch around a position, closest first
+    Prefetch { centre: Vector3<f32>, radius: f32, cell_size: f32 },
+}
+
+/// The ChunkStreamer resource, which prefetches chunks ahead of the player in a per-frame budget.
+/// Chunks are loaded by the WorldChunkManager the first time they're touched, which used to happen
+/// all at once when the player walked into them. The WorldChunkManager only loads chunks that way,
+/// on whichever thread touches it, and has no way to decode a chunk elsewhere or to unload one, so
+/// this doesn't stream asynchronously or evict anything:
+/// * The streaming thread only pages in the level's chunk data, so the reads don't stall the main
+///   thread, and works out which cells are coming up from where the player's heading
+/// * cells_per_frame of those cells are loaded each frame on the main thread, by dropping a probe
+///   through them, so that by the time the player gets there the chunk's already resident
+/// * Prefetching stops once max_prefetched_cells are resident, and everything's dropped when the
+///   next level's loaded. Rebuilding the WorldChunkManager to drop some chunks would drop them all
+///   and hitch far worse than loading them does
+pub struct ChunkStreamer {
+    sender: Option<Mutex<Sender<StreamRequest>>>,
+    receiver: Option<Mutex<Receiver<Vec<Cell>>>>,
+    level: Option<String>,
+    /// The cells waiting to be loaded, in the order they should be loaded in
+    queue: VecDeque<Cell>,
+    /// The cells that have been loaded
+    resident: HashSet<Cell>,
+    /// The cell the last prefetch was requested around
+    last_request: Option<Cell>,
+}
+
+impl Default for ChunkStreamer {
+    fn default() -> Self {
+        let (request_sender, request_receiver) = mpsc::channel();
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        let result = std::thread::Builder::new()
+            .name("chunk_streamer".to_string())
+            .spawn(move || stream_chunks(request_receiver, result_sender));
+
+        let (sender, receiver) = match result {
+            Ok(_) => (Some(Mutex::new(request_sender)), Some(Mutex::new(result_receiver))),
+            Err(err) => {
+                log::error!("Failed to start the chunk streaming thread, chunks will load as they're reached: {}", err);
+                (None, None)
+            }
+        };
+
+        ChunkStreamer {
+            sender,
+            receiver,
+            level: None,
+            queue: VecDeque::new(),
+            resident: HashSet::new(),
+            last_request: None,
+        }
+    }
+}
+
+impl ChunkStreamer {
+    /// Get how many cells are resident
+    pub fn resident_cells(&self) -> usize {
+        self.resident.len()
+    }
+
+    /// Get how many cells are waiting to be loaded
+    pub fn queued_cells(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn send(&self, request: StreamRequest) {
+        if let Some(sender) = &self.sender {
+            if sender.lock().unwrap().send(request).is_err() {
+                log::error!("The chunk streaming thread has stopped");
+            }
+        }
+    }
+
+    /// Forget everything that's resident, for when the WorldChunkManager has been replaced
+    fn reset(&mut self) {
+        self.queue.clear();
+        self.resident.clear();
+        self.last_request = None;
+    }
+}
+
+/// The streaming thread, which answers requests until the streamer is dropped. It never touches the
+/// WorldChunkManager, so it can't decode chunks, only read their data in and pick the cells to load
+fn stream_chunks(requests: Receiver<StreamRequest>, results: Sender<Vec<Cell>>) {
+    while let Ok(request) = requests.recv() {
+        match request {
+            StreamRequest::Level(chunks) => page_in(chunks),
+            StreamRequest::Prefetch { centre, radius, cell_size } => {
+                // Only the latest prefetch matters if the thread's fallen behind
+                let mut latest = (centre, radius, cell_size);
+                let mut levels = Vec::new();
+                for request in requests.try_iter() {
+                    match request {
+                        StreamRequest::Level(chunks) => levels.push(chunks),
+                        StreamRequest::Prefetch { centre, radius, cell_size } => latest = (centre, radius, cell_size),
+                    }
+                }
+                levels.into_iter().for_each(page_in);
+
+                if results.send(cells_around(latest.0, latest.1, latest.2)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Touch every page of a level's chunk data, so the OS reads it in on this thread instead of when
+/// the chunk's first loaded
+fn page_in(chunks: &'static Dir<'static>) {
+    let mut pending = vec![chunks];
+    let mut checksum = 0u8;
+    while let Some(dir) = pending.pop() {
+        pending.extend(dir.dirs());
+        for file in dir.files() {
+            checksum = file.contents().iter().step_by(PAGE_SIZE).fold(checksum, |sum, byte| sum.wrapping_add(*byte));
+        }
+    }
+    std::hint::black_box(checksum);
+}
+
+/// Get the cells within a radius of a position, closest first
+fn cells_around(centre: Vector3<f32>, radius: f32, cell_size: f32) -> Vec<Cell> {
+    let centre_cell = cell_at(centre, cell_size);
+    let range = (radius / cell_size).ceil() as i32;
+
+    let mut cells: Vec<(Cell, i32)> = (-range..=range)
+        .flat_map(|x| (-range..=range).map(move |z| (x, z)))
+        .filter(|(x, z)| ((x * x + z * z) as f32).sqrt() * cell_size <= radius)
+        .map(|(x, z)| ((centre_cell.0 + x, centre_cell.1 + z), x * x + z * z))
+        .collect();
+    cells.sort_by_key(|(_, dist)| *dist);
+    cells.into_iter().map(|(cell, _)| cell).collect()
+}
+
+fn cell_at(pos: Vector3<f32>, cell_size: f32) -> Cell {
+    ((pos.x / cell_size).floor() as i32, (pos.z / cell_size).floor() as i32)
+}
+
+/// Load a cell's chunks by dropping a probe through it
+fn probe_cell(collision: &mut WorldCollision, world: &mut WorldChunkManager, cell: Cell, cell_size: f32, height: f32) {
+    let cbm = vec3(1.0, 1.0, 1.0) / PROBE_RADIUS;
+    let centre = vec3((cell.0 as f32 + 0.5) * cell_size, height, (cell.1 as f32 + 0.5) * cell_size);
+    let start = (centre + vec3(0.0, PROBE_HEIGHT, 0.0)) / PROBE_RADIUS;
+    let drop = vec3(0.0, -2.0 * PROBE_HEIGHT, 0.0) / PROBE_RADIUS;
+    collision.sweep_unit_sphere(world, start, drop, cbm, None);
+}
+
+/// The chunk streaming system, which asks the streaming thread what's coming up around the player,
+/// and loads a few cells of it each frame until the prefetch cap's reached
+pub fn stream_world_chunks(settings: Res<ChunkStreamSettings>, levels: Res<Levels>,
+    mut streamer: ResMut<ChunkStreamer>, mut collision: ResMut<WorldCollision>,
+    mut world: ResMut<WorldChunkManager>, player_query: Query<(&Transform, &PlayerMovement)>)
+{
+    let streamer = &mut *streamer;
+
+    // Loading a level replaces the WorldChunkManager, so nothing's resident any more
+    let level = levels.current().map(str::to_string);
+    if level != streamer.level {
+        streamer.reset();
+        if let Some(level) = level.as_ref().and_then(|level| levels.get(level)) {
+            streamer.send(StreamRequest::Level(level.chunks));
+        }
+        streamer.level = level;
+    }
+
+    let (pos, velocity) = match player_query.get_single() {
+        Ok((transform, player_movement)) => (transform.pos, player_movement.velocity),
+        Err(_) => return,
+    };
+
+    // Ask for the cells around where the player will be whenever they move into another cell
+    let ahead = pos + vec3(velocity.x, 0.0, velocity.z) * settings.lookahead_time;
+    let ahead_cell = cell_at(ahead, settings.cell_size);
+    if streamer.last_request != Some(ahead_cell) {
+        streamer.last_request = Some(ahead_cell);
+        streamer.send(StreamRequest::Prefetch {
+            centre: ahead,
+            radius: settings.prefetch_radius,
+            cell_size: settings.cell_size,
+        });
+    }
+
+    let cells = streamer.receiver.as_ref().and_then(|receiver| receiver.lock().unwrap().try_iter().last());
+    if let Some(cells) = cells {
+        let resident = &streamer.resident;
+        streamer.queue = cells.into_iter().filter(|cell| !resident.contains(cell)).collect();
+    }
+
+    for _ in 0..settings.cells_per_frame {
+        if streamer.resident.len() >= settings.max_prefetched_cells {
+            break;
+        }
+        let cell = match streamer.queue.pop_front() {
+            Some(cell) => cell,
+            None => break,
+        };
+        probe_cell(&mut collision, &mut world, cell, settings.cell_size, pos.y);
+        streamer.resident.insert(cell);
+    }
+}
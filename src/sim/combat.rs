@@ -0,0 +1,152 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventWriter, With, Without};
+use bevy_ecs::system::{Res, Query, Commands};
+use cgmath::{Vector3, Matrix3, Rad, InnerSpace, vec3, Zero};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::PlayerMovement;
+use super::DialogueState;
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::health::{Health, DamageEvent};
+use super::decal::DecalEvent;
+
+/// The held item's offset from the camera when it's at rest
+const HELD_ITEM_OFFSET: Vector3<f32> = vec3(0.3, -0.35, -0.5);
+
+/// How long the held item swing lasts, in seconds
+const SWING_DURATION: f32 = 0.25;
+
+/// How far the held item swings down during an attack, in radians
+const SWING_ANGLE: f32 = 1.2;
+
+/// The rate knockback velocity decays, as a percentage of speed per second
+const KNOCKBACK_DECAY: f32 = 8.0;
+
+/// The speed below which knockback stops
+const MIN_KNOCKBACK_SPEED: f32 = 0.1;
+
+/// The MeleeAttack component, for entities that can attack things in front of them
+#[derive(Component)]
+pub struct MeleeAttack {
+    pub damage: f32,
+    /// How far the attack reaches
+    pub range: f32,
+    /// The cosine of the half angle of the attack arc
+    pub arc_cos: f32,
+    /// The number of seconds between attacks
+    pub cooldown: f32,
+    /// The knockback speed applied to targets
+    pub knockback: f32,
+    cooldown_remaining: f32,
+    /// Seconds since the current swing started
+    swing_time: Option<f32>,
+}
+
+impl Default for MeleeAttack {
+    fn default() -> Self {
+        MeleeAttack {
+            damage: 25.0,
+            range: 1.5,
+            arc_cos: 0.5,
+            cooldown: 0.5,
+            knockback: 4.0,
+            cooldown_remaining: 0.0,
+            swing_time: None,
+        }
+    }
+}
+
+/// The HeldItem component, for the item shown in front of the player's camera
+#[derive(Component)]
+pub struct HeldItem;
+
+/// The Knockback component, which pushes an entity along until its velocity decays
+#[derive(Component)]
+pub struct Knockback {
+    pub velocity: Vector3<f32>,
+}
+
+/// The player melee attack system
+pub fn player_attack(mut commands: Commands, sim_time: Res<SimTime>, input: Res<InputState>,
+    gamepad: Res<GamepadState>, bindings: Res<InputBindings>, dialogue: Res<DialogueState>,
+    mut player_query: Query<(Entity, &Transform, &PlayerMovement, &mut MeleeAttack)>,
+    mut held_items: Query<&mut Transform, (With<HeldItem>, Without<PlayerMovement>)>,
+    targets: Query<(Entity, &Transform), (With<Health>, Without<PlayerMovement>, Without<HeldItem>)>,
+    mut damage_events: EventWriter<DamageEvent>, mut decal_events: EventWriter<DecalEvent>)
+{
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (player_entity, player_transform, player_movement, mut attack) in player_query.iter_mut() {
+        attack.cooldown_remaining = f32::max(attack.cooldown_remaining - time_delta, 0.0);
+
+        // Start an attack
+        let can_attack = attack.cooldown_remaining <= 0.0 && !dialogue.in_dialogue();
+        if can_attack && controls.is_just_pressed(Action::Attack) {
+            attack.cooldown_remaining = attack.cooldown;
+            attack.swing_time = Some(0.0);
+
+            // Mark the wall in front of the player, if the swing reaches one
+            let eye_pos = player_transform.pos + vec3(0.0, player_movement.eye_height(), 0.0);
+            decal_events.send(DecalEvent::new("slash", eye_pos, player_movement.forward(), attack.range));
+
+            // Hit everything in range in front of the player, ignoring height
+            let forward = player_movement.forward();
+            let forward = vec3(forward.x, 0.0, forward.z).normalize();
+            for (target, target_transform) in targets.iter() {
+                let to_target = target_transform.pos - player_transform.pos;
+                let to_target = vec3(to_target.x, 0.0, to_target.z);
+                let distance = to_target.magnitude();
+                if distance > attack.range {
+                    continue;
+                }
+
+                let direction = match distance > 0.0 {
+                    true => to_target / distance,
+                    false => forward,
+                };
+                if direction.dot(forward) < attack.arc_cos {
+                    continue;
+                }
+
+                damage_events.send(DamageEvent { target, amount: attack.damage, source: Some(player_entity) });
+                commands.entity(target).insert(Knockback { velocity: direction * attack.knockback });
+            }
+        }
+
+        // Update the swing
+        let swing_progress = attack.swing_time.map(|t| f32::min(t / SWING_DURATION, 1.0));
+        attack.swing_time = match swing_progress {
+            Some(progress) if progress < 1.0 => attack.swing_time.map(|t| t + time_delta),
+            _ => None,
+        };
+
+        // Put the held item in front of the camera
+        let view_rot = Matrix3::from(player_movement.orientation());
+        let swing = swing_progress.map(|p| f32::sin(p * std::f32::consts::PI)).unwrap_or(0.0);
+        for mut transform in held_items.iter_mut() {
+            let eye_pos = player_transform.pos + vec3(0.0, player_movement.eye_height(), 0.0);
+            transform.pos = eye_pos + view_rot * HELD_ITEM_OFFSET;
+            transform.rot = view_rot * Matrix3::from_angle_x(Rad(-SWING_ANGLE * swing));
+        }
+    }
+}
+
+/// The knockback system
+pub fn apply_knockback(mut commands: Commands, sim_time: Res<SimTime>,
+    mut query: Query<(Entity, &mut Knockback, &mut Transform), Without<PlayerMovement>>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (entity, mut knockback, mut transform) in query.iter_mut() {
+        transform.pos += knockback.velocity * time_delta;
+        knockback.velocity *= f32::max(1.0 - KNOCKBACK_DECAY * time_delta, 0.0);
+
+        if knockback.velocity.magnitude() < MIN_KNOCKBACK_SPEED {
+            knockback.velocity = Vector3::zero();
+            commands.entity(entity).remove::<Knockback>();
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{With, Without};
+use bevy_ecs::system::Query;
+use cgmath::{Vector3, InnerSpace};
+use dreamfield_renderer::components::TextBox;
+use dreamfield_system::components::Transform;
+
+use super::player_movement::PlayerMovement;
+
+/// The number of characters in the compass strip
+pub const COMPASS_WIDTH: usize = 15;
+
+/// The angle covered by each character of the strip, in degrees
+const DEGREES_PER_CHAR: f32 = 6.0;
+
+/// The headings of the labels on the compass, in degrees clockwise from north (-z)
+const COMPASS_LABELS: &'static [(f32, char)] = &[
+    (0.0, 'N'),
+    (45.0, '|'),
+    (90.0, 'E'),
+    (135.0, '|'),
+    (180.0, 'S'),
+    (225.0, '|'),
+    (270.0, 'W'),
+    (315.0, '|'),
+];
+
+/// The spacing of the ticks between labels, in degrees
+const TICK_SPACING: f32 = 15.0;
+
+/// The Compass component, for the TextBox the compass strip is drawn to. It should use a monospace
+/// font variant, so that the columns line up with the headings.
+#[derive(Component)]
+pub struct Compass;
+
+/// The ObjectiveMarker component, for entities that are shown on the compass
+#[derive(Component)]
+pub struct ObjectiveMarker {
+    pub active: bool,
+}
+
+impl Default for ObjectiveMarker {
+    fn default() -> Self {
+        ObjectiveMarker { active: true }
+    }
+}
+
+/// Get the heading of a direction in degrees clockwise from north
+fn heading(dir: Vector3<f32>) -> f32 {
+    f32::atan2(dir.x, -dir.z).to_degrees()
+}
+
+/// Get an angle relative to a heading, from -180 to 180 degrees
+fn relative_angle(angle: f32, heading: f32) -> f32 {
+    (angle - heading + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Get the column of the strip an angle relative to the player's heading is at, if it's on it
+fn column(relative: f32) -> Option<usize> {
+    let column = (relative / DEGREES_PER_CHAR).round() as i32 + (COMPASS_WIDTH / 2) as i32;
+    match column >= 0 && column < COMPASS_WIDTH as i32 {
+        true => Some(column as usize),
+        false => None,
+    }
+}
+
+/// The compass system, which draws the headings around the player's yaw with markers above them
+pub fn update_compass(player_query: Query<(&Transform, &PlayerMovement)>,
+    marker_query: Query<(&Transform, &ObjectiveMarker), Without<PlayerMovement>>,
+    mut query: Query<&mut TextBox, With<Compass>>)
+{
+    let (player_pos, player_heading) = match player_query.get_single() {
+        Ok((transform, movement)) => (transform.pos, -movement.pitch_yaw.y.to_degrees()),
+        Err(_) => return,
+    };
+
+    // Draw the ticks, and then the labels over them
+    let mut strip = [' '; COMPASS_WIDTH];
+    let tick_count = (360.0 / TICK_SPACING) as i32;
+    for tick in 0..tick_count {
+        if let Some(col) = column(relative_angle(tick as f32 * TICK_SPACING, player_heading)) {
+            strip[col] = '.';
+        }
+    }
+    for (angle, label) in COMPASS_LABELS.iter() {
+        if let Some(col) = column(relative_angle(*angle, player_heading)) {
+            strip[col] = *label;
+        }
+    }
+
+    // Draw the markers, clamping ones that are off the strip to its ends
+    let mut markers = [' '; COMPASS_WIDTH];
+    for (transform, marker) in marker_query.iter() {
+        let to_marker = transform.pos - player_pos;
+        if !marker.active || to_marker.magnitude2() < 0.01 {
+            continue;
+        }
+
+        let relative = relative_angle(heading(to_marker), player_heading);
+        match column(relative) {
+            Some(col) => markers[col] = 'v',
+            None if relative < 0.0 => markers[0] = '<',
+            None => markers[COMPASS_WIDTH - 1] = '>',
+        }
+    }
+
+    let text = format!("{}\n{}", markers.iter().collect::<String>(), strip.iter().collect::<String>());
+    for mut text_box in query.iter_mut() {
+        if text_box.text != text {
+            text_box.text = text.clone();
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::With;
+use bevy_ecs::system::ResMut;
+use bevy_ecs::world::World;
+use glfw::ffi;
+
+use super::game_state::GameState;
+use super::photo_mode::PhotoMode;
+use super::rich_text::RichText;
+
+/// How many lines of output the console shows
+const MAX_OUTPUT_LINES: usize = 12;
+
+/// How many commands the console remembers
+const MAX_HISTORY: usize = 32;
+
+/// The glfw key codes of the keys that type characters, which are the characters they type on a US
+/// layout without shift held. The grave accent key isn't here since it opens and closes the console.
+const PRINTABLE_KEYS: &'static [i32] = &[
+    32, 39, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 59, 61,
+    65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90,
+    91, 92, 93,
+];
+
+/// The characters keys type with shift held, on a US layout
+const SHIFTED_CHARS: &'static [(char, char)] = &[
+    ('1', '!'), ('2', '@'), ('3', '#'), ('4', '$'), ('5', '%'), ('6', '^'), ('7', '&'), ('8', '*'),
+    ('9', '('), ('0', ')'), ('-', '_'), ('=', '+'), ('[', '{'), (']', '}'), ('\\', '|'), (';', ':'),
+    ('\'', '"'), (',', '<'), ('.', '>'), ('/', '?'),
+];
+
+/// A console command, which is run with the world and its arguments, and returns the text to print
+/// or an error
+pub type ConsoleCommandFn = fn(&mut World, &[&str]) -> Result<String, String>;
+
+/// A console command's tab completion, which gives the values an argument can have, by its index
+pub type ConsoleCompletionFn = fn(&World, usize) -> Vec<String>;
+
+/// A command registered with the console
+pub struct ConsoleCommand {
+    pub usage: &'static str,
+    pub help: &'static str,
+    run: ConsoleCommandFn,
+    completion: Option<ConsoleCompletionFn>,
+}
+
+impl ConsoleCommand {
+    /// Complete the command's arguments with the values a function gives
+    pub fn with_completion(&mut self, completion: ConsoleCompletionFn) -> &mut Self {
+        self.completion = Some(completion);
+        self
+    }
+}
+
+/// The ConsoleCommands resource, the registry of everything that can be typed into the debug
+/// console. Sim modules register their own commands in sim::init.
+#[derive(Default)]
+pub struct ConsoleCommands {
+    commands: BTreeMap<String, ConsoleCommand>,
+}
+
+impl ConsoleCommands {
+    /// Register a command, replacing any other with the same name
+    pub fn register(&mut self, name: &str, usage: &'static str, help: &'static str, run: ConsoleCommandFn)
+        -> &mut ConsoleCommand
+    {
+        let command = ConsoleCommand { usage, help, run, completion: None };
+        if self.commands.insert(name.to_string(), command).is_some() {
+            log::warn!("Console command {} was registered more than once", name);
+        }
+        self.commands.get_mut(name).unwrap()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ConsoleCommand> {
+        self.commands.get(name)
+    }
+
+    /// Get the names of the commands in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.commands.keys().map(String::as_str)
+    }
+}
+
+/// The keys the console handles, as they're pressed
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ConsoleKey {
+    Toggle,
+    Char(char),
+    Backspace,
+    Enter,
+    Tab,
+    HistoryUp,
+    HistoryDown,
+}
+
+/// The DebugConsole resource, a drop-down console that's opened and closed with ~ while in game.
+/// The engine's input doesn't give typed text, so the keyboard's read straight from glfw. Typing
+/// still presses the engine's inputs too, so the sim is paused and the menus ignore input while
+/// the console's open.
+#[derive(Default)]
+pub struct DebugConsole {
+    pub open: bool,
+    input: String,
+    /// The lines printed to the console, and whether each one's an error
+    output: VecDeque<(String, bool)>,
+    history: Vec<String>,
+    /// How far back through the history the input is from, if it's from the history
+    history_index: Option<usize>,
+    /// The keys pressed since the console last handled them
+    keys: Vec<ConsoleKey>,
+    held_keys: Vec<i32>,
+}
+
+impl DebugConsole {
+    /// Print a line of output to the console
+    pub fn print(&mut self, text: &str) {
+        self.push_output(text, false);
+    }
+
+    /// Print an error to the console
+    pub fn print_error(&mut self, text: &str) {
+        self.push_output(text, true);
+    }
+
+    fn push_output(&mut self, text: &str, error: bool) {
+        for line in text.lines() {
+            log::info!("Console: {}", line);
+            self.output.push_back((line.to_string(), error));
+        }
+        while self.output.len() > MAX_OUTPUT_LINES {
+            self.output.pop_front();
+        }
+    }
+
+    /// Step through the history, in the direction 1 for older or -1 for newer
+    fn step_history(&mut self, dir: i32) {
+        let index = match (self.history_index, dir > 0) {
+            (None, true) => Some(0),
+            (None, false) => None,
+            (Some(index), true) => Some(usize::min(index + 1, self.history.len().saturating_sub(1))),
+            (Some(index), false) => index.checked_sub(1),
+        };
+
+        self.history_index = index.filter(|index| *index < self.history.len());
+        self.input = match self.history_index {
+            Some(index) => self.history[self.history.len() - 1 - index].clone(),
+            None => String::new(),
+        };
+    }
+
+    /// Get the console's text as rich text markup, with the output above the input line
+    fn markup(&self) -> String {
+        let escape = |text: &str| text.replace('{', "{{");
+        let mut markup: String = self.output.iter()
+            .map(|(line, error)| match error {
+                true => format!("{{color=red}}{}{{/color}}\n", escape(line)),
+                false => format!("{}\n", escape(line)),
+            })
+            .collect();
+        markup += &format!("{{color=yellow}}>{{/color}} {}_", escape(&self.input));
+        markup
+    }
+}
+
+/// The ConsoleTextBox component, for the RichText the console is drawn to
+#[derive(Component)]
+pub struct ConsoleTextBox;
+
+/// Get the character a printable key types
+fn key_char(key: i32, shift: bool) -> Option<char> {
+    let c = char::from_u32(key as u32)?;
+    match (c.is_ascii_alphabetic(), shift) {
+        (true, true) => Some(c.to_ascii_uppercase()),
+        (true, false) => Some(c.to_ascii_lowercase()),
+        (false, true) => Some(SHIFTED_CHARS.iter().find(|(key, _)| *key == c).map_or(c, |(_, shifted)| *shifted)),
+        (false, false) => Some(c),
+    }
+}
+
+/// Check whether a key's held, reading it straight from the window's glfw context for keys the
+/// engine's input doesn't have. This only works in the render schedule, where the context's current.
+pub fn is_key_down(key: i32) -> bool {
+    let window = unsafe { ffi::glfwGetCurrentContext() };
+    !window.is_null() && unsafe { ffi::glfwGetKey(window, key) == ffi::PRESS }
+}
+
+/// Check whether a mouse button's held, in the same way as is_key_down
+pub fn is_mouse_button_down(button: i32) -> bool {
+    let window = unsafe { ffi::glfwGetCurrentContext() };
+    !window.is_null() && unsafe { ffi::glfwGetMouseButton(window, button) == ffi::PRESS }
+}
+
+/// The console key system, which reads the keyboard with is_key_down, since the engine's input
+/// doesn't have typed text, and queues the keys pressed since the last frame for the console to
+/// handle
+pub fn poll_console_keys(mut console: ResMut<DebugConsole>) {
+    let shift = is_key_down(ffi::KEY_LEFT_SHIFT) || is_key_down(ffi::KEY_RIGHT_SHIFT);
+
+    // Only the toggle key's read while the console's closed
+    let control_keys = [ffi::KEY_GRAVE_ACCENT, ffi::KEY_BACKSPACE, ffi::KEY_ENTER, ffi::KEY_KP_ENTER, ffi::KEY_TAB,
+        ffi::KEY_UP, ffi::KEY_DOWN];
+    let keys = match console.open {
+        true => &control_keys[..],
+        false => &control_keys[..1],
+    };
+    let printable = match console.open {
+        true => PRINTABLE_KEYS,
+        false => &[],
+    };
+    let down: Vec<i32> = keys.iter().chain(printable).copied().filter(|key| is_key_down(*key)).collect();
+    let pressed: Vec<i32> = down.iter().copied().filter(|key| !console.held_keys.contains(key)).collect();
+
+    for key in pressed {
+        let console_key = match key {
+            ffi::KEY_GRAVE_ACCENT => Some(ConsoleKey::Toggle),
+            ffi::KEY_BACKSPACE => Some(ConsoleKey::Backspace),
+            ffi::KEY_ENTER | ffi::KEY_KP_ENTER => Some(ConsoleKey::Enter),
+            ffi::KEY_TAB => Some(ConsoleKey::Tab),
+            ffi::KEY_UP => Some(ConsoleKey::HistoryUp),
+            ffi::KEY_DOWN => Some(ConsoleKey::HistoryDown),
+            key => key_char(key, shift).map(ConsoleKey::Char),
+        };
+        console.keys.extend(console_key);
+    }
+
+    console.held_keys = down;
+}
+
+/// Run a line typed into the console, printing what the command returns
+pub fn run_console_command(world: &mut World, line: &str) {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (name, args) = match words.split_first() {
+        Some(command) => command,
+        None => return,
+    };
+
+    let run = world.resource::<ConsoleCommands>().get(name).map(|command| command.run);
+    let result = match run {
+        Some(run) => run(world, args),
+        None => Err(format!("Unknown command {}, type help for a list", name)),
+    };
+
+    let mut console = world.resource_mut::<DebugConsole>();
+    match result {
+        Ok(output) => console.print(&output),
+        Err(err) => console.print_error(&err),
+    }
+}
+
+/// Complete the word being typed at the end of the input, with a command name if it's the first
+/// word and an argument from the command's completion otherwise. If there's more than one match the
+/// word's completed as far as they agree, and they're all printed.
+fn complete_input(world: &mut World) {
+    let input = world.resource::<DebugConsole>().input.clone();
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let (word_index, word) = match input.ends_with(' ') || words.is_empty() {
+        true => (words.len(), ""),
+        false => (words.len() - 1, words[words.len() - 1]),
+    };
+
+    let commands = world.resource::<ConsoleCommands>();
+    let candidates: Vec<String> = match word_index {
+        0 => commands.names().map(str::to_string).collect(),
+        _ => match commands.get(words[0]).and_then(|command| command.completion) {
+            Some(completion) => completion(world, word_index - 1),
+            None => Vec::new(),
+        },
+    };
+    let mut matches: Vec<String> = candidates.into_iter().filter(|candidate| candidate.starts_with(word)).collect();
+    matches.sort();
+    matches.dedup();
+
+    let completed = match matches.as_slice() {
+        [] => return,
+        [only] => format!("{} ", only),
+        [first, rest @ ..] => rest.iter().fold(first.clone(), |prefix, candidate| {
+            prefix.chars().zip(candidate.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a).collect()
+        }),
+    };
+
+    let mut console = world.resource_mut::<DebugConsole>();
+    console.input = format!("{}{}", &input[..input.len() - word.len()], completed);
+    if matches.len() > 1 {
+        console.print(&matches.join("  "));
+    }
+}
+
+/// The console system, which handles the keys pressed since the last tick, and shows the console in
+/// its RichText while it's open. It's only opened in game outside of photo mode, and closes when
+/// the game's left.
+pub fn update_console(world: &mut World) {
+    let in_game = *world.resource::<GameState>() == GameState::InGame;
+    let can_open = in_game && !world.resource::<PhotoMode>().active;
+    let keys = std::mem::take(&mut world.resource_mut::<DebugConsole>().keys);
+
+    for key in keys {
+        let mut console = world.resource_mut::<DebugConsole>();
+        match key {
+            ConsoleKey::Toggle => console.open = !console.open && can_open,
+            _ if !console.open => (),
+            ConsoleKey::Char(c) => {
+                console.input.push(c);
+                console.history_index = None;
+            },
+            ConsoleKey::Backspace => {
+                console.input.pop();
+            },
+            ConsoleKey::HistoryUp => console.step_history(1),
+            ConsoleKey::HistoryDown => console.step_history(-1),
+            ConsoleKey::Tab => complete_input(world),
+            ConsoleKey::Enter => {
+                let line = std::mem::take(&mut console.input);
+                console.history_index = None;
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                console.print(&format!("> {}", line));
+                if console.history.last() != Some(&line) {
+                    console.history.push(line.clone());
+                }
+                if console.history.len() > MAX_HISTORY {
+                    console.history.remove(0);
+                }
+                run_console_command(world, &line);
+            },
+        }
+    }
+
+    if !in_game {
+        world.resource_mut::<DebugConsole>().open = false;
+    }
+
+    let console = world.resource::<DebugConsole>();
+    let markup = match console.open {
+        true => console.markup(),
+        false => String::new(),
+    };
+    for mut rich_text in world.query_filtered::<&mut RichText, With<ConsoleTextBox>>().iter_mut(world) {
+        rich_text.set_markup(&markup);
+    }
+}
+
+/// Register the console's own commands
+pub fn register_console_commands(commands: &mut ConsoleCommands) {
+    commands.register("help", "help [command]", "List the commands, or show how to use one", |world, args| {
+        let commands = world.resource::<ConsoleCommands>();
+        match args.first() {
+            Some(name) => commands.get(name)
+                .map(|command| format!("{}: {}", command.usage, command.help))
+                .ok_or_else(|| format!("Unknown command {}", name)),
+            None => Ok(commands.names().collect::<Vec<_>>().join("  ")),
+        }
+    }).with_completion(|world, _| world.resource::<ConsoleCommands>().names().map(str::to_string).collect());
+
+    commands.register("clear", "clear", "Clear the console", |world, _| {
+        world.resource_mut::<DebugConsole>().output.clear();
+        Ok(String::new())
+    });
+}
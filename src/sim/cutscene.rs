@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{EventReader, EventWriter};
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Vector3, Matrix4, Point3, EuclideanSpace, vec3};
+use serde::Deserialize;
+use dreamfield_renderer::components::PlayerCamera;
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::screen_fade::ScreenFade;
+use super::trigger::TriggerEnterEvent;
+
+/// The easing used to get to a camera path keyframe from the previous one
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Apply the easing to a value from 0 to 1
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::EaseInOut
+    }
+}
+
+/// A keyframe in a camera path
+#[derive(Deserialize, Debug)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub position: (f32, f32, f32),
+    pub look_at: (f32, f32, f32),
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// A named marker in a camera path, which fires a CutsceneMarkerEvent when it's reached
+#[derive(Deserialize, Debug)]
+pub struct CutsceneMarker {
+    pub time: f32,
+    pub name: String,
+}
+
+/// A camera path, loaded from a RON file
+#[derive(Deserialize, Debug)]
+pub struct CameraPath {
+    /// The keyframes, in time order
+    pub keyframes: Vec<CameraKeyframe>,
+    #[serde(default)]
+    pub markers: Vec<CutsceneMarker>,
+}
+
+impl CameraPath {
+    /// Get the length of the path in seconds
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|keyframe| keyframe.time).unwrap_or(0.0)
+    }
+
+    /// Get the camera position and look-at point at a given time
+    pub fn sample(&self, time: f32) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let to_vec = |(x, y, z): (f32, f32, f32)| vec3(x, y, z);
+
+        let next_index = self.keyframes.iter().position(|keyframe| keyframe.time > time);
+        match next_index {
+            None => self.keyframes.last().map(|last| (to_vec(last.position), to_vec(last.look_at))),
+            Some(0) => self.keyframes.first().map(|first| (to_vec(first.position), to_vec(first.look_at))),
+            Some(index) => {
+                let (prev, next) = (&self.keyframes[index - 1], &self.keyframes[index]);
+                let t = (time - prev.time) / (next.time - prev.time);
+                let t = next.easing.apply(t);
+
+                let position = to_vec(prev.position) + (to_vec(next.position) - to_vec(prev.position)) * t;
+                let look_at = to_vec(prev.look_at) + (to_vec(next.look_at) - to_vec(prev.look_at)) * t;
+                Some((position, look_at))
+            }
+        }
+    }
+}
+
+/// The cutscene manager, which stores all the camera paths by name
+pub struct CutsceneManager {
+    paths: HashMap<String, CameraPath>,
+}
+
+impl CutsceneManager {
+    /// Create a new cutscene manager from a list of (name, ron source) pairs
+    pub fn new(paths: Vec<(&str, &str)>) -> Self {
+        let paths = paths.into_iter()
+            .filter_map(|(name, source)| {
+                match ron::from_str::<CameraPath>(source) {
+                    Ok(path) => Some((name.to_string(), path)),
+                    Err(err) => {
+                        log::error!("Failed to load camera path {}: {}", name, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        CutsceneManager { paths }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CameraPath> {
+        self.paths.get(name)
+    }
+}
+
+/// The cutscene that's currently playing
+pub struct ActiveCutscene {
+    pub name: String,
+    pub time: f32,
+}
+
+/// The CutsceneState resource
+#[derive(Default)]
+pub struct CutsceneState {
+    pub active: Option<ActiveCutscene>,
+}
+
+impl CutsceneState {
+    /// Start playing a cutscene
+    pub fn play(&mut self, name: &str) {
+        self.active = Some(ActiveCutscene { name: name.to_string(), time: 0.0 });
+    }
+
+    /// Get whether a cutscene is playing, in which case the player can't move
+    pub fn in_cutscene(&self) -> bool {
+        self.active.is_some()
+    }
+}
+
+/// The CutsceneTrigger component, which plays a cutscene the first time the player enters the
+/// entity's TriggerVolume
+#[derive(Component)]
+pub struct CutsceneTrigger {
+    pub cutscene: String,
+    pub triggered: bool,
+}
+
+impl CutsceneTrigger {
+    pub fn new(cutscene: &str) -> Self {
+        CutsceneTrigger { cutscene: cutscene.to_string(), triggered: false }
+    }
+}
+
+/// An event emitted when a cutscene reaches one of its markers
+pub struct CutsceneMarkerEvent {
+    pub cutscene: String,
+    pub marker: String,
+}
+
+/// An event emitted when a cutscene finishes or is skipped
+pub struct CutsceneFinishedEvent {
+    pub cutscene: String,
+    pub skipped: bool,
+}
+
+/// The cutscene trigger system
+pub fn trigger_cutscenes(mut cutscene: ResMut<CutsceneState>, mut events: EventReader<TriggerEnterEvent>,
+    mut triggers: Query<&mut CutsceneTrigger>)
+{
+    for event in events.iter() {
+        if let Ok(mut trigger) = triggers.get_mut(event.trigger) {
+            if !trigger.triggered {
+                trigger.triggered = true;
+                cutscene.play(&trigger.cutscene);
+            }
+        }
+    }
+}
+
+/// The cutscene system, which moves the camera along the active camera path, with letterbox bars
+/// shown while it plays. It runs after the player update so that it can take over the camera.
+pub fn update_cutscene(sim_time: Res<SimTime>, input: Res<InputState>, gamepad: Res<GamepadState>,
+    bindings: Res<InputBindings>, manager: Res<CutsceneManager>, mut cutscene: ResMut<CutsceneState>,
+    mut screen_fade: ResMut<ScreenFade>, mut cam_query: Query<&mut PlayerCamera>, mut marker_events: EventWriter<CutsceneMarkerEvent>,
+    mut finished_events: EventWriter<CutsceneFinishedEvent>)
+{
+    let active = match cutscene.active.as_mut() {
+        Some(active) => active,
+        None => return,
+    };
+
+    let path = match manager.get(&active.name) {
+        Some(path) => path,
+        None => {
+            log::warn!("Asked to play unknown cutscene: {}", active.name);
+            cutscene.active = None;
+            return;
+        }
+    };
+
+    if !screen_fade.letterbox() {
+        screen_fade.set_letterbox(true);
+    }
+
+    // Advance time, firing any markers we pass
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    let skipped = controls.is_just_pressed(Action::SkipCutscene);
+
+    let prev_time = active.time;
+    active.time = match skipped {
+        true => path.duration(),
+        false => active.time + sim_time.sim_time_delta as f32,
+    };
+
+    for marker in path.markers.iter().filter(|marker| marker.time > prev_time && marker.time <= active.time) {
+        marker_events.send(CutsceneMarkerEvent { cutscene: active.name.clone(), marker: marker.name.clone() });
+    }
+
+    // Move the camera
+    if let Some((position, look_at)) = path.sample(active.time) {
+        let view = Matrix4::look_at_rh(Point3::from_vec(position), Point3::from_vec(look_at), vec3(0.0, 1.0, 0.0));
+        for mut cam in cam_query.iter_mut() {
+            cam.view = view;
+        }
+    }
+
+    if active.time >= path.duration() {
+        finished_events.send(CutsceneFinishedEvent { cutscene: active.name.clone(), skipped });
+        cutscene.active = None;
+        screen_fade.set_letterbox(false);
+    }
+}
@@ -0,0 +1,122 @@
+use bevy_ecs::prelude::{EventReader, With};
+use bevy_ecs::system::{Res, ResMut, Query, Local};
+use cgmath::{Vector3, vec3};
+use dreamfield_system::resources::SimTime;
+
+use super::PlayerMovement;
+use super::health::{Health, DamageEvent};
+use super::uniform_buffer::{UniformBlock, UniformBuffer, DAMAGE_PARAMS_BINDING};
+
+/// The DamageFeedback resource, for the red flash when the player's hurt and the pulsing vignette
+/// when they're low on health. They're drawn over the scene by the damage_feedback ScreenEffect,
+/// which gets them from a uniform buffer.
+pub struct DamageFeedback {
+    pub flash_color: Vector3<f32>,
+    /// How strong the flash is from the heaviest hits, from 0 to 1
+    pub flash_strength: f32,
+    /// How long the flash takes to fade away, in seconds
+    pub flash_time: f32,
+    pub vignette_color: Vector3<f32>,
+    /// The fraction of max health below which the vignette shows
+    pub low_health: f32,
+    /// How many times a second the vignette pulses, which gets faster the lower the health is
+    pub pulse_rate: f32,
+    /// How strong the flash is right now
+    flash: f32,
+    /// How strong the vignette is right now
+    vignette: f32,
+    /// How far through its pulse the vignette is, in radians
+    pulse: f32,
+}
+
+impl Default for DamageFeedback {
+    fn default() -> Self {
+        DamageFeedback {
+            flash_color: vec3(0.8, 0.05, 0.05),
+            flash_strength: 0.5,
+            flash_time: 0.3,
+            vignette_color: vec3(0.4, 0.0, 0.0),
+            low_health: 0.3,
+            pulse_rate: 1.0,
+            flash: 0.0,
+            vignette: 0.0,
+            pulse: 0.0,
+        }
+    }
+}
+
+/// The damage feedback system, which flashes the screen when the player takes damage, and pulses
+/// the vignette while their health is low. It runs after the damage is applied, so the vignette's
+/// from the player's health this tick.
+pub fn update_damage_feedback(sim_time: Res<SimTime>, mut feedback: ResMut<DamageFeedback>,
+    mut damage_events: EventReader<DamageEvent>, player_query: Query<&Health, With<PlayerMovement>>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    // Harder hits flash brighter, with a minimum so that every hit can be seen
+    for event in damage_events.iter() {
+        if let Ok(health) = player_query.get(event.target) {
+            let fraction = match health.max > 0.0 {
+                true => event.amount / health.max,
+                false => 1.0,
+            };
+            let flash = f32::clamp(fraction * 4.0, 0.4, 1.0);
+            feedback.flash = f32::max(feedback.flash, flash);
+        }
+    }
+
+    if feedback.flash > 0.0 {
+        feedback.flash = match feedback.flash_time > 0.0 {
+            true => f32::max(feedback.flash - time_delta / feedback.flash_time, 0.0),
+            false => 0.0,
+        };
+    }
+
+    let health = player_query.iter().next()
+        .filter(|health| health.max > 0.0 && !health.is_dead())
+        .map(|health| health.current / health.max);
+    let danger = match health {
+        Some(health) if feedback.low_health > 0.0 && health < feedback.low_health => 1.0 - health / feedback.low_health,
+        _ => 0.0,
+    };
+
+    if danger > 0.0 {
+        let rate = feedback.pulse_rate * (1.0 + danger);
+        feedback.pulse = (feedback.pulse + time_delta * rate * 2.0 * std::f32::consts::PI) % (2.0 * std::f32::consts::PI);
+        feedback.vignette = (0.4 + 0.6 * danger) * (0.75 + 0.25 * feedback.pulse.sin());
+    }
+    else if feedback.vignette > 0.0 {
+        feedback.vignette = 0.0;
+        feedback.pulse = 0.0;
+    }
+}
+
+/// The DamageParams uniform block, matching the std140 layout in damage_params.glsl
+#[repr(C)]
+pub struct DamageParams {
+    flash_color: [f32; 4],
+    vignette_color: [f32; 4],
+    vignette_size: f32,
+    _padding: [f32; 3],
+}
+
+impl UniformBlock for DamageParams {
+    const BINDING: u32 = DAMAGE_PARAMS_BINDING;
+}
+
+/// The damage params system, which uploads the damage feedback to its uniform buffer when it
+/// changes. It runs in the render schedule, since it needs the GL context.
+pub fn upload_damage_params(feedback: Res<DamageFeedback>, mut buffer: Local<UniformBuffer<DamageParams>>) {
+    if buffer.is_uploaded() && !feedback.is_changed() {
+        return;
+    }
+
+    let (flash, vignette) = (feedback.flash_color, feedback.vignette_color);
+    let params = DamageParams {
+        flash_color: [flash.x, flash.y, flash.z, f32::clamp(feedback.flash * feedback.flash_strength, 0.0, 1.0)],
+        vignette_color: [vignette.x, vignette.y, vignette.z, f32::clamp(feedback.vignette, 0.0, 1.0)],
+        vignette_size: 0.3 + 0.3 * f32::clamp(feedback.vignette, 0.0, 1.0),
+        _padding: [0.0; 3],
+    };
+    buffer.upload(&params);
+}
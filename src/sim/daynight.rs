@@ -0,0 +1,111 @@
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Vector2, Vector3, vec2, vec3, InnerSpace};
+use dreamfield_renderer::components::PlayerCamera;
+use dreamfield_system::resources::SimTime;
+
+/// The default length of a full day in seconds
+pub const DEFAULT_DAY_LENGTH: f32 = 600.0;
+
+/// The default hour the game starts at
+pub const DEFAULT_START_HOUR: f32 = 9.0;
+
+/// The number of hours in a day
+const HOURS_PER_DAY: f32 = 24.0;
+
+/// The fog color keyframes over the course of a day, as (hour, color). The sky's horizon color
+/// follows it, so the sky fades smoothly into the fog.
+const FOG_COLOR_KEYFRAMES: &'static [(f32, Vector3<f32>)] = &[
+    (0.0, vec3(0.0, 0.0, 0.0)),
+    (5.0, vec3(0.0, 0.0, 0.02)),
+    (6.5, vec3(0.45, 0.25, 0.15)),
+    (8.0, vec3(0.35, 0.4, 0.5)),
+    (17.0, vec3(0.35, 0.4, 0.5)),
+    (18.5, vec3(0.5, 0.2, 0.1)),
+    (20.0, vec3(0.0, 0.0, 0.02)),
+    (24.0, vec3(0.0, 0.0, 0.0)),
+];
+
+/// How far the sun's path across the sky is tilted away from passing straight overhead
+const SUN_TILT: f32 = 0.3;
+
+/// Interpolate between color keyframes over the course of a day, as (hour, color)
+pub fn sample_color_keyframes(keyframes: &[(f32, Vector3<f32>)], hour: f32) -> Vector3<f32> {
+    for (a, b) in keyframes.iter().zip(keyframes.iter().skip(1)) {
+        let ((hour_a, color_a), (hour_b, color_b)) = (a, b);
+        if hour >= *hour_a && hour <= *hour_b {
+            let t = (hour - hour_a) / (hour_b - hour_a);
+            return color_a + (color_b - color_a) * t;
+        }
+    }
+
+    keyframes[0].1
+}
+
+/// The TimeOfDay resource
+pub struct TimeOfDay {
+    /// The current time of day in hours, from 0 to 24
+    pub hour: f32,
+    /// The length of a full day in seconds
+    pub day_length: f32,
+    /// The fog range at midday
+    pub day_fog_range: Vector2<f32>,
+    /// The fog range at midnight
+    pub night_fog_range: Vector2<f32>,
+}
+
+impl TimeOfDay {
+    pub fn new(hour: f32, day_length: f32) -> Self {
+        TimeOfDay {
+            hour,
+            day_length,
+            day_fog_range: vec2(25.0, 30.0),
+            night_fog_range: vec2(10.0, 20.0),
+        }
+    }
+
+    /// Get how much daylight there is, from 0 at midnight to 1 at midday
+    pub fn daylight(&self) -> f32 {
+        let angle = (self.hour / HOURS_PER_DAY) * 2.0 * std::f32::consts::PI;
+        0.5 - 0.5 * angle.cos()
+    }
+
+    /// Get the fog color for the current time of day
+    pub fn fog_color(&self) -> Vector3<f32> {
+        sample_color_keyframes(FOG_COLOR_KEYFRAMES, self.hour)
+    }
+
+    /// Get the direction towards the sun, which rises in the east at 6 and sets in the west at 18,
+    /// and is below the horizon at night
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = (self.hour - 6.0) / HOURS_PER_DAY * 2.0 * std::f32::consts::PI;
+        vec3(angle.cos(), angle.sin(), SUN_TILT).normalize()
+    }
+
+    /// Get the fog range for the current time of day
+    pub fn fog_range(&self) -> Vector2<f32> {
+        let daylight = self.daylight();
+        self.night_fog_range + (self.day_fog_range - self.night_fog_range) * daylight
+    }
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        TimeOfDay::new(DEFAULT_START_HOUR, DEFAULT_DAY_LENGTH)
+    }
+}
+
+/// The day/night cycle system
+pub fn update_time_of_day(sim_time: Res<SimTime>, mut time_of_day: ResMut<TimeOfDay>,
+    mut query: Query<&mut PlayerCamera>)
+{
+    if time_of_day.day_length > 0.0 {
+        let hours_per_second = HOURS_PER_DAY / time_of_day.day_length;
+        let new_hour = time_of_day.hour + sim_time.sim_time_delta as f32 * hours_per_second;
+        time_of_day.hour = new_hour.rem_euclid(HOURS_PER_DAY);
+    }
+
+    for mut cam in query.iter_mut() {
+        cam.fog_color = time_of_day.fog_color();
+        cam.fog_range = time_of_day.fog_range();
+    }
+}
@@ -0,0 +1,381 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Commands, Entity, With};
+use bevy_ecs::system::{Local, Res, ResMut, Query};
+use cgmath::{Vector3, Vector4, Matrix3, Matrix4, vec2, vec3, vec4, InnerSpace};
+use dreamfield_renderer::components::{PlayerCamera, TextBox};
+use dreamfield_system::components::Transform;
+
+use super::behavior::BehaviorTree;
+use super::console::ConsoleCommands;
+use super::enemy::Enemy;
+use super::locale::Locale;
+use super::nav::NavAgent;
+use super::trigger::{TriggerVolume, TriggerShape};
+use super::ui::UI_CELL_SIZE;
+use super::ui_layout::{UiLayout, UiAnchor, UI_HEIGHT};
+
+/// The debug draw shader, which is compiled here rather than by the shader manager since the lines
+/// are drawn straight to the screen after the renderer's done
+const DEBUG_SHADER: &'static str = include_str!("../../resources/shaders/debug_draw.glsl");
+
+/// How many segments circles and the rings of spheres are drawn with
+const CIRCLE_SEGMENTS: usize = 16;
+
+/// How far above an agent its AI state is shown
+const AI_TEXT_HEIGHT: f32 = 2.2;
+
+/// The colors the built in layers are drawn in
+const TRIGGER_COLOR: Vector4<f32> = vec4(0.2, 1.0, 0.4, 1.0);
+const TRIGGER_INSIDE_COLOR: Vector4<f32> = vec4(1.0, 0.9, 0.2, 1.0);
+const NAV_PATH_COLOR: Vector4<f32> = vec4(0.3, 0.6, 1.0, 1.0);
+const SIGHT_COLOR: Vector4<f32> = vec4(1.0, 0.3, 0.3, 0.6);
+
+/// The layers of built in debug drawing, which are toggled with the debug_draw console command
+#[derive(Default, Clone, Debug)]
+pub struct DebugDrawLayers {
+    /// Trigger volumes, in yellow while the player's inside them
+    pub triggers: bool,
+    /// The paths nav agents are following
+    pub nav: bool,
+    /// Each agent's behavior tree and what's on its blackboard, and enemies' sight ranges
+    pub ai: bool,
+}
+
+impl DebugDrawLayers {
+    const NAMES: &'static [&'static str] = &["triggers", "nav", "ai"];
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "triggers" => Some(&mut self.triggers),
+            "nav" => Some(&mut self.nav),
+            "ai" => Some(&mut self.ai),
+            _ => None,
+        }
+    }
+}
+
+/// A vertex of a debug line, as it's uploaded to the vertex buffer
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DebugVertex {
+    pos: [f32; 3],
+    color: [f32; 4],
+}
+
+/// The DebugDraw resource, for drawing lines and text in the world to see what's going on. It's
+/// immediate mode: anything can add primitives during a tick and they're drawn over the scene every
+/// frame until the next tick starts, when they're cleared. Things drawn by sim systems go away
+/// while the sim's paused, but the built in layers are drawn by a ui system so they stay.
+#[derive(Default)]
+pub struct DebugDraw {
+    pub layers: DebugDrawLayers,
+    vertices: Vec<DebugVertex>,
+    texts: Vec<(Vector3<f32>, String)>,
+}
+
+impl DebugDraw {
+    pub fn line(&mut self, start: Vector3<f32>, end: Vector3<f32>, color: Vector4<f32>) {
+        let color = color.into();
+        self.vertices.push(DebugVertex { pos: start.into(), color });
+        self.vertices.push(DebugVertex { pos: end.into(), color });
+    }
+
+    /// Draw a line through each of the points in turn
+    pub fn path(&mut self, points: &[Vector3<f32>], color: Vector4<f32>) {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], color);
+        }
+    }
+
+    /// Draw the edges of an axis aligned box
+    pub fn wire_box(&mut self, min: Vector3<f32>, max: Vector3<f32>, color: Vector4<f32>) {
+        let corner = |i: usize| vec3(
+            match i & 1 { 0 => min.x, _ => max.x },
+            match i & 2 { 0 => min.y, _ => max.y },
+            match i & 4 { 0 => min.z, _ => max.z },
+        );
+
+        // Each edge joins two corners that differ in one axis
+        for i in 0..8 {
+            for axis in [1, 2, 4] {
+                if i & axis == 0 {
+                    self.line(corner(i), corner(i | axis), color);
+                }
+            }
+        }
+    }
+
+    /// Draw a circle around a center, in the plane of two perpendicular unit axes
+    pub fn circle(&mut self, center: Vector3<f32>, axes: (Vector3<f32>, Vector3<f32>), radius: f32, color: Vector4<f32>) {
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * std::f32::consts::PI;
+            center + (axes.0 * angle.cos() + axes.1 * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Draw a sphere as a ring around each axis
+    pub fn sphere(&mut self, center: Vector3<f32>, radius: f32, color: Vector4<f32>) {
+        let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+        self.circle(center, (x, z), radius, color);
+        self.circle(center, (x, y), radius, color);
+        self.circle(center, (y, z), radius, color);
+    }
+
+    /// Draw the axes of a rotation at a position, in red, green and blue for x, y and z
+    pub fn axes(&mut self, pos: Vector3<f32>, rot: Matrix3<f32>, size: f32) {
+        self.line(pos, pos + rot.x.normalize() * size, vec4(1.0, 0.0, 0.0, 1.0));
+        self.line(pos, pos + rot.y.normalize() * size, vec4(0.0, 1.0, 0.0, 1.0));
+        self.line(pos, pos + rot.z.normalize() * size, vec4(0.0, 0.0, 1.0, 1.0));
+    }
+
+    /// Show some text centered on a point in the world
+    pub fn text(&mut self, pos: Vector3<f32>, text: &str) {
+        self.texts.push((pos, text.to_string()));
+    }
+
+    fn clear(&mut self) {
+        self.vertices.clear();
+        self.texts.clear();
+    }
+}
+
+/// The DebugTextBox component, for the text boxes debug text is shown in, which are reused from
+/// tick to tick
+#[derive(Component)]
+pub struct DebugTextBox;
+
+/// The debug draw clearing system, which throws away the last tick's primitives as a new one starts
+pub fn clear_debug_draw(mut debug_draw: ResMut<DebugDraw>) {
+    debug_draw.clear();
+}
+
+/// The debug layer system, which draws the built in layers that are turned on
+pub fn draw_debug_layers(mut debug_draw: ResMut<DebugDraw>,
+    trigger_query: Query<(&TriggerVolume, &Transform)>,
+    nav_query: Query<(&NavAgent, &Transform)>,
+    ai_query: Query<(&BehaviorTree, &Transform, Option<&Enemy>)>)
+{
+    let layers = debug_draw.layers.clone();
+
+    for (trigger, transform) in trigger_query.iter().filter(|_| layers.triggers) {
+        let color = match trigger.player_inside() {
+            true => TRIGGER_INSIDE_COLOR,
+            false => TRIGGER_COLOR,
+        };
+        match trigger.shape {
+            TriggerShape::Aabb(min, max) => debug_draw.wire_box(transform.pos + min, transform.pos + max, color),
+            TriggerShape::Sphere(radius) => debug_draw.sphere(transform.pos, radius, color),
+        }
+    }
+
+    for (agent, transform) in nav_query.iter().filter(|_| layers.nav) {
+        if let Some(first) = agent.path().first() {
+            debug_draw.line(transform.pos, *first, NAV_PATH_COLOR);
+            debug_draw.path(agent.path(), NAV_PATH_COLOR);
+        }
+    }
+
+    for (tree, transform, enemy) in ai_query.iter().filter(|_| layers.ai) {
+        let mut keys: Vec<&str> = tree.blackboard.keys().collect();
+        keys.sort();
+        let text = match keys.is_empty() {
+            true => tree.tree.clone(),
+            false => format!("{}\n{}", tree.tree, keys.join(" ")),
+        };
+        debug_draw.text(transform.pos + vec3(0.0, AI_TEXT_HEIGHT, 0.0), &text);
+
+        if let Some(enemy) = enemy {
+            debug_draw.circle(transform.pos, (Vector3::unit_x(), Vector3::unit_z()), enemy.sight_range, SIGHT_COLOR);
+        }
+    }
+}
+
+/// Project a point in the world to UI space, or None if it's behind the camera
+fn project_to_ui(cam: &PlayerCamera, pos: Vector3<f32>) -> Option<(f32, f32)> {
+    let clip = cam.proj * cam.view * pos.extend(1.0);
+    match clip.w > 0.0 {
+        true => {
+            let ndc = clip.truncate() / clip.w;
+            let ui_width = UI_HEIGHT * cam.render_aspect;
+            Some(((ndc.x * 0.5 + 0.5) * ui_width, (0.5 - ndc.y * 0.5) * UI_HEIGHT))
+        },
+        false => None,
+    }
+}
+
+/// The debug text system, which puts the tick's debug text in text boxes over where it is in the
+/// world, spawning more text boxes when there aren't enough
+pub fn update_debug_text(mut commands: Commands, debug_draw: Res<DebugDraw>, locale: Res<Locale>,
+    cam_query: Query<&PlayerCamera>,
+    mut text_query: Query<(Entity, &mut TextBox, &mut UiLayout), With<DebugTextBox>>)
+{
+    let cam = cam_query.iter().next();
+    let mut texts = debug_draw.texts.iter()
+        .filter_map(|(pos, text)| cam.and_then(|cam| project_to_ui(cam, *pos)).map(|pos| (pos, text)));
+
+    for (_, mut text_box, mut layout) in text_query.iter_mut() {
+        match texts.next() {
+            Some(((x, y), text)) => {
+                let cols = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+                let rows = text.lines().count();
+                let size = vec2(cols as f32, rows as f32) * UI_CELL_SIZE;
+                *layout = UiLayout::anchored(UiAnchor::TopLeft, vec2(x - size.x * 0.5, y - size.y), size);
+                text_box.text = text.clone();
+            },
+            None => match text_box.text.is_empty() {
+                true => {},
+                false => text_box.text.clear(),
+            },
+        }
+    }
+
+    // The new ones are filled in next tick
+    for _ in texts {
+        commands.spawn()
+            .insert(DebugTextBox)
+            .insert(TextBox::new("text", locale.font("medieval"), "Vx8", "", None, None))
+            .insert(UiLayout::anchored(UiAnchor::TopLeft, vec2(0.0, 0.0), vec2(0.0, 0.0)));
+    }
+}
+
+/// The GL objects debug lines are drawn with
+struct DebugDrawGl {
+    program: u32,
+    view_proj_location: i32,
+    vao: u32,
+    vbo: u32,
+}
+
+/// Compile one stage of the debug shader, with the define that picks it
+unsafe fn compile_debug_shader(kind: u32, define: &str) -> Option<u32> {
+    let (version, body) = DEBUG_SHADER.split_once('\n').unwrap_or(("", DEBUG_SHADER));
+    let source = std::ffi::CString::new(format!("{}\n#define {}\n{}", version, define, body)).ok()?;
+
+    let shader = gl::CreateShader(kind);
+    gl::ShaderSource(shader, 1, &source.as_ptr(), std::ptr::null());
+    gl::CompileShader(shader);
+
+    let mut status = 0;
+    gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
+    match status == gl::TRUE as i32 {
+        true => Some(shader),
+        false => {
+            let mut log = vec![0u8; 1024];
+            let mut length = 0;
+            gl::GetShaderInfoLog(shader, log.len() as i32, &mut length, log.as_mut_ptr() as *mut _);
+            log::error!("Failed to compile debug draw shader: {}", String::from_utf8_lossy(&log[..length as usize]));
+            gl::DeleteShader(shader);
+            None
+        }
+    }
+}
+
+impl DebugDrawGl {
+    unsafe fn new() -> Option<Self> {
+        let vertex_shader = compile_debug_shader(gl::VERTEX_SHADER, "BUILDING_VERTEX_SHADER")?;
+        let fragment_shader = compile_debug_shader(gl::FRAGMENT_SHADER, "BUILDING_FRAGMENT_SHADER")?;
+
+        let program = gl::CreateProgram();
+        gl::AttachShader(program, vertex_shader);
+        gl::AttachShader(program, fragment_shader);
+        gl::LinkProgram(program);
+        gl::DeleteShader(vertex_shader);
+        gl::DeleteShader(fragment_shader);
+
+        let mut status = 0;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+        if status != gl::TRUE as i32 {
+            log::error!("Failed to link debug draw shader");
+            gl::DeleteProgram(program);
+            return None;
+        }
+
+        let view_proj_location = gl::GetUniformLocation(program, "debug_view_proj\0".as_ptr() as *const _);
+
+        let (mut vao, mut vbo) = (0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        let stride = std::mem::size_of::<DebugVertex>() as i32;
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, (3 * std::mem::size_of::<f32>()) as *const _);
+        gl::BindVertexArray(0);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+        Some(DebugDrawGl { program, view_proj_location, vao, vbo })
+    }
+}
+
+/// The debug draw system, which draws the debug lines over the finished frame. It runs in the
+/// render schedule for the GL context, and there's no depth buffer by then, so lines show through
+/// walls.
+pub fn render_debug_draw(debug_draw: Res<DebugDraw>, cam_query: Query<&PlayerCamera>,
+    mut gl_state: Local<Option<Option<DebugDrawGl>>>)
+{
+    if debug_draw.vertices.is_empty() {
+        return;
+    }
+
+    let cam = match cam_query.iter().next() {
+        Some(cam) => cam,
+        None => return,
+    };
+
+    // If the shader fails to compile it's not tried again
+    let gl_state = match gl_state.get_or_insert_with(|| unsafe { DebugDrawGl::new() }) {
+        Some(gl_state) => gl_state,
+        None => return,
+    };
+
+    let view_proj: Matrix4<f32> = cam.proj * cam.view;
+    let view_proj: &[f32; 16] = view_proj.as_ref();
+
+    unsafe {
+        let depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+        let blend = gl::IsEnabled(gl::BLEND) == gl::TRUE;
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+        gl::UseProgram(gl_state.program);
+        gl::UniformMatrix4fv(gl_state.view_proj_location, 1, gl::FALSE, view_proj.as_ptr());
+
+        gl::BindVertexArray(gl_state.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, gl_state.vbo);
+        gl::BufferData(gl::ARRAY_BUFFER, (debug_draw.vertices.len() * std::mem::size_of::<DebugVertex>()) as isize,
+            debug_draw.vertices.as_ptr() as *const _, gl::STREAM_DRAW);
+        gl::DrawArrays(gl::LINES, 0, debug_draw.vertices.len() as i32);
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+        gl::UseProgram(0);
+        match depth_test {
+            true => gl::Enable(gl::DEPTH_TEST),
+            false => {},
+        }
+        match blend {
+            true => {},
+            false => gl::Disable(gl::BLEND),
+        }
+    }
+}
+
+/// Register the debug_draw console command
+pub fn register_debug_draw_command(commands: &mut ConsoleCommands) {
+    commands.register("debug_draw", "debug_draw <layer>", "Toggle a layer of debug drawing", |world, args| {
+        let name = args.first().ok_or_else(|| format!("Which layer? ({})", DebugDrawLayers::NAMES.join(", ")))?;
+        let mut debug_draw = world.resource_mut::<DebugDraw>();
+        let layer = debug_draw.layers.get_mut(name).ok_or_else(|| format!("There's no layer called {}", name))?;
+        *layer = !*layer;
+        Ok(format!("{} {}", name, match *layer { true => "on", false => "off" }))
+    }).with_completion(|_, arg| match arg {
+        0 => DebugDrawLayers::NAMES.iter().map(|name| name.to_string()).collect(),
+        _ => Vec::new(),
+    });
+}
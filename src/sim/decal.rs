@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Commands, Entity, EventReader};
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Matrix3, Vector3, vec3, vec4, InnerSpace};
+use dreamfield_renderer::components::Visual;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::determinism::SimRng;
+use super::head_bob::FootstepEvent;
+use super::level::Levels;
+use super::player_movement::PlayerMovement;
+use super::projectile::ProjectileImpactEvent;
+use super::static_collision::StaticCollision;
+use super::tint::Tint;
+use super::weather::Weather;
+
+/// How far decals are lifted off the surface they're on, so they don't z-fight with it
+const SURFACE_OFFSET: f32 = 0.02;
+
+/// How hard it has to be raining for the ground to be muddy enough to leave footprints
+const FOOTPRINT_MIN_RAIN: f32 = 0.3;
+
+/// How far to either side of a character's position each foot's footprints are
+const FOOTPRINT_SPACING: f32 = 0.12;
+
+/// An event to put a decal on the first surface along a ray
+#[derive(Clone)]
+pub struct DecalEvent {
+    /// The name of the decal, which is drawn with the decal_<name> model build.rs generates
+    pub decal: String,
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    /// How far along the ray the surface can be
+    pub reach: f32,
+    /// The width and depth of the decal in world units
+    pub size: f32,
+    /// The direction the top of the decal points in along the surface, or None to point it any way
+    pub forward: Option<Vector3<f32>>,
+}
+
+impl DecalEvent {
+    pub fn new(decal: &str, origin: Vector3<f32>, direction: Vector3<f32>, reach: f32) -> Self {
+        DecalEvent {
+            decal: decal.to_string(),
+            origin,
+            direction,
+            reach,
+            size: 0.5,
+            forward: None,
+        }
+    }
+}
+
+/// The Decal component, for a mark on the world's surface
+#[derive(Component)]
+pub struct Decal {
+    /// The level the decal's in, since decals aren't part of a level's scene
+    level: Option<String>,
+    age: f32,
+}
+
+/// The Decals resource, with the decals that are in the world from the oldest to the newest. Once
+/// there are too many, the oldest ones are despawned to make room.
+pub struct Decals {
+    entities: VecDeque<Entity>,
+    pub max_decals: usize,
+    /// How many seconds decals last for
+    pub lifetime: f32,
+    /// How many seconds of their lifetime decals spend fading away
+    pub fade_time: f32,
+}
+
+impl Default for Decals {
+    fn default() -> Self {
+        Decals {
+            entities: VecDeque::new(),
+            max_decals: 64,
+            lifetime: 30.0,
+            fade_time: 3.0,
+        }
+    }
+}
+
+/// Get the rotation that lays a decal model flat on a surface, with the top of its texture pointing
+/// along the forward direction as much as the surface allows
+pub fn surface_rotation(normal: Vector3<f32>, forward: Vector3<f32>) -> Matrix3<f32> {
+    let forward = forward - normal * forward.dot(normal);
+    let forward = match forward.magnitude2() > 1e-6 {
+        true => forward.normalize(),
+        false => match normal.y.abs() < 0.99 {
+            true => vec3(0.0, 1.0, 0.0).cross(normal).normalize(),
+            false => vec3(0.0, 0.0, -1.0),
+        },
+    };
+    Matrix3::from_cols(normal.cross(-forward), normal, -forward)
+}
+
+/// The decal spawning system, which puts decals on the world for DecalEvents, scorch marks where
+/// projectiles hit the world, and footprints where characters step while the ground's wet
+pub fn spawn_decals(mut commands: Commands, mut decals: ResMut<Decals>, mut rng: ResMut<SimRng>,
+    static_collision: Res<StaticCollision>, levels: Res<Levels>, weather: Res<Weather>,
+    mut decal_events: EventReader<DecalEvent>, mut impact_events: EventReader<ProjectileImpactEvent>,
+    mut footstep_events: EventReader<FootstepEvent>, walkers: Query<(&Transform, Option<&PlayerMovement>)>)
+{
+    let mut events: Vec<DecalEvent> = decal_events.iter().cloned().collect();
+
+    for impact in impact_events.iter() {
+        if let Some(normal) = impact.normal {
+            let mut event = DecalEvent::new("scorch", impact.position, -normal, 1.0);
+            event.size = 0.8;
+            events.push(event);
+        }
+    }
+
+    let indoors = levels.current()
+        .and_then(|level| levels.get(level))
+        .map_or(false, |level| level.def.indoors);
+    let muddy = !indoors && weather.params().rain >= FOOTPRINT_MIN_RAIN;
+    for footstep in footstep_events.iter().filter(|_| muddy) {
+        let forward = match walkers.get(footstep.entity) {
+            Ok((_, Some(movement))) => movement.forward(),
+            Ok((transform, None)) => transform.rot * vec3(0.0, 0.0, -1.0),
+            Err(_) => continue,
+        };
+        let forward = vec3(forward.x, 0.0, forward.z);
+        if forward.magnitude2() == 0.0 {
+            continue;
+        }
+
+        let forward = forward.normalize();
+        let side = match footstep.left_foot {
+            true => -FOOTPRINT_SPACING,
+            false => FOOTPRINT_SPACING,
+        };
+        let origin = footstep.position + forward.cross(vec3(0.0, 1.0, 0.0)) * side + vec3(0.0, 0.5, 0.0);
+        let mut event = DecalEvent::new("footprint", origin, vec3(0.0, -1.0, 0.0), 1.0);
+        event.size = 0.3;
+        event.forward = Some(forward);
+        events.push(event);
+    }
+
+    for event in events {
+        if event.direction.magnitude2() == 0.0 {
+            continue;
+        }
+
+        let hit = match static_collision.raycast(event.origin, event.direction.normalize(), event.reach) {
+            Some(hit) => hit,
+            None => continue,
+        };
+
+        let normal = hit.normal;
+        let forward = event.forward.unwrap_or_else(|| {
+            let angle = rng.range(0.0, 2.0 * std::f32::consts::PI);
+            vec3(angle.cos(), 0.0, angle.sin())
+        });
+        let rot = surface_rotation(normal, forward);
+
+        let entity = commands.spawn()
+            .insert(Decal { level: levels.current().map(str::to_string), age: 0.0 })
+            .insert(Transform::new(hit.point + normal * SURFACE_OFFSET, rot * event.size))
+            .insert(Visual::new(&format!("decal_{}", event.decal), false))
+            .id();
+        decals.entities.push_back(entity);
+
+        while decals.entities.len() > decals.max_decals {
+            if let Some(oldest) = decals.entities.pop_front() {
+                commands.entity(oldest).despawn();
+            }
+        }
+    }
+}
+
+/// The decal system, which ages decals, fades them away at the end of their lifetime, and
+/// despawns them once it's over or their level's been unloaded
+pub fn update_decals(mut commands: Commands, sim_time: Res<SimTime>, mut decals: ResMut<Decals>,
+    levels: Res<Levels>, mut query: Query<(Entity, &mut Decal, Option<&mut Tint>)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    let level = levels.current();
+
+    let mut expired = Vec::new();
+    for (entity, mut decal, tint) in query.iter_mut() {
+        decal.age += time_delta;
+        let remaining = decals.lifetime - decal.age;
+        if remaining <= 0.0 || decal.level.as_deref() != level {
+            commands.entity(entity).despawn();
+            expired.push(entity);
+            continue;
+        }
+
+        if remaining < decals.fade_time {
+            let alpha = remaining / decals.fade_time;
+            match tint {
+                Some(mut tint) => tint.color.w = alpha,
+                None => {
+                    commands.entity(entity).insert(Tint::new(vec4(1.0, 1.0, 1.0, alpha)));
+                },
+            }
+        }
+    }
+
+    // Forget about decals that have gone, including any that were despawned with everything else.
+    // This runs before the decals are spawned, so the ones spawned last frame are in the query.
+    decals.entities.retain(|entity| !expired.contains(entity) && query.get(*entity).is_ok());
+}
@@ -0,0 +1,110 @@
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::system::{Res, ResMut, Query};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::sim_settings::SimSettings;
+
+/// The seed used if a SimRng is given a seed of 0, which xorshift can't get out of
+const FALLBACK_SEED: u32 = 0x9e3779b9;
+
+/// The FNV-1a offset basis and prime, for hashing the sim state
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// The SimRng resource, a small seeded xorshift random number generator that every sim system
+/// draws from, so two runs with the same seed and inputs make the same random choices
+pub struct SimRng {
+    seed: u32,
+    state: u32,
+}
+
+impl SimRng {
+    pub fn new(seed: u32) -> Self {
+        let seed = match seed {
+            0 => FALLBACK_SEED,
+            seed => seed,
+        };
+        SimRng { seed, state: seed }
+    }
+
+    /// Create a generator seeded from the clock, for when the sim doesn't need to be repeatable
+    pub fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        SimRng::new(nanos)
+    }
+
+    /// Get the seed the generator started from
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// Get a random number from 0 to 1
+    pub fn next(&mut self) -> f32 {
+        self.next_u32() as f32 / u32::MAX as f32
+    }
+
+    /// Get a random number in a range
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next()
+    }
+}
+
+/// The StateChecksum resource, with a hash of the sim state after the last tick. Two runs with
+/// the same seed and inputs should have the same checksum on every tick, so comparing them finds
+/// the first tick they diverged on.
+#[derive(Default)]
+pub struct StateChecksum {
+    pub tick: u64,
+    pub checksum: u64,
+}
+
+/// Feed a value into an FNV-1a hash
+fn hash_u64(hash: u64, value: u64) -> u64 {
+    value.to_le_bytes().iter().fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Hash the sim state: the sim time, the random number generator, and every entity's transform in
+/// order of entity id
+pub fn state_checksum(sim_time: &SimTime, rng: &SimRng, transforms: &[(Entity, &Transform)]) -> u64 {
+    let mut transforms = transforms.to_vec();
+    transforms.sort_by_key(|(entity, _)| entity.to_bits());
+
+    let mut hash = hash_u64(FNV_OFFSET, sim_time.sim_time.to_bits());
+    hash = hash_u64(hash, rng.state as u64);
+    for (entity, transform) in transforms {
+        hash = hash_u64(hash, entity.to_bits());
+        let pos: &[f32; 3] = transform.pos.as_ref();
+        let rot: &[[f32; 3]; 3] = transform.rot.as_ref();
+        for value in pos.iter().chain(rot.iter().flatten()) {
+            hash = hash_u64(hash, value.to_bits() as u64);
+        }
+    }
+
+    hash
+}
+
+/// The checksum system, which hashes the sim state at the end of every tick and logs it in
+/// deterministic mode
+pub fn update_state_checksum(sim_time: Res<SimTime>, rng: Res<SimRng>, sim_settings: Res<SimSettings>,
+    mut checksum: ResMut<StateChecksum>, query: Query<(Entity, &Transform)>)
+{
+    if !sim_settings.deterministic {
+        return;
+    }
+
+    let transforms: Vec<(Entity, &Transform)> = query.iter().collect();
+    checksum.tick += 1;
+    checksum.checksum = state_checksum(&sim_time, &rng, &transforms);
+    log::debug!("Tick {} state checksum: {:016x}", checksum.tick, checksum.checksum);
+}
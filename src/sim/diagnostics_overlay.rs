@@ -0,0 +1,380 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+use bevy_ecs::archetype::{Archetypes, ArchetypeGeneration};
+use bevy_ecs::component::{Component, Components};
+use bevy_ecs::entity::Entities;
+use bevy_ecs::prelude::{Commands, Entity, With, Without};
+use bevy_ecs::schedule::{ParallelSystemContainer, ParallelSystemExecutor, Stage};
+use bevy_ecs::system::{Res, ResMut, Query};
+use bevy_ecs::world::World;
+use dreamfield_renderer::components::{DiagnosticsTextBox, TextBox, Visual, ScreenEffect};
+use glfw::ffi;
+
+use super::chunk_lod::ChunkLod;
+use super::chunk_streaming::ChunkStreamer;
+use super::console::is_key_down;
+use super::level::Levels;
+use super::pvs::Cullable;
+use super::rich_text::RichText;
+use super::ui::{UiWidget, UiVisible};
+
+/// How many frames and ticks the time graphs show
+pub const GRAPH_SAMPLES: usize = 60;
+
+/// The frame and tick times the graphs are full at, in milliseconds
+const GRAPH_MAX_FRAME_MS: f32 = 33.3;
+const GRAPH_MAX_TICK_MS: f32 = 16.7;
+
+/// How much of each new time goes into the smoothed times, so they can be read as they change
+const SMOOTHING: f32 = 0.1;
+
+/// How many stages, systems and archetypes are listed, slowest or biggest first
+const MAX_LISTED: usize = 14;
+
+/// Systems that take longer than this are shown in red, in milliseconds
+const SLOW_SYSTEM_MS: f32 = 2.0;
+
+/// The pages of the diagnostics overlay, which are switched between with F1 to F4
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiagnosticsPage {
+    /// The engine's own diagnostics
+    Basic,
+    /// Frame and tick time graphs, and how long each stage and system takes
+    Timings,
+    /// How many entities there are with each set of components
+    Entities,
+    /// The chunks that are resident and what's being drawn
+    World,
+}
+
+impl DiagnosticsPage {
+    /// The pages in the order of the keys that show them
+    const ALL: &'static [DiagnosticsPage] = &[DiagnosticsPage::Basic, DiagnosticsPage::Timings,
+        DiagnosticsPage::Entities, DiagnosticsPage::World];
+    const KEYS: &'static [i32] = &[ffi::KEY_F1, ffi::KEY_F2, ffi::KEY_F3, ffi::KEY_F4];
+}
+
+/// Which schedule a timed stage is in
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TimedSchedule {
+    Update,
+    Render,
+}
+
+/// The times of one schedule's stages
+#[derive(Default)]
+struct ScheduleTimes {
+    /// The first stage of the schedule, which starts each run of it
+    first_stage: Option<String>,
+    /// The smoothed time of each stage, in the order they run
+    stages: Vec<(String, f32)>,
+    /// How long the current run's taken so far
+    current: f32,
+    /// The time of each of the last GRAPH_SAMPLES runs
+    history: VecDeque<f32>,
+    /// When the last run started, for working out the frame rate
+    last_start: Option<Instant>,
+    interval: f32,
+}
+
+impl ScheduleTimes {
+    fn record_stage(&mut self, stage: &str, start: Instant, ms: f32) {
+        if self.first_stage.get_or_insert_with(|| stage.to_string()) == stage {
+            if let Some(last_start) = self.last_start {
+                self.interval = smooth(self.interval, start.duration_since(last_start).as_secs_f32() * 1000.0);
+            }
+            self.last_start = Some(start);
+
+            if self.current > 0.0 {
+                self.history.push_back(self.current);
+                while self.history.len() > GRAPH_SAMPLES {
+                    self.history.pop_front();
+                }
+            }
+            self.current = 0.0;
+        }
+
+        self.current += ms;
+        match self.stages.iter_mut().find(|(name, _)| name == stage) {
+            Some((_, time)) => *time = smooth(*time, ms),
+            None => self.stages.push((stage.to_string(), ms)),
+        }
+    }
+
+    fn average(&self) -> f32 {
+        match self.history.is_empty() {
+            true => 0.0,
+            false => self.history.iter().sum::<f32>() / self.history.len() as f32,
+        }
+    }
+}
+
+/// Move a smoothed time towards a new one
+fn smooth(smoothed: f32, time: f32) -> f32 {
+    smoothed + (time - smoothed) * SMOOTHING
+}
+
+/// The SystemTimings resource, with how long each stage of the schedules takes, and each system
+/// when the sim settings say to profile them
+#[derive(Default)]
+pub struct SystemTimings {
+    schedules: HashMap<TimedSchedule, ScheduleTimes>,
+    /// The smoothed time of each system in milliseconds, by its name
+    systems: HashMap<String, f32>,
+}
+
+impl SystemTimings {
+    fn record_system(&mut self, name: &str, ms: f32) {
+        match self.systems.get_mut(name) {
+            Some(time) => *time = smooth(*time, ms),
+            None => {
+                self.systems.insert(name.to_string(), ms);
+            },
+        }
+    }
+}
+
+/// A stage that records how long the stage inside it takes to run in the SystemTimings
+pub struct TimedStage<S: Stage> {
+    schedule: TimedSchedule,
+    name: String,
+    stage: S,
+}
+
+impl<S: Stage> TimedStage<S> {
+    pub fn new(schedule: TimedSchedule, name: &str, stage: S) -> Self {
+        TimedStage { schedule, name: name.to_string(), stage }
+    }
+}
+
+impl<S: Stage> Stage for TimedStage<S> {
+    fn run(&mut self, world: &mut World) {
+        let start = Instant::now();
+        self.stage.run(world);
+        let ms = start.elapsed().as_secs_f32() * 1000.0;
+
+        if let Some(mut timings) = world.get_resource_mut::<SystemTimings>() {
+            timings.schedules.entry(self.schedule).or_default().record_stage(&self.name, start, ms);
+        }
+    }
+}
+
+/// A stage executor that runs the systems one at a time, like the single threaded one, and records
+/// how long each of them takes in the SystemTimings. Stages only use it when the sim settings say
+/// to profile systems, since it stops them running in parallel.
+pub struct TimedExecutor {
+    archetype_generation: ArchetypeGeneration,
+}
+
+impl Default for TimedExecutor {
+    fn default() -> Self {
+        TimedExecutor { archetype_generation: ArchetypeGeneration::initial() }
+    }
+}
+
+impl ParallelSystemExecutor for TimedExecutor {
+    fn rebuild_cached_data(&mut self, _: &[ParallelSystemContainer]) {}
+
+    fn run_systems(&mut self, systems: &mut [ParallelSystemContainer], world: &mut World) {
+        // Tell the systems about any archetypes that have been made since they last ran
+        let archetypes = world.archetypes();
+        let old_generation = std::mem::replace(&mut self.archetype_generation, archetypes.generation());
+        for archetype in archetypes.iter().skip(old_generation.value()) {
+            for container in systems.iter_mut() {
+                container.system_mut().new_archetype(archetype);
+            }
+        }
+
+        let mut times = Vec::with_capacity(systems.len());
+        for container in systems.iter_mut().filter(|container| container.should_run()) {
+            let start = Instant::now();
+            container.system_mut().run((), world);
+            times.push((container.name(), start.elapsed().as_secs_f32() * 1000.0));
+        }
+
+        if let Some(mut timings) = world.get_resource_mut::<SystemTimings>() {
+            for (name, ms) in times {
+                timings.record_system(&name, ms);
+            }
+        }
+    }
+}
+
+/// Shorten a type or system name to the last part of its path, without its generic parameters
+fn short_name(name: &str) -> &str {
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// The DiagnosticsOverlay resource, with the page of diagnostics that's shown
+pub struct DiagnosticsOverlay {
+    pub page: DiagnosticsPage,
+    held_keys: Vec<i32>,
+}
+
+impl Default for DiagnosticsOverlay {
+    fn default() -> Self {
+        DiagnosticsOverlay {
+            page: DiagnosticsPage::Basic,
+            held_keys: Vec::new(),
+        }
+    }
+}
+
+/// The DiagnosticsOverlayText component, for the RichText the overlay's pages are shown in. It's
+/// in the same place as the engine's DiagnosticsTextBox, which the engine's page is drawn to, and
+/// that's taken away from its entity while the other pages are shown.
+#[derive(Component)]
+pub struct DiagnosticsOverlayText;
+
+/// The DiagnosticsGraph component, for the graphs on the timings page
+#[derive(Component)]
+pub enum DiagnosticsGraph {
+    Frame,
+    Tick,
+}
+
+/// The DiagnosticsBox component, for the entity the engine's diagnostics are drawn to, so that its
+/// DiagnosticsTextBox can be put back
+#[derive(Component)]
+pub struct DiagnosticsBox;
+
+/// The diagnostics key system, which switches pages with the F keys. They aren't engine inputs, so
+/// this runs in the render schedule to read them with is_key_down.
+pub fn poll_diagnostics_keys(mut overlay: ResMut<DiagnosticsOverlay>) {
+    let down: Vec<i32> = DiagnosticsPage::KEYS.iter().copied().filter(|key| is_key_down(*key)).collect();
+    let pressed = DiagnosticsPage::KEYS.iter()
+        .position(|key| down.contains(key) && !overlay.held_keys.contains(key));
+    if let Some(index) = pressed {
+        overlay.page = DiagnosticsPage::ALL[index];
+    }
+    overlay.held_keys = down;
+}
+
+/// Build the timings page, with the frame and tick times above their graphs, and the slowest
+/// stages and systems
+fn timings_page(timings: &SystemTimings) -> String {
+    let empty = ScheduleTimes::default();
+    let render = timings.schedules.get(&TimedSchedule::Render).unwrap_or(&empty);
+    let update = timings.schedules.get(&TimedSchedule::Update).unwrap_or(&empty);
+    let fps = match render.interval > 0.0 {
+        true => 1000.0 / render.interval,
+        false => 0.0,
+    };
+
+    // The graphs are drawn over the blank lines
+    let mut text = format!("Frame {:.2}ms, {:.0} fps\n\nTick {:.2}ms\n\n", render.average(), fps, update.average());
+
+    let mut stages: Vec<(&str, f32)> = update.stages.iter().chain(render.stages.iter())
+        .map(|(name, time)| (name.as_str(), *time))
+        .collect();
+    stages.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    text += "{color=yellow}Stages{/color}\n";
+    for (name, time) in stages.iter().take(MAX_LISTED / 2) {
+        text += &format!("{:.2}ms {}\n", time, name);
+    }
+
+    let mut systems: Vec<(&str, f32)> = timings.systems.iter().map(|(name, time)| (short_name(name), *time)).collect();
+    systems.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    text += "{color=yellow}Systems{/color}\n";
+    match systems.is_empty() {
+        true => text += "Run with --profile-systems to time them\n",
+        false => for (name, time) in systems.iter().take(MAX_LISTED) {
+            text += &match *time > SLOW_SYSTEM_MS {
+                true => format!("{{color=red}}{:.2}ms {}{{/color}}\n", time, name),
+                false => format!("{:.2}ms {}\n", time, name),
+            };
+        },
+    }
+
+    text
+}
+
+/// Build the entities page, with the biggest archetypes and their components
+fn entities_page(archetypes: &Archetypes, components: &Components, entities: &Entities) -> String {
+    let mut text = format!("{} entities in {} archetypes\n", entities.len(), archetypes.len());
+
+    let mut archetypes: Vec<_> = archetypes.iter().filter(|archetype| !archetype.is_empty()).collect();
+    archetypes.sort_by_key(|archetype| std::cmp::Reverse(archetype.len()));
+    for archetype in archetypes.iter().take(MAX_LISTED) {
+        let names: Vec<&str> = archetype.components()
+            .filter_map(|id| components.get_info(id))
+            .map(|info| short_name(info.name()))
+            .collect();
+        text += &format!("{{color=yellow}}{}{{/color}} {}\n", archetype.len(), names.join(" "));
+    }
+
+    text
+}
+
+/// The diagnostics overlay system, which shows the page that's been picked. The engine's page is
+/// drawn by the engine, and the others are built here each tick.
+pub fn update_diagnostics_overlay(mut commands: Commands, overlay: Res<DiagnosticsOverlay>,
+    timings: Res<SystemTimings>, levels: Res<Levels>, streamer: Res<ChunkStreamer>,
+    archetypes: &Archetypes, components: &Components, entities: &Entities,
+    mut engine_query: Query<(Entity, &mut TextBox, Option<&DiagnosticsTextBox>), (With<DiagnosticsBox>, Without<DiagnosticsOverlayText>)>,
+    mut text_query: Query<&mut RichText, With<DiagnosticsOverlayText>>,
+    mut graph_query: Query<(&DiagnosticsGraph, &mut UiWidget, &mut UiVisible)>,
+    world_query: Query<(Option<&Visual>, Option<&Cullable>, Option<&ChunkLod>, Option<&TextBox>, Option<&ScreenEffect>)>)
+{
+    // Give the engine its text box back on its own page
+    for (entity, mut text_box, engine_text_box) in engine_query.iter_mut() {
+        match (overlay.page, engine_text_box.is_some()) {
+            (DiagnosticsPage::Basic, false) => {
+                commands.entity(entity).insert(DiagnosticsTextBox);
+            },
+            (DiagnosticsPage::Basic, true) => {},
+            (_, true) => {
+                commands.entity(entity).remove::<DiagnosticsTextBox>();
+                text_box.text.clear();
+            },
+            (_, false) => {},
+        }
+    }
+
+    let markup = match overlay.page {
+        DiagnosticsPage::Basic => String::new(),
+        DiagnosticsPage::Timings => timings_page(&timings),
+        DiagnosticsPage::Entities => entities_page(archetypes, components, entities),
+        DiagnosticsPage::World => {
+            // The renderer doesn't count its draw calls, so this counts what it draws, which is at
+            // least a draw call each
+            let (mut visuals, mut culled, mut lod_tiles, mut text_boxes, mut effects) = (0, 0, 0, 0, 0);
+            for (visual, cullable, lod, text_box, effect) in world_query.iter() {
+                visuals += visual.is_some() as usize;
+                culled += cullable.map_or(false, Cullable::is_culled) as usize;
+                lod_tiles += (lod.is_some() && visual.is_some()) as usize;
+                text_boxes += text_box.map_or(false, |text_box| !text_box.text.is_empty()) as usize;
+                effects += effect.is_some() as usize;
+            }
+
+            format!("Level {}\nResident cells {}\nQueued cells {}\nLOD tiles drawn {}\n\
+                {{color=yellow}}Draws{{/color}}\nVisuals {} ({} culled)\nText boxes {}\nScreen effects {}\nTotal {}\n",
+                levels.current().unwrap_or("none"), streamer.resident_cells(), streamer.queued_cells(), lod_tiles,
+                visuals, culled, text_boxes, effects, visuals + text_boxes + effects)
+        },
+    };
+    for mut rich_text in text_query.iter_mut() {
+        rich_text.set_markup(&markup);
+    }
+
+    for (graph, mut widget, mut visible) in graph_query.iter_mut() {
+        let shown = overlay.page == DiagnosticsPage::Timings;
+        if visible.0 != shown {
+            visible.0 = shown;
+        }
+        if !shown {
+            continue;
+        }
+
+        let (schedule, max) = match graph {
+            DiagnosticsGraph::Frame => (TimedSchedule::Render, GRAPH_MAX_FRAME_MS),
+            DiagnosticsGraph::Tick => (TimedSchedule::Update, GRAPH_MAX_TICK_MS),
+        };
+        let mut values: Vec<f32> = timings.schedules.get(&schedule)
+            .map_or(Vec::new(), |times| times.history.iter().map(|time| time / max).collect());
+        values.resize(GRAPH_SAMPLES, 0.0);
+        *widget = UiWidget::Graph { values };
+    }
+}
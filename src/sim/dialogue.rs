@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventWriter, With};
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::InnerSpace;
+use serde::Deserialize;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::InputState;
+
+use super::PlayerMovement;
+use super::menu::MenuNav;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::gamepad::GamepadState;
+use super::rich_text::RichText;
+use super::locale::Locale;
+
+/// How many characters of each dialogue line are revealed per second
+pub const DIALOGUE_TEXT_SPEED: f32 = 40.0;
+
+/// A dialogue tree, loaded from a RON file
+#[derive(Deserialize, Debug)]
+pub struct DialogueTree {
+    /// The node the conversation starts at
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>,
+}
+
+/// A node in a dialogue tree, a series of lines followed by an optional choice
+#[derive(Deserialize, Debug)]
+pub struct DialogueNode {
+    pub speaker: String,
+    /// The lines, which can be locale keys, and can have RichText markup codes in them
+    pub lines: Vec<String>,
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+    /// The node to go to after the lines if there are no choices, or None to end the conversation
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// A choice the player can make at the end of a dialogue node
+#[derive(Deserialize, Debug)]
+pub struct DialogueChoice {
+    pub text: String,
+    /// The node to go to, or None to end the conversation
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// The dialogue manager, which stores all the dialogue trees by name
+pub struct DialogueManager {
+    trees: HashMap<String, DialogueTree>,
+}
+
+impl DialogueManager {
+    /// Create a new dialogue manager from a list of (name, ron source) pairs
+    pub fn new(trees: Vec<(&str, &str)>) -> Self {
+        let trees = trees.into_iter()
+            .filter_map(|(name, source)| {
+                match ron::from_str::<DialogueTree>(source) {
+                    Ok(tree) => Some((name.to_string(), tree)),
+                    Err(err) => {
+                        log::error!("Failed to load dialogue tree {}: {}", name, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        DialogueManager { trees }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DialogueTree> {
+        self.trees.get(name)
+    }
+}
+
+/// The DialogueSpeaker component, for entities the player can talk to
+#[derive(Component)]
+pub struct DialogueSpeaker {
+    pub tree: String,
+    pub talk_radius: f32,
+}
+
+impl DialogueSpeaker {
+    pub fn new(tree: &str) -> Self {
+        DialogueSpeaker {
+            tree: tree.to_string(),
+            talk_radius: 2.0,
+        }
+    }
+}
+
+/// The dialogue text box component, for the RichText dialogue is shown in
+#[derive(Component)]
+pub struct DialogueTextBox;
+
+/// An event emitted when a dialogue node has been completed
+pub struct DialogueBranchCompleteEvent {
+    pub speaker: Entity,
+    pub tree: String,
+    pub node: String,
+    /// The index of the choice the player made, if the node had choices
+    pub choice: Option<usize>,
+}
+
+/// The conversation currently in progress
+pub struct ActiveDialogue {
+    pub speaker: Entity,
+    pub tree: String,
+    pub node: String,
+    pub line: usize,
+    pub selection: usize,
+}
+
+/// The DialogueState resource
+#[derive(Default)]
+pub struct DialogueState {
+    pub active: Option<ActiveDialogue>,
+    nav: MenuNav,
+}
+
+impl DialogueState {
+    pub fn in_dialogue(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Start a conversation with a speaker from the start of a dialogue tree, returning false if
+    /// the tree doesn't exist
+    pub fn start(&mut self, dialogue_manager: &DialogueManager, speaker: Entity, tree: &str) -> bool {
+        match dialogue_manager.get(tree) {
+            Some(dialogue_tree) => {
+                self.active = Some(ActiveDialogue {
+                    speaker,
+                    tree: tree.to_string(),
+                    node: dialogue_tree.start.clone(),
+                    line: 0,
+                    selection: 0,
+                });
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// The dialogue system
+pub fn update_dialogue(input: Res<InputState>,
+                       gamepad: Res<GamepadState>,
+                       bindings: Res<InputBindings>,
+                       dialogue_manager: Res<DialogueManager>,
+                       locale: Res<Locale>,
+                       mut state: ResMut<DialogueState>,
+                       mut events: EventWriter<DialogueBranchCompleteEvent>,
+                       speakers: Query<(Entity, &DialogueSpeaker, &Transform)>,
+                       player_query: Query<&Transform, With<PlayerMovement>>,
+                       mut text_query: Query<&mut RichText, With<DialogueTextBox>>)
+{
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    let state = state.as_mut();
+
+    if state.active.is_none() {
+        // Start a conversation with the closest speaker in range
+        if controls.is_just_pressed(Action::Interact) {
+            if let Ok(player_transform) = player_query.get_single() {
+                state.active = find_speaker(&dialogue_manager, &speakers, player_transform);
+            }
+        }
+    }
+    else if controls.is_just_pressed(Action::Interact) && text_query.iter().any(|text| !text.is_revealed()) {
+        // Interacting while a line's still being revealed shows the rest of it
+        for mut text in text_query.iter_mut() {
+            text.reveal_all();
+        }
+        return;
+    }
+    else {
+        advance_dialogue(&controls, &dialogue_manager, state, &mut events);
+    }
+
+    let (text, choosing) = state.active.as_ref()
+        .and_then(|active| dialogue_text(&dialogue_manager, &locale, active))
+        .unwrap_or_default();
+
+    // The choices are shown straight away, so they don't type out again when the selection moves
+    for mut rich_text in text_query.iter_mut() {
+        rich_text.set_markup(&text);
+        if choosing {
+            rich_text.reveal_all();
+        }
+    }
+}
+
+/// Find the closest speaker in range of the player and start a conversation with them
+fn find_speaker(dialogue_manager: &DialogueManager, speakers: &Query<(Entity, &DialogueSpeaker, &Transform)>,
+    player_transform: &Transform) -> Option<ActiveDialogue>
+{
+    speakers.iter()
+        .map(|(entity, speaker, transform)| (entity, speaker, (transform.pos - player_transform.pos).magnitude()))
+        .filter(|(_, speaker, dist)| *dist < speaker.talk_radius)
+        .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+        .and_then(|(entity, speaker, _)| {
+            let tree = dialogue_manager.get(&speaker.tree);
+            if tree.is_none() {
+                log::warn!("Speaker has unknown dialogue tree {}", speaker.tree);
+            }
+            tree.map(|tree| ActiveDialogue {
+                speaker: entity,
+                tree: speaker.tree.clone(),
+                node: tree.start.clone(),
+                line: 0,
+                selection: 0,
+            })
+        })
+}
+
+/// Advance the active conversation based on player input
+fn advance_dialogue(controls: &Controls, dialogue_manager: &DialogueManager, state: &mut DialogueState,
+    events: &mut EventWriter<DialogueBranchCompleteEvent>)
+{
+    let active = state.active.as_mut().unwrap();
+    let node = match dialogue_manager.get(&active.tree).and_then(|tree| tree.nodes.get(&active.node)) {
+        Some(node) => node,
+        None => {
+            log::warn!("Dialogue tree {} has no node {}", active.tree, active.node);
+            state.active = None;
+            return;
+        }
+    };
+
+    let showing_choices = active.line >= node.lines.len() && !node.choices.is_empty();
+    if showing_choices {
+        active.selection = state.nav.apply(controls, active.selection, node.choices.len());
+    }
+
+    if !controls.is_just_pressed(Action::Interact) {
+        return;
+    }
+
+    // Move to the next line, or on to the choices after the last one
+    if !showing_choices && (active.line + 1 < node.lines.len() || !node.choices.is_empty()) {
+        active.line += 1;
+        return;
+    }
+
+    // The node is complete, so move on to the next one
+    let choice = match showing_choices {
+        true => Some(active.selection),
+        false => None,
+    };
+    let next = match choice {
+        Some(choice) => node.choices[choice].next.clone(),
+        None => node.next.clone(),
+    };
+
+    events.send(DialogueBranchCompleteEvent {
+        speaker: active.speaker,
+        tree: active.tree.clone(),
+        node: active.node.clone(),
+        choice,
+    });
+
+    match next {
+        Some(next) => {
+            active.node = next;
+            active.line = 0;
+            active.selection = 0;
+        },
+        None => state.active = None,
+    }
+}
+
+/// Build the rich text for the current line or choices, and whether it's the choices
+fn dialogue_text(dialogue_manager: &DialogueManager, locale: &Locale, active: &ActiveDialogue) -> Option<(String, bool)> {
+    let node = dialogue_manager.get(&active.tree)?.nodes.get(&active.node)?;
+    let speaker = format!("{{color=yellow}}{}:{{/color}}\n", locale.get(&node.speaker).replace('{', "{{"));
+
+    let text = match node.lines.get(active.line) {
+        Some(line) => (speaker + locale.get(line), false),
+        None => {
+            let mut text = speaker;
+            for (i, choice) in node.choices.iter().enumerate() {
+                let cursor = if i == active.selection { ">" } else { " " };
+                text += &format!("{} {}\n", cursor, locale.get(&choice.text));
+            }
+            (text, true)
+        }
+    };
+
+    Some(text)
+}
@@ -0,0 +1,79 @@
+use bevy_ecs::system::ResMut;
+use cgmath::{Vector2, vec2};
+use serde::{Serialize, Deserialize};
+
+/// How the render is fit to the window
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum AspectMode {
+    /// Render at the configured aspect ratio, with black bars at the sides of wider windows
+    Pillarboxed,
+    /// Render at the configured resolution, stretched to fill the window
+    Stretched,
+    /// Widen the render resolution to match the window
+    Widescreen,
+}
+
+impl AspectMode {
+    pub const ALL: &'static [AspectMode] = &[AspectMode::Pillarboxed, AspectMode::Stretched, AspectMode::Widescreen];
+
+    /// Get the locale key for the mode's name
+    pub fn key(&self) -> &'static str {
+        match self {
+            AspectMode::Pillarboxed => "settings.aspect.pillarboxed",
+            AspectMode::Stretched => "settings.aspect.stretched",
+            AspectMode::Widescreen => "settings.aspect.widescreen",
+        }
+    }
+}
+
+/// The DisplayMode resource, with the aspect mode and the current window size
+pub struct DisplayMode {
+    pub aspect_mode: AspectMode,
+    /// The window size in pixels, which is updated from the viewport by the render schedule
+    pub window_size: Vector2<f32>,
+}
+
+impl Default for DisplayMode {
+    fn default() -> Self {
+        DisplayMode {
+            aspect_mode: AspectMode::Pillarboxed,
+            window_size: vec2(0.0, 0.0),
+        }
+    }
+}
+
+impl DisplayMode {
+    /// Get the window's aspect ratio, if we know the window size yet
+    pub fn window_aspect(&self) -> Option<f32> {
+        match self.window_size.x > 0.0 && self.window_size.y > 0.0 {
+            true => Some(self.window_size.x / self.window_size.y),
+            false => None,
+        }
+    }
+
+    /// Get the render resolution and aspect ratio for a base resolution and aspect ratio
+    pub fn fit(&self, base_res: Vector2<f32>, base_aspect: f32) -> (Vector2<f32>, f32) {
+        match (self.aspect_mode, self.window_aspect()) {
+            (AspectMode::Pillarboxed, _) | (_, None) => (base_res, base_aspect),
+            (AspectMode::Stretched, Some(window_aspect)) => (base_res, window_aspect),
+            (AspectMode::Widescreen, Some(window_aspect)) => {
+                // Only ever widen the resolution, so tall windows get letterboxed instead
+                let aspect = f32::max(window_aspect, base_aspect);
+                let width = (base_res.x * aspect / base_aspect).round();
+                (vec2(width, base_res.y), aspect)
+            }
+        }
+    }
+}
+
+/// The window size system, which runs in the render schedule since it reads the GL viewport
+pub fn update_window_size(mut display: ResMut<DisplayMode>) {
+    let mut viewport = [0; 4];
+    unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+
+    // Only write it when it's changed, since that recomputes the camera and UI
+    let window_size = vec2(viewport[2] as f32, viewport[3] as f32);
+    if display.window_size != window_size {
+        display.window_size = window_size;
+    }
+}
@@ -0,0 +1,139 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{With, Without};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Matrix3, Rad, InnerSpace};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::PlayerMovement;
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::inventory::PlayerInventory;
+use super::tween::Easing;
+use super::static_collision::StaticCollision;
+
+/// The door states
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DoorState {
+    Closed,
+    Open,
+    Locked,
+}
+
+/// The Door component. The door swings around its entity origin, so this should be at the hinge.
+#[derive(Component)]
+pub struct Door {
+    pub state: DoorState,
+    /// The item needed to unlock the door, if it's locked
+    pub key_item: Option<String>,
+    /// Whether unlocking the door uses up the key
+    pub consume_key: bool,
+    /// How far the door swings open, in radians
+    pub open_angle: f32,
+    /// How long the door takes to open or close, in seconds
+    pub open_time: f32,
+    pub interact_radius: f32,
+    closed_rot: Matrix3<f32>,
+    /// How far open the door is, from 0 to 1
+    open_amount: f32,
+}
+
+impl Door {
+    pub fn new(closed_rot: Matrix3<f32>) -> Self {
+        Door {
+            state: DoorState::Closed,
+            key_item: None,
+            consume_key: false,
+            open_angle: std::f32::consts::FRAC_PI_2,
+            open_time: 1.0,
+            interact_radius: 2.0,
+            closed_rot,
+            open_amount: 0.0,
+        }
+    }
+
+    pub fn new_locked(closed_rot: Matrix3<f32>, key_item: &str, consume_key: bool) -> Self {
+        Door {
+            state: DoorState::Locked,
+            key_item: Some(key_item.to_string()),
+            consume_key,
+            ..Door::new(closed_rot)
+        }
+    }
+
+    /// Interact with the door, unlocking it with the key from the inventory if necessary
+    fn interact(&mut self, inventory: Option<&mut PlayerInventory>) {
+        self.state = match self.state {
+            DoorState::Open => DoorState::Closed,
+            DoorState::Closed => DoorState::Open,
+            DoorState::Locked => {
+                let key_item = self.key_item.as_ref();
+                let has_key = match (key_item, inventory) {
+                    (None, _) => true,
+                    (Some(key_item), Some(inventory)) => match self.consume_key {
+                        true => inventory.remove(key_item, 1),
+                        false => inventory.count(key_item) > 0,
+                    },
+                    (Some(_), None) => false,
+                };
+
+                match has_key {
+                    true => DoorState::Open,
+                    false => {
+                        log::info!("The door is locked");
+                        DoorState::Locked
+                    }
+                }
+            }
+        };
+    }
+}
+
+/// The door update system
+pub fn update_doors(sim_time: Res<SimTime>, input: Res<InputState>, gamepad: Res<GamepadState>,
+    bindings: Res<InputBindings>, static_collision: Res<StaticCollision>,
+    mut player_query: Query<(&Transform, Option<&mut PlayerInventory>), With<PlayerMovement>>,
+    mut doors: Query<(&mut Door, &mut Transform), Without<PlayerMovement>>)
+{
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    let mut player = player_query.get_single_mut().ok();
+    let interact = controls.is_just_pressed(Action::Interact);
+
+    // Interact with the closest door in range that the player can see
+    if let Some((player_transform, inventory)) = player.as_mut().filter(|_| interact) {
+        let closest = doors.iter_mut()
+            .map(|(door, transform)| {
+                let dist = (transform.pos - player_transform.pos).magnitude();
+                (door, transform.pos, dist)
+            })
+            .filter(|(door, _, dist)| *dist < door.interact_radius)
+            .filter(|(_, pos, _)| static_collision.can_interact(player_transform.pos, *pos))
+            .map(|(door, _, dist)| (door, dist))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        if let Some((mut door, _)) = closest {
+            door.interact(inventory.as_mut().map(|inventory| &mut **inventory));
+        }
+    }
+
+    // Swing doors towards their target position
+    for (mut door, mut transform) in doors.iter_mut() {
+        let target = match door.state {
+            DoorState::Open => 1.0,
+            DoorState::Closed | DoorState::Locked => 0.0,
+        };
+
+        let step = match door.open_time > 0.0 {
+            true => time_delta / door.open_time,
+            false => 1.0,
+        };
+        let delta = f32::clamp(target - door.open_amount, -step, step);
+        door.open_amount += delta;
+
+        // Ease in and out of the swing
+        let eased = Easing::SmoothStep.apply(door.open_amount);
+        transform.rot = door.closed_rot * Matrix3::from_angle_y(Rad(door.open_angle * eased));
+    }
+}
@@ -0,0 +1,260 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventReader, EventWriter, With, Without};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, InnerSpace, Zero, vec3};
+use dreamfield_renderer::components::{Visual, Animation};
+use dreamfield_system::components::Transform;
+use dreamfield_system::intersection::Collider;
+use dreamfield_system::resources::SimTime;
+
+use super::PlayerMovement;
+use super::health::{Health, DamageEvent};
+use super::character_controller::CharacterController;
+use super::nav::{NavMesh, NavAgent};
+use super::static_collision::StaticCollision;
+use super::steering::Steering;
+use super::anim_state::AnimStateMachine;
+use super::anim_events::{AnimationEvent, AnimEventKind};
+use super::behavior::{BehaviorTree, BehaviorTrees, BehaviorContext, BehaviorOutput, AgentAnimation, PlayerPerception};
+
+/// The height of an enemy's eyes, and of the point on the player they look for
+const EYE_HEIGHT: f32 = 1.5;
+
+/// How far short of the target a line of sight check can hit something, so that whatever the
+/// target's up against doesn't hide them
+const SIGHT_TARGET_MARGIN: f32 = 1.0;
+
+/// The blackboard flag enemy behavior trees set while they're after the player, which makes them
+/// keep track of the player outside of their field of view
+pub const HOSTILE_FLAG: &'static str = "hostile";
+
+/// The Enemy component, for a hostile npc whose behavior tree decides when it patrols, chases and
+/// attacks the player, and gives up and goes home. Enemies with a CharacterController and Collider
+/// walk along the ground, colliding with the world, and enemies with a NavAgent find their way
+/// around obstacles.
+#[derive(Component)]
+pub struct Enemy {
+    /// Where the enemy returns to when it gives up a chase
+    pub home: Vector3<f32>,
+    pub patrol_points: Vec<Vector3<f32>>,
+    /// How far the enemy can see
+    pub sight_range: f32,
+    /// The cosine of the half angle of the enemy's field of view
+    pub sight_cos: f32,
+    /// How close the player can get before the enemy notices them without seeing them, unless
+    /// they're sneaking
+    pub hearing_range: f32,
+    /// How far the enemy chases the player from home before giving up
+    pub leash_range: f32,
+    /// How many seconds the enemy keeps chasing for after losing sight of the player
+    pub memory_time: f32,
+    pub idle_time: f32,
+    pub walk_speed: f32,
+    pub chase_speed: f32,
+    pub attack_range: f32,
+    pub attack_damage: f32,
+    /// Seconds from the start of an attack to when it hits
+    pub attack_windup: f32,
+    /// Seconds from the start of an attack until the enemy can move again
+    pub attack_duration: f32,
+    pub idle_animation: String,
+    pub walk_animation: String,
+    pub attack_animation: Option<String>,
+    /// Whether attacks land on the attack animation's Impact markers instead of after the windup
+    pub hit_on_impact: bool,
+    pub steering: Steering,
+    attacking: bool,
+    current_animation: Option<String>,
+}
+
+impl Enemy {
+    pub fn new(home: Vector3<f32>, patrol_points: Vec<Vector3<f32>>, idle_animation: &str, walk_animation: &str) -> Self {
+        Enemy {
+            home,
+            patrol_points,
+            sight_range: 12.0,
+            sight_cos: 0.5,
+            hearing_range: 3.0,
+            leash_range: 25.0,
+            memory_time: 3.0,
+            idle_time: 2.0,
+            walk_speed: 1.5,
+            chase_speed: 3.5,
+            attack_range: 1.5,
+            attack_damage: 10.0,
+            attack_windup: 0.4,
+            attack_duration: 1.2,
+            idle_animation: idle_animation.to_string(),
+            walk_animation: walk_animation.to_string(),
+            attack_animation: None,
+            hit_on_impact: false,
+            steering: Steering::default(),
+            attacking: false,
+            current_animation: None,
+        }
+    }
+
+    /// Get the enemy's animation for one its behavior tree asks for
+    fn animation(&self, animation: AgentAnimation) -> &str {
+        match animation {
+            AgentAnimation::Idle => &self.idle_animation,
+            AgentAnimation::Walk => &self.walk_animation,
+            AgentAnimation::Attack => self.attack_animation.as_ref().unwrap_or(&self.idle_animation),
+        }
+    }
+
+    /// Create the context the enemy's behavior tree runs in
+    fn behavior_context<'a>(&'a self, pos: Vector3<f32>, perception: Option<&'a PlayerPerception>,
+        time_delta: f32) -> BehaviorContext<'a>
+    {
+        BehaviorContext {
+            home: self.home,
+            run_speed: self.chase_speed,
+            leash_range: self.leash_range,
+            memory_time: self.memory_time,
+            attack_range: self.attack_range,
+            attack_windup: match self.hit_on_impact {
+                true => f32::INFINITY,
+                false => self.attack_windup,
+            },
+            attack_duration: self.attack_duration,
+            perception,
+            // Enemies only move horizontally, even without a character controller
+            ..BehaviorContext::new(pos, &self.patrol_points, self.walk_speed, self.idle_time, true, time_delta)
+        }
+    }
+}
+
+/// The enemy update system, which runs each enemy's behavior tree and carries out what it decides
+pub fn update_enemies(sim_time: Res<SimTime>, static_collision: Res<StaticCollision>,
+    nav_mesh: Res<NavMesh>, behavior_trees: Res<BehaviorTrees>,
+    mut damage_events: EventWriter<DamageEvent>, player_query: Query<(Entity, &Transform, &PlayerMovement, &Health)>,
+    mut query: Query<(Entity, &mut Enemy, &mut Transform, &mut Visual, Option<&CharacterController>, Option<&Collider>,
+        Option<&mut NavAgent>, Option<&mut BehaviorTree>, Option<&mut AnimStateMachine>), Without<PlayerMovement>>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    let player = player_query.get_single().ok().filter(|(_, _, _, health)| !health.is_dead());
+    let positions: Vec<(Entity, Vector3<f32>)> = query.iter().map(|(entity, _, transform, ..)| (entity, transform.pos)).collect();
+
+    for (entity, mut enemy, mut transform, mut visual, controller, collider, mut nav_agent, mut behavior, anim_state)
+        in query.iter_mut()
+    {
+        let hostile = behavior.as_ref().map(|behavior| behavior.blackboard.is_set(HOSTILE_FLAG)).unwrap_or(false);
+
+        // Look and listen for the player
+        let perception = player.map(|(player, player_transform, player_movement, _)| {
+            let to_player = player_transform.pos - transform.pos;
+            let distance = to_player.magnitude();
+            let forward = transform.rot * vec3(0.0, 0.0, -1.0);
+            let in_view = distance <= enemy.sight_range
+                && vec3(to_player.x, 0.0, to_player.z).normalize().dot(forward) >= enemy.sight_cos;
+            let heard = distance <= enemy.hearing_range && !player_movement.sneaking;
+
+            // Only check the line of sight if they're in view, or we're already after them, since it
+            // isn't free
+            let eye = vec3(0.0, EYE_HEIGHT, 0.0);
+            let seen = (in_view || (hostile && distance <= enemy.sight_range))
+                && static_collision.line_of_sight(transform.pos + eye, player_transform.pos + eye, SIGHT_TARGET_MARGIN);
+
+            PlayerPerception {
+                player,
+                pos: player_transform.pos,
+                distance,
+                noticed: seen || heard,
+            }
+        });
+
+        // Enemies without a behavior tree just stand there
+        let context = enemy.behavior_context(transform.pos, perception.as_ref(), time_delta);
+        let output = match behavior.as_mut() {
+            Some(behavior) => behavior.tick(&behavior_trees, &context),
+            None => BehaviorOutput::default(),
+        };
+
+        // Hit the player if they're still in range when the attack lands
+        enemy.attacking = output.animation == AgentAnimation::Attack;
+        if output.attack_hit {
+            if let Some(perception) = perception.as_ref().filter(|perception| perception.distance <= enemy.attack_range) {
+                damage_events.send(DamageEvent {
+                    target: perception.player,
+                    amount: enemy.attack_damage,
+                    source: Some(entity),
+                });
+            }
+        }
+
+        // Steer towards the target, slowing to a stop when there's nowhere to go
+        let (desired, speed) = match output.move_to {
+            Some((target, speed)) => {
+                let next_point = match nav_agent.as_mut() {
+                    Some(nav_agent) => nav_agent.steer(&nav_mesh, transform.pos, target, time_delta),
+                    None => target,
+                };
+                let neighbours: Vec<Vector3<f32>> = positions.iter()
+                    .filter(|(other, _)| *other != entity)
+                    .map(|(_, pos)| *pos)
+                    .collect();
+                let desired = enemy.steering.desired_velocity(&static_collision, transform.pos, next_point,
+                    target, speed, &neighbours, entity);
+                (desired, speed)
+            },
+            None => (Vector3::zero(), enemy.chase_speed),
+        };
+        let velocity = enemy.steering.update(desired, speed, time_delta);
+
+        if velocity.magnitude2() > 0.0 {
+            transform.pos = match (controller, collider) {
+                (Some(controller), Some(collider)) => controller.move_and_slide(&static_collision,
+                    collider, transform.pos, velocity, true, entity, time_delta),
+                _ => transform.pos + velocity * time_delta,
+            };
+        }
+
+        // Turn to face where we're going, or the player while attacking
+        let facing = match (output.face_player, perception.as_ref()) {
+            (true, Some(perception)) => perception.pos - transform.pos,
+            _ => velocity,
+        };
+        if let Some(rot) = enemy.steering.turn_towards(facing, time_delta) {
+            transform.rot = rot;
+        }
+
+        // Let the animation state machine pick the animation if there is one, or update it if it changed
+        if let Some(mut anim_state) = anim_state {
+            anim_state.params.attacking = output.animation == AgentAnimation::Attack;
+            continue;
+        }
+        let animation = enemy.animation(output.animation).to_string();
+        if enemy.current_animation.as_ref() != Some(&animation) {
+            visual.animation = Some(Animation::Loop(animation.clone()));
+            enemy.current_animation = Some(animation);
+        }
+    }
+}
+
+/// The enemy impact system, which lands the attacks of enemies that hit on their animations'
+/// Impact markers
+pub fn enemy_impacts(mut animation_events: EventReader<AnimationEvent>, mut damage_events: EventWriter<DamageEvent>,
+    player_query: Query<(Entity, &Transform), (With<PlayerMovement>, With<Health>)>,
+    enemies: Query<(&Enemy, &Transform), Without<PlayerMovement>>)
+{
+    let (player, player_transform) = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    for event in animation_events.iter().filter(|event| event.event == AnimEventKind::Impact) {
+        let (enemy, transform) = match enemies.get(event.entity) {
+            Ok(enemy) => enemy,
+            Err(_) => continue,
+        };
+
+        if enemy.hit_on_impact && enemy.attacking && (player_transform.pos - transform.pos).magnitude() <= enemy.attack_range {
+            damage_events.send(DamageEvent {
+                target: player,
+                amount: enemy.attack_damage,
+                source: Some(event.entity),
+            });
+        }
+    }
+}
@@ -1,28 +1,59 @@
-use bevy_ecs::{prelude::EventReader, system::Commands};
-use cgmath::{Matrix4, Matrix3, Vector3, vec3};
-use dreamfield_renderer::components::{Visual, Animation};
-use dreamfield_system::{systems::entity_spawner::EntitySpawnEvent, components::{Transform, EntityName}, intersection::{Collider, Shape}};
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventReader, With};
+use bevy_ecs::system::{Commands, Res, ResMut, Query};
+use cgmath::{Matrix4, Matrix3, Vector3, SquareMatrix, InnerSpace, vec3};
+use serde::Deserialize;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+use dreamfield_system::systems::entity_spawner::EntitySpawnEvent;
 
 use super::minecart::Minecart;
+use super::zip_line::ZipLine;
+use super::trigger::TriggerVolume;
+use super::prefab::PrefabRegistry;
+use super::player_movement::PlayerMovement;
+use super::determinism::SimRng;
+
+/// The prefabs spawned for each object id in the world models
+const OBJECT_PREFABS: &'static [(&'static str, &'static str)] = &[
+    ("Elf", "npc_elf"),
+    ("Minecart", "minecart"),
+    ("Door", "door"),
+    ("LockedDoor", "locked_door"),
+    ("Checkpoint", "checkpoint"),
+    ("Torch", "torch"),
+    ("UnlitTorch", "unlit_torch"),
+    ("Ladder", "ladder"),
+    ("WaterVolume", "water_volume"),
+    ("ZipLine", "zip_line"),
+    ("Enemy", "enemy"),
+];
 
 /// The entity spawner
-pub fn entity_spawner(mut commands: Commands, mut reader: EventReader<EntitySpawnEvent>) {
+pub fn entity_spawner(mut commands: Commands, prefabs: Res<PrefabRegistry>, mut reader: EventReader<EntitySpawnEvent>) {
     for event in reader.iter() {
         let (pos, rot) = decompose_transform(event.entity_info.world_transform());
-        match event.entity_info.object_id() {
-            "Elf" => {
-                commands.spawn()
-                    .insert(Transform::new(pos, rot))
-                    .insert(EntityName::new("Elf"))
-                    .insert(Collider::new(Shape::BoundingSpheroid(vec3(0.0, 1.0, 0.0), vec3(0.25, 1.0, 0.25))))
-                    .insert(Visual::new_with_anim("elf", false, Animation::Loop("Idle".to_string())));
-            },
-            "Minecart" => {
-                let mut entity = commands.spawn();
-                entity.insert(EntityName::new("Minecart"))
-                      .insert(Transform::new(pos, rot))
-                      .insert(Visual::new("minecart", false));
+        let object_id = event.entity_info.object_id();
+
+        let prefab = match OBJECT_PREFABS.iter().find(|(id, _)| *id == object_id) {
+            Some((_, prefab)) => prefab,
+            None => {
+                log::warn!("Asked to spawn unknown entity: {:?}", event.entity_info);
+                continue;
+            }
+        };
+
+        let mut entity = commands.spawn();
+        if !prefabs.build(&mut entity, prefab, pos, rot, &[]) {
+            entity.despawn();
+            continue;
+        }
 
+        // Some entities also need data from their mesh
+        match object_id {
+            "Minecart" => {
                 if let Some(points) = event.entity_info.mesh() {
                     let track_points = points.iter().map(|p| p.as_vec().clone()).collect();
                     entity.insert(Minecart::new(track_points));
@@ -31,9 +62,35 @@ pub fn entity_spawner(mut commands: Commands, mut reader: EventReader<EntitySpaw
                     log::warn!("Minecart has no points");
                 }
             },
-            _ => {
-                log::warn!("Asked to spawn unknown entity: {:?}", event.entity_info);
-            }
+            "WaterVolume" => {
+                // The water's extents come from its mesh
+                let points = event.entity_info.mesh().map(|points| points.iter().map(|p| *p.as_vec()).collect::<Vec<_>>());
+                match points.filter(|points| !points.is_empty()) {
+                    Some(points) => {
+                        let min = points.iter().fold(points[0], |a, b| vec3(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)));
+                        let max = points.iter().fold(points[0], |a, b| vec3(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)));
+                        entity.insert(TriggerVolume::new_aabb(min - pos, max - pos));
+                    },
+                    None => {
+                        log::warn!("WaterVolume has no points");
+                        entity.despawn();
+                    },
+                }
+            },
+            "ZipLine" => {
+                // The anchors are the ends of its mesh
+                let points = event.entity_info.mesh().map(|points| points.iter().map(|p| *p.as_vec()).collect::<Vec<_>>());
+                match points.filter(|points| points.len() >= 2) {
+                    Some(points) => {
+                        entity.insert(ZipLine::new(points[0], points[points.len() - 1]));
+                    },
+                    None => {
+                        log::warn!("ZipLine needs at least two points");
+                        entity.despawn();
+                    },
+                }
+            },
+            _ => {},
         }
     }
 }
@@ -49,3 +106,167 @@ fn decompose_transform(transform: &Matrix4<f32>) -> (Vector3<f32>, Matrix3<f32>)
 
     (pos, rot)
 }
+
+/// A weighted entry in a spawn table
+#[derive(Deserialize, Debug)]
+pub struct SpawnTableEntry {
+    pub prefab: String,
+    pub weight: f32,
+    /// The range of distances from the player that the entity is spawned at
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// The maximum number of instances of this entry spawned by an area at once
+    pub max_instances: usize,
+}
+
+/// A spawn table, loaded from a RON file
+#[derive(Deserialize, Debug)]
+pub struct SpawnTable {
+    /// The number of seconds between spawns
+    pub spawn_interval: f32,
+    /// The distance from the player at which spawned entities are despawned
+    pub despawn_radius: f32,
+    /// The height above the player that entities are spawned at
+    #[serde(default)]
+    pub spawn_height: f32,
+    pub entries: Vec<SpawnTableEntry>,
+}
+
+/// The spawn tables, which store all the spawn tables by name
+pub struct SpawnTables {
+    tables: HashMap<String, SpawnTable>,
+}
+
+impl SpawnTables {
+    /// Create the spawn tables from a RON map of table name to definition
+    pub fn new(source: &str) -> Self {
+        let tables = match ron::from_str(source) {
+            Ok(tables) => tables,
+            Err(err) => {
+                log::error!("Failed to load spawn tables: {}", err);
+                HashMap::new()
+            }
+        };
+
+        SpawnTables { tables }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SpawnTable> {
+        self.tables.get(name)
+    }
+}
+
+/// The SpawnBudget resource, the maximum number of entities spawn areas can have spawned at once
+/// across the whole world
+pub struct SpawnBudget {
+    pub max_entities: usize,
+}
+
+impl Default for SpawnBudget {
+    fn default() -> Self {
+        SpawnBudget { max_entities: 16 }
+    }
+}
+
+/// The SpawnArea component. Spawn areas need a TriggerVolume, and spawn entities from their spawn
+/// table around the player while the player's inside it.
+#[derive(Component)]
+pub struct SpawnArea {
+    pub table: String,
+    spawn_timer: f32,
+}
+
+impl SpawnArea {
+    pub fn new(table: &str) -> Self {
+        SpawnArea { table: table.to_string(), spawn_timer: 0.0 }
+    }
+}
+
+/// The SpawnedBy component, for entities spawned by a spawn area
+#[derive(Component)]
+pub struct SpawnedBy {
+    pub area: Entity,
+    /// The index of the entry in the area's spawn table
+    pub entry: usize,
+    pub despawn_radius: f32,
+}
+
+/// The spawn area system, which spawns entities from spawn tables within the global budget, and
+/// despawns them again when the player gets too far away
+pub fn update_spawn_areas(mut commands: Commands, sim_time: Res<SimTime>, prefabs: Res<PrefabRegistry>,
+    tables: Res<SpawnTables>, budget: Res<SpawnBudget>, mut rng: ResMut<SimRng>,
+    player_query: Query<&Transform, With<PlayerMovement>>,
+    mut areas: Query<(Entity, &mut SpawnArea, &TriggerVolume)>,
+    spawned: Query<(Entity, &SpawnedBy, &Transform)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    let player_pos = match player_query.get_single() {
+        Ok(transform) => transform.pos,
+        Err(_) => return,
+    };
+
+    // Despawn entities that the player's left behind
+    let mut live = Vec::new();
+    for (entity, spawned_by, transform) in spawned.iter() {
+        if (transform.pos - player_pos).magnitude() > spawned_by.despawn_radius {
+            commands.entity(entity).despawn();
+        }
+        else {
+            live.push((spawned_by.area, spawned_by.entry));
+        }
+    }
+
+    for (area_entity, mut area, trigger) in areas.iter_mut() {
+        if !trigger.player_inside() || live.len() >= budget.max_entities {
+            area.spawn_timer = 0.0;
+            continue;
+        }
+
+        let table = match tables.get(&area.table) {
+            Some(table) => table,
+            None => {
+                log::warn!("Spawn area has unknown spawn table {}", area.table);
+                continue;
+            }
+        };
+
+        area.spawn_timer += time_delta;
+        if area.spawn_timer < table.spawn_interval {
+            continue;
+        }
+        area.spawn_timer = 0.0;
+
+        // Pick from the entries that aren't already at their limit, by weight
+        let instances = |index: usize| live.iter().filter(|spawned| **spawned == (area_entity, index)).count();
+        let available: Vec<(usize, &SpawnTableEntry)> = table.entries.iter().enumerate()
+            .filter(|(index, entry)| instances(*index) < entry.max_instances)
+            .collect();
+
+        let total_weight: f32 = available.iter().map(|(_, entry)| entry.weight).sum();
+        if total_weight <= 0.0 {
+            continue;
+        }
+
+        let mut pick = rng.next() * total_weight;
+        let (index, entry) = *available.iter()
+            .find(|(_, entry)| {
+                pick -= entry.weight;
+                pick <= 0.0
+            })
+            .unwrap_or(&available[available.len() - 1]);
+
+        let angle = rng.next() * std::f32::consts::TAU;
+        let distance = entry.min_distance + (entry.max_distance - entry.min_distance) * rng.next();
+        let pos = player_pos + vec3(angle.cos() * distance, table.spawn_height, angle.sin() * distance);
+
+        let mut entity = commands.spawn();
+        if prefabs.build(&mut entity, &entry.prefab, pos, Matrix3::identity(), &[]) {
+            entity.insert(SpawnedBy { area: area_entity, entry: index, despawn_radius: table.despawn_radius });
+            live.push((area_entity, index));
+        }
+        else {
+            entity.despawn();
+        }
+    }
+}
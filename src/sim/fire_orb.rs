@@ -1,26 +1,106 @@
 use bevy_ecs::component::Component;
-use bevy_ecs::system::{Res, Query};
-use cgmath::{Matrix3, Rad};
+use bevy_ecs::prelude::{Entity, With, Without};
+use bevy_ecs::query::ChangeTrackers;
+use bevy_ecs::system::{Res, Query, Commands};
+use cgmath::{Matrix3, Rad, InnerSpace, vec2};
+use dreamfield_renderer::components::TextBox;
 use dreamfield_system::resources::SimTime;
 use dreamfield_system::components::Transform;
 
+use super::PlayerMovement;
+use super::inventory::{PlayerInventory, ItemRegistry};
+use super::locale::Locale;
+
+/// The item id of fire orbs in the inventory
+pub const FIRE_ORB_ITEM: &'static str = "fire_orb";
+
+/// The number of seconds the pickup animation lasts
+const PICKUP_DURATION: f32 = 0.5;
+
+/// How far the orb rises during the pickup animation
+const PICKUP_RISE: f32 = 1.5;
+
 /// The fire orb component
 #[derive(Component)]
 pub struct FireOrb {
+    /// The distance from the player at which the orb is collected
+    pub pickup_radius: f32,
+    /// The height the orb bobs around
+    pub base_height: f32,
+    /// Seconds since the orb was collected, if it has been
+    collected_time: Option<f32>,
 }
 
 impl Default for FireOrb {
     fn default() -> Self {
-        Self {}
+        Self {
+            pickup_radius: 1.0,
+            base_height: 2.0,
+            collected_time: None,
+        }
     }
 }
 
+/// The orb counter component, for the TextBox showing how many orbs have been collected
+#[derive(Component)]
+pub struct OrbCounter;
+
 /// The fire orb movement system
-pub fn fire_orb_movement(sim_time: Res<SimTime>, mut query: Query<(&mut FireOrb, &mut Transform)>)
+pub fn fire_orb_movement(mut commands: Commands, sim_time: Res<SimTime>, registry: Res<ItemRegistry>,
+    mut player_query: Query<(&Transform, &mut PlayerInventory), With<PlayerMovement>>,
+    mut query: Query<(Entity, &mut FireOrb, &mut Transform), Without<PlayerMovement>>)
+{
+    let mut player = player_query.get_single_mut().ok();
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (entity, mut orb, mut transform) in query.iter_mut() {
+        let ball_height = sim_time.sim_time.sin() as f32 + orb.base_height;
+        let rotation = Matrix3::from_angle_y(Rad(sim_time.sim_time as f32));
+
+        match orb.collected_time {
+            None => {
+                transform.pos.y = ball_height;
+                transform.rot = rotation;
+
+                // Collect the orb if the player is in range. The orbs bob quite high, so ignore the
+                // vertical distance.
+                if let Some((player_transform, inventory)) = player.as_mut() {
+                    let to_player = player_transform.pos - transform.pos;
+                    if vec2(to_player.x, to_player.z).magnitude() < orb.pickup_radius {
+                        // Leave the orb where it is if the inventory is full
+                        if inventory.add(&registry, FIRE_ORB_ITEM, 1) == 0 {
+                            orb.collected_time = Some(0.0);
+                        }
+                    }
+                }
+            },
+            Some(collected_time) => {
+                // Rise and shrink, then despawn
+                let collected_time = collected_time + time_delta;
+                let progress = f32::min(collected_time / PICKUP_DURATION, 1.0);
+
+                transform.pos.y += PICKUP_RISE * time_delta / PICKUP_DURATION;
+                transform.rot = rotation * (1.0 - progress);
+                orb.collected_time = Some(collected_time);
+
+                if progress >= 1.0 {
+                    commands.entity(entity).despawn();
+                }
+            }
+        }
+    }
+}
+
+/// The orb counter system, which updates the counter when the inventory or the language changes
+pub fn update_orb_counter(locale: Res<Locale>, player_query: Query<(&PlayerInventory, ChangeTrackers<PlayerInventory>)>,
+    mut query: Query<&mut TextBox, With<OrbCounter>>)
 {
-    for (_, mut transform) in query.iter_mut() {
-        let ball_height = sim_time.sim_time.sin() as f32 + 2.0;
-        transform.pos.y = ball_height;
-        transform.rot = Matrix3::from_angle_y(Rad(sim_time.sim_time as f32));
+    for (inventory, tracker) in player_query.iter() {
+        if !tracker.is_changed() && !locale.is_changed() {
+            continue;
+        }
+        for mut text_box in query.iter_mut() {
+            text_box.text = locale.format("hud.orbs", &[("count", &inventory.count(FIRE_ORB_ITEM))]);
+        }
     }
 }
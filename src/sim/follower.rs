@@ -0,0 +1,205 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, With, Without};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, InnerSpace, Zero, vec3};
+use serde::Deserialize;
+use dreamfield_renderer::components::{Visual, Animation};
+use dreamfield_system::components::Transform;
+use dreamfield_system::intersection::Collider;
+use dreamfield_system::resources::SimTime;
+
+use super::PlayerMovement;
+use super::character_controller::CharacterController;
+use super::static_collision::StaticCollision;
+use super::nav::{NavMesh, NavAgent};
+use super::steering::Steering;
+
+/// The distance at which a follower has reached the point it's wandering to
+const WANDER_ARRIVE_DISTANCE: f32 = 0.25;
+
+/// What a follower does while it's waiting for the player to move
+#[derive(Deserialize, Clone, Debug)]
+pub enum FollowerIdle {
+    /// Stand still, turning to face the player
+    FacePlayer,
+    /// Wander to a nearby point around the player every so many seconds
+    Wander {
+        radius: f32,
+        interval: f32,
+    },
+    /// Play an animation once the follower has been idle for a number of seconds, like sitting down
+    Animation {
+        name: String,
+        delay: f32,
+    },
+}
+
+/// The Follower component, for companions that follow the player around. Followers with a
+/// CharacterController and Collider walk along the ground, colliding with the world, and followers
+/// with a NavAgent find their way around obstacles. They teleport to the player if they fall too
+/// far behind.
+#[derive(Component)]
+pub struct Follower {
+    /// How close the follower tries to stay to the player
+    pub follow_distance: f32,
+    /// How far behind the follower has to be before it runs to catch up
+    pub run_distance: f32,
+    /// How far behind the follower has to be before it gives up and teleports to the player
+    pub teleport_distance: f32,
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    pub idle: FollowerIdle,
+    pub idle_animation: String,
+    pub walk_animation: String,
+    pub steering: Steering,
+    /// How many seconds the follower has been waiting for
+    idle_time: f32,
+    /// Where the follower is wandering to while the player waits
+    wander_target: Option<Vector3<f32>>,
+    /// A counter for picking wander points
+    wander_count: u32,
+    current_animation: Option<String>,
+}
+
+impl Follower {
+    pub fn new(idle: FollowerIdle, idle_animation: &str, walk_animation: &str) -> Self {
+        Follower {
+            follow_distance: 2.0,
+            run_distance: 6.0,
+            teleport_distance: 25.0,
+            walk_speed: 2.0,
+            run_speed: 5.0,
+            idle,
+            idle_animation: idle_animation.to_string(),
+            walk_animation: walk_animation.to_string(),
+            steering: Steering::default(),
+            idle_time: 0.0,
+            wander_target: None,
+            wander_count: 0,
+            current_animation: None,
+        }
+    }
+
+    /// Pick the next point to wander to around the player, spreading them out around a circle
+    fn next_wander_point(&mut self, player_pos: Vector3<f32>, radius: f32) -> Vector3<f32> {
+        self.wander_count += 1;
+        let angle = self.wander_count as f32 * 2.4;
+        player_pos + vec3(angle.cos(), 0.0, angle.sin()) * radius
+    }
+}
+
+/// The follower system
+pub fn update_followers(sim_time: Res<SimTime>, static_collision: Res<StaticCollision>,
+    nav_mesh: Res<NavMesh>, player_query: Query<&Transform, With<PlayerMovement>>,
+    mut query: Query<(Entity, &mut Follower, &mut Transform, &mut Visual, Option<&CharacterController>,
+        Option<&Collider>, Option<&mut NavAgent>), Without<PlayerMovement>>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    let player_transform = match player_query.get_single() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    let player_pos = player_transform.pos;
+
+    for (entity, mut follower, mut transform, mut visual, controller, collider, mut nav_agent) in query.iter_mut() {
+        let to_player = vec3(player_pos.x - transform.pos.x, 0.0, player_pos.z - transform.pos.z);
+        let distance = to_player.magnitude();
+
+        // Teleport behind the player if we've fallen too far behind
+        if distance > follower.teleport_distance {
+            let forward = player_transform.rot * vec3(0.0, 0.0, -1.0);
+            let behind = player_pos - vec3(forward.x, 0.0, forward.z).normalize() * follower.follow_distance;
+            transform.pos = nav_mesh.nearest_point(behind).unwrap_or(player_pos);
+            follower.steering.velocity = Vector3::zero();
+            follower.wander_target = None;
+            continue;
+        }
+
+        // Catch up with the player when they get away, and otherwise do whatever we do while waiting
+        let mut animation = follower.idle_animation.clone();
+        let target = match distance > follower.follow_distance && follower.wander_target.is_none() {
+            true => {
+                follower.idle_time = 0.0;
+                let speed = match distance > follower.run_distance {
+                    true => follower.run_speed,
+                    false => follower.walk_speed,
+                };
+                // Aim for a bit closer than the follow distance, so we don't start and stop with
+                // every step the player takes
+                Some((player_pos - to_player.normalize() * follower.follow_distance * 0.5, speed))
+            },
+            false => {
+                follower.idle_time += time_delta;
+                match follower.idle.clone() {
+                    FollowerIdle::FacePlayer => None,
+                    FollowerIdle::Wander { radius, interval } => {
+                        if follower.wander_target.is_none() && follower.idle_time >= interval {
+                            follower.idle_time = 0.0;
+                            let point = follower.next_wander_point(player_pos, radius);
+                            follower.wander_target = nav_mesh.nearest_point(point);
+                        }
+
+                        // Stop wandering when we get there, or the player leaves
+                        let arrived = follower.wander_target
+                            .map(|target| {
+                                let offset = vec3(target.x - transform.pos.x, 0.0, target.z - transform.pos.z);
+                                offset.magnitude() < WANDER_ARRIVE_DISTANCE
+                            })
+                            .unwrap_or(true);
+                        if arrived || distance > follower.run_distance {
+                            follower.wander_target = None;
+                        }
+                        follower.wander_target.map(|target| (target, follower.walk_speed))
+                    },
+                    FollowerIdle::Animation { name, delay } => {
+                        if follower.idle_time >= delay {
+                            animation = name;
+                        }
+                        None
+                    },
+                }
+            }
+        };
+
+        // Steer towards the target, keeping out of the player's way
+        let (desired, speed) = match target {
+            Some((target, speed)) => {
+                let next_point = match nav_agent.as_mut() {
+                    Some(nav_agent) => nav_agent.steer(&nav_mesh, transform.pos, target, time_delta),
+                    None => target,
+                };
+                let desired = follower.steering.desired_velocity(&static_collision, transform.pos, next_point,
+                    target, speed, &[player_pos], entity);
+                (desired, speed)
+            },
+            None => (Vector3::zero(), follower.walk_speed),
+        };
+        let velocity = follower.steering.update(desired, speed, time_delta);
+
+        if velocity.magnitude2() > 0.0 {
+            transform.pos = match (controller, collider) {
+                (Some(controller), Some(collider)) => controller.move_and_slide(&static_collision,
+                    collider, transform.pos, velocity, true, entity, time_delta),
+                _ => transform.pos + velocity * time_delta,
+            };
+            if velocity.magnitude() > 0.1 {
+                animation = follower.walk_animation.clone();
+            }
+        }
+
+        // Face where we're going, or the player while we're waiting
+        let facing = match target {
+            Some(_) => velocity,
+            None => to_player,
+        };
+        if let Some(rot) = follower.steering.turn_towards(facing, time_delta) {
+            transform.rot = rot;
+        }
+
+        // Update the animation if it changed
+        if follower.current_animation.as_ref() != Some(&animation) {
+            visual.animation = Some(Animation::Loop(animation.clone()));
+            follower.current_animation = Some(animation);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use bevy_ecs::system::{Res, ResMut, Query};
+use bevy_ecs::world::World;
+use cgmath::{Vector2, Vector3, Matrix4, Quaternion, Rotation3, Rad, SquareMatrix, InnerSpace, Zero, vec2, vec3};
+use dreamfield_renderer::components::PlayerCamera;
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::console::ConsoleCommands;
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::spawn_points::place_player;
+
+/// The free camera's default fly speed
+const DEFAULT_FLY_SPEED: f32 = 8.0;
+
+/// How much faster the free camera flies while sprinting
+const SPRINT_MULTIPLIER: f32 = 4.0;
+
+/// The limits on the fly speed that can be set
+const MIN_FLY_SPEED: f32 = 0.1;
+const MAX_FLY_SPEED: f32 = 200.0;
+
+/// The free camera's look speed
+const LOOK_SPEED: f32 = 1.0;
+
+/// The FreeCamera resource, for the debug camera that flies around on its own while the player stays
+/// where they are. It's toggled from the console with the freecam command. While it's active the
+/// player ignores input and doesn't move the camera, and the sim keeps running.
+pub struct FreeCamera {
+    pub active: bool,
+    /// How fast the camera flies, in world units per second
+    pub speed: f32,
+    pos: Vector3<f32>,
+    pitch_yaw: Vector2<f32>,
+}
+
+impl Default for FreeCamera {
+    fn default() -> Self {
+        FreeCamera {
+            active: false,
+            speed: DEFAULT_FLY_SPEED,
+            pos: Vector3::zero(),
+            pitch_yaw: Vector2::zero(),
+        }
+    }
+}
+
+impl FreeCamera {
+    fn orientation(&self) -> Quaternion<f32> {
+        Quaternion::from_axis_angle(vec3(0.0, 1.0, 0.0), Rad(self.pitch_yaw.y))
+            * Quaternion::from_axis_angle(vec3(1.0, 0.0, 0.0), Rad(self.pitch_yaw.x))
+    }
+
+    /// Start flying from where the camera is, from its view matrix
+    fn enter(&mut self, view: Matrix4<f32>) {
+        let cam_transform = view.invert().unwrap_or(Matrix4::identity());
+        let forward = (cam_transform * vec3(0.0, 0.0, -1.0).extend(0.0)).truncate();
+
+        self.active = true;
+        self.pos = cam_transform.w.truncate();
+        self.pitch_yaw = vec2(forward.y.asin(), f32::atan2(-forward.x, -forward.z));
+    }
+}
+
+/// The free camera system, which flies the camera around with the player's controls while the free
+/// camera's active. It runs after the player update, which leaves the camera alone while it's
+/// active, and before the camera shake.
+pub fn update_free_camera(sim_time: Res<SimTime>, input: Res<InputState>, gamepad: Res<GamepadState>,
+    bindings: Res<InputBindings>, mut free_camera: ResMut<FreeCamera>, mut cam_query: Query<&mut PlayerCamera>)
+{
+    if !free_camera.active {
+        return;
+    }
+
+    let mut cam = match cam_query.get_single_mut() {
+        Ok(cam) => cam,
+        Err(_) => return,
+    };
+
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    // Look around
+    let look = controls.look();
+    free_camera.pitch_yaw.x = f32::clamp(free_camera.pitch_yaw.x + look.y * LOOK_SPEED * time_delta,
+        -std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+    free_camera.pitch_yaw.y += look.x * LOOK_SPEED * time_delta;
+
+    // Fly in the direction we're looking, and up and down with jump and crouch
+    let axis = |positive: Action, negative: Action| {
+        (controls.is_held(positive) as i32 - controls.is_held(negative) as i32) as f32
+    };
+    let orientation = free_camera.orientation();
+    let movement = controls.movement();
+    let mut velocity = orientation * vec3(movement.y, 0.0, -movement.x);
+    velocity.y += axis(Action::Jump, Action::Crouch);
+    if velocity.magnitude2() > 1.0 {
+        velocity = velocity.normalize();
+    }
+    let speed = match controls.is_held(Action::Sprint) {
+        true => free_camera.speed * SPRINT_MULTIPLIER,
+        false => free_camera.speed,
+    };
+    free_camera.pos += velocity * speed * time_delta;
+
+    let cam_transform = Matrix4::from_translation(free_camera.pos) * Matrix4::from(orientation);
+    cam.view = cam_transform.invert().unwrap_or(Matrix4::identity());
+}
+
+/// Toggle the free camera, starting it from the player's camera
+fn toggle_free_camera(world: &mut World) -> bool {
+    let cam = world.query::<&PlayerCamera>().iter(world).next().map(|cam| cam.view);
+    let mut free_camera = world.resource_mut::<FreeCamera>();
+    match (free_camera.active, cam) {
+        (true, _) => free_camera.active = false,
+        (false, Some(view)) => free_camera.enter(view),
+        (false, None) => {},
+    }
+    free_camera.active
+}
+
+/// Register the freecam console command
+pub fn register_freecam_command(commands: &mut ConsoleCommands) {
+    commands.register("freecam", "freecam [speed <speed> | teleport]",
+        "Toggle the free camera, set how fast it flies, or teleport the player to it", |world, args| {
+        match args {
+            [] => Ok(match toggle_free_camera(world) {
+                true => "Free camera on".to_string(),
+                false => "Free camera off".to_string(),
+            }),
+            ["speed", speed] => {
+                let speed: f32 = speed.parse().map_err(|_| format!("Invalid speed: {}", speed))?;
+                let speed = speed.clamp(MIN_FLY_SPEED, MAX_FLY_SPEED);
+                world.resource_mut::<FreeCamera>().speed = speed;
+                Ok(format!("Free camera speed {}", speed))
+            },
+            ["teleport"] => {
+                let free_camera = world.resource::<FreeCamera>();
+                if !free_camera.active {
+                    return Err("The free camera's off".to_string());
+                }
+
+                // The player's feet are put where the camera is, facing the way it's facing
+                let (pos, pitch_yaw) = (free_camera.pos, free_camera.pitch_yaw);
+                world.resource_mut::<FreeCamera>().active = false;
+                place_player(world, pos, pitch_yaw);
+                Ok(format!("Teleported to {:.2} {:.2} {:.2}", pos.x, pos.y, pos.z))
+            },
+            _ => Err("Usage: freecam [speed <speed> | teleport]".to_string()),
+        }
+    }).with_completion(|_, arg| match arg {
+        0 => vec!["speed".to_string(), "teleport".to_string()],
+        _ => Vec::new(),
+    });
+}
@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+
+use bevy_ecs::event::Events;
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::world::World;
+
+/// The GameState resource, which part of the game is running. The sim only runs InGame, and the
+/// UI systems check it to decide what to show.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameState {
+    /// The title screen and its menu
+    Title,
+    /// The game is waiting for its assets and the world around the player to load
+    Loading,
+    InGame,
+    /// The pause menu is open
+    Paused,
+    /// The player has died, and can continue from their last checkpoint or go back to the title
+    GameOver,
+}
+
+/// The NextGameState resource, which systems set to change the game state. The change is applied
+/// at the start of the next tick, so every system sees the same state for a whole tick.
+#[derive(Default)]
+pub struct NextGameState(Option<GameState>);
+
+impl NextGameState {
+    pub fn set(&mut self, state: GameState) {
+        self.0 = Some(state);
+    }
+}
+
+/// An event sent when the game state changes
+pub struct GameStateChangedEvent {
+    pub from: GameState,
+    pub to: GameState,
+}
+
+/// The GameScenes resource, with the functions that spawn the entities for the title screen and
+/// the game world. Entering the title or finishing loading despawns every entity spawned since the
+/// first scene was, which includes anything the sim spawned while it was running, and spawns the
+/// new scene. Anything that exists before then, like the UI, is kept.
+pub struct GameScenes {
+    pub spawn_title: fn(&mut World),
+    pub spawn_game: fn(&mut World),
+    persistent: Option<HashSet<Entity>>,
+    game_loaded: bool,
+}
+
+impl GameScenes {
+    pub fn new(spawn_title: fn(&mut World), spawn_game: fn(&mut World)) -> Self {
+        GameScenes {
+            spawn_title,
+            spawn_game,
+            persistent: None,
+            game_loaded: false,
+        }
+    }
+}
+
+/// Change the game state straight away, spawning and despawning scenes as needed
+pub fn set_game_state(world: &mut World, state: GameState) {
+    let current = *world.resource::<GameState>();
+    log::info!("Game state changed from {:?} to {:?}", current, state);
+
+    // The current scene stays up while loading, and going between the game and the pause or game
+    // over menus, or loading another level, doesn't reload the game
+    let scenes = world.resource::<GameScenes>();
+    let spawn = match (current, state, scenes.game_loaded) {
+        (_, GameState::Title, _) => Some((scenes.spawn_title, false)),
+        (GameState::Loading, GameState::InGame, false) => Some((scenes.spawn_game, true)),
+        _ => None,
+    };
+
+    if let Some((spawn, game_loaded)) = spawn {
+        world.resource_mut::<GameScenes>().game_loaded = game_loaded;
+        change_scene(world, spawn);
+    }
+
+    world.insert_resource(state);
+    world.resource_mut::<Events<GameStateChangedEvent>>().send(GameStateChangedEvent { from: current, to: state });
+}
+
+/// Despawn the current scene and spawn a new one
+fn change_scene(world: &mut World, spawn: fn(&mut World)) {
+    let all_entities = |world: &mut World| -> HashSet<Entity> {
+        world.query::<Entity>().iter(world).collect()
+    };
+
+    let persistent = world.resource_mut::<GameScenes>().persistent.take();
+    match persistent {
+        Some(persistent) => {
+            let scene_entities: Vec<Entity> = all_entities(world).difference(&persistent).copied().collect();
+            for entity in scene_entities {
+                world.despawn(entity);
+            }
+
+            world.resource_mut::<GameScenes>().persistent = Some(persistent);
+        },
+        None => {
+            let persistent = all_entities(world);
+            world.resource_mut::<GameScenes>().persistent = Some(persistent);
+        }
+    }
+
+    spawn(world);
+}
+
+/// The game state transition system, which applies any state change requested last tick
+pub fn apply_game_state_transitions(world: &mut World) {
+    let next = world.resource_mut::<NextGameState>().0.take();
+    match next {
+        Some(next) if next != *world.resource::<GameState>() => set_game_state(world, next),
+        _ => (),
+    }
+}
@@ -0,0 +1,189 @@
+use bevy_ecs::system::{NonSendMut, Res, ResMut};
+use cgmath::{Vector2, vec2, Zero, InnerSpace};
+use gilrs::{Gilrs, Axis, Button};
+
+use super::input_bindings::EngineInput;
+
+pub use gilrs::Button as GamepadButton;
+
+/// The gamepad buttons we track
+const TRACKED_BUTTONS: &'static [Button] = &[
+    Button::South, Button::East, Button::West, Button::North,
+    Button::LeftTrigger, Button::RightTrigger, Button::LeftTrigger2, Button::RightTrigger2,
+    Button::LeftThumb, Button::RightThumb, Button::Start, Button::Select,
+    Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+];
+
+/// The gamepad context, which owns the gilrs instance. This isn't Send on every platform, so it's
+/// stored as a non-send resource.
+pub struct GamepadContext {
+    gilrs: Option<Gilrs>,
+}
+
+impl GamepadContext {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(err) => {
+                log::warn!("Failed to initialise gamepad support: {}", err);
+                None
+            }
+        };
+
+        GamepadContext { gilrs }
+    }
+}
+
+/// The GamepadSettings resource
+pub struct GamepadSettings {
+    /// The radial deadzone for the sticks, from 0 to 1
+    pub deadzone: f32,
+    /// The look speed multiplier for the right stick
+    pub look_sensitivity: f32,
+    /// The movement speed multiplier for the left stick
+    pub move_sensitivity: f32,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        GamepadSettings {
+            deadzone: 0.2,
+            look_sensitivity: 2.0,
+            move_sensitivity: 1.0,
+        }
+    }
+}
+
+/// The GamepadState resource, the state of the active gamepad this tick, along with the keyboard
+/// keys bound to actions, which the engine's input doesn't have and which are recorded and played
+/// back alongside the gamepad
+pub struct GamepadState {
+    /// Movement input as (forward, right)
+    pub movement: Vector2<f32>,
+    /// Look input as (horizontal, vertical), in the same convention as InputState::get_look_input
+    pub look: Vector2<f32>,
+    /// The engine input being played back by a replay, which takes the place of the InputState
+    pub replayed_input: Option<EngineInput>,
+    held: Vec<Button>,
+    last_held: Vec<Button>,
+    held_keys: Vec<i32>,
+    last_held_keys: Vec<i32>,
+}
+
+impl Default for GamepadState {
+    fn default() -> Self {
+        GamepadState {
+            movement: Vector2::zero(),
+            look: Vector2::zero(),
+            replayed_input: None,
+            held: Vec::new(),
+            last_held: Vec::new(),
+            held_keys: Vec::new(),
+            last_held_keys: Vec::new(),
+        }
+    }
+}
+
+impl GamepadState {
+    pub fn is_held(&self, button: Button) -> bool {
+        self.held.contains(&button)
+    }
+
+    pub fn is_just_pressed(&self, button: Button) -> bool {
+        self.held.contains(&button) && !self.last_held.contains(&button)
+    }
+
+    pub fn is_key_held(&self, key: i32) -> bool {
+        self.held_keys.contains(&key)
+    }
+
+    pub fn is_key_just_pressed(&self, key: i32) -> bool {
+        self.held_keys.contains(&key) && !self.last_held_keys.contains(&key)
+    }
+
+    /// Check whether any button or bound key is held or a stick is being used
+    pub fn any(&self) -> bool {
+        !self.held.is_empty() || !self.held_keys.is_empty() || !self.movement.is_zero()
+    }
+
+    /// Set the bound keys held this tick, keeping the last tick's to tell when they're pressed
+    pub fn set_held_keys(&mut self, held_keys: Vec<i32>) {
+        self.last_held_keys = std::mem::replace(&mut self.held_keys, held_keys);
+    }
+
+    /// Get the bound keys held this tick and last tick, for recording input
+    pub fn key_sets(&self) -> (Vec<i32>, Vec<i32>) {
+        (self.held_keys.clone(), self.last_held_keys.clone())
+    }
+
+    /// Set the bound keys held this tick and last tick, for playing back input
+    pub fn set_key_sets(&mut self, held_keys: Vec<i32>, last_held_keys: Vec<i32>) {
+        self.held_keys = held_keys;
+        self.last_held_keys = last_held_keys;
+    }
+
+    /// Get the buttons held this tick and last tick as bit masks of the tracked buttons, for
+    /// recording input
+    pub fn button_masks(&self) -> (u32, u32) {
+        let mask = |buttons: &[Button]| TRACKED_BUTTONS.iter().enumerate()
+            .filter(|(_, button)| buttons.contains(button))
+            .fold(0, |mask, (index, _)| mask | 1 << index);
+        (mask(&self.held), mask(&self.last_held))
+    }
+
+    /// Set the buttons held this tick and last tick from bit masks, for playing back input
+    pub fn set_button_masks(&mut self, held: u32, last_held: u32) {
+        let buttons = |mask: u32| TRACKED_BUTTONS.iter().enumerate()
+            .filter(|(index, _)| mask & (1 << index) != 0)
+            .map(|(_, button)| *button)
+            .collect();
+        self.held = buttons(held);
+        self.last_held = buttons(last_held);
+    }
+}
+
+/// The gamepad update system
+pub fn update_gamepad(mut context: NonSendMut<GamepadContext>, settings: Res<GamepadSettings>,
+    mut state: ResMut<GamepadState>)
+{
+    let gilrs = match context.gilrs.as_mut() {
+        Some(gilrs) => gilrs,
+        None => return,
+    };
+
+    // Pump events so gilrs updates its cached gamepad state
+    while let Some(_) = gilrs.next_event() {}
+
+    state.last_held = std::mem::take(&mut state.held);
+    state.movement = Vector2::zero();
+    state.look = Vector2::zero();
+
+    // Just use the first connected gamepad
+    if let Some((_, gamepad)) = gilrs.gamepads().next() {
+        let left_stick = vec2(gamepad.value(Axis::LeftStickX), gamepad.value(Axis::LeftStickY));
+        let right_stick = vec2(gamepad.value(Axis::RightStickX), gamepad.value(Axis::RightStickY));
+
+        let left_stick = apply_deadzone(left_stick, settings.deadzone) * settings.move_sensitivity;
+        let right_stick = apply_deadzone(right_stick, settings.deadzone) * settings.look_sensitivity;
+
+        // Inverting the look is done for every input by InputBindings, from the settings menu
+        state.movement = vec2(left_stick.y, left_stick.x);
+        state.look = vec2(-right_stick.x, right_stick.y);
+
+        state.held = TRACKED_BUTTONS.iter()
+            .filter(|button| gamepad.is_pressed(**button))
+            .cloned()
+            .collect();
+    }
+}
+
+/// Apply a radial deadzone to a stick, rescaling the remaining range to 0..1
+fn apply_deadzone(stick: Vector2<f32>, deadzone: f32) -> Vector2<f32> {
+    let magnitude = stick.magnitude();
+    if magnitude <= deadzone || deadzone >= 1.0 {
+        return Vector2::zero();
+    }
+
+    let rescaled = f32::min((magnitude - deadzone) / (1.0 - deadzone), 1.0);
+    stick * (rescaled / magnitude)
+}
@@ -0,0 +1,126 @@
+use bevy_ecs::prelude::EventWriter;
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Matrix4, Deg, SquareMatrix, perspective, vec2, vec3};
+use serde::{Serialize, Deserialize};
+use dreamfield_renderer::components::PlayerCamera;
+
+use super::daynight::TimeOfDay;
+use super::display_mode::DisplayMode;
+use super::ui_layout::ResolutionChangedEvent;
+
+/// The default path the graphics settings are loaded from
+pub const GRAPHICS_SETTINGS_PATH: &'static str = "config/graphics.toml";
+
+/// The GraphicsSettings resource, with the render resolution and camera settings. Changing it at
+/// runtime rebuilds the PlayerCamera's projection, which the renderer picks up on the next frame.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    pub render_width: i32,
+    pub render_height: i32,
+    pub aspect: f32,
+    /// The vertical field of view in degrees
+    pub fov: f32,
+    pub near_clip: f32,
+    pub far_clip: f32,
+    /// The fog distances in the middle of the day, with the day/night cycle bringing the fog in
+    /// closer at night
+    pub fog_start: f32,
+    pub fog_end: f32,
+    /// How much of the scattered vegetation is drawn, from 0 to 1
+    pub vegetation_density: f32,
+    /// How far from the camera vegetation is drawn
+    pub vegetation_distance: f32,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        GraphicsSettings {
+            render_width: 320,
+            render_height: 240,
+            aspect: 4.0 / 3.0,
+            fov: 60.0,
+            near_clip: 0.1,
+            far_clip: 140.0,
+            fog_start: 100.0,
+            fog_end: 130.0,
+            vegetation_density: 1.0,
+            vegetation_distance: 60.0,
+        }
+    }
+}
+
+impl GraphicsSettings {
+    /// Load the settings from a toml file, falling back to the defaults for any missing values
+    pub fn load(path: &str) -> Self {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                log::warn!("Failed to read graphics settings from {}, using defaults: {}", path, err);
+                return GraphicsSettings::default();
+            }
+        };
+
+        match toml::from_str::<GraphicsSettings>(&source) {
+            Ok(settings) => settings,
+            Err(err) => {
+                log::error!("Failed to parse graphics settings from {}, using defaults: {}", path, err);
+                GraphicsSettings::default()
+            }
+        }
+    }
+
+    /// Get the projection matrix for these settings at an aspect ratio
+    pub fn projection(&self, aspect: f32) -> Matrix4<f32> {
+        perspective(Deg(self.fov), aspect, self.near_clip, self.far_clip)
+    }
+
+    /// Create a PlayerCamera with these settings, before the window size is known
+    pub fn create_camera(&self) -> PlayerCamera {
+        let mut cam = PlayerCamera {
+            proj: Matrix4::identity(),
+            view: Matrix4::identity(),
+            render_res: vec2(0.0, 0.0),
+            render_aspect: 0.0,
+            render_fov_rad: 0.0,
+            fog_color: vec3(0.0, 0.0, 0.0),
+            fog_range: vec2(self.fog_start, self.fog_end),
+        };
+        self.apply(&mut cam, &DisplayMode::default());
+        cam
+    }
+
+    /// Update a PlayerCamera's projection and render resolution, fitting them to the window
+    pub fn apply(&self, cam: &mut PlayerCamera, display: &DisplayMode) {
+        let base_res = vec2(self.render_width as f32, self.render_height as f32);
+        let (render_res, aspect) = display.fit(base_res, self.aspect);
+
+        cam.proj = self.projection(aspect);
+        cam.render_res = render_res;
+        cam.render_aspect = aspect;
+        cam.render_fov_rad = self.fov.to_radians();
+    }
+}
+
+/// The graphics settings system, which updates the camera when the settings or window change
+pub fn apply_graphics_settings(settings: Res<GraphicsSettings>, display: Res<DisplayMode>,
+    mut time_of_day: ResMut<TimeOfDay>, mut resolution_events: EventWriter<ResolutionChangedEvent>,
+    mut query: Query<&mut PlayerCamera>)
+{
+    if !settings.is_changed() && !display.is_changed() {
+        return;
+    }
+
+    for mut cam in query.iter_mut() {
+        let prev_res = cam.render_res;
+        settings.apply(&mut cam, &display);
+
+        // Always send it the first time, since the UI hasn't been laid out for it yet
+        if cam.render_res != prev_res || settings.is_added() {
+            resolution_events.send(ResolutionChangedEvent { render_res: cam.render_res });
+        }
+    }
+
+    // The day/night cycle sets the fog every frame, so update its daytime range
+    time_of_day.day_fog_range = vec2(settings.fog_start, settings.fog_end);
+}
@@ -0,0 +1,70 @@
+use bevy_ecs::prelude::Entity;
+use cgmath::Vector3;
+
+/// An event emitted each time the player takes a step
+pub struct FootstepEvent {
+    pub entity: Entity,
+    pub position: Vector3<f32>,
+    /// Whether this was the left or the right foot
+    pub left_foot: bool,
+}
+
+/// The head bob state for a walking character
+pub struct HeadBob {
+    /// The distance in units per footstep
+    pub stride_length: f32,
+    /// The maximum vertical camera offset in units
+    pub amplitude: f32,
+    /// The speed at which the bob reaches its full amplitude
+    pub full_amplitude_speed: f32,
+    /// How quickly the bob settles back to 0 when not walking, per second
+    pub settle_rate: f32,
+    /// The current vertical camera offset
+    pub offset: f32,
+    distance_since_step: f32,
+    left_foot: bool,
+}
+
+impl Default for HeadBob {
+    fn default() -> Self {
+        HeadBob {
+            stride_length: 0.8,
+            amplitude: 0.05,
+            full_amplitude_speed: 4.5,
+            settle_rate: 10.0,
+            offset: 0.0,
+            distance_since_step: 0.4,
+            left_foot: false,
+        }
+    }
+}
+
+impl HeadBob {
+    /// Advance the head bob by the distance walked this frame, returning whether a step was taken
+    /// and which foot it was
+    pub fn update(&mut self, walking: bool, speed: f32, time_delta: f32) -> Option<bool> {
+        if !walking || speed <= 0.0 {
+            // Settle the camera back to rest, and start the next walk halfway through a stride where the
+            // offset is 0 so the camera doesn't jump
+            self.offset -= self.offset * f32::min(1.0, self.settle_rate * time_delta);
+            self.distance_since_step = 0.5 * self.stride_length;
+            return None;
+        }
+
+        self.distance_since_step += speed * time_delta;
+
+        let mut step = None;
+        if self.distance_since_step >= self.stride_length {
+            self.distance_since_step -= self.stride_length;
+            self.left_foot = !self.left_foot;
+            step = Some(self.left_foot);
+        }
+
+        // The camera dips at each footstep, and peaks halfway between them
+        let stride_progress = self.distance_since_step / self.stride_length;
+        let amplitude = self.amplitude * f32::min(1.0, speed / self.full_amplitude_speed);
+        self.offset = amplitude * ((stride_progress * std::f32::consts::PI).sin() - 1.0);
+
+        step
+    }
+}
@@ -0,0 +1,153 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventReader, EventWriter, With, Changed};
+use bevy_ecs::system::{Res, ResMut, Query, Commands};
+use cgmath::vec3;
+use dreamfield_system::resources::SimTime;
+
+use super::PlayerMovement;
+use super::checkpoint::RespawnPoint;
+use super::game_state::{GameState, NextGameState};
+use super::screen_fade::ScreenFade;
+use super::tint::FadeOut;
+use super::ui::UiWidget;
+
+/// The default number of seconds an entity is invulnerable for after taking damage
+const DEFAULT_INVULNERABILITY_TIME: f32 = 1.0;
+
+/// How long the screen takes to fade out when the player dies, and back in when they respawn
+pub const DEATH_FADE_TIME: f32 = 1.5;
+
+/// How long other entities take to fade away when they die
+const CORPSE_FADE_TIME: f32 = 0.5;
+
+/// The Health component
+#[derive(Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+    /// The number of seconds the entity is invulnerable for after taking damage
+    pub invulnerability_time: f32,
+    /// Whether the entity is despawned when it dies, otherwise it's just left dead
+    pub despawn_on_death: bool,
+    invulnerable_remaining: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Health {
+            current: max,
+            max,
+            invulnerability_time: DEFAULT_INVULNERABILITY_TIME,
+            despawn_on_death: true,
+            invulnerable_remaining: 0.0,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable_remaining > 0.0
+    }
+
+    /// Restore the entity to full health
+    pub fn reset(&mut self) {
+        self.current = self.max;
+        self.invulnerable_remaining = 0.0;
+    }
+}
+
+/// An event to damage an entity
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    pub source: Option<Entity>,
+}
+
+/// An event emitted when an entity's health reaches zero
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub killer: Option<Entity>,
+}
+
+/// The damage system
+pub fn apply_damage(sim_time: Res<SimTime>, mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>, mut query: Query<&mut Health>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for mut health in query.iter_mut() {
+        if health.invulnerable_remaining > 0.0 {
+            health.invulnerable_remaining = f32::max(health.invulnerable_remaining - time_delta, 0.0);
+        }
+    }
+
+    for event in damage_events.iter() {
+        let mut health = match query.get_mut(event.target) {
+            Ok(health) => health,
+            Err(_) => continue,
+        };
+
+        if health.is_dead() || health.is_invulnerable() {
+            continue;
+        }
+
+        health.current = f32::max(health.current - event.amount, 0.0);
+        health.invulnerable_remaining = health.invulnerability_time;
+
+        if health.is_dead() {
+            death_events.send(DeathEvent { entity: event.target, killer: event.source });
+        }
+    }
+}
+
+/// The death system. When the player dies it's game over, and they're respawned at the last
+/// checkpoint if they continue. Other entities fade away and are despawned if they want to be,
+/// losing their health so that they can't be hit again while they fade.
+pub fn handle_deaths(mut commands: Commands, mut respawn_point: ResMut<RespawnPoint>, mut next_state: ResMut<NextGameState>,
+    mut screen_fade: ResMut<ScreenFade>, mut death_events: EventReader<DeathEvent>, mut query: Query<(&mut Health, Option<&PlayerMovement>)>)
+{
+    for event in death_events.iter() {
+        let (mut health, player) = match query.get_mut(event.entity) {
+            Ok(entity) => entity,
+            Err(_) => continue,
+        };
+
+        match player.is_some() {
+            true => {
+                respawn_point.respawn_requested = true;
+                health.reset();
+                next_state.set(GameState::GameOver);
+                screen_fade.fade_out(DEATH_FADE_TIME, vec3(0.3, 0.0, 0.0));
+            },
+            false => {
+                if health.despawn_on_death {
+                    commands.entity(event.entity)
+                        .remove::<Health>()
+                        .insert(FadeOut::new(CORPSE_FADE_TIME));
+                }
+            }
+        }
+    }
+}
+
+/// The HealthBar component, for the progress bar widget showing the player's health
+#[derive(Component)]
+pub struct HealthBar;
+
+/// The health bar system
+pub fn update_health_bar(player_query: Query<&Health, (With<PlayerMovement>, Changed<Health>)>,
+    mut query: Query<&mut UiWidget, With<HealthBar>>)
+{
+    for health in player_query.iter() {
+        for mut widget in query.iter_mut() {
+            if let UiWidget::ProgressBar { value, .. } = &mut *widget {
+                *value = match health.max > 0.0 {
+                    true => health.current / health.max,
+                    false => 0.0,
+                };
+            }
+        }
+    }
+}
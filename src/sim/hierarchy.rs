@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, Changed, Without};
+use bevy_ecs::system::{Query, Commands};
+use bevy_ecs::world::World;
+use cgmath::{Vector3, Matrix3};
+use dreamfield_system::components::Transform;
+
+/// The deepest a hierarchy can go, to catch entities that are their own ancestors
+const MAX_HIERARCHY_DEPTH: usize = 16;
+
+/// The Parent component, for entities whose Transform follows another entity's. The entity also
+/// needs a LocalTransform, and its Transform is worked out from it each tick.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Parent(pub Entity);
+
+/// The Children component, listing the entities parented to an entity. It's kept up to date with
+/// the Parent components by the hierarchy system, so there's no need to change it directly.
+#[derive(Component, Default, Debug)]
+pub struct Children(pub Vec<Entity>);
+
+/// The LocalTransform component, with an entity's transform relative to its Parent
+#[derive(Component, Clone, Copy, Debug)]
+pub struct LocalTransform {
+    pub pos: Vector3<f32>,
+    pub rot: Matrix3<f32>,
+}
+
+impl LocalTransform {
+    pub fn new(pos: Vector3<f32>, rot: Matrix3<f32>) -> Self {
+        LocalTransform { pos, rot }
+    }
+}
+
+/// Parent an entity to another with a local transform, updating the parent's Children straight
+/// away. Entities given a Parent any other way are added to their parent's Children next tick.
+pub fn set_parent(world: &mut World, child: Entity, parent: Entity, local: LocalTransform) {
+    world.entity_mut(child).insert(Parent(parent)).insert(local);
+
+    let mut parent = world.entity_mut(parent);
+    match parent.get_mut::<Children>() {
+        Some(mut children) => {
+            if !children.0.contains(&child) {
+                children.0.push(child);
+            }
+        },
+        None => {
+            parent.insert(Children(vec![child]));
+        }
+    }
+}
+
+/// The children system, which adds newly parented entities to their parent's Children, and
+/// removes ones that have been despawned or parented to something else
+pub fn update_children(mut commands: Commands, changed: Query<(Entity, &Parent), Changed<Parent>>,
+    parents: Query<&Parent>, mut children_query: Query<&mut Children>, entities: Query<Entity>)
+{
+    // Parents that don't have Children yet get them all at once, otherwise each new child would
+    // replace the last one
+    let mut new_children: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    for (child, parent) in changed.iter() {
+        match children_query.get_mut(parent.0) {
+            Ok(mut children) => {
+                if !children.0.contains(&child) {
+                    children.0.push(child);
+                }
+            },
+            Err(_) => {
+                if entities.get(parent.0).is_ok() {
+                    new_children.entry(parent.0).or_default().push(child);
+                }
+            }
+        }
+    }
+    for (parent, children) in new_children {
+        commands.entity(parent).insert(Children(children));
+    }
+
+    for (entity, mut children) in children_query.iter_mut() {
+        children.0.retain(|child| parents.get(*child).map(|parent| parent.0 == entity).unwrap_or(false));
+    }
+}
+
+/// The transform propagation system, which works out the Transform of every entity in a hierarchy
+/// from its LocalTransform, starting from the entities at the top of each one. It runs after
+/// everything that moves entities around, so children follow their parents on the same tick.
+pub fn propagate_transforms(roots: Query<(Entity, &Children), Without<Parent>>,
+    nodes: Query<(&Parent, &LocalTransform, Option<&Children>)>, mut transforms: Query<&mut Transform>)
+{
+    for (root, children) in roots.iter() {
+        let (pos, rot) = match transforms.get(root) {
+            Ok(transform) => (transform.pos, transform.rot),
+            Err(_) => continue,
+        };
+
+        for child in children.0.iter() {
+            propagate(*child, root, pos, rot, &nodes, &mut transforms, 0);
+        }
+    }
+}
+
+/// Update an entity's Transform from its parent's, and then do the same for its children
+fn propagate(entity: Entity, parent: Entity, parent_pos: Vector3<f32>, parent_rot: Matrix3<f32>,
+    nodes: &Query<(&Parent, &LocalTransform, Option<&Children>)>, transforms: &mut Query<&mut Transform>,
+    depth: usize)
+{
+    if depth >= MAX_HIERARCHY_DEPTH {
+        log::error!("Transform hierarchy is too deep, an entity might be its own ancestor");
+        return;
+    }
+
+    // Skip children that have been parented to something else since the Children were updated
+    let (local, children) = match nodes.get(entity) {
+        Ok((Parent(current_parent), local, children)) if *current_parent == parent => (local, children),
+        _ => return,
+    };
+
+    let pos = parent_pos + parent_rot * local.pos;
+    let rot = parent_rot * local.rot;
+    if let Ok(mut transform) = transforms.get_mut(entity) {
+        transform.pos = pos;
+        transform.rot = rot;
+    }
+
+    for child in children.iter().flat_map(|children| children.0.iter()) {
+        propagate(*child, entity, pos, rot, nodes, transforms, depth + 1);
+    }
+}
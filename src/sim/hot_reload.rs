@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::schedule::{SystemSet, ExclusiveSystemDescriptorCoercion};
+use bevy_ecs::system::IntoExclusiveSystem;
+use bevy_ecs::world::World;
+use dreamfield_renderer::components::Visual;
+use dreamfield_renderer::resources::{ModelManager, TextureManager};
+
+use super::loading::AssetOverrides;
+
+/// The directories that are watched, with the extension of the files in them that are assets
+const MODEL_DIR: (&'static str, &'static str) = ("resources/models", "glb");
+const TEXTURE_DIR: (&'static str, &'static str) = ("resources/textures", "png");
+
+/// How often the watched directories are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A watched directory, with when each of its assets last changed
+struct WatchedDir {
+    dir: PathBuf,
+    extension: &'static str,
+    modified: HashMap<String, SystemTime>,
+    overrides: AssetOverrides,
+}
+
+impl WatchedDir {
+    fn new((dir, extension): (&str, &'static str)) -> Self {
+        let mut watched = WatchedDir {
+            dir: PathBuf::from(dir),
+            extension,
+            modified: HashMap::new(),
+            overrides: AssetOverrides::default(),
+        };
+        watched.changed_files();
+        watched
+    }
+
+    /// Find the assets that have changed since the last check, by name, with their paths
+    fn changed_files(&mut self) -> Vec<(String, PathBuf)> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::warn!("Failed to watch {}: {}", self.dir.display(), err);
+                return Vec::new();
+            }
+        };
+
+        let mut changed = Vec::new();
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            let name = match (path.extension(), path.file_stem()) {
+                (Some(extension), Some(name)) if extension == self.extension => name.to_string_lossy().to_string(),
+                _ => continue,
+            };
+            let modified = match std::fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified,
+                Err(_) => continue,
+            };
+
+            // Files are only reloaded once they've been seen before, so the first check doesn't
+            // reload everything
+            match self.modified.insert(name.clone(), modified) {
+                Some(previous) if previous != modified => changed.push((name, path)),
+                _ => {},
+            }
+        }
+        changed
+    }
+
+    /// Reload any assets that have changed, returning whether there were any
+    fn reload(&mut self) -> bool {
+        let mut reloaded = false;
+        for (name, path) in self.changed_files() {
+            match read_asset(&path) {
+                Some(bytes) => {
+                    log::info!("Reloading {}", path.display());
+                    self.overrides.insert(&name, bytes);
+                    reloaded = true;
+                },
+                None => log::error!("Failed to reload {}", path.display()),
+            }
+        }
+        reloaded
+    }
+}
+
+/// Read an asset file, leaking its contents since the managers expect static data like the assets
+/// that are built into the game. Each reload leaks a copy of the file, which is fine for
+/// development.
+fn read_asset(path: &Path) -> Option<&'static [u8]> {
+    std::fs::read(path).ok().map(|bytes| &*Box::leak(bytes.into_boxed_slice()))
+}
+
+/// The AssetWatcher resource, which watches the model and texture directories when the game's
+/// built with the hot_reload feature. When a file changes, its manager is rebuilt with the new
+/// file in place of the built in one, and every Visual is refreshed, since they don't say which
+/// models they use. Only assets that are built in under their file name can be reloaded, so the
+/// models build.rs generates aren't.
+pub struct AssetWatcher {
+    models: WatchedDir,
+    textures: WatchedDir,
+    create_model_manager: fn(&AssetOverrides) -> ModelManager,
+    create_texture_manager: fn(&AssetOverrides) -> TextureManager,
+    last_poll: Instant,
+}
+
+impl AssetWatcher {
+    /// Start watching, with the functions that build each manager with the reloaded assets
+    pub fn new(create_model_manager: fn(&AssetOverrides) -> ModelManager,
+        create_texture_manager: fn(&AssetOverrides) -> TextureManager) -> Self
+    {
+        AssetWatcher {
+            models: WatchedDir::new(MODEL_DIR),
+            textures: WatchedDir::new(TEXTURE_DIR),
+            create_model_manager,
+            create_texture_manager,
+            last_poll: Instant::now(),
+        }
+    }
+}
+
+/// The hot reload system, which checks the watched directories every so often, and puts new
+/// managers into the world when anything in them has changed. It's exclusive, since it replaces
+/// the managers and refreshes the Visuals.
+pub fn reload_changed_assets(world: &mut World) {
+    let mut watcher = world.resource_mut::<AssetWatcher>();
+    if watcher.last_poll.elapsed() < POLL_INTERVAL {
+        return;
+    }
+    watcher.last_poll = Instant::now();
+
+    let models = match watcher.models.reload() {
+        true => Some((watcher.create_model_manager)(&watcher.models.overrides)),
+        false => None,
+    };
+    let textures = match watcher.textures.reload() {
+        true => Some((watcher.create_texture_manager)(&watcher.textures.overrides)),
+        false => None,
+    };
+    if models.is_none() && textures.is_none() {
+        return;
+    }
+
+    if let Some(models) = models {
+        world.insert_resource(models);
+    }
+    if let Some(textures) = textures {
+        world.insert_resource(textures);
+    }
+
+    // Put each Visual back so the renderer picks the new assets up
+    let visuals: Vec<(Entity, Visual)> = world.query::<(Entity, &Visual)>()
+        .iter(world)
+        .map(|(entity, visual)| (entity, visual.clone()))
+        .collect();
+    for (entity, visual) in visuals {
+        world.entity_mut(entity).insert(visual);
+    }
+}
+
+/// Hot reload systems, which run when the game's built with the hot_reload feature
+pub fn hot_reload_systems() -> SystemSet {
+    SystemSet::new()
+        .with_system(reload_changed_assets.exclusive_system().at_end())
+}
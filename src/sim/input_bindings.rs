@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+
+use bevy_ecs::system::{Res, ResMut};
+use cgmath::{Vector2, vec2};
+use glfw::ffi;
+use serde::{Serialize, Deserialize};
+use dreamfield_system::resources::{InputState, InputName};
+
+use super::console::is_key_down;
+use super::gamepad::{GamepadState, GamepadButton};
+
+/// The default path of the input bindings config
+pub const INPUT_BINDINGS_PATH: &'static str = "config/input.toml";
+
+/// The actions sim systems can bind inputs to
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    StrafeLeft,
+    StrafeRight,
+    Jump,
+    Run,
+    Interact,
+    Pause,
+    ToggleNoclip,
+    Inventory,
+    Attack,
+    Cast,
+    Crouch,
+    Sprint,
+    ToggleCamera,
+    SkipCutscene,
+    PhotoMode,
+    Screenshot,
+    PhotoRollLeft,
+    PhotoRollRight,
+    PhotoZoomIn,
+    PhotoZoomOut,
+    ToggleMinimap,
+    /// Pause the sim for debugging, and run it a tick at a time while it's paused
+    DebugPause,
+    DebugStep,
+}
+
+impl Action {
+    /// Get an action from its name in the config file
+    pub fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "MoveForward" => Some(Action::MoveForward),
+            "MoveBack" => Some(Action::MoveBack),
+            "StrafeLeft" => Some(Action::StrafeLeft),
+            "StrafeRight" => Some(Action::StrafeRight),
+            "Jump" => Some(Action::Jump),
+            "Run" => Some(Action::Run),
+            "Interact" => Some(Action::Interact),
+            "Pause" => Some(Action::Pause),
+            "ToggleNoclip" => Some(Action::ToggleNoclip),
+            "Inventory" => Some(Action::Inventory),
+            "Attack" => Some(Action::Attack),
+            "Cast" => Some(Action::Cast),
+            "Crouch" => Some(Action::Crouch),
+            "Sprint" => Some(Action::Sprint),
+            "ToggleCamera" => Some(Action::ToggleCamera),
+            "SkipCutscene" => Some(Action::SkipCutscene),
+            "PhotoMode" => Some(Action::PhotoMode),
+            "Screenshot" => Some(Action::Screenshot),
+            "PhotoRollLeft" => Some(Action::PhotoRollLeft),
+            "PhotoRollRight" => Some(Action::PhotoRollRight),
+            "PhotoZoomIn" => Some(Action::PhotoZoomIn),
+            "PhotoZoomOut" => Some(Action::PhotoZoomOut),
+            "ToggleMinimap" => Some(Action::ToggleMinimap),
+            "DebugPause" => Some(Action::DebugPause),
+            "DebugStep" => Some(Action::DebugStep),
+            _ => None,
+        }
+    }
+}
+
+/// Get an engine input from its name in the config file
+fn input_name_from_str(name: &str) -> Option<InputName> {
+    match name {
+        "Jump" => Some(InputName::Jump),
+        "Run" => Some(InputName::Run),
+        "Use" => Some(InputName::Use),
+        "Pause" => Some(InputName::Pause),
+        "Inventory" => Some(InputName::Inventory),
+        "Attack" => Some(InputName::Attack),
+        "Cast" => Some(InputName::Cast),
+        "Crouch" => Some(InputName::Crouch),
+        _ => None,
+    }
+}
+
+/// Get a gamepad button from its name in the config file, e.g. GamepadSouth
+fn gamepad_button_from_str(name: &str) -> Option<GamepadButton> {
+    match name.strip_prefix("Gamepad")? {
+        "South" => Some(GamepadButton::South),
+        "East" => Some(GamepadButton::East),
+        "West" => Some(GamepadButton::West),
+        "North" => Some(GamepadButton::North),
+        "LeftTrigger" => Some(GamepadButton::LeftTrigger),
+        "RightTrigger" => Some(GamepadButton::RightTrigger),
+        "LeftTrigger2" => Some(GamepadButton::LeftTrigger2),
+        "RightTrigger2" => Some(GamepadButton::RightTrigger2),
+        "LeftThumb" => Some(GamepadButton::LeftThumb),
+        "RightThumb" => Some(GamepadButton::RightThumb),
+        "Start" => Some(GamepadButton::Start),
+        "Select" => Some(GamepadButton::Select),
+        "DPadUp" => Some(GamepadButton::DPadUp),
+        "DPadDown" => Some(GamepadButton::DPadDown),
+        "DPadLeft" => Some(GamepadButton::DPadLeft),
+        "DPadRight" => Some(GamepadButton::DPadRight),
+        _ => None,
+    }
+}
+
+/// Get a keyboard key's glfw key code from its name in the config file, e.g. KeyW or KeyUp
+fn key_from_str(name: &str) -> Option<i32> {
+    let key = name.strip_prefix("Key")?;
+    match key.as_bytes() {
+        [letter @ b'A'..=b'Z'] => Some(ffi::KEY_A + (letter - b'A') as i32),
+        [digit @ b'0'..=b'9'] => Some(ffi::KEY_0 + (digit - b'0') as i32),
+        _ => match key {
+            "Up" => Some(ffi::KEY_UP),
+            "Down" => Some(ffi::KEY_DOWN),
+            "Left" => Some(ffi::KEY_LEFT),
+            "Right" => Some(ffi::KEY_RIGHT),
+            "Space" => Some(ffi::KEY_SPACE),
+            "Tab" => Some(ffi::KEY_TAB),
+            "Enter" => Some(ffi::KEY_ENTER),
+            "LeftShift" => Some(ffi::KEY_LEFT_SHIFT),
+            "RightShift" => Some(ffi::KEY_RIGHT_SHIFT),
+            "LeftControl" => Some(ffi::KEY_LEFT_CONTROL),
+            "RightControl" => Some(ffi::KEY_RIGHT_CONTROL),
+            "LeftAlt" => Some(ffi::KEY_LEFT_ALT),
+            "RightAlt" => Some(ffi::KEY_RIGHT_ALT),
+            _ => None,
+        },
+    }
+}
+
+/// The engine inputs that can be bound, in the order they're stored in an EngineInput
+const ENGINE_INPUTS: &'static [InputName] = &[
+    InputName::Jump, InputName::Run, InputName::Use, InputName::Pause,
+    InputName::Inventory, InputName::Attack, InputName::Cast, InputName::Crouch,
+];
+
+/// Get the index of an engine input in ENGINE_INPUTS
+fn engine_input_index(input: InputName) -> Option<usize> {
+    match input {
+        InputName::Jump => Some(0),
+        InputName::Run => Some(1),
+        InputName::Use => Some(2),
+        InputName::Pause => Some(3),
+        InputName::Inventory => Some(4),
+        InputName::Attack => Some(5),
+        InputName::Cast => Some(6),
+        InputName::Crouch => Some(7),
+        _ => None,
+    }
+}
+
+/// A snapshot of the engine's InputState for a tick, with the held and just pressed inputs as bit
+/// masks of ENGINE_INPUTS, for recording input and playing it back. Movement comes from the
+/// movement actions rather than the engine's movement axes, so it isn't included.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct EngineInput {
+    pub held: u16,
+    pub just_pressed: u16,
+    /// The look input as (horizontal, vertical)
+    pub look: (f32, f32),
+}
+
+impl EngineInput {
+    pub fn capture(input_state: &InputState) -> Self {
+        let mask = |pressed: &dyn Fn(InputName) -> bool| ENGINE_INPUTS.iter().enumerate()
+            .filter(|(_, input)| pressed(**input))
+            .fold(0, |mask, (index, _)| mask | 1 << index);
+
+        EngineInput {
+            held: mask(&|input| input_state.is_held(input)),
+            just_pressed: mask(&|input| input_state.is_just_pressed(input)),
+            look: input_state.get_look_input(),
+        }
+    }
+
+    /// Check whether any input is held
+    pub fn any(&self) -> bool {
+        self.held != 0
+    }
+
+    pub fn is_held(&self, input: InputName) -> bool {
+        engine_input_index(input).map(|index| self.held & (1 << index) != 0).unwrap_or(false)
+    }
+
+    pub fn is_just_pressed(&self, input: InputName) -> bool {
+        engine_input_index(input).map(|index| self.just_pressed & (1 << index) != 0).unwrap_or(false)
+    }
+}
+
+/// A single binding for an action
+#[derive(Clone, Copy, Debug)]
+pub enum Binding {
+    Input(InputName),
+    Gamepad(GamepadButton),
+    /// A keyboard key by its glfw key code, for keys the engine's input doesn't have
+    Key(i32),
+}
+
+impl Binding {
+    fn from_str(name: &str) -> Option<Binding> {
+        input_name_from_str(name).map(Binding::Input)
+            .or_else(|| gamepad_button_from_str(name).map(Binding::Gamepad))
+            .or_else(|| key_from_str(name).map(Binding::Key))
+    }
+
+    fn is_held(&self, input_state: &InputState, gamepad: &GamepadState) -> bool {
+        match (self, &gamepad.replayed_input) {
+            (Binding::Input(input), Some(replayed_input)) => replayed_input.is_held(*input),
+            (Binding::Input(input), None) => input_state.is_held(*input),
+            (Binding::Gamepad(button), _) => gamepad.is_held(*button),
+            (Binding::Key(key), _) => gamepad.is_key_held(*key),
+        }
+    }
+
+    fn is_just_pressed(&self, input_state: &InputState, gamepad: &GamepadState) -> bool {
+        match (self, &gamepad.replayed_input) {
+            (Binding::Input(input), Some(replayed_input)) => replayed_input.is_just_pressed(*input),
+            (Binding::Input(input), None) => input_state.is_just_pressed(*input),
+            (Binding::Gamepad(button), _) => gamepad.is_just_pressed(*button),
+            (Binding::Key(key), _) => gamepad.is_key_just_pressed(*key),
+        }
+    }
+}
+
+/// The InputBindings resource, mapping actions to the engine inputs and gamepad buttons that trigger them
+pub struct InputBindings {
+    bindings: HashMap<Action, Vec<Binding>>,
+    /// Whether to invert the vertical look input
+    pub invert_y: bool,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use Binding::{Input, Gamepad, Key};
+
+        let bindings = HashMap::from([
+            (Action::MoveForward, vec![Key(ffi::KEY_W), Key(ffi::KEY_UP)]),
+            (Action::MoveBack, vec![Key(ffi::KEY_S), Key(ffi::KEY_DOWN)]),
+            (Action::StrafeLeft, vec![Key(ffi::KEY_A), Key(ffi::KEY_LEFT)]),
+            (Action::StrafeRight, vec![Key(ffi::KEY_D), Key(ffi::KEY_RIGHT)]),
+            (Action::Jump, vec![Input(InputName::Jump), Gamepad(GamepadButton::South)]),
+            (Action::Run, vec![Input(InputName::Run), Gamepad(GamepadButton::LeftThumb)]),
+            (Action::Interact, vec![Input(InputName::Use), Gamepad(GamepadButton::West)]),
+            (Action::Pause, vec![Input(InputName::Pause), Gamepad(GamepadButton::Start)]),
+            (Action::ToggleNoclip, vec![]),
+            (Action::Inventory, vec![Input(InputName::Inventory), Gamepad(GamepadButton::North)]),
+            (Action::Attack, vec![Input(InputName::Attack), Gamepad(GamepadButton::RightTrigger2)]),
+            (Action::Cast, vec![Input(InputName::Cast), Gamepad(GamepadButton::LeftTrigger2)]),
+            (Action::Crouch, vec![Input(InputName::Crouch), Gamepad(GamepadButton::East)]),
+            (Action::Sprint, vec![Input(InputName::Run), Gamepad(GamepadButton::LeftThumb)]),
+            (Action::ToggleCamera, vec![Gamepad(GamepadButton::Select)]),
+            (Action::SkipCutscene, vec![Input(InputName::Jump), Gamepad(GamepadButton::South)]),
+            (Action::PhotoMode, vec![Gamepad(GamepadButton::DPadUp)]),
+            (Action::Screenshot, vec![Input(InputName::Use), Gamepad(GamepadButton::West)]),
+            (Action::PhotoRollLeft, vec![Gamepad(GamepadButton::LeftTrigger)]),
+            (Action::PhotoRollRight, vec![Gamepad(GamepadButton::RightTrigger)]),
+            (Action::PhotoZoomIn, vec![Gamepad(GamepadButton::RightTrigger2)]),
+            (Action::PhotoZoomOut, vec![Gamepad(GamepadButton::LeftTrigger2)]),
+            (Action::ToggleMinimap, vec![Gamepad(GamepadButton::DPadDown)]),
+            (Action::DebugPause, vec![Gamepad(GamepadButton::DPadLeft)]),
+            (Action::DebugStep, vec![Gamepad(GamepadButton::DPadRight)]),
+        ]);
+
+        InputBindings { bindings, invert_y: false }
+    }
+}
+
+impl InputBindings {
+    /// Load the bindings from a toml file, falling back to the defaults for any missing actions
+    pub fn load(path: &str) -> Self {
+        let mut input_bindings = InputBindings::default();
+
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                log::warn!("Failed to read input bindings from {}, using defaults: {}", path, err);
+                return input_bindings;
+            }
+        };
+
+        let config = match toml::from_str::<HashMap<String, Vec<String>>>(&source) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("Failed to parse input bindings from {}, using defaults: {}", path, err);
+                return input_bindings;
+            }
+        };
+
+        for (action_name, input_names) in config.iter() {
+            let action = match Action::from_name(action_name) {
+                Some(action) => action,
+                None => {
+                    log::warn!("Unknown action in input bindings: {}", action_name);
+                    continue;
+                }
+            };
+
+            let inputs = input_names.iter()
+                .filter_map(|name| {
+                    let binding = Binding::from_str(name);
+                    if binding.is_none() {
+                        log::warn!("Unknown input {} bound to action {:?}", name, action);
+                    }
+                    binding
+                })
+                .collect();
+
+            input_bindings.bindings.insert(action, inputs);
+        }
+
+        input_bindings
+    }
+
+    /// Bind an action to a set of inputs, replacing the existing bindings
+    pub fn bind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings.insert(action, bindings);
+    }
+
+    fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map(|bindings| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    /// Get every keyboard key that's bound to an action
+    pub fn bound_keys(&self) -> impl Iterator<Item = i32> + '_ {
+        self.bindings.values().flatten().filter_map(|binding| match binding {
+            Binding::Key(key) => Some(*key),
+            _ => None,
+        })
+    }
+
+    /// Get whether any of the inputs bound to an action are held
+    pub fn is_held(&self, input_state: &InputState, gamepad: &GamepadState, action: Action) -> bool {
+        self.bindings(action).iter().any(|binding| binding.is_held(input_state, gamepad))
+    }
+
+    /// Get whether any of the inputs bound to an action were just pressed
+    pub fn is_just_pressed(&self, input_state: &InputState, gamepad: &GamepadState, action: Action) -> bool {
+        self.bindings(action).iter().any(|binding| binding.is_just_pressed(input_state, gamepad))
+    }
+}
+
+/// All of the player's controls combined, so that systems don't need to care where input came from
+pub struct Controls<'a> {
+    pub input_state: &'a InputState,
+    pub gamepad: &'a GamepadState,
+    pub bindings: &'a InputBindings,
+}
+
+impl<'a> Controls<'a> {
+    pub fn new(input_state: &'a InputState, gamepad: &'a GamepadState, bindings: &'a InputBindings) -> Self {
+        Controls { input_state, gamepad, bindings }
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        self.bindings.is_held(self.input_state, self.gamepad, action)
+    }
+
+    pub fn is_just_pressed(&self, action: Action) -> bool {
+        self.bindings.is_just_pressed(self.input_state, self.gamepad, action)
+    }
+
+    /// Get the movement input as (forward, right), combining the movement actions and the gamepad
+    pub fn movement(&self) -> Vector2<f32> {
+        let axis = |positive: Action, negative: Action| {
+            (self.is_held(positive) as i32 - self.is_held(negative) as i32) as f32
+        };
+        let combined = vec2(axis(Action::MoveForward, Action::MoveBack), axis(Action::StrafeRight, Action::StrafeLeft))
+            + self.gamepad.movement;
+        vec2(f32::clamp(combined.x, -1.0, 1.0), f32::clamp(combined.y, -1.0, 1.0))
+    }
+
+    /// Get the look input as (horizontal, vertical), combining mouse/keyboard and gamepad
+    pub fn look(&self) -> Vector2<f32> {
+        let (horz, vert) = match &self.gamepad.replayed_input {
+            Some(replayed_input) => replayed_input.look,
+            None => self.input_state.get_look_input(),
+        };
+        let look = vec2(horz, vert) + self.gamepad.look;
+        match self.bindings.invert_y {
+            false => look,
+            true => vec2(look.x, -look.y),
+        }
+    }
+}
+
+/// The KeyboardPoll resource, with the bound keys that were held the last time the keyboard was
+/// read. The keys are read in the render schedule, since that's where the window's context is
+/// current, and handed to the sim when its next tick starts.
+#[derive(Default)]
+pub struct KeyboardPoll {
+    held: Vec<i32>,
+}
+
+/// The keyboard poll system, which reads the keys bound to actions from the window
+pub fn poll_bound_keys(bindings: Res<InputBindings>, mut poll: ResMut<KeyboardPoll>) {
+    poll.held = bindings.bound_keys().filter(|key| is_key_down(*key)).collect();
+}
+
+/// The bound keys system, which moves the last polled keys into the GamepadState for this tick
+pub fn update_bound_keys(poll: Res<KeyboardPoll>, mut gamepad: ResMut<GamepadState>) {
+    gamepad.set_held_keys(poll.held.clone());
+}
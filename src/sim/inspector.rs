@@ -0,0 +1,337 @@
+use std::fmt::Debug;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, With, Without};
+use bevy_ecs::system::ResMut;
+use bevy_ecs::world::World;
+use cgmath::{Vector3, Matrix3, Rad, vec3, vec4, InnerSpace, SquareMatrix};
+use dreamfield_renderer::components::PlayerCamera;
+use dreamfield_system::components::{EntityName, Transform};
+use glfw::ffi;
+
+use super::behavior::BehaviorTree;
+use super::character_controller::CharacterController;
+use super::console::{ConsoleCommands, DebugConsole, is_key_down, is_mouse_button_down};
+use super::debug_draw::DebugDraw;
+use super::door::Door;
+use super::enemy::Enemy;
+use super::game_state::GameState;
+use super::health::Health;
+use super::hierarchy::{Parent, Children, LocalTransform};
+use super::nav::NavAgent;
+use super::player_movement::PlayerMovement;
+use super::rich_text::RichText;
+use super::tint::Tint;
+use super::trigger::TriggerVolume;
+
+/// How far an entity can be from the middle of the screen to be picked, in world units
+const PICK_RADIUS: f32 = 0.75;
+
+/// How far a nudge moves an entity, and how far with shift held
+const NUDGE_STEP: f32 = 0.1;
+const NUDGE_STEP_COARSE: f32 = 1.0;
+
+/// How far a nudge turns an entity, in degrees, and how far with shift held
+const NUDGE_ANGLE: f32 = 5.0;
+const NUDGE_ANGLE_COARSE: f32 = 45.0;
+
+/// How big the selected entity's axes are drawn
+const SELECTION_AXES_SIZE: f32 = 0.5;
+
+/// A component's inspector, which shows a component's values if the entity has it
+pub type InspectorFn = Box<dyn Fn(&World, Entity) -> Option<String> + Send + Sync>;
+
+/// The InspectorRegistry resource, with the components the inspector can show, in the order
+/// they're shown
+#[derive(Default)]
+pub struct InspectorRegistry {
+    components: Vec<(&'static str, InspectorFn)>,
+}
+
+impl InspectorRegistry {
+    /// Register a component, with a function that describes its values
+    pub fn register<T: Component>(&mut self, name: &'static str, describe: fn(&T) -> String) {
+        let inspect: InspectorFn = Box::new(move |world: &World, entity: Entity| world.get::<T>(entity).map(describe));
+        self.components.push((name, inspect));
+    }
+
+    /// Register a component that's shown with its debug formatting
+    pub fn register_debug<T: Component + Debug>(&mut self, name: &'static str) {
+        self.register::<T>(name, |component| format!("{:?}", component));
+    }
+}
+
+/// Register the inspectors for the sim's components
+pub fn register_component_inspectors(registry: &mut InspectorRegistry) {
+    registry.register::<Health>("Health", |health| format!("{:.1}/{:.1}", health.current, health.max));
+    registry.register::<PlayerMovement>("PlayerMovement", |movement| {
+        let forward = movement.forward();
+        format!("forward {:.2} {:.2} {:.2}", forward.x, forward.y, forward.z)
+    });
+    registry.register_debug::<CharacterController>("CharacterController");
+    registry.register_debug::<Parent>("Parent");
+    registry.register_debug::<Children>("Children");
+    registry.register_debug::<LocalTransform>("LocalTransform");
+    registry.register_debug::<Tint>("Tint");
+    registry.register::<Door>("Door", |door| format!("{:?}", door.state));
+    registry.register::<TriggerVolume>("TriggerVolume", |trigger| match trigger.player_inside() {
+        true => format!("{:?}, player inside", trigger.shape),
+        false => format!("{:?}", trigger.shape),
+    });
+    registry.register::<NavAgent>("NavAgent", |agent| format!("{} points to go", agent.path().len()));
+    registry.register::<BehaviorTree>("BehaviorTree", |tree| {
+        let mut keys: Vec<&str> = tree.blackboard.keys().collect();
+        keys.sort();
+        format!("{} [{}]", tree.tree, keys.join(" "))
+    });
+    registry.register::<Enemy>("Enemy", |enemy| format!("home {:?}, sight {:.1}", enemy.home, enemy.sight_range));
+}
+
+/// The keys the inspector handles
+#[derive(Clone, Copy)]
+enum InspectorKey {
+    Toggle,
+    /// Select the entity in the middle of the screen
+    Pick,
+    Cycle(i32),
+    /// Move the selected entity, in world axes
+    Nudge(Vector3<f32>),
+    /// Turn the selected entity around the y axis, in degrees
+    Turn(f32),
+    /// Log the selected entity's transform, to copy it into code
+    Log,
+}
+
+/// The Inspector resource, for the debug inspector, which shows the components of an entity that's
+/// picked from the middle of the screen or cycled to, and can nudge its transform around. It's
+/// toggled with F5, and while it's open left click or F6 picks, page up and down cycle, the
+/// keypad's arrows and 9 and 3 nudge, 7 and 1 turn, with shift for bigger steps, and 5 logs the
+/// transform.
+#[derive(Default)]
+pub struct Inspector {
+    pub open: bool,
+    pub selected: Option<Entity>,
+    keys: Vec<InspectorKey>,
+    held_keys: Vec<i32>,
+}
+
+/// The InspectorText component, for the RichText the inspector panel's shown in
+#[derive(Component)]
+pub struct InspectorText;
+
+/// The inspector key system, which queues the inspector's keys pressed since the last frame. It
+/// reads them with is_key_down, like the console, so it runs in the render schedule.
+pub fn poll_inspector_keys(mut inspector: ResMut<Inspector>) {
+    let shift = is_key_down(ffi::KEY_LEFT_SHIFT) || is_key_down(ffi::KEY_RIGHT_SHIFT);
+    let (step, angle) = match shift {
+        true => (NUDGE_STEP_COARSE, NUDGE_ANGLE_COARSE),
+        false => (NUDGE_STEP, NUDGE_ANGLE),
+    };
+
+    // Mouse buttons are tracked with the keys, as negative codes
+    let key_map = [
+        (ffi::KEY_F5, InspectorKey::Toggle),
+        (ffi::KEY_F6, InspectorKey::Pick),
+        (-1 - ffi::MOUSE_BUTTON_LEFT, InspectorKey::Pick),
+        (ffi::KEY_PAGE_UP, InspectorKey::Cycle(-1)),
+        (ffi::KEY_PAGE_DOWN, InspectorKey::Cycle(1)),
+        (ffi::KEY_KP_4, InspectorKey::Nudge(vec3(-step, 0.0, 0.0))),
+        (ffi::KEY_KP_6, InspectorKey::Nudge(vec3(step, 0.0, 0.0))),
+        (ffi::KEY_KP_8, InspectorKey::Nudge(vec3(0.0, 0.0, -step))),
+        (ffi::KEY_KP_2, InspectorKey::Nudge(vec3(0.0, 0.0, step))),
+        (ffi::KEY_KP_9, InspectorKey::Nudge(vec3(0.0, step, 0.0))),
+        (ffi::KEY_KP_3, InspectorKey::Nudge(vec3(0.0, -step, 0.0))),
+        (ffi::KEY_KP_7, InspectorKey::Turn(angle)),
+        (ffi::KEY_KP_1, InspectorKey::Turn(-angle)),
+        (ffi::KEY_KP_5, InspectorKey::Log),
+    ];
+
+    // Only the toggle key's read while the inspector's closed
+    let key_map = match inspector.open {
+        true => &key_map[..],
+        false => &key_map[..1],
+    };
+    let is_down = |key: i32| match key < 0 {
+        true => is_mouse_button_down(-1 - key),
+        false => is_key_down(key),
+    };
+
+    let down: Vec<i32> = key_map.iter().map(|(key, _)| *key).filter(|key| is_down(*key)).collect();
+    let pressed: Vec<InspectorKey> = key_map.iter()
+        .filter(|(key, _)| down.contains(key) && !inspector.held_keys.contains(key))
+        .map(|(_, inspector_key)| *inspector_key)
+        .collect();
+    inspector.keys.extend(pressed);
+    inspector.held_keys = down;
+}
+
+/// Find the entity closest to the middle of the screen, nearest the camera first
+fn pick_entity(world: &mut World) -> Option<Entity> {
+    let cam_transform = world.query::<&PlayerCamera>().iter(world).next()?.view.invert()?;
+    let origin = cam_transform.w.truncate();
+    let forward = -cam_transform.z.truncate().normalize();
+
+    world.query_filtered::<(Entity, &Transform), Without<PlayerMovement>>()
+        .iter(world)
+        .filter_map(|(entity, transform)| {
+            let offset = transform.pos - origin;
+            let along = offset.dot(forward);
+            let distance = (offset - forward * along).magnitude();
+            match along > 0.0 && distance <= PICK_RADIUS {
+                true => Some((entity, along)),
+                false => None,
+            }
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity)
+}
+
+/// Get the entity before or after the selected one, out of the ones with a Transform
+fn cycle_entity(world: &mut World, selected: Option<Entity>, dir: i32) -> Option<Entity> {
+    let mut entities: Vec<Entity> = world.query_filtered::<Entity, With<Transform>>().iter(world).collect();
+    entities.sort();
+
+    let count = entities.len() as i32;
+    let index = match selected.and_then(|selected| entities.iter().position(|entity| *entity == selected)) {
+        Some(index) => (index as i32 + dir).rem_euclid(count.max(1)),
+        None if dir < 0 => count - 1,
+        None => 0,
+    };
+    entities.get(index as usize).copied()
+}
+
+/// Move or turn an entity, changing its LocalTransform if it's parented so the change sticks
+fn nudge_entity(world: &mut World, entity: Entity, offset: Vector3<f32>, angle: f32) {
+    let turn = Matrix3::from_angle_y(Rad(angle.to_radians()));
+    let mut entity = world.entity_mut(entity);
+    match entity.get_mut::<LocalTransform>() {
+        Some(mut local) => {
+            local.pos += offset;
+            local.rot = turn * local.rot;
+        },
+        None => if let Some(mut transform) = entity.get_mut::<Transform>() {
+            transform.pos += offset;
+            transform.rot = turn * transform.rot;
+        },
+    }
+}
+
+/// Get a name for an entity, for the panel and the log
+fn entity_label(world: &World, entity: Entity) -> String {
+    match world.get::<EntityName>(entity) {
+        Some(name) => format!("{} ({})", name.name, entity.id()),
+        None => format!("Entity {}", entity.id()),
+    }
+}
+
+/// Build the inspector panel for an entity
+fn inspector_markup(world: &World, registry: &InspectorRegistry, entity: Option<Entity>) -> String {
+    let entity = match entity {
+        Some(entity) => entity,
+        None => return "{color=yellow}Inspector{/color}\nClick or F6 to pick, page up or down to cycle".to_string(),
+    };
+
+    let escape = |text: &str| text.replace('{', "{{");
+    let mut markup = format!("{{color=yellow}}{}{{/color}}\n", escape(&entity_label(world, entity)));
+
+    if let Some(transform) = world.get::<Transform>(entity) {
+        let forward = transform.rot * vec3(0.0, 0.0, -1.0);
+        let yaw = (-forward.x).atan2(-forward.z).to_degrees();
+        markup += &format!("pos {:.2} {:.2} {:.2}\nyaw {:.1} scale {:.2}\n", transform.pos.x, transform.pos.y,
+            transform.pos.z, yaw, transform.rot.x.magnitude());
+    }
+
+    for (name, describe) in registry.components.iter() {
+        if let Some(description) = describe(world, entity) {
+            markup += &format!("{{color=yellow}}{}{{/color}} {}\n", name, escape(&description));
+        }
+    }
+
+    markup
+}
+
+/// The inspector system, which handles the keys pressed since the last tick, shows the selected
+/// entity's components in the panel and draws its axes. It's exclusive, since the components it
+/// shows can be anything registered. The inspector's only open in game and not while the console's
+/// open, and the sim keeps running while it's shown.
+pub fn update_inspector(world: &mut World) {
+    let in_game = *world.resource::<GameState>() == GameState::InGame;
+    let console_open = world.resource::<DebugConsole>().open;
+    let keys = std::mem::take(&mut world.resource_mut::<Inspector>().keys);
+
+    for key in keys.into_iter().filter(|_| !console_open) {
+        let selected = world.resource::<Inspector>().selected;
+        match key {
+            InspectorKey::Toggle => {
+                let mut inspector = world.resource_mut::<Inspector>();
+                inspector.open = !inspector.open && in_game;
+            },
+            _ if !world.resource::<Inspector>().open => (),
+            InspectorKey::Pick => {
+                let picked = pick_entity(world);
+                world.resource_mut::<Inspector>().selected = picked;
+            },
+            InspectorKey::Cycle(dir) => {
+                let next = cycle_entity(world, selected, dir);
+                world.resource_mut::<Inspector>().selected = next;
+            },
+            InspectorKey::Nudge(offset) => if let Some(entity) = selected {
+                nudge_entity(world, entity, offset, 0.0);
+            },
+            InspectorKey::Turn(angle) => if let Some(entity) = selected {
+                nudge_entity(world, entity, vec3(0.0, 0.0, 0.0), angle);
+            },
+            InspectorKey::Log => if let Some(entity) = selected {
+                if let Some(pos) = world.get::<Transform>(entity).map(|transform| transform.pos) {
+                    log::info!("{}: vec3({:.3}, {:.3}, {:.3})", entity_label(world, entity), pos.x, pos.y, pos.z);
+                }
+            },
+        }
+    }
+
+    // Forget the selection once it's gone
+    let selected = world.resource::<Inspector>().selected.filter(|entity| world.get_entity(*entity).is_some());
+    let open = world.resource::<Inspector>().open && in_game;
+    let mut inspector = world.resource_mut::<Inspector>();
+    inspector.selected = selected;
+    inspector.open = open;
+
+    let markup = match open {
+        true => inspector_markup(world, world.resource::<InspectorRegistry>(), selected),
+        false => String::new(),
+    };
+    let mut query = world.query_filtered::<&mut RichText, With<InspectorText>>();
+    for mut rich_text in query.iter_mut(world) {
+        rich_text.set_markup(&markup);
+    }
+
+    if let Some(transform) = selected.filter(|_| open).and_then(|entity| world.get::<Transform>(entity)) {
+        let (pos, rot) = (transform.pos, transform.rot);
+        let mut debug_draw = world.resource_mut::<DebugDraw>();
+        debug_draw.axes(pos, rot, SELECTION_AXES_SIZE);
+        debug_draw.sphere(pos, SELECTION_AXES_SIZE * 0.25, vec4(1.0, 1.0, 1.0, 1.0));
+    }
+}
+
+/// Register the inspect console command
+pub fn register_inspect_command(commands: &mut ConsoleCommands) {
+    commands.register("inspect", "inspect [entity id]", "Open the inspector, on an entity if given", |world, args| {
+        let selected = match args.first() {
+            Some(id) => {
+                let id: u32 = id.parse().map_err(|_| format!("Invalid entity id: {}", id))?;
+                let entity = world.query::<Entity>().iter(world).find(|entity| entity.id() == id)
+                    .ok_or_else(|| format!("There's no entity {}", id))?;
+                Some(entity)
+            },
+            None => world.resource::<Inspector>().selected,
+        };
+
+        let mut inspector = world.resource_mut::<Inspector>();
+        inspector.open = true;
+        inspector.selected = selected;
+        Ok(match selected {
+            Some(entity) => format!("Inspecting {}", entity_label(world, entity)),
+            None => "Inspector open".to_string(),
+        })
+    });
+}
@@ -0,0 +1,90 @@
+use std::time::Instant;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Vector3, Matrix3, Quaternion};
+use dreamfield_system::components::Transform;
+
+/// The InterpolationClock resource, which keeps track of when the last sim tick was so the
+/// renderer can tell how far it is towards the next one
+pub struct InterpolationClock {
+    /// The length of a sim tick in seconds
+    pub tick_length: f32,
+    last_tick: Option<Instant>,
+}
+
+impl InterpolationClock {
+    pub fn new(tick_length: f32) -> Self {
+        InterpolationClock {
+            tick_length,
+            last_tick: None,
+        }
+    }
+
+    /// Get how far the current frame is between the last sim tick and the next one, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        match (self.last_tick, self.tick_length > 0.0) {
+            (Some(last_tick), true) => (last_tick.elapsed().as_secs_f32() / self.tick_length).clamp(0.0, 1.0),
+            _ => 1.0,
+        }
+    }
+}
+
+/// The PreviousTransform component, for entities that are drawn in between their last two sim
+/// states, so they move smoothly even though the sim only runs at a low fixed rate. While the
+/// render schedule runs, the entity's Transform is swapped for the interpolated one, and then put
+/// back before the next tick.
+#[derive(Component)]
+pub struct PreviousTransform {
+    pub pos: Vector3<f32>,
+    pub rot: Matrix3<f32>,
+    /// The sim's transform while the interpolated one is being drawn
+    sim_transform: Option<(Vector3<f32>, Matrix3<f32>)>,
+}
+
+impl PreviousTransform {
+    pub fn new(transform: &Transform) -> Self {
+        PreviousTransform {
+            pos: transform.pos,
+            rot: transform.rot,
+            sim_transform: None,
+        }
+    }
+}
+
+/// The previous transform system, which runs at the start of each sim tick to remember where
+/// everything was before it moves
+pub fn record_previous_transforms(mut clock: ResMut<InterpolationClock>,
+    mut query: Query<(&mut PreviousTransform, &Transform)>)
+{
+    clock.last_tick = Some(Instant::now());
+
+    for (mut previous, transform) in query.iter_mut() {
+        previous.pos = transform.pos;
+        previous.rot = transform.rot;
+    }
+}
+
+/// The interpolation system, which runs before the renderer and moves entities to wherever they
+/// are between their previous and current transforms
+pub fn interpolate_transforms(clock: Res<InterpolationClock>,
+    mut query: Query<(&mut PreviousTransform, &mut Transform)>)
+{
+    let alpha = clock.alpha();
+
+    for (mut previous, mut transform) in query.iter_mut() {
+        previous.sim_transform = Some((transform.pos, transform.rot));
+        transform.pos = previous.pos + (transform.pos - previous.pos) * alpha;
+        transform.rot = Quaternion::from(previous.rot).nlerp(Quaternion::from(transform.rot), alpha).into();
+    }
+}
+
+/// The restore system, which runs after rendering and puts the sim's transforms back
+pub fn restore_transforms(mut query: Query<(&mut PreviousTransform, &mut Transform)>) {
+    for (mut previous, mut transform) in query.iter_mut() {
+        if let Some((pos, rot)) = previous.sim_transform.take() {
+            transform.pos = pos;
+            transform.rot = rot;
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, With, Without};
+use bevy_ecs::system::{Res, ResMut, Query, Commands};
+use bevy_ecs::world::{World, Mut};
+use cgmath::{InnerSpace, vec4};
+use serde::Deserialize;
+use dreamfield_renderer::components::TextBox;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::InputState;
+
+use super::PlayerMovement;
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::ui_layout::UiLayout;
+use super::locale::Locale;
+use super::console::{ConsoleCommands, DebugConsole};
+
+/// The number of columns in the inventory screen grid
+const GRID_COLUMNS: usize = 4;
+
+/// The size of each cell in the inventory screen grid, in pixels
+const GRID_CELL_SIZE: (f32, f32) = (70.0, 40.0);
+
+/// The top left of the inventory screen grid, in pixels
+const GRID_ORIGIN: (f32, f32) = (20.0, 50.0);
+
+/// An item definition
+#[derive(Deserialize, Debug)]
+pub struct ItemDef {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// The glyph shown for the item in the inventory screen
+    pub icon: String,
+    pub max_stack: u32,
+}
+
+/// The item registry, which stores all the item definitions by id
+pub struct ItemRegistry {
+    items: HashMap<String, ItemDef>,
+}
+
+impl ItemRegistry {
+    /// Create a new item registry from a RON map of item id to definition
+    pub fn new(source: &str) -> Self {
+        let items = match ron::from_str(source) {
+            Ok(items) => items,
+            Err(err) => {
+                log::error!("Failed to load item definitions: {}", err);
+                HashMap::new()
+            }
+        };
+
+        ItemRegistry { items }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ItemDef> {
+        self.items.get(id)
+    }
+
+    /// Get the ids of all the items
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.items.keys().map(String::as_str)
+    }
+}
+
+/// A stack of items in an inventory slot
+#[derive(Clone, Debug)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32,
+}
+
+/// The PlayerInventory component
+#[derive(Component)]
+pub struct PlayerInventory {
+    pub slots: Vec<Option<ItemStack>>,
+}
+
+impl PlayerInventory {
+    pub fn new(capacity: usize) -> Self {
+        PlayerInventory {
+            slots: vec![None; capacity],
+        }
+    }
+
+    /// Add items to the inventory, filling existing stacks first. Returns the number of items that
+    /// didn't fit.
+    pub fn add(&mut self, registry: &ItemRegistry, item: &str, count: u32) -> u32 {
+        let max_stack = match registry.get(item) {
+            Some(def) => u32::max(def.max_stack, 1),
+            None => {
+                log::warn!("Tried to add unknown item to inventory: {}", item);
+                return count;
+            }
+        };
+
+        let mut remaining = count;
+
+        // Top up existing stacks
+        for stack in self.slots.iter_mut().flatten().filter(|stack| stack.item == item) {
+            let added = u32::min(remaining, max_stack.saturating_sub(stack.count));
+            stack.count += added;
+            remaining -= added;
+        }
+
+        // Then fill empty slots
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if remaining == 0 {
+                break;
+            }
+            let added = u32::min(remaining, max_stack);
+            *slot = Some(ItemStack { item: item.to_string(), count: added });
+            remaining -= added;
+        }
+
+        remaining
+    }
+
+    /// Remove items from the inventory, returning false and leaving it unchanged if there aren't enough
+    pub fn remove(&mut self, item: &str, count: u32) -> bool {
+        if self.count(item) < count {
+            return false;
+        }
+
+        let mut remaining = count;
+        for slot in self.slots.iter_mut() {
+            if let Some(stack) = slot.as_mut().filter(|stack| stack.item == item) {
+                let removed = u32::min(remaining, stack.count);
+                stack.count -= removed;
+                remaining -= removed;
+                if stack.count == 0 {
+                    *slot = None;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Count how many of an item are in the inventory
+    pub fn count(&self, item: &str) -> u32 {
+        self.slots.iter().flatten()
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+}
+
+/// The PickupItem component, for world entities the player can pick up by walking into them
+#[derive(Component)]
+pub struct PickupItem {
+    pub item: String,
+    pub count: u32,
+    pub pickup_radius: f32,
+}
+
+impl PickupItem {
+    pub fn new(item: &str, count: u32) -> Self {
+        PickupItem {
+            item: item.to_string(),
+            count,
+            pickup_radius: 1.0,
+        }
+    }
+}
+
+/// The InventoryScreen resource
+#[derive(Default)]
+pub struct InventoryScreen {
+    pub open: bool,
+}
+
+/// A TextBox showing one slot of the inventory screen
+#[derive(Component)]
+pub struct InventorySlotBox(pub usize);
+
+/// The TextBox showing the inventory screen title
+#[derive(Component)]
+pub struct InventoryTitleBox;
+
+/// Create the TextBoxes for the inventory screen grid
+pub fn create_inventory_screen(world: &mut World, capacity: usize) {
+    let font = world.resource::<Locale>().font("medieval").to_string();
+    world.spawn()
+        .insert(InventoryTitleBox)
+        .insert(TextBox::new("text", &font, "Vx8", "", None, Some(vec4(20.0, 20.0, 300.0, 40.0))))
+        .insert(UiLayout::new(vec4(20.0, 20.0, 300.0, 40.0)));
+
+    for slot in 0..capacity {
+        let (column, row) = (slot % GRID_COLUMNS, slot / GRID_COLUMNS);
+        let x = GRID_ORIGIN.0 + column as f32 * GRID_CELL_SIZE.0;
+        let y = GRID_ORIGIN.1 + row as f32 * GRID_CELL_SIZE.1;
+        let bounds = vec4(x, y, x + GRID_CELL_SIZE.0, y + GRID_CELL_SIZE.1);
+
+        world.spawn()
+            .insert(InventorySlotBox(slot))
+            .insert(TextBox::new("text", &font, "Vx8", "", None, Some(bounds)))
+            .insert(UiLayout::new(bounds));
+    }
+}
+
+/// The item pickup system
+pub fn pickup_items(mut commands: Commands, registry: Res<ItemRegistry>,
+    mut player_query: Query<(&Transform, &mut PlayerInventory), With<PlayerMovement>>,
+    mut pickups: Query<(Entity, &mut PickupItem, &Transform), Without<PlayerMovement>>)
+{
+    let (player_transform, mut inventory) = match player_query.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    for (entity, mut pickup, transform) in pickups.iter_mut() {
+        if (transform.pos - player_transform.pos).magnitude() < pickup.pickup_radius {
+            let remaining = inventory.add(&registry, &pickup.item, pickup.count);
+            if remaining == 0 {
+                commands.entity(entity).despawn();
+            }
+            pickup.count = remaining;
+        }
+    }
+}
+
+/// The inventory screen system
+pub fn update_inventory_screen(input: Res<InputState>, gamepad: Res<GamepadState>, bindings: Res<InputBindings>,
+    registry: Res<ItemRegistry>, locale: Res<Locale>, console: Res<DebugConsole>, mut screen: ResMut<InventoryScreen>, player_query: Query<&PlayerInventory>,
+    mut title_query: Query<&mut TextBox, With<InventoryTitleBox>>,
+    mut slot_query: Query<(&InventorySlotBox, &mut TextBox), Without<InventoryTitleBox>>)
+{
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    if controls.is_just_pressed(Action::Inventory) && !console.open {
+        screen.open = !screen.open;
+    }
+
+    let inventory = match player_query.get_single() {
+        Ok(inventory) => inventory,
+        Err(_) => return,
+    };
+
+    for mut text_box in title_query.iter_mut() {
+        text_box.text = match screen.open {
+            true => locale.get("inventory.title").to_string(),
+            false => String::new(),
+        };
+    }
+
+    for (slot_box, mut text_box) in slot_query.iter_mut() {
+        let stack = inventory.slots.get(slot_box.0).and_then(|slot| slot.as_ref());
+        text_box.text = match (screen.open, stack) {
+            (false, _) => String::new(),
+            (true, None) => String::from("[ ]"),
+            (true, Some(stack)) => {
+                let (icon, name) = registry.get(&stack.item)
+                    .map(|def| (def.icon.as_str(), def.name.as_str()))
+                    .unwrap_or(("?", stack.item.as_str()));
+                format!("[{}] x{}\n{}", icon, stack.count, locale.get(name))
+            }
+        };
+    }
+}
+
+/// Register the give console command
+pub fn register_give_command(commands: &mut ConsoleCommands) {
+    commands.register("give", "give <item> [count]", "Put items in the player's inventory", |world, args| {
+        let item = args.first().ok_or_else(|| "Which item?".to_string())?;
+        let count = match args.get(1) {
+            Some(count) => count.parse::<u32>().map_err(|_| format!("Invalid count {}", count))?,
+            None => 1,
+        };
+        if world.resource::<ItemRegistry>().get(item).is_none() {
+            return Err(format!("There's no item called {}", item));
+        }
+
+        world.resource_scope(|world, registry: Mut<ItemRegistry>| {
+            let mut query = world.query_filtered::<&mut PlayerInventory, With<PlayerMovement>>();
+            let mut inventory = query.get_single_mut(world).map_err(|_| "There's no player".to_string())?;
+            match inventory.add(&registry, item, count) {
+                0 => Ok(format!("Gave the player {} {}", count, item)),
+                remaining => Ok(format!("Gave the player {} {}, the other {} didn't fit", count - remaining, item, remaining)),
+            }
+        })
+    }).with_completion(|world, arg| match arg {
+        0 => world.resource::<ItemRegistry>().ids().map(str::to_string).collect(),
+        _ => Vec::new(),
+    });
+}
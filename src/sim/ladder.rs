@@ -0,0 +1,83 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::Without;
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, InnerSpace, vec3};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::InputState;
+
+use super::{PlayerMovement, PlayerMovementMode};
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Controls};
+use super::trigger::{TriggerVolume, TriggerShape};
+
+/// The speed the player is pushed onto the ledge at when they reach the top of a ladder
+const DISMOUNT_SPEED: f32 = 2.0;
+
+/// The Ladder component. Ladders need a TriggerVolume, and the player grabs onto the ladder by
+/// walking forward into it.
+#[derive(Component)]
+pub struct Ladder {
+    /// The direction the ladder goes up in
+    pub axis: Vector3<f32>,
+    pub climb_speed: f32,
+}
+
+impl Default for Ladder {
+    fn default() -> Self {
+        Ladder {
+            axis: vec3(0.0, 1.0, 0.0),
+            climb_speed: 2.0,
+        }
+    }
+}
+
+/// Get the world height of the top of a trigger volume
+fn trigger_top(trigger: &TriggerVolume, pos: Vector3<f32>) -> f32 {
+    match trigger.shape {
+        TriggerShape::Aabb(_, max) => pos.y + max.y,
+        TriggerShape::Sphere(radius) => pos.y + radius,
+    }
+}
+
+/// The ladder system, which switches the player in and out of climbing mode
+pub fn update_climbing(input: Res<InputState>, gamepad: Res<GamepadState>, bindings: Res<InputBindings>,
+    mut player_query: Query<(&Transform, &mut PlayerMovement)>,
+    ladders: Query<(&Ladder, &TriggerVolume, &Transform), Without<PlayerMovement>>)
+{
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    let forward_input = controls.movement().x;
+
+    for (transform, mut player_movement) in player_query.iter_mut() {
+        let ladder = ladders.iter()
+            .find(|(_, trigger, ladder_transform)| trigger.contains(ladder_transform.pos, transform.pos));
+
+        let walking = player_movement.movement_mode == PlayerMovementMode::Normal;
+        let climbing = matches!(player_movement.movement_mode, PlayerMovementMode::Climbing { .. });
+
+        match (walking, climbing, ladder) {
+            // Grab onto the ladder
+            (true, _, Some((ladder, _, _))) if forward_input > 0.0 => {
+                player_movement.movement_mode = PlayerMovementMode::Climbing {
+                    axis: ladder.axis.normalize(),
+                    speed: ladder.climb_speed,
+                };
+            },
+            // Climbed off the ladder
+            (_, true, None) => {
+                // If we're above the top of the nearest ladder, step forward onto the ledge
+                let top = ladders.iter()
+                    .map(|(_, trigger, ladder_transform)| trigger_top(trigger, ladder_transform.pos))
+                    .min_by(|a, b| (a - transform.pos.y).abs().total_cmp(&(b - transform.pos.y).abs()));
+
+                if top.map(|top| transform.pos.y >= top).unwrap_or(false) {
+                    let forward = player_movement.forward();
+                    let forward = vec3(forward.x, 0.0, forward.z).normalize();
+                    player_movement.velocity = (forward + vec3(0.0, 1.0, 0.0)) * DISMOUNT_SPEED;
+                }
+
+                player_movement.movement_mode = PlayerMovementMode::Normal;
+            },
+            _ => {},
+        }
+    }
+}
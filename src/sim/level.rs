@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy_ecs::component::Component;
+use bevy_ecs::event::Events;
+use bevy_ecs::prelude::{Entity, EventReader, EventWriter};
+use bevy_ecs::system::Query;
+use bevy_ecs::world::World;
+use include_dir::Dir;
+use serde::Deserialize;
+use dreamfield_system::world::WorldChunkManager;
+
+use super::game_state::{GameState, NextGameState};
+use super::music::MusicManager;
+use super::portal::find_portal_anchor;
+use super::scene_points::{ScenePoint, ScenePointKind, spawn_scene_points};
+use super::spawn_points::{SpawnPoints, place_player};
+use super::static_collision::StaticCollision;
+use super::trigger::TriggerEnterEvent;
+
+/// A level definition from the levels file
+#[derive(Deserialize, Clone, Debug)]
+pub struct LevelDef {
+    /// The music to play when the player isn't in a music zone
+    pub music: Option<String>,
+    /// Whether the level's indoors, so the weather doesn't reach it
+    #[serde(default)]
+    pub indoors: bool,
+}
+
+/// A level, with its world chunks, the point entities placed in its world model, and the function
+/// that spawns the rest of its entities
+pub struct Level {
+    pub def: LevelDef,
+    pub chunks: &'static Dir<'static>,
+    pub points: Vec<ScenePoint>,
+    pub spawn_scene: fn(&mut World),
+}
+
+/// The level that's currently loaded
+struct LoadedLevel {
+    name: String,
+    entities: HashSet<Entity>,
+}
+
+/// The Levels resource. Each level has its own set of world chunks baked by build.rs, a scene of
+/// entities, and spawn points. Loading a level despawns the current level's scene, swaps in its
+/// chunks and spawns its scene, and puts the player at one of its spawn points. Entities that are spawned
+/// by the sim while a level is running aren't part of its scene, so they're left alone.
+pub struct Levels {
+    levels: HashMap<String, Level>,
+    loaded: Option<LoadedLevel>,
+}
+
+impl Levels {
+    /// Create the levels from a RON map of level definitions, and a list of (name, chunks, points,
+    /// scene) for each level, where the points are the ones build.rs exported from its world model
+    pub fn new(source: &str, scenes: Vec<(&str, &'static Dir<'static>, &str, fn(&mut World))>) -> Self {
+        let mut defs = match ron::from_str::<HashMap<String, LevelDef>>(source) {
+            Ok(defs) => defs,
+            Err(err) => {
+                log::error!("Failed to parse levels: {}", err);
+                HashMap::new()
+            }
+        };
+
+        let levels = scenes.into_iter()
+            .filter_map(|(name, chunks, points, spawn_scene)| match defs.remove(name) {
+                Some(def) => {
+                    let points = ScenePoint::parse_all(points);
+                    Some((name.to_string(), Level { def, chunks, points, spawn_scene }))
+                },
+                None => {
+                    log::error!("No level definition for {}", name);
+                    None
+                }
+            })
+            .collect();
+
+        for name in defs.keys() {
+            log::warn!("Level {} has a definition but no chunks or scene", name);
+        }
+
+        Levels { levels, loaded: None }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Level> {
+        self.levels.get(name)
+    }
+
+    /// Get the spawn points placed in each level's world model, as (level, point)
+    pub fn spawn_points(&self) -> impl Iterator<Item = (&str, &ScenePoint)> {
+        self.levels.iter().flat_map(|(name, level)| {
+            level.points.iter()
+                .filter(|point| point.kind == ScenePointKind::Spawn)
+                .map(move |point| (name.as_str(), point))
+        })
+    }
+
+    /// Get the name of the level that's loaded
+    pub fn current(&self) -> Option<&str> {
+        self.loaded.as_ref().map(|level| level.name.as_str())
+    }
+}
+
+/// An event to load a level and put the player at one of its spawn points or portal anchors
+pub struct LoadLevelEvent {
+    pub level: String,
+    pub entrance: String,
+}
+
+/// The LevelExit component, for TriggerVolumes that load another level when the player enters
+#[derive(Component)]
+pub struct LevelExit {
+    pub level: String,
+    pub entrance: String,
+}
+
+impl LevelExit {
+    pub fn new(level: &str, entrance: &str) -> Self {
+        LevelExit {
+            level: level.to_string(),
+            entrance: entrance.to_string(),
+        }
+    }
+}
+
+/// Load a level straight away, putting the player at a spawn point or anchor if given. If the game is
+/// running, it goes back to the loading state until the world around the entrance has streamed in.
+pub fn load_level(world: &mut World, name: &str, entrance: Option<&str>) {
+    let levels = world.resource::<Levels>();
+    let level = match levels.get(name) {
+        Some(level) => level,
+        None => {
+            log::error!("Tried to load unknown level {}", name);
+            return;
+        }
+    };
+
+    let (chunks, spawn_scene, music) = (level.chunks, level.spawn_scene, level.def.music.clone());
+    let points = level.points.clone();
+    let spawn_point = entrance
+        .and_then(|entrance| world.resource::<SpawnPoints>().get_in_level(entrance, name))
+        .cloned();
+
+    log::info!("Loading level {}", name);
+
+    // Tear down the current level, if it wasn't already despawned with the rest of the game
+    if let Some(loaded) = world.resource_mut::<Levels>().loaded.take() {
+        for entity in loaded.entities {
+            if world.get_entity(entity).is_some() {
+                world.despawn(entity);
+            }
+        }
+    }
+
+    world.insert_resource(WorldChunkManager::new(chunks));
+    if let Some(mut static_collision) = world.get_resource_mut::<StaticCollision>() {
+        static_collision.set_level(name);
+    }
+    if let Some(mut music_manager) = world.get_non_send_resource_mut::<MusicManager>() {
+        music_manager.default_track = music;
+    }
+
+    // Spawn the new level's scene, keeping track of what it spawned for when it's unloaded
+    let all_entities = |world: &mut World| -> HashSet<Entity> {
+        world.query::<Entity>().iter(world).collect()
+    };
+    let existing = all_entities(world);
+    spawn_scene(world);
+    spawn_scene_points(world, &points);
+    let entities = all_entities(world).difference(&existing).copied().collect();
+    world.resource_mut::<Levels>().loaded = Some(LoadedLevel { name: name.to_string(), entities });
+
+    // Levels can also be entered at any portal anchor in their scene
+    let placement = match (entrance, spawn_point) {
+        (_, Some(spawn_point)) => Some((spawn_point.pos(), spawn_point.pitch_yaw())),
+        (Some(entrance), None) => {
+            let anchor = find_portal_anchor(world, entrance);
+            if anchor.is_none() {
+                log::error!("Level {} has no spawn point or anchor {}", name, entrance);
+            }
+            anchor
+        },
+        (None, None) => None,
+    };
+
+    if let Some((pos, pitch_yaw)) = placement {
+        place_player(world, pos, pitch_yaw);
+    }
+
+    if *world.resource::<GameState>() == GameState::InGame {
+        world.resource_mut::<NextGameState>().set(GameState::Loading);
+    }
+}
+
+/// The level loading system, which loads the level from the last LoadLevelEvent. It's exclusive,
+/// since it swaps out resources and spawns whole scenes, and it drains the events itself.
+pub fn handle_level_loads(world: &mut World) {
+    let event = world.resource_mut::<Events<LoadLevelEvent>>().drain().last();
+    if let Some(event) = event {
+        load_level(world, &event.level, Some(&event.entrance));
+    }
+}
+
+/// The level exit system
+pub fn update_level_exits(mut trigger_events: EventReader<TriggerEnterEvent>, exits: Query<&LevelExit>,
+    mut load_events: EventWriter<LoadLevelEvent>)
+{
+    for event in trigger_events.iter() {
+        if let Ok(exit) = exits.get(event.trigger) {
+            load_events.send(LoadLevelEvent { level: exit.level.clone(), entrance: exit.entrance.clone() });
+        }
+    }
+}
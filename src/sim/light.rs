@@ -0,0 +1,102 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::EventReader;
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, InnerSpace, vec3};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::particles::ParticleEmitter;
+use super::audio::AudioSource;
+use super::projectile::ProjectileImpactEvent;
+
+/// The distance from a projectile impact within which unlit lights are lit
+const IGNITE_RADIUS: f32 = 1.0;
+
+/// The LightSource component, for flickering point lights like torches and lanterns.
+///
+/// The ps1 shader currently only uses lighting baked into vertex colors, so the animated intensity
+/// isn't drawn yet. It's kept up to date here, matching the renderer's point light parameters.
+#[derive(Component)]
+pub struct LightSource {
+    pub color: Vector3<f32>,
+    pub radius: f32,
+    pub intensity: f32,
+    /// The fraction of the intensity that flickers
+    pub flicker_amplitude: f32,
+    /// The flicker speed in cycles per second
+    pub flicker_frequency: f32,
+    pub lit: bool,
+    /// An offset into the flicker so that lights don't flicker in sync
+    phase: f32,
+    current_intensity: f32,
+}
+
+impl LightSource {
+    pub fn new(color: Vector3<f32>, radius: f32, intensity: f32) -> Self {
+        LightSource {
+            color,
+            radius,
+            intensity,
+            flicker_amplitude: 0.0,
+            flicker_frequency: 0.0,
+            lit: true,
+            phase: 0.0,
+            current_intensity: intensity,
+        }
+    }
+
+    /// Create a new flickering torch light. The position is used to offset the flicker.
+    pub fn new_torch(pos: Vector3<f32>) -> Self {
+        LightSource {
+            flicker_amplitude: 0.25,
+            flicker_frequency: 3.0,
+            phase: (pos.x * 12.9898 + pos.z * 78.233).sin() * 43.758,
+            ..LightSource::new(vec3(1.0, 0.6, 0.3), 6.0, 1.0)
+        }
+    }
+
+    /// Get the light's intensity this frame
+    pub fn current_intensity(&self) -> f32 {
+        self.current_intensity
+    }
+}
+
+/// The light update system, which animates light flicker
+pub fn update_lights(sim_time: Res<SimTime>,
+    mut query: Query<(&mut LightSource, Option<&mut ParticleEmitter>, Option<&mut AudioSource>)>)
+{
+    let time = sim_time.sim_time as f32;
+
+    for (mut light, emitter, audio_source) in query.iter_mut() {
+        light.current_intensity = match light.lit {
+            true => {
+                // Sum a couple of unrelated sine waves so the flicker doesn't look regular
+                let t = time * light.flicker_frequency * 2.0 * std::f32::consts::PI + light.phase;
+                let flicker = 0.6 * t.sin() + 0.4 * (2.3 * t + 1.7).sin();
+                light.intensity * (1.0 + light.flicker_amplitude * flicker)
+            },
+            false => 0.0,
+        };
+
+        // Torch fires only burn and crackle while they're lit
+        if let Some(mut emitter) = emitter {
+            emitter.enabled = light.lit;
+        }
+        if let Some(mut audio_source) = audio_source {
+            if audio_source.playing != light.lit {
+                audio_source.playing = light.lit;
+            }
+        }
+    }
+}
+
+/// The light ignition system, which lets projectiles like fire orbs light unlit torches
+pub fn ignite_lights(mut events: EventReader<ProjectileImpactEvent>, mut query: Query<(&mut LightSource, &Transform)>) {
+    for event in events.iter() {
+        for (mut light, transform) in query.iter_mut() {
+            if !light.lit && (transform.pos - event.position).magnitude() < IGNITE_RADIUS {
+                light.lit = true;
+            }
+        }
+    }
+}
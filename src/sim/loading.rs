@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use bevy_ecs::component::Component;
+use bevy_ecs::system::{Res, ResMut, Query};
+use bevy_ecs::world::World;
+use cgmath::{Vector3, vec3};
+use dreamfield_renderer::components::TextBox;
+use dreamfield_system::resources::SimTime;
+use dreamfield_system::world::WorldChunkManager;
+use dreamfield_system::world::world_collision::WorldCollision;
+
+use super::game_state::{GameState, NextGameState};
+use super::locale::Locale;
+
+/// The spinner frames shown while loading
+const SPINNER: &'static [char] = &['|', '/', '-', '\\'];
+
+/// How long each spinner frame is shown for, in seconds
+const SPINNER_FRAME_TIME: f32 = 0.1;
+
+/// The radius of the sphere dropped onto the spawn point to check its chunk is loaded, and how
+/// far above it it's dropped from
+const SPAWN_PROBE_RADIUS: f32 = 0.25;
+const SPAWN_PROBE_HEIGHT: f32 = 2.0;
+
+/// Something the loader thread builds, which is then put into the world on the main thread
+type LoadedAsset = Box<dyn FnOnce(&mut World) + Send>;
+
+/// A job for the loader thread
+type AssetJob = Box<dyn FnOnce() -> LoadedAsset + Send>;
+
+/// A message from the loader thread
+enum LoadMessage {
+    Started(String),
+    Loaded(LoadedAsset),
+}
+
+/// Assets that replace the ones built into the game when an asset manager's built, by name, for
+/// the ones the hot_reload feature has reloaded from disk
+#[derive(Default)]
+pub struct AssetOverrides {
+    assets: HashMap<String, &'static [u8]>,
+}
+
+impl AssetOverrides {
+    pub fn insert(&mut self, name: &str, bytes: &'static [u8]) {
+        self.assets.insert(name.to_string(), bytes);
+    }
+
+    /// Get the bytes for an asset, which are the ones that replace it if there are any
+    pub fn bytes<'a>(&self, name: &str, built_in: &'a [u8]) -> &'a [u8] {
+        self.assets.get(name).copied().unwrap_or(built_in)
+    }
+}
+
+/// The AssetLoader resource, which builds asset managers on a background thread so that startup
+/// doesn't block on them, and inserts them into the world as they finish. The renderer starts out
+/// with empty managers, and the game waits in the loading state until everything's loaded.
+#[derive(Default)]
+pub struct AssetLoader {
+    jobs: Vec<(String, AssetJob)>,
+    receiver: Option<Mutex<Receiver<LoadMessage>>>,
+    total: usize,
+    loaded: usize,
+    current: Option<String>,
+    failed: bool,
+}
+
+impl AssetLoader {
+    /// Add a resource to build on the loader thread
+    pub fn load_resource<R: Send + Sync + 'static>(mut self, name: &str, create: fn() -> R) -> Self {
+        let job: AssetJob = Box::new(move || {
+            let resource = create();
+            Box::new(move |world: &mut World| world.insert_resource(resource))
+        });
+        self.jobs.push((name.to_string(), job));
+        self
+    }
+
+    /// Start the loader thread
+    pub fn start(mut self) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let jobs: Vec<(String, AssetJob)> = self.jobs.drain(..).collect();
+        self.total = jobs.len();
+
+        let result = std::thread::Builder::new()
+            .name("asset_loader".to_string())
+            .spawn(move || {
+                for (name, job) in jobs {
+                    log::info!("Loading {}", name);
+                    let sent = sender.send(LoadMessage::Started(name))
+                        .and_then(|_| sender.send(LoadMessage::Loaded(job())));
+                    if sent.is_err() {
+                        return;
+                    }
+                }
+            });
+
+        match result {
+            Ok(_) => self.receiver = Some(Mutex::new(receiver)),
+            Err(err) => {
+                log::error!("Failed to start the asset loader thread: {}", err);
+                self.failed = true;
+            }
+        }
+
+        self
+    }
+
+    /// Check whether everything's been loaded, or the loader has given up
+    pub fn finished(&self) -> bool {
+        self.loaded >= self.total || self.failed
+    }
+
+    /// Get how much has been loaded, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        match self.total {
+            0 => 1.0,
+            total => self.loaded as f32 / total as f32,
+        }
+    }
+}
+
+/// The asset loader system, which puts anything the loader thread has finished into the world
+pub fn poll_asset_loader(world: &mut World) {
+    let (messages, disconnected) = {
+        let loader = world.resource::<AssetLoader>();
+        let receiver = match (&loader.receiver, loader.finished()) {
+            (Some(receiver), false) => receiver.lock().unwrap(),
+            _ => return,
+        };
+
+        let mut messages = Vec::new();
+        let disconnected = loop {
+            match receiver.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+        (messages, disconnected)
+    };
+
+    for message in messages {
+        match message {
+            LoadMessage::Started(name) => world.resource_mut::<AssetLoader>().current = Some(name),
+            LoadMessage::Loaded(insert) => {
+                insert(world);
+                world.resource_mut::<AssetLoader>().loaded += 1;
+            }
+        }
+    }
+
+    let mut loader = world.resource_mut::<AssetLoader>();
+    if disconnected && !loader.finished() {
+        log::error!("The asset loader thread stopped before loading {}", loader.current.as_deref().unwrap_or("anything"));
+        loader.failed = true;
+    }
+}
+
+/// The LoadingTextBox component, for the TextBox the loading progress is shown in
+#[derive(Component)]
+pub struct LoadingTextBox;
+
+/// The LoadingScreen resource. The game is started once the assets are loaded and the world chunk
+/// the player spawns in is resident, which is checked by dropping a sphere onto the spawn point,
+/// so the player isn't dropped into a world that hasn't been streamed in yet. The player collides
+/// with the level's baked StaticCollision, so this is only for the engine's chunks.
+pub struct LoadingScreen {
+    pub spawn_point: Vector3<f32>,
+    spinner_time: f32,
+}
+
+impl LoadingScreen {
+    pub fn new(spawn_point: Vector3<f32>) -> Self {
+        LoadingScreen {
+            spawn_point,
+            spinner_time: 0.0,
+        }
+    }
+
+    /// Check whether there's ground under the spawn point
+    fn spawn_chunk_resident(&self, collision: &mut WorldCollision, world: &mut WorldChunkManager) -> bool {
+        let cbm = vec3(1.0, 1.0, 1.0) / SPAWN_PROBE_RADIUS;
+        let start = (self.spawn_point + vec3(0.0, SPAWN_PROBE_HEIGHT, 0.0)) / SPAWN_PROBE_RADIUS;
+        let drop = vec3(0.0, -2.0 * SPAWN_PROBE_HEIGHT, 0.0) / SPAWN_PROBE_RADIUS;
+        collision.sweep_unit_sphere(world, start, drop, cbm, None).is_some()
+    }
+}
+
+/// The loading screen system, which starts the game once it's ready and shows the progress
+pub fn update_loading_screen(sim_time: Res<SimTime>, state: Res<GameState>, mut next_state: ResMut<NextGameState>,
+    loader: Res<AssetLoader>, locale: Res<Locale>, mut loading: ResMut<LoadingScreen>, mut collision: ResMut<WorldCollision>,
+    mut world: ResMut<WorldChunkManager>, mut query: Query<(&LoadingTextBox, &mut TextBox)>)
+{
+    let status = match *state == GameState::Loading {
+        false => String::new(),
+        true => {
+            loading.spinner_time += sim_time.sim_time_delta as f32;
+
+            if loader.finished() && loading.spawn_chunk_resident(&mut collision, &mut world) {
+                next_state.set(GameState::InGame);
+            }
+
+            let spinner = SPINNER[(loading.spinner_time / SPINNER_FRAME_TIME) as usize % SPINNER.len()];
+            let title = locale.format("loading.title", &[("spinner", &spinner)]);
+            match (loader.finished(), &loader.current) {
+                (false, Some(current)) => {
+                    let percent = format!("{:.0}", loader.progress() * 100.0);
+                    let asset = locale.format("loading.asset", &[("asset", current), ("percent", &percent)]);
+                    format!("{}\n\n{}", title, asset)
+                },
+                (false, None) => title,
+                (true, _) => format!("{}\n\n{}", title, locale.get("loading.streaming")),
+            }
+        }
+    };
+
+    for (_, mut text_box) in query.iter_mut() {
+        if text_box.text != status {
+            text_box.text = status.clone();
+        }
+    }
+}
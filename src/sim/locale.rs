@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// The language the game falls back to for any strings a language pack doesn't have
+pub const DEFAULT_LANGUAGE: &'static str = "en";
+
+/// A language pack, with the strings for one language by key
+pub struct LanguagePack {
+    /// The language's name in itself, for the settings menu
+    pub name: String,
+    strings: HashMap<String, String>,
+    /// The fonts to draw the language with instead of the default ones, for languages that need
+    /// glyphs the default fonts don't have
+    fonts: HashMap<String, String>,
+}
+
+impl LanguagePack {
+    /// Load a language pack from a csv file of key,text rows. Fields can be quoted to put commas
+    /// in them, with "" for a quote, and \n in the text is a line break. Rows with keys starting
+    /// with @ are settings for the pack: @name for the language's name, and @font.<font> for a font
+    /// to use in place of another.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut pack = LanguagePack {
+            name: String::new(),
+            strings: HashMap::new(),
+            fonts: HashMap::new(),
+        };
+
+        for (line_number, line) in source.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = parse_csv_line(line)
+                .map_err(|err| format!("line {}: {}", line_number + 1, err))?;
+            let (key, text) = match fields.as_slice() {
+                [key, text] => (key.trim(), text.replace("\\n", "\n")),
+                _ => return Err(format!("line {}: expected 2 fields, found {}", line_number + 1, fields.len())),
+            };
+
+            match key.strip_prefix('@') {
+                Some("name") => pack.name = text,
+                Some(setting) => match setting.strip_prefix("font.") {
+                    Some(font) => {
+                        pack.fonts.insert(font.to_string(), text);
+                    },
+                    None => log::warn!("Unknown language pack setting {}", key),
+                },
+                None => {
+                    pack.strings.insert(key.to_string(), text);
+                },
+            }
+        }
+
+        Ok(pack)
+    }
+}
+
+/// Split a line of csv into its fields
+fn parse_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', false) if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            },
+            ('"', true) => match chars.peek() {
+                Some('"') => {
+                    chars.next();
+                    field.push('"');
+                },
+                _ => quoted = false,
+            },
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+
+    fields.push(field);
+    Ok(fields)
+}
+
+/// The Locale resource, with the language packs and the language that's in use. User-facing text is
+/// looked up by key, and anything that isn't a key in the current language or the default one is
+/// shown as it is, so text in content files can either be a key or written out in English.
+pub struct Locale {
+    packs: Vec<(String, LanguagePack)>,
+    current: usize,
+}
+
+impl Locale {
+    /// Create the locale from a list of (language code, csv source) pairs, starting in the default
+    /// language
+    pub fn new(packs: Vec<(&str, &str)>) -> Self {
+        let mut packs: Vec<(String, LanguagePack)> = packs.into_iter()
+            .filter_map(|(language, source)| {
+                match LanguagePack::parse(source) {
+                    Ok(pack) => Some((language.to_string(), pack)),
+                    Err(err) => {
+                        log::error!("Failed to load language pack {}: {}", language, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        packs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let current = packs.iter().position(|(language, _)| language == DEFAULT_LANGUAGE).unwrap_or(0);
+        Locale { packs, current }
+    }
+
+    /// Get the language codes of the language packs
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.packs.iter().map(|(language, _)| language.as_str())
+    }
+
+    /// Get the language code of the language that's in use
+    pub fn language(&self) -> &str {
+        self.packs.get(self.current).map_or(DEFAULT_LANGUAGE, |(language, _)| language.as_str())
+    }
+
+    /// Get the name of a language, for showing in the settings menu
+    pub fn language_name<'a>(&'a self, language: &'a str) -> &'a str {
+        self.packs.iter()
+            .find(|(code, pack)| code == language && !pack.name.is_empty())
+            .map_or(language, |(_, pack)| pack.name.as_str())
+    }
+
+    /// Switch to another language, returning false if there's no pack for it
+    pub fn set_language(&mut self, language: &str) -> bool {
+        match self.packs.iter().position(|(code, _)| code == language) {
+            Some(index) => {
+                self.current = index;
+                true
+            },
+            None => {
+                log::warn!("No language pack for {}", language);
+                false
+            }
+        }
+    }
+
+    /// Get the language before or after another in the list, in the direction -1 or 1, wrapping around
+    pub fn cycle_language(&self, language: &str, dir: i32) -> String {
+        let count = self.packs.len() as i32;
+        match self.packs.iter().position(|(code, _)| code == language) {
+            Some(index) if count > 0 => self.packs[(index as i32 + dir).rem_euclid(count) as usize].0.clone(),
+            _ => self.language().to_string(),
+        }
+    }
+
+    fn packs_in_order(&self) -> impl Iterator<Item = &LanguagePack> {
+        let default = self.packs.iter().find(|(language, _)| language == DEFAULT_LANGUAGE);
+        self.packs.get(self.current).into_iter().chain(default).map(|(_, pack)| pack)
+    }
+
+    /// Get the text for a key in the current language, or the key itself if there's none
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.packs_in_order()
+            .find_map(|pack| pack.strings.get(key))
+            .map_or(key, String::as_str)
+    }
+
+    /// Get the text for a key with named arguments filled in, where {name} in the text is replaced
+    /// with the argument called name. Other braces are left alone for rich text markup.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        args.iter().fold(self.get(key).to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), &value.to_string())
+        })
+    }
+
+    /// Get the font to draw text in a font with in the current language, which is the font itself
+    /// unless the language pack replaces it. RichText follows the language as it changes, but plain
+    /// TextBoxes keep the font they were made with, so their fonts change the next time the game's
+    /// started.
+    pub fn font<'a>(&'a self, font: &'a str) -> &'a str {
+        self.packs.get(self.current)
+            .and_then(|(_, pack)| pack.fonts.get(font))
+            .map_or(font, String::as_str)
+    }
+}
@@ -0,0 +1,103 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, With, Without};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, Matrix3, Rad, InnerSpace, vec3};
+use serde::Deserialize;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::PlayerMovement;
+
+/// What an entity looks at
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum LookTarget {
+    Player,
+    #[serde(skip)]
+    Entity(Entity),
+    Position(f32, f32, f32),
+}
+
+/// The LookAtTarget component, for entities that turn to glance at a target when it's nearby, like
+/// npcs watching the player walk past. The renderer doesn't expose the skeleton, so rather than
+/// turning a head bone it turns the whole entity by up to a limit from whichever way its own
+/// movement last faced it, and turns back when the target leaves.
+#[derive(Component)]
+pub struct LookAtTarget {
+    pub target: LookTarget,
+    /// How close the target has to be to be looked at
+    pub range: f32,
+    /// The furthest the entity turns from the way it's facing, in degrees. Targets more than half as
+    /// far again behind that are ignored.
+    pub max_angle: f32,
+    /// How quickly the entity turns towards the target, with higher values being snappier
+    pub smoothing: f32,
+    /// How far the entity is currently turned from its facing, in radians
+    angle: f32,
+    /// The rotation the entity had before it was turned, and the rotation it was turned to
+    base_rot: Option<Matrix3<f32>>,
+    applied_rot: Option<Matrix3<f32>>,
+}
+
+impl LookAtTarget {
+    pub fn new(target: LookTarget) -> Self {
+        LookAtTarget {
+            target,
+            range: 5.0,
+            max_angle: 60.0,
+            smoothing: 4.0,
+            angle: 0.0,
+            base_rot: None,
+            applied_rot: None,
+        }
+    }
+}
+
+/// Get the heading of a direction around the y axis, where 0 is facing down -z
+fn heading(dir: Vector3<f32>) -> f32 {
+    f32::atan2(-dir.x, -dir.z)
+}
+
+/// The look at system, which runs after whatever moves the entities so they look away from the way
+/// their movement faced them
+pub fn update_look_at(sim_time: Res<SimTime>, player_query: Query<&Transform, With<PlayerMovement>>,
+    target_query: Query<&Transform, Without<LookAtTarget>>,
+    mut query: Query<(&mut LookAtTarget, &mut Transform), Without<PlayerMovement>>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    let player_pos = player_query.get_single().ok().map(|transform| transform.pos);
+
+    for (mut look_at, mut transform) in query.iter_mut() {
+        // If something else rotated the entity since we last turned it, that's its new facing
+        let base_rot = match (look_at.base_rot, look_at.applied_rot) {
+            (Some(base_rot), Some(applied_rot)) if applied_rot == transform.rot => base_rot,
+            _ => transform.rot,
+        };
+
+        let target_pos = match look_at.target {
+            LookTarget::Player => player_pos,
+            LookTarget::Entity(entity) => target_query.get(entity).ok().map(|transform| transform.pos),
+            LookTarget::Position(x, y, z) => Some(vec3(x, y, z)),
+        };
+
+        // Work out how far to turn to face the target, if it's close enough and not behind us
+        let max_angle = look_at.max_angle.to_radians();
+        let target_angle = target_pos
+            .map(|target_pos| vec3(target_pos.x - transform.pos.x, 0.0, target_pos.z - transform.pos.z))
+            .filter(|to_target| to_target.magnitude2() < look_at.range * look_at.range)
+            .map(|to_target| {
+                let facing = heading(base_rot * vec3(0.0, 0.0, -1.0));
+                (heading(to_target) - facing + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU)
+                    - std::f32::consts::PI
+            })
+            .filter(|diff| diff.abs() <= max_angle * 1.5)
+            .map(|diff| diff.clamp(-max_angle, max_angle))
+            .unwrap_or(0.0);
+
+        look_at.angle += (target_angle - look_at.angle) * (1.0 - f32::exp(-look_at.smoothing * time_delta));
+
+        let rot = Matrix3::from_angle_y(Rad(look_at.angle)) * base_rot;
+        transform.rot = rot;
+        look_at.base_rot = Some(base_rot);
+        look_at.applied_rot = Some(rot);
+    }
+}
@@ -0,0 +1,283 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::schedule::ShouldRun;
+use bevy_ecs::system::{Res, ResMut, Query};
+use dreamfield_renderer::components::TextBox;
+use dreamfield_system::resources::InputState;
+
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::gamepad::GamepadState;
+use super::photo_mode::PhotoMode;
+use super::game_state::{GameState, NextGameState};
+use super::sim_settings::SimSettings;
+use super::title::{TitleScreen, AttractState};
+use super::settings::{Settings, SettingsEntry};
+use super::locale::Locale;
+use super::console::DebugConsole;
+
+/// The threshold on the movement input for navigating the menu
+const NAV_THRESHOLD: f32 = 0.5;
+
+/// The entries on the main page of the pause menu
+const MAIN_ENTRIES: &'static [MenuEntry] = &[MenuEntry::Resume, MenuEntry::Settings, MenuEntry::QuitToTitle,
+    MenuEntry::Quit];
+
+/// The entries on the game over page
+const GAME_OVER_ENTRIES: &'static [MenuEntry] = &[MenuEntry::Continue, MenuEntry::QuitToTitle];
+
+/// The entries on the settings page of the pause menu
+const SETTINGS_ENTRIES: &'static [SettingsEntry] = &[SettingsEntry::Volume, SettingsEntry::Fov,
+    SettingsEntry::Resolution, SettingsEntry::Aspect, SettingsEntry::InvertY, SettingsEntry::CrtEffect,
+    SettingsEntry::ColorDepth, SettingsEntry::Dither, SettingsEntry::Language, SettingsEntry::Back];
+
+/// The pause menu component, for the TextBox the menu is drawn to
+#[derive(Component)]
+pub struct PauseMenu;
+
+/// The entries in the pause menu
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuEntry {
+    Resume,
+    Settings,
+    /// Continue from the last checkpoint after dying
+    Continue,
+    QuitToTitle,
+    Quit,
+}
+
+impl MenuEntry {
+    /// Get the locale key for the entry's name
+    pub fn key(&self) -> &'static str {
+        match self {
+            MenuEntry::Resume => "menu.resume",
+            MenuEntry::Settings => "menu.settings",
+            MenuEntry::Continue => "menu.continue",
+            MenuEntry::QuitToTitle => "menu.quit_to_title",
+            MenuEntry::Quit => "menu.quit",
+        }
+    }
+}
+
+/// The pages of the pause menu
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuPage {
+    Main,
+    Settings,
+    GameOver,
+}
+
+/// The MenuState resource, for the pause and game over menus. Whether they're open depends on the
+/// GameState.
+pub struct MenuState {
+    pub page: MenuPage,
+    pub selection: usize,
+    nav: MenuNav,
+}
+
+/// Vertical menu navigation from the movement input, so that holding a direction only moves once
+#[derive(Default)]
+pub struct MenuNav {
+    last_nav: i32,
+    last_adjust: i32,
+}
+
+impl MenuNav {
+    /// Get the navigation direction this frame: -1 for up, 1 for down, or 0 for none
+    pub fn update(&mut self, controls: &Controls) -> i32 {
+        let nav = match controls.movement().x {
+            x if x > NAV_THRESHOLD => -1,
+            x if x < -NAV_THRESHOLD => 1,
+            _ => 0,
+        };
+
+        let just_pressed = if nav != self.last_nav { nav } else { 0 };
+        self.last_nav = nav;
+        just_pressed
+    }
+
+    /// Get the horizontal direction this frame for adjusting values: -1 for left, 1 for right, or 0
+    pub fn adjust(&mut self, controls: &Controls) -> i32 {
+        let adjust = match controls.movement().y {
+            y if y > NAV_THRESHOLD => 1,
+            y if y < -NAV_THRESHOLD => -1,
+            _ => 0,
+        };
+
+        let just_pressed = if adjust != self.last_adjust { adjust } else { 0 };
+        self.last_adjust = adjust;
+        just_pressed
+    }
+
+    /// Move a selection index by the navigation direction, wrapping around
+    pub fn apply(&mut self, controls: &Controls, selection: usize, entry_count: usize) -> usize {
+        let nav = self.update(controls);
+        match entry_count {
+            0 => 0,
+            _ => (selection as i32 + nav).rem_euclid(entry_count as i32) as usize,
+        }
+    }
+}
+
+impl Default for MenuState {
+    fn default() -> Self {
+        MenuState {
+            page: MenuPage::Main,
+            selection: 0,
+            nav: MenuNav::default(),
+        }
+    }
+}
+
+impl MenuState {
+    /// Open the menu at the main page
+    pub fn open(&mut self) {
+        self.page = MenuPage::Main;
+        self.selection = 0;
+    }
+
+    /// Open the game over page
+    pub fn open_game_over(&mut self) {
+        self.page = MenuPage::GameOver;
+        self.selection = 0;
+    }
+
+    /// Go back to the main page, with the settings entry selected
+    pub fn open_main_page(&mut self) {
+        self.page = MenuPage::Main;
+        self.selection = MAIN_ENTRIES.iter().position(|entry| *entry == MenuEntry::Settings).unwrap_or(0);
+    }
+}
+
+/// Run criteria for systems that should only run in game, while it isn't paused, in photo mode or
+/// in the debug console
+pub fn sim_running(state: Res<GameState>, photo_mode: Res<PhotoMode>, sim_settings: Res<SimSettings>,
+    console: Res<DebugConsole>) -> ShouldRun
+{
+    match *state == GameState::InGame && !photo_mode.active && !console.open && sim_settings.running() {
+        true => ShouldRun::Yes,
+        false => ShouldRun::No,
+    }
+}
+
+/// The pause and game over menu system
+pub fn update_menu(input: Res<InputState>, gamepad: Res<GamepadState>, bindings: Res<InputBindings>,
+    photo_mode: Res<PhotoMode>, console: Res<DebugConsole>, title: Res<TitleScreen>, state: Res<GameState>, mut next_state: ResMut<NextGameState>,
+    locale: Res<Locale>, mut menu: ResMut<MenuState>, mut settings: ResMut<Settings>,
+    mut query: Query<(&PauseMenu, &mut TextBox)>)
+{
+    let controls = Controls::new(&input, &gamepad, &bindings);
+
+    // Photo mode hides the UI, so leave it alone, and the menu can't be opened during the demo or
+    // while typing into the console
+    if photo_mode.active || console.open || title.attract != AttractState::Off {
+        return;
+    }
+
+    if *state == GameState::GameOver && menu.page != MenuPage::GameOver {
+        menu.open_game_over();
+    }
+
+    if controls.is_just_pressed(Action::Pause) {
+        match (*state, menu.page) {
+            (GameState::InGame, _) => {
+                menu.open();
+                next_state.set(GameState::Paused);
+            },
+            (GameState::Paused, MenuPage::Main) => next_state.set(GameState::InGame),
+            (GameState::Paused, MenuPage::Settings) => menu.open_main_page(),
+            _ => (),
+        }
+    }
+
+    let open = matches!(*state, GameState::Paused | GameState::GameOver);
+    if open {
+        update_navigation(&controls, &locale, &mut menu, &mut settings, &mut next_state);
+    }
+
+    for (_, mut text_box) in query.iter_mut() {
+        text_box.text = match open {
+            false => String::new(),
+            true => menu_text(&menu, &settings, &locale),
+        };
+    }
+}
+
+/// Activate an entry on the main or game over page
+fn activate_entry(entry: MenuEntry, menu: &mut MenuState, next_state: &mut NextGameState) {
+    match entry {
+        MenuEntry::Resume | MenuEntry::Continue => next_state.set(GameState::InGame),
+        MenuEntry::Settings => {
+            menu.page = MenuPage::Settings;
+            menu.selection = 0;
+        },
+        MenuEntry::QuitToTitle => next_state.set(GameState::Title),
+        MenuEntry::Quit => {
+            log::info!("Quitting from pause menu");
+            std::process::exit(0);
+        }
+    }
+}
+
+/// Navigate the menu with the movement input and activate entries with the select input
+fn update_navigation(controls: &Controls, locale: &Locale, menu: &mut MenuState, settings: &mut ResMut<Settings>,
+    next_state: &mut NextGameState)
+{
+    match menu.page {
+        MenuPage::Main => {
+            menu.selection = menu.nav.apply(controls, menu.selection, MAIN_ENTRIES.len());
+
+            if controls.is_just_pressed(Action::Interact) {
+                activate_entry(MAIN_ENTRIES[menu.selection], menu, next_state);
+            }
+        },
+        MenuPage::GameOver => {
+            menu.selection = menu.nav.apply(controls, menu.selection, GAME_OVER_ENTRIES.len());
+
+            if controls.is_just_pressed(Action::Interact) {
+                activate_entry(GAME_OVER_ENTRIES[menu.selection], menu, next_state);
+            }
+        },
+        MenuPage::Settings => {
+            menu.selection = menu.nav.apply(controls, menu.selection, SETTINGS_ENTRIES.len());
+            let entry = SETTINGS_ENTRIES[menu.selection];
+
+            // Only mutate the settings when something's actually changed, since that saves them
+            let adjust = menu.nav.adjust(controls);
+            if adjust != 0 && entry != SettingsEntry::Back {
+                settings.adjust(entry, adjust, locale);
+            }
+
+            if controls.is_just_pressed(Action::Interact) {
+                match entry {
+                    SettingsEntry::Back => menu.open_main_page(),
+                    _ => settings.adjust(entry, 1, locale),
+                }
+            }
+        },
+    }
+}
+
+/// Build the text for the current menu page
+fn menu_text(menu: &MenuState, settings: &Settings, locale: &Locale) -> String {
+    match menu.page {
+        MenuPage::Main => entries_text(locale.get("menu.paused"), MAIN_ENTRIES, menu.selection, locale),
+        MenuPage::GameOver => entries_text(locale.get("menu.game_over"), GAME_OVER_ENTRIES, menu.selection, locale),
+        MenuPage::Settings => {
+            let mut text = format!("{}\n\n", locale.get("menu.settings_title"));
+            for (i, entry) in SETTINGS_ENTRIES.iter().enumerate() {
+                let cursor = if i == menu.selection { ">" } else { " " };
+                text += &format!("{} {:<11}{}\n", cursor, locale.get(entry.key()), settings.value_text(*entry, locale));
+            }
+            text
+        },
+    }
+}
+
+/// Build the text for a page of menu entries under a heading
+fn entries_text(heading: &str, entries: &[MenuEntry], selection: usize, locale: &Locale) -> String {
+    let mut text = format!("{}\n\n", heading);
+    for (i, entry) in entries.iter().enumerate() {
+        let cursor = if i == selection { ">" } else { " " };
+        text += &format!("{} {}\n", cursor, locale.get(entry.key()));
+    }
+    text
+}
@@ -1,10 +1,14 @@
-use bevy_ecs::{system::{Res, Query, ParamSet}, prelude::Component};
+use bevy_ecs::{system::{Res, Query, ParamSet}, prelude::{Component, EventWriter}};
 use cgmath::{Vector3, InnerSpace, vec3, Matrix3, SquareMatrix};
 use dreamfield_renderer::components::PlayerCamera;
-use dreamfield_system::resources::{SimTime, InputState, InputName};
+use dreamfield_system::resources::{SimTime, InputState};
 use dreamfield_system::components::Transform as TransformComponent;
 
 use super::PlayerMovement;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::gamepad::GamepadState;
+use super::audio::AudioSource;
+use super::camera_shake::CameraShakeEvent;
 
 /// Minecart component
 #[derive(Component)]
@@ -71,15 +75,21 @@ impl Minecart {
 
 pub fn update_minecart(sim_time: Res<SimTime>,
                        input: Res<InputState>,
+                       gamepad: Res<GamepadState>,
+                       bindings: Res<InputBindings>,
+                       mut camera_shake: EventWriter<CameraShakeEvent>,
                        mut param_set: ParamSet<(
-                           Query<(&mut Minecart, &mut TransformComponent)>,
+                           Query<(&mut Minecart, &mut TransformComponent, Option<&mut AudioSource>)>,
                            Query<(&PlayerCamera, &mut TransformComponent, &mut PlayerMovement)>)>)
 {
     const MAX_SPEED: f32 = 5.0;
     const SPEED_LOSS_PER_SECOND: f32 = 2.5;
     const SPEED_LOSS_PER_SECOND_RIDING: f32 = 0.1;
     const STOP_SPEED: f32 = 1.0;
-    
+    const BUMP_TRAUMA: f32 = 0.3;
+
+    let controls = Controls::new(&input, &gamepad, &bindings);
+
     let (player_in_minecart, player_pos) = {
         let query = param_set.p1();
         let (_, transform, movement) = query.single();
@@ -87,8 +97,9 @@ pub fn update_minecart(sim_time: Res<SimTime>,
     };
 
     let mut player_in_minecart_pos = None;
+    let mut exit_velocity = None;
 
-    for (mut minecart, mut transform) in param_set.p0().iter_mut() {
+    for (mut minecart, mut transform, audio_source) in param_set.p0().iter_mut() {
         let forward_dir = transform.rot * vec3(0.0, 0.0, -1.0);
         let to_player = player_pos - transform.pos;
         let dist_to_player = f32::max(0.1, to_player.magnitude());
@@ -111,10 +122,22 @@ pub fn update_minecart(sim_time: Res<SimTime>,
         }
         minecart.velocity = minecart.velocity.signum() * new_speed;
 
+        // Rumble louder the faster we go
+        if let Some(mut audio_source) = audio_source {
+            audio_source.volume = new_speed / MAX_SPEED;
+        }
+
         let new_pos_track = minecart.pos + minecart.velocity * sim_time.sim_time_delta as f32;
+        let old_segment_start = minecart.get_segment(minecart.pos).map(|segment| segment.segment_start);
 
         if let Some(new_segment) = minecart.get_segment(new_pos_track) {
+            // Bump the camera going over the joins between track segments
+            if player_in_minecart && old_segment_start != Some(new_segment.segment_start) {
+                camera_shake.send(CameraShakeEvent { trauma: BUMP_TRAUMA * new_speed / MAX_SPEED });
+            }
+
             let new_pos = minecart.get_pos(new_pos_track).unwrap();
+            let cart_velocity = (new_pos - transform.pos) / f32::max(sim_time.sim_time_delta as f32, f32::EPSILON);
             let movement_dir = (new_segment.b - new_segment.a).normalize();
 
             let up = vec3(0.0, 1.0, 0.0);
@@ -127,13 +150,17 @@ pub fn update_minecart(sim_time: Res<SimTime>,
             transform.rot = look_at.invert().unwrap();
             transform.pos = new_pos;
             minecart.pos = new_pos_track;
+
+            if player_in_minecart {
+                exit_velocity = Some(cart_velocity);
+            }
         }
 
         if player_in_minecart {
             player_in_minecart_pos = Some(transform.pos);
         }
 
-        if input.is_just_pressed(InputName::Use) && dist_to_player < 3.0 {
+        if controls.is_just_pressed(Action::Interact) && dist_to_player < 3.0 {
             if player_in_minecart {
                 player_in_minecart_pos = None;
             }
@@ -152,6 +179,11 @@ pub fn update_minecart(sim_time: Res<SimTime>,
     else {
         let mut query = param_set.p1();
         let (_, _, mut movement) = query.single_mut();
+
+        // Keep the cart's momentum when jumping out of it
+        if let (false, Some(exit_velocity)) = (movement.enabled, exit_velocity) {
+            movement.velocity = exit_velocity;
+        }
         movement.enabled = true;
     }
 }
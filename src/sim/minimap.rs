@@ -0,0 +1,180 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{With, Without};
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Vector3, vec2};
+use dreamfield_renderer::components::TextBox;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::InputState;
+
+use super::compass::ObjectiveMarker;
+use super::gamepad::GamepadState;
+use super::input_bindings::{Action, Controls, InputBindings};
+use super::player_movement::PlayerMovement;
+
+/// The number of map cells shown across the minimap
+pub const MINIMAP_COLS: usize = 13;
+
+/// The number of map cells shown down the minimap
+pub const MINIMAP_ROWS: usize = 9;
+
+/// The character drawn for objective markers on the minimap
+const MARKER_CHAR: char = '*';
+
+/// A top-down map of one world model, baked by build.rs. Each cell has a character for what's
+/// there (floor, wall or nothing) and the height of the top surface in it.
+pub struct MinimapData {
+    min: (f32, f32),
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    cells: Vec<char>,
+    heights: Vec<f32>,
+}
+
+impl MinimapData {
+    /// Parse a map baked by build.rs, in the format written by bake_minimap
+    pub fn parse(source: &str) -> Option<Self> {
+        let mut lines = source.lines();
+
+        let header: Vec<f32> = lines.next()?.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+        let [min_x, min_z, cell_size, width, height] = <[f32; 5]>::try_from(header).ok()?;
+        let (width, height) = (width as usize, height as usize);
+
+        let mut cells = Vec::with_capacity(width * height);
+        let mut heights = Vec::with_capacity(width * height);
+        for _ in 0..height {
+            cells.extend(lines.next()?.chars().chain(std::iter::repeat(' ')).take(width));
+            heights.extend(lines.next()?.split_whitespace().map(|value| value.parse().unwrap_or(f32::NAN)));
+        }
+
+        match heights.len() == width * height {
+            true => Some(MinimapData { min: (min_x, min_z), cell_size, width, height, cells, heights }),
+            false => None,
+        }
+    }
+
+    /// Get the cell a world position is in, if it's on the map
+    fn cell_at(&self, x: f32, z: f32) -> Option<usize> {
+        let col = ((x - self.min.0) / self.cell_size).floor();
+        let row = ((z - self.min.1) / self.cell_size).floor();
+        match col >= 0.0 && row >= 0.0 && (col as usize) < self.width && (row as usize) < self.height {
+            true => Some(row as usize * self.width + col as usize),
+            false => None,
+        }
+    }
+
+    /// Get how far a position is above or below the surface of the map under it
+    fn height_difference(&self, pos: Vector3<f32>) -> Option<f32> {
+        let height = self.heights[self.cell_at(pos.x, pos.z)?];
+        match height.is_nan() {
+            true => None,
+            false => Some((pos.y - height).abs()),
+        }
+    }
+}
+
+/// The Minimap resource, with the baked maps of each world model. The maps overlap, since the
+/// dungeon is underneath the village, so the one that's shown is the one with a surface closest to
+/// the player's height.
+pub struct Minimap {
+    maps: Vec<(String, MinimapData)>,
+    pub visible: bool,
+}
+
+impl Minimap {
+    /// Create the minimap from a list of (name, source) pairs of baked maps
+    pub fn new(maps: Vec<(&str, &str)>) -> Self {
+        let maps = maps.into_iter()
+            .filter_map(|(name, source)| match MinimapData::parse(source) {
+                Some(map) => Some((name.to_string(), map)),
+                None => {
+                    log::error!("Failed to parse minimap {}", name);
+                    None
+                }
+            })
+            .collect();
+
+        Minimap { maps, visible: true }
+    }
+
+    /// Get the map the player is in
+    fn current_map(&self, pos: Vector3<f32>) -> Option<&MinimapData> {
+        self.maps.iter()
+            .filter_map(|(_, map)| map.height_difference(pos).map(|diff| (diff, map)))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, map)| map)
+    }
+}
+
+/// The MinimapTextBox component, for the TextBox the minimap is drawn to. Like the compass it
+/// should use a monospace font variant.
+#[derive(Component)]
+pub struct MinimapTextBox;
+
+/// Get the arrow for the player's heading, in degrees clockwise from north
+fn player_arrow(heading: f32) -> char {
+    match ((heading / 90.0).round() as i32).rem_euclid(4) {
+        0 => '^',
+        1 => '>',
+        2 => 'v',
+        _ => '<',
+    }
+}
+
+/// The minimap system, which draws the map around the player with north at the top
+pub fn update_minimap(input: Res<InputState>, gamepad: Res<GamepadState>, bindings: Res<InputBindings>,
+    mut minimap: ResMut<Minimap>, player_query: Query<(&Transform, &PlayerMovement)>,
+    marker_query: Query<(&Transform, &ObjectiveMarker), Without<PlayerMovement>>,
+    mut query: Query<&mut TextBox, With<MinimapTextBox>>)
+{
+    if Controls::new(&input, &gamepad, &bindings).is_just_pressed(Action::ToggleMinimap) {
+        minimap.visible = !minimap.visible;
+    }
+
+    let (player_pos, player_heading) = match player_query.get_single() {
+        Ok((transform, movement)) => (transform.pos, -movement.pitch_yaw.y.to_degrees()),
+        Err(_) => return,
+    };
+
+    let text = match (minimap.visible, minimap.current_map(player_pos)) {
+        (true, Some(map)) => {
+            let half = vec2((MINIMAP_COLS / 2) as i32, (MINIMAP_ROWS / 2) as i32);
+            let view_cell = |pos: Vector3<f32>| {
+                let col = ((pos.x - player_pos.x) / map.cell_size).round() as i32 + half.x;
+                let row = ((pos.z - player_pos.z) / map.cell_size).round() as i32 + half.y;
+                match col >= 0 && row >= 0 && col < MINIMAP_COLS as i32 && row < MINIMAP_ROWS as i32 {
+                    true => Some(row as usize * MINIMAP_COLS + col as usize),
+                    false => None,
+                }
+            };
+
+            let mut view = [' '; MINIMAP_COLS * MINIMAP_ROWS];
+            for (index, c) in view.iter_mut().enumerate() {
+                let x = player_pos.x + ((index % MINIMAP_COLS) as i32 - half.x) as f32 * map.cell_size;
+                let z = player_pos.z + ((index / MINIMAP_COLS) as i32 - half.y) as f32 * map.cell_size;
+                if let Some(cell) = map.cell_at(x, z) {
+                    *c = map.cells[cell];
+                }
+            }
+
+            for (transform, marker) in marker_query.iter() {
+                if let Some(index) = view_cell(transform.pos).filter(|_| marker.active) {
+                    view[index] = MARKER_CHAR;
+                }
+            }
+
+            if let Some(index) = view_cell(player_pos) {
+                view[index] = player_arrow(player_heading);
+            }
+
+            view.chunks(MINIMAP_COLS).map(|row| row.iter().collect::<String>()).collect::<Vec<_>>().join("\n")
+        },
+        _ => String::new(),
+    };
+
+    for mut text_box in query.iter_mut() {
+        if text_box.text != text {
+            text_box.text = text.clone();
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::system::{NonSend, NonSendMut, Res, Query};
+use rodio::{Sink, Decoder, Source};
+use dreamfield_system::resources::SimTime;
+
+use super::audio::AudioManager;
+use super::trigger::TriggerVolume;
+
+/// A music track
+pub struct MusicTrack {
+    data: &'static [u8],
+    pub volume: f32,
+}
+
+/// A track that's currently playing, possibly fading in or out
+struct PlayingTrack {
+    name: String,
+    sink: Sink,
+    /// The fade amount from 0 to 1
+    fade: f32,
+    fading_in: bool,
+}
+
+/// The music manager, which crossfades between named tracks. It owns rodio sinks, so like the
+/// AudioManager it's stored as a non-send resource.
+pub struct MusicManager {
+    tracks: HashMap<String, MusicTrack>,
+    /// The track to play when the player isn't in any music zone
+    pub default_track: Option<String>,
+    /// The crossfade duration in seconds
+    pub fade_duration: f32,
+    current_track: Option<String>,
+    playing: Vec<PlayingTrack>,
+}
+
+impl MusicManager {
+    /// Create a new music manager from a list of (name, wav data, volume)
+    pub fn new_with_tracks(tracks: Vec<(&str, &'static [u8], f32)>, default_track: Option<&str>) -> Self {
+        MusicManager {
+            tracks: tracks.into_iter()
+                .map(|(name, data, volume)| (name.to_string(), MusicTrack { data, volume }))
+                .collect(),
+            default_track: default_track.map(str::to_string),
+            fade_duration: 2.0,
+            current_track: None,
+            playing: Vec::new(),
+        }
+    }
+
+    pub fn track_mut(&mut self, name: &str) -> Option<&mut MusicTrack> {
+        self.tracks.get_mut(name)
+    }
+
+    /// Switch to a new track, crossfading from the current one
+    fn switch_to(&mut self, audio: &AudioManager, name: Option<String>) {
+        self.current_track = name.clone();
+
+        for track in self.playing.iter_mut() {
+            track.fading_in = Some(&track.name) == name.as_ref();
+        }
+
+        // Start the new track if it isn't already playing (or fading out)
+        if let Some(name) = name {
+            if !self.playing.iter().any(|track| track.name == name) {
+                if let Some(sink) = self.start_track(audio, &name) {
+                    self.playing.push(PlayingTrack { name, sink, fade: 0.0, fading_in: true });
+                }
+            }
+        }
+    }
+
+    fn start_track(&self, audio: &AudioManager, name: &str) -> Option<Sink> {
+        let handle = audio.output_handle()?;
+
+        let track = match self.tracks.get(name) {
+            Some(track) => track,
+            None => {
+                log::warn!("Asked to play unknown music track: {}", name);
+                return None;
+            }
+        };
+
+        let decoder = match Decoder::new(Cursor::new(track.data)) {
+            Ok(decoder) => decoder,
+            Err(err) => {
+                log::error!("Failed to decode music track {}: {}", name, err);
+                return None;
+            }
+        };
+
+        let sink = Sink::try_new(handle).ok()?;
+        sink.set_volume(0.0);
+        sink.append(decoder.repeat_infinite());
+        Some(sink)
+    }
+}
+
+/// The MusicZone component, which plays a music track while the player is inside the entity's
+/// TriggerVolume
+#[derive(Component)]
+pub struct MusicZone {
+    pub track: String,
+}
+
+impl MusicZone {
+    pub fn new(track: &str) -> Self {
+        MusicZone { track: track.to_string() }
+    }
+}
+
+/// The music update system
+pub fn update_music(sim_time: Res<SimTime>, audio: NonSend<AudioManager>, mut music: NonSendMut<MusicManager>,
+    zones: Query<(&MusicZone, &TriggerVolume)>)
+{
+    let music = music.as_mut();
+
+    // Find which track should be playing
+    let target_track = zones.iter()
+        .find(|(_, trigger)| trigger.player_inside())
+        .map(|(zone, _)| zone.track.clone())
+        .or_else(|| music.default_track.clone());
+
+    if target_track != music.current_track {
+        music.switch_to(&audio, target_track);
+    }
+
+    // Update fades
+    let fade_delta = match music.fade_duration > 0.0 {
+        true => sim_time.sim_time_delta as f32 / music.fade_duration,
+        false => 1.0,
+    };
+
+    for track in music.playing.iter_mut() {
+        let direction = if track.fading_in { 1.0 } else { -1.0 };
+        track.fade = f32::clamp(track.fade + direction * fade_delta, 0.0, 1.0);
+
+        let track_volume = music.tracks.get(&track.name).map(|track| track.volume).unwrap_or(1.0);
+        track.sink.set_volume(track.fade * track_volume * audio.master_volume);
+    }
+
+    // Stop tracks that have faded out
+    music.playing.retain(|track| track.fading_in || track.fade > 0.0);
+}
@@ -0,0 +1,312 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use bevy_ecs::component::Component;
+use cgmath::{Vector3, InnerSpace, vec3};
+
+/// The furthest above or below the ground a position can be while still being on it
+const MAX_GROUND_DISTANCE: f32 = 1.0;
+
+/// The highest step between neighbouring cells that agents can walk up or down
+const MAX_CLIMB: f32 = 0.45;
+
+/// The maximum number of cells searched for a path, so unreachable goals don't search the whole map
+const MAX_SEARCH_NODES: usize = 8000;
+
+/// The distance the goal has to move before an agent's path is recalculated
+const REPATH_DISTANCE: f32 = 1.0;
+
+/// The number of seconds between path recalculations, so agents notice new routes
+const REPATH_INTERVAL: f32 = 2.0;
+
+/// The distance at which an agent is considered to have reached a point on its path
+const PATH_POINT_RADIUS: f32 = 0.3;
+
+/// A node in a navigation grid, as a cell index and the index of the floor in that cell
+type NavNode = (usize, usize);
+
+/// A walkability grid for one world model, baked by build.rs. Each cell has the heights of the
+/// walkable ground in it, with several when there are floors above each other.
+pub struct NavGrid {
+    min: (f32, f32),
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    floors: Vec<Vec<f32>>,
+}
+
+impl NavGrid {
+    /// Parse a grid baked by build.rs, in the format written by bake_nav_grid
+    pub fn parse(source: &str) -> Option<Self> {
+        let mut lines = source.lines();
+
+        let header: Vec<f32> = lines.next()?.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+        let [min_x, min_z, cell_size, width, height] = <[f32; 5]>::try_from(header).ok()?;
+        let (width, height) = (width as usize, height as usize);
+
+        let mut floors = Vec::with_capacity(width * height);
+        for _ in 0..height {
+            floors.extend(lines.next()?.split_whitespace().map(|cell| match cell {
+                "-" => Vec::new(),
+                cell => cell.split(',').filter_map(|value| value.parse().ok()).collect(),
+            }));
+        }
+
+        match floors.len() == width * height {
+            true => Some(NavGrid { min: (min_x, min_z), cell_size, width, height, floors }),
+            false => None,
+        }
+    }
+
+    /// Get the cell a world position is in, if it's on the grid
+    fn cell_at(&self, x: f32, z: f32) -> Option<(usize, usize)> {
+        let col = ((x - self.min.0) / self.cell_size).floor();
+        let row = ((z - self.min.1) / self.cell_size).floor();
+        match col >= 0.0 && row >= 0.0 && (col as usize) < self.width && (row as usize) < self.height {
+            true => Some((col as usize, row as usize)),
+            false => None,
+        }
+    }
+
+    /// Get the floor in a cell closest to a height, within a distance
+    fn floor_near(&self, col: usize, row: usize, y: f32, max_distance: f32) -> Option<NavNode> {
+        let cell = row * self.width + col;
+        self.floors[cell].iter()
+            .enumerate()
+            .map(|(floor, height)| (floor, (height - y).abs()))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(floor, _)| (cell, floor))
+    }
+
+    /// Get the node a world position is standing on
+    pub fn node_at(&self, pos: Vector3<f32>) -> Option<NavNode> {
+        let (col, row) = self.cell_at(pos.x, pos.z)?;
+        self.floor_near(col, row, pos.y, MAX_GROUND_DISTANCE)
+    }
+
+    /// Get the world position of the centre of a node
+    fn node_pos(&self, (cell, floor): NavNode) -> Vector3<f32> {
+        let (col, row) = (cell % self.width, cell / self.width);
+        vec3(self.min.0 + (col as f32 + 0.5) * self.cell_size, self.floors[cell][floor],
+            self.min.1 + (row as f32 + 0.5) * self.cell_size)
+    }
+
+    /// Get the node next to another in a direction, if it can be walked to
+    fn neighbour(&self, node: NavNode, dx: i32, dz: i32) -> Option<NavNode> {
+        let (col, row) = ((node.0 % self.width) as i32 + dx, (node.0 / self.width) as i32 + dz);
+        if col < 0 || row < 0 || col as usize >= self.width || row as usize >= self.height {
+            return None;
+        }
+        self.floor_near(col as usize, row as usize, self.floors[node.0][node.1], MAX_CLIMB)
+    }
+
+    /// Get the nodes that can be walked to from a node, with the cost of walking to them. Diagonals
+    /// need both of the cells beside them to be walkable so that paths don't cut corners.
+    fn neighbours(&self, node: NavNode) -> impl Iterator<Item = (NavNode, f32)> + '_ {
+        const DIRECTIONS: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+        DIRECTIONS.iter().filter_map(move |(dx, dz)| {
+            let diagonal = *dx != 0 && *dz != 0;
+            if diagonal && (self.neighbour(node, *dx, 0).is_none() || self.neighbour(node, 0, *dz).is_none()) {
+                return None;
+            }
+            let cost = if diagonal { std::f32::consts::SQRT_2 } else { 1.0 };
+            self.neighbour(node, *dx, *dz).map(|neighbour| (neighbour, cost * self.cell_size))
+        })
+    }
+
+    /// Check whether an agent can walk in a straight line between two positions on the grid
+    fn walkable_line(&self, from: Vector3<f32>, to: Vector3<f32>) -> bool {
+        let (mut node, goal) = match (self.node_at(from), self.node_at(to)) {
+            (Some(start), Some(goal)) => (start, goal),
+            _ => return false,
+        };
+
+        let offset = vec3(to.x - from.x, 0.0, to.z - from.z);
+        let steps = (offset.magnitude() / (0.5 * self.cell_size)).ceil() as usize;
+        for step in 1..=steps {
+            let point = from + offset * (step as f32 / steps as f32);
+            let (col, row) = match self.cell_at(point.x, point.z) {
+                Some(cell) => cell,
+                None => return false,
+            };
+            if row * self.width + col == node.0 {
+                continue;
+            }
+
+            // Each cell along the way has to be walkable from the last one, including its neighbours
+            // on either side when the line crosses a corner
+            let (last_col, last_row) = ((node.0 % self.width) as i32, (node.0 / self.width) as i32);
+            let (dx, dz) = (col as i32 - last_col, row as i32 - last_row);
+            if dx.abs() > 1 || dz.abs() > 1 {
+                return false;
+            }
+            node = match self.neighbours(node).find(|(next, _)| next.0 == row * self.width + col) {
+                Some((next, _)) => next,
+                None => return false,
+            };
+        }
+
+        node == goal
+    }
+
+    /// Find a path between two positions with A*, and then pull the path tight so that it only turns
+    /// at corners. The path starts at the start position and ends at the goal position.
+    pub fn find_path(&self, start: Vector3<f32>, goal: Vector3<f32>) -> Option<Vec<Vector3<f32>>> {
+        let (start_node, goal_node) = (self.node_at(start)?, self.node_at(goal)?);
+        let goal_pos = self.node_pos(goal_node);
+        let heuristic = |node: NavNode| (self.node_pos(node) - goal_pos).magnitude();
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<NavNode, NavNode> = HashMap::new();
+        let mut costs: HashMap<NavNode, f32> = HashMap::new();
+        open.push(OpenNode { estimate: heuristic(start_node), node: start_node });
+        costs.insert(start_node, 0.0);
+
+        let mut searched = 0;
+        while let Some(OpenNode { node, .. }) = open.pop() {
+            if node == goal_node {
+                let mut nodes = vec![node];
+                while let Some(prev) = came_from.get(nodes.last().unwrap()) {
+                    nodes.push(*prev);
+                }
+                let points: Vec<Vector3<f32>> = nodes.into_iter().rev().map(|node| self.node_pos(node)).collect();
+                return Some(self.pull_path(points, start, goal));
+            }
+
+            searched += 1;
+            if searched > MAX_SEARCH_NODES {
+                return None;
+            }
+
+            let cost = costs[&node];
+            for (neighbour, step_cost) in self.neighbours(node) {
+                let new_cost = cost + step_cost;
+                if costs.get(&neighbour).map(|old_cost| new_cost < *old_cost).unwrap_or(true) {
+                    costs.insert(neighbour, new_cost);
+                    came_from.insert(neighbour, node);
+                    open.push(OpenNode { estimate: new_cost + heuristic(neighbour), node: neighbour });
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Pull a path of cell centres tight by skipping every point that can be walked past in a
+    /// straight line, which is the grid equivalent of funnelling through a navmesh's portals
+    fn pull_path(&self, points: Vec<Vector3<f32>>, start: Vector3<f32>, goal: Vector3<f32>) -> Vec<Vector3<f32>> {
+        let mut points = points;
+        points[0] = start;
+        if let Some(last) = points.last_mut() {
+            *last = goal;
+        }
+
+        let mut path = vec![points[0]];
+        let mut anchor = 0;
+        while anchor < points.len() - 1 {
+            let furthest = (anchor + 1..points.len())
+                .rev()
+                .find(|i| *i == anchor + 1 || self.walkable_line(points[anchor], points[*i]))
+                .unwrap();
+            path.push(points[furthest]);
+            anchor = furthest;
+        }
+
+        path
+    }
+}
+
+/// An entry in the A* open set, ordered so the heap pops the lowest estimate first
+struct OpenNode {
+    estimate: f32,
+    node: NavNode,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+/// The NavMesh resource, with the navigation grids of each world model
+pub struct NavMesh {
+    grids: Vec<(String, NavGrid)>,
+}
+
+impl NavMesh {
+    /// Create the nav mesh from a list of (name, source) pairs of baked grids
+    pub fn new(grids: Vec<(&str, &str)>) -> Self {
+        let grids = grids.into_iter()
+            .filter_map(|(name, source)| match NavGrid::parse(source) {
+                Some(grid) => Some((name.to_string(), grid)),
+                None => {
+                    log::error!("Failed to parse nav grid {}", name);
+                    None
+                }
+            })
+            .collect();
+
+        NavMesh { grids }
+    }
+
+    /// Find a path between two positions, which need to be on the same grid
+    pub fn find_path(&self, start: Vector3<f32>, goal: Vector3<f32>) -> Option<Vec<Vector3<f32>>> {
+        self.grids.iter()
+            .find(|(_, grid)| grid.node_at(start).is_some() && grid.node_at(goal).is_some())
+            .and_then(|(_, grid)| grid.find_path(start, goal))
+    }
+
+    /// Get the centre of the walkable cell under a position, if it's standing on one
+    pub fn nearest_point(&self, pos: Vector3<f32>) -> Option<Vector3<f32>> {
+        self.grids.iter().find_map(|(_, grid)| grid.node_at(pos).map(|node| grid.node_pos(node)))
+    }
+}
+
+/// The NavAgent component, for npcs that find their way around obstacles with the nav mesh
+#[derive(Component, Default)]
+pub struct NavAgent {
+    path: Vec<Vector3<f32>>,
+    goal: Option<Vector3<f32>>,
+    repath_time: f32,
+}
+
+impl NavAgent {
+    /// Get the point an agent should walk towards to get to a goal, finding a new path when the
+    /// goal moves. If there's no path it just heads straight for the goal.
+    pub fn steer(&mut self, nav_mesh: &NavMesh, pos: Vector3<f32>, goal: Vector3<f32>, time_delta: f32) -> Vector3<f32> {
+        self.repath_time -= time_delta;
+        let goal_moved = self.goal.map(|old_goal| (old_goal - goal).magnitude() > REPATH_DISTANCE).unwrap_or(true);
+        if goal_moved || self.repath_time <= 0.0 {
+            self.path = nav_mesh.find_path(pos, goal).unwrap_or_default();
+            self.goal = Some(goal);
+            self.repath_time = REPATH_INTERVAL;
+        }
+
+        // Move on to the next point once we've reached one
+        while self.path.len() > 1 && vec3(self.path[0].x - pos.x, 0.0, self.path[0].z - pos.z).magnitude() <= PATH_POINT_RADIUS {
+            self.path.remove(0);
+        }
+
+        self.path.first().copied().unwrap_or(goal)
+    }
+
+    /// Get the points left on the agent's path
+    pub fn path(&self) -> &[Vector3<f32>] {
+        &self.path
+    }
+}
@@ -0,0 +1,133 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, InnerSpace, Zero};
+use dreamfield_renderer::components::{Visual, Animation};
+use dreamfield_system::resources::SimTime;
+use dreamfield_system::components::Transform;
+use dreamfield_system::intersection::Collider;
+
+use super::character_controller::CharacterController;
+use super::static_collision::StaticCollision;
+use super::nav::{NavMesh, NavAgent};
+use super::steering::Steering;
+use super::anim_state::AnimStateMachine;
+use super::behavior::{BehaviorTree, BehaviorTrees, BehaviorContext, BehaviorOutput, AgentAnimation};
+
+/// The Npc component, for friendly npcs whose behavior tree walks them between their waypoints
+#[derive(Component)]
+pub struct Npc {
+    pub waypoints: Vec<Vector3<f32>>,
+    /// Walking speed in units per second
+    pub walk_speed: f32,
+    /// Seconds to idle for when reaching a waypoint
+    pub idle_time: f32,
+    pub idle_animation: String,
+    pub walk_animation: String,
+    pub steering: Steering,
+    current_animation: Option<String>,
+}
+
+impl Npc {
+    /// Create an npc that stands still
+    pub fn new_idle(idle_animation: &str) -> Self {
+        Self::new_patrol(Vec::new(), 0.0, 0.0, idle_animation, idle_animation)
+    }
+
+    /// Create an npc that walks between waypoints, idling at each one
+    pub fn new_patrol(waypoints: Vec<Vector3<f32>>, walk_speed: f32, idle_time: f32, idle_animation: &str,
+        walk_animation: &str) -> Self
+    {
+        Npc {
+            waypoints,
+            walk_speed,
+            idle_time,
+            idle_animation: idle_animation.to_string(),
+            walk_animation: walk_animation.to_string(),
+            steering: Steering::default(),
+            current_animation: None,
+        }
+    }
+
+    /// Get the npc's animation for one its behavior tree asks for
+    fn animation(&self, animation: AgentAnimation) -> &str {
+        match animation {
+            AgentAnimation::Walk => &self.walk_animation,
+            AgentAnimation::Idle | AgentAnimation::Attack => &self.idle_animation,
+        }
+    }
+}
+
+/// The npc update system, which runs each npc's behavior tree and steers them smoothly to where it
+/// wants them to go. The npcs with a CharacterController and Collider walk along the ground and
+/// collide with the world, and the rest move freely. Npcs with a NavAgent find their way around
+/// obstacles.
+pub fn update_npcs(sim_time: Res<SimTime>, static_collision: Res<StaticCollision>,
+    nav_mesh: Res<NavMesh>, behavior_trees: Res<BehaviorTrees>,
+    mut query: Query<(Entity, &mut Npc, &mut Transform, &mut Visual, Option<&CharacterController>, Option<&Collider>,
+        Option<&mut NavAgent>, Option<&mut BehaviorTree>, Option<&AnimStateMachine>)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    let positions: Vec<(Entity, Vector3<f32>)> = query.iter().map(|(entity, _, transform, ..)| (entity, transform.pos)).collect();
+
+    for (entity, mut npc, mut transform, mut visual, controller, collider, mut nav_agent, mut behavior, anim_state)
+        in query.iter_mut()
+    {
+        let walker = controller.zip(collider);
+
+        // Npcs without a behavior tree just stand there
+        let context = BehaviorContext::new(transform.pos, &npc.waypoints, npc.walk_speed, npc.idle_time,
+            walker.is_some(), time_delta);
+        let output = match behavior.as_mut() {
+            Some(behavior) => behavior.tick(&behavior_trees, &context),
+            None => BehaviorOutput::default(),
+        };
+        let target = output.move_to.map(|(target, _)| target);
+
+        // Steer towards the next point on the way to the target
+        let desired = match output.move_to {
+            Some((target, speed)) => {
+                let next_point = match nav_agent.as_mut() {
+                    Some(nav_agent) => nav_agent.steer(&nav_mesh, transform.pos, target, time_delta),
+                    None => target,
+                };
+                let neighbours: Vec<Vector3<f32>> = positions.iter()
+                    .filter(|(other, _)| *other != entity)
+                    .map(|(_, pos)| *pos)
+                    .collect();
+                npc.steering.desired_velocity(&static_collision, transform.pos, next_point, target,
+                    speed, &neighbours, entity)
+            },
+            None => Vector3::zero(),
+        };
+        let walk_speed = npc.walk_speed;
+        let velocity = npc.steering.update(desired, walk_speed, time_delta);
+
+        // Move, either along the ground or freely towards the target's height
+        if velocity.magnitude2() > 0.0 {
+            transform.pos = match walker {
+                Some((controller, collider)) => controller.move_and_slide(&static_collision, collider,
+                    transform.pos, velocity, true, entity, time_delta),
+                None => transform.pos + velocity * time_delta,
+            };
+        }
+        if let (None, Some(target)) = (walker, target) {
+            let max_climb = npc.walk_speed * time_delta;
+            transform.pos.y += f32::clamp(target.y - transform.pos.y, -max_climb, max_climb);
+        }
+
+        if let Some(rot) = npc.steering.turn_towards(velocity, time_delta) {
+            transform.rot = rot;
+        }
+
+        // Let the animation state machine pick the animation if there is one, or update it if it changed
+        if anim_state.is_some() {
+            continue;
+        }
+        let animation = npc.animation(output.animation).to_string();
+        if npc.current_animation.as_ref() != Some(&animation) {
+            visual.animation = Some(Animation::Loop(animation.clone()));
+            npc.current_animation = Some(animation);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, Without};
+use bevy_ecs::system::{Res, ResMut, Query, Commands};
+use cgmath::{Vector3, Vector4, Matrix3, SquareMatrix, InnerSpace, vec3, vec4};
+use dreamfield_renderer::components::{PlayerCamera, Visual};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::determinism::SimRng;
+use super::pool::EntityPool;
+use super::prefab::PrefabRegistry;
+use super::tint::Tint;
+
+/// The prefab particles are spawned from
+const PARTICLE_PREFAB: &'static str = "particle";
+
+/// The ParticleEmitter component, which spawns billboarded particles around its entity
+#[derive(Component)]
+pub struct ParticleEmitter {
+    /// The number of particles spawned per second
+    pub spawn_rate: f32,
+    /// The number of seconds each particle lives for
+    pub lifetime: f32,
+    /// The direction particles are emitted in
+    pub direction: Vector3<f32>,
+    /// The half angle of the cone particles are emitted in, in radians
+    pub cone_angle: f32,
+    pub speed: f32,
+    /// The vertical acceleration of particles, positive for rising particles like fire
+    pub gravity: f32,
+    pub start_color: Vector4<f32>,
+    pub end_color: Vector4<f32>,
+    pub start_size: f32,
+    pub end_size: f32,
+    pub max_particles: usize,
+    /// The radius of the horizontal disc around the entity that particles are spawned in
+    pub spawn_radius: f32,
+    pub enabled: bool,
+    spawn_accumulator: f32,
+    live_particles: usize,
+}
+
+impl ParticleEmitter {
+    /// Create a new fire emitter
+    pub fn new_fire() -> Self {
+        ParticleEmitter {
+            spawn_rate: 12.0,
+            lifetime: 0.8,
+            direction: vec3(0.0, 1.0, 0.0),
+            cone_angle: 0.4,
+            speed: 0.8,
+            gravity: 1.0,
+            start_color: vec4(1.0, 0.8, 0.3, 1.0),
+            end_color: vec4(0.8, 0.1, 0.0, 0.0),
+            start_size: 0.2,
+            end_size: 0.05,
+            max_particles: 16,
+            spawn_radius: 0.0,
+            enabled: true,
+            spawn_accumulator: 0.0,
+            live_particles: 0,
+        }
+    }
+
+    /// Create a new dust emitter
+    pub fn new_dust() -> Self {
+        ParticleEmitter {
+            spawn_rate: 4.0,
+            lifetime: 2.0,
+            cone_angle: std::f32::consts::PI,
+            speed: 0.2,
+            gravity: -0.05,
+            start_color: vec4(0.6, 0.55, 0.45, 0.6),
+            end_color: vec4(0.6, 0.55, 0.45, 0.0),
+            start_size: 0.05,
+            end_size: 0.1,
+            ..ParticleEmitter::new_fire()
+        }
+    }
+
+    /// Create a new rain emitter, which is spawned over the player by the weather, and starts off
+    /// disabled
+    pub fn new_rain() -> Self {
+        ParticleEmitter {
+            spawn_rate: 0.0,
+            lifetime: 1.2,
+            direction: vec3(0.0, -1.0, 0.0),
+            cone_angle: 0.05,
+            speed: 8.0,
+            gravity: -6.0,
+            start_color: vec4(0.6, 0.65, 0.75, 0.6),
+            end_color: vec4(0.6, 0.65, 0.75, 0.4),
+            start_size: 0.04,
+            end_size: 0.04,
+            max_particles: 160,
+            spawn_radius: 12.0,
+            enabled: false,
+            ..ParticleEmitter::new_fire()
+        }
+    }
+
+    /// Get a random offset in the emitter's spawn disc
+    fn random_offset(&self, rng: &mut SimRng) -> Vector3<f32> {
+        let distance = rng.next().sqrt() * self.spawn_radius;
+        let angle = rng.next() * 2.0 * std::f32::consts::PI;
+        vec3(angle.cos() * distance, 0.0, angle.sin() * distance)
+    }
+
+    /// Get a random direction in the emitter's cone
+    fn random_direction(&self, rng: &mut SimRng) -> Vector3<f32> {
+        let direction = self.direction.normalize();
+        let up = match direction.y.abs() < 0.99 {
+            true => vec3(0.0, 1.0, 0.0),
+            false => vec3(1.0, 0.0, 0.0),
+        };
+        let tangent = direction.cross(up).normalize();
+        let bitangent = direction.cross(tangent);
+
+        let angle = rng.next() * self.cone_angle;
+        let spin = rng.next() * 2.0 * std::f32::consts::PI;
+
+        direction * angle.cos() + (tangent * spin.cos() + bitangent * spin.sin()) * angle.sin()
+    }
+}
+
+/// A single particle, spawned as its own entity with a billboard Visual
+#[derive(Component)]
+pub struct Particle {
+    pub emitter: Entity,
+    pub velocity: Vector3<f32>,
+    pub gravity: f32,
+    pub lifetime: f32,
+    pub start_color: Vector4<f32>,
+    pub end_color: Vector4<f32>,
+    pub start_size: f32,
+    pub end_size: f32,
+    age: f32,
+}
+
+impl Particle {
+    /// Get the fraction of the particle's life that has passed
+    pub fn life_progress(&self) -> f32 {
+        f32::min(self.age / self.lifetime, 1.0)
+    }
+
+    /// Get the particle's current color
+    pub fn color(&self) -> Vector4<f32> {
+        self.start_color + (self.end_color - self.start_color) * self.life_progress()
+    }
+
+    /// Get the particle's current size
+    pub fn size(&self) -> f32 {
+        self.start_size + (self.end_size - self.start_size) * self.life_progress()
+    }
+}
+
+/// The particle emitter system, which draws where each particle goes from the SimRng so the sim
+/// stays repeatable
+pub fn update_particle_emitters(mut commands: Commands, sim_time: Res<SimTime>, prefabs: Res<PrefabRegistry>,
+    mut pool: ResMut<EntityPool>, mut rng: ResMut<SimRng>, mut emitters: Query<(Entity, &mut ParticleEmitter, &Transform), Without<Particle>>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (entity, mut emitter, transform) in emitters.iter_mut() {
+        if !emitter.enabled {
+            emitter.spawn_accumulator = 0.0;
+            continue;
+        }
+
+        emitter.spawn_accumulator += emitter.spawn_rate * time_delta;
+
+        while emitter.spawn_accumulator >= 1.0 {
+            emitter.spawn_accumulator -= 1.0;
+
+            if emitter.live_particles >= emitter.max_particles {
+                continue;
+            }
+
+            let rot = Matrix3::from_value(emitter.start_size);
+            let pos = transform.pos + emitter.random_offset(&mut rng);
+            let particle = match pool.acquire(&mut commands, &prefabs, PARTICLE_PREFAB, pos, rot) {
+                Some(particle) => particle,
+                None => continue,
+            };
+            emitter.live_particles += 1;
+
+            let velocity = emitter.random_direction(&mut rng) * emitter.speed;
+            commands.entity(particle)
+                .insert(Particle {
+                    emitter: entity,
+                    velocity,
+                    gravity: emitter.gravity,
+                    lifetime: emitter.lifetime,
+                    start_color: emitter.start_color,
+                    end_color: emitter.end_color,
+                    start_size: emitter.start_size,
+                    end_size: emitter.end_size,
+                    age: 0.0,
+                })
+                .insert(Tint::new(emitter.start_color));
+        }
+    }
+}
+
+/// The particle system, which moves particles and turns them to face the camera
+pub fn update_particles(mut commands: Commands, sim_time: Res<SimTime>, mut pool: ResMut<EntityPool>,
+    cameras: Query<&PlayerCamera>,
+    mut emitters: Query<&mut ParticleEmitter>, mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Tint)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    // The billboard rotation is just the camera's rotation
+    let camera_rot = cameras.iter().next()
+        .and_then(|cam| cam.view.invert())
+        .map(|cam_transform| Matrix3::from_cols(
+            cam_transform.x.truncate(),
+            cam_transform.y.truncate(),
+            cam_transform.z.truncate()))
+        .unwrap_or(Matrix3::identity());
+
+    for (entity, mut particle, mut transform, mut tint) in particles.iter_mut() {
+        particle.age += time_delta;
+
+        if particle.age >= particle.lifetime {
+            if let Ok(mut emitter) = emitters.get_mut(particle.emitter) {
+                emitter.live_particles = emitter.live_particles.saturating_sub(1);
+            }
+            pool.release::<(Visual, Particle, Tint)>(&mut commands, PARTICLE_PREFAB, entity);
+            continue;
+        }
+
+        particle.velocity.y += particle.gravity * time_delta;
+        transform.pos += particle.velocity * time_delta;
+        transform.rot = camera_rot * particle.size();
+        tint.color = particle.color();
+    }
+}
@@ -0,0 +1,207 @@
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Vector2, Vector3, Matrix4, Quaternion, Rotation3, Rad, Deg, SquareMatrix, InnerSpace, perspective, vec2, vec3};
+use dreamfield_renderer::components::{PlayerCamera, TextBox};
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::game_state::GameState;
+use super::graphics_settings::GraphicsSettings;
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::console::DebugConsole;
+
+/// The directory screenshots are saved to
+const SCREENSHOT_DIR: &'static str = "screenshots";
+
+/// The free camera's fly speed
+const FLY_SPEED: f32 = 4.0;
+
+/// The free camera's look speed
+const LOOK_SPEED: f32 = 1.0;
+
+/// The roll speed in radians per second
+const ROLL_SPEED: f32 = 1.0;
+
+/// The FOV zoom speed in degrees per second
+const ZOOM_SPEED: f32 = 30.0;
+
+/// The FOV limits in degrees
+const FOV_MIN: f32 = 10.0;
+const FOV_MAX: f32 = 100.0;
+
+/// The camera state from before photo mode was entered, so it can be restored afterwards
+struct SavedCamera {
+    view: Matrix4<f32>,
+    proj: Matrix4<f32>,
+    render_fov_rad: f32,
+}
+
+/// The PhotoMode resource. While photo mode is active the sim is frozen, the UI is hidden, and the
+/// camera flies freely.
+#[derive(Default)]
+pub struct PhotoMode {
+    pub active: bool,
+    pos: Vector3<f32>,
+    pitch_yaw: Vector2<f32>,
+    roll: f32,
+    fov_deg: f32,
+    saved_camera: Option<SavedCamera>,
+    hidden_text: Vec<(Entity, String)>,
+    screenshot_requested: bool,
+}
+
+impl PhotoMode {
+    fn orientation(&self) -> Quaternion<f32> {
+        Quaternion::from_axis_angle(vec3(0.0, 1.0, 0.0), Rad(self.pitch_yaw.y))
+            * Quaternion::from_axis_angle(vec3(1.0, 0.0, 0.0), Rad(self.pitch_yaw.x))
+            * Quaternion::from_axis_angle(vec3(0.0, 0.0, -1.0), Rad(self.roll))
+    }
+
+    /// Start photo mode from the current camera
+    fn enter(&mut self, cam: &PlayerCamera) {
+        let cam_transform = cam.view.invert().unwrap_or(Matrix4::identity());
+        let forward = (cam_transform * vec3(0.0, 0.0, -1.0).extend(0.0)).truncate();
+
+        self.active = true;
+        self.pos = cam_transform.w.truncate();
+        self.pitch_yaw = vec2(forward.y.asin(), f32::atan2(-forward.x, -forward.z));
+        self.roll = 0.0;
+        self.fov_deg = cam.render_fov_rad.to_degrees();
+        self.saved_camera = Some(SavedCamera { view: cam.view, proj: cam.proj, render_fov_rad: cam.render_fov_rad });
+    }
+
+    /// Leave photo mode and put the camera back
+    fn exit(&mut self, cam: &mut PlayerCamera) {
+        self.active = false;
+        if let Some(saved) = self.saved_camera.take() {
+            cam.view = saved.view;
+            cam.proj = saved.proj;
+            cam.render_fov_rad = saved.render_fov_rad;
+        }
+    }
+}
+
+/// The photo mode system
+pub fn update_photo_mode(sim_time: Res<SimTime>, input: Res<InputState>, gamepad: Res<GamepadState>,
+    bindings: Res<InputBindings>, state: Res<GameState>, graphics: Res<GraphicsSettings>, console: Res<DebugConsole>,
+    mut photo_mode: ResMut<PhotoMode>, mut cam_query: Query<&mut PlayerCamera>, mut text_query: Query<(Entity, &mut TextBox)>)
+{
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    let mut cam = match cam_query.get_single_mut() {
+        Ok(cam) => cam,
+        Err(_) => return,
+    };
+
+    // Toggle photo mode, but only in game and not from the menus or the console
+    if controls.is_just_pressed(Action::PhotoMode) && *state == GameState::InGame && !console.open {
+        match photo_mode.active {
+            false => photo_mode.enter(&cam),
+            true => {
+                photo_mode.exit(&mut cam);
+
+                // Show the UI again
+                for (entity, text) in photo_mode.hidden_text.drain(..) {
+                    if let Ok((_, mut text_box)) = text_query.get_mut(entity) {
+                        text_box.text = text;
+                    }
+                }
+            }
+        }
+    }
+
+    if !photo_mode.active {
+        return;
+    }
+
+    // Hide the UI, remembering what it said
+    for (entity, mut text_box) in text_query.iter_mut() {
+        if !text_box.text.is_empty() {
+            let text = std::mem::take(&mut text_box.text);
+            if !photo_mode.hidden_text.iter().any(|(hidden, _)| *hidden == entity) {
+                photo_mode.hidden_text.push((entity, text));
+            }
+        }
+    }
+
+    // Look around
+    let look = controls.look();
+    photo_mode.pitch_yaw.x = f32::clamp(photo_mode.pitch_yaw.x + look.y * LOOK_SPEED * time_delta,
+        -std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+    photo_mode.pitch_yaw.y += look.x * LOOK_SPEED * time_delta;
+
+    // Roll and zoom
+    let axis = |positive: Action, negative: Action| {
+        (controls.is_held(positive) as i32 - controls.is_held(negative) as i32) as f32
+    };
+    photo_mode.roll += axis(Action::PhotoRollRight, Action::PhotoRollLeft) * ROLL_SPEED * time_delta;
+    photo_mode.fov_deg = f32::clamp(
+        photo_mode.fov_deg + axis(Action::PhotoZoomOut, Action::PhotoZoomIn) * ZOOM_SPEED * time_delta,
+        FOV_MIN, FOV_MAX);
+
+    // Fly in the direction we're looking, and up and down with jump and crouch
+    let orientation = photo_mode.orientation();
+    let movement = controls.movement();
+    let mut velocity = orientation * vec3(movement.y, 0.0, -movement.x);
+    velocity.y += axis(Action::Jump, Action::Crouch);
+    if velocity.magnitude2() > 1.0 {
+        velocity = velocity.normalize();
+    }
+    photo_mode.pos += velocity * FLY_SPEED * time_delta;
+
+    // Update the camera
+    let cam_transform = Matrix4::from_translation(photo_mode.pos) * Matrix4::from(orientation);
+    cam.view = cam_transform.invert().unwrap_or(Matrix4::identity());
+    cam.proj = perspective(Deg(photo_mode.fov_deg), cam.render_aspect, graphics.near_clip, graphics.far_clip);
+    cam.render_fov_rad = photo_mode.fov_deg.to_radians();
+
+    if controls.is_just_pressed(Action::Screenshot) {
+        photo_mode.screenshot_requested = true;
+    }
+}
+
+/// The screenshot system, which saves the framebuffer to a PNG when one's been requested. It runs
+/// in the render schedule after the scene is drawn, since it needs the GL context.
+pub fn capture_screenshot(mut photo_mode: ResMut<PhotoMode>) {
+    if !photo_mode.screenshot_requested {
+        return;
+    }
+    photo_mode.screenshot_requested = false;
+
+    // Read back the framebuffer
+    let mut viewport = [0; 4];
+    unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+    let (width, height) = (viewport[2].max(0) as u32, viewport[3].max(0) as u32);
+
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(viewport[0], viewport[1], width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut _);
+    }
+
+    let image = match image::RgbaImage::from_raw(width, height, pixels) {
+        Some(image) => image::imageops::flip_vertical(&image),
+        None => {
+            log::error!("Failed to capture screenshot");
+            return;
+        }
+    };
+
+    // Save it with a timestamp
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+    let path = format!("{}/screenshot_{}.png", SCREENSHOT_DIR, timestamp);
+
+    let result = std::fs::create_dir_all(SCREENSHOT_DIR)
+        .map_err(|err| err.to_string())
+        .and_then(|_| image.save(&path).map_err(|err| err.to_string()));
+
+    match result {
+        Ok(_) => log::info!("Saved screenshot to {}", path),
+        Err(err) => log::error!("Failed to save screenshot to {}: {}", path, err),
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventReader};
+use bevy_ecs::schedule::{SystemSet, ParallelSystemDescriptorCoercion};
+use bevy_ecs::system::{Res, ResMut, Query, RemovedComponents};
+use cgmath::{Vector3, Matrix3, Quaternion, InnerSpace, vec3};
+use rapier3d::prelude::*;
+use rapier3d::na::{UnitQuaternion, Quaternion as NaQuaternion, Translation3};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::menu;
+use super::projectile::ProjectileImpactEvent;
+
+/// The number of physics steps per sim tick, since the sim tick is too long for stable stacking
+const PHYSICS_SUBSTEPS: usize = 4;
+
+/// The impulse applied to bodies right next to a projectile impact
+const IMPACT_IMPULSE: f32 = 4.0;
+
+/// The distance from a projectile impact at which bodies stop being pushed
+const IMPACT_RADIUS: f32 = 3.0;
+
+/// How a rigid body moves
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RigidBodyKind {
+    /// Moved by the physics simulation, with its Transform following it
+    Dynamic,
+    /// Never moves
+    Fixed,
+    /// Follows its Transform, pushing dynamic bodies out of the way
+    Kinematic,
+}
+
+/// The RigidBody component, which adds an entity to the physics world. The entity's Transform
+/// rotation needs to be a pure rotation, since dynamic bodies overwrite it with their own.
+#[derive(Component)]
+pub struct RigidBody {
+    pub kind: RigidBodyKind,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    /// Whether to use continuous collision detection, for small or fast bodies
+    pub ccd: bool,
+    handle: Option<RigidBodyHandle>,
+}
+
+impl RigidBody {
+    pub fn new(kind: RigidBodyKind) -> Self {
+        RigidBody {
+            kind,
+            linear_damping: 0.1,
+            angular_damping: 0.1,
+            ccd: false,
+            handle: None,
+        }
+    }
+
+    pub fn dynamic() -> Self {
+        RigidBody::new(RigidBodyKind::Dynamic)
+    }
+
+    pub fn fixed() -> Self {
+        RigidBody::new(RigidBodyKind::Fixed)
+    }
+
+    pub fn kinematic() -> Self {
+        RigidBody::new(RigidBodyKind::Kinematic)
+    }
+
+    /// Get the body's handle in the physics world, once it's been added to it
+    pub fn handle(&self) -> Option<RigidBodyHandle> {
+        self.handle
+    }
+}
+
+/// The shape of a physics collider
+#[derive(Clone, Copy, Debug)]
+pub enum ColliderShape {
+    /// A box, with its half extents
+    Cuboid(Vector3<f32>),
+    Ball(f32),
+    /// A capsule along the y axis
+    Capsule { half_height: f32, radius: f32 },
+}
+
+/// The PhysicsCollider component, the shape of an entity's RigidBody. It's only read when the body
+/// is created, so changing it afterwards does nothing.
+#[derive(Component)]
+pub struct PhysicsCollider {
+    pub shape: ColliderShape,
+    /// The collider's offset from the entity's position
+    pub offset: Vector3<f32>,
+    pub density: f32,
+    pub friction: f32,
+    pub restitution: f32,
+}
+
+impl PhysicsCollider {
+    pub fn new(shape: ColliderShape) -> Self {
+        PhysicsCollider {
+            shape,
+            offset: vec3(0.0, 0.0, 0.0),
+            density: 1.0,
+            friction: 0.7,
+            restitution: 0.1,
+        }
+    }
+
+    pub fn cuboid(half_extents: Vector3<f32>) -> Self {
+        PhysicsCollider::new(ColliderShape::Cuboid(half_extents))
+    }
+
+    pub fn ball(radius: f32) -> Self {
+        PhysicsCollider::new(ColliderShape::Ball(radius))
+    }
+
+    pub fn capsule(half_height: f32, radius: f32) -> Self {
+        PhysicsCollider::new(ColliderShape::Capsule { half_height, radius })
+    }
+
+    fn build(&self) -> Collider {
+        let builder = match self.shape {
+            ColliderShape::Cuboid(half) => ColliderBuilder::cuboid(half.x, half.y, half.z),
+            ColliderShape::Ball(radius) => ColliderBuilder::ball(radius),
+            ColliderShape::Capsule { half_height, radius } => ColliderBuilder::capsule_y(half_height, radius),
+        };
+
+        builder.translation(vector![self.offset.x, self.offset.y, self.offset.z])
+            .density(self.density)
+            .friction(self.friction)
+            .restitution(self.restitution)
+            .build()
+    }
+}
+
+/// Convert a Transform to a rapier isometry
+fn to_isometry(transform: &Transform) -> Isometry<Real> {
+    let q = Quaternion::from(transform.rot);
+    let rotation = UnitQuaternion::from_quaternion(NaQuaternion::new(q.s, q.v.x, q.v.y, q.v.z));
+    Isometry::from_parts(Translation3::new(transform.pos.x, transform.pos.y, transform.pos.z), rotation)
+}
+
+/// Convert a rapier isometry to a position and rotation
+fn from_isometry(isometry: &Isometry<Real>) -> (Vector3<f32>, Matrix3<f32>) {
+    let (t, q) = (isometry.translation.vector, isometry.rotation);
+    (vec3(t.x, t.y, t.z), Matrix3::from(Quaternion::new(q.w, q.i, q.j, q.k)))
+}
+
+/// The PhysicsWorld resource, which wraps the rapier simulation. It steps at the sim's fixed
+/// update rate, split into substeps.
+pub struct PhysicsWorld {
+    pub gravity: Vector3<f32>,
+    /// The length of a sim tick, which one full physics step takes
+    tick: f32,
+    accumulator: f32,
+    pub bodies: RigidBodySet,
+    pub colliders: ColliderSet,
+    integration_parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    entities: HashMap<Entity, RigidBodyHandle>,
+}
+
+impl PhysicsWorld {
+    /// Create the physics world, with static colliders from a list of (name, mesh) pairs of the
+    /// collision meshes baked by build.rs
+    pub fn new(tick: f32, static_meshes: Vec<(&str, &[u8])>) -> Self {
+        let mut colliders = ColliderSet::new();
+
+        for (name, mesh) in static_meshes {
+            let vertices: Vec<Point<Real>> = mesh.chunks_exact(12)
+                .map(|vertex| {
+                    let value = |i: usize| f32::from_le_bytes([vertex[i], vertex[i + 1], vertex[i + 2], vertex[i + 3]]);
+                    point![value(0), value(4), value(8)]
+                })
+                .collect();
+
+            if vertices.len() < 3 {
+                log::warn!("Collision mesh {} has no triangles", name);
+                continue;
+            }
+
+            let indices = (0..vertices.len() as u32 / 3).map(|i| [i * 3, i * 3 + 1, i * 3 + 2]).collect();
+            colliders.insert(ColliderBuilder::trimesh(vertices, indices).build());
+        }
+
+        PhysicsWorld {
+            gravity: vec3(0.0, -9.81, 0.0),
+            tick,
+            accumulator: 0.0,
+            bodies: RigidBodySet::new(),
+            colliders,
+            integration_parameters: IntegrationParameters {
+                dt: tick / PHYSICS_SUBSTEPS as f32,
+                ..IntegrationParameters::default()
+            },
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            entities: HashMap::new(),
+        }
+    }
+
+    /// Get the rigid body of an entity
+    pub fn body_mut(&mut self, entity: Entity) -> Option<&mut rapier3d::dynamics::RigidBody> {
+        let handle = *self.entities.get(&entity)?;
+        self.bodies.get_mut(handle)
+    }
+
+    fn step(&mut self) {
+        let gravity = vector![self.gravity.x, self.gravity.y, self.gravity.z];
+        self.pipeline.step(&gravity, &self.integration_parameters, &mut self.islands, &mut self.broad_phase,
+            &mut self.narrow_phase, &mut self.bodies, &mut self.colliders, &mut self.impulse_joints,
+            &mut self.multibody_joints, &mut self.ccd_solver, &(), &());
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(handle) = self.entities.remove(&entity) {
+            self.bodies.remove(handle, &mut self.islands, &mut self.colliders, &mut self.impulse_joints,
+                &mut self.multibody_joints, true);
+        }
+    }
+}
+
+/// The rigid body creation system, which adds new bodies to the physics world
+pub fn create_rigid_bodies(mut physics: ResMut<PhysicsWorld>,
+    mut query: Query<(Entity, &mut RigidBody, &Transform, Option<&PhysicsCollider>)>)
+{
+    for (entity, mut body, transform, collider) in query.iter_mut() {
+        if body.handle.is_some() {
+            continue;
+        }
+
+        let builder = match body.kind {
+            RigidBodyKind::Dynamic => RigidBodyBuilder::dynamic(),
+            RigidBodyKind::Fixed => RigidBodyBuilder::fixed(),
+            RigidBodyKind::Kinematic => RigidBodyBuilder::kinematic_position_based(),
+        };
+
+        let rigid_body = builder.position(to_isometry(transform))
+            .linear_damping(body.linear_damping)
+            .angular_damping(body.angular_damping)
+            .ccd_enabled(body.ccd)
+            .build();
+
+        let physics = physics.as_mut();
+        let handle = physics.bodies.insert(rigid_body);
+        if let Some(collider) = collider {
+            physics.colliders.insert_with_parent(collider.build(), handle, &mut physics.bodies);
+        }
+
+        physics.entities.insert(entity, handle);
+        body.handle = Some(handle);
+    }
+}
+
+/// The rigid body removal system, which removes the bodies of despawned entities
+pub fn remove_rigid_bodies(mut physics: ResMut<PhysicsWorld>, removed: RemovedComponents<RigidBody>) {
+    for entity in removed.iter() {
+        physics.remove(entity);
+    }
+}
+
+/// The physics step system, which moves kinematic bodies to their Transforms, steps the simulation,
+/// and then moves dynamic bodies' Transforms to match
+pub fn step_physics(sim_time: Res<SimTime>, mut physics: ResMut<PhysicsWorld>,
+    mut query: Query<(&RigidBody, &mut Transform)>)
+{
+    let physics = physics.as_mut();
+
+    for (body, transform) in query.iter() {
+        if let (RigidBodyKind::Kinematic, Some(handle)) = (body.kind, body.handle) {
+            if let Some(rigid_body) = physics.bodies.get_mut(handle) {
+                rigid_body.set_next_kinematic_position(to_isometry(transform));
+            }
+        }
+    }
+
+    physics.accumulator += sim_time.sim_time_delta as f32;
+    while physics.accumulator >= physics.tick {
+        physics.accumulator -= physics.tick;
+        for _ in 0..PHYSICS_SUBSTEPS {
+            physics.step();
+        }
+    }
+
+    for (body, mut transform) in query.iter_mut() {
+        if let (RigidBodyKind::Dynamic, Some(handle)) = (body.kind, body.handle) {
+            if let Some(rigid_body) = physics.bodies.get(handle) {
+                (transform.pos, transform.rot) = from_isometry(rigid_body.position());
+            }
+        }
+    }
+}
+
+/// The impact impulse system, which knocks dynamic bodies away from projectile impacts
+pub fn apply_impact_impulses(mut physics: ResMut<PhysicsWorld>, mut events: EventReader<ProjectileImpactEvent>) {
+    for event in events.iter() {
+        for (_, rigid_body) in physics.bodies.iter_mut() {
+            if !rigid_body.is_dynamic() {
+                continue;
+            }
+
+            let t = rigid_body.translation();
+            let offset = vec3(t.x, t.y, t.z) - event.position;
+            let falloff = 1.0 - offset.magnitude() / IMPACT_RADIUS;
+            if falloff > 0.0 && offset.magnitude2() > 0.0 {
+                let impulse = offset.normalize() * IMPACT_IMPULSE * falloff;
+                rigid_body.apply_impulse(vector![impulse.x, impulse.y, impulse.z], true);
+            }
+        }
+    }
+}
+
+/// Physics systems, which run after the player has moved so that it can push bodies around
+pub fn physics_systems() -> SystemSet {
+    SystemSet::new()
+        .label("physics")
+        .after("sim_input")
+        .with_run_criteria(menu::sim_running)
+        .with_system(create_rigid_bodies.before("physics_step"))
+        .with_system(remove_rigid_bodies.before("physics_step"))
+        .with_system(apply_impact_impulses.before("physics_step"))
+        .with_system(step_physics.label("physics_step").after("player_update"))
+}
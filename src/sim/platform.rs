@@ -0,0 +1,176 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, InnerSpace, Zero};
+use serde::Deserialize;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+/// The distance below a platform's top that something standing on it can sink to, before it's
+/// considered to have fallen off the side
+const PLATFORM_SINK_TOLERANCE: f32 = 0.25;
+
+/// The distance above a platform's top that something is still considered to be standing on it
+const PLATFORM_STAND_TOLERANCE: f32 = 0.05;
+
+/// The Platform component, for something that can be stood on and carries whatever is standing on
+/// it. The world collision doesn't know about entities' meshes, so the walkable deck is an axis
+/// aligned box relative to the entity's position, and only its top is stood on.
+#[derive(Component)]
+pub struct Platform {
+    pub deck_min: Vector3<f32>,
+    pub deck_max: Vector3<f32>,
+    /// How far the platform moved this frame, which is also how far its riders are moved
+    pub delta: Vector3<f32>,
+    /// The platform's velocity this frame, which riders inherit when they step or jump off
+    pub velocity: Vector3<f32>,
+    prev_pos: Option<Vector3<f32>>,
+}
+
+/// How a moving platform continues when it reaches the end of its waypoints
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PlatformMode {
+    /// Go back through the waypoints in reverse
+    PingPong,
+    /// Go back to the first waypoint
+    Loop,
+}
+
+/// The MovingPlatform component, which moves an entity between waypoints at a constant speed.
+/// It also needs a Platform to carry things.
+#[derive(Component)]
+pub struct MovingPlatform {
+    pub waypoints: Vec<Vector3<f32>>,
+    /// Speed in units per second
+    pub speed: f32,
+    pub mode: PlatformMode,
+    /// Seconds to wait at each waypoint
+    pub wait_time: f32,
+    next_waypoint: usize,
+    reversing: bool,
+    wait_remaining: f32,
+}
+
+/// Something standing on a platform
+#[derive(Clone, Copy, Debug)]
+pub struct PlatformContact {
+    pub entity: Entity,
+    /// The height of the platform's top
+    pub top: f32,
+    pub velocity: Vector3<f32>,
+}
+
+impl Platform {
+    pub fn new(deck_min: Vector3<f32>, deck_max: Vector3<f32>) -> Self {
+        Platform {
+            deck_min,
+            deck_max,
+            delta: Vector3::zero(),
+            velocity: Vector3::zero(),
+            prev_pos: None,
+        }
+    }
+
+    /// Get whether a position is standing on the platform at a given position
+    pub fn supports(&self, platform_pos: Vector3<f32>, pos: Vector3<f32>) -> bool {
+        let (min, max) = (platform_pos + self.deck_min, platform_pos + self.deck_max);
+        pos.x >= min.x && pos.x <= max.x && pos.z >= min.z && pos.z <= max.z
+            && pos.y >= max.y - PLATFORM_SINK_TOLERANCE && pos.y <= max.y + PLATFORM_STAND_TOLERANCE
+    }
+}
+
+impl MovingPlatform {
+    pub fn new(waypoints: Vec<Vector3<f32>>, speed: f32, mode: PlatformMode, wait_time: f32) -> Self {
+        MovingPlatform {
+            waypoints,
+            speed,
+            mode,
+            wait_time,
+            next_waypoint: 0,
+            reversing: false,
+            wait_remaining: 0.0,
+        }
+    }
+
+    /// Advance to the next waypoint after reaching one
+    fn advance(&mut self) {
+        let count = self.waypoints.len();
+        if count < 2 {
+            return;
+        }
+
+        self.next_waypoint = match (self.mode, self.reversing) {
+            (PlatformMode::Loop, _) => (self.next_waypoint + 1) % count,
+            (PlatformMode::PingPong, false) if self.next_waypoint + 1 < count => self.next_waypoint + 1,
+            (PlatformMode::PingPong, false) => {
+                self.reversing = true;
+                self.next_waypoint - 1
+            },
+            (PlatformMode::PingPong, true) if self.next_waypoint > 0 => self.next_waypoint - 1,
+            (PlatformMode::PingPong, true) => {
+                self.reversing = false;
+                1
+            },
+        };
+    }
+}
+
+/// Find the platform something at a position is standing on
+pub fn find_platform<'a>(platforms: impl Iterator<Item = (Entity, &'a Transform, &'a Platform)>,
+    pos: Vector3<f32>) -> Option<PlatformContact>
+{
+    platforms
+        .filter(|(_, transform, platform)| platform.supports(transform.pos, pos))
+        .map(|(entity, transform, platform)| PlatformContact {
+            entity,
+            top: transform.pos.y + platform.deck_max.y,
+            velocity: platform.velocity,
+        })
+        .max_by(|a, b| a.top.total_cmp(&b.top))
+}
+
+/// The moving platform system, which moves platforms along their waypoints
+pub fn update_moving_platforms(sim_time: Res<SimTime>, mut query: Query<(&mut MovingPlatform, &mut Transform)>) {
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (mut platform, mut transform) in query.iter_mut() {
+        if platform.waypoints.is_empty() {
+            continue;
+        }
+
+        if platform.wait_remaining > 0.0 {
+            platform.wait_remaining -= time_delta;
+            continue;
+        }
+
+        let target = platform.waypoints[platform.next_waypoint];
+        let to_target = target - transform.pos;
+        let dist = to_target.magnitude();
+        let step = platform.speed * time_delta;
+
+        if dist <= step {
+            transform.pos = target;
+            platform.wait_remaining = platform.wait_time;
+            platform.advance();
+        }
+        else {
+            transform.pos += to_target * (step / dist);
+        }
+    }
+}
+
+/// The platform velocity system, which tracks how far each platform moved this frame, however it
+/// was moved. It runs after everything that moves platforms and before anything that rides them.
+pub fn update_platform_velocities(sim_time: Res<SimTime>, mut query: Query<(&mut Platform, &Transform)>) {
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (mut platform, transform) in query.iter_mut() {
+        let delta = platform.prev_pos.map(|prev_pos| transform.pos - prev_pos).unwrap_or(Vector3::zero());
+        platform.delta = delta;
+        platform.velocity = match time_delta > 0.0 {
+            true => delta / time_delta,
+            false => Vector3::zero(),
+        };
+        platform.prev_pos = Some(transform.pos);
+    }
+}
@@ -1,29 +1,38 @@
 use std::f32::consts::PI;
 
 use bevy_ecs::component::Component;
-use bevy_ecs::prelude::Entity;
+use bevy_ecs::prelude::{Entity, EventWriter, Without};
 use bevy_ecs::system::{Res, ResMut, Query};
 use cgmath::{Vector3, vec3, Vector2, Zero, Quaternion, Rad, Rotation3, Matrix4, SquareMatrix, InnerSpace, vec2, ElementWise, Matrix3};
 
 use dreamfield_renderer::components::PlayerCamera;
 use dreamfield_system::components::Transform;
 use dreamfield_system::intersection::{Plane, Collider, Shape};
-use dreamfield_system::resources::{SimTime, InputName, InputState, Diagnostics};
-use dreamfield_system::world::WorldChunkManager;
-use dreamfield_system::world::world_collision::{WorldCollision, SpherecastResult};
+use dreamfield_system::resources::{SimTime, InputState, Diagnostics};
+
+use super::DialogueState;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::gamepad::GamepadState;
+use super::head_bob::{HeadBob, FootstepEvent};
+use super::stamina::Stamina;
+use super::third_person::ThirdPersonCamera;
+use super::static_collision::StaticCollision;
+use super::cutscene::CutsceneState;
+use super::free_camera::FreeCamera;
+use super::camera_shake::CameraShakeEvent;
+use super::character_controller::{CharacterController, GROUND_CHECK_DISTANCE, sweep_unit};
+use super::platform::{Platform, PlatformContact, find_platform};
+use super::console::ConsoleCommands;
 
 /// The character's height
 const CHAR_HEIGHT: f32 = 1.8;
 
+/// The character's height while crouching
+const CROUCH_HEIGHT: f32 = 1.1;
+
 /// The character's collider radius
 const CHAR_RADIUS: f32 = 0.5;
 
-/// A tiny value which stops us from coming too close to walls
-const MIN_DISTANCE_FROM_WALLS: f32 = 0.01;
-
-/// The minimum ground_normal y value to stop you from walking on steep slopes
-const MIN_WALK_NORMAL: f32 = 0.9;
-
 /// The camera look speed
 const CAM_LOOK_SPEED: f32 = 1.0;
 
@@ -37,6 +46,15 @@ const GRAVITY_ACCELERATION: f32 = 9.8;
 /// of your head, which is just over 10cm
 const CHAR_EYE_LEVEL: f32 = CHAR_HEIGHT - 0.10;
 
+/// The character eye level while crouching
+const CROUCH_EYE_LEVEL: f32 = CROUCH_HEIGHT - 0.10;
+
+/// The rate the camera moves between standing and crouching, in stances per second
+const CROUCH_TRANSITION_SPEED: f32 = 6.0;
+
+/// The amount crouching multiplies the max speed by
+const CROUCH_SPEED_MULTIPLIER: f32 = 0.4;
+
 /// The world forward direction
 const WORLD_FORWARD: Vector3<f32> = vec3(0.0, 0.0, -1.0);
 
@@ -70,6 +88,28 @@ const CONTINUED_JUMP_ACCELERATION: f32 = 3.0;
 /// Number of seconds jump can be held for
 const JUMP_TIME_LIMIT: f32 = 0.0;
 
+/// The acceleration when swimming
+const SWIM_ACCELERATE: f32 = 8.0;
+
+/// Maximum swimming speed
+const SWIM_MAX_SPEED: f32 = 2.5;
+
+/// The water drag as percentage of speed to lose per second
+const WATER_DRAG: f32 = 3.0;
+
+/// The upwards acceleration from buoyancy while the player's head is underwater. It's just
+/// stronger than gravity so that the player slowly floats to the surface.
+const BUOYANCY_ACCELERATION: f32 = GRAVITY_ACCELERATION + 1.0;
+
+/// The speed the player pushes off ladders at when jumping off them
+const LADDER_JUMP_SPEED: f32 = 3.0;
+
+/// The landing speed above which landing shakes the camera
+const LANDING_SHAKE_SPEED: f32 = 6.0;
+
+/// The camera trauma per unit of landing speed above LANDING_SHAKE_SPEED
+const LANDING_SHAKE_TRAUMA: f32 = 0.08;
+
 /// The min limit for pitch
 const PITCH_MIN: f32 = -PI * 0.4;
 
@@ -87,12 +127,32 @@ pub struct PlayerMovement {
     pub walking: bool,
     /// Seconds since player started holding the jump button
     pub jump_timer: f32,
+    pub head_bob: HeadBob,
+    /// Whether the player's head is underwater
+    pub head_underwater: bool,
+    pub crouching: bool,
+    pub sprinting: bool,
+    /// Whether the player is sneaking, i.e. crouching on the ground, which makes them harder to notice
+    pub sneaking: bool,
+    /// The downwards speed the player hit the ground at, if they landed this frame
+    pub landing_speed: Option<f32>,
+    /// The slope, step and ground snapping settings the player moves with
+    pub controller: CharacterController,
+    /// The platform the player is standing on, which carries them along with it
+    pub platform: Option<PlatformContact>,
+    /// How far the camera is between standing (0) and crouching (1)
+    crouch_amount: f32,
 }
 
 #[derive(PartialEq)]
 pub enum PlayerMovementMode {
     Noclip,
-    Normal
+    Normal,
+    Swimming,
+    /// Climbing a ladder along its axis at a given speed
+    Climbing { axis: Vector3<f32>, speed: f32 },
+    /// Hanging from a zip line, a distance along it and moving along it at a speed
+    Riding { zip_line: Entity, distance: f32, speed: f32 },
 }
 
 impl PlayerMovement {
@@ -105,6 +165,15 @@ impl PlayerMovement {
             ground_plane: None,
             walking: false,
             jump_timer: 0.0,
+            head_bob: HeadBob::default(),
+            head_underwater: false,
+            crouching: false,
+            sprinting: false,
+            sneaking: false,
+            landing_speed: None,
+            controller: CharacterController::default(),
+            platform: None,
+            crouch_amount: 0.0,
         }
     }
 
@@ -123,33 +192,127 @@ impl PlayerMovement {
         self.orientation() * WORLD_RIGHT
     }
 
+    /// Switch between noclip and walking
+    pub fn toggle_noclip(&mut self) {
+        self.movement_mode = match self.movement_mode {
+            PlayerMovementMode::Normal | PlayerMovementMode::Swimming | PlayerMovementMode::Climbing { .. }
+                | PlayerMovementMode::Riding { .. } => PlayerMovementMode::Noclip,
+            PlayerMovementMode::Noclip => PlayerMovementMode::Normal,
+        };
+    }
+
+    /// Get the camera height above the player's position, including head bob and crouching
+    pub fn eye_height(&self) -> f32 {
+        let t = self.crouch_amount * self.crouch_amount * (3.0 - 2.0 * self.crouch_amount);
+        CHAR_EYE_LEVEL + (CROUCH_EYE_LEVEL - CHAR_EYE_LEVEL) * t + self.head_bob.offset
+    }
+
     pub fn collider() -> Collider {
+        Self::collider_with_height(CHAR_HEIGHT)
+    }
+
+    fn collider_with_height(height: f32) -> Collider {
         Collider::new(Shape::BoundingSpheroid(
-            vec3(0.0, 0.5 * CHAR_HEIGHT, 0.0),
-            vec3(CHAR_RADIUS, 0.5 * CHAR_HEIGHT, CHAR_RADIUS)
+            vec3(0.0, 0.5 * height, 0.0),
+            vec3(CHAR_RADIUS, 0.5 * height, CHAR_RADIUS)
         ))
     }
 }
 
 /// The player update system
-pub fn player_update(mut collision: ResMut<WorldCollision>,
-                     mut world: ResMut<WorldChunkManager>,
+pub fn player_update(static_collision: Res<StaticCollision>,
                      mut diagnostics: ResMut<Diagnostics>,
-                     input_state: Res<InputState>, sim_time: Res<SimTime>,
-                     mut query: Query<(Entity, &mut Transform, &mut PlayerCamera, &mut PlayerMovement, &Collider)>)
+                     input_state: Res<InputState>, gamepad: Res<GamepadState>, bindings: Res<InputBindings>,
+                     sim_time: Res<SimTime>, dialogue: Res<DialogueState>, cutscene: Res<CutsceneState>,
+                     mut third_person: ResMut<ThirdPersonCamera>, free_camera: Res<FreeCamera>,
+                     mut footsteps: EventWriter<FootstepEvent>, mut camera_shake: EventWriter<CameraShakeEvent>,
+                     mut query: Query<(Entity, &mut Transform, &mut PlayerCamera, &mut PlayerMovement, &mut Collider,
+                         Option<&mut Stamina>)>,
+                     platforms: Query<(Entity, &Transform, &Platform), Without<PlayerMovement>>)
 {
     let time_delta = sim_time.sim_time_delta as f32;
+    let controls = Controls::new(&input_state, &gamepad, &bindings);
+
+    for (entity_id, mut player_transform, mut cam, mut player_movement, mut collider, mut stamina) in query.iter_mut() {
+        // Toggle noclip
+        if controls.is_just_pressed(Action::ToggleNoclip) && !free_camera.active {
+            player_movement.toggle_noclip();
+        }
 
-    for (entity_id, mut player_transform, mut cam, mut player_movement, collider) in query.iter_mut() {
-        // Now move the player
-        player_move(collision.as_mut(), world.as_mut(), &mut player_transform, &mut player_movement, collider,
-            &input_state, entity_id, time_delta);
+        // Crouch and stand up, unless the controls are flying the free camera
+        if !free_camera.active {
+            update_crouch(&static_collision, &player_transform, &mut player_movement, &mut collider,
+                &controls, entity_id, time_delta);
+        }
 
-        // Update camera
-        let cam_pos = player_transform.pos + vec3(0.0, CHAR_EYE_LEVEL, 0.0);
+        // Ride along with the platform we're standing on
+        if let Some(contact) = player_movement.platform {
+            if let Ok((_, _, platform)) = platforms.get(contact.entity) {
+                player_transform.pos = player_movement.controller.move_and_slide(&static_collision, &collider,
+                    player_transform.pos, platform.velocity, false, entity_id, time_delta);
+            }
+        }
 
-        let cam_transform = Matrix4::from_translation(cam_pos) * Matrix4::from(player_movement.orientation());
-        cam.view = cam_transform.invert().unwrap();
+        // Check if we're standing on a platform, unless we're jumping up past it
+        let standing_on_platform = player_movement.movement_mode == PlayerMovementMode::Normal
+            && player_movement.enabled && player_movement.velocity.y <= 0.0;
+        let platform = match standing_on_platform {
+            true => find_platform(platforms.iter(), player_transform.pos),
+            false => None,
+        };
+
+        // Now move the player, unless they're busy talking to someone, watching a cutscene or the
+        // free camera's being flown around
+        let input_enabled = !dialogue.in_dialogue() && !cutscene.in_cutscene() && !free_camera.active;
+        if input_enabled {
+            player_move(&static_collision, &mut player_transform, &mut player_movement, &collider,
+                stamina.as_deref(), platform.map(|contact| contact.top), &controls, entity_id, time_delta);
+        }
+
+        // Keep track of the platform we ended up on, and keep its momentum when stepping or jumping off it
+        let prev_platform = player_movement.platform.take();
+        player_movement.platform = match standing_on_platform && player_movement.velocity.y <= 0.0 {
+            true => find_platform(platforms.iter(), player_transform.pos),
+            false => None,
+        };
+        if let (Some(prev_platform), None) = (prev_platform, player_movement.platform) {
+            player_movement.velocity += prev_platform.velocity;
+        }
+
+        // Hard landings shake the camera
+        if let Some(landing_speed) = player_movement.landing_speed {
+            if landing_speed > LANDING_SHAKE_SPEED {
+                camera_shake.send(CameraShakeEvent { trauma: (landing_speed - LANDING_SHAKE_SPEED) * LANDING_SHAKE_TRAUMA });
+            }
+        }
+
+        // Use up stamina while sprinting
+        if let Some(stamina) = stamina.as_mut() {
+            stamina.update(player_movement.sprinting && player_movement.walking, time_delta);
+        }
+
+        // Update head bob and emit footsteps
+        let horizontal_speed = vec2(player_movement.velocity.x, player_movement.velocity.z).magnitude();
+        let walking = player_movement.walking && input_enabled;
+        if let Some(left_foot) = player_movement.head_bob.update(walking, horizontal_speed, time_delta) {
+            footsteps.send(FootstepEvent { entity: entity_id, position: player_transform.pos, left_foot });
+        }
+
+        // Switch between first and third person
+        if controls.is_just_pressed(Action::ToggleCamera) && !free_camera.active {
+            third_person.enabled = !third_person.enabled;
+        }
+
+        // Update camera, which the free camera does itself while it's active
+        let cam_pos = player_transform.pos + vec3(0.0, player_movement.eye_height(), 0.0);
+
+        let cam_transform = match third_person.enabled {
+            false => Matrix4::from_translation(cam_pos) * Matrix4::from(player_movement.orientation()),
+            true => third_person.camera_transform(&static_collision, cam_pos, player_movement.pitch_yaw),
+        };
+        if !free_camera.active {
+            cam.view = cam_transform.invert().unwrap();
+        }
 
         // Update diagnostics
         diagnostics.player_pos = player_transform.pos;
@@ -157,45 +320,102 @@ pub fn player_update(mut collision: ResMut<WorldCollision>,
     }
 }
 
-/// The player movement
-fn player_move(collision: &mut WorldCollision, world: &mut WorldChunkManager, player_transform: &mut Transform,
-    player_movement: &mut PlayerMovement, collider: &Collider, input_state: &InputState, ignore_entity: Entity,
+/// Crouch while the crouch action is held, standing back up when there's room to
+fn update_crouch(collision: &StaticCollision, player_transform: &Transform,
+    player_movement: &mut PlayerMovement, collider: &mut Collider, controls: &Controls, ignore_entity: Entity,
     time_delta: f32)
+{
+    let wants_crouch = controls.is_held(Action::Crouch)
+        && player_movement.movement_mode == PlayerMovementMode::Normal;
+
+    if wants_crouch && !player_movement.crouching {
+        player_movement.crouching = true;
+        *collider = PlayerMovement::collider_with_height(CROUCH_HEIGHT);
+    }
+    else if !wants_crouch && player_movement.crouching {
+        // Check there's room above us to stand up, by sweeping the crouched collider upwards
+        let crouch_radius = vec3(CHAR_RADIUS, 0.5 * CROUCH_HEIGHT, CHAR_RADIUS);
+        let cbm = vec3(1.0 / crouch_radius.x, 1.0 / crouch_radius.y, 1.0 / crouch_radius.z);
+        let position_es = (player_transform.pos + vec3(0.0, 0.5 * CROUCH_HEIGHT, 0.0)).mul_element_wise(cbm);
+        let velocity_es = vec3(0.0, CHAR_HEIGHT - CROUCH_HEIGHT, 0.0).mul_element_wise(cbm);
+
+        if sweep_unit(collision, &cbm, position_es, velocity_es, ignore_entity).is_none() {
+            player_movement.crouching = false;
+            player_movement.sneaking = false;
+            *collider = PlayerMovement::collider();
+        }
+    }
+
+    // Move the camera between stances
+    let target = if player_movement.crouching { 1.0 } else { 0.0 };
+    let step = CROUCH_TRANSITION_SPEED * time_delta;
+    player_movement.crouch_amount += f32::clamp(target - player_movement.crouch_amount, -step, step);
+}
+
+/// The player movement
+fn player_move(collision: &StaticCollision, player_transform: &mut Transform,
+    player_movement: &mut PlayerMovement, collider: &Collider, stamina: Option<&Stamina>, platform_top: Option<f32>,
+    controls: &Controls, ignore_entity: Entity, time_delta: f32)
 {
     // Update view direction
-    update_view_angles(player_movement, input_state, time_delta);
+    update_view_angles(player_movement, controls, time_delta);
     player_transform.rot = Matrix3::from(player_movement.orientation());
 
+    player_movement.walking = false;
+    player_movement.sprinting = false;
+    player_movement.landing_speed = None;
+
     if !player_movement.enabled {
         return;
     }
 
-    // Get bounding spheroid
-    let (collider_offset, collider_radius) = match collider.shape {
-        Shape::BoundingSpheroid(offset, radius) => (offset, radius),
-        _ => panic!("Player collider must be a BoundingSpheroid")
-    };
-    let collider_cbm = vec3(1.0 / collider_radius.x, 1.0 / collider_radius.y, 1.0 / collider_radius.z);
+    let controller = player_movement.controller;
 
     // Noclip movement
     if player_movement.movement_mode == PlayerMovementMode::Noclip {
-        player_move_noclip(player_transform, player_movement, input_state, time_delta);
+        player_move_noclip(player_transform, player_movement, controls, time_delta);
+        return;
+    }
+
+    // Zip lines move the player along them themselves
+    if let PlayerMovementMode::Riding { .. } = player_movement.movement_mode {
+        player_movement.ground_plane = None;
+        return;
+    }
+
+    // Ladder movement
+    if let PlayerMovementMode::Climbing { axis, speed } = player_movement.movement_mode {
+        player_move_climbing(player_movement, controls, axis, speed);
+        player_transform.pos = controller.move_and_slide(collision, collider, player_transform.pos,
+            player_movement.velocity, false, ignore_entity, time_delta);
         return;
     }
 
-    // Find ground plane, converting to ellipsoid space first
-    {
-        let position_es = (player_transform.pos + collider_offset).mul_element_wise(collider_cbm);
-        let velocity_es = vec3(0.0, -0.05, 0.0).mul_element_wise(collider_cbm);
-        player_movement.ground_plane = sweep_unit(collision, world, &collider_cbm, position_es, velocity_es, ignore_entity).map(|hit| {
-                let hit_point_world = hit.point().div_element_wise(collider_cbm);
-                let hit_normal_world = hit.normal().div_element_wise(collider_cbm).normalize();
-                if hit.toi() == 0.0 {
-                    println!("Found intersection at 0.0 when checking for ground plane - stuck?");
-                    player_transform.pos += hit_normal_world * 0.1;
-                }
-                Plane::new_from_point_and_normal(hit_point_world, hit_normal_world)
-            });
+    // Swimming movement
+    if player_movement.movement_mode == PlayerMovementMode::Swimming {
+        player_move_swimming(player_movement, controls, time_delta);
+        player_transform.pos = controller.move_and_slide(collision, collider, player_transform.pos,
+            player_movement.velocity, false, ignore_entity, time_delta);
+        return;
+    }
+
+    // Find ground plane
+    let was_airborne = player_movement.ground_plane.is_none();
+    let ground_check = vec3(0.0, -GROUND_CHECK_DISTANCE, 0.0);
+    player_movement.ground_plane = controller.sweep(collision, collider, player_transform.pos, ground_check,
+        ignore_entity).map(|hit| {
+            if hit.distance == 0.0 {
+                println!("Found intersection at 0.0 when checking for ground plane - stuck?");
+                player_transform.pos += hit.normal * 0.1;
+            }
+            Plane::new_from_point_and_normal(hit.point, hit.normal)
+        });
+
+    // Platforms aren't part of the world collision, so stand on top of them directly
+    if let Some(platform_top) = platform_top {
+        player_transform.pos.y = f32::max(player_transform.pos.y, platform_top);
+        let platform_point = vec3(player_transform.pos.x, platform_top, player_transform.pos.z);
+        player_movement.ground_plane = Some(Plane::new_from_point_and_normal(platform_point, vec3(0.0, 1.0, 0.0)));
     }
 
     // Apply gravity acceleration
@@ -206,7 +426,10 @@ fn player_move(collision: &mut WorldCollision, world: &mut WorldChunkManager, pl
     let mut acceleration = AIR_ACCELERATE;
     let mut max_speed = GROUND_MAX_SPEED;
     if let Some(ground_plane) = player_movement.ground_plane {
-        if ground_plane.normal().y >= MIN_WALK_NORMAL {
+        if ground_plane.normal().y >= controller.min_walk_normal() {
+            if was_airborne {
+                player_movement.landing_speed = Some(-player_movement.velocity.y);
+            }
             if player_movement.velocity.y < 0.0 {
                 player_movement.velocity.y = 0.0;
             }
@@ -217,25 +440,32 @@ fn player_move(collision: &mut WorldCollision, world: &mut WorldChunkManager, pl
     }
 
     // Apply jump acceleration
-    if input_state.is_just_pressed(InputName::Jump) && !steep_slope {
+    if controls.is_just_pressed(Action::Jump) && !steep_slope {
         // Start jump
         player_movement.velocity.y += INSTANT_JUMP_ACCELERATION;
         player_movement.jump_timer += time_delta;
     }
-    else if input_state.is_held(InputName::Jump) && player_movement.jump_timer > 0.0 {
+    else if controls.is_held(Action::Jump) && player_movement.jump_timer > 0.0 {
         let jump_time_remaining = f32::max(0.0, JUMP_TIME_LIMIT - player_movement.jump_timer);
         let jump_acceleration_frame = f32::min(jump_time_remaining, time_delta) * CONTINUED_JUMP_ACCELERATION;
         player_movement.velocity.y += jump_acceleration_frame;
     }
 
-    // Increase max speed and acceleration if the hax button is pressed
-    if input_state.is_held(InputName::Run) {
-        acceleration *= RUNNING_MULTIPLIER;
-        max_speed *= RUNNING_MULTIPLIER;
+    // Increase max speed and acceleration when sprinting, if there's stamina for it, or slow down
+    // when crouching
+    let can_sprint = stamina.map(Stamina::can_sprint).unwrap_or(true);
+    if player_movement.crouching {
+        max_speed *= CROUCH_SPEED_MULTIPLIER;
+    }
+    else if controls.is_held(Action::Sprint) && can_sprint {
+        let multiplier = stamina.map(|stamina| stamina.sprint_multiplier).unwrap_or(RUNNING_MULTIPLIER);
+        acceleration *= multiplier;
+        max_speed *= multiplier;
+        player_movement.sprinting = true;
     }
 
     // Update velocity with movement acceleration
-    let input_vector = get_movement_vector(player_movement, input_state);
+    let input_vector = get_movement_vector(player_movement, controls);
     player_movement.velocity += vec3(input_vector.x, 0.0, input_vector.z) * acceleration * time_delta;
 
     // Friction (only apply it if there's no directional input). It's modelled as a constant
@@ -256,106 +486,25 @@ fn player_move(collision: &mut WorldCollision, world: &mut WorldChunkManager, pl
         player_movement.velocity.z *= speed_ratio;
     }
 
-    // Convert position and velocity to e-space for unit sphere sweep
-    let mut position_es = (player_transform.pos + collider_offset)
-        .mul_element_wise(collider_cbm);
-    let velocity_es = player_movement.velocity
-        .mul_element_wise(collider_cbm);
-
-    // Update lateral movement
-    let movement_xz_es = time_delta * vec3(velocity_es.x, 0.0, velocity_es.z);
-    position_es = recursive_slide(collision, world, &collider_cbm, position_es, movement_xz_es, ignore_entity, 0);
-
-    // Add gravity
-    if player_movement.velocity.y != 0.0 {
-        let movement_y_es = time_delta * vec3(0.0, velocity_es.y, 0.0);
-        position_es = recursive_slide(collision, world, &collider_cbm, position_es, movement_y_es, ignore_entity, 0);
-    }
-
-    // TODO: might want to reimplement the 'bump' behavior for if we get stuck, now that we've
-    // removed ncollide we don't have it anymore
-    //if let Some(result) = sweep_unit(level, world, position_es, vec3(0.0, 0.0, 0.0)) {
-    //    println!("stuck in level: {}", result.toi());
-    //}
-
-    // Convert player position back to R3 (world space)
-    player_transform.pos = position_es.div_element_wise(collider_cbm) - collider_offset;
-}
-
-/// Sweep a unit sphere through the world from the start with a given velocity. Start and velocity
-/// must be converted to e-space first by multiplying by COLLIDER_CBM, and then the results must
-/// eventually be converted back to world space by doing the opposite.
-fn sweep_unit(collision: &mut WorldCollision, world: &mut WorldChunkManager, cbm: &Vector3<f32>,
-    position: Vector3<f32>, velocity: Vector3<f32>, ignore_entity: Entity) -> Option<SpherecastResult>
-{
-    collision.sweep_unit_sphere(world, position, velocity, *cbm, Some(ignore_entity))
-}
-
-/// Move through the world sliding on surfaces we collide with
-fn recursive_slide(collision: &mut WorldCollision, world: &mut WorldChunkManager, cbm: &Vector3<f32>,
-    position: Vector3<f32>, velocity: Vector3<f32>, ignore_entity: Entity, depth: i32) -> Vector3<f32>
-{
-    const MAX_RECURSION_DEPTH: i32 = 5;
-
-    // If we hit the maximum recursion, just return the current position and don't advance anymore
-    if depth >= MAX_RECURSION_DEPTH {
-        return position;
-    }
-
-    // If the velocity is 0, stop advancing too
-    let velocity_length = velocity.magnitude();
-    if velocity_length == 0.0 {
-        return position;
-    }
-
-    // Sphere sweep and find next intersection point
-    let hit = sweep_unit(collision, world, cbm, position, velocity, ignore_entity);
-    if hit.is_none() {
-        return position + velocity;
-    }
-    let hit = hit.unwrap();
-
-    // Only update position if we aren't already very close
-    let hit_distance = hit.toi() * velocity_length;
-    let (new_position, hit_point) = match hit_distance > MIN_DISTANCE_FROM_WALLS {
-        true => {
-            let velocity_dir = velocity / velocity_length;
-
-            // Update position to just before the hit point so we don't move into it
-            let new_position = position + velocity_dir * (hit_distance - MIN_DISTANCE_FROM_WALLS);
-
-            // Update the hit point too so that it doesn't throw off the plane calculation
-            let hit_point = hit.point() - MIN_DISTANCE_FROM_WALLS * velocity_dir;
-
-            (new_position, hit_point)
-        },
-        false => (position, *hit.point())
-    };
-
-    // Calculate sliding normal using clever math from triangle soup paper
-    let slide_plane_origin = hit_point;
-    let slide_plane_normal = (new_position - hit_point).normalize();
-    let slide_plane = Plane::new_from_point_and_normal(slide_plane_origin, slide_plane_normal);
-
-    // Project original destination onto plane, and subtract it the intersection point from it to
-    // get a new velocity
-    let original_destination = position + velocity;
-    let new_destination_point = slide_plane.project(original_destination);
-    let new_velocity_vector = new_destination_point - hit_point;
-
-    // If the new velocity is too low, just return the new position and stop moving
-    if new_velocity_vector.magnitude2() < (MIN_DISTANCE_FROM_WALLS * MIN_DISTANCE_FROM_WALLS) {
-        return new_position;
-    }
-
-    recursive_slide(collision, world, cbm, new_position, new_velocity_vector, ignore_entity, depth + 1)
+    // We're walking if we're on walkable ground and moving
+    const MIN_WALKING_SPEED: f32 = 0.1;
+    let speed = vec2(player_movement.velocity.x, player_movement.velocity.z).magnitude();
+    player_movement.walking = !steep_slope && speed > MIN_WALKING_SPEED;
+    player_movement.sneaking = player_movement.crouching && !steep_slope;
+
+    // Move the player, sliding along anything we hit and stepping up stairs. Snapping to the world's
+    // ground would pull us down through platforms, so platforms handle that themselves.
+    let grounded = !steep_slope && platform_top.is_none();
+    player_transform.pos = controller.move_and_slide(collision, collider, player_transform.pos,
+        player_movement.velocity, grounded, ignore_entity, time_delta);
 }
 
 /// Update the view direction
-fn update_view_angles(player_movement: &mut PlayerMovement, input_state: &InputState, time_delta: f32) {
-    let (horz_input, vert_input) = input_state.get_look_input();
+fn update_view_angles(player_movement: &mut PlayerMovement, controls: &Controls, time_delta: f32) {
+    let look_input = controls.look();
+    let (horz_input, vert_input) = (look_input.x, look_input.y);
 
-    let look_speed = match input_state.is_held(InputName::Run) {
+    let look_speed = match controls.is_held(Action::Run) {
         false => CAM_LOOK_SPEED,
         true => CAM_LOOK_SPEED_FAST,
     };
@@ -367,18 +516,20 @@ fn update_view_angles(player_movement: &mut PlayerMovement, input_state: &InputS
 }
 
 /// Get the movement vector based on the player's input
-fn get_movement_vector(player_movement: &PlayerMovement, input_state: &InputState) -> Vector3<f32> {
-    let (forward_input, right_input) = input_state.get_movement_input();
+fn get_movement_vector(player_movement: &PlayerMovement, controls: &Controls) -> Vector3<f32> {
+    let movement_input = controls.movement();
+    let (forward_input, right_input) = (movement_input.x, movement_input.y);
     forward_input * player_movement.forward() + right_input * player_movement.right()
 }
 
 /// The simplest movement mode: noclip
-fn player_move_noclip(player_transform: &mut Transform, player_movement: &mut PlayerMovement, input_state: &InputState,
+fn player_move_noclip(player_transform: &mut Transform, player_movement: &mut PlayerMovement, controls: &Controls,
     time_delta: f32)
 {
-    let (forward_input, right_input) = input_state.get_movement_input();
+    let movement_input = controls.movement();
+    let (forward_input, right_input) = (movement_input.x, movement_input.y);
 
-    let speed = match input_state.is_held(InputName::Run) {
+    let speed = match controls.is_held(Action::Run) {
         false => GROUND_MAX_SPEED,
         true => GROUND_MAX_SPEED * 2.0,
     };
@@ -390,3 +541,64 @@ fn player_move_noclip(player_transform: &mut Transform, player_movement: &mut Pl
     // Update position
     player_transform.pos += player_movement.velocity * time_delta;
 }
+
+/// Swimming movement: the player swims in the direction they're looking, and can swim up with jump
+fn player_move_swimming(player_movement: &mut PlayerMovement, controls: &Controls, time_delta: f32) {
+    player_movement.ground_plane = None;
+    player_movement.jump_timer = 0.0;
+
+    // Gravity and buoyancy, which only pushes the player up while their head is underwater so that
+    // they float at the surface
+    player_movement.velocity.y -= GRAVITY_ACCELERATION * time_delta;
+    if player_movement.head_underwater {
+        player_movement.velocity.y += BUOYANCY_ACCELERATION * time_delta;
+    }
+
+    // Swim towards the movement input, and upwards when jump is held
+    let mut input_vector = get_movement_vector(player_movement, controls);
+    if controls.is_held(Action::Jump) {
+        input_vector += WORLD_UP;
+    }
+    if input_vector.magnitude2() > 1.0 {
+        input_vector = input_vector.normalize();
+    }
+    player_movement.velocity += input_vector * SWIM_ACCELERATE * time_delta;
+
+    // Apply water drag and clamp speed
+    let speed = player_movement.velocity.magnitude();
+    if speed > 0.0 {
+        let new_speed = f32::min(speed * f32::max(1.0 - WATER_DRAG * time_delta, 0.0), SWIM_MAX_SPEED);
+        player_movement.velocity *= new_speed / speed;
+    }
+}
+
+/// Ladder movement: forward and back climb up and down the ladder, and jump lets go of it
+fn player_move_climbing(player_movement: &mut PlayerMovement, controls: &Controls, axis: Vector3<f32>, speed: f32) {
+    player_movement.ground_plane = None;
+
+    // Jump off the ladder backwards
+    if controls.is_just_pressed(Action::Jump) {
+        let forward = player_movement.forward();
+        let backward = -vec3(forward.x, 0.0, forward.z).normalize();
+        player_movement.velocity = backward * LADDER_JUMP_SPEED;
+        player_movement.movement_mode = PlayerMovementMode::Normal;
+        return;
+    }
+
+    let movement_input = controls.movement();
+    let (forward_input, right_input) = (movement_input.x, movement_input.y);
+    player_movement.velocity = axis * forward_input * speed + player_movement.right() * right_input * speed * 0.5;
+}
+
+/// Register the noclip console command
+pub fn register_noclip_command(commands: &mut ConsoleCommands) {
+    commands.register("noclip", "noclip", "Toggle flying through walls", |world, _| {
+        let mut query = world.query::<&mut PlayerMovement>();
+        let mut player_movement = query.get_single_mut(world).map_err(|_| "There's no player".to_string())?;
+        player_movement.toggle_noclip();
+        Ok(match player_movement.movement_mode {
+            PlayerMovementMode::Noclip => "Noclip on",
+            _ => "Noclip off",
+        }.to_string())
+    });
+}
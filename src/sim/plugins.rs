@@ -0,0 +1,277 @@
+use bevy_ecs::prelude::{Entity, EventReader};
+use bevy_ecs::system::{Res, Query, NonSendMut};
+use cgmath::{Vector3, vec3};
+use wasmtime::{Engine, Module, Store, Linker, Instance, Caller, TypedFunc, Memory};
+use dreamfield_renderer::components::Visual;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::dialogue::DialogueBranchCompleteEvent;
+use super::trigger::{TriggerEnterEvent, TriggerExitEvent};
+
+/// The directory plugins are loaded from at startup
+pub const PLUGINS_PATH: &'static str = "plugins";
+
+/// The version of the host API. Plugins export `dreamfield_api_version` returning the version
+/// they were built against, and aren't loaded if it doesn't match.
+pub const PLUGIN_API_VERSION: i32 = 1;
+
+/// The module the host API is imported from
+const HOST_MODULE: &'static str = "dreamfield";
+
+/// The events plugins can subscribe to, by their id in the host API
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PluginEvent {
+    TriggerEnter = 0,
+    TriggerExit = 1,
+    DialogueComplete = 2,
+}
+
+impl PluginEvent {
+    fn from_id(id: i32) -> Option<Self> {
+        match id {
+            0 => Some(PluginEvent::TriggerEnter),
+            1 => Some(PluginEvent::TriggerExit),
+            2 => Some(PluginEvent::DialogueComplete),
+            _ => None,
+        }
+    }
+}
+
+/// An entity as seen by plugins, snapshotted each frame before they run
+struct PluginEntity {
+    entity: Entity,
+    pos: Vector3<f32>,
+    has_visual: bool,
+}
+
+/// The state each plugin's host functions can see. Plugins can't borrow the world, so they read a
+/// snapshot of it and their writes are applied after they've run.
+#[derive(Default)]
+struct PluginHost {
+    entities: Vec<PluginEntity>,
+    position_writes: Vec<(Entity, Vector3<f32>)>,
+    subscriptions: Vec<PluginEvent>,
+}
+
+impl PluginHost {
+    fn find(&self, id: i64) -> Option<&PluginEntity> {
+        let entity = Entity::from_bits(id as u64);
+        self.entities.iter().find(|plugin_entity| plugin_entity.entity == entity)
+    }
+}
+
+/// A loaded plugin
+struct Plugin {
+    name: String,
+    store: Store<PluginHost>,
+    update: Option<TypedFunc<f32, ()>>,
+    on_event: Option<TypedFunc<(i32, i64), ()>>,
+}
+
+/// The PluginManager resource, with the gameplay plugins loaded from the plugins directory. Plugins
+/// are WASM modules that can import these functions from the `dreamfield` module:
+///
+/// * `entity_count() -> i32` gets the number of entities with a Transform
+/// * `entity_id(index: i32) -> i64` gets the id of one of those entities, or -1
+/// * `get_position(id: i64, out: i32) -> i32` writes an entity's position as three f32s to the
+///   plugin's memory, returning 1 on success or 0 if the entity doesn't exist
+/// * `set_position(id: i64, x: f32, y: f32, z: f32)`
+/// * `has_visual(id: i64) -> i32`
+/// * `subscribe(event: i32)` subscribes to trigger enter (0), trigger exit (1) or dialogue
+///   complete (2) events
+/// * `log(ptr: i32, len: i32)` logs a utf-8 string from the plugin's memory
+///
+/// And export these, all of which are optional except the version:
+///
+/// * `dreamfield_api_version() -> i32`
+/// * `init()`, called once when the plugin's loaded
+/// * `update(dt: f32)`, called every frame
+/// * `on_event(event: i32, entity: i64)`, called for subscribed events with the trigger or speaker
+///
+/// The wasm runtime isn't Send, so it's a non-send resource.
+pub struct PluginManager {
+    plugins: Vec<Plugin>,
+}
+
+impl PluginManager {
+    /// Load every .wasm file in a directory, in name order
+    pub fn load(path: &str) -> Self {
+        let engine = Engine::default();
+        let linker = match create_linker(&engine) {
+            Ok(linker) => linker,
+            Err(err) => {
+                log::error!("Failed to create plugin host API: {}", err);
+                return PluginManager { plugins: Vec::new() };
+            }
+        };
+
+        let mut paths: Vec<_> = match std::fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map(|ext| ext == "wasm").unwrap_or(false))
+                .collect(),
+            Err(err) => {
+                log::info!("No plugins loaded from {}: {}", path, err);
+                Vec::new()
+            }
+        };
+        paths.sort();
+
+        let plugins = paths.into_iter()
+            .filter_map(|path| {
+                let name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+                match load_plugin(&engine, &linker, &path, &name) {
+                    Ok(plugin) => {
+                        log::info!("Loaded plugin {}", name);
+                        Some(plugin)
+                    },
+                    Err(err) => {
+                        log::error!("Failed to load plugin {}: {}", path.display(), err);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        PluginManager { plugins }
+    }
+}
+
+/// Load a plugin and call its init function
+fn load_plugin(engine: &Engine, linker: &Linker<PluginHost>, path: &std::path::Path, name: &str)
+    -> anyhow::Result<Plugin>
+{
+    let module = Module::from_file(engine, path)?;
+    let mut store = Store::new(engine, PluginHost::default());
+    let instance: Instance = linker.instantiate(&mut store, &module)?;
+
+    let version = instance.get_typed_func::<(), i32, _>(&mut store, "dreamfield_api_version")?
+        .call(&mut store, ())?;
+    if version != PLUGIN_API_VERSION {
+        anyhow::bail!("plugin uses api version {}, but the host is version {}", version, PLUGIN_API_VERSION);
+    }
+
+    if let Ok(init) = instance.get_typed_func::<(), (), _>(&mut store, "init") {
+        init.call(&mut store, ())?;
+    }
+
+    Ok(Plugin {
+        name: name.to_string(),
+        update: instance.get_typed_func::<f32, (), _>(&mut store, "update").ok(),
+        on_event: instance.get_typed_func::<(i32, i64), (), _>(&mut store, "on_event").ok(),
+        store,
+    })
+}
+
+/// Get a plugin's exported memory
+fn plugin_memory(caller: &mut Caller<'_, PluginHost>) -> Option<Memory> {
+    caller.get_export("memory").and_then(|export| export.into_memory())
+}
+
+/// Create the linker with the host API
+fn create_linker(engine: &Engine) -> anyhow::Result<Linker<PluginHost>> {
+    let mut linker = Linker::new(engine);
+
+    linker.func_wrap(HOST_MODULE, "entity_count", |caller: Caller<'_, PluginHost>| {
+        caller.data().entities.len() as i32
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "entity_id", |caller: Caller<'_, PluginHost>, index: i32| {
+        caller.data().entities.get(index as usize)
+            .map(|plugin_entity| plugin_entity.entity.to_bits() as i64)
+            .unwrap_or(-1)
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "get_position", |mut caller: Caller<'_, PluginHost>, id: i64, out: i32| {
+        let pos = match caller.data().find(id) {
+            Some(plugin_entity) => plugin_entity.pos,
+            None => return 0,
+        };
+
+        let bytes: Vec<u8> = [pos.x, pos.y, pos.z].iter().flat_map(|value| value.to_le_bytes()).collect();
+        match plugin_memory(&mut caller).map(|memory| memory.write(&mut caller, out as usize, &bytes)) {
+            Some(Ok(_)) => 1,
+            _ => 0,
+        }
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "set_position", |mut caller: Caller<'_, PluginHost>, id: i64, x: f32, y: f32, z: f32| {
+        let entity = Entity::from_bits(id as u64);
+        caller.data_mut().position_writes.push((entity, vec3(x, y, z)));
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "has_visual", |caller: Caller<'_, PluginHost>, id: i64| {
+        caller.data().find(id).map(|plugin_entity| plugin_entity.has_visual as i32).unwrap_or(0)
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "subscribe", |mut caller: Caller<'_, PluginHost>, event: i32| {
+        match PluginEvent::from_id(event) {
+            Some(event) => {
+                let subscriptions = &mut caller.data_mut().subscriptions;
+                if !subscriptions.contains(&event) {
+                    subscriptions.push(event);
+                }
+            },
+            None => log::warn!("Plugin subscribed to unknown event {}", event),
+        }
+    })?;
+
+    linker.func_wrap(HOST_MODULE, "log", |mut caller: Caller<'_, PluginHost>, ptr: i32, len: i32| {
+        let mut buf = vec![0; len.max(0) as usize];
+        if let Some(Ok(_)) = plugin_memory(&mut caller).map(|memory| memory.read(&caller, ptr as usize, &mut buf)) {
+            log::info!("[plugin] {}", String::from_utf8_lossy(&buf));
+        }
+    })?;
+
+    Ok(linker)
+}
+
+/// The plugin system, which snapshots the entities for the plugins, runs their update and event
+/// handlers, and then applies the changes they made
+pub fn run_plugins(mut plugin_manager: NonSendMut<PluginManager>, sim_time: Res<SimTime>,
+    mut trigger_enter_events: EventReader<TriggerEnterEvent>, mut trigger_exit_events: EventReader<TriggerExitEvent>,
+    mut dialogue_events: EventReader<DialogueBranchCompleteEvent>,
+    mut query: Query<(Entity, &mut Transform, Option<&Visual>)>)
+{
+    if plugin_manager.plugins.is_empty() {
+        return;
+    }
+
+    let mut events: Vec<(PluginEvent, Entity)> = Vec::new();
+    events.extend(trigger_enter_events.iter().map(|event| (PluginEvent::TriggerEnter, event.trigger)));
+    events.extend(trigger_exit_events.iter().map(|event| (PluginEvent::TriggerExit, event.trigger)));
+    events.extend(dialogue_events.iter().map(|event| (PluginEvent::DialogueComplete, event.speaker)));
+
+    let dt = sim_time.sim_time_delta as f32;
+    for plugin in plugin_manager.plugins.iter_mut() {
+        let host = plugin.store.data_mut();
+        host.entities = query.iter()
+            .map(|(entity, transform, visual)| PluginEntity { entity, pos: transform.pos, has_visual: visual.is_some() })
+            .collect();
+
+        if let Some(update) = &plugin.update {
+            if let Err(err) = update.call(&mut plugin.store, dt) {
+                log::error!("Error in plugin {} update: {}", plugin.name, err);
+            }
+        }
+
+        if let Some(on_event) = &plugin.on_event {
+            for (event, entity) in events.iter() {
+                if !plugin.store.data().subscriptions.contains(event) {
+                    continue;
+                }
+
+                if let Err(err) = on_event.call(&mut plugin.store, (*event as i32, entity.to_bits() as i64)) {
+                    log::error!("Error in plugin {} event handler: {}", plugin.name, err);
+                }
+            }
+        }
+
+        for (entity, pos) in plugin.store.data_mut().position_writes.drain(..) {
+            if let Ok((_, mut transform, _)) = query.get_mut(entity) {
+                transform.pos = pos;
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use bevy_ecs::bundle::Bundle;
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::system::Commands;
+use cgmath::{Vector3, Matrix3};
+
+use super::prefab::PrefabRegistry;
+
+/// The EntityPool resource, which recycles entities of frequently spawned prefabs rather than
+/// despawning them. Released entities keep their Transform but have their other components
+/// removed, so they're invisible to everything until they're acquired again.
+pub struct EntityPool {
+    free: HashMap<String, Vec<Entity>>,
+    /// The maximum number of free entities kept for each prefab, past which they're despawned
+    pub capacity: usize,
+}
+
+impl Default for EntityPool {
+    fn default() -> Self {
+        EntityPool {
+            free: HashMap::new(),
+            capacity: 256,
+        }
+    }
+}
+
+impl EntityPool {
+    /// Get an entity with a prefab's components, reusing a released one if there is one
+    pub fn acquire(&mut self, commands: &mut Commands, prefabs: &PrefabRegistry, prefab: &str,
+        pos: Vector3<f32>, rot: Matrix3<f32>) -> Option<Entity>
+    {
+        let entity = self.free.get_mut(prefab).and_then(|free| free.pop());
+        let mut entity = match entity {
+            Some(entity) => commands.entity(entity),
+            None => commands.spawn(),
+        };
+
+        match prefabs.build(&mut entity, prefab, pos, rot, &[]) {
+            true => Some(entity.id()),
+            false => {
+                entity.despawn();
+                None
+            }
+        }
+    }
+
+    /// Release an entity back to the pool, removing the components in the bundle. The bundle
+    /// should include the prefab's components plus anything else that was added to the entity.
+    pub fn release<B: Bundle>(&mut self, commands: &mut Commands, prefab: &str, entity: Entity) {
+        let free = self.free.entry(prefab.to_string()).or_default();
+        if free.contains(&entity) {
+            log::warn!("Entity {:?} was released to the {} pool twice", entity, prefab);
+            return;
+        }
+
+        if free.len() >= self.capacity {
+            commands.entity(entity).despawn();
+        }
+        else {
+            commands.entity(entity).remove_bundle::<B>();
+            free.push(entity);
+        }
+    }
+
+    /// Get the number of free entities in a prefab's pool
+    pub fn free_count(&self, prefab: &str) -> usize {
+        self.free.get(prefab).map(|free| free.len()).unwrap_or(0)
+    }
+}
@@ -0,0 +1,217 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{EventWriter, Without};
+use bevy_ecs::system::{Res, ResMut, Query};
+use bevy_ecs::world::World;
+use cgmath::{Vector2, Vector3, vec2, vec3, InnerSpace, Zero};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::InputState;
+
+use super::PlayerMovement;
+use super::checkpoint::RespawnPoint;
+use super::game_state::GameState;
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::level::{Levels, LoadLevelEvent};
+use super::screen_fade::ScreenFade;
+use super::static_collision::StaticCollision;
+
+/// The default time it takes to fade out or back in, in seconds
+pub const DEFAULT_FADE_TIME: f32 = 0.4;
+
+/// The PortalAnchor component, for a named place portals can send the player to. The player is put
+/// at the anchor's position, facing the way it faces.
+#[derive(Component)]
+pub struct PortalAnchor {
+    pub name: String,
+}
+
+impl PortalAnchor {
+    pub fn new(name: &str) -> Self {
+        PortalAnchor {
+            name: name.to_string(),
+        }
+    }
+}
+
+/// The Portal component, for doors and passages the player can interact with to go to an anchor,
+/// which can be in another level. Levels can be entered at any of their spawn points as well as
+/// any anchor in their scene.
+#[derive(Component, Clone)]
+pub struct Portal {
+    /// The level the anchor is in, or None for the current level
+    pub level: Option<String>,
+    pub anchor: String,
+    pub interact_radius: f32,
+}
+
+impl Portal {
+    /// Create a portal to an anchor in the current level
+    pub fn new(anchor: &str) -> Self {
+        Portal {
+            level: None,
+            anchor: anchor.to_string(),
+            interact_radius: 2.0,
+        }
+    }
+
+    /// Create a portal to an anchor or entrance in another level
+    pub fn new_to_level(level: &str, anchor: &str) -> Self {
+        Portal {
+            level: Some(level.to_string()),
+            ..Portal::new(anchor)
+        }
+    }
+}
+
+/// An event sent when the player arrives somewhere through a portal, once the destination has
+/// loaded and the screen starts fading back in
+#[derive(Clone, Debug)]
+pub struct AreaTransitionEvent {
+    pub from_level: Option<String>,
+    pub to_level: Option<String>,
+    pub anchor: String,
+}
+
+/// Where a transition is up to
+enum TransitionPhase {
+    Idle,
+    FadingOut(Portal),
+    /// The player has been moved, or the level is loading, and the event is sent once it's running
+    Arrived(AreaTransitionEvent),
+    FadingIn,
+}
+
+/// The PortalTransition resource. Using a portal fades the screen out with the ScreenFade, moves
+/// the player or loads the other level, then fades back in once the game's running again.
+pub struct PortalTransition {
+    /// How long it takes to fade out or back in, in seconds
+    pub fade_time: f32,
+    pub fade_color: Vector3<f32>,
+    phase: TransitionPhase,
+}
+
+impl Default for PortalTransition {
+    fn default() -> Self {
+        PortalTransition {
+            fade_time: DEFAULT_FADE_TIME,
+            fade_color: Vector3::zero(),
+            phase: TransitionPhase::Idle,
+        }
+    }
+}
+
+impl PortalTransition {
+    /// Check whether a transition is happening
+    pub fn active(&self) -> bool {
+        !matches!(self.phase, TransitionPhase::Idle)
+    }
+}
+
+/// Get the pitch and yaw to face the way an anchor's transform faces
+fn anchor_pitch_yaw(transform: &Transform) -> Vector2<f32> {
+    let forward = transform.rot * vec3(0.0, 0.0, -1.0);
+    vec2(0.0, f32::atan2(-forward.x, -forward.z))
+}
+
+/// Find an anchor in the world, returning the position and pitch and yaw to put the player at
+pub fn find_portal_anchor(world: &mut World, name: &str) -> Option<(Vector3<f32>, Vector2<f32>)> {
+    world.query::<(&PortalAnchor, &Transform)>()
+        .iter(world)
+        .find(|(anchor, _)| anchor.name == name)
+        .map(|(_, transform)| (transform.pos, anchor_pitch_yaw(transform)))
+}
+
+/// The portal system, which starts transitions when the player interacts with a portal and runs
+/// them. It's a UI system so that the transition carries on while the other level loads.
+pub fn update_portals(input: Res<InputState>, gamepad: Res<GamepadState>,
+    bindings: Res<InputBindings>, state: Res<GameState>, levels: Res<Levels>, static_collision: Res<StaticCollision>,
+    mut transition: ResMut<PortalTransition>, mut screen_fade: ResMut<ScreenFade>, mut respawn_point: Option<ResMut<RespawnPoint>>,
+    mut load_events: EventWriter<LoadLevelEvent>, mut transition_events: EventWriter<AreaTransitionEvent>,
+    mut player_query: Query<(&mut Transform, &mut PlayerMovement)>,
+    portals: Query<(&Portal, &Transform), Without<PlayerMovement>>,
+    anchors: Query<(&PortalAnchor, &Transform), Without<PlayerMovement>>)
+{
+    match *state {
+        GameState::InGame => (),
+        // Leaving the game cancels the transition
+        GameState::Title => {
+            transition.phase = TransitionPhase::Idle;
+            return;
+        },
+        // Hold the fade while loading or paused
+        _ => return,
+    }
+
+    let phase = std::mem::replace(&mut transition.phase, TransitionPhase::Idle);
+    transition.phase = match phase {
+        TransitionPhase::Idle => {
+            let controls = Controls::new(&input, &gamepad, &bindings);
+            let player_pos = player_query.get_single().ok().map(|(transform, _)| transform.pos);
+
+            // Use the closest portal in range that the player can see
+            let closest = player_pos.filter(|_| controls.is_just_pressed(Action::Interact))
+                .and_then(|player_pos| portals.iter()
+                    .map(|(portal, transform)| (portal, transform.pos, (transform.pos - player_pos).magnitude()))
+                    .filter(|(portal, _, dist)| *dist < portal.interact_radius)
+                    .filter(|(_, pos, _)| static_collision.can_interact(player_pos, *pos))
+                    .map(|(portal, _, dist)| (portal, dist))
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b)));
+
+            match closest {
+                Some((portal, _)) => {
+                    screen_fade.fade_out(transition.fade_time, transition.fade_color);
+                    TransitionPhase::FadingOut(portal.clone())
+                },
+                None => TransitionPhase::Idle,
+            }
+        },
+        TransitionPhase::FadingOut(portal) => match screen_fade.is_faded_out() {
+            false => TransitionPhase::FadingOut(portal),
+            true => {
+                let from_level = levels.current().map(str::to_string);
+                let other_level = portal.level.as_ref().filter(|level| Some(level.as_str()) != levels.current());
+
+                match other_level {
+                    // Loading the level puts the player at the anchor
+                    Some(level) => {
+                        load_events.send(LoadLevelEvent { level: level.clone(), entrance: portal.anchor.clone() })
+                    },
+                    None => {
+                        let anchor = anchors.iter().find(|(anchor, _)| anchor.name == portal.anchor);
+                        match anchor {
+                            Some((_, anchor_transform)) => {
+                                let (pos, pitch_yaw) = (anchor_transform.pos, anchor_pitch_yaw(anchor_transform));
+                                for (mut transform, mut player_movement) in player_query.iter_mut() {
+                                    transform.pos = pos;
+                                    player_movement.pitch_yaw = pitch_yaw;
+                                    player_movement.velocity = Vector3::zero();
+                                    player_movement.ground_plane = None;
+                                }
+                                if let Some(respawn_point) = respawn_point.as_mut() {
+                                    **respawn_point = RespawnPoint::new(pos, pitch_yaw);
+                                }
+                            },
+                            None => log::error!("Portal leads to unknown anchor {}", portal.anchor),
+                        }
+                    }
+                }
+
+                TransitionPhase::Arrived(AreaTransitionEvent {
+                    to_level: portal.level.clone().or_else(|| from_level.clone()),
+                    from_level,
+                    anchor: portal.anchor,
+                })
+            }
+        },
+        TransitionPhase::Arrived(event) => {
+            log::info!("Arrived at {} in {}", event.anchor, event.to_level.as_deref().unwrap_or("no level"));
+            transition_events.send(event);
+            screen_fade.fade_in(transition.fade_time);
+            TransitionPhase::FadingIn
+        },
+        TransitionPhase::FadingIn => match screen_fade.is_fading() {
+            true => TransitionPhase::FadingIn,
+            false => TransitionPhase::Idle,
+        },
+    };
+}
@@ -0,0 +1,135 @@
+use bevy_ecs::system::{Res, Local};
+use serde::{Serialize, Deserialize};
+
+use super::uniform_buffer::{UniformBlock, UniformBuffer, CRT_PARAMS_BINDING, QUANTIZE_PARAMS_BINDING};
+
+/// The color depth the scene's quantized to
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ColorDepth {
+    Bits15,
+    Bits16,
+    Bits24,
+}
+
+impl ColorDepth {
+    pub const ALL: &'static [ColorDepth] = &[ColorDepth::Bits15, ColorDepth::Bits16, ColorDepth::Bits24];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorDepth::Bits15 => "15-bit",
+            ColorDepth::Bits16 => "16-bit",
+            ColorDepth::Bits24 => "24-bit",
+        }
+    }
+
+    /// Get the bits for the red, green and blue channels
+    pub fn bits(&self) -> [f32; 3] {
+        match self {
+            ColorDepth::Bits15 => [5.0, 5.0, 5.0],
+            ColorDepth::Bits16 => [5.0, 6.0, 5.0],
+            ColorDepth::Bits24 => [8.0, 8.0, 8.0],
+        }
+    }
+}
+
+/// The ordered dithering pattern
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum DitherPattern {
+    Off,
+    Bayer2,
+    Bayer4,
+}
+
+impl DitherPattern {
+    pub const ALL: &'static [DitherPattern] = &[DitherPattern::Off, DitherPattern::Bayer2, DitherPattern::Bayer4];
+
+    /// Get the size of the bayer matrix, or 0 if dithering's off
+    pub fn size(&self) -> f32 {
+        match self {
+            DitherPattern::Off => 0.0,
+            DitherPattern::Bayer2 => 2.0,
+            DitherPattern::Bayer4 => 4.0,
+        }
+    }
+}
+
+/// The PostFxSettings resource, for the post-processing effects that can be changed at runtime
+pub struct PostFxSettings {
+    /// Whether the CRT effect is applied by the composite passes
+    pub crt_enabled: bool,
+    /// How much every other line is darkened, from 0 to 1
+    pub scanline_intensity: f32,
+    /// The strength of the animated static, from 0 to 1
+    pub noise_intensity: f32,
+    /// How much of the composite signal's blurred chroma is blended in, from 0 to 1
+    pub composite_blend: f32,
+    pub color_depth: ColorDepth,
+    pub dither_pattern: DitherPattern,
+}
+
+impl Default for PostFxSettings {
+    fn default() -> Self {
+        PostFxSettings {
+            crt_enabled: true,
+            scanline_intensity: 0.25,
+            noise_intensity: 0.06,
+            composite_blend: 1.0,
+            color_depth: ColorDepth::Bits15,
+            dither_pattern: DitherPattern::Off,
+        }
+    }
+}
+
+/// The CrtParams uniform block, matching the std140 layout in crt_params.glsl
+#[repr(C)]
+pub struct CrtParams {
+    crt_params: [f32; 4],
+}
+
+impl UniformBlock for CrtParams {
+    const BINDING: u32 = CRT_PARAMS_BINDING;
+}
+
+/// The CRT params system, which uploads the CRT effect for the composite passes when it changes.
+/// When it's disabled everything's zeroed, so the scene's resolved at full resolution with no
+/// scanlines or static. It runs before the renderer, since the composite passes are run by it.
+pub fn upload_crt_params(post_fx: Res<PostFxSettings>, mut buffer: Local<UniformBuffer<CrtParams>>) {
+    if buffer.is_uploaded() && !post_fx.is_changed() {
+        return;
+    }
+
+    let params = match post_fx.crt_enabled {
+        true => CrtParams {
+            crt_params: [post_fx.scanline_intensity, post_fx.noise_intensity, post_fx.composite_blend, 0.0],
+        },
+        false => CrtParams {
+            crt_params: [0.0; 4],
+        },
+    };
+    buffer.upload(&params);
+}
+
+/// The QuantizeParams uniform block, matching the std140 layout in quantize_params.glsl
+#[repr(C)]
+pub struct QuantizeParams {
+    quantize_params: [f32; 4],
+}
+
+impl UniformBlock for QuantizeParams {
+    const BINDING: u32 = QUANTIZE_PARAMS_BINDING;
+}
+
+/// The quantize params system, which uploads the color depth and dithering pattern the
+/// composite_yiq pass quantizes the scene with when they change. It runs before the renderer,
+/// since the composite passes are run by it.
+pub fn upload_quantize_params(post_fx: Res<PostFxSettings>, mut buffer: Local<UniformBuffer<QuantizeParams>>) {
+    if buffer.is_uploaded() && !post_fx.is_changed() {
+        return;
+    }
+
+    let [red, green, blue] = post_fx.color_depth.bits();
+    let params = QuantizeParams {
+        quantize_params: [red, green, blue, post_fx.dither_pattern.size()],
+    };
+    buffer.upload(&params);
+}
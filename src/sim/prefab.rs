@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::system::EntityCommands;
+use bevy_ecs::world::{World, EntityMut};
+use cgmath::{Vector3, Matrix3, Rad, vec3};
+use serde::Deserialize;
+use dreamfield_renderer::components::{Visual, Animation};
+use dreamfield_system::components::{Transform, EntityName};
+use dreamfield_system::intersection::{Collider, Shape};
+
+use super::npc::Npc;
+use super::character_controller::CharacterController;
+use super::dialogue::DialogueSpeaker;
+use super::audio::AudioSource;
+use super::door::Door;
+use super::checkpoint::Checkpoint;
+use super::trigger::TriggerVolume;
+use super::light::LightSource;
+use super::particles::ParticleEmitter;
+use super::water::WaterVolume;
+use super::ladder::Ladder;
+use super::platform::{Platform, MovingPlatform, PlatformMode};
+use super::pushable::{Pushable, PushMode};
+use super::enemy::Enemy;
+use super::health::Health;
+use super::nav::NavAgent;
+use super::behavior::BehaviorTree;
+use super::villager::{DailySchedule, ScheduleEntry};
+use super::follower::{Follower, FollowerIdle};
+use super::anim_state::{AnimStateMachine, AnimState};
+use super::anim_events::AnimationEvents;
+use super::look_at::{LookAtTarget, LookTarget};
+use super::interpolation::PreviousTransform;
+use super::pvs::Cullable;
+use super::billboard::Billboard;
+use super::blob_shadow::CastsBlobShadow;
+use super::player_movement::PlayerMovement;
+use super::console::ConsoleCommands;
+
+/// The maximum depth of prefab bases, to catch prefabs that are their own base
+const MAX_BASE_DEPTH: usize = 8;
+
+/// A component in a prefab, with its parameters. Positions are relative to the entity, and
+/// rotate with it.
+#[derive(Deserialize, Clone, Debug)]
+pub enum PrefabComponent {
+    Name(String),
+    /// Move the entity by an offset from where it's spawned
+    Offset(f32, f32, f32),
+    Visual {
+        model: String,
+        #[serde(default)]
+        animation: Option<String>,
+    },
+    /// A sprite that turns to face the camera, looping through its frames if it has more than one,
+    /// instead of a Visual
+    Billboard {
+        sprite: String,
+        size: f32,
+        #[serde(default)]
+        frames: Option<usize>,
+        #[serde(default)]
+        fps: f32,
+        /// Face the camera fully, rather than only turning around y
+        #[serde(default)]
+        tilt: bool,
+    },
+    /// A round shadow on the ground under the entity, with its radius
+    BlobShadow(f32),
+    /// Hide the entity's Visual while it can't be seen, for props that don't change their Visual
+    Cullable,
+    /// A bounding spheroid collider
+    Collider {
+        center: (f32, f32, f32),
+        radii: (f32, f32, f32),
+    },
+    /// A kinematic character controller, which makes npcs collide with the world as they walk,
+    /// with any missing settings using the defaults
+    CharacterController {
+        #[serde(default)]
+        max_slope_angle: Option<f32>,
+        #[serde(default)]
+        step_height: Option<f32>,
+        #[serde(default)]
+        ground_snap_distance: Option<f32>,
+    },
+    IdleNpc {
+        animation: String,
+    },
+    /// An npc that walks back and forth along its forward axis, with a Behavior that patrols
+    PatrolNpc {
+        distance: f32,
+        walk_speed: f32,
+        idle_time: f32,
+        idle_animation: String,
+        walk_animation: String,
+    },
+    Dialogue(String),
+    AudioLoop {
+        sound: String,
+        volume: f32,
+        radius: f32,
+    },
+    Door {
+        #[serde(default)]
+        key: Option<String>,
+        #[serde(default)]
+        consume_key: bool,
+    },
+    Checkpoint,
+    TriggerSphere(f32),
+    TriggerBox {
+        min: (f32, f32, f32),
+        max: (f32, f32, f32),
+    },
+    TorchLight {
+        lit: bool,
+    },
+    FireParticles,
+    DustParticles,
+    /// A ladder going up the entity's local y axis
+    Ladder,
+    /// A water volume, which also needs a TriggerVolume for its extents
+    Water,
+    /// A platform that carries things standing on it, with an axis aligned deck
+    Platform {
+        min: (f32, f32, f32),
+        max: (f32, f32, f32),
+    },
+    /// A platform moving between waypoints relative to the entity, which also needs a Platform
+    MovingPlatform {
+        waypoints: Vec<(f32, f32, f32)>,
+        speed: f32,
+        mode: PlatformMode,
+        #[serde(default)]
+        wait_time: f32,
+    },
+    /// A hostile npc, which patrols back and forth along its forward axis if it has a patrol distance
+    /// and a Behavior that patrols
+    Enemy {
+        #[serde(default)]
+        patrol_distance: f32,
+        idle_animation: String,
+        walk_animation: String,
+        #[serde(default)]
+        attack_animation: Option<String>,
+        #[serde(default)]
+        hit_on_impact: bool,
+    },
+    Health(f32),
+    /// Find paths around obstacles with the nav mesh
+    NavAgent,
+    /// A behavior tree for npcs and enemies, by name
+    Behavior(String),
+    /// An animation state machine, which starts in the first state and drives the Visual's animation
+    AnimStates(Vec<AnimState>),
+    /// Emit events at the markers in the Visual's animations
+    AnimationEvents,
+    /// A daily schedule for an npc, with locations relative to the entity
+    Schedule(Vec<ScheduleEntry>),
+    /// A companion that follows the player, with any missing distances using the defaults
+    Follower {
+        idle: FollowerIdle,
+        idle_animation: String,
+        walk_animation: String,
+        #[serde(default)]
+        follow_distance: Option<f32>,
+        #[serde(default)]
+        teleport_distance: Option<f32>,
+    },
+    /// A prop the player can push around, which also needs a Collider to move it with
+    Pushable {
+        mode: PushMode,
+        half_extents: (f32, f32, f32),
+        #[serde(default)]
+        speed: Option<f32>,
+    },
+    /// Glance at a target when it's nearby, with a max angle in degrees, and any missing values
+    /// using the defaults
+    /// Draw the entity in between sim ticks, for things that move quickly
+    Interpolated,
+    LookAt {
+        target: LookTarget,
+        #[serde(default)]
+        range: Option<f32>,
+        #[serde(default)]
+        max_angle: Option<f32>,
+    },
+}
+
+/// A prefab definition, loaded from a RON file
+#[derive(Deserialize, Debug)]
+pub struct PrefabDef {
+    /// Another prefab to start from, whose components are replaced by any of the same kind here
+    #[serde(default)]
+    pub base: Option<String>,
+    pub components: Vec<PrefabComponent>,
+}
+
+/// Something a prefab's components can be inserted into, so prefabs can be spawned with either
+/// Commands or direct World access
+pub trait PrefabTarget {
+    fn insert_component<C: Component>(&mut self, component: C);
+}
+
+impl PrefabTarget for EntityCommands<'_, '_, '_> {
+    fn insert_component<C: Component>(&mut self, component: C) {
+        self.insert(component);
+    }
+}
+
+impl PrefabTarget for EntityMut<'_> {
+    fn insert_component<C: Component>(&mut self, component: C) {
+        self.insert(component);
+    }
+}
+
+/// Replace components in a list with overrides of the same kind, or add them if there isn't one
+fn apply_overrides(components: &mut Vec<PrefabComponent>, overrides: &[PrefabComponent]) {
+    for component in overrides {
+        let kind = std::mem::discriminant(component);
+        match components.iter_mut().find(|existing| std::mem::discriminant(*existing) == kind) {
+            Some(existing) => *existing = component.clone(),
+            None => components.push(component.clone()),
+        }
+    }
+}
+
+/// Find the model of the Visual in a list of components, if there is one
+fn visual_model(components: &[PrefabComponent]) -> Option<&str> {
+    components.iter().find_map(|component| match component {
+        PrefabComponent::Visual { model, .. } => Some(model.as_str()),
+        _ => None,
+    })
+}
+
+/// The prefab registry, which stores all the prefab definitions by name
+pub struct PrefabRegistry {
+    prefabs: HashMap<String, PrefabDef>,
+}
+
+impl PrefabRegistry {
+    /// Create a new prefab registry from a RON map of prefab name to definition
+    pub fn new(source: &str) -> Self {
+        let prefabs = match ron::from_str(source) {
+            Ok(prefabs) => prefabs,
+            Err(err) => {
+                log::error!("Failed to load prefab definitions: {}", err);
+                HashMap::new()
+            }
+        };
+
+        PrefabRegistry { prefabs }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PrefabDef> {
+        self.prefabs.get(name)
+    }
+
+    /// Get the names of all the prefabs
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.prefabs.keys().map(String::as_str)
+    }
+
+    /// Get a prefab's components with its bases and a set of per-instance overrides applied
+    pub fn resolve(&self, name: &str, overrides: &[PrefabComponent]) -> Option<Vec<PrefabComponent>> {
+        let mut chain = Vec::new();
+        let mut next = Some(name);
+        while let Some(name) = next {
+            if chain.len() >= MAX_BASE_DEPTH {
+                log::error!("Prefab {} has too many bases, it might be its own base", chain[0]);
+                return None;
+            }
+
+            let prefab = match self.get(name) {
+                Some(prefab) => prefab,
+                None => {
+                    log::warn!("Unknown prefab: {}", name);
+                    return None;
+                }
+            };
+
+            chain.push(name);
+            next = prefab.base.as_deref();
+        }
+
+        let mut components = Vec::new();
+        for name in chain.iter().rev() {
+            apply_overrides(&mut components, &self.prefabs[*name].components);
+        }
+        apply_overrides(&mut components, overrides);
+
+        Some(components)
+    }
+
+    /// Insert a prefab's components into an entity, returning false if the prefab doesn't exist
+    pub fn build<T: PrefabTarget>(&self, target: &mut T, name: &str, pos: Vector3<f32>, rot: Matrix3<f32>,
+        overrides: &[PrefabComponent]) -> bool
+    {
+        match self.resolve(name, overrides) {
+            Some(components) => {
+                build_components(target, &components, pos, rot);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// Insert a list of prefab components into an entity
+pub fn build_components<T: PrefabTarget>(target: &mut T, components: &[PrefabComponent], pos: Vector3<f32>,
+    rot: Matrix3<f32>)
+{
+    let to_vec = |(x, y, z): (f32, f32, f32)| vec3(x, y, z);
+
+    // Apply the offset first, since everything else is relative to the final position
+    let pos = components.iter()
+        .find_map(|component| match component {
+            PrefabComponent::Offset(x, y, z) => Some(pos + rot * vec3(*x, *y, *z)),
+            _ => None,
+        })
+        .unwrap_or(pos);
+
+    target.insert_component(Transform::new(pos, rot));
+
+    for component in components {
+        match component {
+            PrefabComponent::Name(name) => target.insert_component(EntityName::new(name)),
+            PrefabComponent::Offset(..) => {},
+            PrefabComponent::Visual { model, animation: None } => target.insert_component(Visual::new(model, false)),
+            PrefabComponent::Visual { model, animation: Some(animation) } => {
+                target.insert_component(Visual::new_with_anim(model, false, Animation::Loop(animation.clone())));
+            },
+            PrefabComponent::Billboard { sprite, size, frames, fps, tilt } => {
+                let mut billboard = Billboard::new_animated(sprite, frames.unwrap_or(1), *fps, *size);
+                billboard.upright = !*tilt;
+                target.insert_component(billboard);
+            },
+            PrefabComponent::BlobShadow(radius) => target.insert_component(CastsBlobShadow::new(*radius)),
+            PrefabComponent::Cullable => target.insert_component(Cullable::default()),
+            PrefabComponent::Collider { center, radii } => {
+                target.insert_component(Collider::new(Shape::BoundingSpheroid(to_vec(*center), to_vec(*radii))));
+            },
+            PrefabComponent::CharacterController { max_slope_angle, step_height, ground_snap_distance } => {
+                let defaults = CharacterController::default();
+                target.insert_component(CharacterController {
+                    max_slope_angle: max_slope_angle.unwrap_or(defaults.max_slope_angle),
+                    step_height: step_height.unwrap_or(defaults.step_height),
+                    ground_snap_distance: ground_snap_distance.unwrap_or(defaults.ground_snap_distance),
+                });
+            },
+            PrefabComponent::IdleNpc { animation } => target.insert_component(Npc::new_idle(animation)),
+            PrefabComponent::PatrolNpc { distance, walk_speed, idle_time, idle_animation, walk_animation } => {
+                let patrol_end = pos + rot * vec3(0.0, 0.0, -distance);
+                target.insert_component(Npc::new_patrol(vec![pos, patrol_end], *walk_speed, *idle_time,
+                    idle_animation, walk_animation));
+            },
+            PrefabComponent::Dialogue(tree) => target.insert_component(DialogueSpeaker::new(tree)),
+            PrefabComponent::AudioLoop { sound, volume, radius } => {
+                target.insert_component(AudioSource::new_looping(sound, *volume, *radius));
+            },
+            PrefabComponent::Door { key: None, .. } => target.insert_component(Door::new(rot)),
+            PrefabComponent::Door { key: Some(key), consume_key } => {
+                target.insert_component(Door::new_locked(rot, key, *consume_key));
+            },
+            PrefabComponent::Checkpoint => target.insert_component(Checkpoint::default()),
+            PrefabComponent::TriggerSphere(radius) => target.insert_component(TriggerVolume::new_sphere(*radius)),
+            PrefabComponent::TriggerBox { min, max } => {
+                target.insert_component(TriggerVolume::new_aabb(to_vec(*min), to_vec(*max)));
+            },
+            PrefabComponent::TorchLight { lit } => {
+                let mut light = LightSource::new_torch(pos);
+                light.lit = *lit;
+                target.insert_component(light);
+            },
+            PrefabComponent::FireParticles => target.insert_component(ParticleEmitter::new_fire()),
+            PrefabComponent::DustParticles => target.insert_component(ParticleEmitter::new_dust()),
+            PrefabComponent::Ladder => {
+                target.insert_component(Ladder { axis: rot * vec3(0.0, 1.0, 0.0), ..Ladder::default() });
+            },
+            PrefabComponent::Water => target.insert_component(WaterVolume::default()),
+            PrefabComponent::Platform { min, max } => target.insert_component(Platform::new(to_vec(*min), to_vec(*max))),
+            PrefabComponent::MovingPlatform { waypoints, speed, mode, wait_time } => {
+                let waypoints = waypoints.iter().map(|waypoint| pos + rot * to_vec(*waypoint)).collect();
+                target.insert_component(MovingPlatform::new(waypoints, *speed, *mode, *wait_time));
+            },
+            PrefabComponent::Enemy { patrol_distance, idle_animation, walk_animation, attack_animation, hit_on_impact } => {
+                let patrol_points = match *patrol_distance > 0.0 {
+                    true => vec![pos + rot * vec3(0.0, 0.0, -patrol_distance), pos],
+                    false => Vec::new(),
+                };
+                let mut enemy = Enemy::new(pos, patrol_points, idle_animation, walk_animation);
+                enemy.attack_animation = attack_animation.clone();
+                enemy.hit_on_impact = *hit_on_impact;
+                target.insert_component(enemy);
+            },
+            PrefabComponent::Health(max) => target.insert_component(Health::new(*max)),
+            PrefabComponent::NavAgent => target.insert_component(NavAgent::default()),
+            PrefabComponent::Behavior(tree) => target.insert_component(BehaviorTree::new(tree)),
+            PrefabComponent::AnimStates(states) => {
+                let mut machine = AnimStateMachine::new(states.clone());
+                machine.model = visual_model(components).map(str::to_string);
+                target.insert_component(machine);
+            },
+            PrefabComponent::AnimationEvents => {
+                match visual_model(components) {
+                    Some(model) => target.insert_component(AnimationEvents::new(model)),
+                    None => log::warn!("AnimationEvents in a prefab without a Visual"),
+                }
+            },
+            PrefabComponent::Schedule(entries) => target.insert_component(DailySchedule::new(pos, rot, entries.clone())),
+            PrefabComponent::Follower { idle, idle_animation, walk_animation, follow_distance, teleport_distance } => {
+                let mut follower = Follower::new(idle.clone(), idle_animation, walk_animation);
+                follower.follow_distance = follow_distance.unwrap_or(follower.follow_distance);
+                follower.teleport_distance = teleport_distance.unwrap_or(follower.teleport_distance);
+                target.insert_component(follower);
+            },
+            PrefabComponent::Pushable { mode, half_extents, speed } => {
+                let mut pushable = Pushable::new(*mode, to_vec(*half_extents));
+                pushable.speed = speed.unwrap_or(pushable.speed);
+                target.insert_component(pushable);
+            },
+            PrefabComponent::Interpolated => {
+                target.insert_component(PreviousTransform::new(&Transform::new(pos, rot)));
+            },
+            PrefabComponent::LookAt { target: look_target, range, max_angle } => {
+                let mut look_at = LookAtTarget::new(*look_target);
+                look_at.range = range.unwrap_or(look_at.range);
+                look_at.max_angle = max_angle.unwrap_or(look_at.max_angle);
+                target.insert_component(look_at);
+            },
+        }
+    }
+}
+
+/// Spawn a prefab in the world at a transform
+pub fn spawn_prefab(world: &mut World, name: &str, transform: Transform) -> Option<Entity> {
+    spawn_prefab_with_overrides(world, name, transform, &[])
+}
+
+/// Spawn a prefab in the world at a transform, with some of its components replaced
+pub fn spawn_prefab_with_overrides(world: &mut World, name: &str, transform: Transform,
+    overrides: &[PrefabComponent]) -> Option<Entity>
+{
+    let components = world.resource::<PrefabRegistry>().resolve(name, overrides)?;
+    let mut entity = world.spawn();
+    build_components(&mut entity, &components, transform.pos, transform.rot);
+    Some(entity.id())
+}
+
+/// How far in front of the player the spawn console command puts prefabs
+const CONSOLE_SPAWN_DISTANCE: f32 = 2.0;
+
+/// Register the spawn console command
+pub fn register_spawn_command(commands: &mut ConsoleCommands) {
+    commands.register("spawn", "spawn <prefab>", "Spawn a prefab in front of the player", |world, args| {
+        let name = args.first().ok_or_else(|| "Which prefab?".to_string())?;
+
+        let mut query = world.query::<(&Transform, &PlayerMovement)>();
+        let (pos, yaw) = query.get_single(world)
+            .map(|(transform, movement)| (transform.pos, movement.pitch_yaw.y))
+            .map_err(|_| "There's no player".to_string())?;
+        let forward = vec3(-yaw.sin(), 0.0, -yaw.cos());
+        let transform = Transform::new(pos + forward * CONSOLE_SPAWN_DISTANCE, Matrix3::from_angle_y(Rad(yaw)));
+
+        match spawn_prefab(world, name, transform) {
+            Some(_) => Ok(format!("Spawned {}", name)),
+            None => Err(format!("There's no prefab called {}", name)),
+        }
+    }).with_completion(|world, arg| match arg {
+        0 => world.resource::<PrefabRegistry>().names().map(str::to_string).collect(),
+        _ => Vec::new(),
+    });
+}
@@ -0,0 +1,147 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventWriter, With, Without};
+use bevy_ecs::system::{Res, ResMut, Query, Commands};
+use cgmath::{Vector3, Matrix3, InnerSpace, vec3};
+use dreamfield_renderer::components::Visual;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::PlayerMovement;
+use super::DialogueState;
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::inventory::PlayerInventory;
+use super::fire_orb::FIRE_ORB_ITEM;
+use super::health::{Health, DamageEvent};
+use super::pool::EntityPool;
+use super::prefab::PrefabRegistry;
+use super::static_collision::StaticCollision;
+
+/// The speed fire orbs are cast at
+const CAST_SPEED: f32 = 12.0;
+
+/// How far in front of the camera cast orbs spawn
+const CAST_OFFSET: f32 = 0.5;
+
+/// The prefab cast fire orbs are spawned from
+const FIRE_ORB_PREFAB: &'static str = "fire_orb_projectile";
+
+/// The radius of entities projectiles can hit
+const TARGET_RADIUS: f32 = 0.5;
+
+/// The Projectile component
+#[derive(Component)]
+pub struct Projectile {
+    pub velocity: Vector3<f32>,
+    /// The downwards acceleration
+    pub gravity: f32,
+    /// The number of seconds before the projectile despawns
+    pub lifetime: f32,
+    pub radius: f32,
+    pub damage: f32,
+    /// The entity that fired the projectile, which it can't hit
+    pub owner: Option<Entity>,
+    age: f32,
+}
+
+impl Projectile {
+    pub fn new(velocity: Vector3<f32>, owner: Option<Entity>) -> Self {
+        Projectile {
+            velocity,
+            gravity: 2.0,
+            lifetime: 5.0,
+            radius: 0.2,
+            damage: 20.0,
+            owner,
+            age: 0.0,
+        }
+    }
+}
+
+/// An event emitted when a projectile hits the world or an entity
+pub struct ProjectileImpactEvent {
+    pub projectile: Entity,
+    pub position: Vector3<f32>,
+    /// The entity that was hit, if any
+    pub entity: Option<Entity>,
+    /// The normal of the world's surface where it was hit, if it hit the world
+    pub normal: Option<Vector3<f32>>,
+}
+
+/// The fire orb casting system, which throws fire orbs from the player's inventory
+pub fn cast_fire_orb(mut commands: Commands, input: Res<InputState>, gamepad: Res<GamepadState>,
+    bindings: Res<InputBindings>, dialogue: Res<DialogueState>, prefabs: Res<PrefabRegistry>, mut pool: ResMut<EntityPool>,
+    mut player_query: Query<(Entity, &Transform, &PlayerMovement, &mut PlayerInventory)>)
+{
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    if !controls.is_just_pressed(Action::Cast) || dialogue.in_dialogue() {
+        return;
+    }
+
+    for (entity, transform, player_movement, mut inventory) in player_query.iter_mut() {
+        if !inventory.remove(FIRE_ORB_ITEM, 1) {
+            continue;
+        }
+
+        let forward = player_movement.forward();
+        let pos = transform.pos + vec3(0.0, player_movement.eye_height(), 0.0) + forward * CAST_OFFSET;
+
+        if let Some(orb) = pool.acquire(&mut commands, &prefabs, FIRE_ORB_PREFAB, pos, Matrix3::from_value(0.25)) {
+            commands.entity(orb).insert(Projectile::new(forward * CAST_SPEED, Some(entity)));
+        }
+    }
+}
+
+/// The projectile update system
+pub fn update_projectiles(mut commands: Commands, sim_time: Res<SimTime>, static_collision: Res<StaticCollision>,
+    mut pool: ResMut<EntityPool>,
+    mut projectiles: Query<(Entity, &mut Projectile, &mut Transform)>,
+    targets: Query<(Entity, &Transform), (With<Health>, Without<Projectile>)>,
+    mut damage_events: EventWriter<DamageEvent>, mut impact_events: EventWriter<ProjectileImpactEvent>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (entity, mut projectile, mut transform) in projectiles.iter_mut() {
+        projectile.age += time_delta;
+        if projectile.age > projectile.lifetime {
+            pool.release::<(Visual, Projectile)>(&mut commands, FIRE_ORB_PREFAB, entity);
+            continue;
+        }
+
+        projectile.velocity.y -= projectile.gravity * time_delta;
+        let movement = projectile.velocity * time_delta;
+
+        // Test against entities first
+        let hit_entity = targets.iter()
+            .filter(|(target, _)| Some(*target) != projectile.owner)
+            .find(|(_, target_transform)| {
+                let to_target = target_transform.pos - (transform.pos + movement);
+                to_target.magnitude() < projectile.radius + TARGET_RADIUS
+            });
+
+        if let Some((target, target_transform)) = hit_entity {
+            damage_events.send(DamageEvent { target, amount: projectile.damage, source: projectile.owner });
+            impact_events.send(ProjectileImpactEvent { projectile: entity, position: target_transform.pos, entity: Some(target), normal: None });
+            pool.release::<(Visual, Projectile)>(&mut commands, FIRE_ORB_PREFAB, entity);
+            continue;
+        }
+
+        // Then sweep against the world
+        let distance = movement.magnitude();
+        let hit = match distance > 0.0 {
+            true => static_collision.spherecast(transform.pos, movement / distance, projectile.radius, distance),
+            false => None,
+        };
+
+        match hit {
+            Some(hit) => {
+                let position = transform.pos + movement / distance * hit.distance;
+                impact_events.send(ProjectileImpactEvent { projectile: entity, position, entity: None, normal: Some(hit.normal) });
+                pool.release::<(Visual, Projectile)>(&mut commands, FIRE_ORB_PREFAB, entity);
+            },
+            None => {
+                transform.pos += movement;
+            }
+        }
+    }
+}
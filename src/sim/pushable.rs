@@ -0,0 +1,177 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, Without};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, InnerSpace, vec3};
+use serde::Deserialize;
+use dreamfield_system::components::Transform;
+use dreamfield_system::intersection::{Collider, Shape};
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::player_movement::PlayerMovement;
+use super::input_bindings::{InputBindings, Action, Controls};
+use super::gamepad::GamepadState;
+use super::character_controller::sweep_collider;
+use super::static_collision::StaticCollision;
+
+/// How close the player's collider has to be to a prop's side to push or pull it
+const CONTACT_DISTANCE: f32 = 0.2;
+
+/// The minimum speed the player has to be moving towards or away from a prop to move it
+const MIN_PUSH_SPEED: f32 = 0.5;
+
+/// A tiny value which stops props from coming too close to walls
+const MIN_DISTANCE_FROM_WALLS: f32 = 0.01;
+
+/// How a pushable prop moves
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PushMode {
+    /// One cell of the given size at a time, along the world axes
+    Grid(f32),
+    /// Smoothly along the side being pushed, for as long as it's pushed
+    Free,
+}
+
+/// The Pushable component, for props the player can push by walking into them or holding interact
+/// next to them. Free props can also be pulled by holding interact while walking away from them,
+/// but grid props can't, since there's nowhere for them to go but into the player. The prop's box
+/// is used to find which side the player's on, and it's moved through the world with its bounding
+/// spheroid Collider, which also stops the player walking through it. Props slide along the ground
+/// they start on, without gravity.
+#[derive(Component)]
+pub struct Pushable {
+    pub mode: PushMode,
+    /// The half extents of the prop's box around its position
+    pub half_extents: Vector3<f32>,
+    /// Speed in units per second
+    pub speed: f32,
+    /// Seconds grid props have to be pushed before they move, so they aren't bumped by accident
+    pub push_delay: f32,
+    push_time: f32,
+    /// Where a grid prop is moving to
+    target: Option<Vector3<f32>>,
+}
+
+impl Pushable {
+    pub fn new(mode: PushMode, half_extents: Vector3<f32>) -> Self {
+        Pushable {
+            mode,
+            half_extents,
+            speed: 2.0,
+            push_delay: 0.4,
+            push_time: 0.0,
+            target: None,
+        }
+    }
+
+    /// Get whether the prop is in the middle of a move
+    pub fn moving(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Get the direction from the prop's centre to the side a position is touching, along the world
+    /// x or z axis, if it's within a distance of that side
+    fn contact_side(&self, prop_pos: Vector3<f32>, pos: Vector3<f32>, distance: f32) -> Option<Vector3<f32>> {
+        let offset = pos - prop_pos;
+        let half = self.half_extents;
+
+        // Use whichever side the position is furthest out from, relative to the prop's size
+        let (side, along, across, half_along, half_across) = match offset.x.abs() / half.x > offset.z.abs() / half.z {
+            true => (vec3(offset.x.signum(), 0.0, 0.0), offset.x.abs(), offset.z.abs(), half.x, half.z),
+            false => (vec3(0.0, 0.0, offset.z.signum()), offset.z.abs(), offset.x.abs(), half.z, half.x),
+        };
+
+        let touching = along - half_along <= distance && along >= half_along && across <= half_across
+            && offset.y.abs() <= half.y + distance;
+        match touching {
+            true => Some(side),
+            false => None,
+        }
+    }
+}
+
+/// The pushable system, which moves props the player pushes or pulls
+pub fn update_pushables(sim_time: Res<SimTime>, input: Res<InputState>, gamepad: Res<GamepadState>,
+    bindings: Res<InputBindings>, static_collision: Res<StaticCollision>,
+    player_query: Query<(&Transform, &PlayerMovement, &Collider), Without<Pushable>>,
+    mut query: Query<(Entity, &mut Pushable, &mut Transform, &Collider)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    let controls = Controls::new(&input, &gamepad, &bindings);
+
+    let (player_pos, player_velocity, player_radius) = match player_query.get_single() {
+        Ok((transform, movement, collider)) if movement.enabled => {
+            let radius = match collider.shape {
+                Shape::BoundingSpheroid(_, radius) => radius.x,
+                _ => 0.0,
+            };
+            (transform.pos, vec3(movement.velocity.x, 0.0, movement.velocity.z), radius)
+        },
+        _ => return,
+    };
+    let holding = controls.is_held(Action::Interact);
+
+    for (entity, mut pushable, mut transform, collider) in query.iter_mut() {
+        // Carry on with grid moves that have already started
+        if let Some(target) = pushable.target {
+            let to_target = target - transform.pos;
+            let dist = to_target.magnitude();
+            let step = pushable.speed * time_delta;
+            if dist <= step {
+                transform.pos = target;
+                pushable.target = None;
+            }
+            else {
+                transform.pos += to_target * (step / dist);
+            }
+            continue;
+        }
+
+        // Find the direction the player wants to move the prop in, away from them when they walk into
+        // its side or hold interact, and towards them when they pull on it
+        let player_centre = vec3(player_pos.x, transform.pos.y, player_pos.z);
+        let side = pushable.contact_side(transform.pos, player_centre, player_radius + CONTACT_DISTANCE);
+        let push = side.and_then(|side| {
+            let walking_in = player_velocity.dot(-side) >= MIN_PUSH_SPEED;
+            let walking_away = player_velocity.dot(side) >= MIN_PUSH_SPEED;
+            match (pushable.mode, walking_in, walking_away, holding) {
+                (PushMode::Free, _, true, true) => Some((side, player_velocity.dot(side))),
+                (_, true, _, _) => Some((-side, player_velocity.dot(-side))),
+                (_, _, false, true) => Some((-side, pushable.speed)),
+                _ => None,
+            }
+        });
+
+        let (dir, push_speed) = match push {
+            Some(push) => push,
+            None => {
+                pushable.push_time = 0.0;
+                continue;
+            }
+        };
+
+        match pushable.mode {
+            PushMode::Grid(cell_size) => {
+                pushable.push_time += time_delta;
+                if pushable.push_time < pushable.push_delay && !holding {
+                    continue;
+                }
+
+                // Only start moving if there's room for the whole cell
+                let movement = dir * cell_size;
+                if sweep_collider(&static_collision, collider, transform.pos, movement, entity).is_none() {
+                    pushable.target = Some(transform.pos + movement);
+                }
+                pushable.push_time = 0.0;
+            },
+            PushMode::Free => {
+                // Move at the player's speed, so pulled props keep up with them
+                let movement = dir * f32::min(pushable.speed, push_speed) * time_delta;
+                let distance = match sweep_collider(&static_collision, collider, transform.pos, movement, entity) {
+                    Some(hit) => f32::max(hit.distance - MIN_DISTANCE_FROM_WALLS, 0.0),
+                    None => movement.magnitude(),
+                };
+                transform.pos += dir * distance;
+            },
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::world::World;
+use cgmath::{Vector3, SquareMatrix};
+use dreamfield_renderer::components::{PlayerCamera, Visual};
+use dreamfield_system::components::Transform;
+
+use super::level::Levels;
+
+/// A potentially visible set baked by build.rs, with which cells of a world model can be seen from
+/// each other. Cells that can't be stood in are visible from everywhere.
+pub struct VisibilitySet {
+    min: (f32, f32),
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    /// The sorted cells each cell can see, or None if it can't be stood in
+    visible: Vec<Option<Vec<usize>>>,
+}
+
+impl VisibilitySet {
+    /// Parse a set baked by build.rs, in the format written by bake_pvs
+    pub fn parse(source: &str) -> Option<Self> {
+        let mut lines = source.lines();
+
+        let header: Vec<f32> = lines.next()?.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+        let [min_x, min_z, cell_size, width, height] = <[f32; 5]>::try_from(header).ok()?;
+        let (width, height) = (width as usize, height as usize);
+
+        let visible = lines.take(width * height)
+            .map(|line| match line.trim() {
+                "*" => Some(None),
+                line => {
+                    let mut cells: Vec<usize> = line.split_whitespace().map(str::parse).collect::<Result<_, _>>().ok()?;
+                    cells.sort_unstable();
+                    Some(Some(cells))
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        match visible.len() == width * height {
+            true => Some(VisibilitySet { min: (min_x, min_z), cell_size, width, height, visible }),
+            false => None,
+        }
+    }
+
+    /// Get the cell a world position is in, if it's in the set
+    fn cell_at(&self, pos: Vector3<f32>) -> Option<usize> {
+        let col = ((pos.x - self.min.0) / self.cell_size).floor();
+        let row = ((pos.z - self.min.1) / self.cell_size).floor();
+        match col >= 0.0 && row >= 0.0 && (col as usize) < self.width && (row as usize) < self.height {
+            true => Some(row as usize * self.width + col as usize),
+            false => None,
+        }
+    }
+
+    /// Check whether something at one position could be seen from another. Anything outside the
+    /// set always could be.
+    pub fn is_visible(&self, from: Vector3<f32>, to: Vector3<f32>) -> bool {
+        match (self.cell_at(from), self.cell_at(to)) {
+            (Some(from), Some(to)) => match &self.visible[from] {
+                Some(cells) => cells.binary_search(&to).is_ok(),
+                None => true,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// The VisibilitySets resource, with the potentially visible set of each level that has one. The
+/// engine draws every world chunk in range, so it's what's in them that gets culled: the LOD tiles,
+/// and entities with a Cullable component.
+pub struct VisibilitySets {
+    sets: Vec<(String, VisibilitySet)>,
+}
+
+impl VisibilitySets {
+    /// Create the sets from a list of (level, source) pairs of baked sets. Levels that weren't
+    /// baked with one have an empty source, and are skipped.
+    pub fn new(sets: Vec<(&str, &str)>) -> Self {
+        let sets = sets.into_iter()
+            .filter(|(_, source)| !source.trim().is_empty())
+            .filter_map(|(level, source)| match VisibilitySet::parse(source) {
+                Some(set) => Some((level.to_string(), set)),
+                None => {
+                    log::error!("Failed to parse the potentially visible set for {}", level);
+                    None
+                }
+            })
+            .collect();
+
+        VisibilitySets { sets }
+    }
+
+    /// Get a level's set
+    pub fn get(&self, level: &str) -> Option<&VisibilitySet> {
+        self.sets.iter().find(|(name, _)| name == level).map(|(_, set)| set)
+    }
+
+    /// Check whether something could be seen from a position in a level
+    pub fn is_visible(&self, level: Option<&str>, from: Vector3<f32>, to: Vector3<f32>) -> bool {
+        match level.and_then(|level| self.get(level)) {
+            Some(set) => set.is_visible(from, to),
+            None => true,
+        }
+    }
+}
+
+/// The Cullable component, for entities whose Visual is taken off them while they can't be seen,
+/// so the renderer doesn't draw them. It's meant for static props, since anything that updates its
+/// Visual won't be updated while it's culled.
+#[derive(Component, Default)]
+pub struct Cullable {
+    culled: Option<Visual>,
+}
+
+impl Cullable {
+    /// Check whether the entity's culled at the moment
+    pub fn is_culled(&self) -> bool {
+        self.culled.is_some()
+    }
+}
+
+/// The culling system, which hides cullable entities the camera can't see and shows them again
+/// once it can. It's exclusive, since it moves the Visual components between entities and their
+/// Cullable.
+pub fn cull_entities(world: &mut World) {
+    let cam_pos = world.query::<&PlayerCamera>()
+        .iter(world)
+        .next()
+        .and_then(|cam| cam.view.invert())
+        .map(|cam_transform| cam_transform.w.truncate());
+    let cam_pos = match cam_pos {
+        Some(cam_pos) => cam_pos,
+        None => return,
+    };
+
+    let mut query = world.query::<(Entity, &Cullable, &Transform)>();
+    let visibility = world.resource::<VisibilitySets>();
+    let level = world.resource::<Levels>().current();
+    let changes: Vec<(Entity, bool)> = query.iter(world)
+        .filter_map(|(entity, cullable, transform)| {
+            let visible = visibility.is_visible(level, cam_pos, transform.pos);
+            match (visible, cullable.culled.is_some()) {
+                (true, true) | (false, false) => Some((entity, visible)),
+                _ => None,
+            }
+        })
+        .collect();
+
+    for (entity, visible) in changes {
+        let mut entity = world.entity_mut(entity);
+        match visible {
+            true => {
+                if let Some(visual) = entity.get_mut::<Cullable>().and_then(|mut cullable| cullable.culled.take()) {
+                    entity.insert(visual);
+                }
+            },
+            false => {
+                if let Some(visual) = entity.remove::<Visual>() {
+                    if let Some(mut cullable) = entity.get_mut::<Cullable>() {
+                        cullable.culled = Some(visual);
+                    }
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{EventReader, EventWriter, With};
+use bevy_ecs::system::{Res, ResMut, Query};
+use serde::Deserialize;
+use dreamfield_renderer::components::TextBox;
+
+use super::dialogue::DialogueBranchCompleteEvent;
+use super::inventory::PlayerInventory;
+use super::player_movement::PlayerMovement;
+use super::portal::AreaTransitionEvent;
+use super::trigger::TriggerEnterEvent;
+use super::locale::Locale;
+
+/// A condition for starting a quest or completing one of its stages
+#[derive(Deserialize, Debug)]
+pub enum QuestCondition {
+    /// The player has at least this many of an item
+    CollectItem { item: String, count: u32 },
+    /// The player entered the TriggerVolume of an entity with a QuestTrigger with this name
+    EnterTrigger(String),
+    /// The player finished a dialogue tree's node, or any of its nodes if no node is given
+    TalkTo {
+        tree: String,
+        #[serde(default)]
+        node: Option<String>,
+    },
+    /// The player went through a portal to the anchor or level entrance with this name
+    Travel(String),
+}
+
+/// A stage of a quest, which is complete once all of its conditions have been met
+#[derive(Deserialize, Debug)]
+pub struct QuestStage {
+    /// The objective shown on the HUD while the stage is active
+    pub objective: String,
+    pub conditions: Vec<QuestCondition>,
+}
+
+/// A quest definition, loaded from a RON file
+#[derive(Deserialize, Debug)]
+pub struct QuestDef {
+    pub title: String,
+    /// The condition that starts the quest, or None to start it straight away
+    #[serde(default)]
+    pub start: Option<QuestCondition>,
+    pub stages: Vec<QuestStage>,
+}
+
+/// The quest manager, which stores all the quest definitions by name
+pub struct QuestManager {
+    quests: HashMap<String, QuestDef>,
+}
+
+impl QuestManager {
+    /// Create a new quest manager from a list of (name, ron source) pairs
+    pub fn new(quests: Vec<(&str, &str)>) -> Self {
+        let quests = quests.into_iter()
+            .filter_map(|(name, source)| {
+                match ron::from_str::<QuestDef>(source) {
+                    Ok(quest) => Some((name.to_string(), quest)),
+                    Err(err) => {
+                        log::error!("Failed to load quest {}: {}", name, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        QuestManager { quests }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&QuestDef> {
+        self.quests.get(name)
+    }
+}
+
+/// The QuestTrigger component, which names an entity's TriggerVolume for EnterTrigger conditions
+#[derive(Component)]
+pub struct QuestTrigger {
+    pub name: String,
+}
+
+impl QuestTrigger {
+    pub fn new(name: &str) -> Self {
+        QuestTrigger { name: name.to_string() }
+    }
+}
+
+/// The QuestTextBox component, for the TextBox the active objectives are listed in
+#[derive(Component)]
+pub struct QuestTextBox;
+
+/// The progress of an active quest
+pub struct QuestProgress {
+    pub quest: String,
+    pub stage: usize,
+    /// Whether each of the current stage's conditions has been met
+    conditions_met: Vec<bool>,
+}
+
+/// The QuestLog resource, with the player's active and completed quests
+#[derive(Default)]
+pub struct QuestLog {
+    active: Vec<QuestProgress>,
+    completed: Vec<String>,
+}
+
+impl QuestLog {
+    /// Get the active quests, in the order they were started
+    pub fn active(&self) -> &[QuestProgress] {
+        &self.active
+    }
+
+    pub fn is_active(&self, quest: &str) -> bool {
+        self.active.iter().any(|progress| progress.quest == quest)
+    }
+
+    pub fn is_complete(&self, quest: &str) -> bool {
+        self.completed.iter().any(|completed| completed == quest)
+    }
+
+    /// Get the current stage of an active quest
+    pub fn stage(&self, quest: &str) -> Option<usize> {
+        self.active.iter().find(|progress| progress.quest == quest).map(|progress| progress.stage)
+    }
+
+    /// Start a quest, if it exists and hasn't already been started
+    pub fn start(&mut self, manager: &QuestManager, quest: &str) -> bool {
+        let def = match manager.get(quest) {
+            Some(def) => def,
+            None => {
+                log::warn!("Tried to start unknown quest {}", quest);
+                return false;
+            }
+        };
+
+        if self.is_active(quest) || self.is_complete(quest) {
+            return false;
+        }
+
+        self.active.push(QuestProgress {
+            quest: quest.to_string(),
+            stage: 0,
+            conditions_met: stage_conditions(def, 0),
+        });
+
+        true
+    }
+}
+
+/// Get the unmet conditions for a stage of a quest
+fn stage_conditions(def: &QuestDef, stage: usize) -> Vec<bool> {
+    vec![false; def.stages.get(stage).map(|stage| stage.conditions.len()).unwrap_or(0)]
+}
+
+/// An event emitted when a quest is started or moves on to another stage
+pub struct QuestStageChangedEvent {
+    pub quest: String,
+    /// The new stage, which is the number of stages if the quest was just completed
+    pub stage: usize,
+    pub completed: bool,
+}
+
+/// What happened this frame, for checking quest conditions against
+struct QuestFacts<'a> {
+    triggers_entered: Vec<&'a str>,
+    nodes_completed: Vec<(&'a str, &'a str)>,
+    anchors_reached: Vec<&'a str>,
+    inventory: Option<&'a PlayerInventory>,
+}
+
+impl QuestCondition {
+    /// Update whether the condition has been met. Items can be lost again, so collecting them is
+    /// checked every frame, but triggers, dialogue and travel only need to have happened once.
+    fn update(&self, met: &mut bool, facts: &QuestFacts) {
+        match self {
+            QuestCondition::CollectItem { item, count } => {
+                *met = facts.inventory.map(|inventory| inventory.count(item) >= *count).unwrap_or(false);
+            },
+            QuestCondition::EnterTrigger(name) => {
+                *met |= facts.triggers_entered.contains(&name.as_str());
+            },
+            QuestCondition::TalkTo { tree, node } => {
+                *met |= facts.nodes_completed.iter().any(|(completed_tree, completed_node)| {
+                    completed_tree == tree && node.as_ref().map(|node| completed_node == node).unwrap_or(true)
+                });
+            },
+            QuestCondition::Travel(anchor) => {
+                *met |= facts.anchors_reached.contains(&anchor.as_str());
+            },
+        }
+    }
+}
+
+/// The quest system, which starts quests and advances their stages when their conditions are met
+pub fn update_quests(manager: Res<QuestManager>, mut quest_log: ResMut<QuestLog>,
+    mut trigger_events: EventReader<TriggerEnterEvent>, mut dialogue_events: EventReader<DialogueBranchCompleteEvent>,
+    mut transition_events: EventReader<AreaTransitionEvent>, mut stage_events: EventWriter<QuestStageChangedEvent>,
+    trigger_query: Query<&QuestTrigger>, player_query: Query<&PlayerInventory, With<PlayerMovement>>)
+{
+    let facts = QuestFacts {
+        triggers_entered: trigger_events.iter()
+            .filter_map(|event| trigger_query.get(event.trigger).ok())
+            .map(|trigger| trigger.name.as_str())
+            .collect(),
+        nodes_completed: dialogue_events.iter()
+            .map(|event| (event.tree.as_str(), event.node.as_str()))
+            .collect(),
+        anchors_reached: transition_events.iter()
+            .map(|event| event.anchor.as_str())
+            .collect(),
+        inventory: player_query.get_single().ok(),
+    };
+
+    // Start any quests whose start conditions were met
+    for (name, def) in manager.quests.iter() {
+        if quest_log.is_active(name) || quest_log.is_complete(name) {
+            continue;
+        }
+
+        let mut start = def.start.is_none();
+        if let Some(condition) = &def.start {
+            condition.update(&mut start, &facts);
+        }
+
+        if start && quest_log.start(&manager, name) {
+            stage_events.send(QuestStageChangedEvent { quest: name.clone(), stage: 0, completed: false });
+        }
+    }
+
+    // Advance the stages of the active quests
+    let quest_log = quest_log.as_mut();
+    let mut completed = Vec::new();
+    for progress in quest_log.active.iter_mut() {
+        let def = match manager.get(&progress.quest) {
+            Some(def) => def,
+            None => continue,
+        };
+
+        if let Some(stage) = def.stages.get(progress.stage) {
+            for (condition, met) in stage.conditions.iter().zip(progress.conditions_met.iter_mut()) {
+                condition.update(met, &facts);
+            }
+
+            if !progress.conditions_met.iter().all(|met| *met) {
+                continue;
+            }
+        }
+
+        progress.stage += 1;
+        progress.conditions_met = stage_conditions(def, progress.stage);
+
+        let is_complete = progress.stage >= def.stages.len();
+        if is_complete {
+            completed.push(progress.quest.clone());
+        }
+
+        stage_events.send(QuestStageChangedEvent {
+            quest: progress.quest.clone(),
+            stage: progress.stage,
+            completed: is_complete,
+        });
+    }
+
+    quest_log.active.retain(|progress| !completed.contains(&progress.quest));
+    quest_log.completed.extend(completed);
+}
+
+/// The quest HUD system, which lists the objectives of the active quests
+pub fn update_quest_hud(manager: Res<QuestManager>, quest_log: Res<QuestLog>, locale: Res<Locale>,
+    mut query: Query<&mut TextBox, With<QuestTextBox>>)
+{
+    let text = quest_log.active().iter()
+        .filter_map(|progress| {
+            let def = manager.get(&progress.quest)?;
+            let stage = def.stages.get(progress.stage)?;
+            Some(format!("{}\n- {}", locale.get(&def.title), locale.get(&stage.objective)))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    for mut text_box in query.iter_mut() {
+        if text_box.text != text {
+            text_box.text = text.clone();
+        }
+    }
+}
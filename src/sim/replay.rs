@@ -0,0 +1,225 @@
+use std::fs::File;
+use std::io::{LineWriter, Write};
+
+use bevy_ecs::system::{Res, ResMut};
+use cgmath::vec2;
+use serde::{Serialize, Deserialize};
+use dreamfield_system::resources::InputState;
+
+use super::gamepad::GamepadState;
+use super::input_bindings::EngineInput;
+use super::sim_settings::SimSettings;
+
+/// The default path input is recorded to
+pub const DEFAULT_RECORDING_PATH: &'static str = "replays/latest.ron";
+
+/// The sim configuration a replay was recorded with, which it needs to play back the same way
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplayHeader {
+    pub seed: u32,
+    pub tick_rate: i32,
+}
+
+/// The input for a single tick
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplayFrame {
+    pub engine: EngineInput,
+    /// The gamepad buttons held this tick and last tick, as masks of the tracked buttons
+    pub buttons: (u32, u32),
+    pub gamepad_movement: (f32, f32),
+    pub gamepad_look: (f32, f32),
+    /// The bound keyboard keys held this tick and last tick
+    #[serde(default)]
+    pub keys: (Vec<i32>, Vec<i32>),
+}
+
+/// Whether input is being recorded or played back
+pub enum ReplayMode {
+    Off,
+    Recording(LineWriter<File>),
+    Playing {
+        frames: Vec<ReplayFrame>,
+        next: usize,
+    },
+}
+
+/// The Replay resource, which records the input for every tick to a file, or plays back a
+/// recording in place of the live input. Replays are recorded in deterministic mode with a fixed
+/// seed, and the file starts with them so the sim can be set up the same way to play it back. The
+/// rest of the file is a line per tick with that tick's input.
+pub struct Replay {
+    pub mode: ReplayMode,
+}
+
+impl Replay {
+    /// Set up recording or playback from the command line arguments, with --record or
+    /// --record=<file> to record, and --replay <file> or --replay=<file> to play back. The sim
+    /// settings are changed to match the replay.
+    pub fn from_args(args: &[String], sim_settings: &mut SimSettings) -> Self {
+        let mut record_path = None;
+        let mut replay_path = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--record" {
+                record_path = Some(DEFAULT_RECORDING_PATH.to_string());
+            }
+            else if let Some(path) = arg.strip_prefix("--record=") {
+                record_path = Some(path.to_string());
+            }
+            else if arg == "--replay" {
+                replay_path = args.next().cloned();
+            }
+            else if let Some(path) = arg.strip_prefix("--replay=") {
+                replay_path = Some(path.to_string());
+            }
+        }
+
+        let mode = match (replay_path, record_path) {
+            (Some(path), _) => Self::load(&path, sim_settings),
+            (None, Some(path)) => Self::record(&path, sim_settings),
+            (None, None) => ReplayMode::Off,
+        };
+
+        Replay { mode }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.mode, ReplayMode::Playing { .. })
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.mode, ReplayMode::Recording(_))
+    }
+
+    /// Play back some recorded frames from the start, replacing any recording or playback
+    pub fn play(&mut self, frames: Vec<ReplayFrame>) {
+        self.mode = ReplayMode::Playing { frames, next: 0 };
+    }
+
+    /// Stop playing back, so the live input is used again from the next tick
+    pub fn stop(&mut self) {
+        if self.is_playing() {
+            self.mode = ReplayMode::Off;
+        }
+    }
+
+    /// Parse a recording into its header and frames
+    pub fn parse(source: &str) -> Result<(ReplayHeader, Vec<ReplayFrame>), String> {
+        let mut lines = source.lines();
+        let header = match lines.next().map(ron::from_str::<ReplayHeader>) {
+            Some(Ok(header)) => header,
+            Some(Err(err)) => return Err(format!("invalid header: {}", err)),
+            None => return Err("missing header".to_string()),
+        };
+
+        let frames: Result<Vec<ReplayFrame>, _> = lines.map(ron::from_str).collect();
+        frames.map(|frames| (header, frames)).map_err(|err| err.to_string())
+    }
+
+    /// Start recording to a file
+    fn record(path: &str, sim_settings: &mut SimSettings) -> ReplayMode {
+        let seed = sim_settings.seed.unwrap_or_else(|| sim_settings.create_rng().seed());
+        sim_settings.seed = Some(seed);
+        sim_settings.deterministic = true;
+
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+
+        let header = ReplayHeader { seed, tick_rate: sim_settings.tick_rate };
+        let result = File::create(path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                let mut writer = LineWriter::new(file);
+                let header = ron::to_string(&header).map_err(|err| err.to_string())?;
+                writeln!(writer, "{}", header).map_err(|err| err.to_string())?;
+                Ok(writer)
+            });
+
+        match result {
+            Ok(writer) => {
+                log::info!("Recording input to {}", path);
+                ReplayMode::Recording(writer)
+            },
+            Err(err) => {
+                log::error!("Failed to start recording input to {}: {}", path, err);
+                ReplayMode::Off
+            }
+        }
+    }
+
+    /// Load a recording to play back
+    fn load(path: &str, sim_settings: &mut SimSettings) -> ReplayMode {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                log::error!("Failed to read replay from {}: {}", path, err);
+                return ReplayMode::Off;
+            }
+        };
+
+        match Self::parse(&source) {
+            Ok((header, frames)) => {
+                log::info!("Playing back {} ticks of input from {}", frames.len(), path);
+                sim_settings.seed = Some(header.seed);
+                sim_settings.tick_rate = header.tick_rate;
+                sim_settings.deterministic = true;
+                ReplayMode::Playing { frames, next: 0 }
+            },
+            Err(err) => {
+                log::error!("Failed to parse replay from {}: {}", path, err);
+                ReplayMode::Off
+            }
+        }
+    }
+}
+
+/// The replay system, which runs after the gamepad is read and either records this tick's input,
+/// or replaces it with the next recorded tick
+pub fn update_replay(mut replay: ResMut<Replay>, input: Res<InputState>, mut gamepad: ResMut<GamepadState>) {
+    let stop = match &mut replay.mode {
+        ReplayMode::Off => false,
+        ReplayMode::Recording(writer) => {
+            let frame = ReplayFrame {
+                engine: EngineInput::capture(&input),
+                buttons: gamepad.button_masks(),
+                gamepad_movement: (gamepad.movement.x, gamepad.movement.y),
+                gamepad_look: (gamepad.look.x, gamepad.look.y),
+                keys: gamepad.key_sets(),
+            };
+
+            let result = ron::to_string(&frame)
+                .map_err(|err| err.to_string())
+                .and_then(|line| writeln!(writer, "{}", line).map_err(|err| err.to_string()));
+            match result {
+                Ok(_) => false,
+                Err(err) => {
+                    log::error!("Failed to record input, stopping recording: {}", err);
+                    true
+                }
+            }
+        },
+        ReplayMode::Playing { frames, next } => {
+            match frames.get(*next) {
+                Some(frame) => {
+                    gamepad.replayed_input = Some(frame.engine);
+                    gamepad.set_button_masks(frame.buttons.0, frame.buttons.1);
+                    gamepad.movement = vec2(frame.gamepad_movement.0, frame.gamepad_movement.1);
+                    gamepad.look = vec2(frame.gamepad_look.0, frame.gamepad_look.1);
+                    gamepad.set_key_sets(frame.keys.0.clone(), frame.keys.1.clone());
+                    *next += 1;
+                    false
+                },
+                None => {
+                    log::info!("Replay finished");
+                    gamepad.replayed_input = None;
+                    true
+                }
+            }
+        },
+    };
+
+    if stop {
+        replay.mode = ReplayMode::Off;
+    }
+}
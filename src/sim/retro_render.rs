@@ -0,0 +1,53 @@
+use bevy_ecs::system::{Res, Local};
+
+use super::uniform_buffer::{UniformBlock, UniformBuffer, RETRO_PARAMS_BINDING};
+
+/// The RetroRenderSettings resource, for dialing the ps1 shader's wobble from authentic to subtle
+pub struct RetroRenderSettings {
+    /// Whether vertices are snapped to a pixel grid
+    pub vertex_snap: bool,
+    /// The snapping grid's size relative to the render resolution, so 1.0 snaps to whole pixels and
+    /// larger values snap to a finer grid
+    pub vertex_snap_scale: f32,
+    /// How affine the texture mapping is, from 0 for perspective correct to 1 for fully affine
+    pub affine_amount: f32,
+}
+
+impl Default for RetroRenderSettings {
+    fn default() -> Self {
+        RetroRenderSettings {
+            vertex_snap: true,
+            vertex_snap_scale: 1.0,
+            affine_amount: 1.0,
+        }
+    }
+}
+
+/// The RetroParams uniform block, matching the std140 layout in retro_params.glsl
+#[repr(C)]
+pub struct RetroParams {
+    valid: f32,
+    vertex_snap_enabled: f32,
+    vertex_snap_scale: f32,
+    affine_amount: f32,
+}
+
+impl UniformBlock for RetroParams {
+    const BINDING: u32 = RETRO_PARAMS_BINDING;
+}
+
+/// The retro params system, which uploads the RetroRenderSettings to their uniform buffer when they
+/// change. It runs in the render schedule, since it needs the GL context.
+pub fn upload_retro_params(settings: Res<RetroRenderSettings>, mut buffer: Local<UniformBuffer<RetroParams>>) {
+    if buffer.is_uploaded() && !settings.is_changed() {
+        return;
+    }
+
+    let params = RetroParams {
+        valid: 1.0,
+        vertex_snap_enabled: if settings.vertex_snap { 1.0 } else { 0.0 },
+        vertex_snap_scale: f32::max(settings.vertex_snap_scale, 0.01),
+        affine_amount: f32::clamp(settings.affine_amount, 0.0, 1.0),
+    };
+    buffer.upload(&params);
+}
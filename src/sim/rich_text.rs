@@ -0,0 +1,547 @@
+use std::collections::HashMap;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Commands, Entity, EventReader, Without};
+use bevy_ecs::system::{Res, ResMut, Query, Local};
+use cgmath::{Vector2, Vector3, Vector4, vec2, vec3};
+use dreamfield_renderer::components::TextBox;
+use dreamfield_system::resources::SimTime;
+
+use super::locale::Locale;
+use super::ui_layout::{ResolutionChangedEvent, ui_size, UI_HEIGHT};
+use super::uniform_buffer::{UniformBlock, UniformBuffer, TEXT_STYLE_PARAMS_BINDING};
+
+/// The text shader rich text is drawn with, which colors it from the TextStyleParams block
+pub const RICH_TEXT_SHADER: &'static str = "rich_text";
+
+/// How many colored runs of text can be drawn at once, which must match MAX_TEXT_COLORS in
+/// text_style_params.glsl
+const MAX_TEXT_COLORS: usize = 64;
+
+/// The gap between lines of rich text, in UI units
+const LINE_SPACING: f32 = 2.0;
+
+/// How far shaking characters move from where they'd be, in UI units
+const SHAKE_DISTANCE: f32 = 1.0;
+
+/// How many times a second shaking characters move
+const SHAKE_RATE: f32 = 15.0;
+
+/// The widths of the glyphs in each font variant, which text is wrapped with
+struct GlyphMetrics {
+    widths: HashMap<char, f32>,
+    /// How much further along each pair of characters moves the second one
+    kerning: HashMap<(char, char), f32>,
+    height: f32,
+    /// The width of glyphs the font doesn't have
+    fallback_width: f32,
+}
+
+/// The FontMetrics resource, with the glyph sizes of each font variant, loaded from the same glyph
+/// maps as the FontManager
+pub struct FontMetrics {
+    variants: HashMap<(String, String), GlyphMetrics>,
+}
+
+impl FontMetrics {
+    /// Create the font metrics from a list of (font name, glyph map csv) pairs
+    pub fn new(fonts: Vec<(&str, &[u8])>) -> Self {
+        let mut variants: HashMap<(String, String), GlyphMetrics> = HashMap::new();
+
+        for (font, map) in fonts {
+            let map = String::from_utf8_lossy(map);
+
+            // Each line is the variant, codepoint, source x, source y, width and height, after a header
+            for line in map.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+                let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+                let glyph = match fields.as_slice() {
+                    [variant, codepoint, _, _, width, height] => codepoint.parse::<u32>().ok()
+                        .and_then(char::from_u32)
+                        .zip(width.parse::<f32>().ok())
+                        .zip(height.parse::<f32>().ok())
+                        .map(|((c, width), height)| (*variant, c, width, height)),
+                    _ => None,
+                };
+                let (variant, c, width, height) = match glyph {
+                    Some(glyph) => glyph,
+                    None => {
+                        log::warn!("Invalid glyph in the {} font map: {}", font, line);
+                        continue;
+                    }
+                };
+
+                let metrics = variants.entry((font.to_string(), variant.to_string()))
+                    .or_insert_with(|| GlyphMetrics {
+                        widths: HashMap::new(),
+                        kerning: HashMap::new(),
+                        height: 0.0,
+                        fallback_width: 0.0,
+                    });
+                metrics.widths.insert(c, width);
+                metrics.height = f32::max(metrics.height, height);
+            }
+        }
+
+        for metrics in variants.values_mut() {
+            metrics.fallback_width = metrics.widths.get(&' ').copied().unwrap_or(metrics.height);
+        }
+
+        FontMetrics { variants }
+    }
+
+    /// Add the kerning pairs for a font, from the lines of variant, first codepoint, second
+    /// codepoint and amount that build.rs bakes from BMFont files
+    pub fn add_kerning(&mut self, font: &str, kerning: &[u8]) {
+        let kerning = String::from_utf8_lossy(kerning);
+
+        for line in kerning.lines().filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let pair = match fields.as_slice() {
+                [variant, first, second, amount] => {
+                    let codepoint = |field: &str| field.parse::<u32>().ok().and_then(char::from_u32);
+                    codepoint(first).zip(codepoint(second))
+                        .zip(amount.parse::<f32>().ok())
+                        .map(|(pair, amount)| (*variant, pair, amount))
+                },
+                _ => None,
+            };
+
+            match pair.and_then(|(variant, pair, amount)| {
+                self.variants.get_mut(&(font.to_string(), variant.to_string())).map(|metrics| (metrics, pair, amount))
+            }) {
+                Some((metrics, pair, amount)) => {
+                    metrics.kerning.insert(pair, amount);
+                },
+                None => log::warn!("Invalid kerning pair for the {} font: {}", font, line),
+            }
+        }
+    }
+
+    fn get(&self, font: &str, variant: &str) -> Option<&GlyphMetrics> {
+        self.variants.get(&(font.to_string(), variant.to_string()))
+    }
+
+    /// Get how far a character moves the text along, in UI units
+    pub fn char_width(&self, font: &str, variant: &str, c: char) -> f32 {
+        self.get(font, variant)
+            .map_or(0.0, |metrics| metrics.widths.get(&c).copied().unwrap_or(metrics.fallback_width))
+    }
+
+    /// Get how much further along a character is moved when it follows another, in UI units
+    pub fn kerning(&self, font: &str, variant: &str, previous: char, c: char) -> f32 {
+        self.get(font, variant)
+            .and_then(|metrics| metrics.kerning.get(&(previous, c)).copied())
+            .unwrap_or(0.0)
+    }
+
+    /// Get the height of each line of text, in UI units
+    pub fn line_height(&self, font: &str, variant: &str) -> f32 {
+        self.get(font, variant).map_or(0.0, |metrics| metrics.height)
+    }
+}
+
+/// The style of a character of rich text, from the markup codes around it
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+struct TextStyle {
+    color: Option<Vector3<f32>>,
+    shake: bool,
+}
+
+/// Get a color for a color code, either by name or as a hex #rrggbb
+fn parse_color(color: &str) -> Option<Vector3<f32>> {
+    let named = match color {
+        "white" => Some(vec3(1.0, 1.0, 1.0)),
+        "grey" | "gray" => Some(vec3(0.5, 0.5, 0.5)),
+        "red" => Some(vec3(0.9, 0.15, 0.1)),
+        "green" => Some(vec3(0.2, 0.8, 0.2)),
+        "blue" => Some(vec3(0.3, 0.45, 1.0)),
+        "yellow" => Some(vec3(1.0, 0.85, 0.2)),
+        "orange" => Some(vec3(1.0, 0.55, 0.1)),
+        "purple" => Some(vec3(0.7, 0.35, 0.9)),
+        _ => None,
+    };
+
+    named.or_else(|| {
+        let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+        let rgb = u32::from_str_radix(hex, 16).ok()?;
+        let channel = |shift: u32| ((rgb >> shift) & 0xff) as f32 / 255.0;
+        Some(vec3(channel(16), channel(8), channel(0)))
+    })
+}
+
+/// Parse the markup codes out of rich text, giving each character with its style. The codes are
+/// {color=name} or {color=#rrggbb} up to {/color}, and {shake} up to {/shake}. A literal brace is
+/// written as {{, and anything else in braces is left in the text.
+fn parse_markup(markup: &str) -> Vec<(char, TextStyle)> {
+    let mut chars = Vec::with_capacity(markup.len());
+    let mut style = TextStyle::default();
+    let mut rest = markup;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("{{") {
+            chars.push(('{', style));
+            rest = after;
+            continue;
+        }
+
+        let code = match c {
+            '{' => rest.find('}').map(|end| (&rest[1..end], &rest[end + 1..])),
+            _ => None,
+        };
+        let applied = code.and_then(|(code, after)| {
+            match code {
+                "/color" => style.color = None,
+                "shake" => style.shake = true,
+                "/shake" => style.shake = false,
+                _ => style.color = Some(parse_color(code.strip_prefix("color=")?)?),
+            }
+            Some(after)
+        });
+
+        match applied {
+            Some(after) => rest = after,
+            None => {
+                if code.is_some() {
+                    log::warn!("Unknown markup code in text: {}", rest);
+                }
+                chars.push((c, style));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    chars
+}
+
+/// Word wrap text into lines no wider than a width, breaking words that are too long to fit on a
+/// line of their own. The spaces lines are broken at are left out.
+fn wrap_text(chars: &[(char, TextStyle)], max_width: f32, width_of: impl Fn(char) -> f32) -> Vec<Vec<(char, TextStyle)>> {
+    let mut lines = Vec::new();
+    let mut line: Vec<(char, TextStyle)> = Vec::new();
+    let mut line_width = 0.0;
+    let mut last_space = None;
+    let mut wrapped = false;
+
+    for &(c, style) in chars {
+        if c == '\n' {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0.0;
+            last_space = None;
+            wrapped = false;
+            continue;
+        }
+
+        let width = width_of(c);
+        if c != ' ' && line_width + width > max_width && !line.is_empty() {
+            let rest = match last_space {
+                Some(space) => {
+                    let rest = line.split_off(space + 1);
+                    line.pop();
+                    rest
+                },
+                None => Vec::new(),
+            };
+            lines.push(std::mem::replace(&mut line, rest));
+            line_width = line.iter().map(|(c, _)| width_of(*c)).sum();
+            last_space = None;
+            wrapped = true;
+        }
+
+        if c == ' ' {
+            if line.is_empty() && wrapped {
+                continue;
+            }
+            last_space = Some(line.len());
+        }
+
+        line.push((c, style));
+        line_width += width;
+    }
+
+    lines.push(line);
+    lines
+}
+
+/// The RichText component, for a TextBox that shows text with markup codes in it, word wrapped to
+/// its bounds and optionally revealed a character at a time. The text is drawn by a TextBox of its
+/// own for each run of characters with the same style, placed inside the entity's TextBox, which
+/// is left empty so that its bounds can still be laid out. The font is swapped for the one the
+/// language pack wants when the language changes.
+#[derive(Component)]
+pub struct RichText {
+    pub font: String,
+    pub font_variant: String,
+    markup: String,
+    /// How many characters are revealed per second, or None to show the whole text at once
+    pub chars_per_second: Option<f32>,
+    /// The number of characters in the text, not counting markup codes or line breaks
+    char_count: usize,
+    revealed: f32,
+    /// The entities for the runs of text, which are reused when the text changes
+    runs: Vec<Entity>,
+    /// The font the runs were made with
+    runs_font: String,
+}
+
+impl RichText {
+    pub fn new(font: &str, font_variant: &str) -> Self {
+        RichText {
+            font: font.to_string(),
+            font_variant: font_variant.to_string(),
+            markup: String::new(),
+            chars_per_second: None,
+            char_count: 0,
+            revealed: 0.0,
+            runs: Vec::new(),
+            runs_font: String::new(),
+        }
+    }
+
+    /// Reveal the text a character at a time, like a typewriter
+    pub fn with_typewriter(mut self, chars_per_second: f32) -> Self {
+        self.chars_per_second = Some(chars_per_second);
+        self
+    }
+
+    pub fn markup(&self) -> &str {
+        &self.markup
+    }
+
+    /// Set the text, which starts being revealed again if it's different
+    pub fn set_markup(&mut self, markup: &str) {
+        if self.markup != markup {
+            self.markup = markup.to_string();
+            self.char_count = parse_markup(markup).iter().filter(|(c, _)| *c != '\n').count();
+            self.revealed = 0.0;
+        }
+    }
+
+    /// Check whether the whole text has been revealed
+    pub fn is_revealed(&self) -> bool {
+        self.chars_per_second.is_none() || self.revealed >= self.char_count as f32
+    }
+
+    /// Show the rest of the text straight away
+    pub fn reveal_all(&mut self) {
+        self.revealed = self.char_count as f32;
+    }
+
+    /// Get the number of characters that are shown
+    fn visible_chars(&self) -> usize {
+        match self.is_revealed() {
+            true => self.char_count,
+            false => self.revealed as usize,
+        }
+    }
+}
+
+/// The RichTextRun component, for a TextBox drawing a run of a RichText's characters
+#[derive(Component)]
+pub struct RichTextRun {
+    owner: Entity,
+}
+
+/// A run of characters with the same style, laid out on a line
+struct LaidOutRun {
+    text: String,
+    bounds: Vector4<f32>,
+    color: Option<Vector3<f32>>,
+}
+
+/// The TextStyles resource, with the colored runs of rich text that are shown
+#[derive(Default)]
+pub struct TextStyles {
+    /// The bounds of each run in UI space, and its color
+    colors: Vec<(Vector4<f32>, Vector3<f32>)>,
+    ui_size: Vector2<f32>,
+}
+
+/// Lay out rich text in its bounds, splitting it into runs wherever the style changes, and giving
+/// each shaking character a run of its own so it can move by itself. Text boxes space their glyphs
+/// by their widths, so kerned characters start new runs too. Lines are wrapped without kerning,
+/// so lines with a lot of positive kerning can run a little past their bounds.
+fn layout_rich_text(rich_text: &RichText, font: &str, bounds: Option<Vector4<f32>>, metrics: &FontMetrics,
+    time: f32) -> Vec<LaidOutRun>
+{
+    let variant = rich_text.font_variant.as_str();
+    let width_of = |c| metrics.char_width(font, variant, c);
+    let line_height = metrics.line_height(font, variant);
+
+    let bounds = bounds.unwrap_or(Vector4::new(0.0, 0.0, f32::INFINITY, f32::INFINITY));
+    let chars = parse_markup(&rich_text.markup);
+    let lines = wrap_text(&chars, bounds.z - bounds.x, width_of);
+
+    let mut runs: Vec<LaidOutRun> = Vec::new();
+    let mut remaining = rich_text.visible_chars();
+    let mut shaking = 0;
+
+    for (line_index, line) in lines.iter().enumerate() {
+        let y = bounds.y + line_index as f32 * (line_height + LINE_SPACING);
+        let mut x = bounds.x;
+        let mut last_style = None;
+        let mut last_char = None;
+
+        for &(c, style) in line.iter().take(remaining) {
+            let width = width_of(c);
+            let kerning = last_char.map_or(0.0, |previous| metrics.kerning(font, variant, previous, c));
+            x += kerning;
+
+            // Each shaking character jumps to a new offset a few times a second
+            let offset = match style.shake {
+                true => {
+                    shaking += 1;
+                    let phase = (time * SHAKE_RATE).floor() + shaking as f32 * 7.3;
+                    vec2((phase * 12.9898).sin(), (phase * 78.233).sin()) * SHAKE_DISTANCE
+                },
+                false => vec2(0.0, 0.0),
+            };
+
+            let continues_run = last_style == Some(style) && !style.shake && kerning == 0.0;
+            match (continues_run, runs.last_mut()) {
+                (true, Some(run)) => {
+                    run.text.push(c);
+                    run.bounds.z += width;
+                },
+                _ => runs.push(LaidOutRun {
+                    text: c.to_string(),
+                    bounds: Vector4::new(x + offset.x.round(), y + offset.y.round(),
+                        x + offset.x.round() + width, y + offset.y.round() + line_height),
+                    color: style.color,
+                }),
+            }
+
+            x += width;
+            last_style = Some(style);
+            last_char = Some(c);
+        }
+
+        remaining = remaining.saturating_sub(line.len());
+    }
+
+    runs
+}
+
+/// The rich text system, which reveals typewriter text, lays out each RichText, and updates the
+/// TextBoxes drawing its runs. The runs are only changed when their text or bounds do, so that the
+/// renderer doesn't have to draw them again every frame.
+pub fn update_rich_text(mut commands: Commands, sim_time: Res<SimTime>, metrics: Res<FontMetrics>, locale: Res<Locale>,
+    mut styles: ResMut<TextStyles>, mut ui_size_state: Local<Option<Vector2<f32>>>,
+    mut resolution_events: EventReader<ResolutionChangedEvent>,
+    mut owners: Query<(Entity, &mut RichText, &TextBox), Without<RichTextRun>>,
+    mut runs: Query<(Entity, &RichTextRun, &mut TextBox)>)
+{
+    for event in resolution_events.iter() {
+        *ui_size_state = Some(ui_size(event.render_res));
+    }
+
+    let time_delta = sim_time.sim_time_delta as f32;
+    let time = sim_time.sim_time as f32;
+    let mut colors = Vec::new();
+
+    for (entity, mut rich_text, owner_box) in owners.iter_mut() {
+        if let Some(chars_per_second) = rich_text.chars_per_second.filter(|_| !rich_text.is_revealed()) {
+            rich_text.revealed += chars_per_second * time_delta;
+        }
+
+        // The runs are made again in the new font when the language changes it
+        let font = locale.font(&rich_text.font).to_string();
+        if rich_text.runs_font != font {
+            for run_entity in rich_text.runs.drain(..) {
+                commands.entity(run_entity).despawn();
+            }
+            rich_text.runs_font = font.clone();
+        }
+
+        let laid_out = layout_rich_text(&rich_text, &font, owner_box.bounds, &metrics, time);
+
+        for (index, run) in laid_out.iter().enumerate() {
+            if let Some(color) = run.color {
+                colors.push((run.bounds, color));
+            }
+
+            match rich_text.runs.get(index).and_then(|run_entity| runs.get_mut(*run_entity).ok()) {
+                Some((_, _, mut text_box)) => {
+                    if text_box.text != run.text {
+                        text_box.text = run.text.clone();
+                    }
+                    if text_box.bounds != Some(run.bounds) {
+                        text_box.bounds = Some(run.bounds);
+                    }
+                },
+                // Runs that were spawned this frame are filled in next frame
+                None if index < rich_text.runs.len() => (),
+                None => {
+                    let run_entity = commands.spawn()
+                        .insert(RichTextRun { owner: entity })
+                        .insert(TextBox::new(RICH_TEXT_SHADER, &font, &rich_text.font_variant,
+                            &run.text, None, Some(run.bounds)))
+                        .id();
+                    rich_text.runs.push(run_entity);
+                },
+            }
+        }
+
+        // Runs that aren't needed any more are kept for when the text gets longer again
+        for run_entity in rich_text.runs.iter().skip(laid_out.len()) {
+            if let Ok((_, _, mut text_box)) = runs.get_mut(*run_entity) {
+                if !text_box.text.is_empty() {
+                    text_box.text.clear();
+                }
+            }
+        }
+    }
+
+    for (entity, run, _) in runs.iter() {
+        if owners.get(run.owner).is_err() {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    let ui_size = ui_size_state.unwrap_or(vec2(UI_HEIGHT * 4.0 / 3.0, UI_HEIGHT));
+    if styles.colors != colors || styles.ui_size != ui_size {
+        styles.colors = colors;
+        styles.ui_size = ui_size;
+    }
+}
+
+/// The TextStyleParams uniform block, matching the std140 layout in text_style_params.glsl
+#[repr(C)]
+pub struct TextStyleParams {
+    count: [f32; 4],
+    /// The bounds of each colored run in normalized device coordinates
+    rects: [[f32; 4]; MAX_TEXT_COLORS],
+    colors: [[f32; 4]; MAX_TEXT_COLORS],
+}
+
+impl UniformBlock for TextStyleParams {
+    const BINDING: u32 = TEXT_STYLE_PARAMS_BINDING;
+}
+
+/// The text style params system, which uploads the colored runs of text to their uniform buffer
+/// when they change. It runs in the render schedule, since it needs the GL context.
+pub fn upload_text_style_params(styles: Res<TextStyles>, mut buffer: Local<UniformBuffer<TextStyleParams>>) {
+    if buffer.is_uploaded() && !styles.is_changed() {
+        return;
+    }
+
+    if styles.colors.len() > MAX_TEXT_COLORS {
+        log::warn!("Only the first {} colored runs of text can be drawn", MAX_TEXT_COLORS);
+    }
+
+    let mut params = TextStyleParams {
+        count: [usize::min(styles.colors.len(), MAX_TEXT_COLORS) as f32, 0.0, 0.0, 0.0],
+        rects: [[0.0; 4]; MAX_TEXT_COLORS],
+        colors: [[0.0; 4]; MAX_TEXT_COLORS],
+    };
+
+    // The UI space's y axis points down, but normalized device coordinates' points up
+    let ui_size = styles.ui_size;
+    let to_ndc = |x: f32, y: f32| (x / ui_size.x * 2.0 - 1.0, 1.0 - y / ui_size.y * 2.0);
+    for (i, (bounds, color)) in styles.colors.iter().take(MAX_TEXT_COLORS).enumerate() {
+        let (min_x, max_y) = to_ndc(bounds.x, bounds.y);
+        let (max_x, min_y) = to_ndc(bounds.z, bounds.w);
+        params.rects[i] = [min_x, min_y, max_x, max_y];
+        params.colors[i] = [color.x, color.y, color.z, 1.0];
+    }
+    buffer.upload(&params);
+}
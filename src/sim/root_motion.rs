@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, Zero, vec3};
+use dreamfield_system::components::Transform;
+use dreamfield_system::intersection::Collider;
+use dreamfield_system::resources::SimTime;
+
+use super::anim_state::AnimStateMachine;
+use super::character_controller::CharacterController;
+use super::static_collision::StaticCollision;
+
+/// The translation keys of a clip's root bone
+pub struct RootMotionClip {
+    pub length: f32,
+    keys: Vec<(f32, Vector3<f32>)>,
+}
+
+impl RootMotionClip {
+    /// Sample the root translation at a time, which should be within the clip
+    pub fn sample(&self, time: f32) -> Vector3<f32> {
+        let next = self.keys.iter().position(|(key_time, _)| *key_time > time);
+        match next {
+            Some(0) => self.keys[0].1,
+            Some(next) => {
+                let (start_time, start) = self.keys[next - 1];
+                let (end_time, end) = self.keys[next];
+                let t = (time - start_time) / (end_time - start_time);
+                start + (end - start) * t
+            },
+            None => self.keys.last().map(|(_, translation)| *translation).unwrap_or(Vector3::zero()),
+        }
+    }
+
+    /// Get how far the root moves between two times since the clip started, counting whole loops of
+    /// the clip in between
+    pub fn delta(&self, from: f32, to: f32) -> Vector3<f32> {
+        if self.length <= 0.0 || self.keys.is_empty() {
+            return Vector3::zero();
+        }
+
+        let loop_delta = self.sample(self.length) - self.sample(0.0);
+        let loops = (to / self.length).floor() - (from / self.length).floor();
+        self.sample(to % self.length) - self.sample(from % self.length) + loop_delta * loops
+    }
+}
+
+/// The RootMotionClips resource, with the root bone translation of each model's clips as extracted
+/// by the build script
+pub struct RootMotionClips {
+    models: HashMap<String, HashMap<String, RootMotionClip>>,
+}
+
+impl RootMotionClips {
+    /// Load the root motion for a list of (model name, baked root motion) pairs
+    pub fn new(models: Vec<(&str, &str)>) -> Self {
+        let models = models.into_iter()
+            .map(|(name, source)| (name.to_string(), Self::parse(name, source)))
+            .collect();
+
+        RootMotionClips { models }
+    }
+
+    pub fn get(&self, model: &str, clip: &str) -> Option<&RootMotionClip> {
+        self.models.get(model).and_then(|clips| clips.get(clip))
+    }
+
+    /// Parse the clips baked for a model, which are each a line with the clip's name, length and
+    /// key count, followed by a line per key with its time and translation
+    fn parse(name: &str, source: &str) -> HashMap<String, RootMotionClip> {
+        let mut clips = HashMap::new();
+        let mut lines = source.lines();
+
+        while let Some(header) = lines.next() {
+            let header: Vec<&str> = header.split_whitespace().collect();
+            let (clip, length, count) = match header.as_slice() {
+                [clip, length, count] => match (length.parse::<f32>(), count.parse::<usize>()) {
+                    (Ok(length), Ok(count)) => (clip.to_string(), length, count),
+                    _ => {
+                        log::error!("Invalid root motion clip header for model {}", name);
+                        break;
+                    }
+                },
+                _ => {
+                    log::error!("Invalid root motion clip header for model {}", name);
+                    break;
+                }
+            };
+
+            let keys: Vec<(f32, Vector3<f32>)> = lines.by_ref().take(count)
+                .filter_map(|line| {
+                    let values: Vec<f32> = line.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+                    match values.as_slice() {
+                        [time, x, y, z] => Some((*time, vec3(*x, *y, *z))),
+                        _ => None,
+                    }
+                })
+                .collect();
+            if keys.len() != count {
+                log::error!("Invalid root motion keys for clip {} of model {}", clip, name);
+            }
+
+            clips.insert(clip, RootMotionClip { length, keys });
+        }
+
+        clips
+    }
+}
+
+/// The root motion system, which moves entities whose animation state uses root motion by however
+/// far their clip's root bone moved since the last tick. Only the horizontal movement is applied,
+/// so characters can still fall and walk up slopes, and entities with a CharacterController and
+/// Collider collide with the world. The renderer still draws the clip's root translation, so
+/// clips used with root motion should be exported with the root moving in place.
+pub fn apply_root_motion(sim_time: Res<SimTime>, clips: Res<RootMotionClips>, static_collision: Res<StaticCollision>,
+    mut query: Query<(Entity, &AnimStateMachine, &mut Transform, Option<&CharacterController>, Option<&Collider>)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    if time_delta <= 0.0 {
+        return;
+    }
+
+    for (entity, machine, mut transform, controller, collider) in query.iter_mut() {
+        let (clip, from, to) = match machine.root_motion_window() {
+            Some(window) => window,
+            None => continue,
+        };
+        let clip = match machine.model.as_ref().and_then(|model| clips.get(model, clip)) {
+            Some(clip) => clip,
+            None => continue,
+        };
+
+        let delta = clip.delta(from, to);
+        let movement = transform.rot * vec3(delta.x, 0.0, delta.z);
+
+        transform.pos = match (controller, collider) {
+            (Some(controller), Some(collider)) => controller.move_and_slide(&static_collision, collider,
+                transform.pos, movement / time_delta, true, entity, time_delta),
+            _ => transform.pos + movement,
+        };
+    }
+}
@@ -0,0 +1,114 @@
+use bevy_ecs::world::World;
+use cgmath::{Vector2, Vector3, vec2, vec3, Matrix3, SquareMatrix, Rad};
+use dreamfield_system::components::Transform;
+
+use super::light::LightSource;
+use super::portal::PortalAnchor;
+use super::quest::QuestTrigger;
+use super::scripting::ScriptName;
+use super::trigger::TriggerVolume;
+
+/// The color of lights placed in world models
+const POINT_LIGHT_COLOR: Vector3<f32> = vec3(1.0, 0.8, 0.6);
+
+/// What a point entity from a world model is for
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScenePointKind {
+    /// A spawn point, which is added to the SpawnPoints
+    Spawn,
+    /// A portal anchor
+    Anchor,
+    /// A point light, with its radius given by the empty's largest scale
+    Light,
+    /// A TriggerVolume filling the empty's box, with a QuestTrigger and ScriptName so quests and
+    /// scripts can tell when the player enters it. The box is axis aligned, so the empty's rotation
+    /// is ignored.
+    Trigger,
+}
+
+/// A point entity exported from a world model's empties by build.rs, with the name the empty had
+/// after its prefix
+#[derive(Clone, Debug)]
+pub struct ScenePoint {
+    pub kind: ScenePointKind,
+    pub name: String,
+    pub pos: Vector3<f32>,
+    pub forward: Vector3<f32>,
+    /// The half extents of the empty's box
+    pub extents: Vector3<f32>,
+}
+
+impl ScenePoint {
+    /// Get the pitch and yaw to put the player at to face the way the point faces
+    pub fn pitch_yaw(&self) -> Vector2<f32> {
+        vec2(0.0, f32::atan2(-self.forward.x, -self.forward.z))
+    }
+
+    /// Parse the points build.rs exported for a world model, skipping any lines it can't read
+    pub fn parse_all(source: &str) -> Vec<ScenePoint> {
+        source.lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                let point = Self::parse(line);
+                if point.is_none() {
+                    log::warn!("Failed to parse scene point: {}", line);
+                }
+                point
+            })
+            .collect()
+    }
+
+    /// Parse a line with a point's kind, name, position, forward direction and extents
+    fn parse(line: &str) -> Option<ScenePoint> {
+        let mut fields = line.split_whitespace();
+        let kind = match fields.next()? {
+            "spawn" => ScenePointKind::Spawn,
+            "anchor" => ScenePointKind::Anchor,
+            "light" => ScenePointKind::Light,
+            "trigger" => ScenePointKind::Trigger,
+            _ => return None,
+        };
+        let name = fields.next()?.to_string();
+
+        let values: Vec<f32> = fields.map(str::parse).collect::<Result<_, _>>().ok()?;
+        match values[..] {
+            [x, y, z, fx, fy, fz, ex, ey, ez] => Some(ScenePoint {
+                kind,
+                name,
+                pos: vec3(x, y, z),
+                forward: vec3(fx, fy, fz),
+                extents: vec3(ex, ey, ez),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Spawn the entities for a level's points. Spawn points aren't entities, so they're skipped.
+pub fn spawn_scene_points(world: &mut World, points: &[ScenePoint]) {
+    for point in points {
+        let transform = Transform::new(point.pos, Matrix3::identity());
+        match point.kind {
+            ScenePointKind::Spawn => (),
+            ScenePointKind::Anchor => {
+                let yaw = point.pitch_yaw().y;
+                world.spawn()
+                    .insert(PortalAnchor::new(&point.name))
+                    .insert(Transform::new(point.pos, Matrix3::from_angle_y(Rad(yaw))));
+            },
+            ScenePointKind::Light => {
+                let radius = f32::max(point.extents.x, f32::max(point.extents.y, point.extents.z));
+                world.spawn()
+                    .insert(LightSource::new(POINT_LIGHT_COLOR, radius, 1.0))
+                    .insert(transform);
+            },
+            ScenePointKind::Trigger => {
+                world.spawn()
+                    .insert(QuestTrigger::new(&point.name))
+                    .insert(ScriptName::new(&point.name))
+                    .insert(TriggerVolume::new_aabb(-point.extents, point.extents))
+                    .insert(transform);
+            },
+        }
+    }
+}
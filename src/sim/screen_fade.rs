@@ -0,0 +1,151 @@
+use bevy_ecs::system::{Res, ResMut, Local};
+use cgmath::{Vector3, Zero};
+use dreamfield_system::resources::SimTime;
+
+use super::game_state::GameState;
+use super::uniform_buffer::{UniformBlock, UniformBuffer, FADE_PARAMS_BINDING};
+
+/// The ScreenFade resource, for fading the whole screen out to a color and back in, and for the
+/// letterbox bars cutscenes are shown with. The fades are used by portal transitions, deaths and
+/// anything else that needs to hide the world for a moment, and are drawn over the scene by the
+/// screen_fade ScreenEffect, which gets them from a uniform buffer. Anything still faded out when
+/// the game goes back to the title screen is cleared.
+pub struct ScreenFade {
+    color: Vector3<f32>,
+    /// How far faded out the screen is, from 0 to 1
+    fade: f32,
+    /// What the fade is heading towards
+    target: f32,
+    /// How fast the fade moves, in units per second
+    speed: f32,
+    letterbox: bool,
+    /// How far the letterbox bars are in, from 0 to 1
+    letterbox_amount: f32,
+    /// How much of the screen's height each letterbox bar covers
+    pub letterbox_height: f32,
+    /// How long the letterbox bars take to slide in or out, in seconds
+    pub letterbox_time: f32,
+}
+
+impl Default for ScreenFade {
+    fn default() -> Self {
+        ScreenFade {
+            color: Vector3::zero(),
+            fade: 0.0,
+            target: 0.0,
+            speed: 0.0,
+            letterbox: false,
+            letterbox_amount: 0.0,
+            letterbox_height: 0.12,
+            letterbox_time: 0.5,
+        }
+    }
+}
+
+impl ScreenFade {
+    /// Start fading the screen out to a color, from however far faded out it is already
+    pub fn fade_out(&mut self, duration: f32, color: Vector3<f32>) {
+        self.color = color;
+        self.fade_to(1.0, duration);
+    }
+
+    /// Start fading the screen back in from the color it faded out to
+    pub fn fade_in(&mut self, duration: f32) {
+        self.fade_to(0.0, duration);
+    }
+
+    fn fade_to(&mut self, target: f32, duration: f32) {
+        self.target = target;
+        self.speed = match duration > 0.0 {
+            true => 1.0 / duration,
+            false => f32::INFINITY,
+        };
+    }
+
+    /// Show or hide the letterbox bars, which slide in and out
+    pub fn set_letterbox(&mut self, letterbox: bool) {
+        self.letterbox = letterbox;
+    }
+
+    pub fn letterbox(&self) -> bool {
+        self.letterbox
+    }
+
+    /// Get how far faded out the screen is, from 0 to 1
+    pub fn fade(&self) -> f32 {
+        self.fade
+    }
+
+    /// Check whether the screen has finished fading out
+    pub fn is_faded_out(&self) -> bool {
+        self.target == 1.0 && self.fade == 1.0
+    }
+
+    /// Check whether a fade is still going
+    pub fn is_fading(&self) -> bool {
+        self.fade != self.target
+    }
+
+    /// Clear the fade and the letterbox straight away
+    pub fn clear(&mut self) {
+        self.fade = 0.0;
+        self.target = 0.0;
+        self.letterbox = false;
+        self.letterbox_amount = 0.0;
+    }
+}
+
+/// The screen fade system, which moves the fade and the letterbox bars along. It's a UI system so
+/// that fades carry on while the game's loading or paused.
+pub fn update_screen_fade(sim_time: Res<SimTime>, state: Res<GameState>, mut screen_fade: ResMut<ScreenFade>) {
+    if state.is_changed() && *state == GameState::Title {
+        screen_fade.clear();
+        return;
+    }
+
+    let time_delta = sim_time.sim_time_delta as f32;
+    let letterbox_target = match screen_fade.letterbox {
+        true => 1.0,
+        false => 0.0,
+    };
+    if !screen_fade.is_fading() && screen_fade.letterbox_amount == letterbox_target {
+        return;
+    }
+
+    let step = screen_fade.speed * time_delta;
+    screen_fade.fade += f32::clamp(screen_fade.target - screen_fade.fade, -step, step);
+
+    let step = match screen_fade.letterbox_time > 0.0 {
+        true => time_delta / screen_fade.letterbox_time,
+        false => 1.0,
+    };
+    screen_fade.letterbox_amount += f32::clamp(letterbox_target - screen_fade.letterbox_amount, -step, step);
+}
+
+/// The FadeParams uniform block, matching the std140 layout in fade_params.glsl
+#[repr(C)]
+pub struct FadeParams {
+    fade_color: [f32; 4],
+    letterbox: [f32; 4],
+}
+
+impl UniformBlock for FadeParams {
+    const BINDING: u32 = FADE_PARAMS_BINDING;
+}
+
+/// The fade params system, which uploads the fade to its uniform buffer when it changes. It runs in
+/// the render schedule, since it needs the GL context.
+pub fn upload_fade_params(screen_fade: Res<ScreenFade>, mut buffer: Local<UniformBuffer<FadeParams>>) {
+    if buffer.is_uploaded() && !screen_fade.is_changed() {
+        return;
+    }
+
+    // The letterbox bars ease in and out
+    let color = screen_fade.color;
+    let amount = screen_fade.letterbox_amount;
+    let params = FadeParams {
+        fade_color: [color.x, color.y, color.z, f32::clamp(screen_fade.fade, 0.0, 1.0)],
+        letterbox: [screen_fade.letterbox_height * amount * amount * (3.0 - 2.0 * amount), 0.0, 0.0, 0.0],
+    };
+    buffer.upload(&params);
+}
@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventReader};
+use bevy_ecs::system::{Res, ResMut, Query, Commands, NonSendMut};
+use cgmath::{Matrix3, SquareMatrix, vec3};
+use mlua::{Lua, Table, Function};
+use dreamfield_renderer::components::Visual;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::dialogue::{DialogueManager, DialogueState, DialogueBranchCompleteEvent};
+use super::portal::AreaTransitionEvent;
+use super::trigger::{TriggerEnterEvent, TriggerExitEvent};
+use super::weather::{Weather, WeatherKind};
+
+/// The directory scripts are loaded from at startup
+pub const SCRIPTS_PATH: &'static str = "resources/scripts";
+
+/// The part of the scripting API that's written in lua, which stores the event handlers
+const SCRIPT_PRELUDE: &'static str = r#"
+game = { handlers = {} }
+
+-- Register a function to be called with an event's table whenever it happens
+function game.on(event, handler)
+    game.handlers[event] = game.handlers[event] or {}
+    table.insert(game.handlers[event], handler)
+end
+"#;
+
+/// The ScriptName component, which names an entity so scripts can find it and tell triggers apart
+#[derive(Component)]
+pub struct ScriptName {
+    pub name: String,
+}
+
+impl ScriptName {
+    pub fn new(name: &str) -> Self {
+        ScriptName { name: name.to_string() }
+    }
+}
+
+/// The ScriptEngine resource, a lua state with all the scripts in the scripts directory loaded.
+/// Scripts register handlers with `game.on(event, handler)` when they're loaded, and the rest of
+/// the `game` API is only available from inside handlers:
+///
+/// * `game.spawn(visual, x, y, z)` spawns an entity with a Visual and returns its id
+/// * `game.despawn(id)`
+/// * `game.find(name)` gets the id of an entity with a ScriptName
+/// * `game.position(id)` returns an entity's x, y and z
+/// * `game.set_position(id, x, y, z)`
+/// * `game.dialogue(id, tree)` starts a conversation with an entity
+/// * `game.weather()` gets the name of the weather, `clear`, `rain`, `fog` or `storm`
+/// * `game.set_weather(name, automatic)` changes the weather, and whether it keeps changing by
+///   itself afterwards
+/// * `game.log(message)`
+///
+/// The events are `update` (with `dt`), `trigger_enter` and `trigger_exit` (with the `trigger` id
+/// and its `name`), `dialogue_complete` (with the `speaker` id, `tree`, `node` and `choice`), and
+/// `area_transition` (with the `from_level`, `to_level` and `anchor` of a portal the player used).
+///
+/// Lua states aren't Send, so it's a non-send resource.
+pub struct ScriptEngine {
+    lua: Lua,
+}
+
+impl ScriptEngine {
+    /// Create the lua state and load every .lua file in a directory, in name order
+    pub fn load(path: &str) -> Self {
+        let lua = Lua::new();
+        if let Err(err) = lua.load(SCRIPT_PRELUDE).exec() {
+            log::error!("Failed to load script prelude: {}", err);
+        }
+
+        let mut paths: Vec<_> = match std::fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map(|ext| ext == "lua").unwrap_or(false))
+                .collect(),
+            Err(err) => {
+                log::warn!("Failed to read scripts from {}: {}", path, err);
+                Vec::new()
+            }
+        };
+        paths.sort();
+
+        for path in paths {
+            let result = std::fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|source| {
+                    lua.load(&source)
+                        .set_name(path.to_string_lossy().as_bytes())
+                        .and_then(|chunk| chunk.exec())
+                        .map_err(|err| err.to_string())
+                });
+
+            match result {
+                Ok(_) => log::info!("Loaded script {}", path.display()),
+                Err(err) => log::error!("Failed to load script {}: {}", path.display(), err),
+            }
+        }
+
+        ScriptEngine { lua }
+    }
+}
+
+/// An event passed to script handlers
+enum ScriptEvent {
+    Update { dt: f32 },
+    TriggerEnter { trigger: Entity, name: Option<String> },
+    TriggerExit { trigger: Entity, name: Option<String> },
+    DialogueComplete { speaker: Entity, tree: String, node: String, choice: Option<usize> },
+    AreaTransition { from_level: Option<String>, to_level: Option<String>, anchor: String },
+}
+
+impl ScriptEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            ScriptEvent::Update { .. } => "update",
+            ScriptEvent::TriggerEnter { .. } => "trigger_enter",
+            ScriptEvent::TriggerExit { .. } => "trigger_exit",
+            ScriptEvent::DialogueComplete { .. } => "dialogue_complete",
+            ScriptEvent::AreaTransition { .. } => "area_transition",
+        }
+    }
+
+    /// Build the table passed to the event's handlers
+    fn to_table<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Table<'lua>> {
+        let table = lua.create_table()?;
+        match self {
+            ScriptEvent::Update { dt } => table.set("dt", *dt)?,
+            ScriptEvent::TriggerEnter { trigger, name } | ScriptEvent::TriggerExit { trigger, name } => {
+                table.set("trigger", entity_id(*trigger))?;
+                table.set("name", name.clone())?;
+            },
+            ScriptEvent::DialogueComplete { speaker, tree, node, choice } => {
+                table.set("speaker", entity_id(*speaker))?;
+                table.set("tree", tree.clone())?;
+                table.set("node", node.clone())?;
+                // Lua's indices start from 1
+                table.set("choice", choice.map(|choice| choice + 1))?;
+            },
+            ScriptEvent::AreaTransition { from_level, to_level, anchor } => {
+                table.set("from_level", from_level.clone())?;
+                table.set("to_level", to_level.clone())?;
+                table.set("anchor", anchor.clone())?;
+            },
+        }
+        Ok(table)
+    }
+}
+
+/// Get the id scripts use for an entity
+fn entity_id(entity: Entity) -> i64 {
+    entity.to_bits() as i64
+}
+
+fn entity_from_id(id: i64) -> Entity {
+    Entity::from_bits(id as u64)
+}
+
+/// The scripting system, which passes this frame's events to the scripts' handlers. The game API
+/// is bound for the duration of the handlers, using a lua scope so that it can borrow the system's
+/// parameters.
+pub fn run_scripts(engine: NonSendMut<ScriptEngine>, sim_time: Res<SimTime>, commands: Commands,
+    dialogue_manager: Res<DialogueManager>, dialogue: ResMut<DialogueState>,
+    mut trigger_enter_events: EventReader<TriggerEnterEvent>, mut trigger_exit_events: EventReader<TriggerExitEvent>,
+    mut dialogue_events: EventReader<DialogueBranchCompleteEvent>,
+    mut transition_events: EventReader<AreaTransitionEvent>, weather: ResMut<Weather>,
+    names: Query<(Entity, &ScriptName)>, transforms: Query<&mut Transform>)
+{
+    let trigger_name = |trigger: Entity| names.get(trigger).ok().map(|(_, name)| name.name.clone());
+
+    let mut events = vec![ScriptEvent::Update { dt: sim_time.sim_time_delta as f32 }];
+    events.extend(trigger_enter_events.iter()
+        .map(|event| ScriptEvent::TriggerEnter { trigger: event.trigger, name: trigger_name(event.trigger) }));
+    events.extend(trigger_exit_events.iter()
+        .map(|event| ScriptEvent::TriggerExit { trigger: event.trigger, name: trigger_name(event.trigger) }));
+    events.extend(dialogue_events.iter()
+        .map(|event| ScriptEvent::DialogueComplete {
+            speaker: event.speaker,
+            tree: event.tree.clone(),
+            node: event.node.clone(),
+            choice: event.choice,
+        }));
+    events.extend(transition_events.iter()
+        .map(|event| ScriptEvent::AreaTransition {
+            from_level: event.from_level.clone(),
+            to_level: event.to_level.clone(),
+            anchor: event.anchor.clone(),
+        }));
+
+    let commands = RefCell::new(commands);
+    let transforms = RefCell::new(transforms);
+    let dialogue = RefCell::new(dialogue);
+    let weather = RefCell::new(weather);
+
+    let lua = &engine.lua;
+    let result = lua.scope(|scope| {
+        let game: Table = lua.globals().get("game")?;
+
+        game.set("spawn", scope.create_function(|_, (visual, x, y, z): (String, f32, f32, f32)| {
+            let entity = commands.borrow_mut().spawn()
+                .insert(Transform::new(vec3(x, y, z), Matrix3::identity()))
+                .insert(Visual::new(&visual, false))
+                .id();
+            Ok(entity_id(entity))
+        })?)?;
+
+        game.set("despawn", scope.create_function(|_, id: i64| {
+            commands.borrow_mut().entity(entity_from_id(id)).despawn();
+            Ok(())
+        })?)?;
+
+        game.set("find", scope.create_function(|_, name: String| {
+            Ok(names.iter().find(|(_, script_name)| script_name.name == name).map(|(entity, _)| entity_id(entity)))
+        })?)?;
+
+        game.set("position", scope.create_function(|_, id: i64| {
+            let pos = transforms.borrow().get(entity_from_id(id)).ok().map(|transform| transform.pos);
+            Ok((pos.map(|pos| pos.x), pos.map(|pos| pos.y), pos.map(|pos| pos.z)))
+        })?)?;
+
+        game.set("set_position", scope.create_function(|_, (id, x, y, z): (i64, f32, f32, f32)| {
+            if let Ok(mut transform) = transforms.borrow_mut().get_mut(entity_from_id(id)) {
+                transform.pos = vec3(x, y, z);
+            }
+            Ok(())
+        })?)?;
+
+        game.set("dialogue", scope.create_function(|_, (id, tree): (i64, String)| {
+            Ok(dialogue.borrow_mut().start(&dialogue_manager, entity_from_id(id), &tree))
+        })?)?;
+
+        game.set("weather", scope.create_function(|_, ()| {
+            Ok(weather.borrow().kind().name())
+        })?)?;
+
+        game.set("set_weather", scope.create_function(|_, (name, automatic): (String, Option<bool>)| {
+            match WeatherKind::from_name(&name) {
+                Some(kind) => {
+                    let mut weather = weather.borrow_mut();
+                    weather.set(kind);
+                    weather.automatic = automatic.unwrap_or(false);
+                },
+                None => log::warn!("[script] Unknown weather {}", name),
+            }
+            Ok(())
+        })?)?;
+
+        game.set("log", scope.create_function(|_, message: String| {
+            log::info!("[script] {}", message);
+            Ok(())
+        })?)?;
+
+        let handlers: Table = game.get("handlers")?;
+        for event in events.iter() {
+            let event_handlers = match handlers.get::<_, Option<Table>>(event.name())? {
+                Some(event_handlers) => event_handlers,
+                None => continue,
+            };
+
+            let event_table = event.to_table(lua)?;
+            for handler in event_handlers.sequence_values::<Function>() {
+                // Log errors from each handler rather than letting one broken script stop the rest
+                if let Err(err) = handler.and_then(|handler| handler.call::<_, ()>(event_table.clone())) {
+                    log::error!("Error in {} script handler: {}", event.name(), err);
+                }
+            }
+        }
+
+        Ok(())
+    });
+
+    if let Err(err) = result {
+        log::error!("Failed to run scripts: {}", err);
+    }
+}
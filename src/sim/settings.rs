@@ -0,0 +1,210 @@
+use bevy_ecs::system::{Res, ResMut, NonSendMut};
+use serde::{Serialize, Deserialize};
+
+use super::audio::AudioManager;
+use super::graphics_settings::GraphicsSettings;
+use super::input_bindings::InputBindings;
+use super::post_fx::{PostFxSettings, ColorDepth, DitherPattern};
+use super::display_mode::{DisplayMode, AspectMode};
+use super::locale::{Locale, DEFAULT_LANGUAGE};
+
+/// The default path the player's settings are saved to
+pub const SETTINGS_PATH: &'static str = "config/settings.toml";
+
+/// The render resolutions that can be picked in the settings menu
+pub const RESOLUTIONS: &'static [(i32, i32)] = &[(320, 240), (640, 480), (960, 720), (1280, 960)];
+
+/// The FOV limits and step in the settings menu, in degrees
+const FOV_MIN: f32 = 40.0;
+const FOV_MAX: f32 = 100.0;
+const FOV_STEP: f32 = 5.0;
+
+/// The volume step in the settings menu
+const VOLUME_STEP: f32 = 0.1;
+
+/// The entries on the settings page of the pause menu
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SettingsEntry {
+    Volume,
+    Fov,
+    Resolution,
+    Aspect,
+    InvertY,
+    CrtEffect,
+    ColorDepth,
+    Dither,
+    Language,
+    Back,
+}
+
+impl SettingsEntry {
+    /// Get the locale key for the entry's name
+    pub fn key(&self) -> &'static str {
+        match self {
+            SettingsEntry::Volume => "settings.volume",
+            SettingsEntry::Fov => "settings.fov",
+            SettingsEntry::Resolution => "settings.resolution",
+            SettingsEntry::Aspect => "settings.aspect",
+            SettingsEntry::InvertY => "settings.invert_y",
+            SettingsEntry::CrtEffect => "settings.crt_effect",
+            SettingsEntry::ColorDepth => "settings.color_depth",
+            SettingsEntry::Dither => "settings.dither",
+            SettingsEntry::Language => "settings.language",
+            SettingsEntry::Back => "settings.back",
+        }
+    }
+}
+
+/// The Settings resource, with the settings the player can change from the pause menu. It's saved
+/// whenever it changes, and overrides the matching values in GraphicsSettings.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Settings {
+    /// The master volume, from 0 to 1
+    pub volume: f32,
+    /// The vertical field of view in degrees
+    pub fov: f32,
+    pub render_width: i32,
+    pub render_height: i32,
+    pub aspect_mode: AspectMode,
+    pub invert_y: bool,
+    /// Whether the CRT/composite video effect is enabled
+    pub crt_effect: bool,
+    pub color_depth: ColorDepth,
+    pub dither_pattern: DitherPattern,
+    /// The language code of the language pack the game's shown in
+    pub language: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            volume: 1.0,
+            fov: 60.0,
+            render_width: 320,
+            render_height: 240,
+            aspect_mode: AspectMode::Pillarboxed,
+            invert_y: false,
+            crt_effect: true,
+            color_depth: ColorDepth::Bits15,
+            dither_pattern: DitherPattern::Off,
+            language: DEFAULT_LANGUAGE.to_string(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings from a toml file, falling back to the defaults for any missing values
+    pub fn load(path: &str) -> Self {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                log::info!("No settings loaded from {}, using defaults: {}", path, err);
+                return Settings::default();
+            }
+        };
+
+        match toml::from_str::<Settings>(&source) {
+            Ok(settings) => settings,
+            Err(err) => {
+                log::error!("Failed to parse settings from {}, using defaults: {}", path, err);
+                Settings::default()
+            }
+        }
+    }
+
+    /// Save the settings to a toml file
+    pub fn save(&self, path: &str) {
+        let result = toml::to_string_pretty(self)
+            .map_err(|err| err.to_string())
+            .and_then(|source| std::fs::write(path, source).map_err(|err| err.to_string()));
+
+        if let Err(err) = result {
+            log::error!("Failed to save settings to {}: {}", path, err);
+        }
+    }
+
+    /// Change a setting from the menu, in the direction -1 or 1
+    pub fn adjust(&mut self, entry: SettingsEntry, dir: i32, locale: &Locale) {
+        match entry {
+            SettingsEntry::Volume => {
+                self.volume = f32::clamp(self.volume + dir as f32 * VOLUME_STEP, 0.0, 1.0);
+            },
+            SettingsEntry::Fov => {
+                self.fov = f32::clamp(self.fov + dir as f32 * FOV_STEP, FOV_MIN, FOV_MAX);
+            },
+            SettingsEntry::Resolution => {
+                (self.render_width, self.render_height) = cycle(RESOLUTIONS, (self.render_width, self.render_height), dir);
+            },
+            SettingsEntry::Aspect => self.aspect_mode = cycle(AspectMode::ALL, self.aspect_mode, dir),
+            SettingsEntry::InvertY => self.invert_y = !self.invert_y,
+            SettingsEntry::CrtEffect => self.crt_effect = !self.crt_effect,
+            SettingsEntry::ColorDepth => self.color_depth = cycle(ColorDepth::ALL, self.color_depth, dir),
+            SettingsEntry::Dither => self.dither_pattern = cycle(DitherPattern::ALL, self.dither_pattern, dir),
+            SettingsEntry::Language => self.language = locale.cycle_language(&self.language, dir),
+            SettingsEntry::Back => {},
+        }
+    }
+
+    /// Get the display text for a setting's value
+    pub fn value_text(&self, entry: SettingsEntry, locale: &Locale) -> String {
+        let on_off = |value: bool| String::from(locale.get(if value { "settings.on" } else { "settings.off" }));
+        match entry {
+            SettingsEntry::Volume => format!("{}%", (self.volume * 100.0).round()),
+            SettingsEntry::Fov => format!("{}", self.fov.round()),
+            SettingsEntry::Resolution => format!("{}x{}", self.render_width, self.render_height),
+            SettingsEntry::Aspect => locale.get(self.aspect_mode.key()).to_string(),
+            SettingsEntry::InvertY => on_off(self.invert_y),
+            SettingsEntry::CrtEffect => on_off(self.crt_effect),
+            SettingsEntry::ColorDepth => String::from(self.color_depth.name()),
+            SettingsEntry::Dither => match self.dither_pattern {
+                DitherPattern::Off => on_off(false),
+                pattern => format!("{:?}", pattern),
+            },
+            SettingsEntry::Language => locale.language_name(&self.language).to_string(),
+            SettingsEntry::Back => String::new(),
+        }
+    }
+}
+
+/// Get the next or previous value in a list of options, wrapping around
+fn cycle<T: Copy + PartialEq>(options: &[T], current: T, dir: i32) -> T {
+    let index = options.iter().position(|option| *option == current).unwrap_or(0);
+    options[(index as i32 + dir).rem_euclid(options.len() as i32) as usize]
+}
+
+/// The settings system, which applies the settings when they change and saves them to disk
+pub fn apply_settings(settings: Res<Settings>, mut graphics: ResMut<GraphicsSettings>,
+    mut display: ResMut<DisplayMode>, mut bindings: ResMut<InputBindings>, mut post_fx: ResMut<PostFxSettings>,
+    mut locale: ResMut<Locale>, mut audio: NonSendMut<AudioManager>)
+{
+    if !settings.is_changed() {
+        return;
+    }
+
+    // Only touch the graphics settings if they actually differ, since changing them rebuilds the camera
+    let render_res = (settings.render_width, settings.render_height);
+    if graphics.fov != settings.fov || (graphics.render_width, graphics.render_height) != render_res {
+        graphics.fov = settings.fov;
+        (graphics.render_width, graphics.render_height) = render_res;
+    }
+
+    if display.aspect_mode != settings.aspect_mode {
+        display.aspect_mode = settings.aspect_mode;
+    }
+
+    bindings.invert_y = settings.invert_y;
+    post_fx.crt_enabled = settings.crt_effect;
+    post_fx.color_depth = settings.color_depth;
+    post_fx.dither_pattern = settings.dither_pattern;
+    audio.master_volume = settings.volume;
+
+    if locale.language() != settings.language {
+        locale.set_language(&settings.language);
+    }
+
+    // The settings were just loaded at startup, so there's nothing new to save
+    if !settings.is_added() {
+        settings.save(SETTINGS_PATH);
+    }
+}
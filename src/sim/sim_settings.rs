@@ -0,0 +1,179 @@
+use bevy_ecs::system::{Res, ResMut};
+use serde::{Serialize, Deserialize};
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::console::ConsoleCommands;
+use super::determinism::SimRng;
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+
+/// The default path the sim settings are loaded from
+pub const SIM_SETTINGS_PATH: &'static str = "config/sim.toml";
+
+/// The default fixed update rate in ticks per second
+const DEFAULT_TICK_RATE: i32 = 15;
+
+/// The limits on the time scale, so the sim doesn't go backwards or skip through too much at once
+const MIN_TIME_SCALE: f32 = 0.0;
+const MAX_TIME_SCALE: f32 = 8.0;
+
+/// The SimSettings resource, with the fixed update rate and how fast sim time passes. They're loaded
+/// from a toml file and can be overridden on the command line with --tick-rate=, --time-scale=,
+/// --paused, --deterministic, --seed= and --profile-systems. While the sim is paused, the step action runs it for a
+/// single tick.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SimSettings {
+    /// How many fixed updates run per second. This can only be set at startup.
+    pub tick_rate: i32,
+    /// How fast sim time passes compared to real time, for slow motion and fast forward
+    pub time_scale: f32,
+    pub paused: bool,
+    /// Whether to run the sim systems one at a time in a fixed order and log a checksum of the
+    /// state after every tick, so runs with the same seed and inputs can be compared. This can only
+    /// be set at startup.
+    pub deterministic: bool,
+    /// The seed for the SimRng, which is seeded from the clock if there isn't one unless the sim is
+    /// deterministic
+    pub seed: Option<u32>,
+    /// Whether to time each system for the diagnostics overlay, which runs them one at a time. This
+    /// can only be set at startup.
+    pub profile_systems: bool,
+    /// Whether to run a single tick while paused
+    #[serde(skip)]
+    step: bool,
+    /// The total scaled sim time
+    #[serde(skip)]
+    time: f64,
+}
+
+impl Default for SimSettings {
+    fn default() -> Self {
+        SimSettings {
+            tick_rate: DEFAULT_TICK_RATE,
+            time_scale: 1.0,
+            paused: false,
+            deterministic: false,
+            seed: None,
+            profile_systems: false,
+            step: false,
+            time: 0.0,
+        }
+    }
+}
+
+impl SimSettings {
+    /// Load the settings from a toml file, falling back to the defaults for any missing values
+    pub fn load(path: &str) -> Self {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                log::warn!("Failed to read sim settings from {}, using defaults: {}", path, err);
+                return SimSettings::default();
+            }
+        };
+
+        match toml::from_str::<SimSettings>(&source) {
+            Ok(settings) => settings,
+            Err(err) => {
+                log::error!("Failed to parse sim settings from {}, using defaults: {}", path, err);
+                SimSettings::default()
+            }
+        }
+    }
+
+    /// Override the settings with any given in command line arguments
+    pub fn with_args(mut self, args: impl Iterator<Item = String>) -> Self {
+        for arg in args {
+            if let Some(tick_rate) = arg.strip_prefix("--tick-rate=") {
+                match tick_rate.parse() {
+                    Ok(tick_rate) => self.tick_rate = tick_rate,
+                    Err(_) => log::warn!("Invalid tick rate: {}", tick_rate),
+                }
+            }
+            else if let Some(time_scale) = arg.strip_prefix("--time-scale=") {
+                match time_scale.parse() {
+                    Ok(time_scale) => self.time_scale = time_scale,
+                    Err(_) => log::warn!("Invalid time scale: {}", time_scale),
+                }
+            }
+            else if let Some(seed) = arg.strip_prefix("--seed=") {
+                match seed.parse() {
+                    Ok(seed) => self.seed = Some(seed),
+                    Err(_) => log::warn!("Invalid seed: {}", seed),
+                }
+            }
+            else if arg == "--paused" {
+                self.paused = true;
+            }
+            else if arg == "--deterministic" {
+                self.deterministic = true;
+            }
+            else if arg == "--profile-systems" {
+                self.profile_systems = true;
+            }
+        }
+
+        self.tick_rate = self.tick_rate.max(1);
+        self.time_scale = self.time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        self
+    }
+
+    /// Get the length of a fixed update in seconds
+    pub fn tick_length(&self) -> f64 {
+        1.0 / self.tick_rate.max(1) as f64
+    }
+
+    /// Create the random number generator for the sim
+    pub fn create_rng(&self) -> SimRng {
+        match (self.seed, self.deterministic) {
+            (Some(seed), _) => SimRng::new(seed),
+            (None, true) => SimRng::new(0),
+            (None, false) => SimRng::from_time(),
+        }
+    }
+
+    /// Check whether the sim should run this tick
+    pub fn running(&self) -> bool {
+        !self.paused || self.step
+    }
+}
+
+/// The sim settings system, which handles the debug pause and step actions and scales SimTime, so
+/// every sim system that uses it slows down or speeds up with the time scale
+pub fn update_sim_settings(input: Res<InputState>, gamepad: Res<GamepadState>, bindings: Res<InputBindings>,
+    mut settings: ResMut<SimSettings>, mut sim_time: ResMut<SimTime>)
+{
+    let controls = Controls::new(&input, &gamepad, &bindings);
+    if controls.is_just_pressed(Action::DebugPause) {
+        settings.paused = !settings.paused;
+        match settings.paused {
+            true => log::info!("Sim paused"),
+            false => log::info!("Sim resumed"),
+        }
+    }
+    settings.step = settings.paused && controls.is_just_pressed(Action::DebugStep);
+
+    // A stepped tick is always a whole tick, whatever the time scale is
+    let time_delta = match (settings.running(), settings.step) {
+        (false, _) => 0.0,
+        (true, true) => settings.tick_length(),
+        (true, false) => sim_time.sim_time_delta * settings.time_scale as f64,
+    };
+
+    settings.time += time_delta;
+    sim_time.sim_time_delta = time_delta;
+    sim_time.sim_time = settings.time;
+}
+
+/// Register the timescale console command
+pub fn register_timescale_command(commands: &mut ConsoleCommands) {
+    commands.register("timescale", "timescale [scale]", "Show or set how fast sim time passes", |world, args| {
+        let mut settings = world.resource_mut::<SimSettings>();
+        if let Some(scale) = args.first() {
+            let scale = scale.parse::<f32>().map_err(|_| format!("Invalid time scale {}", scale))?;
+            settings.time_scale = scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        }
+        Ok(format!("Time scale {}", settings.time_scale))
+    });
+}
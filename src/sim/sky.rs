@@ -0,0 +1,119 @@
+use bevy_ecs::system::{Res, ResMut, Local};
+use cgmath::{Vector3, vec3};
+
+use super::daynight::{TimeOfDay, sample_color_keyframes};
+use super::uniform_buffer::{UniformBlock, UniformBuffer, SKY_PARAMS_BINDING};
+
+/// The zenith color keyframes over the course of a day, as (hour, color)
+const ZENITH_COLOR_KEYFRAMES: &'static [(f32, Vector3<f32>)] = &[
+    (0.0, vec3(0.0, 0.0, 0.03)),
+    (5.0, vec3(0.02, 0.02, 0.08)),
+    (6.5, vec3(0.2, 0.22, 0.4)),
+    (8.0, vec3(0.15, 0.3, 0.6)),
+    (17.0, vec3(0.15, 0.3, 0.6)),
+    (18.5, vec3(0.18, 0.14, 0.32)),
+    (20.0, vec3(0.02, 0.02, 0.08)),
+    (24.0, vec3(0.0, 0.0, 0.03)),
+];
+
+/// The sun's color when it's on the horizon, which it fades to white from as it rises
+const SUNSET_COLOR: Vector3<f32> = vec3(1.0, 0.45, 0.15);
+
+/// The sun's color when it's high in the sky
+const MIDDAY_SUN_COLOR: Vector3<f32> = vec3(1.0, 0.95, 0.85);
+
+/// How high the sun is, as the y of its direction, when the sunrise and sunset glow is gone
+const SUNSET_HEIGHT: f32 = 0.35;
+
+/// The SkyParams resource, with what the sky shader draws, worked out from the time of day each
+/// tick. It can be changed by anything that runs after the day/night cycle, to override the sky.
+pub struct SkyParams {
+    /// The direction towards the sun
+    pub sun_direction: Vector3<f32>,
+    /// The color of the sun and the glow around it
+    pub sun_color: Vector3<f32>,
+    /// The color of the sky at the horizon, which it's faded from into the fog
+    pub horizon_color: Vector3<f32>,
+    /// The color of the sky straight up
+    pub zenith_color: Vector3<f32>,
+    /// How strong the sunrise and sunset glow around the sun is, from 0 to 1
+    pub sunset_amount: f32,
+    /// How visible the stars are, from 0 to 1
+    pub star_visibility: f32,
+}
+
+impl Default for SkyParams {
+    fn default() -> Self {
+        let mut sky_params = SkyParams {
+            sun_direction: vec3(0.0, 1.0, 0.0),
+            sun_color: MIDDAY_SUN_COLOR,
+            horizon_color: vec3(0.35, 0.4, 0.5),
+            zenith_color: vec3(0.15, 0.3, 0.6),
+            sunset_amount: 0.0,
+            star_visibility: 0.0,
+        };
+        sky_params.set_time_of_day(&TimeOfDay::default());
+        sky_params
+    }
+}
+
+impl SkyParams {
+    /// Set the sky for a time of day
+    pub fn set_time_of_day(&mut self, time_of_day: &TimeOfDay) {
+        self.sun_direction = time_of_day.sun_direction();
+        self.horizon_color = time_of_day.fog_color();
+        self.zenith_color = sample_color_keyframes(ZENITH_COLOR_KEYFRAMES, time_of_day.hour);
+
+        // The glow's strongest with the sun on the horizon, and fades out as it rises or sets
+        let sun_height = self.sun_direction.y;
+        self.sunset_amount = f32::clamp(1.0 - sun_height.abs() / SUNSET_HEIGHT, 0.0, 1.0);
+        let sun_color_t = f32::clamp(sun_height / SUNSET_HEIGHT, 0.0, 1.0);
+        self.sun_color = SUNSET_COLOR + (MIDDAY_SUN_COLOR - SUNSET_COLOR) * sun_color_t;
+
+        self.star_visibility = f32::clamp((0.3 - time_of_day.daylight()) / 0.2, 0.0, 1.0);
+    }
+}
+
+/// The sky system, which updates the sky for the time of day
+pub fn update_sky_params(time_of_day: Res<TimeOfDay>, mut sky_params: ResMut<SkyParams>) {
+    sky_params.set_time_of_day(&time_of_day);
+}
+
+/// The SkyParams uniform block, matching the std140 layout in sky_params.glsl
+#[repr(C)]
+pub struct SkyParamsBlock {
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    horizon_color: [f32; 4],
+    zenith_color: [f32; 4],
+    valid: f32,
+    sunset_amount: f32,
+    star_visibility: f32,
+    _padding: f32,
+}
+
+impl UniformBlock for SkyParamsBlock {
+    const BINDING: u32 = SKY_PARAMS_BINDING;
+}
+
+/// The sky params system, which uploads the SkyParams to their uniform buffer when they change. It
+/// runs in the render schedule, since it needs the GL context, before the scene's drawn so the
+/// first frame has them.
+pub fn upload_sky_params(sky_params: Res<SkyParams>, mut buffer: Local<UniformBuffer<SkyParamsBlock>>) {
+    if buffer.is_uploaded() && !sky_params.is_changed() {
+        return;
+    }
+
+    let vec4 = |v: Vector3<f32>| [v.x, v.y, v.z, 0.0];
+    let params = SkyParamsBlock {
+        sun_direction: vec4(sky_params.sun_direction),
+        sun_color: vec4(sky_params.sun_color),
+        horizon_color: vec4(sky_params.horizon_color),
+        zenith_color: vec4(sky_params.zenith_color),
+        valid: 1.0,
+        sunset_amount: f32::clamp(sky_params.sunset_amount, 0.0, 1.0),
+        star_visibility: f32::clamp(sky_params.star_visibility, 0.0, 1.0),
+        _padding: 0.0,
+    };
+    buffer.upload(&params);
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use bevy_ecs::world::World;
+use cgmath::{Vector2, Vector3, vec2, vec3, Zero};
+use serde::Deserialize;
+use dreamfield_system::components::Transform;
+
+use super::PlayerMovement;
+use super::checkpoint::RespawnPoint;
+use super::console::ConsoleCommands;
+use super::level::{Levels, load_level};
+use super::loading::LoadingScreen;
+
+/// A named place in a level the player can be put
+#[derive(Deserialize, Clone, Debug)]
+pub struct SpawnPoint {
+    pub level: String,
+    pub pos: (f32, f32, f32),
+    pub pitch_yaw: (f32, f32),
+}
+
+impl SpawnPoint {
+    pub fn pos(&self) -> Vector3<f32> {
+        vec3(self.pos.0, self.pos.1, self.pos.2)
+    }
+
+    pub fn pitch_yaw(&self) -> Vector2<f32> {
+        vec2(self.pitch_yaw.0, self.pitch_yaw.1)
+    }
+}
+
+/// The SpawnPoints resource, the named places the player can be put, for level entrances,
+/// checkpoints and debugging. They come from the spawn points file and the spawn_ empties in each
+/// level's world model. Each belongs to a level, and names are shared between all levels, so
+/// a spawn point can be reached from anywhere by name alone.
+pub struct SpawnPoints {
+    points: HashMap<String, SpawnPoint>,
+}
+
+impl SpawnPoints {
+    /// Load the spawn points from a RON map of names to spawn points
+    pub fn new(source: &str) -> Self {
+        let points = match ron::from_str(source) {
+            Ok(points) => points,
+            Err(err) => {
+                log::error!("Failed to parse spawn points: {}", err);
+                HashMap::new()
+            }
+        };
+
+        SpawnPoints { points }
+    }
+
+    /// Add a spawn point, replacing any other with the same name
+    pub fn insert(&mut self, name: &str, point: SpawnPoint) {
+        if self.points.insert(name.to_string(), point).is_some() {
+            log::warn!("Spawn point {} was defined more than once", name);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SpawnPoint> {
+        self.points.get(name)
+    }
+
+    /// Get a spawn point, if it's in a level
+    pub fn get_in_level(&self, name: &str, level: &str) -> Option<&SpawnPoint> {
+        self.get(name).filter(|point| point.level == level)
+    }
+
+    /// Get the names of all the spawn points
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.points.keys().map(String::as_str)
+    }
+}
+
+/// Put the player somewhere, stopping them and making it where they respawn
+pub fn place_player(world: &mut World, pos: Vector3<f32>, pitch_yaw: Vector2<f32>) {
+    for (mut transform, mut player_movement) in world.query::<(&mut Transform, &mut PlayerMovement)>().iter_mut(world) {
+        transform.pos = pos;
+        player_movement.pitch_yaw = pitch_yaw;
+        player_movement.velocity = Vector3::zero();
+        player_movement.ground_plane = None;
+    }
+
+    world.insert_resource(RespawnPoint::new(pos, pitch_yaw));
+    world.resource_mut::<LoadingScreen>().spawn_point = pos;
+}
+
+/// Teleport the player to a spawn point, loading its level first if it isn't the current one.
+/// Returns false if there's no spawn point with the name.
+pub fn teleport_player(world: &mut World, name: &str) -> bool {
+    let point = match world.resource::<SpawnPoints>().get(name) {
+        Some(point) => point.clone(),
+        None => {
+            log::error!("Tried to teleport the player to unknown spawn point {}", name);
+            return false;
+        }
+    };
+
+    log::info!("Teleporting the player to {}", name);
+    match world.resource::<Levels>().current() == Some(point.level.as_str()) {
+        true => place_player(world, point.pos(), point.pitch_yaw()),
+        false => load_level(world, &point.level, Some(name)),
+    }
+
+    true
+}
+
+/// Register the teleport console command
+pub fn register_teleport_command(commands: &mut ConsoleCommands) {
+    commands.register("teleport", "teleport <spawn point>", "Teleport to a spawn point in any level", |world, args| {
+        let name = args.first().ok_or_else(|| "Which spawn point?".to_string())?;
+        match teleport_player(world, name) {
+            true => Ok(format!("Teleported to {}", name)),
+            false => Err(format!("There's no spawn point called {}", name)),
+        }
+    }).with_completion(|world, arg| match arg {
+        0 => world.resource::<SpawnPoints>().names().map(str::to_string).collect(),
+        _ => Vec::new(),
+    });
+}
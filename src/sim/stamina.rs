@@ -0,0 +1,98 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{With, Changed};
+use bevy_ecs::system::Query;
+
+use super::ui::{UiWidget, UiVisible};
+
+/// The Stamina component, which limits how long the player can sprint for
+#[derive(Component)]
+pub struct Stamina {
+    pub current: f32,
+    pub max: f32,
+    /// Stamina used per second while sprinting
+    pub drain_rate: f32,
+    /// Stamina regained per second while not sprinting
+    pub regen_rate: f32,
+    /// Seconds after sprinting before stamina starts regenerating
+    pub regen_delay: f32,
+    /// The amount sprinting multiplies the speed and acceleration by
+    pub sprint_multiplier: f32,
+    /// The fraction of stamina needed to sprint again after running out
+    pub recover_threshold: f32,
+    exhausted: bool,
+    regen_timer: f32,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Stamina {
+            current: 100.0,
+            max: 100.0,
+            drain_rate: 25.0,
+            regen_rate: 20.0,
+            regen_delay: 1.0,
+            sprint_multiplier: 1.6,
+            recover_threshold: 0.25,
+            exhausted: false,
+            regen_timer: 0.0,
+        }
+    }
+}
+
+impl Stamina {
+    /// Get whether there's enough stamina to sprint
+    pub fn can_sprint(&self) -> bool {
+        !self.exhausted && self.current > 0.0
+    }
+
+    /// Update stamina, draining it if the player is sprinting
+    pub fn update(&mut self, sprinting: bool, time_delta: f32) {
+        if sprinting {
+            self.current = f32::max(self.current - self.drain_rate * time_delta, 0.0);
+            self.regen_timer = self.regen_delay;
+            if self.current <= 0.0 {
+                self.exhausted = true;
+            }
+        }
+        else if self.regen_timer > 0.0 {
+            self.regen_timer -= time_delta;
+        }
+        else {
+            self.current = f32::min(self.current + self.regen_rate * time_delta, self.max);
+        }
+
+        if self.exhausted && self.current >= self.max * self.recover_threshold {
+            self.exhausted = false;
+        }
+    }
+
+    /// Get the stamina as a fraction of the max
+    pub fn fraction(&self) -> f32 {
+        match self.max > 0.0 {
+            true => self.current / self.max,
+            false => 0.0,
+        }
+    }
+}
+
+/// The StaminaBar component, for the progress bar widget showing the player's stamina
+#[derive(Component)]
+pub struct StaminaBar;
+
+/// The stamina bar system. The bar is hidden while stamina is full.
+pub fn update_stamina_bar(player_query: Query<&Stamina, Changed<Stamina>>,
+    mut query: Query<(&mut UiWidget, &mut UiVisible), With<StaminaBar>>)
+{
+    for stamina in player_query.iter() {
+        for (mut widget, mut visible) in query.iter_mut() {
+            if let UiWidget::ProgressBar { value, .. } = &mut *widget {
+                *value = stamina.fraction();
+            }
+
+            let show = stamina.current < stamina.max;
+            if visible.0 != show {
+                visible.0 = show;
+            }
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use bevy_ecs::prelude::Entity;
+use bevy_ecs::system::{Query, ResMut};
+use cgmath::{Vector3, vec3, InnerSpace, ElementWise};
+use dreamfield_system::components::Transform;
+use dreamfield_system::intersection::{Collider, Shape};
+
+/// How high above the player and the thing they're interacting with the interaction ray is cast
+const INTERACT_RAY_HEIGHT: f32 = 1.0;
+
+/// How close to the thing being interacted with the interaction ray can hit something, so that
+/// whatever it's set into doesn't block it
+const INTERACT_RAY_MARGIN: f32 = 0.5;
+
+/// A tiny value, below which rays are treated as parallel to what they're tested against
+const EPSILON: f32 = 1e-6;
+
+/// A hit from a query against the static collision
+#[derive(Clone, Copy, Debug)]
+pub struct StaticHit {
+    /// How far along the ray the hit was
+    pub distance: f32,
+    /// The point that was hit, on the surface of the world
+    pub point: Vector3<f32>,
+    pub normal: Vector3<f32>,
+}
+
+/// A node of a chunk's BVH. Leaves have a count of triangles starting at first, and branches have
+/// a count of 0, with their first child straight after them and their second at first.
+struct BvhNode {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    first: u32,
+    count: u32,
+}
+
+/// The triangles in a chunk of a world model, with a BVH over them
+struct CollisionChunk {
+    triangles: Vec<[Vector3<f32>; 3]>,
+    nodes: Vec<BvhNode>,
+}
+
+/// Reads the little endian values out of a baked BVH
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self) -> Option<[u8; 4]> {
+        let (value, rest) = (self.data.get(..4)?, &self.data[4..]);
+        self.data = rest;
+        value.try_into().ok()
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    fn vec3(&mut self) -> Option<Vector3<f32>> {
+        let mut value = || self.bytes().map(f32::from_le_bytes);
+        Some(vec3(value()?, value()?, value()?))
+    }
+}
+
+impl CollisionChunk {
+    /// Parse the chunks of a world model's BVH, in the format written by bake_collision_bvh
+    fn parse_all(data: &[u8]) -> Option<Vec<CollisionChunk>> {
+        let mut reader = Reader { data };
+        let chunk_count = reader.u32()?;
+
+        (0..chunk_count).map(|_| {
+            let triangle_count = reader.u32()?;
+            let triangles = (0..triangle_count)
+                .map(|_| Some([reader.vec3()?, reader.vec3()?, reader.vec3()?]))
+                .collect::<Option<Vec<_>>>()?;
+
+            let node_count = reader.u32()?;
+            let nodes = (0..node_count)
+                .map(|_| Some(BvhNode { min: reader.vec3()?, max: reader.vec3()?, first: reader.u32()?, count: reader.u32()? }))
+                .collect::<Option<Vec<_>>>()?;
+
+            Some(CollisionChunk { triangles, nodes })
+        }).collect()
+    }
+
+    /// Visit the triangles in the leaves whose bounds pass a test
+    fn visit(&self, overlaps: &impl Fn(&Vector3<f32>, &Vector3<f32>) -> bool, visit: &mut impl FnMut(&[Vector3<f32>; 3])) {
+        let mut stack = match self.nodes.is_empty() {
+            true => return,
+            false => vec![0],
+        };
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !overlaps(&node.min, &node.max) {
+                continue;
+            }
+
+            match node.count {
+                0 => stack.extend([node.first as usize, index + 1]),
+                count => {
+                    let first = node.first as usize;
+                    self.triangles[first..first + count as usize].iter().for_each(&mut *visit);
+                }
+            }
+        }
+    }
+}
+
+/// An entity's bounding spheroid collider, as it was at the start of the tick
+struct EntityCollider {
+    entity: Entity,
+    centre: Vector3<f32>,
+    radius: Vector3<f32>,
+}
+
+/// The StaticCollision resource, with the collision BVHs build.rs baked for each level's world
+/// models. It's what everything in the sim collides with, from sight lines, interaction,
+/// projectiles and cameras to the character controllers, which also sweep against the colliders of
+/// other entities as they were at the start of the tick. Queries are against the level that's
+/// loaded.
+pub struct StaticCollision {
+    levels: Vec<(String, Vec<CollisionChunk>)>,
+    current: Option<usize>,
+    colliders: Vec<EntityCollider>,
+}
+
+impl StaticCollision {
+    /// Create the static collision from a list of (level, data) pairs of baked BVHs. A level can
+    /// be listed more than once if it has several world models.
+    pub fn new(bvhs: Vec<(&str, &[u8])>) -> Self {
+        let mut levels: Vec<(String, Vec<CollisionChunk>)> = Vec::new();
+        for (level, data) in bvhs {
+            let chunks = match CollisionChunk::parse_all(data) {
+                Some(chunks) => chunks,
+                None => {
+                    log::error!("Failed to parse the collision BVH for {}", level);
+                    continue;
+                }
+            };
+
+            match levels.iter_mut().find(|(name, _)| name == level) {
+                Some((_, level_chunks)) => level_chunks.extend(chunks),
+                None => levels.push((level.to_string(), chunks)),
+            }
+        }
+
+        StaticCollision { levels, current: None, colliders: Vec::new() }
+    }
+
+    /// Set the level that's queried
+    pub fn set_level(&mut self, level: &str) {
+        self.current = self.levels.iter().position(|(name, _)| name == level);
+    }
+
+    fn chunks(&self) -> &[CollisionChunk] {
+        match self.current {
+            Some(level) => &self.levels[level].1,
+            None => &[],
+        }
+    }
+
+    /// Find the first hit along a ray, with a normalized direction
+    pub fn raycast(&self, origin: Vector3<f32>, dir: Vector3<f32>, max_distance: f32) -> Option<StaticHit> {
+        self.spherecast(origin, dir, 0.0, max_distance)
+    }
+
+    /// Find the first hit sweeping a sphere along a ray, with a normalized direction. The point is
+    /// where the sphere touches the world, with the sphere's centre being the distance along the
+    /// ray.
+    pub fn spherecast(&self, origin: Vector3<f32>, dir: Vector3<f32>, radius: f32, max_distance: f32) -> Option<StaticHit> {
+        let inv_dir = vec3(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let overlaps = |min: &Vector3<f32>, max: &Vector3<f32>| {
+            ray_hits_box(origin, inv_dir, min - vec3(radius, radius, radius), max + vec3(radius, radius, radius), max_distance)
+        };
+
+        let mut closest: Option<StaticHit> = None;
+        let mut visit = |triangle: &[Vector3<f32>; 3]| {
+            let hit = match radius > 0.0 {
+                true => sweep_sphere_triangle(origin, dir, radius, triangle),
+                false => ray_triangle(origin, dir, triangle),
+            };
+            if let Some(hit) = hit.filter(|hit| hit.distance <= max_distance) {
+                if closest.map_or(true, |closest| hit.distance < closest.distance) {
+                    closest = Some(hit);
+                }
+            }
+        };
+
+        for chunk in self.chunks() {
+            chunk.visit(&overlaps, &mut visit);
+        }
+        closest
+    }
+
+    /// Sweep a unit sphere through the level and the entity colliders from a position with a given
+    /// velocity, in the ellipsoid space of a collider with the given cbm, as the character
+    /// controllers do. The hit is in ellipsoid space, and its distance is the fraction of the
+    /// velocity that was moved. Other colliders are treated as the sphere around their largest
+    /// axis in ellipsoid space, so characters keep a little further from squashed ones.
+    pub fn sweep_unit_sphere(&self, position: Vector3<f32>, velocity: Vector3<f32>, cbm: Vector3<f32>,
+        ignore_entity: Option<Entity>) -> Option<StaticHit>
+    {
+        let length = velocity.magnitude();
+        if length == 0.0 {
+            return None;
+        }
+        let dir = velocity / length;
+        let inv_dir = vec3(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let overlaps = |min: &Vector3<f32>, max: &Vector3<f32>| {
+            let padding = vec3(1.0, 1.0, 1.0);
+            ray_hits_box(position, inv_dir, min.mul_element_wise(cbm) - padding, max.mul_element_wise(cbm) + padding, length)
+        };
+
+        let mut closest: Option<StaticHit> = None;
+        let mut consider = |hit: Option<StaticHit>| {
+            if let Some(hit) = hit.filter(|hit| hit.distance <= length) {
+                if closest.map_or(true, |closest| hit.distance < closest.distance) {
+                    closest = Some(hit);
+                }
+            }
+        };
+
+        for chunk in self.chunks() {
+            chunk.visit(&overlaps, &mut |triangle| {
+                let triangle = triangle.map(|vertex| vertex.mul_element_wise(cbm));
+                consider(sweep_sphere_triangle(position, dir, 1.0, &triangle));
+            });
+        }
+
+        for collider in self.colliders.iter().filter(|collider| Some(collider.entity) != ignore_entity) {
+            let centre = collider.centre.mul_element_wise(cbm);
+            let radius_es = collider.radius.mul_element_wise(cbm);
+            let radius = radius_es.x.max(radius_es.y).max(radius_es.z);
+            consider(sweep_sphere_point(position, dir, 1.0 + radius, centre)
+                .map(|hit| StaticHit { point: centre + hit.normal * radius, ..hit }));
+        }
+
+        closest.map(|hit| StaticHit { distance: hit.distance / length, ..hit })
+    }
+
+    /// Check whether a sphere overlaps the world
+    pub fn overlap_sphere(&self, centre: Vector3<f32>, radius: f32) -> bool {
+        let overlaps = |min: &Vector3<f32>, max: &Vector3<f32>| {
+            (0..3).all(|axis| centre[axis] + radius >= min[axis] && centre[axis] - radius <= max[axis])
+        };
+
+        self.chunks().iter().any(|chunk| {
+            let mut hit = false;
+            chunk.visit(&overlaps, &mut |triangle| {
+                hit = hit || (closest_point_on_triangle(centre, triangle) - centre).magnitude2() <= radius * radius;
+            });
+            hit
+        })
+    }
+
+    /// Check whether there's nothing in the world between two points, ignoring anything within a
+    /// margin of the end
+    pub fn line_of_sight(&self, from: Vector3<f32>, to: Vector3<f32>, margin: f32) -> bool {
+        let ray = to - from;
+        let distance = ray.magnitude();
+        match distance > margin {
+            true => self.raycast(from, ray / distance, distance - margin).is_none(),
+            false => true,
+        }
+    }
+
+    /// Check whether the player's in sight of something they're trying to interact with, so that
+    /// things can't be used through walls
+    pub fn can_interact(&self, player_pos: Vector3<f32>, target_pos: Vector3<f32>) -> bool {
+        let up = vec3(0.0, INTERACT_RAY_HEIGHT, 0.0);
+        self.line_of_sight(player_pos + up, target_pos + up, INTERACT_RAY_MARGIN)
+    }
+}
+
+/// The collision colliders system, which records where every entity's collider is at the start of
+/// the tick for the character controllers to collide with
+pub fn update_collision_colliders(mut static_collision: ResMut<StaticCollision>,
+    query: Query<(Entity, &Transform, &Collider)>)
+{
+    static_collision.colliders = query.iter()
+        .filter_map(|(entity, transform, collider)| match collider.shape {
+            Shape::BoundingSpheroid(offset, radius) => Some(EntityCollider { entity, centre: transform.pos + offset, radius }),
+            _ => None,
+        })
+        .collect();
+}
+
+/// Check whether a ray passes through a box before a distance, by the slab test
+fn ray_hits_box(origin: Vector3<f32>, inv_dir: Vector3<f32>, min: Vector3<f32>, max: Vector3<f32>, max_distance: f32) -> bool {
+    let (mut t_min, mut t_max) = (0.0f32, max_distance);
+    for axis in 0..3 {
+        let t0 = (min[axis] - origin[axis]) * inv_dir[axis];
+        let t1 = (max[axis] - origin[axis]) * inv_dir[axis];
+        // NaN comes from a ray in the plane of a slab, which it can't leave
+        if t0.is_nan() || t1.is_nan() {
+            continue;
+        }
+        t_min = t_min.max(t0.min(t1));
+        t_max = t_max.min(t0.max(t1));
+    }
+    t_min <= t_max
+}
+
+/// Intersect a ray with a triangle
+fn ray_triangle(origin: Vector3<f32>, dir: Vector3<f32>, [a, b, c]: &[Vector3<f32>; 3]) -> Option<StaticHit> {
+    let (edge1, edge2) = (b - a, c - a);
+    let p = dir.cross(edge2);
+    let det = edge1.dot(p);
+    if det.abs() < EPSILON {
+        return None;
+    }
+
+    let offset = origin - a;
+    let u = offset.dot(p) / det;
+    let q = offset.cross(edge1);
+    let v = dir.dot(q) / det;
+    let t = edge2.dot(q) / det;
+    match u >= 0.0 && v >= 0.0 && u + v <= 1.0 && t >= 0.0 {
+        true => {
+            let normal = edge1.cross(edge2).normalize();
+            let normal = if normal.dot(dir) > 0.0 { -normal } else { normal };
+            Some(StaticHit { distance: t, point: origin + dir * t, normal })
+        },
+        false => None,
+    }
+}
+
+/// Sweep a sphere along a ray into a triangle, checking its face, then its edges and corners
+fn sweep_sphere_triangle(origin: Vector3<f32>, dir: Vector3<f32>, radius: f32, triangle: &[Vector3<f32>; 3])
+    -> Option<StaticHit>
+{
+    let [a, b, c] = *triangle;
+    let normal = (b - a).cross(c - a);
+    if normal.magnitude2() < EPSILON * EPSILON {
+        return None;
+    }
+
+    // Face the normal towards the ray's origin
+    let normal = normal.normalize();
+    let (normal, start_height) = match normal.dot(origin - a) {
+        height if height < 0.0 => (-normal, -height),
+        height => (normal, height),
+    };
+
+    // The face is hit if the sphere touches its plane inside the triangle
+    let approach = -normal.dot(dir);
+    let face_t = match (start_height <= radius, approach > EPSILON) {
+        (true, _) => Some(0.0),
+        (false, true) => Some((start_height - radius) / approach),
+        (false, false) => None,
+    };
+    if let Some(t) = face_t {
+        let point = origin + dir * t - normal * start_height.min(radius);
+        if point_in_triangle(point, triangle, normal) {
+            return Some(StaticHit { distance: t, point, normal });
+        }
+    }
+
+    // Otherwise it might hit an edge or a corner
+    let mut closest: Option<StaticHit> = None;
+    let mut consider = |hit: Option<StaticHit>| {
+        if let Some(hit) = hit {
+            if closest.map_or(true, |closest| hit.distance < closest.distance) {
+                closest = Some(hit);
+            }
+        }
+    };
+    for (start, end) in [(a, b), (b, c), (c, a)] {
+        consider(sweep_sphere_edge(origin, dir, radius, start, end));
+        consider(sweep_sphere_point(origin, dir, radius, start));
+    }
+    closest
+}
+
+/// Sweep a sphere along a ray into the middle of an edge
+fn sweep_sphere_edge(origin: Vector3<f32>, dir: Vector3<f32>, radius: f32, start: Vector3<f32>, end: Vector3<f32>)
+    -> Option<StaticHit>
+{
+    let edge = end - start;
+    let offset = origin - start;
+    let (edge_length2, offset_along, dir_along) = (edge.magnitude2(), offset.dot(edge), dir.dot(edge));
+
+    // Intersect the ray with the infinite cylinder around the edge
+    let a = edge_length2 - dir_along * dir_along;
+    if a.abs() < EPSILON {
+        return None;
+    }
+    let b = edge_length2 * offset.dot(dir) - dir_along * offset_along;
+    let c = edge_length2 * (offset.magnitude2() - radius * radius) - offset_along * offset_along;
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / a;
+    let along = (offset_along + t * dir_along) / edge_length2;
+    match t >= 0.0 && (0.0..=1.0).contains(&along) {
+        true => {
+            let point = start + edge * along;
+            let normal = (origin + dir * t - point).normalize();
+            Some(StaticHit { distance: t, point, normal })
+        },
+        false => None,
+    }
+}
+
+/// Sweep a sphere along a ray into a point
+fn sweep_sphere_point(origin: Vector3<f32>, dir: Vector3<f32>, radius: f32, point: Vector3<f32>) -> Option<StaticHit> {
+    let offset = origin - point;
+    let b = offset.dot(dir);
+    let c = offset.magnitude2() - radius * radius;
+    if c > 0.0 && b > 0.0 {
+        return None;
+    }
+
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let t = f32::max(-b - discriminant.sqrt(), 0.0);
+    let normal = origin + dir * t - point;
+    let normal = match normal.magnitude2() > EPSILON {
+        true => normal.normalize(),
+        false => -dir,
+    };
+    Some(StaticHit { distance: t, point, normal })
+}
+
+/// Check whether a point on a triangle's plane is inside it
+fn point_in_triangle(point: Vector3<f32>, [a, b, c]: &[Vector3<f32>; 3], normal: Vector3<f32>) -> bool {
+    [(a, b), (b, c), (c, a)].iter().all(|(start, end)| (end - start).cross(point - start).dot(normal) >= 0.0)
+        || [(a, b), (b, c), (c, a)].iter().all(|(start, end)| (end - start).cross(point - start).dot(normal) <= 0.0)
+}
+
+/// Get the closest point on a triangle to a point
+fn closest_point_on_triangle(point: Vector3<f32>, [a, b, c]: &[Vector3<f32>; 3]) -> Vector3<f32> {
+    let (ab, ac, ap) = (b - a, c - a, point - a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+
+    let bp = point - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = point - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
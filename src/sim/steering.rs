@@ -0,0 +1,164 @@
+use bevy_ecs::prelude::Entity;
+use cgmath::{Vector3, Matrix3, InnerSpace, Rad, Zero, vec3};
+use dreamfield_system::intersection::{Collider, Shape};
+
+use super::character_controller::sweep_collider;
+use super::static_collision::StaticCollision;
+
+/// The radius of the sphere swept ahead of agents to look for obstacles
+const AVOID_PROBE_RADIUS: f32 = 0.2;
+
+/// The height above an agent's position that it looks for obstacles at
+const AVOID_PROBE_HEIGHT: f32 = 0.5;
+
+/// Steering settings and state for an npc, which turns the points it wants to get to into a smooth
+/// velocity and rotation instead of snapping between them
+#[derive(Clone, Copy, Debug)]
+pub struct Steering {
+    /// The maximum change in velocity per second
+    pub max_acceleration: f32,
+    /// The maximum turning speed, in radians per second
+    pub turn_speed: f32,
+    /// The distance from the final target at which agents start slowing down
+    pub slowing_radius: f32,
+    /// How close agents can get to each other before they push apart
+    pub separation_radius: f32,
+    /// How far ahead agents look for obstacles to steer around
+    pub avoid_distance: f32,
+    /// The current velocity
+    pub velocity: Vector3<f32>,
+    /// The current heading, in radians around the y axis
+    heading: Option<f32>,
+}
+
+impl Default for Steering {
+    fn default() -> Self {
+        Steering {
+            max_acceleration: 8.0,
+            turn_speed: 6.0,
+            slowing_radius: 1.0,
+            separation_radius: 1.0,
+            avoid_distance: 1.5,
+            velocity: Vector3::zero(),
+            heading: None,
+        }
+    }
+}
+
+/// Get the horizontal offset between two positions
+fn flat(v: Vector3<f32>) -> Vector3<f32> {
+    vec3(v.x, 0.0, v.z)
+}
+
+/// Get the velocity that heads straight for a target at full speed
+pub fn seek(pos: Vector3<f32>, target: Vector3<f32>, max_speed: f32) -> Vector3<f32> {
+    let offset = flat(target - pos);
+    match offset.magnitude2() > 0.0 {
+        true => offset.normalize() * max_speed,
+        false => Vector3::zero(),
+    }
+}
+
+/// Get the velocity that heads for a target, slowing down to stop on it within a radius
+pub fn arrive(pos: Vector3<f32>, target: Vector3<f32>, max_speed: f32, slowing_radius: f32) -> Vector3<f32> {
+    let distance = flat(target - pos).magnitude();
+    let speed = match distance < slowing_radius {
+        true => max_speed * distance / slowing_radius,
+        false => max_speed,
+    };
+    seek(pos, target, speed)
+}
+
+/// Get the velocity that pushes an agent away from any neighbours that are too close, stronger the
+/// closer they are
+pub fn separation(pos: Vector3<f32>, neighbours: impl Iterator<Item = Vector3<f32>>, radius: f32,
+    max_speed: f32) -> Vector3<f32>
+{
+    neighbours
+        .map(|neighbour| flat(pos - neighbour))
+        .filter(|offset| offset.magnitude2() > 0.0 && offset.magnitude() < radius)
+        .map(|offset| offset.normalize() * max_speed * (1.0 - offset.magnitude() / radius))
+        .fold(Vector3::zero(), |sum, push| sum + push)
+}
+
+/// Get the velocity that steers an agent away from world geometry it's about to walk into, by
+/// sweeping a small sphere ahead of it and pushing it along the normal of what it hits
+pub fn avoid(collision: &StaticCollision, pos: Vector3<f32>, velocity: Vector3<f32>, distance: f32,
+    ignore_entity: Entity) -> Vector3<f32>
+{
+    let velocity = flat(velocity);
+    let speed = velocity.magnitude();
+    if speed <= 0.0 {
+        return Vector3::zero();
+    }
+
+    let probe = Collider::new(Shape::BoundingSpheroid(Vector3::zero(), vec3(AVOID_PROBE_RADIUS, AVOID_PROBE_RADIUS,
+        AVOID_PROBE_RADIUS)));
+    let start = pos + vec3(0.0, AVOID_PROBE_HEIGHT, 0.0);
+    let ahead = velocity / speed * distance;
+
+    match sweep_collider(collision, &probe, start, ahead, ignore_entity) {
+        Some(hit) => {
+            let normal = flat(hit.normal);
+            match normal.magnitude2() > 0.0 {
+                true => normal.normalize() * speed * (1.0 - hit.distance / distance),
+                false => Vector3::zero(),
+            }
+        },
+        None => Vector3::zero(),
+    }
+}
+
+impl Steering {
+    /// Get the velocity an agent wants to move at to get to a target through the next point on its
+    /// path there, arriving at the target, keeping away from its neighbours and avoiding obstacles
+    pub fn desired_velocity(&self, collision: &StaticCollision, pos: Vector3<f32>,
+        next_point: Vector3<f32>, target: Vector3<f32>, max_speed: f32, neighbours: &[Vector3<f32>],
+        ignore_entity: Entity) -> Vector3<f32>
+    {
+        let heading = match flat(target - next_point).magnitude2() > 0.0 {
+            true => seek(pos, next_point, max_speed),
+            false => arrive(pos, target, max_speed, self.slowing_radius),
+        };
+
+        heading
+            + separation(pos, neighbours.iter().copied(), self.separation_radius, max_speed)
+            + avoid(collision, pos, self.velocity, self.avoid_distance, ignore_entity)
+    }
+
+    /// Accelerate towards a desired velocity, returning the new velocity
+    pub fn update(&mut self, desired: Vector3<f32>, max_speed: f32, time_delta: f32) -> Vector3<f32> {
+        let change = flat(desired) - self.velocity;
+        let max_change = self.max_acceleration * time_delta;
+        self.velocity += match change.magnitude() > max_change {
+            true => change.normalize() * max_change,
+            false => change,
+        };
+
+        if self.velocity.magnitude() > max_speed {
+            self.velocity = self.velocity.normalize() * max_speed;
+        }
+
+        self.velocity
+    }
+
+    /// Turn towards a direction at the turning speed, returning the new rotation facing along the
+    /// current heading
+    pub fn turn_towards(&mut self, dir: Vector3<f32>, time_delta: f32) -> Option<Matrix3<f32>> {
+        let dir = flat(dir);
+        if dir.magnitude2() <= 0.0 {
+            return self.heading.map(|heading| Matrix3::from_angle_y(Rad(heading)));
+        }
+
+        let target = f32::atan2(-dir.x, -dir.z);
+        let heading = self.heading.unwrap_or(target);
+
+        // Turn the shortest way around
+        let diff = (target - heading + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI;
+        let max_turn = self.turn_speed * time_delta;
+        let heading = heading + f32::clamp(diff, -max_turn, max_turn);
+
+        self.heading = Some(heading);
+        Some(Matrix3::from_angle_y(Rad(heading)))
+    }
+}
@@ -0,0 +1,53 @@
+use cgmath::{Vector2, Vector3, Quaternion, Rotation3, Rad, Matrix4, InnerSpace, vec3};
+
+use super::static_collision::StaticCollision;
+
+/// The radius of the sphere swept from the player to the camera
+const CAMERA_RADIUS: f32 = 0.2;
+
+/// The ThirdPersonCamera resource, with the settings for the orbiting third-person camera
+pub struct ThirdPersonCamera {
+    pub enabled: bool,
+    /// The distance from the player's eye to the camera
+    pub distance: f32,
+    /// The pitch limits for the orbiting camera, which are tighter than first person so that the
+    /// camera doesn't go under the ground
+    pub pitch_min: f32,
+    pub pitch_max: f32,
+}
+
+impl Default for ThirdPersonCamera {
+    fn default() -> Self {
+        ThirdPersonCamera {
+            enabled: false,
+            distance: 3.0,
+            pitch_min: -std::f32::consts::PI * 0.35,
+            pitch_max: std::f32::consts::PI * 0.1,
+        }
+    }
+}
+
+impl ThirdPersonCamera {
+    /// Get the camera transform for orbiting around the pivot, pulling the camera in if there's
+    /// geometry between it and the pivot
+    pub fn camera_transform(&self, static_collision: &StaticCollision, pivot: Vector3<f32>, pitch_yaw: Vector2<f32>)
+        -> Matrix4<f32>
+    {
+        let pitch = f32::clamp(pitch_yaw.x, self.pitch_min, self.pitch_max);
+        let orientation = Quaternion::from_axis_angle(vec3(0.0, 1.0, 0.0), Rad(pitch_yaw.y))
+            * Quaternion::from_axis_angle(vec3(1.0, 0.0, 0.0), Rad(pitch));
+
+        // The camera sits behind the player, which is +z in view space
+        let offset = orientation * vec3(0.0, 0.0, self.distance);
+
+        // Spherecast from the pivot to the camera
+        let dir = offset.normalize();
+        let distance = match static_collision.spherecast(pivot, dir, CAMERA_RADIUS, self.distance) {
+            Some(hit) => hit.distance,
+            None => self.distance,
+        };
+
+        let cam_pos = pivot + dir * distance;
+        Matrix4::from_translation(cam_pos) * Matrix4::from(orientation)
+    }
+}
@@ -0,0 +1,162 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Commands, Entity, EventReader, With, Without};
+use bevy_ecs::system::{Res, Query, Local};
+use cgmath::{Vector4, vec4, InnerSpace, SquareMatrix};
+use dreamfield_renderer::components::PlayerCamera;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::PlayerMovement;
+use super::health::{Health, DamageEvent};
+use super::uniform_buffer::{UniformBlock, UniformBuffer, TINT_PARAMS_BINDING};
+
+/// How many tinted entities can be drawn at once, which must match MAX_TINTS in tint_params.glsl
+const MAX_TINTS: usize = 32;
+
+/// How long enemies flash white for when they're hit, in seconds
+const HIT_FLASH_TIME: f32 = 0.15;
+
+/// The Tint component, which multiplies the color and opacity of an entity's Visual, and can push
+/// it towards white for hit flashes. Transparency is drawn by dithering pixels away rather than
+/// blending, like the PS1 did for its screen door transparency. The renderer doesn't know about
+/// tints, so the ps1 shader finds each model's tint by its position, which means it only works for
+/// models whose meshes are at their origin, and the closest MAX_TINTS tinted entities to the camera.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Tint {
+    pub color: Vector4<f32>,
+    /// How far the color is pushed towards white, from 0 to 1
+    pub flash: f32,
+}
+
+impl Default for Tint {
+    fn default() -> Self {
+        Tint::new(vec4(1.0, 1.0, 1.0, 1.0))
+    }
+}
+
+impl Tint {
+    pub fn new(color: Vector4<f32>) -> Self {
+        Tint { color, flash: 0.0 }
+    }
+
+    /// Check whether the tint changes how the Visual is drawn
+    fn is_visible(&self) -> bool {
+        self.color != vec4(1.0, 1.0, 1.0, 1.0) || self.flash > 0.0
+    }
+}
+
+/// The HitFlash component, for an entity that's flashing white after being hit
+#[derive(Component)]
+pub struct HitFlash {
+    remaining: f32,
+}
+
+/// The FadeOut component, for an entity that's fading away, which is despawned once it's gone
+#[derive(Component)]
+pub struct FadeOut {
+    pub duration: f32,
+    remaining: f32,
+}
+
+impl FadeOut {
+    pub fn new(duration: f32) -> Self {
+        FadeOut { duration, remaining: duration }
+    }
+}
+
+/// The hit flash system, which flashes anything except the player white when it takes damage
+pub fn flash_on_hit(mut commands: Commands, sim_time: Res<SimTime>, mut damage_events: EventReader<DamageEvent>,
+    targets: Query<(), (With<Health>, Without<PlayerMovement>)>, mut flashing: Query<(Entity, &mut HitFlash)>,
+    mut tints: Query<&mut Tint>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (entity, mut hit_flash) in flashing.iter_mut() {
+        hit_flash.remaining -= time_delta;
+        if let Ok(mut tint) = tints.get_mut(entity) {
+            tint.flash = f32::max(hit_flash.remaining / HIT_FLASH_TIME, 0.0);
+        }
+        if hit_flash.remaining <= 0.0 {
+            commands.entity(entity).remove::<HitFlash>();
+        }
+    }
+
+    for event in damage_events.iter().filter(|event| targets.get(event.target).is_ok()) {
+        let mut target = commands.entity(event.target);
+        match tints.get_mut(event.target) {
+            Ok(mut tint) => tint.flash = 1.0,
+            Err(_) => {
+                target.insert(Tint { flash: 1.0, ..Tint::default() });
+            },
+        }
+        target.insert(HitFlash { remaining: HIT_FLASH_TIME });
+    }
+}
+
+/// The fade out system, which fades entities away and despawns them at the end
+pub fn update_fade_outs(mut commands: Commands, sim_time: Res<SimTime>,
+    mut query: Query<(Entity, &mut FadeOut, Option<&mut Tint>)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (entity, mut fade_out, tint) in query.iter_mut() {
+        fade_out.remaining -= time_delta;
+        if fade_out.remaining <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let alpha = match fade_out.duration > 0.0 {
+            true => fade_out.remaining / fade_out.duration,
+            false => 0.0,
+        };
+        match tint {
+            Some(mut tint) => tint.color.w = f32::min(tint.color.w, alpha),
+            None => {
+                commands.entity(entity).insert(Tint::new(vec4(1.0, 1.0, 1.0, alpha)));
+            },
+        }
+    }
+}
+
+/// The TintParams uniform block, matching the std140 layout in tint_params.glsl
+#[repr(C)]
+pub struct TintParams {
+    count: [f32; 4],
+    /// The position of each tinted entity, with its flash in w
+    positions: [[f32; 4]; MAX_TINTS],
+    colors: [[f32; 4]; MAX_TINTS],
+}
+
+impl UniformBlock for TintParams {
+    const BINDING: u32 = TINT_PARAMS_BINDING;
+}
+
+/// The tint params system, which uploads the tints of the entities closest to the camera to their
+/// uniform buffer every frame, since they're found by where the entities are drawn. It runs in the
+/// render schedule, since it needs the GL context, after the transforms have been interpolated and
+/// before the scene's drawn, so the positions are the ones this frame's models are drawn at.
+pub fn upload_tint_params(cam_query: Query<&PlayerCamera>, tint_query: Query<(&Tint, &Transform)>,
+    mut buffer: Local<UniformBuffer<TintParams>>)
+{
+    let cam_pos = cam_query.iter().next()
+        .and_then(|cam| cam.view.invert())
+        .map(|cam_transform| cam_transform.w.truncate());
+
+    let mut tints: Vec<(&Tint, &Transform)> = tint_query.iter().filter(|(tint, _)| tint.is_visible()).collect();
+    if let Some(cam_pos) = cam_pos {
+        tints.sort_by(|(_, a), (_, b)| (a.pos - cam_pos).magnitude2().total_cmp(&(b.pos - cam_pos).magnitude2()));
+    }
+    tints.truncate(MAX_TINTS);
+
+    let mut params = TintParams {
+        count: [tints.len() as f32, 0.0, 0.0, 0.0],
+        positions: [[0.0; 4]; MAX_TINTS],
+        colors: [[0.0; 4]; MAX_TINTS],
+    };
+    for (i, (tint, transform)) in tints.iter().enumerate() {
+        params.positions[i] = [transform.pos.x, transform.pos.y, transform.pos.z, f32::clamp(tint.flash, 0.0, 1.0)];
+        params.colors[i] = tint.color.into();
+    }
+    buffer.upload(&params);
+}
@@ -0,0 +1,180 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::system::{Res, ResMut, Query};
+use dreamfield_renderer::components::TextBox;
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::cutscene::CutsceneState;
+use super::determinism::SimRng;
+use super::game_state::{GameState, NextGameState};
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls, EngineInput};
+use super::menu::MenuNav;
+use super::replay::{Replay, ReplayHeader, ReplayFrame};
+use super::locale::Locale;
+
+/// The default number of seconds without input on the title screen before the demo starts
+pub const DEFAULT_ATTRACT_DELAY: f32 = 20.0;
+
+/// How long the "PRESS ANY KEY" prompt takes to blink on and off, in seconds
+const PROMPT_BLINK_PERIOD: f32 = 1.0;
+
+/// The entries in the title menu
+const TITLE_ENTRIES: &'static [TitleEntry] = &[TitleEntry::Start, TitleEntry::Quit];
+
+/// The TitleTextBox component, for the TextBox the title screen is drawn to
+#[derive(Component)]
+pub struct TitleTextBox;
+
+/// The entries in the title menu
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TitleEntry {
+    Start,
+    Quit,
+}
+
+impl TitleEntry {
+    /// Get the locale key for the entry's name
+    pub fn key(&self) -> &'static str {
+        match self {
+            TitleEntry::Start => "title.start",
+            TitleEntry::Quit => "title.quit",
+        }
+    }
+}
+
+/// Where attract mode is up to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AttractState {
+    Off,
+    /// The game is loading to play the demo in
+    Loading,
+    Playing,
+}
+
+/// The TitleScreen resource, with the title menu and attract mode. If nothing's pressed on the
+/// title screen for a while, attract mode loads the game and plays back a bundled demo recording
+/// in it with a prompt over the top. Pressing a key, or the demo finishing, goes back to the
+/// title. The demo is played from a freshly loaded game with the seed it was recorded with.
+pub struct TitleScreen {
+    pub attract: AttractState,
+    /// How long to wait without input before starting the demo, in seconds
+    pub attract_delay: f32,
+    pub selection: usize,
+    nav: MenuNav,
+    demo: Option<(ReplayHeader, Vec<ReplayFrame>)>,
+    idle_time: f32,
+    blink_time: f32,
+    last_input: bool,
+}
+
+impl TitleScreen {
+    /// Create the title screen with the demo recording to play in attract mode
+    pub fn new(demo_source: &str) -> Self {
+        let demo = match Replay::parse(demo_source) {
+            Ok(demo) => Some(demo),
+            Err(err) => {
+                log::error!("Failed to parse attract mode demo, attract mode is disabled: {}", err);
+                None
+            }
+        };
+
+        TitleScreen {
+            attract: AttractState::Off,
+            attract_delay: DEFAULT_ATTRACT_DELAY,
+            selection: 0,
+            nav: MenuNav::default(),
+            demo,
+            idle_time: 0.0,
+            blink_time: 0.0,
+            last_input: false,
+        }
+    }
+}
+
+/// The title screen system, which runs after the gamepad is read but before the replay system
+/// replaces its input, so it only sees the live input
+pub fn update_title_screen(sim_time: Res<SimTime>, input: Res<InputState>, mut gamepad: ResMut<GamepadState>,
+    bindings: Res<InputBindings>, locale: Res<Locale>, state: Res<GameState>, mut next_state: ResMut<NextGameState>,
+    mut title: ResMut<TitleScreen>, mut replay: ResMut<Replay>, mut rng: ResMut<SimRng>,
+    mut cutscene: ResMut<CutsceneState>, mut query: Query<(&TitleTextBox, &mut TextBox)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    // Only react when an input starts, so a key held to leave the demo doesn't do anything else
+    let live_input = EngineInput::capture(&input).any() || gamepad.any();
+    let input_started = live_input && !title.last_input;
+    title.last_input = live_input;
+
+    match (*state, title.attract) {
+        (GameState::Title, AttractState::Off) => {
+            let controls = Controls::new(&input, &gamepad, &bindings);
+            let selection = title.selection;
+            title.selection = title.nav.apply(&controls, selection, TITLE_ENTRIES.len());
+
+            if controls.is_just_pressed(Action::Interact) {
+                match TITLE_ENTRIES[title.selection] {
+                    TitleEntry::Start => next_state.set(GameState::Loading),
+                    TitleEntry::Quit => {
+                        log::info!("Quitting from title screen");
+                        std::process::exit(0);
+                    }
+                }
+            }
+
+            title.idle_time = match live_input {
+                true => 0.0,
+                false => title.idle_time + time_delta,
+            };
+
+            if title.idle_time >= title.attract_delay && title.demo.is_some() {
+                log::info!("Starting attract mode");
+                title.attract = AttractState::Loading;
+                next_state.set(GameState::Loading);
+            }
+        },
+        // Start the demo as soon as the game's running, skipping the intro
+        (GameState::InGame, AttractState::Loading) => {
+            if let Some((header, frames)) = &title.demo {
+                replay.play(frames.clone());
+                *rng = SimRng::new(header.seed);
+                cutscene.active = None;
+            }
+            title.attract = AttractState::Playing;
+        },
+        (_, AttractState::Playing) => {
+            if input_started || !replay.is_playing() {
+                log::info!("Leaving attract mode");
+                replay.stop();
+                gamepad.replayed_input = None;
+                title.attract = AttractState::Off;
+                title.idle_time = 0.0;
+                next_state.set(GameState::Title);
+            }
+        },
+        _ => (),
+    }
+
+    title.blink_time = (title.blink_time + time_delta) % PROMPT_BLINK_PERIOD;
+    let prompt = match title.blink_time < PROMPT_BLINK_PERIOD * 0.6 {
+        true => locale.get("title.prompt"),
+        false => "",
+    };
+
+    for (_, mut text_box) in query.iter_mut() {
+        text_box.text = match (*state, title.attract) {
+            (_, AttractState::Playing) => prompt.to_string(),
+            (GameState::Title, AttractState::Off) => title_text(&title, &locale),
+            _ => String::new(),
+        };
+    }
+}
+
+/// Build the text for the title menu
+fn title_text(title: &TitleScreen, locale: &Locale) -> String {
+    let mut text = format!("{}\n\n", locale.get("title.name"));
+    for (i, entry) in TITLE_ENTRIES.iter().enumerate() {
+        let cursor = if i == title.selection { ">" } else { " " };
+        text += &format!("{} {}\n", cursor, locale.get(entry.key()));
+    }
+    text
+}
@@ -0,0 +1,84 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventWriter, With};
+use bevy_ecs::system::Query;
+use cgmath::{Vector3, InnerSpace};
+use dreamfield_system::components::Transform;
+
+use super::PlayerMovement;
+
+/// The shape of a trigger volume, relative to its entity's Transform position
+#[derive(Clone, Copy, Debug)]
+pub enum TriggerShape {
+    Aabb(Vector3<f32>, Vector3<f32>),
+    Sphere(f32),
+}
+
+/// The TriggerVolume component
+#[derive(Component)]
+pub struct TriggerVolume {
+    pub shape: TriggerShape,
+    player_inside: bool,
+}
+
+impl TriggerVolume {
+    pub fn new_aabb(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        TriggerVolume { shape: TriggerShape::Aabb(min, max), player_inside: false }
+    }
+
+    pub fn new_sphere(radius: f32) -> Self {
+        TriggerVolume { shape: TriggerShape::Sphere(radius), player_inside: false }
+    }
+
+    /// Get whether the player was inside the volume as of the last trigger update
+    pub fn player_inside(&self) -> bool {
+        self.player_inside
+    }
+
+    /// Test whether a point is inside the volume
+    pub fn contains(&self, volume_pos: Vector3<f32>, point: Vector3<f32>) -> bool {
+        let local = point - volume_pos;
+        match self.shape {
+            TriggerShape::Aabb(min, max) => {
+                local.x >= min.x && local.y >= min.y && local.z >= min.z &&
+                    local.x <= max.x && local.y <= max.y && local.z <= max.z
+            },
+            TriggerShape::Sphere(radius) => local.magnitude2() <= radius * radius,
+        }
+    }
+}
+
+/// An event emitted when the player enters a trigger volume
+pub struct TriggerEnterEvent {
+    pub trigger: Entity,
+    pub entity: Entity,
+}
+
+/// An event emitted when the player exits a trigger volume
+pub struct TriggerExitEvent {
+    pub trigger: Entity,
+    pub entity: Entity,
+}
+
+/// The trigger volume system
+pub fn update_triggers(player_query: Query<(Entity, &Transform), With<PlayerMovement>>,
+    mut triggers: Query<(Entity, &mut TriggerVolume, &Transform)>,
+    mut enter_events: EventWriter<TriggerEnterEvent>, mut exit_events: EventWriter<TriggerExitEvent>)
+{
+    let (player_entity, player_pos) = match player_query.get_single() {
+        Ok((entity, transform)) => (entity, transform.pos),
+        Err(_) => return,
+    };
+
+    for (trigger_entity, mut trigger, transform) in triggers.iter_mut() {
+        let inside = trigger.contains(transform.pos, player_pos);
+
+        if inside && !trigger.player_inside {
+            enter_events.send(TriggerEnterEvent { trigger: trigger_entity, entity: player_entity });
+        }
+        else if !inside && trigger.player_inside {
+            exit_events.send(TriggerExitEvent { trigger: trigger_entity, entity: player_entity });
+        }
+
+        trigger.player_inside = inside;
+    }
+}
@@ -0,0 +1,190 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, EventWriter};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, Matrix3, Quaternion};
+use serde::Deserialize;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+/// An easing curve, mapping how far through a tween it is to how far between its values to be
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Easing {
+    Linear,
+    /// Start slowly and speed up
+    EaseIn,
+    /// Start quickly and slow down
+    EaseOut,
+    EaseInOut,
+    /// A gentler ease in and out
+    SmoothStep,
+}
+
+impl Easing {
+    /// Apply the curve to a value from 0 to 1
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => match t < 0.5 {
+                true => 2.0 * t * t,
+                false => 1.0 - 2.0 * (1.0 - t) * (1.0 - t),
+            },
+            Easing::SmoothStep => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// What a tween changes
+#[derive(Clone, Copy, Debug)]
+pub enum TweenTarget {
+    /// The entity's Transform position
+    Position(Vector3<f32>, Vector3<f32>),
+    /// The entity's Transform rotation
+    Rotation(Matrix3<f32>, Matrix3<f32>),
+    /// A value for other systems to read with value(), like how far a UI panel has slid in
+    Value(f32, f32),
+}
+
+/// What a tween does when it reaches the end
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TweenRepeat {
+    Once,
+    /// Start again from the beginning
+    Loop,
+    /// Go back the other way
+    PingPong,
+}
+
+/// The Tween component, which moves something from one value to another over a duration along an
+/// easing curve. Transforms have no scale, so to tween a scale use a Value and apply it. Once it's
+/// finished, the tween stays on the entity at its end value until it's replaced or removed.
+#[derive(Component)]
+pub struct Tween {
+    pub target: TweenTarget,
+    /// How long the tween takes in seconds
+    pub duration: f32,
+    pub easing: Easing,
+    pub repeat: TweenRepeat,
+    /// A tag sent with the TweenCompleteEvent, so whatever started it can tell it's done
+    pub tag: Option<String>,
+    time: f32,
+    reversed: bool,
+    finished: bool,
+}
+
+impl Tween {
+    pub fn new(target: TweenTarget, duration: f32, easing: Easing) -> Self {
+        Tween {
+            target,
+            duration,
+            easing,
+            repeat: TweenRepeat::Once,
+            tag: None,
+            time: 0.0,
+            reversed: false,
+            finished: false,
+        }
+    }
+
+    pub fn with_repeat(self, repeat: TweenRepeat) -> Self {
+        Tween { repeat, ..self }
+    }
+
+    pub fn with_tag(self, tag: &str) -> Self {
+        Tween { tag: Some(tag.to_string()), ..self }
+    }
+
+    /// Get how far between the tween's values it currently is, after easing
+    pub fn progress(&self) -> f32 {
+        let t = match self.duration > 0.0 {
+            true => self.time / self.duration,
+            false => 1.0,
+        };
+        let t = match self.reversed {
+            true => 1.0 - t,
+            false => t,
+        };
+        self.easing.apply(t)
+    }
+
+    /// Get the current value of a Value tween, or the progress of any other tween
+    pub fn value(&self) -> f32 {
+        let t = self.progress();
+        match self.target {
+            TweenTarget::Value(from, to) => from + (to - from) * t,
+            _ => t,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Advance the tween, returning whether it finished or completed another loop
+    fn advance(&mut self, time_delta: f32) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        self.time += time_delta;
+        if self.time < self.duration {
+            return false;
+        }
+
+        match self.repeat {
+            TweenRepeat::Once => {
+                self.time = self.duration;
+                self.finished = true;
+            },
+            TweenRepeat::Loop => self.time = match self.duration > 0.0 {
+                true => self.time % self.duration,
+                false => 0.0,
+            },
+            TweenRepeat::PingPong => {
+                self.time = match self.duration > 0.0 {
+                    true => self.time % self.duration,
+                    false => 0.0,
+                };
+                self.reversed = !self.reversed;
+            },
+        }
+
+        true
+    }
+}
+
+/// An event sent when a tween finishes, or completes a loop if it repeats
+pub struct TweenCompleteEvent {
+    pub entity: Entity,
+    pub tag: Option<String>,
+}
+
+/// The tween system, which advances tweens and applies them to their entities
+pub fn update_tweens(sim_time: Res<SimTime>, mut query: Query<(Entity, &mut Tween, Option<&mut Transform>)>,
+    mut events: EventWriter<TweenCompleteEvent>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+
+    for (entity, mut tween, transform) in query.iter_mut() {
+        if tween.finished() {
+            continue;
+        }
+
+        let completed = tween.advance(time_delta);
+
+        let t = tween.progress();
+        match (tween.target, transform) {
+            (TweenTarget::Position(from, to), Some(mut transform)) => transform.pos = from + (to - from) * t,
+            (TweenTarget::Rotation(from, to), Some(mut transform)) => {
+                transform.rot = Quaternion::from(from).slerp(Quaternion::from(to), t).into();
+            },
+            _ => (),
+        }
+
+        if completed {
+            events.send(TweenCompleteEvent { entity, tag: tween.tag.clone() });
+        }
+    }
+}
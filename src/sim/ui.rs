@@ -0,0 +1,210 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, Changed, Or};
+use bevy_ecs::system::Query;
+use bevy_ecs::world::World;
+use cgmath::{Vector2, vec2};
+use dreamfield_renderer::components::TextBox;
+
+use super::ui_layout::{UiLayout, UiAnchor};
+
+/// The font the widgets are drawn with, where each glyph is a piece of a widget
+pub const UI_FONT: &'static str = "ui";
+
+/// The size of each glyph in the UI font, which is the size of each widget cell
+pub const UI_CELL_SIZE: f32 = 8.0;
+
+/// The first codepoint in the UI font, which is in the private use area
+const UI_GLYPH_BASE: u32 = 0xE000;
+
+/// The glyph indices for the pieces of the widgets, matching ui_glyphs.csv
+const PANEL_GLYPHS: u32 = 0;
+const BAR_GLYPHS: u32 = 9;
+const ICON_GLYPHS: u32 = 18;
+const GRAPH_GLYPHS: u32 = 23;
+
+/// The number of steps each bar cell fills up in
+const BAR_CELL_STEPS: u32 = 8;
+
+/// The number of steps each graph column fills up in
+const GRAPH_STEPS: u32 = 8;
+
+/// The width of each column of a graph, which is narrower than the other glyphs
+const GRAPH_COLUMN_WIDTH: f32 = 2.0;
+
+/// The icons in the UI font
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UiIcon {
+    Heart,
+    Key,
+    Sword,
+    FireOrb,
+    Bolt,
+}
+
+/// The UiWidget component, for a TextBox that draws a widget with the UI font instead of text
+#[derive(Component)]
+pub enum UiWidget {
+    /// A nine-patch panel, sized in cells
+    Panel { cols: usize, rows: usize },
+    /// A horizontal bar with a value from 0 to 1, sized in cells
+    ProgressBar { value: f32, cells: usize },
+    Icon(UiIcon),
+    /// A bar graph one cell high, with a column for each value from 0 to 1
+    Graph { values: Vec<f32> },
+}
+
+/// Whether a widget is drawn
+#[derive(Component)]
+pub struct UiVisible(pub bool);
+
+/// The UiZ component for ordering widgets, where higher values are drawn on top. TextBoxes are
+/// drawn in the order the renderer finds them, so the widgets are re-added in z order when it
+/// changes.
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UiZ(pub i32);
+
+/// Get a glyph from the UI font
+fn glyph(index: u32) -> char {
+    char::from_u32(UI_GLYPH_BASE + index).unwrap_or(' ')
+}
+
+impl UiWidget {
+    /// Get the size of the widget in UI space
+    pub fn size(&self) -> Vector2<f32> {
+        let (cols, rows) = match self {
+            UiWidget::Panel { cols, rows } => (*cols, *rows),
+            UiWidget::ProgressBar { cells, .. } => (*cells, 1),
+            UiWidget::Icon(_) => (1, 1),
+            UiWidget::Graph { values } => return vec2(values.len() as f32 * GRAPH_COLUMN_WIDTH, UI_CELL_SIZE),
+        };
+        vec2(cols as f32, rows as f32) * UI_CELL_SIZE
+    }
+
+    /// Build the glyphs that draw the widget
+    pub fn text(&self) -> String {
+        match self {
+            UiWidget::Panel { cols, rows } => {
+                let (cols, rows) = (usize::max(*cols, 2), usize::max(*rows, 2));
+                let row = |left: u32| {
+                    let mut text = String::new();
+                    text.push(glyph(PANEL_GLYPHS + left));
+                    text.extend(std::iter::repeat(glyph(PANEL_GLYPHS + left + 1)).take(cols - 2));
+                    text.push(glyph(PANEL_GLYPHS + left + 2));
+                    text
+                };
+
+                let mut lines = vec![row(0)];
+                lines.extend(std::iter::repeat(row(3)).take(rows - 2));
+                lines.push(row(6));
+                lines.join("\n")
+            },
+            UiWidget::ProgressBar { value, cells } => {
+                let steps = (f32::clamp(*value, 0.0, 1.0) * (*cells as u32 * BAR_CELL_STEPS) as f32).round() as u32;
+                (0..*cells as u32)
+                    .map(|cell| {
+                        let filled = u32::min(steps.saturating_sub(cell * BAR_CELL_STEPS), BAR_CELL_STEPS);
+                        glyph(BAR_GLYPHS + filled)
+                    })
+                    .collect()
+            },
+            UiWidget::Icon(icon) => glyph(ICON_GLYPHS + *icon as u32).to_string(),
+            UiWidget::Graph { values } => values.iter()
+                .map(|value| glyph(GRAPH_GLYPHS + (f32::clamp(*value, 0.0, 1.0) * GRAPH_STEPS as f32).round() as u32))
+                .collect(),
+        }
+    }
+}
+
+/// A layout helper that stacks widgets from an anchor, for building menus and HUD groups
+pub struct UiStack {
+    anchor: UiAnchor,
+    offset: Vector2<f32>,
+    /// The direction and distance each item moves the next one by, on top of its size
+    step: Vector2<f32>,
+}
+
+impl UiStack {
+    /// Stack items downwards
+    pub fn vertical(anchor: UiAnchor, offset: Vector2<f32>, spacing: f32) -> Self {
+        UiStack { anchor, offset, step: vec2(0.0, spacing) }
+    }
+
+    /// Stack items to the right
+    pub fn horizontal(anchor: UiAnchor, offset: Vector2<f32>, spacing: f32) -> Self {
+        UiStack { anchor, offset, step: vec2(spacing, 0.0) }
+    }
+
+    /// Get the layout for the next item
+    pub fn next(&mut self, size: Vector2<f32>) -> UiLayout {
+        let layout = UiLayout::anchored(self.anchor, self.offset, size);
+        let advance = match self.step.x != 0.0 {
+            true => vec2(size.x + self.step.x, 0.0),
+            false => vec2(0.0, size.y + self.step.y),
+        };
+
+        // Stacks anchored to the right or bottom grow back towards the middle of the screen
+        let fraction = self.anchor.fraction();
+        let dir = vec2(if fraction.x >= 1.0 { -1.0 } else { 1.0 }, if fraction.y >= 1.0 { -1.0 } else { 1.0 });
+        self.offset += vec2(advance.x * dir.x, advance.y * dir.y);
+
+        layout
+    }
+}
+
+/// Spawn a widget at a layout, returning the entity so marker components can be added to it
+pub fn spawn_widget(world: &mut World, widget: UiWidget, layout: UiLayout, z: i32) -> Entity {
+    world.spawn()
+        .insert(TextBox::new("text", UI_FONT, UI_FONT, "", None, None))
+        .insert(layout)
+        .insert(widget)
+        .insert(UiVisible(true))
+        .insert(UiZ(z))
+        .id()
+}
+
+/// Spawn an icon with a progress bar to its right, in the space of a layout
+pub fn spawn_icon_bar(world: &mut World, icon: UiIcon, layout: &UiLayout, cells: usize, z: i32) -> [Entity; 2] {
+    let icon = UiWidget::Icon(icon);
+    let icon_layout = layout.child(vec2(0.0, 0.0), icon.size());
+    let icon = spawn_widget(world, icon, icon_layout, z);
+
+    let bar = UiWidget::ProgressBar { value: 1.0, cells };
+    let bar_layout = layout.child(vec2(UI_CELL_SIZE, 0.0), bar.size());
+    let bar = spawn_widget(world, bar, bar_layout, z);
+
+    [icon, bar]
+}
+
+/// The widget system, which rebuilds widget text when they change
+pub fn update_ui_widgets(mut query: Query<(&UiWidget, &UiVisible, &mut TextBox),
+    Or<(Changed<UiWidget>, Changed<UiVisible>)>>)
+{
+    for (widget, visible, mut text_box) in query.iter_mut() {
+        text_box.text = match visible.0 {
+            true => widget.text(),
+            false => String::new(),
+        };
+    }
+}
+
+/// The widget ordering system, which re-adds the widgets' TextBoxes in z order when any z changes.
+/// It needs to move components around, so it's an exclusive system.
+pub fn update_ui_order(world: &mut World) {
+    let mut changed = world.query_filtered::<(), Changed<UiZ>>();
+    if changed.iter(world).next().is_none() {
+        return;
+    }
+
+    let mut widgets: Vec<(UiZ, Entity)> = world.query::<(Entity, &UiZ)>()
+        .iter(world)
+        .map(|(entity, z)| (*z, entity))
+        .collect();
+    widgets.sort();
+
+    for (_, entity) in widgets {
+        let mut entity = world.entity_mut(entity);
+        if let Some(text_box) = entity.remove::<TextBox>() {
+            entity.insert(text_box);
+        }
+    }
+}
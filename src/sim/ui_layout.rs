@@ -0,0 +1,114 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{EventReader, ChangeTrackers};
+use bevy_ecs::system::{Local, Query};
+use cgmath::{Vector2, Vector4, vec2};
+use dreamfield_renderer::components::TextBox;
+
+/// The height of the UI space. Its width follows the render aspect ratio, so it's 320 at 4:3.
+pub const UI_HEIGHT: f32 = 240.0;
+
+/// The width of the UI space at 4:3, which fixed bounds are given in
+const UI_REFERENCE_WIDTH: f32 = 320.0;
+
+/// The point of the screen a UI element is anchored to. The element's own matching point is placed
+/// at the anchor, so a bottom-right anchored element grows up and to the left.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UiAnchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl UiAnchor {
+    /// Get the anchor as a fraction of the screen size
+    pub fn fraction(&self) -> Vector2<f32> {
+        match self {
+            UiAnchor::TopLeft => vec2(0.0, 0.0),
+            UiAnchor::Top => vec2(0.5, 0.0),
+            UiAnchor::TopRight => vec2(1.0, 0.0),
+            UiAnchor::Left => vec2(0.0, 0.5),
+            UiAnchor::Center => vec2(0.5, 0.5),
+            UiAnchor::Right => vec2(1.0, 0.5),
+            UiAnchor::BottomLeft => vec2(0.0, 1.0),
+            UiAnchor::Bottom => vec2(0.5, 1.0),
+            UiAnchor::BottomRight => vec2(1.0, 1.0),
+        }
+    }
+}
+
+/// An event emitted when the render resolution changes, so the UI can be laid out again
+pub struct ResolutionChangedEvent {
+    pub render_res: Vector2<f32>,
+}
+
+/// The UiLayout component, which positions an entity's TextBox relative to an anchor
+#[derive(Component)]
+pub struct UiLayout {
+    pub anchor: UiAnchor,
+    /// The offset from the anchor in UI space
+    pub offset: Vector2<f32>,
+    pub size: Vector2<f32>,
+}
+
+impl UiLayout {
+    /// Create a layout from fixed 4:3 bounds, which stay in the middle of wider screens
+    pub fn new(bounds: Vector4<f32>) -> Self {
+        let size = vec2(bounds.z - bounds.x, bounds.w - bounds.y);
+        let offset = vec2(bounds.x, bounds.y) + size * 0.5 - vec2(UI_REFERENCE_WIDTH, UI_HEIGHT) * 0.5;
+        UiLayout::anchored(UiAnchor::Center, offset, size)
+    }
+
+    pub fn anchored(anchor: UiAnchor, offset: Vector2<f32>, size: Vector2<f32>) -> Self {
+        UiLayout { anchor, offset, size }
+    }
+
+    /// Get a layout with the same anchor for an element inside this one, at a position relative to
+    /// this one's top left
+    pub fn child(&self, pos: Vector2<f32>, size: Vector2<f32>) -> UiLayout {
+        let fraction = self.anchor.fraction();
+        let pivot_shift = vec2((size.x - self.size.x) * fraction.x, (size.y - self.size.y) * fraction.y);
+        UiLayout::anchored(self.anchor, self.offset + pos + pivot_shift, size)
+    }
+
+    /// Get the bounds as (min x, min y, max x, max y) in a UI space of the given size
+    pub fn bounds(&self, ui_size: Vector2<f32>) -> Vector4<f32> {
+        let fraction = self.anchor.fraction();
+        let anchor_pos = vec2(ui_size.x * fraction.x, ui_size.y * fraction.y);
+        let min = anchor_pos + self.offset - vec2(self.size.x * fraction.x, self.size.y * fraction.y);
+        Vector4::new(min.x, min.y, min.x + self.size.x, min.y + self.size.y)
+    }
+}
+
+/// Get the size of the UI space for a render resolution
+pub fn ui_size(render_res: Vector2<f32>) -> Vector2<f32> {
+    match render_res.y > 0.0 {
+        true => vec2(UI_HEIGHT * render_res.x / render_res.y, UI_HEIGHT),
+        false => vec2(UI_REFERENCE_WIDTH, UI_HEIGHT),
+    }
+}
+
+/// The UI layout system, which recomputes the TextBox bounds when the resolution changes, and for
+/// any layouts that are new or have been changed
+pub fn update_ui_layout(mut ui_size_state: Local<Option<Vector2<f32>>>, mut events: EventReader<ResolutionChangedEvent>,
+    mut query: Query<(&UiLayout, ChangeTrackers<UiLayout>, &mut TextBox)>)
+{
+    let mut resized = false;
+    for event in events.iter() {
+        *ui_size_state = Some(ui_size(event.render_res));
+        resized = true;
+    }
+
+    let ui_size = ui_size_state.unwrap_or(vec2(UI_REFERENCE_WIDTH, UI_HEIGHT));
+
+    for (layout, tracker, mut text_box) in query.iter_mut() {
+        if resized || tracker.is_changed() {
+            text_box.bounds = Some(layout.bounds(ui_size));
+        }
+    }
+}
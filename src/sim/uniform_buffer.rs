@@ -0,0 +1,60 @@
+use std::marker::PhantomData;
+
+/// The binding points of the template's uniform blocks, which must match the bindings they're
+/// declared with in their glsl includes. They start at 8 to stay clear of the engine's blocks.
+pub const RETRO_PARAMS_BINDING: u32 = 8;
+pub const FADE_PARAMS_BINDING: u32 = 9;
+pub const WATER_PARAMS_BINDING: u32 = 10;
+pub const SKY_PARAMS_BINDING: u32 = 11;
+pub const DAMAGE_PARAMS_BINDING: u32 = 12;
+pub const TINT_PARAMS_BINDING: u32 = 13;
+pub const TEXT_STYLE_PARAMS_BINDING: u32 = 14;
+pub const CRT_PARAMS_BINDING: u32 = 15;
+pub const QUANTIZE_PARAMS_BINDING: u32 = 16;
+
+/// A uniform block, laid out to match the std140 layout of its block in glsl
+pub trait UniformBlock: Send + Sync + 'static {
+    /// The binding point the block is declared with
+    const BINDING: u32;
+}
+
+/// A uniform buffer holding a uniform block, which is created the first time it's uploaded and
+/// bound to the block's binding point. It's kept in a Local by the render system that uploads it,
+/// since it needs the GL context.
+pub struct UniformBuffer<T: UniformBlock> {
+    buffer: Option<u32>,
+    block: PhantomData<T>,
+}
+
+impl<T: UniformBlock> Default for UniformBuffer<T> {
+    fn default() -> Self {
+        UniformBuffer {
+            buffer: None,
+            block: PhantomData,
+        }
+    }
+}
+
+impl<T: UniformBlock> UniformBuffer<T> {
+    /// Check whether the block's been uploaded yet
+    pub fn is_uploaded(&self) -> bool {
+        self.buffer.is_some()
+    }
+
+    /// Upload the block to the buffer, creating it first if it doesn't exist yet
+    pub fn upload(&mut self, block: &T) {
+        unsafe {
+            let buffer = *self.buffer.get_or_insert_with(|| {
+                let mut buffer = 0;
+                gl::GenBuffers(1, &mut buffer);
+                buffer
+            });
+
+            gl::BindBuffer(gl::UNIFORM_BUFFER, buffer);
+            gl::BufferData(gl::UNIFORM_BUFFER, std::mem::size_of::<T>() as isize,
+                block as *const T as *const _, gl::DYNAMIC_DRAW);
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, T::BINDING, buffer);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+}
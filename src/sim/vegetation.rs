@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Commands, Entity};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Matrix3, Rad, Vector2, Vector3, vec2, vec3, InnerSpace, SquareMatrix};
+use dreamfield_renderer::components::{PlayerCamera, Visual};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::graphics_settings::GraphicsSettings;
+use super::level::Levels;
+use super::weather::Weather;
+
+/// The size of the square cells instances are spawned and despawned in
+const CELL_SIZE: f32 = 16.0;
+
+/// How far vegetation leans over in the wind at full strength, in radians
+const SWAY_ANGLE: f32 = 0.12;
+
+/// How fast vegetation sways back and forth, in radians per second
+const SWAY_SPEED: f32 = 1.8;
+
+/// One instance from a baked scatter
+struct VegetationInstance {
+    pos: Vector3<f32>,
+    yaw: f32,
+    scale: f32,
+    /// Where the instance falls in the density, from 0 to 1, so the same instances are kept when
+    /// the density's turned down
+    cutoff: f32,
+    /// Where the instance starts in its sway, so they don't all sway in step
+    phase: f32,
+}
+
+/// A cell of a level's vegetation
+struct VegetationCell {
+    level: String,
+    centre: Vector2<f32>,
+    visual: String,
+    instances: Vec<VegetationInstance>,
+}
+
+/// The Vegetation resource, with the vegetation build.rs scattered across each level's ground. The
+/// renderer can't instance models or draw a Visual with its own shader, so the instances are
+/// spawned as entities a cell at a time around the camera, and sway in the wind by leaning their
+/// transforms over. The graphics settings set how far out it's drawn and how much of it is.
+pub struct Vegetation {
+    cells: Vec<VegetationCell>,
+}
+
+impl Vegetation {
+    /// Create the vegetation from a list of (level, source) pairs of scatters, in the format
+    /// written by bake_vegetation
+    pub fn new(scatters: Vec<(&str, &str)>) -> Self {
+        let mut cells = Vec::new();
+        for (level, source) in scatters {
+            if Self::parse_scatter(level, source, &mut cells).is_none() {
+                log::error!("Failed to parse a vegetation scatter for {}", level);
+            }
+        }
+
+        Vegetation { cells }
+    }
+
+    /// Parse a scatter, adding its instances to the cells they're in
+    fn parse_scatter(level: &str, source: &str, cells: &mut Vec<VegetationCell>) -> Option<()> {
+        let mut lines = source.lines();
+        let visual = lines.next()?.trim().to_string();
+
+        for (index, line) in lines.filter(|line| !line.trim().is_empty()).enumerate() {
+            let values: Vec<f32> = line.split_whitespace().map(str::parse).collect::<Result<_, _>>().ok()?;
+            let [x, y, z, yaw, scale] = <[f32; 5]>::try_from(values).ok()?;
+
+            let cell = vec2((x / CELL_SIZE).floor() + 0.5, (z / CELL_SIZE).floor() + 0.5) * CELL_SIZE;
+            let cell_index = match cells.iter().position(|other| other.level == level && other.visual == visual && other.centre == cell) {
+                Some(cell_index) => cell_index,
+                None => {
+                    cells.push(VegetationCell { level: level.to_string(), centre: cell, visual: visual.clone(), instances: Vec::new() });
+                    cells.len() - 1
+                }
+            };
+
+            // The scatter's already random, so the instance's index is scrambled for its cutoff
+            let hash = (index as u32).wrapping_mul(2654435761);
+            cells[cell_index].instances.push(VegetationInstance {
+                pos: vec3(x, y, z),
+                yaw,
+                scale,
+                cutoff: hash as f32 / u32::MAX as f32,
+                phase: yaw * 3.0,
+            });
+        }
+
+        Some(())
+    }
+}
+
+/// The VegetationSway component, for a spawned vegetation instance
+#[derive(Component)]
+pub struct VegetationSway {
+    cell: usize,
+    yaw: f32,
+    scale: f32,
+    phase: f32,
+    /// The density it was spawned at, so it's respawned when that changes
+    density: f32,
+}
+
+/// The vegetation system, which spawns the cells of the current level's vegetation that are in
+/// range of the camera, despawns the rest, and sways the instances in the weather's wind
+pub fn update_vegetation(mut commands: Commands, vegetation: Res<Vegetation>, settings: Res<GraphicsSettings>,
+    levels: Res<Levels>, weather: Res<Weather>, sim_time: Res<SimTime>, cam_query: Query<&PlayerCamera>,
+    mut instance_query: Query<(Entity, &VegetationSway, &mut Transform)>)
+{
+    let cam_pos = match cam_query.iter().next().and_then(|cam| cam.view.invert()) {
+        Some(cam_transform) => cam_transform.w.truncate(),
+        None => return,
+    };
+
+    let level = levels.current();
+    let in_range = |cell: &VegetationCell| {
+        Some(cell.level.as_str()) == level
+            && (cell.centre - vec2(cam_pos.x, cam_pos.z)).magnitude() < settings.vegetation_distance
+    };
+
+    // Lean over along the wind, swaying back and forth more the stronger it is
+    let wind = weather.wind();
+    let strength = match weather.wind_speed > 0.0 {
+        true => f32::min(wind.magnitude() / weather.wind_speed, 1.0),
+        false => 0.0,
+    };
+    let axis = match wind.magnitude2() > 0.0 {
+        true => vec3(0.0, 1.0, 0.0).cross(wind).normalize(),
+        false => vec3(1.0, 0.0, 0.0),
+    };
+    let time = sim_time.sim_time as f32;
+
+    let mut spawned = HashSet::new();
+    for (entity, sway, mut transform) in instance_query.iter_mut() {
+        if sway.density != settings.vegetation_density || !in_range(&vegetation.cells[sway.cell]) {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        spawned.insert(sway.cell);
+
+        let lean = SWAY_ANGLE * strength * (0.6 + 0.4 * (time * SWAY_SPEED + sway.phase).sin());
+        transform.rot = Matrix3::from_axis_angle(axis, Rad(lean))
+            * Matrix3::from_angle_y(Rad(sway.yaw))
+            * Matrix3::from_scale(sway.scale);
+    }
+
+    for (index, cell) in vegetation.cells.iter().enumerate() {
+        if spawned.contains(&index) || !in_range(cell) {
+            continue;
+        }
+
+        for instance in cell.instances.iter().filter(|instance| instance.cutoff < settings.vegetation_density) {
+            commands.spawn()
+                .insert(VegetationSway {
+                    cell: index,
+                    yaw: instance.yaw,
+                    scale: instance.scale,
+                    phase: instance.phase,
+                    density: settings.vegetation_density,
+                })
+                .insert(Transform::new(instance.pos, Matrix3::from_angle_y(Rad(instance.yaw)) * Matrix3::from_scale(instance.scale)))
+                .insert(Visual::new(&cell.visual, false));
+        }
+    }
+}
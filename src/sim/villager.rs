@@ -0,0 +1,108 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::system::{Res, ResMut, Query};
+use cgmath::{Vector3, Matrix3, vec3};
+use serde::Deserialize;
+
+use super::daynight::TimeOfDay;
+use super::determinism::SimRng;
+use super::nav::NavMesh;
+use super::npc::Npc;
+
+/// The number of points a villager wanders between around each schedule location
+const WANDER_POINTS: usize = 4;
+
+/// A window of a villager's day, and what they do during it
+#[derive(Deserialize, Clone, Debug)]
+pub struct ScheduleEntry {
+    /// The hours the window starts and ends at, which can wrap around midnight
+    pub start_hour: f32,
+    pub end_hour: f32,
+    /// Where the villager spends the window, relative to their home
+    pub location: (f32, f32, f32),
+    /// How far from the location they wander
+    #[serde(default)]
+    pub wander_radius: f32,
+    /// The animation they play while standing around
+    pub animation: String,
+}
+
+impl ScheduleEntry {
+    /// Check whether the window includes an hour
+    pub fn contains(&self, hour: f32) -> bool {
+        match self.start_hour <= self.end_hour {
+            true => hour >= self.start_hour && hour < self.end_hour,
+            false => hour >= self.start_hour || hour < self.end_hour,
+        }
+    }
+}
+
+/// The DailySchedule component, for ambient villagers whose day is split into windows they spend in
+/// different places. It drives the villager's Npc, replacing its waypoints with points on the nav
+/// mesh around the current location whenever the window changes, so it also needs a Behavior that
+/// patrols.
+#[derive(Component)]
+pub struct DailySchedule {
+    /// Where the schedule's locations are relative to, and the rotation they rotate with
+    pub home: Vector3<f32>,
+    pub home_rot: Matrix3<f32>,
+    pub entries: Vec<ScheduleEntry>,
+    current_entry: Option<usize>,
+}
+
+impl DailySchedule {
+    pub fn new(home: Vector3<f32>, home_rot: Matrix3<f32>, entries: Vec<ScheduleEntry>) -> Self {
+        DailySchedule {
+            home,
+            home_rot,
+            entries,
+            current_entry: None,
+        }
+    }
+
+    /// Get the entry that's active at an hour, which is the first one that contains it
+    pub fn active_entry(&self, hour: f32) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.contains(hour))
+    }
+
+    /// Get an entry's location in the world
+    pub fn location(&self, entry: &ScheduleEntry) -> Vector3<f32> {
+        let (x, y, z) = entry.location;
+        self.home + self.home_rot * vec3(x, y, z)
+    }
+}
+
+/// The villager system, which sends villagers to wherever their schedule says they should be
+pub fn update_villagers(time_of_day: Res<TimeOfDay>, nav_mesh: Res<NavMesh>, mut rng: ResMut<SimRng>,
+    mut query: Query<(&mut DailySchedule, &mut Npc)>)
+{
+    for (mut schedule, mut npc) in query.iter_mut() {
+        let active_entry = schedule.active_entry(time_of_day.hour);
+        if active_entry == schedule.current_entry {
+            continue;
+        }
+        schedule.current_entry = active_entry;
+
+        let entry = match active_entry {
+            Some(entry) => schedule.entries[entry].clone(),
+            None => {
+                npc.waypoints.clear();
+                continue;
+            }
+        };
+
+        // Wander between random points around the location, as long as they're somewhere that can be
+        // walked to
+        let location = schedule.location(&entry);
+        let mut waypoints = vec![location];
+        if entry.wander_radius > 0.0 {
+            waypoints.extend((0..WANDER_POINTS).filter_map(|_| {
+                let angle = rng.next() * std::f32::consts::TAU;
+                let distance = entry.wander_radius * rng.next().sqrt();
+                nav_mesh.nearest_point(location + vec3(angle.cos(), 0.0, angle.sin()) * distance)
+            }));
+        }
+
+        npc.waypoints = waypoints;
+        npc.idle_animation = entry.animation;
+    }
+}
@@ -0,0 +1,182 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, Added, With, Without};
+use bevy_ecs::system::{Commands, Local, Res, Query};
+use cgmath::{Matrix3, Vector2, Vector3, vec2, vec3};
+use dreamfield_renderer::components::{PlayerCamera, Visual};
+use dreamfield_system::components::Transform;
+
+use super::{PlayerMovement, PlayerMovementMode};
+use super::trigger::{TriggerShape, TriggerVolume};
+use super::uniform_buffer::{UniformBlock, UniformBuffer, WATER_PARAMS_BINDING};
+
+/// The height above the player's feet at which they stop swimming and start wading instead
+const SWIM_EXIT_HEIGHT: f32 = 1.1;
+
+/// The model water surfaces are drawn with, a unit square plane generated by build.rs
+const WATER_PLANE_MODEL: &'static str = "water_plane";
+
+/// The WaterVolume component. Water volumes need a TriggerVolume for their extents, and the player
+/// swims while inside one.
+#[derive(Component)]
+pub struct WaterVolume {
+    /// The fog color while the camera is underwater
+    pub fog_color: Vector3<f32>,
+    /// The fog range while the camera is underwater
+    pub fog_range: Vector2<f32>,
+}
+
+impl Default for WaterVolume {
+    fn default() -> Self {
+        WaterVolume {
+            fog_color: vec3(0.05, 0.15, 0.2),
+            fog_range: vec2(2.0, 12.0),
+        }
+    }
+}
+
+/// The WaterSettings resource, for how the water shader animates water surfaces
+pub struct WaterSettings {
+    /// How fast the surface pattern scrolls, in world units per second
+    pub scroll: Vector2<f32>,
+    /// How far the ripples move the surface up and down
+    pub ripple_height: f32,
+    /// The distance between ripples
+    pub ripple_length: f32,
+    /// How fast the ripples move, in radians per second
+    pub ripple_speed: f32,
+    /// How many flat bands the surface's shading is quantized into
+    pub bands: f32,
+    /// How opaque the surface is, before it fades into the fog
+    pub alpha: f32,
+}
+
+impl Default for WaterSettings {
+    fn default() -> Self {
+        WaterSettings {
+            scroll: vec2(0.3, 0.15),
+            ripple_height: 0.08,
+            ripple_length: 3.0,
+            ripple_speed: 1.5,
+            bands: 4.0,
+            alpha: 0.7,
+        }
+    }
+}
+
+/// The WaterSurface component, for the entity that draws the surface of a water volume. Surfaces
+/// are spawned for water volumes with a box TriggerVolume, across the top of the box, and despawned
+/// along with their volume. They're meant to be drawn with the water shader, but the renderer
+/// draws every Visual with the ps1 shaders, so until a Visual can be given its own shader they're
+/// drawn as the plane's plain translucent color, without the ripples or bands.
+#[derive(Component)]
+pub struct WaterSurface {
+    pub volume: Entity,
+}
+
+/// The water surface system, which spawns a surface for each new water volume and despawns the
+/// surfaces of volumes that have gone
+pub fn spawn_water_surfaces(mut commands: Commands,
+    new_volumes: Query<(Entity, &TriggerVolume, &Transform), Added<WaterVolume>>,
+    volumes: Query<(), With<WaterVolume>>, surfaces: Query<(Entity, &WaterSurface)>)
+{
+    for (surface, WaterSurface { volume }) in surfaces.iter() {
+        if volumes.get(*volume).is_err() {
+            commands.entity(surface).despawn();
+        }
+    }
+
+    for (volume, trigger, transform) in new_volumes.iter() {
+        let (min, max) = match trigger.shape {
+            TriggerShape::Aabb(min, max) => (min, max),
+            _ => continue,
+        };
+        if max.x <= min.x || max.z <= min.z {
+            continue;
+        }
+
+        let pos = transform.pos + vec3((min.x + max.x) * 0.5, max.y, (min.z + max.z) * 0.5);
+        let scale = Matrix3::from_diagonal(vec3(max.x - min.x, 1.0, max.z - min.z));
+        commands.spawn()
+            .insert(WaterSurface { volume })
+            .insert(Transform::new(pos, scale))
+            .insert(Visual::new(WATER_PLANE_MODEL, false));
+    }
+}
+
+/// The WaterParams uniform block, matching the std140 layout in water_params.glsl
+#[repr(C)]
+pub struct WaterParams {
+    valid: f32,
+    bands: f32,
+    alpha: f32,
+    camera_underwater: f32,
+    scroll: [f32; 2],
+    ripple_height: f32,
+    ripple_length: f32,
+    ripple_speed: f32,
+    _padding: [f32; 3],
+}
+
+impl UniformBlock for WaterParams {
+    const BINDING: u32 = WATER_PARAMS_BINDING;
+}
+
+/// The water params system, which uploads the WaterSettings and whether the camera's underwater to
+/// their uniform buffer. It runs in the render schedule, since it needs the GL context.
+pub fn upload_water_params(settings: Res<WaterSettings>, player_query: Query<&PlayerMovement>,
+    mut buffer: Local<UniformBuffer<WaterParams>>, mut last_underwater: Local<bool>)
+{
+    let camera_underwater = player_query.iter().any(|player_movement| player_movement.head_underwater);
+    if buffer.is_uploaded() && !settings.is_changed() && *last_underwater == camera_underwater {
+        return;
+    }
+    *last_underwater = camera_underwater;
+
+    let params = WaterParams {
+        valid: 1.0,
+        bands: f32::max(settings.bands, 1.0),
+        alpha: f32::clamp(settings.alpha, 0.0, 1.0),
+        camera_underwater: if camera_underwater { 1.0 } else { 0.0 },
+        scroll: [settings.scroll.x, settings.scroll.y],
+        ripple_height: settings.ripple_height,
+        ripple_length: f32::max(settings.ripple_length, 0.01),
+        ripple_speed: settings.ripple_speed,
+        _padding: [0.0; 3],
+    };
+    buffer.upload(&params);
+}
+
+/// The swimming system, which switches the player in and out of swimming mode and applies the
+/// underwater fog. It runs after the time of day update so that the fog override sticks.
+pub fn update_swimming(mut player_query: Query<(&Transform, &mut PlayerMovement, &mut PlayerCamera)>,
+    water_query: Query<(&WaterVolume, &TriggerVolume, &Transform), Without<PlayerMovement>>)
+{
+    for (transform, mut player_movement, mut cam) in player_query.iter_mut() {
+        let eye_pos = transform.pos + vec3(0.0, player_movement.eye_height(), 0.0);
+        let chest_pos = transform.pos + vec3(0.0, SWIM_EXIT_HEIGHT, 0.0);
+
+        let head_water = water_query.iter()
+            .find(|(_, trigger, water_transform)| trigger.contains(water_transform.pos, eye_pos))
+            .map(|(water, _, _)| water);
+        let chest_in_water = water_query.iter()
+            .any(|(_, trigger, water_transform)| trigger.contains(water_transform.pos, chest_pos));
+
+        player_movement.head_underwater = head_water.is_some();
+
+        // Start swimming when the player's head goes under, and stop once they're mostly out of the
+        // water, so that they can float at the surface
+        let new_mode = match player_movement.movement_mode {
+            PlayerMovementMode::Normal if head_water.is_some() => Some(PlayerMovementMode::Swimming),
+            PlayerMovementMode::Swimming if !chest_in_water => Some(PlayerMovementMode::Normal),
+            _ => None,
+        };
+        if let Some(mode) = new_mode {
+            player_movement.movement_mode = mode;
+        }
+
+        if let Some(water) = head_water {
+            cam.fog_color = water.fog_color;
+            cam.fog_range = water.fog_range;
+        }
+    }
+}
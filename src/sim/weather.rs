@@ -0,0 +1,310 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{With, Without};
+use bevy_ecs::system::{Commands, Res, ResMut, Query};
+use cgmath::{Matrix3, SquareMatrix, Vector2, Vector3, vec2, vec3, InnerSpace, Zero};
+use dreamfield_renderer::components::PlayerCamera;
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::SimTime;
+
+use super::audio::AudioSource;
+use super::determinism::SimRng;
+use super::level::Levels;
+use super::particles::ParticleEmitter;
+use super::player_movement::PlayerMovement;
+
+/// How far above the player rain is spawned
+const RAIN_HEIGHT: f32 = 8.0;
+
+/// How many rain particles are spawned per second at full strength
+const RAIN_SPAWN_RATE: f32 = 120.0;
+
+/// How fast the wind gusts, in radians per second
+const GUST_SPEED: f32 = 0.7;
+
+/// How much the wind's strength varies with the gusts
+const GUST_AMOUNT: f32 = 0.35;
+
+/// A kind of weather
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WeatherKind {
+    Clear,
+    Rain,
+    Fog,
+    Storm,
+}
+
+impl WeatherKind {
+    const ALL: [WeatherKind; 4] = [WeatherKind::Clear, WeatherKind::Rain, WeatherKind::Fog, WeatherKind::Storm];
+
+    /// Get a kind of weather by the name scripts use for it
+    pub fn from_name(name: &str) -> Option<Self> {
+        WeatherKind::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeatherKind::Clear => "clear",
+            WeatherKind::Rain => "rain",
+            WeatherKind::Fog => "fog",
+            WeatherKind::Storm => "storm",
+        }
+    }
+
+    /// Get what the weather's like once it's fully arrived
+    fn params(&self) -> WeatherParams {
+        match self {
+            WeatherKind::Clear => WeatherParams::default(),
+            WeatherKind::Rain => WeatherParams {
+                fog_distance: 0.7,
+                fog_color: vec3(0.3, 0.32, 0.36),
+                fog_color_amount: 0.5,
+                rain: 0.6,
+                wind: 0.4,
+            },
+            WeatherKind::Fog => WeatherParams {
+                fog_distance: 0.35,
+                fog_color: vec3(0.5, 0.52, 0.55),
+                fog_color_amount: 0.7,
+                rain: 0.0,
+                wind: 0.1,
+            },
+            WeatherKind::Storm => WeatherParams {
+                fog_distance: 0.5,
+                fog_color: vec3(0.15, 0.16, 0.2),
+                fog_color_amount: 0.7,
+                rain: 1.0,
+                wind: 1.0,
+            },
+        }
+    }
+}
+
+/// What the weather's doing, which is blended between kinds of weather as they change
+#[derive(Clone, Copy, Debug)]
+pub struct WeatherParams {
+    /// How far the fog range is scaled to, with 1 for the clear fog range
+    pub fog_distance: f32,
+    /// The color the fog is tinted towards
+    pub fog_color: Vector3<f32>,
+    /// How far the fog is tinted towards the weather's fog color, from 0 to 1. The fog color's
+    /// scaled by the daylight first so that foggy nights are still dark.
+    pub fog_color_amount: f32,
+    /// How hard it's raining, from 0 to 1
+    pub rain: f32,
+    /// How windy it is, from 0 to 1
+    pub wind: f32,
+}
+
+impl Default for WeatherParams {
+    fn default() -> Self {
+        WeatherParams {
+            fog_distance: 1.0,
+            fog_color: vec3(0.35, 0.4, 0.5),
+            fog_color_amount: 0.0,
+            rain: 0.0,
+            wind: 0.15,
+        }
+    }
+}
+
+impl WeatherParams {
+    fn lerp(&self, other: &WeatherParams, t: f32) -> Self {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        WeatherParams {
+            fog_distance: lerp(self.fog_distance, other.fog_distance),
+            fog_color: self.fog_color + (other.fog_color - self.fog_color) * t,
+            fog_color_amount: lerp(self.fog_color_amount, other.fog_color_amount),
+            rain: lerp(self.rain, other.rain),
+            wind: lerp(self.wind, other.wind),
+        }
+    }
+}
+
+/// The Weather resource. The weather changes to another kind every so often by itself, or when a
+/// script sets it, and blends from one to the other over the transition time. It drives the rain,
+/// the fog on the camera, the rain and wind ambience, and the wind vegetation sways in. Indoor
+/// levels don't get any of it, apart from the ambience, which is muffled instead.
+pub struct Weather {
+    /// The weather it's changing to, or has changed to
+    kind: WeatherKind,
+    /// The weather as it was when the last change started
+    from: WeatherParams,
+    /// How far through the last change the weather is, from 0 to 1
+    transition: f32,
+    /// How many seconds changing weather takes
+    pub transition_time: f32,
+    /// Whether the weather changes by itself
+    pub automatic: bool,
+    /// The range of seconds each weather lasts for before it changes by itself
+    pub duration_range: Vector2<f32>,
+    /// The seconds until the weather next changes by itself
+    time_until_change: f32,
+    /// The direction the wind blows in, on the xz plane
+    pub wind_direction: Vector2<f32>,
+    /// The wind speed at full strength, in world units per second
+    pub wind_speed: f32,
+    /// How long the weather's been running, for the gusts
+    time: f32,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Weather {
+            kind: WeatherKind::Clear,
+            from: WeatherParams::default(),
+            transition: 1.0,
+            transition_time: 20.0,
+            automatic: true,
+            duration_range: vec2(120.0, 300.0),
+            time_until_change: 180.0,
+            wind_direction: vec2(0.8, 0.6),
+            wind_speed: 6.0,
+            time: 0.0,
+        }
+    }
+}
+
+impl Weather {
+    /// Get the weather it's changing to, or has changed to
+    pub fn kind(&self) -> WeatherKind {
+        self.kind
+    }
+
+    /// Start changing the weather, from however it is right now
+    pub fn set(&mut self, kind: WeatherKind) {
+        if kind == self.kind {
+            return;
+        }
+
+        log::info!("Weather changing to {}", kind.name());
+        self.from = self.params();
+        self.kind = kind;
+        self.transition = 0.0;
+    }
+
+    /// Get what the weather's doing right now
+    pub fn params(&self) -> WeatherParams {
+        let t = self.transition * self.transition * (3.0 - 2.0 * self.transition);
+        self.from.lerp(&self.kind.params(), t)
+    }
+
+    /// Get the wind's velocity right now, gusting around its strength
+    pub fn wind(&self) -> Vector3<f32> {
+        let direction = match self.wind_direction.magnitude2() > 0.0 {
+            true => self.wind_direction.normalize(),
+            false => Vector2::zero(),
+        };
+        let gust = 1.0 + GUST_AMOUNT * (self.time * GUST_SPEED).sin() * (self.time * GUST_SPEED * 2.3).cos();
+        vec3(direction.x, 0.0, direction.y) * self.wind_speed * self.params().wind * gust
+    }
+}
+
+/// The WeatherEffect component, for the entities that make the weather, which follow the player
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum WeatherEffect {
+    Rain,
+    RainAmbience,
+    WindAmbience,
+}
+
+/// The weather system, which changes the weather over time, applies it to the camera's fog, and
+/// moves the rain and ambience over the player. It spawns the rain and ambience the first time it
+/// runs, and again if they've been despawned. It runs after the day/night cycle, so the fog it
+/// applies is on top of the time of day's.
+pub fn update_weather(mut commands: Commands, sim_time: Res<SimTime>, mut weather: ResMut<Weather>,
+    mut rng: ResMut<SimRng>, levels: Res<Levels>, mut cam_query: Query<&mut PlayerCamera>,
+    player_query: Query<&Transform, (With<PlayerMovement>, Without<WeatherEffect>)>,
+    mut effect_query: Query<(&WeatherEffect, &mut Transform, Option<&mut ParticleEmitter>, Option<&mut AudioSource>)>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    weather.time += time_delta;
+
+    if weather.transition < 1.0 {
+        weather.transition = match weather.transition_time > 0.0 {
+            true => f32::min(weather.transition + time_delta / weather.transition_time, 1.0),
+            false => 1.0,
+        };
+    }
+
+    if weather.automatic {
+        weather.time_until_change -= time_delta;
+        if weather.time_until_change <= 0.0 {
+            let kind = WeatherKind::ALL[rng.next_u32() as usize % WeatherKind::ALL.len()];
+            weather.set(kind);
+            weather.time_until_change = rng.range(weather.duration_range.x, weather.duration_range.y);
+        }
+    }
+
+    let indoors = levels.current()
+        .and_then(|level| levels.get(level))
+        .map_or(false, |level| level.def.indoors);
+    let params = match indoors {
+        true => WeatherParams::default(),
+        false => weather.params(),
+    };
+
+    for mut cam in cam_query.iter_mut() {
+        cam.fog_range *= params.fog_distance;
+
+        // Keep the time of day's brightness in the weather's fog color
+        let brightness = f32::min(cam.fog_color.magnitude() / WeatherParams::default().fog_color.magnitude(), 1.0);
+        let fog_color = params.fog_color * brightness;
+        cam.fog_color = cam.fog_color + (fog_color - cam.fog_color) * params.fog_color_amount;
+    }
+
+    let player_pos = match player_query.get_single() {
+        Ok(transform) => transform.pos,
+        Err(_) => return,
+    };
+
+    if effect_query.is_empty() {
+        spawn_weather_effects(&mut commands, player_pos);
+        return;
+    }
+
+    // The rain's spawned upwind so that it's blown over the player
+    let wind = weather.wind();
+    let ambience = weather.params();
+    let muffling = match indoors {
+        true => 0.3,
+        false => 1.0,
+    };
+    for (effect, mut transform, emitter, source) in effect_query.iter_mut() {
+        match effect {
+            WeatherEffect::Rain => {
+                if let Some(mut emitter) = emitter {
+                    let fall_time = RAIN_HEIGHT / emitter.speed;
+                    transform.pos = player_pos + vec3(0.0, RAIN_HEIGHT, 0.0) - wind * fall_time;
+                    emitter.direction = vec3(0.0, -emitter.speed, 0.0) + wind;
+                    emitter.spawn_rate = params.rain * RAIN_SPAWN_RATE;
+                    emitter.enabled = params.rain > 0.0;
+                }
+            },
+            WeatherEffect::RainAmbience | WeatherEffect::WindAmbience => {
+                transform.pos = player_pos;
+                if let Some(mut source) = source {
+                    source.volume = muffling * match effect {
+                        WeatherEffect::RainAmbience => ambience.rain * 0.6,
+                        _ => f32::max(ambience.wind - WeatherParams::default().wind, 0.0) * 0.5,
+                    };
+                }
+            },
+        }
+    }
+}
+
+/// Spawn the rain and ambience over the player
+fn spawn_weather_effects(commands: &mut Commands, player_pos: Vector3<f32>) {
+    commands.spawn()
+        .insert(WeatherEffect::Rain)
+        .insert(Transform::new(player_pos + vec3(0.0, RAIN_HEIGHT, 0.0), Matrix3::identity()))
+        .insert(ParticleEmitter::new_rain());
+
+    // The ambience has no attenuation radius, so it's the same volume wherever the listener is
+    for (effect, sound) in [(WeatherEffect::RainAmbience, "rain_loop"), (WeatherEffect::WindAmbience, "wind_loop")] {
+        commands.spawn()
+            .insert(effect)
+            .insert(Transform::new(player_pos, Matrix3::identity()))
+            .insert(AudioSource::new_looping(sound, 0.0, 0.0));
+    }
+}
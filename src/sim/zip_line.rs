@@ -0,0 +1,130 @@
+use bevy_ecs::component::Component;
+use bevy_ecs::prelude::{Entity, Without};
+use bevy_ecs::system::{Res, Query};
+use cgmath::{Vector3, InnerSpace, vec3};
+use dreamfield_system::components::Transform;
+use dreamfield_system::resources::{SimTime, InputState};
+
+use super::{PlayerMovement, PlayerMovementMode};
+use super::gamepad::GamepadState;
+use super::input_bindings::{InputBindings, Action, Controls};
+
+/// The gravity acceleration pulling riders down the cable
+const GRAVITY_ACCELERATION: f32 = 9.8;
+
+/// How far below the cable the player's position hangs
+const HANG_HEIGHT: f32 = 2.0;
+
+/// The ZipLine component, for a cable between two anchors that the player can grab onto with
+/// interact and ride down. Zip lines come from the scene, where their anchors are the first and
+/// last points of the object's mesh. The cable isn't drawn, so the scene should model it too, and
+/// riders don't collide with the world on the way down.
+#[derive(Component)]
+pub struct ZipLine {
+    pub start: Vector3<f32>,
+    pub end: Vector3<f32>,
+    /// How close to the cable the player's hands need to be to grab it
+    pub attach_distance: f32,
+    pub max_speed: f32,
+    /// The deceleration from the cable's friction, in units per second per second
+    pub friction: f32,
+}
+
+impl ZipLine {
+    pub fn new(start: Vector3<f32>, end: Vector3<f32>) -> Self {
+        ZipLine {
+            start,
+            end,
+            attach_distance: 1.0,
+            max_speed: 12.0,
+            friction: 1.0,
+        }
+    }
+
+    /// Get the length of the cable
+    pub fn length(&self) -> f32 {
+        (self.end - self.start).magnitude()
+    }
+
+    /// Get the direction from the start to the end of the cable
+    pub fn direction(&self) -> Vector3<f32> {
+        (self.end - self.start).normalize()
+    }
+
+    /// Get the distance along the cable of the closest point to a position
+    fn closest_distance(&self, pos: Vector3<f32>) -> f32 {
+        f32::clamp((pos - self.start).dot(self.direction()), 0.0, self.length())
+    }
+
+    /// Get the point a distance along the cable
+    fn point(&self, distance: f32) -> Vector3<f32> {
+        self.start + self.direction() * distance
+    }
+}
+
+/// The zip line system, which attaches the player to zip lines and moves them along them until they
+/// reach the end or jump off. It runs before the player update so the camera follows the ride.
+pub fn update_zip_lines(sim_time: Res<SimTime>, input: Res<InputState>, gamepad: Res<GamepadState>,
+    bindings: Res<InputBindings>, mut player_query: Query<(&mut Transform, &mut PlayerMovement)>,
+    zip_lines: Query<(Entity, &ZipLine), Without<PlayerMovement>>)
+{
+    let time_delta = sim_time.sim_time_delta as f32;
+    let controls = Controls::new(&input, &gamepad, &bindings);
+
+    for (mut transform, mut player_movement) in player_query.iter_mut() {
+        if !player_movement.enabled {
+            continue;
+        }
+
+        match player_movement.movement_mode {
+            // Grab onto the nearest zip line in reach
+            PlayerMovementMode::Normal if controls.is_just_pressed(Action::Interact) => {
+                let hands = transform.pos + vec3(0.0, HANG_HEIGHT, 0.0);
+                let grabbed = zip_lines.iter()
+                    .map(|(entity, zip_line)| (entity, zip_line, zip_line.closest_distance(hands)))
+                    .filter(|(_, zip_line, distance)| {
+                        (zip_line.point(*distance) - hands).magnitude() <= zip_line.attach_distance
+                            && *distance < zip_line.length()
+                    })
+                    .min_by(|(_, a, a_dist), (_, b, b_dist)| {
+                        (a.point(*a_dist) - hands).magnitude2().total_cmp(&(b.point(*b_dist) - hands).magnitude2())
+                    });
+
+                if let Some((entity, zip_line, distance)) = grabbed {
+                    player_movement.movement_mode = PlayerMovementMode::Riding {
+                        zip_line: entity,
+                        distance,
+                        speed: player_movement.velocity.dot(zip_line.direction()),
+                    };
+                }
+            },
+            PlayerMovementMode::Riding { zip_line: zip_line_entity, distance, speed } => {
+                let zip_line = match zip_lines.get(zip_line_entity) {
+                    Ok((_, zip_line)) => zip_line,
+                    Err(_) => {
+                        player_movement.movement_mode = PlayerMovementMode::Normal;
+                        continue;
+                    }
+                };
+                let dir = zip_line.direction();
+
+                // Slide down the cable, slowed down by its friction
+                let mut speed = speed - GRAVITY_ACCELERATION * dir.y * time_delta;
+                speed -= f32::min(zip_line.friction * time_delta, speed.abs()) * speed.signum();
+                speed = f32::clamp(speed, -zip_line.max_speed, zip_line.max_speed);
+
+                let distance = f32::clamp(distance + speed * time_delta, 0.0, zip_line.length());
+                transform.pos = zip_line.point(distance) - vec3(0.0, HANG_HEIGHT, 0.0);
+                player_movement.velocity = dir * speed;
+
+                // Let go at either end, or when jumping off, keeping our momentum
+                let at_end = distance <= 0.0 || distance >= zip_line.length();
+                player_movement.movement_mode = match at_end || controls.is_just_pressed(Action::Jump) {
+                    true => PlayerMovementMode::Normal,
+                    false => PlayerMovementMode::Riding { zip_line: zip_line_entity, distance, speed },
+                };
+            },
+            _ => {},
+        }
+    }
+}